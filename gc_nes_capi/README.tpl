@@ -0,0 +1,5 @@
+# {{crate}}
+
+{{readme}}
+
+Current version: {{version}}
\ No newline at end of file
@@ -0,0 +1,99 @@
+/*
+ * C declarations for gc_nes_capi, the C ABI wrapper for the gc_nes_core NES emulator.
+ * See the documentation in gc_nes_capi/src/lib.rs for the safety requirements of each function.
+ */
+
+#ifndef GC_NES_H
+#define GC_NES_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The dimensions of the NES screen in pixels */
+#define GC_NES_SCREEN_WIDTH 256
+#define GC_NES_SCREEN_HEIGHT 240
+/* The dimensions of the thumbnail written with each save state in pixels */
+#define GC_NES_THUMBNAIL_WIDTH 128
+#define GC_NES_THUMBNAIL_HEIGHT 120
+
+/* The bits that represent each button of a standard NES controller in the state passed to gc_nes_set_input */
+#define GC_NES_BUTTON_A 0x01
+#define GC_NES_BUTTON_B 0x02
+#define GC_NES_BUTTON_SELECT 0x04
+#define GC_NES_BUTTON_START 0x08
+#define GC_NES_BUTTON_UP 0x10
+#define GC_NES_BUTTON_DOWN 0x20
+#define GC_NES_BUTTON_LEFT 0x40
+#define GC_NES_BUTTON_RIGHT 0x80
+
+/* Opaque handle to a NES */
+typedef struct GcNes GcNes;
+/* Opaque handle to an in-memory snapshot of a NES's state */
+typedef struct GcNesState GcNesState;
+
+/* The result of a call that can fail */
+typedef enum GcNesResult {
+    GC_NES_RESULT_OK = 0,
+    GC_NES_RESULT_NULL_POINTER = 1,
+    GC_NES_RESULT_INVALID_ROM = 2,
+    GC_NES_RESULT_INVALID_ARGUMENT = 3,
+    GC_NES_RESULT_PANIC = 4,
+    GC_NES_RESULT_INVALID_STATE = 5,
+} GcNesResult;
+
+/* Gets the version of gc_nes_capi. The string is static and must not be freed. */
+const char *gc_nes_version(void);
+
+/* Creates a NES from the bytes of a .nes file, returning NULL if the ROM could not be loaded */
+GcNes *gc_nes_create(const uint8_t *rom, size_t rom_length);
+/* Replaces the cartridge in a NES, powering it back on. The NES is unchanged if the ROM could not be loaded. */
+GcNesResult gc_nes_load_rom(GcNes *nes, const uint8_t *rom, size_t rom_length);
+/* Frees a NES */
+void gc_nes_destroy(GcNes *nes);
+
+/* Runs a single cycle of the NES */
+GcNesResult gc_nes_cycle(GcNes *nes);
+/* Runs the NES until the current frame is complete */
+GcNesResult gc_nes_frame(GcNes *nes);
+/* Runs the NES until the PPU has set the vertical blank flag, before the CPU sees it */
+GcNesResult gc_nes_run_until_vblank(GcNes *nes);
+/* Runs the NES until the PPU is about to start the scanline (0 to 261), which may be in the next frame */
+GcNesResult gc_nes_run_until_scanline(GcNes *nes, uint16_t scanline);
+/* Gets the screen as GC_NES_SCREEN_WIDTH * GC_NES_SCREEN_HEIGHT ARGB pixels, valid until the NES is next changed */
+const uint32_t *gc_nes_get_screen(GcNes *nes);
+
+/* Updates the controller connected to port 0 or 1 with a combination of GC_NES_BUTTON bits */
+GcNesResult gc_nes_set_input(GcNes *nes, uint8_t port, bool connected, uint8_t state);
+/* Resets the NES, like pressing the reset button on the console */
+GcNesResult gc_nes_reset(GcNes *nes);
+
+/* Shows a null terminated UTF-8 message in the corner of the screen for a duration in milliseconds of emulated time */
+GcNesResult gc_nes_post_message(GcNes *nes, const char *text, uint32_t duration_ms);
+
+/* Takes a snapshot of a NES's state, returning NULL on failure */
+GcNesState *gc_nes_save_state(const GcNes *nes);
+/* Restores a NES to a snapshot, which can be loaded again afterwards */
+GcNesResult gc_nes_load_state(GcNes *nes, const GcNesState *state);
+/* Frees a snapshot */
+void gc_nes_free_state(GcNesState *state);
+
+/* Gets the size of a NES's save state in bytes, which is the same for every state of the loaded ROM, or 0 if NES is NULL */
+size_t gc_nes_save_state_size(const GcNes *nes);
+/* Writes a NES's save state to a buffer of at least gc_nes_save_state_size bytes */
+GcNesResult gc_nes_save_state_to_buffer(const GcNes *nes, uint8_t *buffer, size_t buffer_length);
+/* Restores a NES to a save state written by gc_nes_save_state_to_buffer. The NES is unchanged if it could not be loaded. */
+GcNesResult gc_nes_load_state_from_buffer(GcNes *nes, const uint8_t *buffer, size_t buffer_length);
+/* Reads the screen when a save state was written by gc_nes_save_state_to_buffer into a buffer of
+ * GC_NES_THUMBNAIL_WIDTH * GC_NES_THUMBNAIL_HEIGHT ARGB pixels, without loading the state */
+GcNesResult gc_nes_peek_state_thumbnail(const uint8_t *buffer, size_t buffer_length, uint32_t *thumbnail);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GC_NES_H */
@@ -0,0 +1,551 @@
+//! gc_nes_capi wraps the public functions exposed by my gc_nes_core crate in a stable
+//! C ABI, so that the emulator can be embedded by frontends that aren't written in Rust,
+//! such as C, C#, or Python through ctypes. The declarations for C are in `include/gc_nes.h`.
+//!
+//! ### Using the NES Emulator from C
+//! ```c
+//! #include "gc_nes.h"
+//!
+//! // Create the NES from the bytes of a .nes file
+//! GcNes *nes = gc_nes_create(rom_bytes, rom_length);
+//! if (nes == NULL) { /* The ROM couldn't be loaded */ }
+//! // Provide input state for the first controller
+//! gc_nes_set_input(nes, 0, true, GC_NES_BUTTON_START | GC_NES_BUTTON_A);
+//! // Run the emulator to the completion of the next frame and retrieve it
+//! gc_nes_frame(nes);
+//! const uint32_t *frame = gc_nes_get_screen(nes); // 256x240 ARGB pixels
+//! // Show a message in the corner of the screen for two seconds
+//! gc_nes_post_message(nes, "Controller connected", 2000);
+//! // Take a snapshot of the NES and restore it later
+//! GcNesState *state = gc_nes_save_state(nes);
+//! gc_nes_load_state(nes, state);
+//! gc_nes_free_state(state);
+//! // Or write the state to a buffer that can be saved to a file and loaded in another session
+//! size_t size = gc_nes_save_state_size(nes);
+//! uint8_t *buffer = malloc(size);
+//! gc_nes_save_state_to_buffer(nes, buffer, size);
+//! // Preview the screen when a state was saved without loading it
+//! uint32_t thumbnail[GC_NES_THUMBNAIL_WIDTH * GC_NES_THUMBNAIL_HEIGHT]; // ARGB pixels
+//! gc_nes_peek_state_thumbnail(buffer, size, thumbnail);
+//! gc_nes_load_state_from_buffer(nes, buffer, size);
+//! free(buffer);
+//! // Free the NES once it's no longer needed
+//! gc_nes_destroy(nes);
+//! ```
+//!
+//! Every function catches panics inside the emulator rather than unwinding into the caller,
+//! reporting them as `GC_NES_RESULT_PANIC` or a null pointer instead. The details of errors, such as why a ROM
+//! failed to load, are logged with the `log` crate, so a Rust host can install a logger to see them.
+
+#![allow(clippy::needless_return)] // I prefer clarity of return
+
+use gc_nes_core::cartridge::Cartridge;
+use gc_nes_core::nes::{Nes, NES_SCREEN_DIMENSIONS};
+use gc_nes_core::state::peek_state_thumbnail;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::Duration;
+
+#[macro_use]
+extern crate log;
+
+/// Opaque handle to a NES, created with gc_nes_create and freed with gc_nes_destroy
+pub struct GcNes {
+    nes: Nes,
+}
+
+/// Opaque handle to a snapshot of a NES's state, created with gc_nes_save_state and freed with gc_nes_free_state.
+///
+/// Snapshots are held in memory and can only be restored into a NES in the same process, use
+/// gc_nes_save_state_to_buffer for states that need to outlive it.
+pub struct GcNesState {
+    nes: Nes,
+}
+
+/// The number of pixels in the thumbnail of a save state
+const THUMBNAIL_DIMENSIONS: usize = NES_SCREEN_DIMENSIONS / 4;
+
+/// The result of a call that can fail
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GcNesResult {
+    /// The call succeeded
+    Ok = 0,
+    /// A required pointer argument was null
+    NullPointer = 1,
+    /// The ROM could not be loaded, either because it is invalid or its mapper is unsupported
+    InvalidRom = 2,
+    /// An argument was outside of its valid range
+    InvalidArgument = 3,
+    /// The emulator panicked, and the NES may be in an inconsistent state
+    Panic = 4,
+    /// The save state could not be loaded, either because it is invalid or was saved with a different ROM
+    InvalidState = 5,
+}
+
+/// Runs a closure, catching any panic so that it doesn't unwind across the C ABI
+fn guard<F: FnOnce() -> GcNesResult>(function: F) -> GcNesResult {
+    return catch_unwind(AssertUnwindSafe(function)).unwrap_or(GcNesResult::Panic);
+}
+
+/// Loads a cartridge from a buffer of .nes file bytes, returning None if the buffer is null or the ROM is invalid
+unsafe fn load_cartridge(rom: *const u8, rom_length: usize) -> Option<Cartridge> {
+    if rom.is_null() {
+        return None;
+    }
+    return match Cartridge::load_from_reader(std::slice::from_raw_parts(rom, rom_length)) {
+        Ok(cartridge) => Some(cartridge),
+        Err(error) => {
+            warn!("Failed to load ROM: {}", error);
+            None
+        }
+    };
+}
+
+/// Creates a NES that draws the messages posted with gc_nes_post_message into the screen
+fn create_nes(cartridge: Cartridge) -> Nes {
+    let mut nes = Nes::new(cartridge);
+    nes.get_messages_mut().set_rendering(true);
+    return nes;
+}
+
+/// Gets the version of gc_nes_capi as a null terminated string. The string is static and must not be freed.
+#[no_mangle]
+pub extern "C" fn gc_nes_version() -> *const c_char {
+    return concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char;
+}
+
+/// Creates a new NES with no connected controllers from the bytes of a .nes file, which are copied.
+/// Returns null if the ROM could not be loaded.
+///
+/// # Safety
+/// `rom` must point to at least `rom_length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_create(rom: *const u8, rom_length: usize) -> *mut GcNes {
+    return catch_unwind(|| match load_cartridge(rom, rom_length) {
+        Some(cartridge) => Box::into_raw(Box::new(GcNes { nes: create_nes(cartridge) })),
+        None => std::ptr::null_mut(),
+    })
+    .unwrap_or(std::ptr::null_mut());
+}
+
+/// Replaces the cartridge in a NES with one loaded from the bytes of a .nes file, powering the NES
+/// back on. The speed multiplier is kept. If the ROM could not be loaded, the NES is left unchanged.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed,
+/// and `rom` must point to at least `rom_length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_load_rom(nes: *mut GcNes, rom: *const u8, rom_length: usize) -> GcNesResult {
+    let nes = match nes.as_mut() {
+        Some(nes) => nes,
+        None => return GcNesResult::NullPointer,
+    };
+    return guard(|| match load_cartridge(rom, rom_length) {
+        Some(cartridge) => {
+            let speed = nes.nes.get_speed();
+            nes.nes = create_nes(cartridge);
+            nes.nes.set_speed(speed);
+            GcNesResult::Ok
+        }
+        None if rom.is_null() => GcNesResult::NullPointer,
+        None => GcNesResult::InvalidRom,
+    });
+}
+
+/// Frees a NES. Does nothing if the pointer is null.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't already been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_destroy(nes: *mut GcNes) {
+    if !nes.is_null() {
+        drop(Box::from_raw(nes));
+    }
+}
+
+/// Runs a single cycle of the NES
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_cycle(nes: *mut GcNes) -> GcNesResult {
+    return match nes.as_mut() {
+        Some(nes) => guard(|| {
+            nes.nes.cycle();
+            GcNesResult::Ok
+        }),
+        None => GcNesResult::NullPointer,
+    };
+}
+
+/// Runs as many cycles as necessary to complete the current frame. The frame can then be retrieved with gc_nes_get_screen.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_frame(nes: *mut GcNes) -> GcNesResult {
+    return match nes.as_mut() {
+        Some(nes) => guard(|| {
+            nes.nes.frame();
+            GcNesResult::Ok
+        }),
+        None => GcNesResult::NullPointer,
+    };
+}
+
+/// Runs the NES until the PPU has set the vertical blank flag, before the CPU sees it or takes the NMI
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_run_until_vblank(nes: *mut GcNes) -> GcNesResult {
+    return match nes.as_mut() {
+        Some(nes) => guard(|| {
+            nes.nes.run_until_vblank();
+            GcNesResult::Ok
+        }),
+        None => GcNesResult::NullPointer,
+    };
+}
+
+/// Runs the NES until the PPU is about to start the scanline, from 0 to 261, running into the next frame if the PPU
+/// is already on or past the start of it. Returns GC_NES_RESULT_INVALID_ARGUMENT for scanlines past 261.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_run_until_scanline(nes: *mut GcNes, scanline: u16) -> GcNesResult {
+    return match nes.as_mut() {
+        Some(nes) => guard(|| match nes.nes.run_until_scanline(scanline) {
+            Ok(()) => GcNesResult::Ok,
+            Err(_) => GcNesResult::InvalidArgument,
+        }),
+        None => GcNesResult::NullPointer,
+    };
+}
+
+/// Gets a pointer to the current state of the screen, as 256x240 32 bit ARGB pixels in rows from the top left.
+/// The pointer stays valid until the NES is next run, has a ROM loaded or a state loaded, or is destroyed.
+/// Returns null if the NES is null.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_get_screen(nes: *mut GcNes) -> *const u32 {
+    return match nes.as_mut() {
+        Some(nes) => nes.nes.get_screen().as_ptr(),
+        None => std::ptr::null(),
+    };
+}
+
+/// Updates the state of the controller connected to a port, 0 for the first port and 1 for the second.
+/// The state is a combination of the GC_NES_BUTTON bits, and is ignored if the controller isn't connected.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_set_input(nes: *mut GcNes, port: u8, connected: bool, state: u8) -> GcNesResult {
+    let nes = match nes.as_mut() {
+        Some(nes) => nes,
+        None => return GcNesResult::NullPointer,
+    };
+    let input_state = if connected { Some(state) } else { None };
+    return match port {
+        0 => guard(|| {
+            nes.nes.update_controller_one(input_state);
+            GcNesResult::Ok
+        }),
+        1 => guard(|| {
+            nes.nes.update_controller_two(input_state);
+            GcNesResult::Ok
+        }),
+        _ => GcNesResult::InvalidArgument,
+    };
+}
+
+/// Resets the NES, like pressing the reset button on the console
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_reset(nes: *mut GcNes) -> GcNesResult {
+    return match nes.as_mut() {
+        Some(nes) => guard(|| {
+            nes.nes.reset();
+            GcNesResult::Ok
+        }),
+        None => GcNesResult::NullPointer,
+    };
+}
+
+/// Shows a message, such as a notification that a state was saved, in the bottom left corner of the screen for a
+/// duration in milliseconds of emulated time. The text is copied. Returns GC_NES_RESULT_INVALID_ARGUMENT if the text
+/// isn't valid UTF-8.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed, and `text` must be null or
+/// a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_post_message(nes: *mut GcNes, text: *const c_char, duration_ms: u32) -> GcNesResult {
+    let nes = match nes.as_mut() {
+        Some(nes) if !text.is_null() => nes,
+        _ => return GcNesResult::NullPointer,
+    };
+    return match CStr::from_ptr(text).to_str() {
+        Ok(text) => guard(|| {
+            nes.nes.post_message(text, Duration::from_millis(u64::from(duration_ms)));
+            GcNesResult::Ok
+        }),
+        Err(_) => GcNesResult::InvalidArgument,
+    };
+}
+
+/// Takes a snapshot of the complete state of a NES, which can be restored with gc_nes_load_state.
+/// Returns null if the NES is null.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_save_state(nes: *const GcNes) -> *mut GcNesState {
+    return match nes.as_ref() {
+        Some(nes) => catch_unwind(AssertUnwindSafe(|| Box::into_raw(Box::new(GcNesState { nes: nes.nes.clone() })))).unwrap_or(std::ptr::null_mut()),
+        None => std::ptr::null_mut(),
+    };
+}
+
+/// Restores a NES to a snapshot taken with gc_nes_save_state. The snapshot is copied, so it can be loaded again.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed, and `state`
+/// must be null or a pointer returned by gc_nes_save_state that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_load_state(nes: *mut GcNes, state: *const GcNesState) -> GcNesResult {
+    return match (nes.as_mut(), state.as_ref()) {
+        (Some(nes), Some(state)) => guard(|| {
+            nes.nes = state.nes.clone();
+            GcNesResult::Ok
+        }),
+        _ => GcNesResult::NullPointer,
+    };
+}
+
+/// Gets the size in bytes of a NES's save state, which is the same for every state of the ROM it has loaded, so a
+/// buffer of this size can be reused for every call to gc_nes_save_state_to_buffer. Returns 0 if the NES is null.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_save_state_size(nes: *const GcNes) -> usize {
+    return match nes.as_ref() {
+        Some(nes) => catch_unwind(AssertUnwindSafe(|| nes.nes.save_state_with_thumbnail().len())).unwrap_or(0),
+        None => 0,
+    };
+}
+
+/// Writes the complete state of a NES to a buffer as bytes that can be written to a file and restored with
+/// gc_nes_load_state_from_buffer, even by another process. The state holds a thumbnail of the screen, which
+/// gc_nes_peek_state_thumbnail reads without loading the state. Fails with `GC_NES_RESULT_INVALID_ARGUMENT` if the buffer
+/// is smaller than gc_nes_save_state_size.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed, and `buffer` must be null
+/// or point to `buffer_length` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_save_state_to_buffer(nes: *const GcNes, buffer: *mut u8, buffer_length: usize) -> GcNesResult {
+    return match nes.as_ref() {
+        Some(nes) if !buffer.is_null() => guard(|| {
+            let state = nes.nes.save_state_with_thumbnail();
+            if state.len() > buffer_length {
+                return GcNesResult::InvalidArgument;
+            }
+            std::slice::from_raw_parts_mut(buffer, state.len()).copy_from_slice(&state);
+            GcNesResult::Ok
+        }),
+        _ => GcNesResult::NullPointer,
+    };
+}
+
+/// Restores a NES to a state written by gc_nes_save_state_to_buffer. The NES is unchanged if the state could not be
+/// loaded, which happens if it was saved with a different ROM or version of the emulator.
+///
+/// # Safety
+/// `nes` must be null or a pointer returned by gc_nes_create that hasn't been destroyed, and `buffer` must be null
+/// or point to `buffer_length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_load_state_from_buffer(nes: *mut GcNes, buffer: *const u8, buffer_length: usize) -> GcNesResult {
+    return match nes.as_mut() {
+        Some(nes) if !buffer.is_null() => guard(|| match nes.nes.load_state(std::slice::from_raw_parts(buffer, buffer_length)) {
+            Ok(()) => GcNesResult::Ok,
+            Err(_) => GcNesResult::InvalidState,
+        }),
+        _ => GcNesResult::NullPointer,
+    };
+}
+
+/// Reads the screen when a save state was written by gc_nes_save_state_to_buffer into `thumbnail`, downscaled to
+/// 128x120 32 bit ARGB pixels in rows from the top left, without loading the state, so that frontends can show
+/// previews of save slots. Fails with `GC_NES_RESULT_INVALID_STATE` if the buffer doesn't hold a save state.
+///
+/// # Safety
+/// `buffer` must be null or point to `buffer_length` readable bytes, and `thumbnail` must be null or point to
+/// space for 128x120 pixels.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_peek_state_thumbnail(buffer: *const u8, buffer_length: usize, thumbnail: *mut u32) -> GcNesResult {
+    if buffer.is_null() || thumbnail.is_null() {
+        return GcNesResult::NullPointer;
+    }
+    return guard(|| match peek_state_thumbnail(std::slice::from_raw_parts(buffer, buffer_length)) {
+        Ok(Some(state_thumbnail)) if state_thumbnail.pixels.len() == 4 * THUMBNAIL_DIMENSIONS => {
+            let pixels = std::slice::from_raw_parts_mut(thumbnail, THUMBNAIL_DIMENSIONS);
+            for (pixel, rgba) in pixels.iter_mut().zip(state_thumbnail.pixels.chunks_exact(4)) {
+                *pixel = u32::from_be_bytes([rgba[3], rgba[0], rgba[1], rgba[2]]);
+            }
+            GcNesResult::Ok
+        }
+        _ => GcNesResult::InvalidState,
+    });
+}
+
+/// Frees a snapshot. Does nothing if the pointer is null.
+///
+/// # Safety
+/// `state` must be null or a pointer returned by gc_nes_save_state that hasn't already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_free_state(state: *mut GcNesState) {
+    if !state.is_null() {
+        drop(Box::from_raw(state));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a .nes file with a single bank of program ROM that loops forever at $8000
+    fn get_test_rom() -> Vec<u8> {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 0x01, 0x01, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut program_rom = vec![0x00; 0x4000];
+        // JMP $8000
+        program_rom[..3].copy_from_slice(&[0x4c, 0x00, 0x80]);
+        // Reset vector
+        program_rom[0x3ffc..0x3ffe].copy_from_slice(&[0x00, 0x80]);
+        rom.extend_from_slice(&program_rom);
+        rom.extend_from_slice(&[0x00; 0x2000]);
+        return rom;
+    }
+
+    #[test]
+    fn test_create_invalid_rom() {
+        unsafe {
+            let rom = [0x00; 16];
+            assert!(gc_nes_create(rom.as_ptr(), rom.len()).is_null());
+            assert!(gc_nes_create(std::ptr::null(), 0).is_null());
+        }
+    }
+
+    #[test]
+    fn test_null_nes() {
+        unsafe {
+            assert_eq!(GcNesResult::NullPointer, gc_nes_frame(std::ptr::null_mut()));
+            assert_eq!(GcNesResult::NullPointer, gc_nes_run_until_vblank(std::ptr::null_mut()));
+            assert_eq!(GcNesResult::NullPointer, gc_nes_set_input(std::ptr::null_mut(), 0, true, 0x00));
+            assert!(gc_nes_get_screen(std::ptr::null_mut()).is_null());
+            gc_nes_destroy(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_peek_state_thumbnail() {
+        let rom = get_test_rom();
+        unsafe {
+            let nes = gc_nes_create(rom.as_ptr(), rom.len());
+            assert_eq!(GcNesResult::Ok, gc_nes_frame(nes));
+            let size = gc_nes_save_state_size(nes);
+            let mut buffer = vec![0u8; size];
+            assert_eq!(GcNesResult::Ok, gc_nes_save_state_to_buffer(nes, buffer.as_mut_ptr(), size));
+            let mut thumbnail = vec![0u32; THUMBNAIL_DIMENSIONS];
+            assert_eq!(
+                GcNesResult::Ok,
+                gc_nes_peek_state_thumbnail(buffer.as_ptr(), size, thumbnail.as_mut_ptr())
+            );
+            // The test ROM draws nothing but the backdrop, so the thumbnail is the same colour as the screen, apart from its alpha
+            let screen = *gc_nes_get_screen(nes);
+            assert!(thumbnail.iter().all(|pixel| pixel & 0x00ff_ffff == screen & 0x00ff_ffff));
+            // Peeking doesn't load the state
+            assert_eq!(1, (*nes).nes.get_frame_count());
+
+            assert_eq!(
+                GcNesResult::InvalidState,
+                gc_nes_peek_state_thumbnail(rom.as_ptr(), rom.len(), thumbnail.as_mut_ptr())
+            );
+            assert_eq!(
+                GcNesResult::NullPointer,
+                gc_nes_peek_state_thumbnail(std::ptr::null(), size, thumbnail.as_mut_ptr())
+            );
+            assert_eq!(
+                GcNesResult::NullPointer,
+                gc_nes_peek_state_thumbnail(buffer.as_ptr(), size, std::ptr::null_mut())
+            );
+            gc_nes_destroy(nes);
+        }
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let rom = get_test_rom();
+        unsafe {
+            let nes = gc_nes_create(rom.as_ptr(), rom.len());
+            assert!(!nes.is_null());
+            assert_eq!(GcNesResult::Ok, gc_nes_set_input(nes, 0, true, 0x09));
+            assert_eq!(GcNesResult::InvalidArgument, gc_nes_set_input(nes, 2, true, 0x00));
+            assert_eq!(GcNesResult::Ok, gc_nes_frame(nes));
+            assert!(!gc_nes_get_screen(nes).is_null());
+
+            let state = gc_nes_save_state(nes);
+            assert_eq!(GcNesResult::Ok, gc_nes_frame(nes));
+            assert_eq!(2, (*nes).nes.get_frame_count());
+            assert_eq!(GcNesResult::Ok, gc_nes_load_state(nes, state));
+            assert_eq!(1, (*nes).nes.get_frame_count());
+            gc_nes_free_state(state);
+
+            let size = gc_nes_save_state_size(nes);
+            assert_ne!(0, size);
+            let mut buffer = vec![0u8; size];
+            assert_eq!(
+                GcNesResult::InvalidArgument,
+                gc_nes_save_state_to_buffer(nes, buffer.as_mut_ptr(), size - 1)
+            );
+            assert_eq!(GcNesResult::Ok, gc_nes_save_state_to_buffer(nes, buffer.as_mut_ptr(), size));
+            assert_eq!(GcNesResult::Ok, gc_nes_frame(nes));
+            assert_eq!(GcNesResult::Ok, gc_nes_load_state_from_buffer(nes, buffer.as_ptr(), size));
+            assert_eq!(1, (*nes).nes.get_frame_count());
+            assert_eq!(GcNesResult::InvalidState, gc_nes_load_state_from_buffer(nes, buffer.as_ptr(), size - 1));
+            assert_eq!(GcNesResult::NullPointer, gc_nes_load_state_from_buffer(nes, std::ptr::null(), size));
+            assert_eq!(0, gc_nes_save_state_size(std::ptr::null()));
+
+            assert_eq!(GcNesResult::Ok, gc_nes_run_until_scanline(nes, 20));
+            assert_eq!((20, 0), (*nes).nes.get_ppu_position());
+            assert_eq!(GcNesResult::InvalidArgument, gc_nes_run_until_scanline(nes, 262));
+            assert_eq!(GcNesResult::Ok, gc_nes_run_until_vblank(nes));
+            assert_eq!((241, 2), (*nes).nes.get_ppu_position());
+
+            assert_eq!(
+                GcNesResult::Ok,
+                gc_nes_post_message(nes, b"State saved\0".as_ptr() as *const c_char, 1000)
+            );
+            assert_eq!("State saved", (*nes).nes.get_messages_mut().get_messages().next().unwrap().text);
+            assert_eq!(
+                GcNesResult::InvalidArgument,
+                gc_nes_post_message(nes, b"\xff\0".as_ptr() as *const c_char, 1000)
+            );
+            assert_eq!(GcNesResult::NullPointer, gc_nes_post_message(nes, std::ptr::null(), 1000));
+
+            assert_eq!(GcNesResult::InvalidRom, gc_nes_load_rom(nes, rom.as_ptr(), 16));
+            assert_eq!(GcNesResult::Ok, gc_nes_load_rom(nes, rom.as_ptr(), rom.len()));
+            assert_eq!(0, (*nes).nes.get_frame_count());
+            gc_nes_destroy(nes);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Fuzz target that loads a cartridge and then drives the CPU bus with arbitrary reads, writes and cycles.
+//!
+//! The input starts with a two byte length, followed by that many bytes of .nes file. The rest of the input is
+//! a list of four byte operations: an operation byte, a two byte address, and a data byte.
+
+#![no_main]
+
+use gc_nes_core::cartridge::Cartridge;
+use gc_nes_core::nes::Nes;
+use gc_nes_core::video::NullVideoBackend;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let rom_length = usize::from(u16::from_le_bytes([data[0], data[1]]));
+    let (rom, operations) = data[2..].split_at(rom_length.min(data.len() - 2));
+    let cartridge = match Cartridge::load_from_reader(rom) {
+        Ok(cartridge) => cartridge,
+        Err(_) => return,
+    };
+    let mut nes = Nes::with_video_backend(cartridge, NullVideoBackend);
+    for operation in operations.chunks_exact(4) {
+        let address = u16::from_le_bytes([operation[1], operation[2]]);
+        match operation[0] % 4 {
+            0 => {
+                nes.cpu_bus_read(address);
+            }
+            1 => nes.cpu_bus_write(address, operation[3]),
+            // Running the NES for up to 256 cycles at a time lets the PPU, APU and mapper react to the accesses
+            _ => (0..u16::from(operation[3]) + 1).for_each(|_| nes.cycle()),
+        }
+    }
+});
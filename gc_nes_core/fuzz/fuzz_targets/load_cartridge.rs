@@ -0,0 +1,15 @@
+//! Fuzz target that loads arbitrary bytes as a .nes file. Loading must either fail with an error or produce
+//! a cartridge, without panicking or allocating much more memory than the file takes up.
+
+#![no_main]
+
+use gc_nes_core::cartridge::{calculate_rom_md5, Cartridge, CartridgeInfo};
+use libfuzzer_sys::fuzz_target;
+use std::sync::Arc;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = CartridgeInfo::read_from_reader(data);
+    let _ = calculate_rom_md5(data);
+    let _ = Cartridge::load_from_reader(data);
+    let _ = Cartridge::load_from_bytes(Arc::from(data));
+});
@@ -0,0 +1,378 @@
+//! The audio module contains the sinks that the NES pushes the output of the APU to as it is produced,
+//! one sample for every CPU cycle, and the resampler that converts them to the sample rate of the host.
+
+use crate::nes::NES_AUDIO_SAMPLE_RATE;
+
+pub mod stereo;
+pub mod trace;
+#[cfg(feature = "recorder")]
+pub mod wav;
+
+/// The most samples that an [AudioSampleBuffer](struct.AudioSampleBuffer.html) holds, about a second's worth,
+/// so that frontends which never take them don't run out of memory
+pub(crate) const AUDIO_SAMPLE_LIMIT: usize = 1 << 21;
+
+/// The outputs of the APU's individual channels for a single sample, before they are mixed
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ChannelOutputs {
+    /// The output of the first pulse channel, from 0 to 15
+    pub pulse_one: u8,
+    /// The output of the second pulse channel, from 0 to 15
+    pub pulse_two: u8,
+    /// The output of the triangle channel, from 0 to 15
+    pub triangle: u8,
+    /// The output of the noise channel, from 0 to 15
+    pub noise: u8,
+    /// The output of the delta modulation channel, from 0 to 127
+    pub dmc: u8,
+}
+
+/// Trait for types that receive the audio produced by the APU.
+pub trait AudioSink {
+    /// Called by the NES every CPU cycle with the mixed output of the APU, from 0.0 to 1.0 (or from -1.0 to 1.0
+    /// with the analog filters enabled), and the outputs of the individual channels that it was mixed from.
+    /// The mixed output includes any expansion audio from the cartridge, which can push it past 1.0.
+    fn push_sample(&mut self, sample: f32, channels: ChannelOutputs);
+
+    /// Called by the NES every CPU cycle instead of [push_sample](#tymethod.push_sample) while
+    /// [stereo panning](../nes/struct.Nes.html#method.set_stereo_panning) is enabled, with the left and right outputs
+    /// of the APU. Sinks that only play mono audio can leave this as it is, which passes the average of the two on to
+    /// push_sample.
+    fn push_stereo_sample(&mut self, left: f32, right: f32, channels: ChannelOutputs) {
+        self.push_sample((left + right) / 2.0, channels);
+    }
+
+    /// Called by the NES when its [speed](../nes/struct.Nes.html#method.set_speed) changes, with the new multiplier.
+    /// Sinks that resample the APU's output produce samples at their output rate divided by the speed, so that the
+    /// host gets the same number of samples for each of its refreshes at any speed. Other sinks can leave this as it is.
+    fn set_speed(&mut self, _speed: f64) {}
+}
+
+/// The number of output samples that each band-limited step is spread across
+const STEP_WIDTH: usize = 16;
+/// The number of positions between two output samples that band-limited steps are calculated for
+const STEP_PHASES: usize = 32;
+/// The highest frequency kept by the band-limited steps, as a fraction of the output sample rate
+const STEP_CUTOFF: f64 = 0.45;
+
+/// Converts the samples produced by the APU to a lower sample rate with band-limited step synthesis.
+///
+/// The APU's output only changes in steps, which alias badly when they are sampled directly, as a square wave's
+/// harmonics extend far past what the output rate can represent. Instead, each change in the input is added to the
+/// output as a step with those harmonics removed (a windowed sinc, integrated), placed at the point between two
+/// output samples where the change happened. The cost depends on how often the input changes rather than the
+/// input's sample rate. Output samples lag behind the input by half of the step's width, about eight samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resampler {
+    /// The rate that output samples are produced at, in Hz
+    output_rate: f64,
+    /// How far the timer advances for each input sample, which is the output rate divided by the speed
+    timer_step: f64,
+    /// The fraction of an output sample that has elapsed, scaled by the input sample rate
+    timer: f64,
+    /// The last input sample, which changes in the input are measured from
+    last_input: f32,
+    /// The changes that each upcoming output sample makes to the output level, as a ring buffer
+    deltas: [f32; STEP_WIDTH],
+    /// The index of the next output sample's change in the deltas ring buffer
+    delta_index: usize,
+    /// The level of the last output sample, which is the sum of all of the changes so far
+    level: f32,
+    /// The band-limited steps for each phase, as the change that they make to each of the output samples they cover
+    steps: Box<[[f32; STEP_WIDTH]; STEP_PHASES]>,
+}
+
+impl Resampler {
+    /// Creates a resampler that produces samples at the output rate in Hz, or None if the rate
+    /// isn't positive or is higher than the rate that the APU produces samples at
+    pub fn new(output_rate: f64) -> Option<Self> {
+        if !(output_rate > 0.0 && output_rate <= NES_AUDIO_SAMPLE_RATE) {
+            return None;
+        }
+        return Some(Resampler {
+            output_rate,
+            timer_step: output_rate,
+            timer: 0.0,
+            last_input: 0.0,
+            deltas: [0.0; STEP_WIDTH],
+            delta_index: 0,
+            level: 0.0,
+            steps: Box::new(calculate_steps()),
+        });
+    }
+
+    /// Gets the rate that output samples are produced at, in Hz
+    pub fn get_output_rate(&self) -> f64 {
+        return self.output_rate;
+    }
+
+    /// Sets the speed that the NES is running at, producing samples at the output rate divided by it, so that audio
+    /// played at the output rate keeps pace with the NES. The rate is limited to one sample for every input sample,
+    /// so speeds below the output rate's fraction of the APU's are slowed less than they should be.
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed.is_finite() && speed > 0.0 {
+            self.timer_step = (self.output_rate / speed).min(NES_AUDIO_SAMPLE_RATE);
+        }
+    }
+
+    /// Adds a sample from the APU, returning an output sample once enough have been added to produce one
+    pub fn push_sample(&mut self, sample: f32) -> Option<f32> {
+        let change = sample - self.last_input;
+        if change != 0.0 {
+            self.last_input = sample;
+            let phase = (self.timer / NES_AUDIO_SAMPLE_RATE * STEP_PHASES as f64) as usize;
+            for (offset, step) in self.steps[phase.min(STEP_PHASES - 1)].iter().enumerate() {
+                self.deltas[(self.delta_index + offset) % STEP_WIDTH] += change * step;
+            }
+        }
+
+        self.timer += self.timer_step;
+        if self.timer < NES_AUDIO_SAMPLE_RATE {
+            return None;
+        }
+        self.timer -= NES_AUDIO_SAMPLE_RATE;
+        self.level += std::mem::take(&mut self.deltas[self.delta_index]);
+        self.delta_index = (self.delta_index + 1) % STEP_WIDTH;
+        return Some(self.level);
+    }
+}
+
+/// Calculates the band-limited step for each phase. Each is a windowed sinc impulse centred on the step, sampled at the
+/// output samples around it and scaled so that the changes add up to exactly one, meaning the output settles on the
+/// same level as the input.
+fn calculate_steps() -> [[f32; STEP_WIDTH]; STEP_PHASES] {
+    let mut steps = [[0.0; STEP_WIDTH]; STEP_PHASES];
+    for (phase, step) in steps.iter_mut().enumerate() {
+        let mut impulse = [0.0; STEP_WIDTH];
+        for (offset, sample) in impulse.iter_mut().enumerate() {
+            // The distance in output samples from the step, which happens between output samples STEP_WIDTH / 2 - 1 and STEP_WIDTH / 2
+            let x = offset as f64 - (STEP_WIDTH / 2) as f64 + 1.0 - phase as f64 / STEP_PHASES as f64;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (std::f64::consts::PI * 2.0 * STEP_CUTOFF * x).sin() / (std::f64::consts::PI * 2.0 * STEP_CUTOFF * x)
+            };
+            // Blackman window, spanning the width of the step
+            let position = (x / STEP_WIDTH as f64) + 0.5;
+            let window = 0.42 - 0.5 * (2.0 * std::f64::consts::PI * position).cos() + 0.08 * (4.0 * std::f64::consts::PI * position).cos();
+            *sample = sinc * window;
+        }
+        let total: f64 = impulse.iter().sum();
+        for (change, sample) in step.iter_mut().zip(impulse.iter()) {
+            *change = (sample / total) as f32;
+        }
+    }
+    return steps;
+}
+
+/// Audio sink that holds the mixed samples until the frontend takes them, which is the sink used
+/// unless another is provided. Samples stop being collected once about a second's worth has built up.
+/// Stereo samples are held interleaved, with the left output of each sample before the right.
+#[derive(Default, Clone)]
+pub struct AudioSampleBuffer {
+    /// The mixed samples since they were last taken, oldest first
+    samples: Vec<f32>,
+    /// The resampler that the samples, or the left output of stereo samples, are converted to the output rate with,
+    /// or None to keep the APU's rate
+    resampler: Option<Resampler>,
+    /// The resampler that the right output of stereo samples is converted to the output rate with, or None
+    /// while the samples are mono
+    right_resampler: Option<Resampler>,
+}
+
+impl AudioSampleBuffer {
+    /// Creates a buffer that collects samples at the output rate in Hz, such as 44100 or 48000,
+    /// or None if the rate isn't positive or is higher than the rate that the APU produces samples at
+    pub fn with_sample_rate(output_rate: f64) -> Option<Self> {
+        return Some(AudioSampleBuffer {
+            samples: Vec::new(),
+            resampler: Some(Resampler::new(output_rate)?),
+            right_resampler: None,
+        });
+    }
+
+    /// Gets the rate that samples are collected at, in Hz
+    pub fn get_sample_rate(&self) -> f64 {
+        return self.resampler.as_ref().map_or(NES_AUDIO_SAMPLE_RATE, Resampler::get_output_rate);
+    }
+
+    /// Takes the samples collected since the last call, oldest first
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        return std::mem::take(&mut self.samples);
+    }
+
+    /// Takes up to the requested number of the oldest samples, leaving the rest in the buffer
+    pub fn take_samples_up_to(&mut self, count: usize) -> Vec<f32> {
+        if count >= self.samples.len() {
+            return self.take_samples();
+        }
+        let remaining = self.samples.split_off(count);
+        return std::mem::replace(&mut self.samples, remaining);
+    }
+
+    /// Gets the number of samples waiting to be taken
+    pub fn get_sample_count(&self) -> usize {
+        return self.samples.len();
+    }
+}
+
+impl AudioSink for AudioSampleBuffer {
+    fn push_sample(&mut self, sample: f32, _channels: ChannelOutputs) {
+        self.right_resampler = None;
+        let sample = match &mut self.resampler {
+            Some(resampler) => match resampler.push_sample(sample) {
+                Some(sample) => sample,
+                None => return,
+            },
+            None => sample,
+        };
+        if self.samples.len() < AUDIO_SAMPLE_LIMIT {
+            self.samples.push(sample);
+        }
+    }
+
+    fn push_stereo_sample(&mut self, left: f32, right: f32, _channels: ChannelOutputs) {
+        let (left, right) = match &mut self.resampler {
+            Some(resampler) => {
+                // The right output starts from a copy of the mono resampler, so the two produce their samples on the same cycles
+                let right_resampler = self.right_resampler.get_or_insert_with(|| resampler.clone());
+                match (resampler.push_sample(left), right_resampler.push_sample(right)) {
+                    (Some(left), Some(right)) => (left, right),
+                    _ => return,
+                }
+            }
+            None => (left, right),
+        };
+        if self.samples.len() + 2 <= AUDIO_SAMPLE_LIMIT {
+            self.samples.extend_from_slice(&[left, right]);
+        }
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        // The right resampler is cloned from the mono one, so it picks up the speed either way
+        if let Some(resampler) = &mut self.resampler {
+            resampler.set_speed(speed);
+        }
+        if let Some(resampler) = &mut self.right_resampler {
+            resampler.set_speed(speed);
+        }
+    }
+}
+
+/// Audio sink that wraps another sink, resampling the mixed output of the APU before passing it on.
+/// The channel outputs passed with each sample are those of the last APU sample that it was resampled from.
+#[derive(Clone)]
+pub struct ResamplingSink<A: AudioSink> {
+    /// The resampler that samples, or the left output of stereo samples, are converted to the output rate with
+    resampler: Resampler,
+    /// The resampler that the right output of stereo samples is converted to the output rate with, or None
+    /// while the samples are mono
+    right_resampler: Option<Resampler>,
+    /// The sink that the resampled samples are passed to
+    sink: A,
+}
+
+impl<A: AudioSink> ResamplingSink<A> {
+    /// Creates a sink that passes samples to another sink at the output rate in Hz, or None if
+    /// the rate isn't positive or is higher than the rate that the APU produces samples at
+    pub fn new(output_rate: f64, sink: A) -> Option<Self> {
+        return Some(ResamplingSink {
+            resampler: Resampler::new(output_rate)?,
+            right_resampler: None,
+            sink,
+        });
+    }
+
+    /// Gets a reference to the wrapped sink
+    pub fn get_sink(&self) -> &A {
+        return &self.sink;
+    }
+
+    /// Gets a mutable reference to the wrapped sink
+    pub fn get_sink_mut(&mut self) -> &mut A {
+        return &mut self.sink;
+    }
+}
+
+impl<A: AudioSink> AudioSink for ResamplingSink<A> {
+    fn push_sample(&mut self, sample: f32, channels: ChannelOutputs) {
+        self.right_resampler = None;
+        if let Some(sample) = self.resampler.push_sample(sample) {
+            self.sink.push_sample(sample, channels);
+        }
+    }
+
+    fn push_stereo_sample(&mut self, left: f32, right: f32, channels: ChannelOutputs) {
+        let resampler = &mut self.resampler;
+        let right_resampler = self.right_resampler.get_or_insert_with(|| resampler.clone());
+        if let (Some(left), Some(right)) = (resampler.push_sample(left), right_resampler.push_sample(right)) {
+            self.sink.push_stereo_sample(left, right, channels);
+        }
+    }
+
+    /// Resamples at the output rate divided by the speed. The wrapped sink isn't told about the speed, since the
+    /// samples it gets have already been adjusted for it.
+    fn set_speed(&mut self, speed: f64) {
+        self.resampler.set_speed(speed);
+        if let Some(resampler) = &mut self.right_resampler {
+            resampler.set_speed(speed);
+        }
+    }
+}
+
+/// Audio sink that discards all of the samples produced by the APU, for frontends without audio
+#[derive(Default, Clone)]
+pub struct NullAudioSink;
+
+impl AudioSink for NullAudioSink {
+    fn push_sample(&mut self, _sample: f32, _channels: ChannelOutputs) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sample_buffer_limit() {
+        let mut buffer = AudioSampleBuffer::default();
+        (0..AUDIO_SAMPLE_LIMIT + 10).for_each(|_| buffer.push_sample(0.5, Default::default()));
+        assert_eq!(AUDIO_SAMPLE_LIMIT, buffer.take_samples().len());
+        assert!(buffer.take_samples().is_empty());
+    }
+
+    #[test]
+    fn test_resampler() {
+        assert!(Resampler::new(0.0).is_none());
+        assert!(Resampler::new(f64::NAN).is_none());
+        assert!(Resampler::new(NES_AUDIO_SAMPLE_RATE * 2.0).is_none());
+
+        let mut buffer = AudioSampleBuffer::with_sample_rate(48_000.0).unwrap();
+        // A second of a square wave that alternates every CPU cycle is far too high to represent, leaving its midpoint
+        (0..NES_AUDIO_SAMPLE_RATE as usize).for_each(|cycle| buffer.push_sample((cycle % 2) as f32, Default::default()));
+        let samples = buffer.take_samples();
+        assert!((47_999..=48_000).contains(&samples.len()));
+        assert!(samples[STEP_WIDTH..].iter().all(|sample| (sample - 0.5).abs() < 0.05));
+    }
+
+    #[test]
+    fn test_resampler_speed() {
+        let mut resampler = Resampler::new(44_100.0).unwrap();
+        resampler.set_speed(2.0);
+        let samples = (0..NES_AUDIO_SAMPLE_RATE as usize).filter_map(|_| resampler.push_sample(0.5)).count();
+        assert!((22_049..=22_050).contains(&samples));
+        // Invalid speeds are ignored, and speeds too slow to resample are limited to one sample for every input sample
+        resampler.set_speed(0.0);
+        resampler.set_speed(0.000_001);
+        assert_eq!(1000, (0..1000).filter_map(|_| resampler.push_sample(0.5)).count());
+        assert_eq!(44_100.0, resampler.get_output_rate());
+    }
+
+    #[test]
+    fn test_resampler_step() {
+        let mut resampler = Resampler::new(44_100.0).unwrap();
+        let samples: Vec<f32> = (0..10_000).filter_map(|_| resampler.push_sample(0.75)).collect();
+        // The step is spread across the samples around it, ringing slightly before the output settles on the input's level
+        assert!(samples[..STEP_WIDTH / 2 - 1].iter().all(|sample| sample.abs() < 0.075));
+        assert!(samples[STEP_WIDTH..].iter().all(|sample| (sample - 0.75).abs() < 0.0001));
+    }
+}
@@ -0,0 +1,70 @@
+//! The stereo module contains the pan positions that the APU's channels are mixed into the left and right outputs
+//! with, for the "pseudo-stereo" that some frontends offer. The NES itself only has a mono output, so the NES mixes
+//! in mono unless [Nes::set_stereo_panning](../../nes/struct.Nes.html#method.set_stereo_panning) is called.
+//! ```
+//! use gc_nes_core::audio::stereo::StereoPanning;
+//! use gc_nes_core::cartridge::Cartridge;
+//! use gc_nes_core::nes::Nes;
+//!
+//! # fn run(cartridge: Cartridge) {
+//! let mut nes = Nes::new(cartridge);
+//! nes.set_stereo_panning(Some(StereoPanning::PSEUDO_STEREO));
+//! nes.set_audio_sample_rate(48_000.0);
+//! // The samples alternate between the left and right outputs
+//! let samples = nes.take_audio_samples();
+//! for frame in samples.chunks_exact(2) {
+//!     let (left, right) = (frame[0], frame[1]);
+//! }
+//! # }
+//! ```
+
+/// The pan position of each of the APU's channels, from -1.0 for fully left through 0.0 for the centre to 1.0 for
+/// fully right. A channel in the centre is mixed into both outputs at full volume, and panning it towards one side
+/// quiets it in the other, so panning every channel to the centre gives the same output as mixing in mono.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct StereoPanning {
+    /// The pan position of the first pulse channel
+    pub pulse_one: f32,
+    /// The pan position of the second pulse channel
+    pub pulse_two: f32,
+    /// The pan position of the triangle channel
+    pub triangle: f32,
+    /// The pan position of the noise channel
+    pub noise: f32,
+    /// The pan position of the delta modulation channel
+    pub dmc: f32,
+    /// The pan position of the cartridge's expansion audio
+    pub expansion: f32,
+}
+
+impl StereoPanning {
+    /// The common pseudo-stereo arrangement, with the pulse channels pulled apart to either side, the bass of the
+    /// triangle channel and the expansion audio in the centre, and the noise and delta modulation channels offset slightly
+    pub const PSEUDO_STEREO: StereoPanning = StereoPanning {
+        pulse_one: -0.5,
+        pulse_two: 0.5,
+        triangle: 0.0,
+        noise: 0.25,
+        dmc: -0.25,
+        expansion: 0.0,
+    };
+}
+
+/// Gets the volume that a channel at a pan position is mixed into the left and right outputs at, from 0.0 to 1.0
+pub(crate) fn pan_gains(pan: f32) -> (f32, f32) {
+    let pan = pan.clamp(-1.0, 1.0);
+    return ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pan_gains() {
+        assert_eq!((1.0, 1.0), pan_gains(0.0));
+        assert_eq!((1.0, 0.0), pan_gains(-1.0));
+        assert_eq!((0.5, 1.0), pan_gains(0.5));
+        assert_eq!((0.0, 1.0), pan_gains(4.0));
+    }
+}
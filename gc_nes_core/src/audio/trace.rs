@@ -0,0 +1,125 @@
+//! The trace module contains the record of the writes made to the APU's registers, which
+//! [Nes::set_apu_trace](../../nes/struct.Nes.html#method.set_apu_trace) enables, and the encoders that turn it into
+//! formats that other tools read: a plain text log with one write per line, and a VGM file for music rips.
+//! ```
+//! use gc_nes_core::audio::trace::encode_vgm;
+//! use gc_nes_core::cartridge::Cartridge;
+//! use gc_nes_core::nes::Nes;
+//!
+//! # fn run(cartridge: Cartridge) -> Vec<u8> {
+//! let mut nes = Nes::new(cartridge);
+//! nes.set_apu_trace(true);
+//! let start_cycle = nes.get_cpu_cycle_count();
+//! for _ in 0..600 {
+//!     nes.frame();
+//! }
+//! let writes = nes.take_apu_trace();
+//! for write in writes.iter() {
+//!     println!("{}", write);
+//! }
+//! return encode_vgm(&writes, start_cycle, nes.get_cpu_cycle_count());
+//! # }
+//! ```
+
+use crate::nes::NES_AUDIO_SAMPLE_RATE;
+use std::fmt;
+
+/// The sample rate that the waits between commands in a VGM file are measured at, in Hz
+const VGM_SAMPLE_RATE: f64 = 44_100.0;
+/// The size in bytes of the VGM header, which has room for every field up to version 1.61
+const VGM_HEADER_SIZE: usize = 0x100;
+/// The offset of the field of the VGM header that holds the clock rate of the NES APU
+const VGM_NES_APU_CLOCK_OFFSET: usize = 0x84;
+
+/// A single write to one of the APU's registers
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ApuRegisterWrite {
+    /// The CPU cycle that the write was made on, counted from when the NES was created
+    pub cycle: u64,
+    /// The address of the register, from $4000 to $4013, $4015 or $4017
+    pub register: u16,
+    /// The value written to the register
+    pub value: u8,
+}
+
+/// Formats the write as a line of a register log: the CPU cycle, then the register and value in hexadecimal,
+/// like `1234567 $4000=$BF`
+impl fmt::Display for ApuRegisterWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{} ${:04X}=${:02X}", self.cycle, self.register, self.value);
+    }
+}
+
+/// Encodes the writes made between two CPU cycles as a VGM file, which players and trackers can play back
+/// through their own NES APU emulation. The writes should be traced from before the music starts, so that the
+/// registers it relies on being set earlier are included. Samples played by the DMC channel are read from the
+/// cartridge rather than written to the APU, so they are missing from the file.
+pub fn encode_vgm(writes: &[ApuRegisterWrite], start_cycle: u64, end_cycle: u64) -> Vec<u8> {
+    let to_samples = |cycle: u64| (cycle.saturating_sub(start_cycle) as f64 * VGM_SAMPLE_RATE / NES_AUDIO_SAMPLE_RATE).round() as u64;
+
+    let mut vgm = vec![0; VGM_HEADER_SIZE];
+    let mut samples = 0;
+    for write in writes.iter().filter(|write| write.cycle <= end_cycle) {
+        let write_samples = to_samples(write.cycle);
+        push_vgm_wait(&mut vgm, write_samples - samples);
+        samples = write_samples;
+        vgm.extend_from_slice(&[0xb4, (write.register - 0x4000) as u8, write.value]);
+    }
+    push_vgm_wait(&mut vgm, to_samples(end_cycle).saturating_sub(samples));
+    samples = samples.max(to_samples(end_cycle));
+    vgm.push(0x66); // End of the sound data
+
+    let end_offset = vgm.len() as u32 - 4;
+    vgm[0x00..0x04].copy_from_slice(b"Vgm ");
+    vgm[0x04..0x08].copy_from_slice(&end_offset.to_le_bytes());
+    vgm[0x08..0x0c].copy_from_slice(&0x0161u32.to_le_bytes());
+    vgm[0x18..0x1c].copy_from_slice(&(samples as u32).to_le_bytes());
+    // The offset of the sound data is relative to the field itself
+    vgm[0x34..0x38].copy_from_slice(&(VGM_HEADER_SIZE as u32 - 0x34).to_le_bytes());
+    vgm[VGM_NES_APU_CLOCK_OFFSET..VGM_NES_APU_CLOCK_OFFSET + 4].copy_from_slice(&(NES_AUDIO_SAMPLE_RATE.round() as u32).to_le_bytes());
+    return vgm;
+}
+
+/// Adds the commands to a VGM file that wait for a number of samples, using the shortest form that fits
+fn push_vgm_wait(vgm: &mut Vec<u8>, mut samples: u64) {
+    while samples > 0 {
+        let wait = samples.min(u64::from(u16::MAX));
+        if wait <= 16 {
+            vgm.push(0x70 + (wait - 1) as u8);
+        } else {
+            vgm.push(0x61);
+            vgm.extend_from_slice(&(wait as u16).to_le_bytes());
+        }
+        samples -= wait;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_vgm() {
+        let writes = [
+            ApuRegisterWrite {
+                cycle: 1000,
+                register: 0x4015,
+                value: 0x0f,
+            },
+            ApuRegisterWrite {
+                cycle: 1000 + NES_AUDIO_SAMPLE_RATE as u64,
+                register: 0x4000,
+                value: 0xbf,
+            },
+        ];
+        assert_eq!("1000 $4015=$0F", writes[0].to_string());
+
+        let vgm = encode_vgm(&writes, 1000, 1000 + NES_AUDIO_SAMPLE_RATE as u64 + 410);
+        assert_eq!(b"Vgm ", &vgm[0..4]);
+        assert_eq!((vgm.len() - 4) as u32, u32::from_le_bytes([vgm[4], vgm[5], vgm[6], vgm[7]]));
+        assert_eq!(44_110, u32::from_le_bytes([vgm[0x18], vgm[0x19], vgm[0x1a], vgm[0x1b]]));
+        assert_eq!(1_789_773, u32::from_le_bytes([vgm[0x84], vgm[0x85], vgm[0x86], vgm[0x87]]));
+        // The first write is made straight away, then a second's worth of samples pass before the second
+        assert_eq!([0xb4, 0x15, 0x0f, 0x61, 0x44, 0xac, 0xb4, 0x00, 0xbf, 0x79, 0x66], vgm[VGM_HEADER_SIZE..]);
+    }
+}
@@ -0,0 +1,113 @@
+//! The wav module contains the [WavRecorder](struct.WavRecorder.html), which collects the audio
+//! samples taken from the NES and encodes them as a 16 bit PCM WAV file, for recording soundtracks.
+
+/// The size in bytes of the header that comes before the samples in a WAV file
+const WAV_HEADER_SIZE: usize = 44;
+/// The most samples that fit in a WAV file, whose size is stored in 32 bits
+const WAV_MAX_SAMPLES: usize = (u32::MAX as usize - WAV_HEADER_SIZE) / 2;
+
+/// Collects mono audio samples and encodes them as a 16 bit PCM WAV file.
+/// ```
+/// use gc_nes_core::audio::wav::WavRecorder;
+/// use gc_nes_core::cartridge::Cartridge;
+/// use gc_nes_core::nes::Nes;
+///
+/// # fn run(cartridge: Cartridge) -> Vec<u8> {
+/// let mut nes = Nes::new(cartridge);
+/// nes.set_audio_sample_rate(44_100.0);
+/// let mut recorder = WavRecorder::new(44_100);
+/// for _ in 0..600 {
+///     nes.frame();
+///     recorder.add_samples(&nes.take_audio_samples());
+/// }
+/// return recorder.encode();
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavRecorder {
+    /// The sample rate of the recording, in Hz
+    sample_rate: u32,
+    /// The samples recorded so far, oldest first
+    samples: Vec<i16>,
+}
+
+impl WavRecorder {
+    /// Creates an empty recording at the sample rate in Hz, which should match the rate the samples are taken at
+    pub fn new(sample_rate: u32) -> Self {
+        return WavRecorder {
+            sample_rate,
+            samples: Vec::new(),
+        };
+    }
+
+    /// Adds samples to the end of the recording. Samples are clamped to the range -1.0 to 1.0, which the NES' output,
+    /// from 0.0 to 1.0 or from -1.0 to 1.0 with the analog filters enabled, fits in without clipping.
+    /// Samples past the most that a WAV file can hold, about 13 hours' worth at 44.1 kHz, are dropped.
+    pub fn add_samples(&mut self, samples: &[f32]) {
+        let space = WAV_MAX_SAMPLES - self.samples.len();
+        self.samples.extend(
+            samples
+                .iter()
+                .take(space)
+                .map(|sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)).round() as i16),
+        );
+    }
+
+    /// Gets the sample rate of the recording, in Hz
+    pub fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    /// Gets the number of samples that have been recorded
+    pub fn get_sample_count(&self) -> usize {
+        return self.samples.len();
+    }
+
+    /// Encodes the recording as a mono 16 bit PCM WAV file
+    pub fn encode(&self) -> Vec<u8> {
+        let data_size = (self.samples.len() * 2) as u32;
+        let mut wav = Vec::with_capacity(WAV_HEADER_SIZE + data_size as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(WAV_HEADER_SIZE as u32 - 8 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        // The format chunk, describing a single channel of 16 bit PCM samples
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate.wrapping_mul(2)).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for sample in self.samples.iter() {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        return wav;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wav_recorder() {
+        let mut recorder = WavRecorder::new(44_100);
+        recorder.add_samples(&[0.0, 1.0, -2.0, 0.5]);
+        assert_eq!(4, recorder.get_sample_count());
+        let wav = recorder.encode();
+        assert_eq!(WAV_HEADER_SIZE + 8, wav.len());
+        assert_eq!(b"RIFF", &wav[0..4]);
+        assert_eq!((wav.len() - 8) as u32, u32::from_le_bytes([wav[4], wav[5], wav[6], wav[7]]));
+        assert_eq!(44_100, u32::from_le_bytes([wav[24], wav[25], wav[26], wav[27]]));
+        assert_eq!(b"data", &wav[36..40]);
+        assert_eq!(8, u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]));
+        let samples: Vec<i16> = wav[44..]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(vec![0, i16::MAX, -i16::MAX, 16384], samples);
+    }
+}
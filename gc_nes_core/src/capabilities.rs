@@ -0,0 +1,41 @@
+//! The capabilities module describes the features of the build of gc_nes_core that is in use,
+//! so that frontends (especially ones loading a prebuilt library) can adapt to the core they got.
+
+use crate::cartridge::{is_mapper_registered, SUPPORTED_MAPPERS};
+use crate::nes::ACCURACY_PRESET_NAMES;
+use crate::state::SAVE_STATE_VERSION;
+
+/// Structured description of the features supported by this build of gc_nes_core
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The version of the gc_nes_core crate
+    pub version: &'static str,
+    /// The iNES ids of the mappers built into the core that cartridges can be loaded with
+    pub supported_mappers: &'static [u16],
+    /// Whether or not the core produces audio
+    pub audio: bool,
+    /// The version of the save state format produced by the core, or None if save states are unsupported
+    pub save_state_version: Option<u32>,
+    /// The names of the emulation accuracy presets that can be selected, as accepted by
+    /// [AccuracyPreset::from_name](../nes/enum.AccuracyPreset.html#method.from_name)
+    pub accuracy_modes: &'static [&'static str],
+}
+
+impl Capabilities {
+    /// Checks if a mapper with the provided iNES id is supported, either built in or registered with
+    /// [Cartridge::register_mapper](../cartridge/struct.Cartridge.html#method.register_mapper)
+    pub fn supports_mapper(&self, mapper_id: u16) -> bool {
+        return self.supported_mappers.contains(&mapper_id) || is_mapper_registered(mapper_id);
+    }
+}
+
+/// Returns a description of the features supported by this build of gc_nes_core
+pub fn capabilities() -> Capabilities {
+    return Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        supported_mappers: SUPPORTED_MAPPERS,
+        audio: true,
+        save_state_version: Some(SAVE_STATE_VERSION),
+        accuracy_modes: ACCURACY_PRESET_NAMES,
+    };
+}
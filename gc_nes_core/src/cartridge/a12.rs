@@ -0,0 +1,98 @@
+//! The A12 module watches the PPU's address line A12 for mappers that count its rising edges, like the MMC3 does to
+//! count scanlines. A12 selects the pattern table at $1000, so it rises once a scanline when the background and sprites
+//! use different pattern tables, and whenever a game switches between them mid-frame.
+
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
+
+/// The number of PPU dots that A12 has to stay low for before it rising again is counted. The MMC3 waits for a few M2
+/// cycles, which ignores the nametable fetches between the pattern fetches from $1000 that briefly bring A12 low.
+const A12_FILTER_DOTS: u64 = 10;
+
+/// Watches the addresses that the PPU puts on its address bus for A12 rising
+#[derive(Debug, Clone)]
+pub(super) struct A12Watcher {
+    /// Whether A12 was high in the last address watched
+    high: bool,
+    /// The dot that A12 last fell at
+    low_since: u64,
+    /// Whether rises after A12 has only been low briefly are ignored. Without the filter, only the pattern table
+    /// fetches are watched, so that the nametable fetches between them don't bring A12 low.
+    filtering: bool,
+}
+
+impl A12Watcher {
+    /// Watches an address that the PPU put on its bus at a dot, counted from when the PPU was created.
+    /// Returns true if A12 rose and the rise should be counted.
+    pub(super) fn watch(&mut self, address: u16, dot: u64) -> bool {
+        if !self.filtering && address & 0x2000 != 0 {
+            return false;
+        }
+        let high = address & 0x1000 != 0;
+        let rose = high && !self.high && (!self.filtering || dot.saturating_sub(self.low_since) >= A12_FILTER_DOTS);
+        if self.high && !high {
+            self.low_since = dot;
+        }
+        self.high = high;
+        return rose;
+    }
+
+    /// Sets whether rises after A12 has only been low briefly are ignored
+    pub(super) fn set_filtering(&mut self, filtering: bool) {
+        self.filtering = filtering;
+    }
+
+    /// Writes the level of A12 and when it last fell to a save state. Whether it is filtered is an accuracy option.
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.high);
+        state.write_u64(self.low_since);
+    }
+
+    /// Restores the level of A12 and when it last fell from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.high = state.read_bool()?;
+        self.low_since = state.read_u64()?;
+        return Ok(());
+    }
+}
+
+impl Default for A12Watcher {
+    fn default() -> Self {
+        return A12Watcher {
+            high: false,
+            low_since: 0,
+            filtering: true,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a12_watcher_filtering() {
+        let mut watcher = A12Watcher::default();
+        // The background's fetches from $1000 are separated by nametable fetches, which are too short to count
+        assert!(watcher.watch(0x1000, 100));
+        assert!(!watcher.watch(0x2000, 102));
+        assert!(!watcher.watch(0x1008, 105));
+        // A12 stays low through the sprite fetches from $0000, long enough for the next rise to count
+        assert!(!watcher.watch(0x0ff0, 261));
+        assert!(!watcher.watch(0x2000, 321));
+        assert!(watcher.watch(0x1000, 325));
+    }
+
+    #[test]
+    fn test_a12_watcher_without_filtering() {
+        let mut watcher = A12Watcher::default();
+        watcher.set_filtering(false);
+        // Nametable fetches are ignored rather than bringing A12 low
+        assert!(watcher.watch(0x1000, 100));
+        assert!(!watcher.watch(0x2000, 102));
+        assert!(!watcher.watch(0x1008, 105));
+        // But any fetch from $0000 does, however briefly
+        assert!(!watcher.watch(0x0ff0, 107));
+        assert!(watcher.watch(0x1000, 109));
+    }
+}
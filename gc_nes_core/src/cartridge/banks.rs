@@ -0,0 +1,43 @@
+//! The banks module contains the snapshot of which banks of a cartridge's memory are switched into the
+//! CPU and PPU address spaces, for debuggers that show which bank the code is running from and for
+//! tools that attribute code/data logs to banks rather than addresses.
+
+/// The size in bytes of the program ROM windows that banks are reported for
+pub const PROGRAM_WINDOW_SIZE: usize = 8 * 1024; // 8 KiB
+/// The size in bytes of the character memory windows that banks are reported for
+pub const CHARACTER_WINDOW_SIZE: usize = 1024; // 1 KiB
+/// The number of program ROM windows, which cover $8000-$FFFF on the CPU bus
+pub const PROGRAM_WINDOW_COUNT: usize = 0x8000 / PROGRAM_WINDOW_SIZE;
+/// The number of character memory windows, which cover $0000-$1FFF on the PPU bus
+pub const CHARACTER_WINDOW_COUNT: usize = 0x2000 / CHARACTER_WINDOW_SIZE;
+
+/// Snapshot of the banks of a cartridge's memory that are switched into each window of the CPU and PPU address
+/// spaces. Banks are numbered in units of the window size from the start of the ROM, regardless of the size of the
+/// banks that the mapper switches, so a 16 KiB bank switched into $8000 appears as two consecutive 8 KiB banks.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct BankMapping {
+    /// The 8 KiB bank of program ROM switched into each window from $8000 to $FFFF, in address order
+    pub program_banks: [usize; PROGRAM_WINDOW_COUNT],
+    /// The 1 KiB bank of character memory switched into each window from $0000 to $1FFF, in address order
+    pub character_banks: [usize; CHARACTER_WINDOW_COUNT],
+}
+
+impl BankMapping {
+    /// Gets the 8 KiB bank of program ROM that an address from $8000 to $FFFF on the CPU bus reads from,
+    /// or None for addresses outside of program ROM
+    pub fn get_program_bank(&self, address: u16) -> Option<usize> {
+        return match address {
+            0x8000..=0xffff => Some(self.program_banks[usize::from(address - 0x8000) / PROGRAM_WINDOW_SIZE]),
+            _ => None,
+        };
+    }
+
+    /// Gets the 1 KiB bank of character memory that an address from $0000 to $1FFF on the PPU bus reads from,
+    /// or None for addresses outside of character memory
+    pub fn get_character_bank(&self, address: u16) -> Option<usize> {
+        return match address {
+            0x0000..=0x1fff => Some(self.character_banks[usize::from(address) / CHARACTER_WINDOW_SIZE]),
+            _ => None,
+        };
+    }
+}
@@ -0,0 +1,403 @@
+//! The info module contains the information that can be read from the header of a
+//! .nes file without loading the cartridge, so that frontends can check whether a
+//! ROM is supported before trying to run it.
+
+use super::*;
+use std::fmt;
+
+/// How well the emulator supports a mapper
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapperSupport {
+    /// The mapper is fully implemented
+    Full,
+    /// The mapper is implemented, but some of its behaviour is approximated, which may break some games
+    Partial {
+        /// A description of what is approximated
+        notes: &'static str,
+    },
+    /// The mapper is not implemented, and cartridges using it cannot be loaded
+    Unsupported,
+}
+
+/// The CPU and PPU timing that a cartridge was made for, which depends on the region it was sold in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConsoleTiming {
+    /// The timing of the North American and Japanese NES and Famicom
+    Ntsc,
+    /// The timing of the European NES
+    Pal,
+    /// The game works with either NTSC or PAL timing
+    MultiRegion,
+    /// The timing of the Dendy and other famiclones sold in Russia, which mixes the NTSC CPU divider with the PAL frame
+    Dendy,
+}
+
+/// The kind of console that a cartridge was made for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConsoleType {
+    /// The NES or Famicom
+    Nes,
+    /// The Vs. System arcade hardware
+    VsSystem,
+    /// The PlayChoice-10 arcade hardware
+    PlayChoice10,
+    /// One of the extended console types of NES 2.0, like famiclones with decimal mode or the VT0x chips, identified by
+    /// the number in the header's 14th byte
+    Extended(u8),
+}
+
+/// The error returned when loading a cartridge whose mapper isn't supported, which frontends can downcast
+/// the loading error to in order to tell the player which mapper the game needs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnsupportedMapper {
+    /// The iNES id of the cartridge's mapper
+    pub mapper_id: u16,
+    /// The NES 2.0 submapper id of the cartridge's mapper
+    pub submapper_id: u8,
+    /// The name of the chip or board that games with the mapper id usually use, if it is a well known one.
+    /// This is only a guess, since some ids cover several boards.
+    pub board_name: Option<&'static str>,
+}
+
+impl UnsupportedMapper {
+    /// Creates the error for a mapper and submapper id, guessing the board's name from the mapper id
+    pub(super) fn new(mapper_id: u16, submapper_id: u8) -> Self {
+        return UnsupportedMapper {
+            mapper_id,
+            submapper_id,
+            board_name: guess_board_name(mapper_id),
+        };
+    }
+}
+
+impl fmt::Display for UnsupportedMapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mapper {:03}", self.mapper_id)?;
+        if self.submapper_id != 0 {
+            write!(f, " submapper {}", self.submapper_id)?;
+        }
+        if let Some(board_name) = self.board_name {
+            write!(f, " ({})", board_name)?;
+        }
+        return write!(f, " not supported");
+    }
+}
+
+impl Error for UnsupportedMapper {}
+
+/// Guesses the name of the chip or board that games with a mapper id usually use, for the well known ones
+fn guess_board_name(mapper_id: u16) -> Option<&'static str> {
+    return Some(match mapper_id {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        13 => "CPROM",
+        16 => "Bandai FCG",
+        18 => "Jaleco SS88006",
+        19 => "Namco 163",
+        21 | 22 | 23 | 25 => "VRC2/VRC4",
+        24 | 26 => "VRC6",
+        28 => "Action 53",
+        30 => "UNROM 512",
+        32 => "Irem G-101",
+        33 => "Taito TC0190",
+        34 => "BNROM/NINA-001",
+        48 => "Taito TC0690",
+        64 => "Tengen RAMBO-1",
+        65 => "Irem H3001",
+        66 => "GxROM",
+        67 => "Sunsoft-3",
+        68 => "Sunsoft-4",
+        69 => "Sunsoft FME-7",
+        70 => "Bandai 74161",
+        71 => "Camerica BF909x",
+        73 => "VRC3",
+        75 => "VRC1",
+        76 => "Namco 3446",
+        79 => "NINA-03/NINA-06",
+        85 => "VRC7",
+        87 => "Jaleco JF-xx",
+        94 => "UN1ROM",
+        105 => "NES-EVENT",
+        118 => "TxSROM",
+        119 => "TQROM",
+        180 => "UNROM with AND logic",
+        184 => "Sunsoft-1",
+        185 => "CNROM with copy protection",
+        206 => "Namco 108",
+        210 => "Namco 175/340",
+        228 => "Action 52",
+        232 => "Camerica Quattro",
+        _ => return None,
+    });
+}
+
+/// Information about a cartridge that is read from the header of a .nes file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CartridgeInfo {
+    /// Whether the file is in the NES 2.0 format, rather than the original iNES format
+    pub nes2: bool,
+    /// The iNES id of the cartridge's mapper
+    pub mapper_id: u16,
+    /// The NES 2.0 submapper id of the cartridge's mapper
+    pub submapper_id: u8,
+    /// How well the emulator supports the cartridge's mapper
+    pub mapper_support: MapperSupport,
+    /// The size of the cartridge's program ROM in bytes
+    pub program_rom_size: usize,
+    /// The size of the cartridge's character ROM in bytes, zero if the cartridge uses character RAM
+    pub character_rom_size: usize,
+    /// The size of the cartridge's program RAM in bytes, both volatile and battery backed. iNES headers always give at
+    /// least 8 KiB, while NES 2.0 headers give zero for cartridges without any.
+    pub program_ram_size: usize,
+    /// Whether the nametables are mirrored vertically, rather than horizontally
+    pub vertical_mirroring: bool,
+    /// Whether the cartridge provides its own memory for four nametables
+    pub four_screen: bool,
+    /// Whether the cartridge has battery backed memory that persists between sessions
+    pub battery: bool,
+    /// Whether a 512 byte trainer precedes the program ROM in the file
+    pub trainer: bool,
+    /// The size of the battery backed part of the cartridge's program RAM in bytes, which is included in
+    /// program_ram_size. Only NES 2.0 headers give it, so it is zero for iNES headers.
+    pub program_nvram_size: usize,
+    /// The size of the cartridge's character RAM in bytes, both volatile and battery backed, or zero if the header
+    /// doesn't specify it and the mapper's usual size should be used. Only NES 2.0 headers give it.
+    pub character_ram_size: usize,
+    /// The size of the battery backed part of the cartridge's character RAM in bytes, which is included in
+    /// character_ram_size. Only NES 2.0 headers give it.
+    pub character_nvram_size: usize,
+    /// The CPU and PPU timing that the game was made for. iNES headers can only mark PAL games, and few do.
+    pub timing: ConsoleTiming,
+    /// The kind of console that the game was made for. iNES headers can't give the extended types.
+    pub console_type: ConsoleType,
+    /// The input device that the game expects to be plugged in by default, by its number in the NES 2.0 list of
+    /// default expansion devices, like 0x01 for standard controllers or 0x08 for the Zapper. Zero if the header
+    /// doesn't specify one, which is always the case for iNES headers.
+    pub expansion_device: u8,
+    /// Whether the ROM is for the Vs. System arcade hardware, rather than the NES itself
+    pub vs_system: bool,
+    /// Whether the ROM is for the Vs. DualSystem, two Vs. Systems linked together that each drive their own screen.
+    /// Only NES 2.0 headers can tell the DualSystem apart from the single Vs. System.
+    pub vs_dual_system: bool,
+}
+
+impl CartridgeInfo {
+    /// Reads the cartridge information from the 16 byte header of a .nes file
+    pub fn from_header(header: &[u8; 16]) -> Result<CartridgeInfo, Box<dyn Error>> {
+        if header[..IDENTIFICATION_STRING.len()] != IDENTIFICATION_STRING {
+            bail!("File format is invalid!");
+        }
+
+        let header_flags_6 = HeaderFlags6::from_bits_truncate(header[6]);
+        let header_flags_7 = HeaderFlags7::from_bits_truncate(header[7]);
+
+        // The file is NES 2.0 if the identifier bits are 0b10
+        let nes2: bool = header_flags_7 & HeaderFlags7::NES_2_IDENTIFIER == HeaderFlags7::NES_2_IDENTIFIER_VALUE;
+        let vs_system = header_flags_7 & HeaderFlags7::CONSOLE_TYPE == HeaderFlags7::CONSOLE_TYPE_VS_SYSTEM;
+        // The mapper id is made up of the mapper identification fragments in the 6th and 7th bytes of the header,
+        // and in NES 2.0 files the 8th byte, which also holds the submapper id
+        let mut mapper_id = u16::from(header[7] & HeaderFlags7::MAPPER_HI.bits) | u16::from(header[6] & HeaderFlags6::MAPPER_LO.bits) >> 4;
+        let mut submapper_id = 0;
+        let (program_ram_size, program_nvram_size) = if nes2 {
+            mapper_id |= u16::from(header[8] & 0x0f) << 8;
+            submapper_id = (header[8] & 0xf0) >> 4;
+            // The lower half of the 11th byte gives the size of the volatile program RAM, and the upper half the battery backed
+            let program_nvram_size = calculate_ram_size(header[10], 4);
+            (calculate_ram_size(header[10], 0) + program_nvram_size, program_nvram_size)
+        } else {
+            // The 9th byte of an iNES file gives the program RAM size in 8 KiB units, where 0 also means 8 KiB for compatibility
+            (usize::from(header[8].max(1)) * 0x2000, 0)
+        };
+        let (character_ram_size, character_nvram_size) = if nes2 {
+            // The 12th byte gives the character RAM sizes in the same way as the 11th byte gives the program RAM sizes
+            let character_nvram_size = calculate_ram_size(header[11], 4);
+            (calculate_ram_size(header[11], 0) + character_nvram_size, character_nvram_size)
+        } else {
+            (0, 0)
+        };
+        // NES 2.0 gives the timing in the lower two bits of the 13th byte, and iNES only marks PAL in the 10th byte
+        let timing = if nes2 {
+            match header[12] & 0x03 {
+                0 => ConsoleTiming::Ntsc,
+                1 => ConsoleTiming::Pal,
+                2 => ConsoleTiming::MultiRegion,
+                _ => ConsoleTiming::Dendy,
+            }
+        } else if header[9] & 0x01 != 0 {
+            ConsoleTiming::Pal
+        } else {
+            ConsoleTiming::Ntsc
+        };
+        let console_type = match header[7] & HeaderFlags7::CONSOLE_TYPE.bits {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::PlayChoice10,
+            // iNES headers don't have the extended console type, so only the NES 2.0 ones are extended
+            _ if nes2 => ConsoleType::Extended(header[13] & 0x0f),
+            _ => ConsoleType::Nes,
+        };
+
+        return Ok(CartridgeInfo {
+            nes2,
+            mapper_id,
+            submapper_id,
+            mapper_support: mapper::get_mapper_support(mapper_id),
+            program_rom_size: calculate_rom_size(header[4], header[9] & 0x0f, PROGRAM_ROM_BANK_SIZE, nes2)?,
+            character_rom_size: calculate_rom_size(header[5], header[9] >> 4, CHARACTER_ROM_BANK_SIZE, nes2)?,
+            program_ram_size,
+            program_nvram_size,
+            character_ram_size,
+            character_nvram_size,
+            timing,
+            console_type,
+            // The lower six bits of the 16th byte give the default expansion device
+            expansion_device: if nes2 { header[15] & 0x3f } else { 0 },
+            vertical_mirroring: header_flags_6.contains(HeaderFlags6::VERTICAL_MIRRORING),
+            four_screen: header_flags_6.contains(HeaderFlags6::FOUR_SCREEN_MODE),
+            battery: header_flags_6.contains(HeaderFlags6::PERSISTENT_MEMORY),
+            trainer: header_flags_6.contains(HeaderFlags6::TRAINER_PRESENT),
+            vs_system,
+            // The upper half of the 13th byte gives the Vs. hardware type, where 5 and 6 are the DualSystem
+            vs_dual_system: nes2 && vs_system && matches!(header[13] >> 4, 0x5 | 0x6),
+        });
+    }
+
+    /// Reads the cartridge information from the header at the start of a reader
+    pub fn read_from_reader<T: Read>(mut reader: T) -> Result<CartridgeInfo, Box<dyn Error>> {
+        let mut header: [u8; 16] = [0; 16];
+        reader.read_exact(&mut header)?;
+        return CartridgeInfo::from_header(&header);
+    }
+
+    /// Reads the cartridge information from the header of a .nes file
+    pub fn read_from_file(file_path: &Path) -> Result<CartridgeInfo, Box<dyn Error>> {
+        return CartridgeInfo::read_from_reader(File::open(file_path)?);
+    }
+}
+
+/// Calculates the MD5 hash of the ROM data in a .nes file, excluding the header and trainer.
+/// This is the checksum used to identify ROMs in FM2 movie files.
+pub fn calculate_rom_md5<T: Read>(mut reader: T) -> Result<[u8; 16], Box<dyn Error>> {
+    let mut header: [u8; 16] = [0; 16];
+    reader.read_exact(&mut header)?;
+    if CartridgeInfo::from_header(&header)?.trainer {
+        reader.read_exact(&mut [0; 512])?;
+    }
+    let mut rom = Vec::new();
+    reader.read_to_end(&mut rom)?;
+    return Ok(md5::compute(rom).0);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_header_ines() {
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0x43, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let info = CartridgeInfo::from_header(&header).unwrap();
+        assert!(!info.nes2);
+        assert_eq!(4, info.mapper_id);
+        assert_eq!(0x8000, info.program_rom_size);
+        assert_eq!(0x2000, info.character_rom_size);
+        assert!(info.vertical_mirroring);
+        assert!(info.battery);
+        assert!(!info.trainer);
+        assert!(matches!(info.mapper_support, MapperSupport::Full));
+    }
+
+    #[test]
+    fn test_from_header_program_ram_size() {
+        // iNES gives the size in 8 KiB units in the 9th byte, with 0 meaning 8 KiB
+        let mut header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0x10, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(0x2000, CartridgeInfo::from_header(&header).unwrap().program_ram_size);
+        header[8] = 0x04;
+        let info = CartridgeInfo::from_header(&header).unwrap();
+        assert_eq!(0x8000, info.program_ram_size);
+        // The 9th byte isn't part of the mapper id in iNES files
+        assert_eq!(1, info.mapper_id);
+        assert_eq!(0, info.submapper_id);
+        // NES 2.0 gives the volatile and battery backed sizes as shift counts in the 11th byte
+        header[7] = 0x08;
+        header[8] = 0x00;
+        header[10] = 0x70;
+        assert_eq!(0x2000, CartridgeInfo::from_header(&header).unwrap().program_ram_size);
+        header[10] = 0x77;
+        assert_eq!(0x4000, CartridgeInfo::from_header(&header).unwrap().program_ram_size);
+    }
+
+    #[test]
+    fn test_unsupported_mapper_display() {
+        assert_eq!("Mapper 085 (VRC7) not supported", UnsupportedMapper::new(85, 0).to_string());
+        assert_eq!("Mapper 261 submapper 1 not supported", UnsupportedMapper::new(261, 1).to_string());
+    }
+
+    #[test]
+    fn test_from_header_unsupported_mapper() {
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0x50, 0x08, 0x01, 0, 0, 0, 0, 0, 0, 0];
+        let info = CartridgeInfo::from_header(&header).unwrap();
+        assert!(info.nes2);
+        assert_eq!(0x105, info.mapper_id);
+        assert_eq!(MapperSupport::Unsupported, info.mapper_support);
+    }
+
+    #[test]
+    fn test_from_header_nes2_fields() {
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x00, 0x12, 0x0b, 0x10, 0, 0x77, 0x07, 0x03, 0x03, 0, 0x08];
+        let info = CartridgeInfo::from_header(&header).unwrap();
+        assert_eq!(1, info.submapper_id);
+        assert_eq!(0x4000, info.program_ram_size);
+        assert_eq!(0x2000, info.program_nvram_size);
+        assert_eq!(0x2000, info.character_ram_size);
+        assert_eq!(0, info.character_nvram_size);
+        assert_eq!(ConsoleTiming::Dendy, info.timing);
+        assert_eq!(ConsoleType::Extended(0x03), info.console_type);
+        assert_eq!(0x08, info.expansion_device);
+
+        // iNES headers only mark PAL games, in the 10th byte
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x00, 0x10, 0x02, 0, 0x01, 0, 0, 0, 0, 0, 0x08];
+        let info = CartridgeInfo::from_header(&header).unwrap();
+        assert_eq!(ConsoleTiming::Pal, info.timing);
+        assert_eq!(ConsoleType::PlayChoice10, info.console_type);
+        assert_eq!(0, info.expansion_device);
+        assert_eq!(0, info.character_ram_size);
+    }
+
+    #[test]
+    fn test_from_header_vs_dual_system() {
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x04, 0x02, 0x10, 0x09, 0, 0, 0, 0, 0, 0x50, 0, 0];
+        let info = CartridgeInfo::from_header(&header).unwrap();
+        assert!(info.vs_system);
+        assert!(info.vs_dual_system);
+        // iNES headers only mark the Vs. System, without the hardware type
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x04, 0x02, 0x10, 0x01, 0, 0, 0, 0, 0, 0x50, 0, 0];
+        let info = CartridgeInfo::from_header(&header).unwrap();
+        assert!(info.vs_system);
+        assert!(!info.vs_dual_system);
+    }
+
+    #[test]
+    fn test_calculate_rom_md5_skips_header() {
+        let mut file = vec![0x4e, 0x45, 0x53, 0x1a, 0x01, 0x00, 0x04, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        file.extend_from_slice(&[0xff; 512]);
+        file.extend_from_slice(b"abc");
+        // The MD5 hash of "abc"
+        let expected = [
+            0x90, 0x01, 0x50, 0x98, 0x3c, 0xd2, 0x4f, 0xb0, 0xd6, 0x96, 0x3f, 0x7d, 0x28, 0xe1, 0x7f, 0x72,
+        ];
+        assert_eq!(expected, calculate_rom_md5(file.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_from_header_invalid() {
+        CartridgeInfo::from_header(&[0; 16]).expect_err("Did not produce an error for an invalid header");
+    }
+}
@@ -1,13 +1,80 @@
 //! The mapper module contains implementation code for the various
 //! types of mapping circuits that were present in NES cartridges.
 //!
-//! At present only iNES mappers 000 through 004 are supported.
+//! At present only iNES mappers 000 through 004, 009, 011, 024, 026, 028, 030 and 185 are supported.
+//! The mappers past 004 are only built with the extra-mappers feature, which builds that need to stay small can leave out.
+//! Other crates can add their own mappers, or replace these, with [Cartridge::register_mapper](../struct.Cartridge.html#method.register_mapper).
 
+#[cfg(feature = "extra-mappers")]
+use super::vrc6::Vrc6Audio;
 use super::*;
+use crate::timing::MasterClock;
+use std::sync::RwLock;
+
+/// The number of master clock cycles in each CPU cycle
+const CPU_DIVIDER: u64 = MasterClock::NTSC.cpu_divider as u64;
+
+/// A function that creates a new mapper for a cartridge, given the cartridge's NES 2.0 submapper id
+pub type MapperFactory = fn(submapper_id: u8) -> Box<dyn Mapper>;
+
+/// The mappers registered by other crates, which are used instead of the built in mappers with the same ids
+static REGISTERED_MAPPERS: RwLock<Vec<(u16, MapperFactory)>> = RwLock::new(Vec::new());
+
+/// Registers a mapper for cartridges with the mapper_id argument to be loaded with, replacing any built in or
+/// previously registered mapper with the same id
+pub(super) fn register_mapper(mapper_id: u16, factory: MapperFactory) {
+    let mut registered = REGISTERED_MAPPERS.write().unwrap_or_else(|error| error.into_inner());
+    registered.retain(|(id, _)| *id != mapper_id);
+    registered.push((mapper_id, factory));
+}
+
+/// Gets the factory registered for the mapper with the mapper_id argument, if there is one
+fn get_registered_mapper(mapper_id: u16) -> Option<MapperFactory> {
+    let registered = REGISTERED_MAPPERS.read().unwrap_or_else(|error| error.into_inner());
+    return registered.iter().find(|(id, _)| *id == mapper_id).map(|(_, factory)| *factory);
+}
+
+/// Checks if a mapper has been registered for the mapper_id argument
+pub(crate) fn is_mapper_registered(mapper_id: u16) -> bool {
+    return get_registered_mapper(mapper_id).is_some();
+}
+
+/// The iNES ids of all of the mappers that can be returned by [get_mapper](fn.get_mapper.html)
+#[cfg(feature = "extra-mappers")]
+pub(crate) const SUPPORTED_MAPPERS: &[u16] = &[0, 1, 2, 3, 4, 9, 11, 24, 26, 28, 30, 185];
+/// The iNES ids of all of the mappers that can be returned by [get_mapper](fn.get_mapper.html)
+#[cfg(not(feature = "extra-mappers"))]
+pub(crate) const SUPPORTED_MAPPERS: &[u16] = &[0, 1, 2, 3, 4];
+
+/// Returns how well the mapper with the mapper_id argument is supported
+pub(super) fn get_mapper_support(mapper_id: u16) -> MapperSupport {
+    if is_mapper_registered(mapper_id) {
+        return MapperSupport::Full;
+    }
+    return match mapper_id {
+        #[cfg(feature = "extra-mappers")]
+        24 | 26 => MapperSupport::Partial {
+            notes: "Only the 1 KiB character banking mode that every licensed game uses is emulated",
+        },
+        #[cfg(feature = "extra-mappers")]
+        30 => MapperSupport::Partial {
+            notes: "Four screen mirroring and the flash memory's software ID mode aren't emulated",
+        },
+        #[cfg(feature = "extra-mappers")]
+        185 => MapperSupport::Partial {
+            notes: "Without a NES 2.0 submapper the value that enables character ROM is guessed, which fails for a few games",
+        },
+        id if SUPPORTED_MAPPERS.contains(&id) => MapperSupport::Full,
+        _ => MapperSupport::Unsupported,
+    };
+}
 
 /// Returns a boxed mapper based on the mapper_id argument
 pub(super) fn get_mapper(mapper_id: u16, submapper_id: u8) -> Result<Box<dyn Mapper>, Box<dyn Error>> {
     debug!("Getting mapper with id {}, submapper {}", mapper_id, submapper_id);
+    if let Some(factory) = get_registered_mapper(mapper_id) {
+        return Ok(factory(submapper_id));
+    }
     match mapper_id {
         0 => Ok(Box::new(Mapper000 {})),
         1 => Ok(Box::new(Mapper001 {
@@ -16,6 +83,9 @@ pub(super) fn get_mapper(mapper_id: u16, submapper_id: u8) -> Result<Box<dyn Map
             character_bank_0_register: 0,
             character_bank_1_register: 0,
             program_bank_register: 0,
+            program_ram_banks: 1,
+            master_clock: 0,
+            last_write_clock: None,
         })),
         2 => Ok(Box::new(Mapper002 { bank_select: 0x00 })),
         3 => Ok(Box::new(Mapper003 { bank_select: 0x00 })),
@@ -31,13 +101,99 @@ pub(super) fn get_mapper(mapper_id: u16, submapper_id: u8) -> Result<Box<dyn Map
             interrupt_request_enabled: false,
             pending_interrupt_request: false,
         })),
-        _ => bail!("Mapper ID {:03} unsupported!", mapper_id),
+        #[cfg(feature = "extra-mappers")]
+        9 => Ok(Box::new(Mapper009 {
+            program_bank_select: 0x00,
+            character_bank_select: [[0x00; 2]; 2],
+            character_latches: [CharacterLatch::Fe; 2],
+            mirroring: Mirroring::Vertical,
+        })),
+        #[cfg(feature = "extra-mappers")]
+        11 => Ok(Box::new(Mapper011 { bank_select: 0x00 })),
+        #[cfg(feature = "extra-mappers")]
+        24 | 26 => Ok(Box::new(Mapper024 {
+            swapped_register_lines: mapper_id == 26,
+            program_bank_select: [0x00; 2],
+            character_bank_select: [0x00; 8],
+            banking_control: 0x00,
+            interrupt_request_latch: 0,
+            interrupt_request_counter: 0,
+            interrupt_request_prescaler: 0,
+            master_clock: 0,
+            interrupt_request_enabled: false,
+            interrupt_request_enabled_after_acknowledge: false,
+            interrupt_request_cycle_mode: false,
+            pending_interrupt_request: false,
+            audio: Default::default(),
+        })),
+        #[cfg(feature = "extra-mappers")]
+        28 => Ok(Box::new(Mapper028 {
+            register_select: 0x00,
+            character_bank: 0x00,
+            inner_bank: 0x00,
+            // The menu is in the last bank, which the outer bank starts on in 32 KiB mode
+            mode: 0x00,
+            outer_bank: 0xff,
+        })),
+        #[cfg(feature = "extra-mappers")]
+        30 => Ok(Box::new(Mapper030 {
+            bank_select: 0x00,
+            one_screen_mirroring: false,
+            flash: Vec::new(),
+            flash_command: FlashCommand::Ready,
+            flash_generation: 0,
+        })),
+        #[cfg(feature = "extra-mappers")]
+        185 => Ok(Box::new(Mapper185 {
+            submapper_id,
+            character_enabled: false,
+        })),
+        _ => Err(Box::new(UnsupportedMapper::new(mapper_id, submapper_id))),
+    }
+}
+
+/// Reads from cartridge memory, wrapping indices past the end around to the start. Bank numbers that are
+/// too large for an undersized or misdumped ROM mirror its banks instead of panicking, like the unconnected
+/// address lines on a real board would. Empty memory reads as 0.
+fn read_wrapping(memory: &[u8], index: usize) -> u8 {
+    return if memory.is_empty() { 0x00 } else { memory[index % memory.len()] };
+}
+
+/// Writes to cartridge memory, wrapping indices past the end around to the start. Writes to empty memory are ignored.
+fn write_wrapping(memory: &mut [u8], index: usize, data: u8) {
+    if !memory.is_empty() {
+        let length = memory.len();
+        memory[index % length] = data;
     }
 }
 
+/// Writes a mirroring mode selected by a mapper's registers to a save state
+fn write_mirroring(state: &mut StateWriter, mirroring: Mirroring) {
+    state.write_u8(mirroring as u8);
+}
+
+/// Reads a mirroring mode written by write_mirroring from a save state
+fn read_mirroring(state: &mut StateReader) -> Result<Mirroring, Box<dyn Error>> {
+    return match state.read_u8()? {
+        0 => Ok(Mirroring::OneScreenLower),
+        1 => Ok(Mirroring::OneScreenUpper),
+        2 => Ok(Mirroring::Vertical),
+        3 => Ok(Mirroring::Horizontal),
+        mirroring => bail!("Invalid mirroring in save state: {}", mirroring),
+    };
+}
+
 /// The circuit in the cartridge that is reponsible for mapping the addresses provided by the cpu to the onboard memory.
-/// ROM only for now.
-pub(super) trait Mapper {
+///
+/// The cartridge owns its memory and passes it to the mapper on each access: the program ROM, the program RAM mapped
+/// from $6000 to $7FFF, and the character memory, which is the character ROM or, for cartridges without any, the
+/// character RAM. Mappers bank that memory by mapping addresses to indices into it, which wrap around the end of
+/// the memory, so banks selected past the end of an undersized ROM mirror the start instead of panicking.
+/// Mappers that don't switch banks only need an empty impl, since every method defaults to how a cartridge without a
+/// mapper behaves, and they need to be Clone so that the cartridge can be.
+///
+/// Other crates can add mappers with [Cartridge::register_mapper](struct.Cartridge.html#method.register_mapper).
+pub trait Mapper: MapperClone {
     /// Read from the cartridge's program ROM/RAM through the cartridge's mapper
     fn program_read(&self, program_rom: &[u8], program_ram: &[u8], address: u16) -> u8 {
         match address {
@@ -45,39 +201,39 @@ pub(super) trait Mapper {
                 warn!("Mapper read from {:04X}", address);
                 return 0x00;
             }
-            0x6000..=0x7fff => {
-                if program_ram.is_empty() {
-                    0x00
-                } else {
-                    program_ram[usize::from(address - 0x6000) % program_ram.len()]
-                }
-            }
-            0x8000..=0xffff => {
-                if program_rom.is_empty() {
-                    0x00
-                } else {
-                    program_rom[usize::from(address - 0x8000) % program_rom.len()]
-                }
-            }
+            0x6000..=0x7fff => read_wrapping(program_ram, usize::from(address - 0x6000)),
+            0x8000..=0xffff => read_wrapping(program_rom, self.program_index(program_rom.len(), address)),
         }
     }
 
+    /// Maps an address on the CPU bus from $8000 to $FFFF to an index into the cartridge's program ROM, which may be
+    /// past the end of the ROM if it is undersized. Mappers that fix a bank to the end of the ROM need its size in bytes.
+    fn program_index(&self, _program_rom_size: usize, address: u16) -> usize {
+        return usize::from(address - 0x8000);
+    }
+
     /// Read from the cartridge's character ROM/RAM through the cartridge's mapper
     fn character_read(&self, character_ram: &[u8], address: u16) -> u8 {
-        return character_ram[usize::from(address)];
+        return read_wrapping(character_ram, self.character_index(address));
     }
 
     /// Write to the cartridge's program RAM through the cartridge's mapper
     fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
         match address {
-            0x6000..=0x7fff => program_ram[usize::from(address - 0x6000)] = data,
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
             _ => warn!("Mapper::program_write called with invalid address 0x{:4X}", address),
         }
     }
 
     /// Write to the cartridge's character RAM through the cartridge's mapper
     fn character_write(&mut self, character_ram: &mut [u8], address: u16, data: u8) {
-        character_ram[usize::from(address)] = data;
+        write_wrapping(character_ram, self.character_index(address), data);
+    }
+
+    /// Maps an address on the PPU bus from $0000 to $1FFF to an index into the cartridge's character memory,
+    /// which may be past the end of the memory if the ROM is undersized
+    fn character_index(&self, address: u16) -> usize {
+        return usize::from(address);
     }
 
     /// Get the mirroring mode from the cartridge
@@ -90,66 +246,182 @@ pub(super) trait Mapper {
         return false;
     }
 
-    /// Called at the end of each scanline. Used by iNES Mapper 004 to
+    /// Called when the PPU's address line A12 rises, which happens once a scanline while the background and sprites
+    /// use different pattern tables. Used by iNES Mapper 004 to count scanlines and
     /// trigger interrupt requests at specific times during screen rendering
-    fn end_of_scanline(&mut self) {}
+    fn a12_rising_edge(&mut self) {}
+
+    /// Checks if writes to the mapper's registers in program ROM are ANDed with the ROM's value at the address,
+    /// because the ROM outputs it onto the data bus at the same time. Games avoid the conflicts by writing to
+    /// addresses that already hold the value being written.
+    fn has_bus_conflicts(&self) -> bool {
+        return false;
+    }
+
+    /// Called after the PPU reads from character memory. Used by iNES Mapper 009 to
+    /// switch character banks when the PPU fetches particular tiles
+    fn character_read_notify(&mut self, _address: u16) {}
+
+    /// Gets the state that the mapper keeps in its own non-volatile memory, such as the game last selected on a
+    /// multicart or the DIP switches of the NWC board, which is saved alongside battery backed program RAM.
+    /// Returns None for mappers without any, and the same length of state every time for mappers with it.
+    fn get_nonvolatile_state(&self) -> Option<&[u8]> {
+        return None;
+    }
+
+    /// Restores state previously returned by get_nonvolatile_state, which has already been checked to be the same length
+    fn load_nonvolatile_state(&mut self, _data: &[u8]) {}
+
+    /// Gets the number of writes that have changed the mapper's non-volatile state, which the cartridge watches
+    /// to know when it needs to be saved again
+    fn get_nonvolatile_generation(&self) -> u64 {
+        return 0;
+    }
+
+    /// Writes the mapper's registers, and anything else about it that changes as the game runs, to a save state.
+    /// The cartridge saves its memory and the mapper's non-volatile state itself, so mappers without any other state,
+    /// like NROM, can keep the default, which writes nothing.
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    /// Restores the state written by save_state, reading it back in the same order it was written
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        return Ok(());
+    }
+
+    /// Called once the cartridge's ROM has been loaded, for mappers that depend on more of the header than their ids,
+    /// or that keep their own copy of the program ROM, like the self-flashable UNROM 512. Called again whenever the
+    /// ROM data is reloaded in place, so it shouldn't reset the mapper's registers.
+    fn load_rom(&mut self, _info: &CartridgeInfo, _program_rom: &[u8]) {}
+
+    /// Gets the number of bytes of character RAM that the mapper banks for cartridges without character ROM
+    fn get_character_ram_size(&self) -> usize {
+        return 0x2000;
+    }
+
+    /// Called every CPU cycle with the cycle of the NES' master clock that it runs on. Used by iNES Mappers 024 and 026
+    /// to clock their interrupt counters, which count CPU cycles rather than watching the PPU, and by Mapper 001 to
+    /// time writes. Mappers should time things by the master clock rather than counting the calls themselves.
+    fn cpu_cycle(&mut self, _master_clock: u64) {}
+
+    /// Called every CPU cycle to clock the cartridge's expansion audio, for mappers with their own sound
+    /// channels such as the VRC6, FME-7, MMC5 and the Famicom Disk System
+    fn audio_cycle(&mut self) {}
+
+    /// Gets the current output of the cartridge's expansion audio, which is added to the mixed output of the APU.
+    /// Scaled so that 1.0 is as loud as the APU at full volume, with 0.0 for mappers without expansion audio.
+    fn audio_output(&self) -> f32 {
+        return 0.0;
+    }
+}
+
+/// Trait that allows boxed mappers to be cloned, implemented for every mapper that implements Clone
+pub trait MapperClone {
+    /// Clones the mapper into a new box
+    fn box_clone(&self) -> Box<dyn Mapper>;
+}
+
+impl<T: 'static + Mapper + Clone> MapperClone for T {
+    fn box_clone(&self) -> Box<dyn Mapper> {
+        return Box::new(self.clone());
+    }
+}
+
+impl Clone for Box<dyn Mapper> {
+    fn clone(&self) -> Self {
+        return self.box_clone();
+    }
 }
 
 /// Mapper struct for the NROM Mapper, which is given the iNES id of 000
+#[derive(Clone)]
 pub(super) struct Mapper000 {}
 
 impl Mapper for Mapper000 {}
 
-/// Mapper struct for the SxROM Mappers, which are given the iNES id of 001
+/// Mapper struct for the SxROM Mappers, which are given the iNES id of 001.
+/// The SUROM and SXROM boards with 512KiB of program ROM use bit 4 of the first character bank register to select
+/// which 256KiB half the program banks come from, and the SOROM and SXROM boards with 16KiB or 32KiB of program RAM
+/// use bits 2 and 3 to select its 8KiB bank.
+#[derive(Clone)]
 pub(super) struct Mapper001 {
     load_register: u8,
     control_register: u8,
     character_bank_0_register: u8,
     character_bank_1_register: u8,
     program_bank_register: u8,
+    /// The number of 8KiB banks of program RAM on the board
+    program_ram_banks: usize,
+    /// The cycle of the master clock that the last CPU cycle ran on
+    master_clock: u64,
+    /// The cycle of the master clock that the last write to a register came on, if there has been one
+    last_write_clock: Option<u64>,
+}
+
+impl Mapper001 {
+    /// Maps an address from $6000 to $7FFF to an index into program RAM, through the bank selected on boards with more than 8KiB
+    fn program_ram_index(&self, address: u16) -> usize {
+        let bank = match self.program_ram_banks {
+            4 => usize::from(self.character_bank_0_register >> 2) & 0x03,
+            2 => usize::from(self.character_bank_0_register >> 3) & 0x01,
+            _ => 0,
+        };
+        return usize::from(address - 0x6000) + bank * 0x2000;
+    }
 }
 
 impl Mapper for Mapper001 {
     fn program_read(&self, program_rom: &[u8], program_ram: &[u8], address: u16) -> u8 {
-        match address {
-            0x0000..=0x5fff => {
+        return match address {
+            0x6000..=0x7fff => read_wrapping(program_ram, self.program_ram_index(address)),
+            0x8000..=0xffff => read_wrapping(program_rom, self.program_index(program_rom.len(), address)),
+            _ => {
                 warn!("Mapper001 read from {:04X}", address);
-                return 0x00;
+                0x00
             }
-            0x6000..=0x7fff => {
-                if self.program_bank_register & 0x10 > 0 && program_ram.is_empty() {
-                    0x00
-                } else {
-                    program_ram[usize::from(address - 0x6000) % program_ram.len()]
-                }
-            }
-            0x8000..=0xffff => match ((self.control_register & 0x0c) >> 2, address) {
-                (0, _) => program_rom[usize::from(address & 0x7fff)],
-                (1, _) => program_rom[usize::from(address & 0x7fff) + ((self.program_bank_register as usize & 0x0e) * 0x4000)],
-                (2, 0x8000..=0xbfff) => program_rom[usize::from(address & 0x3fff)],
-                (2, 0xc000..=0xffff) => program_rom[usize::from(address & 0x3fff) + ((self.program_bank_register as usize & 0x0f) * 0x4000)],
-                (3, 0x8000..=0xbfff) => program_rom[usize::from(address & 0x3fff) + ((self.program_bank_register as usize & 0x0f) * 0x4000)],
-                (3, 0xc000..=0xffff) => {
-                    program_rom[(usize::from(address & 0x3fff) + ((program_rom.len() / 0x4000 - 1) * 0x4000)) % program_rom.len()]
-                }
-                _ => unreachable!(),
-            },
-        }
+        };
     }
 
-    fn character_read(&self, character_ram: &[u8], address: u16) -> u8 {
+    fn program_index(&self, program_rom_size: usize, address: u16) -> usize {
+        // The bank registers only reach 256KiB, so larger ROMs are banked as two halves, each with its own fixed last bank
+        let outer_bank = if program_rom_size > 0x40000 {
+            usize::from(self.character_bank_0_register & 0x10) * 0x4000
+        } else {
+            0
+        };
+        let last_bank = (program_rom_size.min(0x40000) / 0x4000).saturating_sub(1);
+        return outer_bank
+            + match ((self.control_register & 0x0c) >> 2, address) {
+                (0, _) | (1, _) => usize::from(address & 0x7fff) + ((self.program_bank_register as usize & 0x0e) * 0x4000),
+                (2, 0x8000..=0xbfff) => usize::from(address & 0x3fff),
+                (2, 0xc000..=0xffff) => usize::from(address & 0x3fff) + ((self.program_bank_register as usize & 0x0f) * 0x4000),
+                (3, 0x8000..=0xbfff) => usize::from(address & 0x3fff) + ((self.program_bank_register as usize & 0x0f) * 0x4000),
+                (3, 0xc000..=0xffff) => usize::from(address & 0x3fff) + (last_bank * 0x4000),
+                _ => panic!("Mapper001::program_index called with invalid address: 0x{:04X}", address),
+            };
+    }
+
+    fn character_index(&self, address: u16) -> usize {
         return match (self.control_register & 0x10, address) {
-            (0x00, 0x0000..=0x1fff) => character_ram[(address as usize) + ((self.character_bank_0_register as usize & 0x1e) * 0x1000)],
-            (0x10, 0x0000..=0x0fff) => character_ram[(address & 0x0fff) as usize + (self.character_bank_0_register as usize * 0x1000)],
-            (0x10, 0x1000..=0x1fff) => character_ram[(address & 0x0fff) as usize + (self.character_bank_1_register as usize * 0x1000)],
+            (0x00, 0x0000..=0x1fff) => (address as usize) + ((self.character_bank_0_register as usize & 0x1e) * 0x1000),
+            (0x10, 0x0000..=0x0fff) => (address & 0x0fff) as usize + (self.character_bank_0_register as usize * 0x1000),
+            (0x10, 0x1000..=0x1fff) => (address & 0x0fff) as usize + (self.character_bank_1_register as usize * 0x1000),
             _ => unreachable!(),
         };
     }
 
     fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
         match address {
-            0x6000..=0x7fff => program_ram[usize::from(address - 0x6000)] = data,
+            0x6000..=0x7fff => write_wrapping(program_ram, self.program_ram_index(address), data),
             0x8000..=0xffff => {
+                // The serial port ignores writes on the cycle after another, so only the first of the two writes
+                // that read-modify-write instructions make is seen
+                let consecutive = self
+                    .last_write_clock
+                    .is_some_and(|clock| self.master_clock.saturating_sub(clock) <= CPU_DIVIDER);
+                self.last_write_clock = Some(self.master_clock);
+                if consecutive {
+                    return;
+                }
                 if data & 0x80 == 0 {
                     // Boolean to determine if the load register should be copied into the target register
                     // after this bit is written.
@@ -171,17 +443,16 @@ impl Mapper for Mapper001 {
                     self.load_register = 0x10
                 }
             }
-            _ => warn!("Mapper000::program_write called with invalid address 0x{:4X}", address),
+            _ => warn!("Mapper001::program_write called with invalid address 0x{:4X}", address),
         }
     }
 
-    fn character_write(&mut self, character_ram: &mut [u8], address: u16, data: u8) {
-        match (self.control_register & 0x10, address) {
-            (0x00, 0x0000..=0x1fff) => character_ram[(address as usize) + ((self.character_bank_0_register as usize & 0x1e) * 0x1000)] = data,
-            (0x01, 0x0000..=0x0fff) => character_ram[(address & 0x0fff) as usize + (self.character_bank_0_register as usize * 0x1000)] = data,
-            (0x01, 0x1000..=0x1fff) => character_ram[(address & 0x0fff) as usize + (self.character_bank_1_register as usize * 0x1000)] = data,
-            _ => unreachable!(),
-        }
+    fn load_rom(&mut self, info: &CartridgeInfo, _program_rom: &[u8]) {
+        self.program_ram_banks = (info.program_ram_size / 0x2000).max(1);
+    }
+
+    fn cpu_cycle(&mut self, master_clock: u64) {
+        self.master_clock = master_clock;
     }
 
     fn get_mirroring(&mut self, _mirroring: Mirroring) -> Mirroring {
@@ -193,57 +464,79 @@ impl Mapper for Mapper001 {
             _ => unreachable!(),
         };
     }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.load_register);
+        state.write_u8(self.control_register);
+        state.write_u8(self.character_bank_0_register);
+        state.write_u8(self.character_bank_1_register);
+        state.write_u8(self.program_bank_register);
+        state.write_u64(self.master_clock);
+        state.write_option_u64(self.last_write_clock);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.load_register = state.read_u8()?;
+        self.control_register = state.read_u8()?;
+        self.character_bank_0_register = state.read_u8()?;
+        self.character_bank_1_register = state.read_u8()?;
+        self.program_bank_register = state.read_u8()?;
+        self.master_clock = state.read_u64()?;
+        self.last_write_clock = state.read_option_u64()?;
+        return Ok(());
+    }
 }
 
 /// Mapper struct for the UxROM Mappers, which are given the iNES id of 002
+#[derive(Clone)]
 pub(super) struct Mapper002 {
     bank_select: u8,
 }
 
 impl Mapper for Mapper002 {
-    fn program_read(&self, program_rom: &[u8], program_ram: &[u8], address: u16) -> u8 {
-        match address {
-            0x0000..=0x5fff => {
-                warn!("Mapper000 read from {:04X}", address);
-                return 0x00;
-            }
-            0x6000..=0x7fff => {
-                if program_ram.is_empty() {
-                    0x00
-                } else {
-                    program_ram[usize::from(address - 0x6000) % program_ram.len()]
-                }
-            }
+    fn program_index(&self, program_rom_size: usize, address: u16) -> usize {
+        return match address {
             // Pick a bank based on the internal register
-            0x8000..=0xbfff => program_rom[usize::from(address & 0x3fff) + (self.bank_select as usize * 0x4000)],
+            0x8000..=0xbfff => usize::from(address & 0x3fff) + (self.bank_select as usize * 0x4000),
             // Always points to the last program rom bank
-            0xc000..=0xffff => program_rom[usize::from(address & 0x3fff) + ((program_rom.len() / 0x4000 - 1) * 0x4000)],
-        }
+            0xc000..=0xffff => usize::from(address & 0x3fff) + ((program_rom_size / 0x4000).saturating_sub(1) * 0x4000),
+            _ => panic!("Mapper002::program_index called with invalid address: 0x{:04X}", address),
+        };
     }
 
     fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
         match address {
-            0x6000..=0x7fff => program_ram[usize::from(address - 0x6000)] = data,
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
             // Writes to the rom set the bank select register
             0x8000..=0xffff => self.bank_select = data & 0x0f,
             _ => warn!("Mapper001::program_write called with invalid address 0x{:4X}", address),
         }
     }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank_select);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.bank_select = state.read_u8()?;
+        return Ok(());
+    }
 }
 
 /// Mapper struct for the CNROM Mapper, which is given the iNES id of 003
+#[derive(Clone)]
 pub(super) struct Mapper003 {
     bank_select: u8,
 }
 
 impl Mapper for Mapper003 {
-    fn character_read(&self, character_ram: &[u8], address: u16) -> u8 {
-        return character_ram[usize::from(address & 0x1fff) | (self.bank_select as usize * 0x2000)];
+    fn character_index(&self, address: u16) -> usize {
+        return usize::from(address & 0x1fff) | (self.bank_select as usize * 0x2000);
     }
 
     fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
         match address {
-            0x6000..=0x7fff => program_ram[usize::from(address - 0x6000)] = data,
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
             0x8000..=0xffff => {
                 // The real CNROM has two security bits, but I'm ignoring those
                 self.bank_select = data & 0x03;
@@ -252,12 +545,18 @@ impl Mapper for Mapper003 {
         }
     }
 
-    fn character_write(&mut self, character_ram: &mut [u8], address: u16, data: u8) {
-        character_ram[usize::from(address & 0x1fff) | (self.bank_select as usize * 0x2000)] = data;
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank_select);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.bank_select = state.read_u8()?;
+        return Ok(());
     }
 }
 
 /// Mapper struct for the CxROM Mapper, which is given the iNES id of 003
+#[derive(Clone)]
 pub(super) struct Mapper004 {
     bank_control: u8,
     bank_select: [u8; 8],
@@ -272,57 +571,46 @@ pub(super) struct Mapper004 {
 }
 
 impl Mapper for Mapper004 {
-    fn program_read(&self, program_rom: &[u8], program_ram: &[u8], address: u16) -> u8 {
-        match address {
-            0x0000..=0x5fff => {
-                warn!("Mapper read from {:04X}", address);
-                return 0x00;
-            }
-            0x6000..=0x7fff => {
-                if program_ram.is_empty() {
-                    0x00
-                } else {
-                    program_ram[usize::from(address - 0x6000) % program_ram.len()]
-                }
-            }
-            0x8000..=0xffff => match (address, self.bank_control & 0x40) {
-                // Point to either the second last bank or the bank selected by the 6th bank selector
-                (0x8000..=0x9fff, 0x00) => program_rom[usize::from(address & 0x1fff) + usize::from(self.bank_select[6]) * 0x2000],
-                (0x8000..=0x9fff, 0x40) => program_rom[usize::from(address & 0x1fff) + ((program_rom.len() / 0x2000 - 2) * 0x2000)],
-                // Always points to the bank selected by the 7th bank selector
-                (0xa000..=0xbfff, _) => program_rom[usize::from(address & 0x1fff) + usize::from(self.bank_select[7]) * 0x2000],
-                // Point to either the second last bank or the bank selected by the 6th bank selector
-                (0xc000..=0xdfff, 0x00) => program_rom[usize::from(address & 0x1fff) + ((program_rom.len() / 0x2000 - 2) * 0x2000)],
-                (0xc000..=0xdfff, 0x40) => program_rom[usize::from(address & 0x1fff) + usize::from(self.bank_select[6]) * 0x2000],
-                // Always points to the last bank
-                (0xe000..=0xffff, _) => program_rom[usize::from(address & 0x1fff) + ((program_rom.len() / 0x2000 - 1) * 0x2000)],
-                _ => unreachable!(),
-            },
-        }
+    fn program_index(&self, program_rom_size: usize, address: u16) -> usize {
+        let bank = match (address, self.bank_control & 0x40) {
+            // Point to either the second last bank or the bank selected by the 6th bank selector
+            (0x8000..=0x9fff, 0x00) => usize::from(self.bank_select[6]),
+            (0x8000..=0x9fff, 0x40) => (program_rom_size / 0x2000).saturating_sub(2),
+            // Always points to the bank selected by the 7th bank selector
+            (0xa000..=0xbfff, _) => usize::from(self.bank_select[7]),
+            // Point to either the second last bank or the bank selected by the 6th bank selector
+            (0xc000..=0xdfff, 0x00) => (program_rom_size / 0x2000).saturating_sub(2),
+            (0xc000..=0xdfff, 0x40) => usize::from(self.bank_select[6]),
+            // Always points to the last bank
+            (0xe000..=0xffff, _) => (program_rom_size / 0x2000).saturating_sub(1),
+            _ => panic!("Mapper004::program_index called with invalid address: 0x{:04X}", address),
+        };
+        return usize::from(address & 0x1fff) + bank * 0x2000;
     }
 
-    fn character_read(&self, character_ram: &[u8], address: u16) -> u8 {
-        return match (address, self.bank_control & 0x80) {
-            (0x0000..=0x07ff, 0x00) => character_ram[usize::from(address & 0x07ff) + usize::from(self.bank_select[0]) * 0x0400], // TODO: Check if 0x03ff is the right increment for the 2kb banks
-            (0x0800..=0x0fff, 0x00) => character_ram[usize::from(address & 0x07ff) + usize::from(self.bank_select[1]) * 0x0400],
-            (0x1000..=0x13ff, 0x00) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[2]) * 0x0400],
-            (0x1400..=0x17ff, 0x00) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[3]) * 0x0400],
-            (0x1800..=0x1bff, 0x00) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[4]) * 0x0400],
-            (0x1c00..=0x1fff, 0x00) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[5]) * 0x0400],
+    fn character_index(&self, address: u16) -> usize {
+        let (bank, offset_mask) = match (address, self.bank_control & 0x80) {
+            (0x0000..=0x07ff, 0x00) => (self.bank_select[0], 0x07ff), // TODO: Check if 0x0400 is the right increment for the 2kb banks
+            (0x0800..=0x0fff, 0x00) => (self.bank_select[1], 0x07ff),
+            (0x1000..=0x13ff, 0x00) => (self.bank_select[2], 0x03ff),
+            (0x1400..=0x17ff, 0x00) => (self.bank_select[3], 0x03ff),
+            (0x1800..=0x1bff, 0x00) => (self.bank_select[4], 0x03ff),
+            (0x1c00..=0x1fff, 0x00) => (self.bank_select[5], 0x03ff),
             // Bank Control 0x80 = data
-            (0x0000..=0x03ff, 0x80) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[2]) * 0x0400],
-            (0x0400..=0x07ff, 0x80) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[3]) * 0x0400],
-            (0x0800..=0x0bff, 0x80) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[4]) * 0x0400],
-            (0x0c00..=0x0fff, 0x80) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[5]) * 0x0400],
-            (0x1000..=0x17ff, 0x80) => character_ram[usize::from(address & 0x07ff) + usize::from(self.bank_select[0]) * 0x0400],
-            (0x1800..=0x1fff, 0x80) => character_ram[usize::from(address & 0x07ff) + usize::from(self.bank_select[1]) * 0x0400],
-            _ => panic!("Mapper004::character_read called with invalid address: 0x{:04X}", address),
+            (0x0000..=0x03ff, 0x80) => (self.bank_select[2], 0x03ff),
+            (0x0400..=0x07ff, 0x80) => (self.bank_select[3], 0x03ff),
+            (0x0800..=0x0bff, 0x80) => (self.bank_select[4], 0x03ff),
+            (0x0c00..=0x0fff, 0x80) => (self.bank_select[5], 0x03ff),
+            (0x1000..=0x17ff, 0x80) => (self.bank_select[0], 0x07ff),
+            (0x1800..=0x1fff, 0x80) => (self.bank_select[1], 0x07ff),
+            _ => panic!("Mapper004::character_index called with invalid address: 0x{:04X}", address),
         };
+        return usize::from(address & offset_mask) + usize::from(bank) * 0x0400;
     }
 
     fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
         match address {
-            0x6000..=0x7fff => program_ram[usize::from(address - 0x6000)] = data,
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
             0x8000..=0xffff => match (address, address & 0x01) {
                 (0x8000..=0x9fff, 0) => self.bank_control = data,
                 (0x8000..=0x9fff, 1) => self.bank_select[self.bank_control as usize & 0x07] = data,
@@ -339,7 +627,8 @@ impl Mapper for Mapper004 {
                 }
                 (0xc000..=0xdfff, 0) => self.scanline_counter_reload = data,
                 (0xc000..=0xdfff, 1) => {
-                    self.scanline_counter = self.scanline_counter_reload;
+                    // The counter is cleared, and reloaded on the next rise of A12
+                    self.scanline_counter = 0;
                     self.scanline_counter_reload_flag = true;
                 }
                 (0xe000..=0xffff, 0) => {
@@ -353,25 +642,6 @@ impl Mapper for Mapper004 {
         }
     }
 
-    fn character_write(&mut self, character_ram: &mut [u8], address: u16, data: u8) {
-        match (address, self.bank_control & 0x80) {
-            (0x0000..=0x07ff, 0x00) => character_ram[usize::from(address & 0x07ff) + usize::from(self.bank_select[0]) * 0x0400] = data, // TODO: Check if 0x0400 is the right increment for the 2kb banks
-            (0x0800..=0x0fff, 0x00) => character_ram[usize::from(address & 0x07ff) + usize::from(self.bank_select[1]) * 0x0400] = data,
-            (0x1000..=0x13ff, 0x00) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[2]) * 0x0400] = data,
-            (0x1400..=0x17ff, 0x00) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[3]) * 0x0400] = data,
-            (0x1800..=0x1bff, 0x00) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[4]) * 0x0400] = data,
-            (0x1c00..=0x1fff, 0x00) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[5]) * 0x0400] = data,
-            // Bank Control 0x80 = data
-            (0x0000..=0x03ff, 0x80) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[2]) * 0x0400] = data,
-            (0x0400..=0x07ff, 0x80) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[3]) * 0x0400] = data,
-            (0x0800..=0x0bff, 0x80) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[4]) * 0x0400] = data,
-            (0x0c00..=0x0fff, 0x80) => character_ram[usize::from(address & 0x03ff) + usize::from(self.bank_select[5]) * 0x0400] = data,
-            (0x1000..=0x17ff, 0x80) => character_ram[usize::from(address & 0x07ff) + usize::from(self.bank_select[0]) * 0x0400] = data,
-            (0x1800..=0x1fff, 0x80) => character_ram[usize::from(address & 0x07ff) + usize::from(self.bank_select[1]) * 0x0400] = data,
-            _ => warn!("Mapper004::character_write called with invalid address: 0x{:04X}", address),
-        }
-    }
-
     fn get_mirroring(&mut self, _mirroring: Mirroring) -> Mirroring {
         return self.mirroring;
     }
@@ -382,15 +652,916 @@ impl Mapper for Mapper004 {
         return value;
     }
 
-    fn end_of_scanline(&mut self) {
-        if self.scanline_counter == 0 && self.interrupt_request_enabled {
-            self.pending_interrupt_request = true;
-        }
+    fn a12_rising_edge(&mut self) {
         if self.scanline_counter == 0 || self.scanline_counter_reload_flag {
             self.scanline_counter = self.scanline_counter_reload;
             self.scanline_counter_reload_flag = false;
         } else {
             self.scanline_counter -= 1
         }
+        // The interrupt is requested as the counter reaches zero, rather than on the rise after it does
+        if self.scanline_counter == 0 && self.interrupt_request_enabled {
+            self.pending_interrupt_request = true;
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank_control);
+        state.write_bytes(&self.bank_select);
+        write_mirroring(state, self.mirroring);
+        state.write_bool(self.program_ram_write_protect);
+        state.write_bool(self.program_ram_enabled);
+        state.write_u8(self.scanline_counter);
+        state.write_u8(self.scanline_counter_reload);
+        state.write_bool(self.scanline_counter_reload_flag);
+        state.write_bool(self.interrupt_request_enabled);
+        state.write_bool(self.pending_interrupt_request);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.bank_control = state.read_u8()?;
+        self.bank_select = state.read_array()?;
+        self.mirroring = read_mirroring(state)?;
+        self.program_ram_write_protect = state.read_bool()?;
+        self.program_ram_enabled = state.read_bool()?;
+        self.scanline_counter = state.read_u8()?;
+        self.scanline_counter_reload = state.read_u8()?;
+        self.scanline_counter_reload_flag = state.read_bool()?;
+        self.interrupt_request_enabled = state.read_bool()?;
+        self.pending_interrupt_request = state.read_bool()?;
+        return Ok(());
+    }
+}
+
+/// The tiles that switch the character banks of iNES Mapper 009 when the PPU fetches them
+#[cfg(feature = "extra-mappers")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CharacterLatch {
+    Fd,
+    Fe,
+}
+
+/// Mapper struct for the MMC2 Mapper, which is given the iNES id of 009
+#[cfg(feature = "extra-mappers")]
+#[derive(Clone)]
+pub(super) struct Mapper009 {
+    program_bank_select: u8,
+    /// The banks selected for each half of character memory, while its latch holds $FD and $FE respectively
+    character_bank_select: [[u8; 2]; 2],
+    /// The latches for each half of character memory, which are set by the PPU fetching tile $FD or $FE from that half
+    character_latches: [CharacterLatch; 2],
+    mirroring: Mirroring,
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper for Mapper009 {
+    fn program_index(&self, program_rom_size: usize, address: u16) -> usize {
+        let bank = match address {
+            0x8000..=0x9fff => usize::from(self.program_bank_select),
+            // The last three banks are fixed to $A000-$FFFF
+            _ => (program_rom_size / 0x2000).saturating_sub(3) + usize::from((address - 0xa000) / 0x2000),
+        };
+        return usize::from(address & 0x1fff) + bank * 0x2000;
+    }
+
+    fn character_index(&self, address: u16) -> usize {
+        let half = usize::from(address >> 12) & 0x01;
+        let bank = match self.character_latches[half] {
+            CharacterLatch::Fd => self.character_bank_select[half][0],
+            CharacterLatch::Fe => self.character_bank_select[half][1],
+        };
+        return usize::from(address & 0x0fff) + usize::from(bank) * 0x1000;
+    }
+
+    fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
+            0x8000..=0x9fff => {}
+            0xa000..=0xafff => self.program_bank_select = data & 0x0f,
+            0xb000..=0xbfff => self.character_bank_select[0][0] = data & 0x1f,
+            0xc000..=0xcfff => self.character_bank_select[0][1] = data & 0x1f,
+            0xd000..=0xdfff => self.character_bank_select[1][0] = data & 0x1f,
+            0xe000..=0xefff => self.character_bank_select[1][1] = data & 0x1f,
+            0xf000..=0xffff => {
+                if data & 0x01 > 0 {
+                    self.mirroring = Mirroring::Horizontal
+                } else {
+                    self.mirroring = Mirroring::Vertical
+                }
+            }
+            _ => warn!("Mapper009::program_write called with invalid address 0x{:4X}", address),
+        }
+    }
+
+    fn get_mirroring(&mut self, _mirroring: Mirroring) -> Mirroring {
+        return self.mirroring;
+    }
+
+    fn character_read_notify(&mut self, address: u16) {
+        // The latches are set after the tile has been fetched, so the tile itself comes from the previous bank.
+        // The first half only watches the first byte of the tile's second plane, while the second half watches all eight.
+        match address {
+            0x0fd8 | 0x1fd8..=0x1fdf => self.character_latches[usize::from(address >> 12)] = CharacterLatch::Fd,
+            0x0fe8 | 0x1fe8..=0x1fef => self.character_latches[usize::from(address >> 12)] = CharacterLatch::Fe,
+            _ => {}
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.program_bank_select);
+        self.character_bank_select.iter().for_each(|banks| state.write_bytes(banks));
+        self.character_latches
+            .iter()
+            .for_each(|latch| state.write_bool(*latch == CharacterLatch::Fe));
+        write_mirroring(state, self.mirroring);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.program_bank_select = state.read_u8()?;
+        for banks in self.character_bank_select.iter_mut() {
+            *banks = state.read_array()?;
+        }
+        for latch in self.character_latches.iter_mut() {
+            *latch = if state.read_bool()? { CharacterLatch::Fe } else { CharacterLatch::Fd };
+        }
+        self.mirroring = read_mirroring(state)?;
+        return Ok(());
+    }
+}
+
+/// Mapper struct for the Color Dreams Mapper, which is given the iNES id of 011
+#[cfg(feature = "extra-mappers")]
+#[derive(Clone)]
+pub(super) struct Mapper011 {
+    /// The program bank in the bottom two bits and the character bank in the top four bits
+    bank_select: u8,
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper for Mapper011 {
+    fn program_index(&self, _program_rom_size: usize, address: u16) -> usize {
+        return usize::from(address & 0x7fff) + usize::from(self.bank_select & 0x03) * 0x8000;
+    }
+
+    fn character_index(&self, address: u16) -> usize {
+        return usize::from(address & 0x1fff) + usize::from(self.bank_select >> 4) * 0x2000;
+    }
+
+    fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
+            0x8000..=0xffff => self.bank_select = data,
+            _ => warn!("Mapper011::program_write called with invalid address 0x{:4X}", address),
+        }
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        return true;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank_select);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.bank_select = state.read_u8()?;
+        return Ok(());
+    }
+}
+
+/// The number of master clock cycles in a scanline, which the VRC6's prescaler clocks the interrupt counter once every
+#[cfg(feature = "extra-mappers")]
+const VRC6_PRESCALER_PERIOD: u64 = MasterClock::NTSC.dots_per_scanline as u64 * MasterClock::NTSC.ppu_divider as u64;
+
+/// Mapper struct for Konami's VRC6, which is given the iNES ids of 024 and 026. The two boards only differ in which
+/// of the CPU's address lines are connected to the VRC6's two register select lines.
+#[cfg(feature = "extra-mappers")]
+#[derive(Clone)]
+pub(super) struct Mapper024 {
+    /// Whether the register select lines are swapped, which is the case for mapper 026
+    swapped_register_lines: bool,
+    /// The 16 KiB bank at $8000-$BFFF and the 8 KiB bank at $C000-$DFFF
+    program_bank_select: [u8; 2],
+    /// The eight 1 KiB character banks
+    character_bank_select: [u8; 8],
+    /// The banking control register at $B003, which selects the mirroring and enables program RAM
+    banking_control: u8,
+    /// The value that the interrupt counter is reloaded with when it overflows
+    interrupt_request_latch: u8,
+    /// The interrupt counter, which counts up and requests an interrupt when it overflows
+    interrupt_request_counter: u8,
+    /// The cycle of the master clock that the prescaler next clocks the interrupt counter on in scanline mode
+    interrupt_request_prescaler: u64,
+    /// The cycle of the master clock that the last CPU cycle ran on
+    master_clock: u64,
+    interrupt_request_enabled: bool,
+    /// The value that interrupt_request_enabled is set to when the interrupt is acknowledged
+    interrupt_request_enabled_after_acknowledge: bool,
+    /// Whether the interrupt counter is clocked every CPU cycle instead of every scanline
+    interrupt_request_cycle_mode: bool,
+    pending_interrupt_request: bool,
+    /// The two pulse channels and sawtooth channel of the VRC6
+    audio: Vrc6Audio,
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper024 {
+    /// Puts the register select lines of an address in the order of mapper 024 and removes the unused address lines
+    fn register_address(&self, address: u16) -> u16 {
+        let address = address & 0xf003;
+        return if self.swapped_register_lines {
+            (address & 0xf000) | ((address & 0x01) << 1) | ((address & 0x02) >> 1)
+        } else {
+            address
+        };
+    }
+
+    /// Checks if program RAM is enabled through bit 7 of the banking control register
+    fn is_program_ram_enabled(&self) -> bool {
+        return self.banking_control & 0x80 > 0;
+    }
+
+    /// Clocks the interrupt counter, which requests an interrupt and reloads from the latch when it overflows
+    fn clock_interrupt_counter(&mut self) {
+        if self.interrupt_request_counter == 0xff {
+            self.interrupt_request_counter = self.interrupt_request_latch;
+            self.pending_interrupt_request = true;
+        } else {
+            self.interrupt_request_counter += 1;
+        }
+    }
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper for Mapper024 {
+    fn program_read(&self, program_rom: &[u8], program_ram: &[u8], address: u16) -> u8 {
+        return match address {
+            0x6000..=0x7fff if !self.is_program_ram_enabled() => 0x00,
+            0x6000..=0x7fff => read_wrapping(program_ram, usize::from(address - 0x6000)),
+            0x8000..=0xffff => read_wrapping(program_rom, self.program_index(program_rom.len(), address)),
+            _ => {
+                warn!("Mapper read from {:04X}", address);
+                0x00
+            }
+        };
+    }
+
+    fn program_index(&self, program_rom_size: usize, address: u16) -> usize {
+        return match address {
+            0x8000..=0xbfff => usize::from(address & 0x3fff) + usize::from(self.program_bank_select[0] & 0x0f) * 0x4000,
+            0xc000..=0xdfff => usize::from(address & 0x1fff) + usize::from(self.program_bank_select[1] & 0x1f) * 0x2000,
+            // The last bank is fixed to $E000-$FFFF
+            _ => usize::from(address & 0x1fff) + (program_rom_size / 0x2000).saturating_sub(1) * 0x2000,
+        };
+    }
+
+    fn character_index(&self, address: u16) -> usize {
+        return usize::from(address & 0x03ff) + usize::from(self.character_bank_select[usize::from(address >> 10) & 0x07]) * 0x0400;
+    }
+
+    fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
+        if (0x6000..=0x7fff).contains(&address) {
+            if self.is_program_ram_enabled() {
+                write_wrapping(program_ram, usize::from(address - 0x6000), data)
+            }
+            return;
+        }
+        match self.register_address(address) {
+            0x8000..=0x8003 => self.program_bank_select[0] = data,
+            register @ (0x9000..=0x9003 | 0xa000..=0xa002 | 0xb000..=0xb002) => self.audio.write(register, data),
+            0xb003 => self.banking_control = data,
+            0xc000..=0xc003 => self.program_bank_select[1] = data,
+            register @ 0xd000..=0xd003 => self.character_bank_select[usize::from(register & 0x03)] = data,
+            register @ 0xe000..=0xe003 => self.character_bank_select[4 + usize::from(register & 0x03)] = data,
+            0xf000 => self.interrupt_request_latch = data,
+            0xf001 => {
+                self.interrupt_request_enabled_after_acknowledge = data & 0x01 > 0;
+                self.interrupt_request_enabled = data & 0x02 > 0;
+                self.interrupt_request_cycle_mode = data & 0x04 > 0;
+                self.pending_interrupt_request = false;
+                if self.interrupt_request_enabled {
+                    self.interrupt_request_counter = self.interrupt_request_latch;
+                    self.interrupt_request_prescaler = self.master_clock + VRC6_PRESCALER_PERIOD;
+                }
+            }
+            0xf002 => {
+                self.pending_interrupt_request = false;
+                self.interrupt_request_enabled = self.interrupt_request_enabled_after_acknowledge;
+            }
+            _ => {}
+        }
+    }
+
+    fn get_mirroring(&mut self, _mirroring: Mirroring) -> Mirroring {
+        return match (self.banking_control >> 2) & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        };
+    }
+
+    fn get_pending_interrupt_request(&mut self) -> bool {
+        let value = self.pending_interrupt_request;
+        self.pending_interrupt_request = false;
+        return value;
+    }
+
+    fn cpu_cycle(&mut self, master_clock: u64) {
+        let elapsed = master_clock.saturating_sub(self.master_clock);
+        self.master_clock = master_clock;
+        if !self.interrupt_request_enabled {
+            // The prescaler holds its place while the interrupt is disabled
+            self.interrupt_request_prescaler += elapsed;
+            return;
+        }
+        if self.interrupt_request_cycle_mode {
+            self.clock_interrupt_counter();
+        } else if master_clock >= self.interrupt_request_prescaler {
+            self.interrupt_request_prescaler += VRC6_PRESCALER_PERIOD;
+            self.clock_interrupt_counter();
+        }
+    }
+
+    fn audio_cycle(&mut self) {
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        return self.audio.output();
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.program_bank_select);
+        state.write_bytes(&self.character_bank_select);
+        state.write_u8(self.banking_control);
+        state.write_u8(self.interrupt_request_latch);
+        state.write_u8(self.interrupt_request_counter);
+        state.write_u64(self.interrupt_request_prescaler);
+        state.write_u64(self.master_clock);
+        state.write_bool(self.interrupt_request_enabled);
+        state.write_bool(self.interrupt_request_enabled_after_acknowledge);
+        state.write_bool(self.interrupt_request_cycle_mode);
+        state.write_bool(self.pending_interrupt_request);
+        self.audio.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.program_bank_select = state.read_array()?;
+        self.character_bank_select = state.read_array()?;
+        self.banking_control = state.read_u8()?;
+        self.interrupt_request_latch = state.read_u8()?;
+        self.interrupt_request_counter = state.read_u8()?;
+        self.interrupt_request_prescaler = state.read_u64()?;
+        self.master_clock = state.read_u64()?;
+        self.interrupt_request_enabled = state.read_bool()?;
+        self.interrupt_request_enabled_after_acknowledge = state.read_bool()?;
+        self.interrupt_request_cycle_mode = state.read_bool()?;
+        self.pending_interrupt_request = state.read_bool()?;
+        return self.audio.load_state(state);
+    }
+}
+
+/// Mapper struct for the Action 53 multicart Mapper, which is given the iNES id of 028. Its outer bank selects the game
+/// in 32 KiB units, while the inner bank switches banks within the game in the style of the mapper it was written for.
+#[cfg(feature = "extra-mappers")]
+#[derive(Clone)]
+pub(super) struct Mapper028 {
+    /// The register that writes to $8000-$FFFF go to, selected by bits 7 and 0 of writes to $5000-$5FFF
+    register_select: u8,
+    /// Register $00, which selects the 8 KiB character RAM bank in its lowest two bits
+    character_bank: u8,
+    /// Register $01, which selects the 16 KiB program bank within the game in its lowest four bits
+    inner_bank: u8,
+    /// Register $80, which selects the size of the game in bits 4-5, the program banking mode in bits 2-3
+    /// and the mirroring in bits 0-1
+    mode: u8,
+    /// Register $81, which selects the 32 KiB bank that the game starts from
+    outer_bank: u8,
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper028 {
+    /// Writes a value to register $00 or $01, which also set the one screen mirroring page in one screen mirroring modes
+    fn write_bank_register(&mut self, data: u8) {
+        if self.mode & 0x02 == 0 {
+            self.mode = (self.mode & !0x01) | ((data >> 4) & 0x01);
+        }
+    }
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper for Mapper028 {
+    fn program_index(&self, _program_rom_size: usize, address: u16) -> usize {
+        let upper_half = usize::from((address >> 14) & 0x01);
+        // The game's size decides how many of the low bits of the 16 KiB bank number come from the inner bank
+        let inner_mask = (2 << ((self.mode >> 4) & 0x03)) - 1;
+        let outer_bank = usize::from(self.outer_bank) << 1;
+        let inner_bank = usize::from(self.inner_bank & 0x0f);
+        let bank = match ((self.mode >> 2) & 0x03, upper_half) {
+            // 32 KiB banks, like BNROM
+            (0 | 1, _) => (outer_bank & !inner_mask) | (((inner_bank << 1) | upper_half) & inner_mask),
+            // The first half is fixed to the outer bank's, like the UNROM board of mapper 180
+            (2, 0) => outer_bank,
+            // The second half is fixed to the outer bank's, like UNROM
+            (3, 1) => outer_bank | 0x01,
+            _ => (outer_bank & !inner_mask) | (inner_bank & inner_mask),
+        };
+        return usize::from(address & 0x3fff) + bank * 0x4000;
+    }
+
+    fn character_index(&self, address: u16) -> usize {
+        return usize::from(address & 0x1fff) + usize::from(self.character_bank & 0x03) * 0x2000;
+    }
+
+    fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
+        match address {
+            0x5000..=0x5fff => self.register_select = data & 0x81,
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
+            0x8000..=0xffff => match self.register_select {
+                0x00 => {
+                    self.character_bank = data;
+                    self.write_bank_register(data);
+                }
+                0x01 => {
+                    self.inner_bank = data;
+                    self.write_bank_register(data);
+                }
+                0x80 => self.mode = data,
+                _ => self.outer_bank = data,
+            },
+            _ => warn!("Mapper028::program_write called with invalid address 0x{:4X}", address),
+        }
+    }
+
+    fn get_mirroring(&mut self, _mirroring: Mirroring) -> Mirroring {
+        return match self.mode & 0x03 {
+            0b00 => Mirroring::OneScreenLower,
+            0b01 => Mirroring::OneScreenUpper,
+            0b10 => Mirroring::Vertical,
+            0b11 => Mirroring::Horizontal,
+            _ => unreachable!(),
+        };
+    }
+
+    fn get_character_ram_size(&self) -> usize {
+        return 0x8000;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register_select);
+        state.write_u8(self.character_bank);
+        state.write_u8(self.inner_bank);
+        state.write_u8(self.mode);
+        state.write_u8(self.outer_bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.register_select = state.read_u8()?;
+        self.character_bank = state.read_u8()?;
+        self.inner_bank = state.read_u8()?;
+        self.mode = state.read_u8()?;
+        self.outer_bank = state.read_u8()?;
+        return Ok(());
+    }
+}
+
+/// The progress through the command sequences that program and erase the flash memory of a self-flashable UNROM 512.
+/// Each command is unlocked by writing $AA to $5555 and $55 to $2AAA in the flash memory, and erasing takes two unlocks.
+#[cfg(feature = "extra-mappers")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FlashCommand {
+    Ready,
+    Unlocking,
+    Unlocked,
+    Program,
+    EraseReady,
+    EraseUnlocking,
+    EraseUnlocked,
+}
+
+/// The size of the sectors that the flash memory of a self-flashable UNROM 512 is erased in
+#[cfg(feature = "extra-mappers")]
+const FLASH_SECTOR_SIZE: usize = 0x1000;
+
+/// Mapper struct for the UNROM 512 Mapper used by homebrew, which is given the iNES id of 030. It switches 16 KiB
+/// program banks like UxROM, along with 8 KiB banks of its 32 KiB of character RAM. Boards with the battery bit set
+/// in their header have flash memory in place of program ROM, which the game can rewrite to save its progress.
+#[cfg(feature = "extra-mappers")]
+#[derive(Clone)]
+pub(super) struct Mapper030 {
+    bank_select: u8,
+    /// Whether the header selects the mirroring mode with one screen mirroring switched by the register
+    one_screen_mirroring: bool,
+    /// The contents of the flash memory, which is empty for boards with program ROM that can't be rewritten
+    flash: Vec<u8>,
+    flash_command: FlashCommand,
+    flash_generation: u64,
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper030 {
+    /// Passes a write to $8000-$BFFF through the command sequences of the flash memory
+    fn flash_write(&mut self, address: u16, data: u8) {
+        let index = usize::from(address & 0x3fff) + usize::from(self.bank_select & 0x1f) * 0x4000;
+        // Only the lower 15 address lines are decoded when checking for commands
+        self.flash_command = match (self.flash_command, index & 0x7fff, data) {
+            (FlashCommand::Ready, 0x5555, 0xaa) => FlashCommand::Unlocking,
+            (FlashCommand::Unlocking, 0x2aaa, 0x55) => FlashCommand::Unlocked,
+            (FlashCommand::Unlocked, 0x5555, 0xa0) => FlashCommand::Program,
+            (FlashCommand::Unlocked, 0x5555, 0x80) => FlashCommand::EraseReady,
+            (FlashCommand::Program, _, _) => {
+                // Programming can only clear bits, setting them again takes an erase
+                let length = self.flash.len();
+                self.update_flash(index % length..index % length + 1, |byte| byte & data);
+                FlashCommand::Ready
+            }
+            (FlashCommand::EraseReady, 0x5555, 0xaa) => FlashCommand::EraseUnlocking,
+            (FlashCommand::EraseUnlocking, 0x2aaa, 0x55) => FlashCommand::EraseUnlocked,
+            (FlashCommand::EraseUnlocked, 0x5555, 0x10) => {
+                self.update_flash(0..self.flash.len(), |_| 0xff);
+                FlashCommand::Ready
+            }
+            (FlashCommand::EraseUnlocked, _, 0x30) => {
+                let sector = index % self.flash.len() / FLASH_SECTOR_SIZE * FLASH_SECTOR_SIZE;
+                self.update_flash(sector..(sector + FLASH_SECTOR_SIZE).min(self.flash.len()), |_| 0xff);
+                FlashCommand::Ready
+            }
+            // Any other write, including the reset command $F0, abandons the sequence
+            _ => FlashCommand::Ready,
+        };
+    }
+
+    /// Updates a range of the flash memory, counting the write towards the non-volatile generation if anything changed
+    fn update_flash(&mut self, range: std::ops::Range<usize>, update: impl Fn(u8) -> u8) {
+        let mut changed = false;
+        for byte in &mut self.flash[range] {
+            let updated = update(*byte);
+            changed |= updated != *byte;
+            *byte = updated;
+        }
+        if changed {
+            self.flash_generation += 1;
+        }
+    }
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper for Mapper030 {
+    fn program_read(&self, program_rom: &[u8], program_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000..=0x5fff => {
+                warn!("Mapper030 read from {:04X}", address);
+                return 0x00;
+            }
+            0x6000..=0x7fff => read_wrapping(program_ram, usize::from(address - 0x6000)),
+            0x8000..=0xffff => {
+                let program_memory = if self.flash.is_empty() { program_rom } else { &self.flash };
+                read_wrapping(program_memory, self.program_index(program_memory.len(), address))
+            }
+        }
+    }
+
+    fn program_index(&self, program_rom_size: usize, address: u16) -> usize {
+        return match address {
+            0x8000..=0xbfff => usize::from(address & 0x3fff) + usize::from(self.bank_select & 0x1f) * 0x4000,
+            // Always points to the last program rom bank
+            0xc000..=0xffff => usize::from(address & 0x3fff) + ((program_rom_size / 0x4000).saturating_sub(1) * 0x4000),
+            _ => panic!("Mapper030::program_index called with invalid address: 0x{:04X}", address),
+        };
+    }
+
+    fn character_index(&self, address: u16) -> usize {
+        return usize::from(address & 0x1fff) + usize::from((self.bank_select >> 5) & 0x03) * 0x2000;
+    }
+
+    fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
+            // Boards with flash memory only decode the register in the upper half
+            0x8000..=0xbfff if !self.flash.is_empty() => self.flash_write(address, data),
+            0x8000..=0xffff => self.bank_select = data,
+            _ => warn!("Mapper030::program_write called with invalid address 0x{:4X}", address),
+        }
+    }
+
+    fn get_mirroring(&mut self, mirroring: Mirroring) -> Mirroring {
+        return match (self.one_screen_mirroring, self.bank_select & 0x80) {
+            (false, _) => mirroring,
+            (true, 0x00) => Mirroring::OneScreenLower,
+            (true, _) => Mirroring::OneScreenUpper,
+        };
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        return self.flash.is_empty();
+    }
+
+    fn get_nonvolatile_state(&self) -> Option<&[u8]> {
+        return if self.flash.is_empty() { None } else { Some(&self.flash) };
+    }
+
+    fn load_nonvolatile_state(&mut self, data: &[u8]) {
+        self.flash.copy_from_slice(data);
+    }
+
+    fn get_nonvolatile_generation(&self) -> u64 {
+        return self.flash_generation;
+    }
+
+    fn load_rom(&mut self, info: &CartridgeInfo, program_rom: &[u8]) {
+        // The four screen bit alone selects one screen mirroring, as the board can't provide four nametables without it
+        self.one_screen_mirroring = info.four_screen && !info.vertical_mirroring;
+        if info.battery {
+            self.flash = program_rom.to_vec();
+        }
+    }
+
+    fn get_character_ram_size(&self) -> usize {
+        return 0x8000;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank_select);
+        state.write_u8(self.flash_command as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.bank_select = state.read_u8()?;
+        self.flash_command = match state.read_u8()? {
+            0 => FlashCommand::Ready,
+            1 => FlashCommand::Unlocking,
+            2 => FlashCommand::Unlocked,
+            3 => FlashCommand::Program,
+            4 => FlashCommand::EraseReady,
+            5 => FlashCommand::EraseUnlocking,
+            6 => FlashCommand::EraseUnlocked,
+            command => bail!("Invalid flash command in save state: {}", command),
+        };
+        return Ok(());
+    }
+}
+
+/// Mapper struct for CNROM boards that use their security diodes as copy protection, which are given the iNES id of
+/// 185. Character ROM is only readable while the value last written to the bank register matches the diodes, and
+/// reads return open bus otherwise, which games check for before enabling it.
+#[cfg(feature = "extra-mappers")]
+#[derive(Clone)]
+pub(super) struct Mapper185 {
+    submapper_id: u8,
+    character_enabled: bool,
+}
+
+#[cfg(feature = "extra-mappers")]
+impl Mapper for Mapper185 {
+    fn character_read(&self, character_ram: &[u8], address: u16) -> u8 {
+        if !self.character_enabled {
+            // The PPU shares its data lines with the low byte of the address, which is all that's left on them to read
+            return address as u8;
+        }
+        return read_wrapping(character_ram, self.character_index(address));
+    }
+
+    fn program_write(&mut self, program_ram: &mut [u8], address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff => write_wrapping(program_ram, usize::from(address - 0x6000), data),
+            0x8000..=0xffff => {
+                self.character_enabled = match self.submapper_id {
+                    // Submappers 4 to 7 give the value of the two diodes that enables character ROM
+                    4..=7 => data & 0x03 == self.submapper_id - 4,
+                    // Otherwise fall back on the guess that most emulators make, that every game disables it with
+                    // a value of zero in the low bits, or with $13, and enables it with anything else
+                    _ => data & 0x0f != 0 && data != 0x13,
+                };
+            }
+            _ => warn!("Mapper185::program_write called with invalid address 0x{:4X}", address),
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.character_enabled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.character_enabled = state.read_bool()?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_mapper_supported_mappers() {
+        for mapper_id in SUPPORTED_MAPPERS {
+            assert!(
+                get_mapper(*mapper_id, 0).is_ok(),
+                "Mapper {:03} is listed as supported but can't be created",
+                mapper_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_mapper_support_matches_supported_mappers() {
+        // Other tests register their own mappers, which are always supported
+        for mapper_id in (0..=0xfff).filter(|mapper_id| !is_mapper_registered(*mapper_id)) {
+            assert_eq!(
+                SUPPORTED_MAPPERS.contains(&mapper_id),
+                get_mapper_support(mapper_id) != MapperSupport::Unsupported,
+                "Mapper {:03} support doesn't match the supported mappers",
+                mapper_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrapping_access_empty_memory() {
+        let mut memory = [];
+        write_wrapping(&mut memory, 0x10, 0xff);
+        assert_eq!(0x00, read_wrapping(&memory, 0x10));
+    }
+
+    /// Clocks a mapper with a number of CPU cycles, moving the master clock on by each of them
+    fn run_cpu_cycles(mapper: &mut Box<dyn Mapper>, master_clock: &mut u64, cycles: u64) {
+        for _ in 0..cycles {
+            *master_clock += CPU_DIVIDER;
+            mapper.cpu_cycle(*master_clock);
+        }
+    }
+
+    /// Writes a value to one of the MMC1's registers with five serial writes, a few CPU cycles apart like a game's would be
+    fn write_mapper_001_register(mapper: &mut Box<dyn Mapper>, master_clock: &mut u64, address: u16, value: u8) {
+        for bit in 0..5 {
+            run_cpu_cycles(mapper, master_clock, 2);
+            mapper.program_write(&mut [], address, (value >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn test_mapper_001_four_kilobyte_character_write() {
+        let mut mapper = get_mapper(1, 0).unwrap();
+        let mut master_clock = 0;
+        let mut character_ram = [0x00; 0x4000];
+        // Switch to 4 KiB character banks, then select bank 2 for the upper half
+        write_mapper_001_register(&mut mapper, &mut master_clock, 0x8000, 0x1c);
+        write_mapper_001_register(&mut mapper, &mut master_clock, 0xc000, 0x02);
+        mapper.character_write(&mut character_ram, 0x1010, 0x5a);
+        assert_eq!(0x5a, character_ram[0x2010]);
+        assert_eq!(0x5a, mapper.character_read(&character_ram, 0x1010));
+    }
+
+    #[test]
+    fn test_mapper_001_consecutive_writes() {
+        let mut mapper = get_mapper(1, 0).unwrap();
+        let mut master_clock = 0;
+        write_mapper_001_register(&mut mapper, &mut master_clock, 0xe000, 0x03);
+        // Read-modify-write instructions write the value they read before the modified one, and the second
+        // of the writes is ignored, so only the reset from the first applies
+        run_cpu_cycles(&mut mapper, &mut master_clock, 2);
+        mapper.program_write(&mut [], 0xe000, 0xff);
+        run_cpu_cycles(&mut mapper, &mut master_clock, 1);
+        mapper.program_write(&mut [], 0xe000, 0x00);
+        write_mapper_001_register(&mut mapper, &mut master_clock, 0xe000, 0x05);
+        assert_eq!(0x05 * 0x4000, mapper.program_index(0x40000, 0x8000));
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_mapper_009_character_latches() {
+        let mut mapper = get_mapper(9, 0).unwrap();
+        let character_rom: Vec<u8> = (0..0x8000).map(|index| (index / 0x1000) as u8).collect();
+        // Select banks 1 and 2 for the first half, and banks 3 and 4 for the second half
+        mapper.program_write(&mut [], 0xb000, 0x01);
+        mapper.program_write(&mut [], 0xc000, 0x02);
+        mapper.program_write(&mut [], 0xd000, 0x03);
+        mapper.program_write(&mut [], 0xe000, 0x04);
+        assert_eq!(0x02, mapper.character_read(&character_rom, 0x0000));
+        assert_eq!(0x04, mapper.character_read(&character_rom, 0x1000));
+        // Fetching tile $FD switches the half it was fetched from to its $FD bank once the fetch is over
+        mapper.character_read_notify(0x0fd8);
+        assert_eq!(0x01, mapper.character_read(&character_rom, 0x0000));
+        assert_eq!(0x04, mapper.character_read(&character_rom, 0x1000));
+        mapper.character_read_notify(0x1fdb);
+        assert_eq!(0x03, mapper.character_read(&character_rom, 0x1000));
+        // The first half only switches on the first byte of the tile's second plane
+        mapper.character_read_notify(0x0fe9);
+        assert_eq!(0x01, mapper.character_read(&character_rom, 0x0000));
+        mapper.character_read_notify(0x0fe8);
+        assert_eq!(0x02, mapper.character_read(&character_rom, 0x0000));
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_mapper_009_program_banks() {
+        let mut mapper = get_mapper(9, 0).unwrap();
+        mapper.program_write(&mut [], 0xa000, 0x05);
+        assert_eq!(0x5 * 0x2000, mapper.program_index(0x20000, 0x8000));
+        assert_eq!(0xd * 0x2000 + 0x0123, mapper.program_index(0x20000, 0xa123));
+        assert_eq!(0xf * 0x2000 + 0x1fff, mapper.program_index(0x20000, 0xffff));
+        mapper.program_write(&mut [], 0xf000, 0x01);
+        assert_eq!(Mirroring::Horizontal, mapper.get_mirroring(Mirroring::Vertical));
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_mapper_026_swapped_registers() {
+        let mut mapper = get_mapper(26, 0).unwrap();
+        let character_rom: Vec<u8> = (0..0x2000).map(|index| (index / 0x0400) as u8).collect();
+        // $D001 on mapper 024 is $D002 on mapper 026
+        mapper.program_write(&mut [], 0xd002, 0x05);
+        assert_eq!(0x05, mapper.character_read(&character_rom, 0x0400));
+        mapper.program_write(&mut [], 0xc000, 0x03);
+        assert_eq!(0x3 * 0x2000 + 0x0123, mapper.program_index(0x40000, 0xc123));
+        assert_eq!(0x1f * 0x2000, mapper.program_index(0x40000, 0xe000));
+        // $B003 on both
+        mapper.program_write(&mut [], 0xb003, 0x24);
+        assert_eq!(Mirroring::Horizontal, mapper.get_mirroring(Mirroring::Vertical));
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_mapper_024_interrupt_counter() {
+        let mut mapper = get_mapper(24, 0).unwrap();
+        let mut master_clock = 0;
+        // Count CPU cycles from 0xfd, so the counter overflows on the third
+        mapper.program_write(&mut [], 0xf000, 0xfd);
+        mapper.program_write(&mut [], 0xf001, 0x07);
+        run_cpu_cycles(&mut mapper, &mut master_clock, 2);
+        assert!(!mapper.get_pending_interrupt_request());
+        run_cpu_cycles(&mut mapper, &mut master_clock, 1);
+        assert!(mapper.get_pending_interrupt_request());
+        // In scanline mode, the counter is clocked once every 341 / 3 CPU cycles
+        mapper.program_write(&mut [], 0xf000, 0xff);
+        mapper.program_write(&mut [], 0xf001, 0x02);
+        run_cpu_cycles(&mut mapper, &mut master_clock, 113);
+        assert!(!mapper.get_pending_interrupt_request());
+        run_cpu_cycles(&mut mapper, &mut master_clock, 1);
+        assert!(mapper.get_pending_interrupt_request());
+        // Acknowledging the interrupt disables the counter unless it was enabled after acknowledgement
+        mapper.program_write(&mut [], 0xf002, 0x00);
+        run_cpu_cycles(&mut mapper, &mut master_clock, 1000);
+        assert!(!mapper.get_pending_interrupt_request());
+        // Enabling the counter resets the prescaler, which holds its place while the counter is disabled and
+        // carries on when acknowledging the interrupt enables it again
+        mapper.program_write(&mut [], 0xf001, 0x03);
+        mapper.program_write(&mut [], 0xf001, 0x01);
+        run_cpu_cycles(&mut mapper, &mut master_clock, 1000);
+        mapper.program_write(&mut [], 0xf002, 0x00);
+        run_cpu_cycles(&mut mapper, &mut master_clock, 113);
+        assert!(!mapper.get_pending_interrupt_request());
+        run_cpu_cycles(&mut mapper, &mut master_clock, 1);
+        assert!(mapper.get_pending_interrupt_request());
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_mapper_185_character_protection() {
+        let character_rom = [0x5a; 0x2000];
+        let mut mapper = get_mapper(185, 0).unwrap();
+        // Character ROM starts disabled, reading back the low byte of the address
+        assert_eq!(0x34, mapper.character_read(&character_rom, 0x1234));
+        mapper.program_write(&mut [], 0x8000, 0x21);
+        assert_eq!(0x5a, mapper.character_read(&character_rom, 0x1234));
+        mapper.program_write(&mut [], 0x8000, 0x13);
+        assert_eq!(0x34, mapper.character_read(&character_rom, 0x1234));
+        // With a submapper, only the value matching the diodes enables it
+        let mut mapper = get_mapper(185, 6).unwrap();
+        mapper.program_write(&mut [], 0x8000, 0x01);
+        assert_eq!(0x34, mapper.character_read(&character_rom, 0x1234));
+        mapper.program_write(&mut [], 0x8000, 0x02);
+        assert_eq!(0x5a, mapper.character_read(&character_rom, 0x1234));
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_mapper_028_banking_modes() {
+        let mut mapper = get_mapper(28, 0).unwrap();
+        let program_rom_size = 0x80000;
+        // The last 32 KiB bank is selected at power on, past the end of smaller ROMs so that it mirrors their last bank
+        assert_eq!(0x78000, mapper.program_index(program_rom_size, 0x8000) % program_rom_size);
+        assert_eq!(0x7c000, mapper.program_index(program_rom_size, 0xc000) % program_rom_size);
+        let write_register = |mapper: &mut Box<dyn Mapper>, register: u8, data: u8| {
+            mapper.program_write(&mut [], 0x5000, register);
+            mapper.program_write(&mut [], 0x8000, data);
+        };
+        // A 128 KiB UNROM game in the second 128 KiB, with its last bank fixed at $C000 and its second bank at $8000
+        write_register(&mut mapper, 0x81, 0x07);
+        write_register(&mut mapper, 0x80, 0x2e);
+        write_register(&mut mapper, 0x01, 0x01);
+        // Writes to the bank registers only change the mirroring in one screen modes
+        write_register(&mut mapper, 0x00, 0x12);
+        assert_eq!(0x24000, mapper.program_index(program_rom_size, 0x8000));
+        assert_eq!(0x3c000, mapper.program_index(program_rom_size, 0xc000));
+        assert_eq!(0x4010, mapper.character_index(0x0010));
+        assert_eq!(Mirroring::Vertical, mapper.get_mirroring(Mirroring::Horizontal));
+        // A 64 KiB game with 32 KiB banks and one screen mirroring
+        write_register(&mut mapper, 0x81, 0x02);
+        write_register(&mut mapper, 0x80, 0x10);
+        write_register(&mut mapper, 0x01, 0x11);
+        assert_eq!(0x18000, mapper.program_index(program_rom_size, 0x8000));
+        assert_eq!(0x1c000, mapper.program_index(program_rom_size, 0xc000));
+        assert_eq!(Mirroring::OneScreenUpper, mapper.get_mirroring(Mirroring::Horizontal));
     }
 }
@@ -1,14 +1,35 @@
 //! The cartridge module contains all of the code related to the functionality of
 //! NES cartridges, as well as the functions for loading .nes files.
 
+mod a12;
+mod banks;
+mod info;
 mod mapper;
+mod rom;
+mod tiles;
+mod usage;
+#[cfg(feature = "extra-mappers")]
+mod vrc6;
 
-use mapper::Mapper;
+use crate::state::{StateReader, StateWriter};
+use a12::A12Watcher;
+pub use banks::{BankMapping, CHARACTER_WINDOW_COUNT, CHARACTER_WINDOW_SIZE, PROGRAM_WINDOW_COUNT, PROGRAM_WINDOW_SIZE};
+pub use info::{calculate_rom_md5, CartridgeInfo, ConsoleTiming, ConsoleType, MapperSupport, UnsupportedMapper};
+pub(crate) use mapper::is_mapper_registered;
+pub(crate) use mapper::SUPPORTED_MAPPERS;
+pub use mapper::{Mapper, MapperClone, MapperFactory};
+use rom::RomData;
+use std::convert::TryInto;
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Read;
 use std::path::Path;
+use std::sync::Arc;
+#[cfg(feature = "png-export")]
+pub use tiles::encode_tiles_png;
+pub use tiles::{decode_tile, encode_tile, TILE_PIXELS};
+pub use usage::{CharacterUsage, TILE_SIZE};
 
 // Header constants
 /// Byte array equivalent to the string "NES\u{001a}", used for testing if the file format is valid
@@ -16,16 +37,40 @@ const IDENTIFICATION_STRING: [u8; 4] = [0x4e, 0x45, 0x53, 0x1a];
 
 const PROGRAM_ROM_BANK_SIZE: usize = 16 * 1024; // 16 KiB
 const CHARACTER_ROM_BANK_SIZE: usize = 8 * 1024; // 8 KiB
+/// The smallest program ROM bank that any supported mapper switches
+const MIN_PROGRAM_BANK_SIZE: usize = 8 * 1024; // 8 KiB
+/// The smallest character ROM bank that any supported mapper switches
+const MIN_CHARACTER_BANK_SIZE: usize = 1024; // 1 KiB
+/// The largest program or character ROM that will be loaded, which is more than the NES 2.0 format can declare without
+/// its exponent form. Larger sizes are only declared by corrupt headers, which shouldn't be able to exhaust the host's memory.
+const MAX_ROM_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
 
 /// Type representing a Cartridge that can be loaded by the emulator, created by the
+#[derive(Clone)]
 pub struct Cartridge {
+    /// The information from the header of the .nes file that the cartridge was loaded from
+    info: CartridgeInfo,
     mapper: Box<dyn Mapper>,
     trainer_data: Box<[u8; 512]>,
     mirroring: Mirroring,
-    program_rom: Box<[u8]>,
+    /// The mirroring mode used in place of the header's and mapper's, for ROMs with the wrong mirroring in their header
+    mirroring_override: Option<Mirroring>,
+    // ROM that is shared with the loaded file stays shared, so cloning the cartridge doesn't copy it
+    program_rom: RomData,
     program_ram: Box<[u8]>,
-    // All character memory is treated as ram as games that only have ROM will not attempt to write to it
-    character_ram: Box<[u8]>,
+    // Character ROM and RAM share a buffer. Character ROM that is shared with the loaded file is only copied
+    // if a frontend replaces it, since the PPU can't write to it.
+    character_ram: RomData,
+    /// Whether the character memory is ROM from the .nes file, which ignores writes, rather than RAM
+    character_rom: bool,
+    /// Whether the program RAM is battery backed and should persist between sessions
+    battery: bool,
+    /// The number of writes that have changed the contents of the battery backed program RAM
+    save_generation: u64,
+    /// The tiles of character memory that have been read, or None if usage isn't being tracked
+    character_usage: Option<CharacterUsage>,
+    /// Watches the PPU's address bus for A12 rising, which some mappers count scanlines with
+    a12: A12Watcher,
 }
 
 impl Cartridge {
@@ -35,23 +80,101 @@ impl Cartridge {
     }
 
     /// Read from the cartridge's character ROM/RAM through the cartridge's mapper
-    pub(crate) fn character_read(&self, address: u16) -> u8 {
+    pub(crate) fn character_read(&mut self, address: u16) -> u8 {
+        if let Some(usage) = &mut self.character_usage {
+            if !self.character_ram.is_empty() {
+                // Indices past the end of character memory wrap around, as they do when they are read
+                usage.record(self.mapper.character_index(address) % self.character_ram.len());
+            }
+        }
+        let value = self.mapper.character_read(&self.character_ram, address);
+        self.mapper.character_read_notify(address);
+        return value;
+    }
+
+    /// Read from the cartridge's character ROM/RAM through the cartridge's mapper for debuggers, without
+    /// recording the read in the character memory usage
+    pub(crate) fn debug_character_read(&self, address: u16) -> u8 {
         return self.mapper.character_read(&self.character_ram, address);
     }
 
     /// Write to the cartridge's program RAM through the cartridge's mapper
     pub(crate) fn program_write(&mut self, address: u16, data: u8) {
-        self.mapper.program_write(&mut self.program_ram, address, data)
+        // Every supported mapper puts program RAM at $6000-$7FFF, so only writes there can change the battery backed RAM
+        if self.battery && (0x6000..=0x7fff).contains(&address) {
+            let previous = self.program_read(address);
+            self.mapper.program_write(&mut self.program_ram, address, data);
+            if self.program_read(address) != previous {
+                self.save_generation += 1;
+            }
+            return;
+        }
+        // Writes to the mapper's registers can change its non-volatile state
+        let previous_generation = self.mapper.get_nonvolatile_generation();
+        if address >= 0x8000 && self.mapper.has_bus_conflicts() {
+            // The ROM drives the data bus at the same time as the CPU, and the zeroes win
+            let data = data & self.program_read(address);
+            self.mapper.program_write(&mut self.program_ram, address, data)
+        } else {
+            self.mapper.program_write(&mut self.program_ram, address, data)
+        }
+        if self.mapper.get_nonvolatile_generation() != previous_generation {
+            self.save_generation += 1;
+        }
     }
 
     /// Write to the cartridge's character RAM through the cartridge's mapper
     pub(crate) fn character_write(&mut self, address: u16, data: u8) {
-        self.mapper.character_write(&mut self.character_ram, address, data)
+        // Writes to character ROM go nowhere, so the mapper isn't given them
+        if !self.character_rom {
+            self.mapper.character_write(self.character_ram.to_mut(), address, data)
+        }
+    }
+
+    /// Gets the information from the header of the .nes file that the cartridge was loaded from, like its mapper,
+    /// memory sizes, and the region and console it was made for
+    pub fn get_info(&self) -> &CartridgeInfo {
+        return &self.info;
+    }
+
+    /// Checks if the cartridge has character RAM that the game fills itself, rather than character ROM
+    pub fn has_character_ram(&self) -> bool {
+        return !self.character_rom;
     }
 
     /// Get the mirroring mode from the cartridge
     pub(crate) fn get_mirroring(&mut self) -> Mirroring {
-        return self.mapper.get_mirroring(self.mirroring);
+        return match self.mirroring_override {
+            Some(mirroring) => mirroring,
+            None => self.mapper.get_mirroring(self.mirroring),
+        };
+    }
+
+    /// Gets the banks of program ROM and character memory that are currently switched into each window of the
+    /// CPU and PPU address spaces. Banks past the end of an undersized ROM are reported as the banks they mirror.
+    pub fn get_bank_mapping(&self) -> BankMapping {
+        let mut mapping = BankMapping::default();
+        for (window, bank) in mapping.program_banks.iter_mut().enumerate() {
+            let index = self
+                .mapper
+                .program_index(self.program_rom.len(), (0x8000 + window * PROGRAM_WINDOW_SIZE) as u16);
+            *bank = index.checked_rem(self.program_rom.len()).unwrap_or(0) / PROGRAM_WINDOW_SIZE;
+        }
+        for (window, bank) in mapping.character_banks.iter_mut().enumerate() {
+            let index = self.mapper.character_index((window * CHARACTER_WINDOW_SIZE) as u16);
+            *bank = index.checked_rem(self.character_ram.len()).unwrap_or(0) / CHARACTER_WINDOW_SIZE;
+        }
+        return mapping;
+    }
+
+    /// Forces the cartridge to use a mirroring mode, regardless of its header and mapper, or restores them with None
+    pub fn override_mirroring(&mut self, mirroring: Option<Mirroring>) {
+        self.mirroring_override = mirroring;
+    }
+
+    /// Gets the mirroring mode that the cartridge has been forced to use, if any
+    pub fn get_mirroring_override(&self) -> Option<Mirroring> {
+        return self.mirroring_override;
     }
 
     /// Check if the cartridge is triggering an interrupt
@@ -59,10 +182,244 @@ impl Cartridge {
         return self.mapper.get_pending_interrupt_request();
     }
 
-    /// Called at the end of each scanline. Used by iNES Mapper 004 to
-    /// trigger interrupt requests at specific times during screen rendering
-    pub(crate) fn end_of_scanline(&mut self) {
-        self.mapper.end_of_scanline();
+    /// Called with each address that the PPU puts on its address bus and the dot it did so at, counted from when
+    /// the PPU was created. Notifies the mapper when A12 rises, after filtering out brief drops if filtering is enabled.
+    pub(crate) fn ppu_address_bus(&mut self, address: u16, dot: u64) {
+        if self.a12.watch(address, dot) {
+            self.mapper.a12_rising_edge();
+        }
+    }
+
+    /// Sets whether rises of the PPU's A12 address line are only passed on to the mapper after it has been low for a
+    /// few CPU cycles, as the MMC3 requires
+    pub(crate) fn set_a12_filtering(&mut self, filtering: bool) {
+        self.a12.set_filtering(filtering);
+    }
+
+    /// Clocks the parts of the cartridge's mapper that are timed by CPU cycles, called every CPU cycle with the cycle
+    /// of the NES' master clock that it runs on
+    pub(crate) fn cpu_cycle(&mut self, master_clock: u64) {
+        self.mapper.cpu_cycle(master_clock);
+    }
+
+    /// Clocks the cartridge's expansion audio, called every CPU cycle
+    pub(crate) fn audio_cycle(&mut self) {
+        self.mapper.audio_cycle();
+    }
+
+    /// Gets the current output of the cartridge's expansion audio, or 0.0 if it doesn't have any
+    pub(crate) fn get_audio_output(&self) -> f32 {
+        return self.mapper.audio_output();
+    }
+
+    /// Checks if the cartridge's program RAM is battery backed, meaning the game expects it to persist between sessions
+    pub fn is_battery_backed(&self) -> bool {
+        return self.battery;
+    }
+
+    /// Checks if the cartridge has anything to save between sessions, either battery backed program RAM or state
+    /// kept in the mapper's own non-volatile memory
+    pub fn has_save_data(&self) -> bool {
+        return self.battery || self.mapper.get_nonvolatile_state().is_some();
+    }
+
+    /// Gets the data that should persist between sessions, or None if the cartridge has nothing to save. The data is
+    /// the contents of the program RAM if it is battery backed, followed by the mapper's non-volatile state if it has any.
+    pub fn get_save_data(&self) -> Option<Vec<u8>> {
+        if !self.has_save_data() {
+            return None;
+        }
+        let mut data = if self.battery { self.program_ram.to_vec() } else { Vec::new() };
+        data.extend_from_slice(self.mapper.get_nonvolatile_state().unwrap_or_default());
+        return Some(data);
+    }
+
+    /// Gets the number of writes that have changed the contents of the battery backed program RAM or the mapper's
+    /// non-volatile state since the cartridge was loaded. Frontends can compare it to the generation they last saved to know if the save data needs saving again.
+    pub fn get_save_generation(&self) -> u64 {
+        return self.save_generation;
+    }
+
+    /// Sets whether the cartridge records which tiles of its character memory are read.
+    /// Enabling tracking when it is already enabled keeps the tiles that have been recorded so far.
+    pub fn set_character_usage_tracking(&mut self, enabled: bool) {
+        self.character_usage = if enabled {
+            Some(
+                self.character_usage
+                    .take()
+                    .unwrap_or_else(|| CharacterUsage::new(self.character_ram.len())),
+            )
+        } else {
+            None
+        };
+    }
+
+    /// Gets the tiles of character memory that have been read since tracking was enabled, or None if it isn't
+    pub fn get_character_usage(&self) -> Option<&CharacterUsage> {
+        return self.character_usage.as_ref();
+    }
+
+    /// Gets a copy of the cartridge's character ROM, or RAM for cartridges without ROM, in the order it is stored in
+    /// the .nes file. Banks are numbered from the start of it, like they are in [BankMapping](struct.BankMapping.html),
+    /// so data exported from the bank a mapper switches in can be found at that bank's offset.
+    pub fn export_chr(&self) -> Vec<u8> {
+        return self.character_ram.to_vec();
+    }
+
+    /// Gets the 8 KiB of character memory that the PPU sees from $0000 to $1FFF through the banks that the mapper
+    /// currently has switched in, which is what the pattern tables show
+    pub fn export_mapped_chr(&self) -> Vec<u8> {
+        return (0x0000..0x2000).map(|address| self.debug_character_read(address)).collect();
+    }
+
+    /// Replaces the contents of the cartridge's character memory with data previously returned by
+    /// [export_chr](#method.export_chr), for injecting edited graphics. The change is visible to the PPU straight away.
+    /// Fails if the data is a different size, as the mapper's banks would no longer line up with it.
+    pub fn import_chr(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if data.len() != self.character_ram.len() {
+            bail!(
+                "Character data is {} bytes but the cartridge has {} bytes of character memory",
+                data.len(),
+                self.character_ram.len()
+            );
+        }
+        self.character_ram.to_mut().copy_from_slice(data);
+        return Ok(());
+    }
+
+    /// Replaces the contents of the cartridge's program ROM and character memory in place, for reloading a homebrew game
+    /// after it has been reassembled without losing the state of the running game. Program RAM, the mapper's registers
+    /// and the banks it has switched in are all kept. An empty character slice keeps the current character memory, for
+    /// cartridges with character RAM that the game fills itself. Mappers that keep their own copy of the program ROM,
+    /// like the flash memory of a self-flashable UNROM 512, are given the new data too, replacing anything the game
+    /// wrote to it. Fails if either is a different size, as the mapper's banks would no longer line up with it.
+    pub fn reload_rom_data(&mut self, program_rom: &[u8], character_rom: &[u8]) -> Result<(), Box<dyn Error>> {
+        if program_rom.len() != self.program_rom.len() {
+            bail!(
+                "Program data is {} bytes but the cartridge has {} bytes of program ROM",
+                program_rom.len(),
+                self.program_rom.len()
+            );
+        }
+        if !character_rom.is_empty() && character_rom.len() != self.character_ram.len() {
+            bail!(
+                "Character data is {} bytes but the cartridge has {} bytes of character memory",
+                character_rom.len(),
+                self.character_ram.len()
+            );
+        }
+        self.program_rom = RomData::Owned(program_rom.to_vec());
+        self.mapper.load_rom(&self.info, &self.program_rom);
+        if !character_rom.is_empty() {
+            self.character_ram.to_mut().copy_from_slice(character_rom);
+        }
+        return Ok(());
+    }
+
+    /// Replaces the contents of the battery backed program RAM and the mapper's non-volatile state with data previously
+    /// returned by [get_save_data](#method.get_save_data). Fails if the cartridge has nothing to save or the data is a different size.
+    pub fn load_save_data(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if !self.has_save_data() {
+            bail!("The cartridge does not have battery backed memory");
+        }
+        let program_ram_length = if self.battery { self.program_ram.len() } else { 0 };
+        let state_length = self.mapper.get_nonvolatile_state().map_or(0, <[u8]>::len);
+        if data.len() != program_ram_length + state_length {
+            bail!(
+                "Save data is {} bytes but the cartridge has {} bytes of program RAM and {} bytes of mapper state",
+                data.len(),
+                program_ram_length,
+                state_length
+            );
+        }
+        let (program_ram, state) = data.split_at(program_ram_length);
+        if self.battery {
+            self.program_ram.copy_from_slice(program_ram);
+        }
+        if state_length > 0 {
+            self.mapper.load_nonvolatile_state(state);
+        }
+        return Ok(());
+    }
+
+    /// Writes the cartridge's RAM, the mapper's registers and its non-volatile state to a save state, along with the
+    /// mapper id and the size of the program ROM, which loading checks to catch states saved with another game
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.info.mapper_id);
+        state.write_u32(self.program_rom.len() as u32);
+        state.write_block(&self.program_ram);
+        state.write_block(if self.character_rom { &[] } else { &self.character_ram });
+        state.write_block(self.mapper.get_nonvolatile_state().unwrap_or_default());
+        let mut mapper_state = StateWriter::new();
+        self.mapper.save_state(&mut mapper_state);
+        state.write_block(&mapper_state.into_bytes());
+        self.a12.save_state(state);
+    }
+
+    /// Restores the cartridge's RAM, the mapper's registers and its non-volatile state from a save state. Fails if the
+    /// state was saved with a cartridge with a different mapper or memory sizes. Restoring different save data counts
+    /// as a change to it, so that it is saved again.
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        let mapper_id = state.read_u16()?;
+        let program_rom_size = state.read_u32()? as usize;
+        if mapper_id != self.info.mapper_id || program_rom_size != self.program_rom.len() {
+            bail!(
+                "Save state is for a cartridge with mapper {} and {} bytes of program ROM, not mapper {} and {} bytes",
+                mapper_id,
+                program_rom_size,
+                self.info.mapper_id,
+                self.program_rom.len()
+            );
+        }
+        let save_data = self.get_save_data();
+        state.read_block_into(&mut self.program_ram)?;
+        let character_ram = state.read_block()?;
+        if !self.character_rom {
+            if character_ram.len() != self.character_ram.len() {
+                bail!(
+                    "Save state has {} bytes of character RAM where {} were expected",
+                    character_ram.len(),
+                    self.character_ram.len()
+                );
+            }
+            self.character_ram.to_mut().copy_from_slice(character_ram);
+        }
+        let nonvolatile_state = state.read_block()?;
+        if nonvolatile_state.len() != self.mapper.get_nonvolatile_state().map_or(0, <[u8]>::len) {
+            bail!(
+                "Save state has {} bytes of mapper state that isn't the expected size",
+                nonvolatile_state.len()
+            );
+        }
+        if !nonvolatile_state.is_empty() {
+            self.mapper.load_nonvolatile_state(nonvolatile_state);
+        }
+        let mut mapper_state = StateReader::new(state.read_block()?);
+        self.mapper.load_state(&mut mapper_state)?;
+        mapper_state.finish()?;
+        self.a12.load_state(state)?;
+        if self.get_save_data() != save_data {
+            self.save_generation += 1;
+        }
+        return Ok(());
+    }
+
+    /// Registers a mapper for cartridges with the iNES or NES 2.0 mapper id to be loaded with, so that other crates
+    /// can add mappers that gc_nes_core doesn't have, or replace its own. Registered mappers replace any mapper
+    /// with the same id, apply to every cartridge loaded afterwards, and are reported as fully supported.
+    /// ```
+    /// use gc_nes_core::cartridge::{Cartridge, Mapper};
+    ///
+    /// /// A board with one fixed 32 KiB program bank and no character banking
+    /// #[derive(Clone)]
+    /// struct FixedMapper {}
+    ///
+    /// impl Mapper for FixedMapper {}
+    ///
+    /// Cartridge::register_mapper(0xfff, |_submapper_id| Box::new(FixedMapper {}));
+    /// ```
+    pub fn register_mapper(mapper_id: u16, factory: MapperFactory) {
+        mapper::register_mapper(mapper_id, factory);
     }
 
     /// Loads a cartridge from a file
@@ -72,95 +429,190 @@ impl Cartridge {
     }
 
     /// Loads a cartridge from a reader and returns
+    ///
+    /// If the cartridge's mapper isn't supported the error is an [`UnsupportedMapper`].
     pub fn load_from_reader<T: Read>(mut reader: T) -> Result<Cartridge, Box<dyn Error>> {
-        let mut header: [u8; 16] = [0; 16];
-        reader.read_exact(&mut header)?;
-
-        // Test file format
-        if header[..IDENTIFICATION_STRING.len()] == IDENTIFICATION_STRING {
-            let header_flags_6 = HeaderFlags6::from_bits_truncate(header[6]);
-            let header_flags_7 = HeaderFlags7::from_bits_truncate(header[7]);
-
-            let nes2: bool = header_flags_7.contains(HeaderFlags7::NES_2_IDENTIFIER); // Check if file is NES 2.0
-            if nes2 {
-                debug!("File is in NES 2.0 format");
-            } else {
-                debug!("File is in iNes format");
-            }
+        let info = CartridgeInfo::read_from_reader(&mut reader)?;
+        let mut cartridge = Cartridge::from_info(&info)?;
 
-            // Get a mapper based on the four mapper identification fragments in the 6th, 7th, and 8th bytes of the header, along with a submapper
-            let mapper = mapper::get_mapper(
-                u16::from(header[8] & 0x0f) << 8
-                    | u16::from(header[7] & HeaderFlags7::MAPPER_HI.bits)
-                    | u16::from(header[6] & HeaderFlags6::MAPPER_LO.bits) >> 4,
-                (header[8] & 0xf0) >> 4,
-            )?;
-
-            let mirroring = if header_flags_6.contains(HeaderFlags6::VERTICAL_MIRRORING) {
-                Mirroring::Vertical
-            } else {
-                Mirroring::Horizontal
-            };
-
-            let program_rom_size = calculate_rom_size(header[4], header[9] & 0x0f, PROGRAM_ROM_BANK_SIZE, nes2)?;
-            debug!("Allocating {} bytes for program ROM", program_rom_size);
-
-            let mut program_ram_size = calculate_ram_size(header[10], 0);
-            if program_ram_size == 0 {
-                program_ram_size = 0x2000
-            }
-            debug!("Allocating {} bytes for program RAM", program_ram_size);
+        if info.trainer {
+            debug!("Trainer is present");
+            reader.read_exact(cartridge.trainer_data.as_mut())?;
+        }
 
-            let mut character_rom_size = calculate_rom_size(header[5], header[9] & 0xf0, CHARACTER_ROM_BANK_SIZE, nes2)?;
-            if character_rom_size == 0 {
-                character_rom_size = 0x2000
-            }
-            debug!("Allocating {} bytes for character ROM", character_rom_size);
-
-            let mut cartridge = Cartridge {
-                mapper,
-                mirroring,
-                trainer_data: Box::new([0; 512]),
-                program_rom: vec![0; program_rom_size].into_boxed_slice(),
-                program_ram: vec![0; program_ram_size].into_boxed_slice(),
-                character_ram: vec![0; character_rom_size].into_boxed_slice(),
-            };
-
-            if HeaderFlags6::from_bits_truncate(header[6]).contains(HeaderFlags6::TRAINER_PRESENT) {
-                debug!("Trainer is present");
-                reader.read_exact(cartridge.trainer_data.as_mut())?;
+        // The ROMs are read before they are allocated in full, so a header can't make the emulator allocate
+        // much more memory than the file itself takes up
+        let program_rom = read_up_to(&mut reader, info.program_rom_size)?;
+        let character_rom = read_up_to(&mut reader, info.character_rom_size)?;
+        cartridge.set_rom(&info, RomData::Owned(program_rom), RomData::Owned(character_rom))?;
+
+        info!("File loaded successfully");
+        return Ok(cartridge);
+    }
+
+    /// Loads a cartridge from the bytes of a .nes file. The bytes are shared with the cartridge rather than copied, so a
+    /// frontend that keeps the buffer, to hash the ROM or list a set of ROMs for example, doesn't need a second copy,
+    /// and cloning the cartridge for a save state doesn't copy its ROM. Character ROM is only copied if a frontend
+    /// replaces it, since the game can't write to it.
+    pub fn load_from_bytes(data: Arc<[u8]>) -> Result<Cartridge, Box<dyn Error>> {
+        let header = match data.get(..16) {
+            Some(header) => header.try_into()?,
+            None => bail!("The .nes file is too short to contain a header"),
+        };
+        let info = CartridgeInfo::from_header(header)?;
+        let mut cartridge = Cartridge::from_info(&info)?;
+
+        let mut offset = header.len();
+        if info.trainer {
+            debug!("Trainer is present");
+            match data.get(offset..offset + 512) {
+                Some(trainer) => cartridge.trainer_data.copy_from_slice(trainer),
+                None => bail!("The .nes file is too short to contain its trainer"),
             }
+            offset += 512;
+        }
 
-            reader.read_exact(cartridge.program_rom.as_mut())?;
-            // Lots of .nes files don't use the exact amount of character memory,
-            // and don't have trailing zeroes until the file reaches the appropriate
-            // length, so using read_exact here would have a risk of crashing.
-            // The length is assigned to a discard to please clippy.
-            let _ = reader.read(cartridge.character_ram.as_mut())?;
+        let program_end = (offset + info.program_rom_size).min(data.len());
+        let character_end = (program_end + info.character_rom_size).min(data.len());
+        let program_rom = RomData::Shared(data.clone(), offset..program_end);
+        let character_rom = RomData::Shared(data, program_end..character_end);
+        cartridge.set_rom(&info, program_rom, character_rom)?;
 
-            info!("File loaded successfully");
-            return Ok(cartridge);
+        info!("File loaded successfully");
+        return Ok(cartridge);
+    }
+
+    /// Creates a cartridge with the mapper and RAM described by a header, without any ROM
+    fn from_info(info: &CartridgeInfo) -> Result<Cartridge, Box<dyn Error>> {
+        if info.nes2 {
+            debug!("File is in NES 2.0 format");
         } else {
-            bail!("File format is invalid!");
+            debug!("File is in iNes format");
         }
+
+        let mapper = mapper::get_mapper(info.mapper_id, info.submapper_id)?;
+
+        let mirroring = if info.vertical_mirroring {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        validate_memory_sizes(info)?;
+
+        let mut program_ram_size = info.program_ram_size;
+        if program_ram_size == 0 {
+            program_ram_size = 0x2000
+        }
+        debug!("Allocating {} bytes for program RAM", program_ram_size);
+
+        return Ok(Cartridge {
+            info: *info,
+            mapper,
+            mirroring,
+            mirroring_override: None,
+            trainer_data: Box::new([0; 512]),
+            program_rom: Default::default(),
+            program_ram: vec![0; program_ram_size].into_boxed_slice(),
+            character_ram: Default::default(),
+            character_rom: info.character_rom_size != 0,
+            battery: info.battery,
+            save_generation: 0,
+            character_usage: None,
+            a12: Default::default(),
+        });
+    }
+
+    /// Fills the cartridge's ROM with the ROM read from a .nes file, which may be shorter than the header declares
+    fn set_rom(&mut self, info: &CartridgeInfo, program_rom: RomData, character_rom: RomData) -> Result<(), Box<dyn Error>> {
+        if program_rom.len() < info.program_rom_size {
+            bail!(
+                "The header declares {} bytes of program ROM but the file only has {}",
+                info.program_rom_size,
+                program_rom.len()
+            );
+        }
+        self.mapper.load_rom(info, &program_rom);
+        self.program_rom = program_rom;
+
+        // Lots of .nes files don't use the exact amount of character memory,
+        // and don't have trailing zeroes until the file reaches the appropriate
+        // length, so the missing bytes are filled with zeroes instead of failing.
+        if character_rom.len() < info.character_rom_size {
+            warn!(
+                "The header declares {} bytes of character ROM but the file only has {}, the ROM may be misdumped",
+                info.character_rom_size,
+                character_rom.len()
+            );
+        }
+        // Cartridges without character ROM have character RAM instead, usually 8 KiB unless a NES 2.0 header says otherwise
+        let character_memory_size = if info.character_rom_size == 0 && info.character_ram_size != 0 {
+            info.character_ram_size
+        } else if info.character_rom_size == 0 {
+            self.mapper.get_character_ram_size()
+        } else {
+            info.character_rom_size
+        };
+        self.character_ram = character_rom;
+        if self.character_ram.len() < character_memory_size {
+            self.character_ram.to_mut().resize(character_memory_size, 0);
+        }
+        return Ok(());
     }
 }
 
+/// Checks that the memory sizes declared in a header can be banked by a mapper. Sizes that aren't a
+/// whole number of the smallest banks are rejected, as they mean that the header is wrong and the
+/// banks that the mapper switches in wouldn't line up with the ROM.
+fn validate_memory_sizes(info: &CartridgeInfo) -> Result<(), Box<dyn Error>> {
+    if info.program_rom_size == 0 {
+        bail!("The .nes file declares no program ROM");
+    }
+    if info.program_rom_size > MAX_ROM_SIZE || info.character_rom_size > MAX_ROM_SIZE {
+        bail!(
+            "The .nes file declares {} bytes of program ROM and {} bytes of character ROM, more than the maximum of {} bytes",
+            info.program_rom_size,
+            info.character_rom_size,
+            MAX_ROM_SIZE
+        );
+    }
+    if !info.program_rom_size.is_multiple_of(MIN_PROGRAM_BANK_SIZE) {
+        bail!(
+            "The .nes file declares {} bytes of program ROM, which isn't a whole number of 8 KiB banks",
+            info.program_rom_size
+        );
+    }
+    if !info.character_rom_size.is_multiple_of(MIN_CHARACTER_BANK_SIZE) {
+        bail!(
+            "The .nes file declares {} bytes of character ROM, which isn't a whole number of 1 KiB banks",
+            info.character_rom_size
+        );
+    }
+    return Ok(());
+}
+
+/// Reads from a reader until it has read the given number of bytes or the reader runs out
+fn read_up_to<T: Read>(reader: &mut T, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut buffer = Vec::new();
+    reader.take(length as u64).read_to_end(&mut buffer)?;
+    return Ok(buffer);
+}
+
 /// Returns the number of bytes of program rom for NES 2.0 or iNes format as a usize
 /// Broken into its own function for ease of testing
 fn calculate_rom_size(least_significant_byte: u8, most_significant_byte: u8, bank_size: usize, nes20: bool) -> Result<usize, Box<dyn Error>> {
     if nes20 && most_significant_byte == 0x0f {
         // In the NES 2.0 format an exponent multiplier format can be used
-        let (size, overflow) = 2usize
-            .pow(u32::from(least_significant_byte >> 2))
-            .overflowing_mul(usize::from(least_significant_byte & 0x03) * 2 + 1);
-        if overflow {
-            bail!(
+        let size = 2usize
+            .checked_pow(u32::from(least_significant_byte >> 2))
+            .and_then(|power| power.checked_mul(usize::from(least_significant_byte & 0x03) * 2 + 1));
+        return match size {
+            Some(size) => Ok(size),
+            None => bail!(
                 ".nes file memory size exceeded the maximum addressable range of the platform: {} bytes",
-                usize::max_value()
-            )
-        }
-        return Ok(size);
+                usize::MAX
+            ),
+        };
     } else {
         // For other cases program rom size is just the value of the lsb and msb combined times 16 KiB
         let mut banks = usize::from(least_significant_byte);
@@ -193,55 +645,122 @@ bitflags! {
     #[derive(Default)]
     struct HeaderFlags7: u8 {
         const CONSOLE_TYPE = 0b0000_0011;
+        const CONSOLE_TYPE_VS_SYSTEM = 0b0000_0001;
         const NES_2_IDENTIFIER = 0b0000_1100;
+        const NES_2_IDENTIFIER_VALUE = 0b0000_1000;
         const MAPPER_HI = 0b1111_0000;
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 /// Enum used to represent the various mirroring modes of the NES,
 /// which are used to map nametable addresses.
-pub(crate) enum Mirroring {
+pub enum Mirroring {
+    /// Every nametable address maps to the first nametable
     OneScreenLower,
+    /// Every nametable address maps to the second nametable
     OneScreenUpper,
+    /// The nametables at $2000 and $2800 share memory, as do $2400 and $2C00
     Vertical,
+    /// The nametables at $2000 and $2400 share memory, as do $2800 and $2C00
     Horizontal,
 }
 
-#[cfg(test)]
-/// Module of some mock types that have been created for testing convenience
-pub(crate) mod test_utils {
+impl Mirroring {
+    /// Gets the lowercase name of the mirroring mode
+    pub fn name(self) -> &'static str {
+        return match self {
+            Mirroring::OneScreenLower => "lower",
+            Mirroring::OneScreenUpper => "upper",
+            Mirroring::Vertical => "vertical",
+            Mirroring::Horizontal => "horizontal",
+        };
+    }
+
+    /// Gets the mirroring mode with a lowercase name, or None if there isn't one
+    pub fn from_name(name: &str) -> Option<Self> {
+        return [
+            Mirroring::OneScreenLower,
+            Mirroring::OneScreenUpper,
+            Mirroring::Vertical,
+            Mirroring::Horizontal,
+        ]
+        .iter()
+        .copied()
+        .find(|mirroring| mirroring.name() == name);
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+/// Module of some mock types that have been created for testing convenience. Available to other crates
+/// with the `testing` feature, for unit testing against a cartridge without a real ROM image:
+/// ```
+/// use gc_nes_core::cartridge::test_utils::{get_mock_cartridge, MapperMock};
+/// use gc_nes_core::nes::Nes;
+///
+/// // A cartridge that fills its program ROM with NOPs, and its reset vector with $8000
+/// let cartridge = get_mock_cartridge(MapperMock {
+///     program_read_stub: |address, _| if address >= 0xfffc { [0x00, 0x80][usize::from(address & 0x01)] } else { 0xea },
+///     ..Default::default()
+/// });
+/// let mut nes = Nes::new(cartridge);
+/// nes.cycle();
+/// ```
+pub mod test_utils {
     use super::*;
 
-    pub(crate) fn get_mock_cartridge(mapper_mock: MapperMock) -> Cartridge {
+    /// Creates a cartridge whose reads and writes are passed to the stubs of the mapper mock,
+    /// with horizontal mirroring unless the mock overrides it
+    pub fn get_mock_cartridge(mapper_mock: MapperMock) -> Cartridge {
+        let header = [0x4e, 0x45, 0x53, 0x1a, 0x01, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
         return Cartridge {
+            info: CartridgeInfo::from_header(&header).unwrap(),
             mapper: Box::new(mapper_mock),
             trainer_data: Box::new([0; 512]),
             mirroring: Mirroring::Horizontal,
-            program_rom: Box::new([0]),
+            mirroring_override: None,
+            program_rom: RomData::Owned(vec![0]),
             program_ram: Box::new([0]),
-            character_ram: Box::new([0]),
+            character_ram: RomData::Owned(vec![0]),
+            character_rom: false,
+            battery: false,
+            save_generation: 0,
+            character_usage: None,
+            a12: Default::default(),
         };
     }
 
+    #[cfg(test)]
     impl Default for Cartridge {
         fn default() -> Self {
             return get_mock_cartridge(Default::default());
         }
     }
 
-    /// Mapper mock struct for testing Cartridge interactions in other modules
-    pub(crate) struct MapperMock {
-        pub(crate) program_read_count: u16,
-        pub(crate) program_read_stub: fn(u16, u16) -> u8,
-        pub(crate) character_read_count: u16,
-        pub(crate) character_read_stub: fn(u16, u16) -> u8,
-        pub(crate) program_write_count: u16,
-        pub(crate) program_write_stub: fn(u16, u8, u16),
-        pub(crate) character_write_count: u16,
-        pub(crate) character_write_stub: fn(u16, u8, u16),
-        pub(crate) get_mirroring_count: u16,
-        pub(crate) get_mirroring_stub: fn(u16) -> Mirroring,
+    /// Mapper mock struct for testing Cartridge interactions in other modules. Each stub is passed the arguments of
+    /// the call along with its count field, and the default stubs panic so that unexpected calls fail the test.
+    #[derive(Clone)]
+    pub struct MapperMock {
+        /// The value passed to program_read_stub as its last argument
+        pub program_read_count: u16,
+        /// Called with the address for reads from the CPU bus above $4020, returning the value read
+        pub program_read_stub: fn(u16, u16) -> u8,
+        /// The value passed to character_read_stub as its last argument
+        pub character_read_count: u16,
+        /// Called with the address for reads from the PPU bus below $2000, returning the value read
+        pub character_read_stub: fn(u16, u16) -> u8,
+        /// The value passed to program_write_stub as its last argument
+        pub program_write_count: u16,
+        /// Called with the address and value for writes to the CPU bus above $4020
+        pub program_write_stub: fn(u16, u8, u16),
+        /// The value passed to character_write_stub as its last argument
+        pub character_write_count: u16,
+        /// Called with the address and value for writes to the PPU bus below $2000
+        pub character_write_stub: fn(u16, u8, u16),
+        /// The value passed to get_mirroring_stub as its argument
+        pub get_mirroring_count: u16,
+        /// Called whenever the PPU accesses the nametables, returning the mirroring to use
+        pub get_mirroring_stub: fn(u16) -> Mirroring,
     }
 
     impl Mapper for MapperMock {
@@ -287,6 +806,7 @@ pub(crate) mod test_utils {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::timing::MasterClock;
 
     //Macro for reducing the amount of boilerplate
     macro_rules! calculate_rom_size_tests {
@@ -304,7 +824,7 @@ mod test {
                 #[test]
                 fn nes2_exp_maximum() {
                    // Pick appropriate size for to match system limitations
-                   if usize::max_value() == u32::max_value() as usize {
+                   if usize::MAX == u32::MAX as usize {
                         calculate_rom_size(0xa0, 0x0f, PROGRAM_ROM_BANK_SIZE, true).expect_err("Did not produce an error for a value that exceeds the maximum addressable range of 32 bit systems");
                    } else {
                         calculate_rom_size(0xff, 0x0f, PROGRAM_ROM_BANK_SIZE, true).expect_err("Did not produce an error for a value that exceeds the maximum addressable range of 64 bit systems");
@@ -314,6 +834,461 @@ mod test {
         }
     }
 
+    /// Builds a .nes file for a mapper with the given number of 16 KiB program banks and 8 KiB character banks
+    fn get_test_rom(mapper_id: u8, program_banks: u8, character_banks: u8) -> Vec<u8> {
+        let mut rom = vec![
+            0x4e,
+            0x45,
+            0x53,
+            0x1a,
+            program_banks,
+            character_banks,
+            mapper_id << 4,
+            0x00,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        rom.resize(
+            16 + usize::from(program_banks) * PROGRAM_ROM_BANK_SIZE + usize::from(character_banks) * CHARACTER_ROM_BANK_SIZE,
+            0x00,
+        );
+        return rom;
+    }
+
+    #[test]
+    fn test_load_without_program_rom() {
+        assert!(Cartridge::load_from_reader(get_test_rom(0, 0, 1).as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_load_truncated_character_rom() {
+        let mut rom = get_test_rom(3, 1, 4);
+        rom.truncate(rom.len() - 3 * CHARACTER_ROM_BANK_SIZE);
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        // Selecting a bank past the end of the file reads zeroes rather than panicking
+        cartridge.program_write(0x8000, 0x03);
+        assert_eq!(0x00, cartridge.character_read(0x1fff));
+    }
+
+    #[test]
+    fn test_load_inconsistent_sizes() {
+        // A NES 2.0 header declaring 1 KiB of program ROM in the exponent form
+        let mut rom = get_test_rom(0, 0x28, 1);
+        rom[7] = 0x08;
+        rom[9] = 0x0f;
+        rom.resize(16 + 0x0400 + CHARACTER_ROM_BANK_SIZE, 0x00);
+        let error = Cartridge::load_from_reader(rom.as_slice()).err().unwrap();
+        assert!(error.to_string().contains("8 KiB banks"));
+        // And one declaring 512 bytes of character ROM
+        let mut rom = get_test_rom(0, 1, 0x24);
+        rom[7] = 0x08;
+        rom[9] = 0xf0;
+        rom.resize(16 + PROGRAM_ROM_BANK_SIZE + 0x0200, 0x00);
+        let error = Cartridge::load_from_reader(rom.as_slice()).err().unwrap();
+        assert!(error.to_string().contains("1 KiB banks"));
+    }
+
+    #[test]
+    fn test_load_unsupported_mapper() {
+        let mut rom = get_test_rom(0x05, 1, 1);
+        rom[7] = 0x50;
+        let error = Cartridge::load_from_reader(rom.as_slice()).err().unwrap();
+        let unsupported = error.downcast_ref::<UnsupportedMapper>().unwrap();
+        assert_eq!(85, unsupported.mapper_id);
+        assert_eq!(0, unsupported.submapper_id);
+        assert_eq!(Some("VRC7"), unsupported.board_name);
+    }
+
+    #[test]
+    fn test_load_oversized_header() {
+        // A NES 2.0 header declaring 2^40 bytes of program ROM in the exponent form
+        let mut rom = get_test_rom(0, 0xa0, 1);
+        rom[7] = 0x08;
+        rom[9] = 0x0f;
+        assert!(Cartridge::load_from_reader(rom.as_slice()).is_err());
+        // An iNES header declaring more program ROM than the file has
+        let mut rom = get_test_rom(0, 2, 0);
+        rom.truncate(rom.len() - 1);
+        assert!(Cartridge::load_from_reader(rom.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_mapper_001_large_boards() {
+        // A 512KiB SXROM board with 32KiB of battery backed program RAM, marking the start of each program bank
+        let mut rom = get_test_rom(1, 32, 0);
+        rom[7] = 0x08;
+        rom[10] = 0x90;
+        for bank in 0..32 {
+            rom[16 + bank * PROGRAM_ROM_BANK_SIZE] = bank as u8;
+        }
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        // Writes one of the MMC1's registers with five serial writes, a few cycles apart
+        let master_clock = std::cell::Cell::new(0);
+        let write_register = |cartridge: &mut Cartridge, address: u16, value: u8| {
+            for bit in 0..5 {
+                for _ in 0..2 {
+                    master_clock.set(master_clock.get() + u64::from(MasterClock::NTSC.cpu_divider));
+                    cartridge.cpu_cycle(master_clock.get());
+                }
+                cartridge.program_write(address, (value >> bit) & 1);
+            }
+        };
+        // The last bank of the first half is fixed at $C000 until bit 4 of the character bank switches to the second
+        assert_eq!(15, cartridge.program_read(0xc000));
+        write_register(&mut cartridge, 0xe000, 0x02);
+        assert_eq!(2, cartridge.program_read(0x8000));
+        write_register(&mut cartridge, 0xa000, 0x10);
+        assert_eq!(18, cartridge.program_read(0x8000));
+        assert_eq!(31, cartridge.program_read(0xc000));
+        // Bits 2 and 3 select the bank of program RAM
+        cartridge.program_write(0x6000, 0xaa);
+        write_register(&mut cartridge, 0xa000, 0x0c);
+        assert_eq!(0x00, cartridge.program_read(0x6000));
+        cartridge.program_write(0x6000, 0xbb);
+        write_register(&mut cartridge, 0xa000, 0x00);
+        assert_eq!(0xaa, cartridge.program_read(0x6000));
+        assert_eq!(0x8000, cartridge.program_ram.len());
+        assert_eq!(0xbb, cartridge.program_ram[0x6000]);
+    }
+
+    /// A mapper for testing registration, which switches a 16KiB program bank at $8000 on writes
+    #[derive(Clone)]
+    struct RegisteredMapper {
+        bank_select: u8,
+    }
+
+    impl Mapper for RegisteredMapper {
+        fn program_index(&self, _program_rom_size: usize, address: u16) -> usize {
+            return usize::from(address & 0x3fff) + usize::from(self.bank_select) * 0x4000;
+        }
+
+        fn program_write(&mut self, _program_ram: &mut [u8], _address: u16, data: u8) {
+            self.bank_select = data;
+        }
+    }
+
+    #[test]
+    fn test_register_mapper() {
+        // A NES 2.0 header for mapper 4094, which nothing else uses
+        let mut rom = get_test_rom(0x0e, 2, 1);
+        rom[7] = 0xf8;
+        rom[8] = 0x0f;
+        rom[16 + PROGRAM_ROM_BANK_SIZE] = 0xab;
+        assert!(Cartridge::load_from_reader(rom.as_slice()).is_err());
+        Cartridge::register_mapper(0xffe, |_| Box::new(RegisteredMapper { bank_select: 0 }));
+        assert!(crate::capabilities().supports_mapper(0xffe));
+        assert_eq!(
+            MapperSupport::Full,
+            CartridgeInfo::read_from_reader(rom.as_slice()).unwrap().mapper_support
+        );
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        assert_eq!(0x00, cartridge.program_read(0x8000));
+        cartridge.program_write(0x8000, 0x01);
+        assert_eq!(0xab, cartridge.program_read(0x8000));
+    }
+
+    #[test]
+    fn test_character_ram() {
+        let mut cartridge = Cartridge::load_from_reader(get_test_rom(0, 1, 0).as_slice()).unwrap();
+        assert!(cartridge.has_character_ram());
+        assert_eq!(0x2000, cartridge.character_ram.len());
+        cartridge.character_write(0x1234, 0x56);
+        assert_eq!(0x56, cartridge.character_read(0x1234));
+
+        let mut cartridge = Cartridge::load_from_reader(get_test_rom(0, 1, 1).as_slice()).unwrap();
+        assert!(!cartridge.has_character_ram());
+        cartridge.character_write(0x1234, 0x56);
+        assert_eq!(0x00, cartridge.character_read(0x1234));
+
+        // NES 2.0 headers can give a different size of character RAM
+        let mut rom = get_test_rom(0, 1, 0);
+        rom[7] = 0x08;
+        rom[11] = 0x09;
+        let cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        assert_eq!(0x8000, cartridge.get_info().character_ram_size);
+        assert_eq!(0x8000, cartridge.character_ram.len());
+    }
+
+    #[test]
+    fn test_load_from_bytes() {
+        let mut rom = get_test_rom(3, 1, 2);
+        rom[16 + 0x0123] = 0xab;
+        rom[16 + PROGRAM_ROM_BANK_SIZE + CHARACTER_ROM_BANK_SIZE + 0x0045] = 0xcd;
+        let rom: Arc<[u8]> = Arc::from(rom);
+        let mut cartridge = Cartridge::load_from_bytes(rom.clone()).unwrap();
+        assert!(matches!(cartridge.program_rom, RomData::Shared(..)));
+        assert!(matches!(cartridge.character_ram, RomData::Shared(..)));
+        // Cloning the cartridge doesn't copy its ROM
+        let clone = cartridge.clone();
+        assert_eq!(5, Arc::strong_count(&rom));
+        drop(clone);
+        assert_eq!(0xab, cartridge.program_read(0x8123));
+        cartridge.program_write(0x8000, 0x01);
+        assert_eq!(0xcd, cartridge.character_read(0x0045));
+
+        // Writes to character ROM are ignored, so it stays shared
+        cartridge.character_write(0x0045, 0xef);
+        assert_eq!(0xcd, cartridge.character_read(0x0045));
+        assert!(matches!(cartridge.character_ram, RomData::Shared(..)));
+
+        assert!(Cartridge::load_from_bytes(Arc::from(&rom[..16 + 0x1000])).is_err());
+        assert!(Cartridge::load_from_bytes(Arc::from(&rom[..8])).is_err());
+    }
+
+    #[test]
+    fn test_bank_select_past_end_of_rom() {
+        let mut rom = get_test_rom(2, 2, 0);
+        rom[16 + 0x1234] = 0xab;
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        // UxROM only has two banks, so bank 4 mirrors bank 0
+        cartridge.program_write(0x8000, 0x04);
+        assert_eq!(0xab, cartridge.program_read(0x9234));
+    }
+
+    #[test]
+    fn test_save_data() {
+        let mut rom = get_test_rom(0, 1, 1);
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        assert!(cartridge.get_save_data().is_none());
+        assert!(cartridge.load_save_data(&[0; 0x2000]).is_err());
+
+        rom[6] |= 0x02; // Battery backed program RAM
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        cartridge.program_write(0x6001, 0x42);
+        assert_eq!(Some(0x42), cartridge.get_save_data().map(|data| data[1]));
+        // Only writes that change the contents move the generation on
+        cartridge.program_write(0x6001, 0x42);
+        assert_eq!(1, cartridge.get_save_generation());
+
+        assert!(cartridge.load_save_data(&[0; 16]).is_err());
+        cartridge.load_save_data(&[0x24; 0x2000]).unwrap();
+        assert_eq!(0x24, cartridge.program_read(0x6001));
+    }
+
+    /// Multicart menu that remembers the game that was last selected in non-volatile memory
+    #[derive(Clone)]
+    struct NonvolatileMenuMapper {
+        selected_game: [u8; 1],
+        generation: u64,
+    }
+
+    impl Mapper for NonvolatileMenuMapper {
+        fn program_write(&mut self, _program_ram: &mut [u8], address: u16, data: u8) {
+            if address >= 0x8000 && self.selected_game[0] != data {
+                self.selected_game[0] = data;
+                self.generation += 1;
+            }
+        }
+
+        fn get_nonvolatile_generation(&self) -> u64 {
+            return self.generation;
+        }
+
+        fn get_nonvolatile_state(&self) -> Option<&[u8]> {
+            return Some(&self.selected_game);
+        }
+
+        fn load_nonvolatile_state(&mut self, data: &[u8]) {
+            self.selected_game.copy_from_slice(data);
+        }
+    }
+
+    #[test]
+    fn test_mapper_save_data() {
+        let mut cartridge = Cartridge::load_from_reader(get_test_rom(0, 1, 1).as_slice()).unwrap();
+        cartridge.mapper = Box::new(NonvolatileMenuMapper {
+            selected_game: [0x00],
+            generation: 0,
+        });
+        assert!(cartridge.has_save_data());
+        cartridge.program_write(0x8000, 0x03);
+        assert_eq!(1, cartridge.get_save_generation());
+        assert_eq!(Some(vec![0x03]), cartridge.get_save_data());
+        cartridge.program_write(0x8000, 0x03);
+        assert_eq!(1, cartridge.get_save_generation());
+
+        // The mapper's state follows the battery backed program RAM
+        cartridge.battery = true;
+        assert_eq!(0x2001, cartridge.get_save_data().unwrap().len());
+        assert!(cartridge.load_save_data(&[0x00; 0x2000]).is_err());
+        let mut save_data = vec![0x24; 0x2000];
+        save_data.push(0x05);
+        cartridge.load_save_data(&save_data).unwrap();
+        assert_eq!(0x24, cartridge.program_read(0x6001));
+        assert_eq!(Some(0x05), cartridge.get_save_data().map(|data| data[0x2000]));
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_mapper_030_flash() {
+        // A self-flashable UNROM 512 with one screen mirroring and four 16 KiB banks, each filled with its number
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 0x04, 0x00, 0xea, 0x10, 0, 0, 0, 0, 0, 0, 0, 0];
+        (0..4).for_each(|bank| rom.extend_from_slice(&[bank; PROGRAM_ROM_BANK_SIZE]));
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        assert_eq!(0x8000, cartridge.character_ram.len());
+        assert_eq!(0x03, cartridge.program_read(0xc000));
+
+        // The register is only in the upper half, as writes to the lower half go to the flash memory
+        cartridge.program_write(0xc000, 0xa2);
+        assert_eq!(0x02, cartridge.program_read(0x8000));
+        assert_eq!(Mirroring::OneScreenUpper, cartridge.get_mirroring());
+        cartridge.character_write(0x0010, 0x5a);
+        assert_eq!(0x5a, cartridge.character_ram[0x2010]);
+
+        // Program $00 to the first byte of bank 2, after erasing the first sector of bank 1
+        let unlock = [(0xc000, 0x01), (0x9555, 0xaa), (0xc000, 0x00), (0xaaaa, 0x55), (0xc000, 0x01)];
+        unlock.iter().for_each(|(address, data)| cartridge.program_write(*address, *data));
+        cartridge.program_write(0x9555, 0x80);
+        unlock.iter().for_each(|(address, data)| cartridge.program_write(*address, *data));
+        cartridge.program_write(0x8000, 0x30);
+        assert_eq!(0xff, cartridge.program_read(0x8fff));
+        assert_eq!(0x01, cartridge.program_read(0x9000));
+        unlock.iter().for_each(|(address, data)| cartridge.program_write(*address, *data));
+        cartridge.program_write(0x9555, 0xa0);
+        cartridge.program_write(0xc000, 0x02);
+        cartridge.program_write(0x8000, 0x00);
+        assert_eq!(0x00, cartridge.program_read(0x8000));
+        assert_eq!(0x02, cartridge.program_read(0x8001));
+        assert_eq!(2, cartridge.get_save_generation());
+
+        // The flash memory is saved after the program RAM
+        let save_data = cartridge.get_save_data().unwrap();
+        assert_eq!(0x2000 + 4 * PROGRAM_ROM_BANK_SIZE, save_data.len());
+        assert_eq!(0xff, save_data[0x2000 + PROGRAM_ROM_BANK_SIZE]);
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_mapper_030_reload_rom_data() {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 0x04, 0x00, 0xea, 0x10, 0, 0, 0, 0, 0, 0, 0, 0];
+        (0..4).for_each(|bank| rom.extend_from_slice(&[bank; PROGRAM_ROM_BANK_SIZE]));
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        cartridge.program_write(0xc000, 0x02);
+        assert_eq!(0x02, cartridge.program_read(0x8000));
+
+        // The flash memory that the game runs from is replaced along with the program ROM, and the bank is kept
+        let program: Vec<u8> = (0x10..0x14).flat_map(|bank| vec![bank; PROGRAM_ROM_BANK_SIZE]).collect();
+        cartridge.reload_rom_data(&program, &[]).unwrap();
+        assert_eq!(0x12, cartridge.program_read(0x8000));
+        assert_eq!(0x13, cartridge.program_read(0xc000));
+        assert_eq!(Mirroring::OneScreenLower, cartridge.get_mirroring());
+        assert_eq!(0x12, cartridge.get_save_data().unwrap()[0x2000 + 2 * PROGRAM_ROM_BANK_SIZE]);
+    }
+
+    #[test]
+    fn test_character_usage() {
+        let mut cartridge = Cartridge::load_from_reader(get_test_rom(3, 1, 4).as_slice()).unwrap();
+        cartridge.character_read(0x0010);
+        assert!(cartridge.get_character_usage().is_none());
+
+        cartridge.set_character_usage_tracking(true);
+        cartridge.character_read(0x0010);
+        cartridge.character_read(0x001f);
+        // Tiles are recorded by their position in the ROM, not the address they were read from
+        cartridge.program_write(0x8000, 0x02);
+        cartridge.character_read(0x1000);
+        let usage = cartridge.get_character_usage().unwrap();
+        assert_eq!(4 * CHARACTER_ROM_BANK_SIZE / TILE_SIZE, usage.get_tiles().len());
+        assert_eq!(2, usage.get_used_tile_count());
+        assert!(usage.is_tile_used(1));
+        assert!(usage.is_tile_used((2 * CHARACTER_ROM_BANK_SIZE + 0x1000) / TILE_SIZE));
+        assert_eq!(vec![true, false, true, false], usage.get_bank_usage(CHARACTER_ROM_BANK_SIZE));
+    }
+
+    #[test]
+    fn test_chr_import_export() {
+        let mut cartridge = Cartridge::load_from_reader(get_test_rom(3, 1, 4).as_slice()).unwrap();
+        let mut chr = cartridge.export_chr();
+        assert_eq!(4 * CHARACTER_ROM_BANK_SIZE, chr.len());
+        assert!(cartridge.import_chr(&chr[..CHARACTER_ROM_BANK_SIZE]).is_err());
+        // Edits to a bank show up at the addresses it is switched into
+        chr[2 * CHARACTER_ROM_BANK_SIZE + 0x0123] = 0xa5;
+        cartridge.import_chr(&chr).unwrap();
+        assert_eq!(chr, cartridge.export_chr());
+        cartridge.program_write(0x8000, 0x02);
+        assert_eq!(0xa5, cartridge.character_read(0x0123));
+        assert_eq!(0xa5, cartridge.export_mapped_chr()[0x0123]);
+    }
+
+    #[test]
+    fn test_reload_rom_data() {
+        let mut cartridge = Cartridge::load_from_reader(get_test_rom(3, 1, 4).as_slice()).unwrap();
+        cartridge.program_write(0x6000, 0x42);
+        cartridge.program_write(0x8000, 0x02);
+        let program = vec![0x24; PROGRAM_ROM_BANK_SIZE];
+        let character = vec![0x18; 4 * CHARACTER_ROM_BANK_SIZE];
+        assert!(cartridge.reload_rom_data(&program[1..], &character).is_err());
+        assert!(cartridge.reload_rom_data(&program, &character[1..]).is_err());
+        // The program RAM and the switched in banks are kept
+        cartridge.reload_rom_data(&program, &character).unwrap();
+        assert_eq!(0x24, cartridge.program_read(0x8000));
+        assert_eq!(0x18, cartridge.character_read(0x0000));
+        assert_eq!(0x42, cartridge.program_read(0x6000));
+        assert_eq!(2, cartridge.get_bank_mapping().character_banks[0] / 8);
+        // An empty character slice keeps the current character memory
+        cartridge.reload_rom_data(&[0x00; PROGRAM_ROM_BANK_SIZE], &[]).unwrap();
+        assert_eq!(0x00, cartridge.program_read(0x8000));
+        assert_eq!(0x18, cartridge.character_read(0x0000));
+    }
+
+    #[test]
+    #[cfg(feature = "extra-mappers")]
+    fn test_bus_conflicts() {
+        // Mapper 011 only sees the bits that are set in both the written value and the ROM at the address
+        let mut rom = get_test_rom(11, 8, 4);
+        rom[0x10 + 0x0100] = 0x21;
+        // The second write conflicts with the bank that the first write switched in
+        rom[0x10 + 0x8200] = 0xff;
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        cartridge.program_write(0x8100, 0x33);
+        assert_eq!(1, cartridge.get_bank_mapping().program_banks[0] / 4);
+        assert_eq!(2, cartridge.get_bank_mapping().character_banks[0] / 8);
+        cartridge.program_write(0x8200, 0x33);
+        assert_eq!(3, cartridge.get_bank_mapping().program_banks[0] / 4);
+        assert_eq!(3, cartridge.get_bank_mapping().character_banks[0] / 8);
+    }
+
+    #[test]
+    fn test_mirroring_override() {
+        let mut rom = get_test_rom(0, 1, 1);
+        rom[6] |= 0x01; // Vertical mirroring
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        assert_eq!(Mirroring::Vertical, cartridge.get_mirroring());
+        cartridge.override_mirroring(Some(Mirroring::Horizontal));
+        assert_eq!(Mirroring::Horizontal, cartridge.get_mirroring());
+        cartridge.override_mirroring(None);
+        assert_eq!(Mirroring::Vertical, cartridge.get_mirroring());
+
+        assert_eq!(Some(Mirroring::OneScreenUpper), Mirroring::from_name("upper"));
+        assert_eq!(None, Mirroring::from_name("four"));
+    }
+
+    #[test]
+    fn test_bank_mapping() {
+        let mut cartridge = Cartridge::load_from_reader(get_test_rom(4, 4, 2).as_slice()).unwrap();
+        // Select 8 KiB program bank 1 for $8000 and 1 KiB character bank 5 for $1000
+        cartridge.program_write(0x8000, 0x06);
+        cartridge.program_write(0x8001, 0x01);
+        cartridge.program_write(0x8000, 0x02);
+        cartridge.program_write(0x8001, 0x05);
+        let mapping = cartridge.get_bank_mapping();
+        assert_eq!([1, 0, 6, 7], mapping.program_banks);
+        assert_eq!(Some(5), mapping.get_character_bank(0x1234));
+        assert_eq!(Some(7), mapping.get_program_bank(0xfffc));
+        assert_eq!(None, mapping.get_program_bank(0x6000));
+
+        // UxROM's 16 KiB banks are reported as pairs of 8 KiB banks, with the fixed bank at the end of the ROM
+        let mut cartridge = Cartridge::load_from_reader(get_test_rom(2, 4, 0).as_slice()).unwrap();
+        cartridge.program_write(0x8000, 0x02);
+        assert_eq!([4, 5, 6, 7], cartridge.get_bank_mapping().program_banks);
+    }
+
     calculate_rom_size_tests! {
         ines_minimum: 16384, calculate_rom_size(0x01, 0x0f, PROGRAM_ROM_BANK_SIZE, false).unwrap(),
         ines_middle: 65536, calculate_rom_size(0x04, 0x0f, PROGRAM_ROM_BANK_SIZE, false).unwrap(),
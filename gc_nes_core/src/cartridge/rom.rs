@@ -0,0 +1,65 @@
+//! The rom module contains the storage for a cartridge's program and character memory, which keeps sharing the
+//! bytes of the .nes file that it was loaded from until something has to be written to it.
+
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+
+/// Program or character memory, either a range of the shared buffer that the cartridge was loaded from or bytes owned
+/// by the cartridge. Cloning shared memory only clones the reference to the buffer.
+#[derive(Debug, Clone)]
+pub(super) enum RomData {
+    /// A range of a buffer that may be shared with the frontend and other cartridges
+    Shared(Arc<[u8]>, Range<usize>),
+    /// Bytes that belong to the cartridge alone
+    Owned(Vec<u8>),
+}
+
+impl Default for RomData {
+    fn default() -> Self {
+        return RomData::Owned(Vec::new());
+    }
+}
+
+impl RomData {
+    /// Gets a mutable reference to the bytes, copying them out of the shared buffer first if they are still in it
+    pub(super) fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let RomData::Shared(data, range) = self {
+            *self = RomData::Owned(data[range.clone()].to_vec());
+        }
+        return match self {
+            RomData::Owned(data) => data,
+            RomData::Shared(..) => unreachable!("Shared ROM data was just copied"),
+        };
+    }
+}
+
+impl Deref for RomData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        return match self {
+            RomData::Shared(data, range) => &data[range.clone()],
+            RomData::Owned(data) => data,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rom_data_copy_on_write() {
+        let buffer: Arc<[u8]> = Arc::from(vec![0x01, 0x02, 0x03, 0x04]);
+        let mut data = RomData::Shared(buffer.clone(), 1..3);
+        assert_eq!([0x02, 0x03], *data);
+        // Clones share the buffer until they are written to
+        let clone = data.clone();
+        assert_eq!(3, Arc::strong_count(&buffer));
+        data.to_mut()[0] = 0xff;
+        assert!(matches!(data, RomData::Owned(_)));
+        assert_eq!([0xff, 0x03], *data);
+        assert_eq!([0x02, 0x03], *clone);
+        assert_eq!(0x02, buffer[1]);
+    }
+}
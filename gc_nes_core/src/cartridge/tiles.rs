@@ -0,0 +1,90 @@
+//! The tiles module contains the conversions between the 8x8 tiles stored in character memory and their pixels,
+//! for graphics hackers who export character memory with [Cartridge::export_chr](struct.Cartridge.html#method.export_chr),
+//! edit it, and inject it again with [Cartridge::import_chr](struct.Cartridge.html#method.import_chr).
+//!
+//! Each tile is stored as two planes of eight bytes, one byte per row. The first plane holds the low bit of each
+//! pixel's colour and the second plane holds the high bit, with the leftmost pixel in the most significant bit.
+
+use super::TILE_SIZE;
+#[cfg(feature = "png-export")]
+use std::convert::TryInto;
+#[cfg(feature = "png-export")]
+use std::error::Error;
+
+/// The number of pixels in a single 8x8 tile
+pub const TILE_PIXELS: usize = 64;
+/// The shades of grey that each of a tile's four colours are exported as, from 0 to 3
+#[cfg(feature = "png-export")]
+const TILE_SHADES: [u8; 4] = [0x00, 0x55, 0xaa, 0xff];
+
+/// Decodes the sixteen bytes of a tile into the colour of each of its pixels, from 0 to 3, row by row
+pub fn decode_tile(tile: &[u8; TILE_SIZE]) -> [u8; TILE_PIXELS] {
+    let mut pixels = [0; TILE_PIXELS];
+    for (index, pixel) in pixels.iter_mut().enumerate() {
+        let (row, bit) = (index / 8, 7 - index % 8);
+        *pixel = ((tile[row] >> bit) & 0x01) | (((tile[row + 8] >> bit) & 0x01) << 1);
+    }
+    return pixels;
+}
+
+/// Encodes the colour of each of a tile's pixels, from 0 to 3 and row by row, into the sixteen bytes of the tile.
+/// Only the bottom two bits of each colour are used.
+pub fn encode_tile(pixels: &[u8; TILE_PIXELS]) -> [u8; TILE_SIZE] {
+    let mut tile = [0; TILE_SIZE];
+    for (index, pixel) in pixels.iter().enumerate() {
+        let (row, bit) = (index / 8, 7 - index % 8);
+        tile[row] |= (pixel & 0x01) << bit;
+        tile[row + 8] |= ((pixel >> 1) & 0x01) << bit;
+    }
+    return tile;
+}
+
+/// Encodes the tiles of some character memory as a greyscale PNG image, laid out left to right and top to bottom
+/// with the number of tiles per row given. 16 tiles per row lays each 4 KiB pattern table out as a square, like
+/// tile editors do. Bytes past the last whole tile are ignored.
+#[cfg(feature = "png-export")]
+pub fn encode_tiles_png(character_memory: &[u8], tiles_per_row: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    if tiles_per_row == 0 {
+        bail!("There must be at least one tile per row");
+    }
+    let tile_count = character_memory.len() / TILE_SIZE;
+    let rows = tile_count.div_ceil(tiles_per_row).max(1);
+    let width = tiles_per_row * 8;
+    let mut rgba = vec![0; width * rows * 8 * 4];
+    for (tile_index, tile) in character_memory.chunks_exact(TILE_SIZE).enumerate() {
+        let pixels = decode_tile(tile.try_into().expect("Chunks are the size of a tile"));
+        let (tile_x, tile_y) = ((tile_index % tiles_per_row) * 8, (tile_index / tiles_per_row) * 8);
+        for (index, colour) in pixels.iter().enumerate() {
+            let offset = ((tile_y + index / 8) * width + tile_x + index % 8) * 4;
+            let shade = TILE_SHADES[usize::from(*colour)];
+            rgba[offset..offset + 4].copy_from_slice(&[shade, shade, shade, 0xff]);
+        }
+    }
+    return crate::video::encode_png(&rgba, width as u32, (rows * 8) as u32);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_tile() {
+        // The top row mixes both planes, and the bottom row only uses the high plane
+        let mut tile = [0x00; TILE_SIZE];
+        tile[0] = 0b1010_0000;
+        tile[8] = 0b1100_0000;
+        tile[15] = 0b0000_0001;
+        let pixels = decode_tile(&tile);
+        assert_eq!([3, 2, 1, 0, 0, 0, 0, 0], pixels[0..8]);
+        assert_eq!(2, pixels[63]);
+        assert_eq!(tile, encode_tile(&pixels));
+    }
+
+    #[cfg(feature = "png-export")]
+    #[test]
+    fn test_encode_tiles_png() {
+        let png_data = encode_tiles_png(&[0xff; 3 * TILE_SIZE], 2).unwrap();
+        assert_eq!([0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a], png_data[0..8]);
+        assert!(encode_tiles_png(&[0xff; TILE_SIZE], 0).is_err());
+    }
+}
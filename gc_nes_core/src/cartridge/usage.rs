@@ -0,0 +1,52 @@
+//! The usage module contains the record of which parts of a cartridge's character memory the PPU has read,
+//! for tools that look for unused graphics or need to know which tiles to replace.
+
+/// The number of bytes that make up a single 8x8 tile in character memory
+pub const TILE_SIZE: usize = 16;
+
+/// Record of which tiles of a cartridge's character memory have been read since tracking was enabled.
+/// Tiles are numbered by their position in the character ROM (or RAM), regardless of which bank they
+/// were switched into when they were read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharacterUsage {
+    /// Whether each tile of character memory has been read, indexed by tile number
+    tiles: Box<[bool]>,
+}
+
+impl CharacterUsage {
+    /// Creates a record with every tile unused for character memory of the given size in bytes
+    pub(super) fn new(character_memory_size: usize) -> Self {
+        return CharacterUsage {
+            tiles: vec![false; character_memory_size.div_ceil(TILE_SIZE)].into_boxed_slice(),
+        };
+    }
+
+    /// Marks the tile containing the byte at an index into character memory as used
+    pub(super) fn record(&mut self, index: usize) {
+        if let Some(tile) = self.tiles.get_mut(index / TILE_SIZE) {
+            *tile = true;
+        }
+    }
+
+    /// Gets whether each tile of character memory has been read, indexed by tile number
+    pub fn get_tiles(&self) -> &[bool] {
+        return &self.tiles;
+    }
+
+    /// Checks if a tile has been read. Tiles past the end of character memory are never used.
+    pub fn is_tile_used(&self, tile: usize) -> bool {
+        return self.tiles.get(tile).copied().unwrap_or(false);
+    }
+
+    /// Gets the number of tiles that have been read
+    pub fn get_used_tile_count(&self) -> usize {
+        return self.tiles.iter().filter(|used| **used).count();
+    }
+
+    /// Gets whether any tile in each bank of character memory has been read, for banks of the given
+    /// size in bytes. Mappers switch banks of 1, 4 or 8 KiB.
+    pub fn get_bank_usage(&self, bank_size: usize) -> Vec<bool> {
+        let tiles_per_bank = (bank_size / TILE_SIZE).max(1);
+        return self.tiles.chunks(tiles_per_bank).map(|bank| bank.contains(&true)).collect();
+    }
+}
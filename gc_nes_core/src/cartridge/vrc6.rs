@@ -0,0 +1,290 @@
+//! The vrc6 module contains the expansion audio of Konami's VRC6, which is used by iNES mappers 024 and 026.
+//! It adds two pulse channels with eight duty cycles and a sawtooth channel to the NES' own five.
+
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
+
+/// The output for each step of volume, scaled so that one of the VRC6's pulse channels at full volume is as loud as
+/// one of the APU's pulse channels at full volume
+const OUTPUT_SCALE: f32 = 95.52 / (8128.0 / 15.0 + 100.0) / 15.0;
+
+/// The number of times the sawtooth channel's timer is clocked before its accumulator is reset
+const SAWTOOTH_STEPS: u8 = 14;
+
+/// One of the VRC6's pulse channels, controlled through $9000-$9002 and $A000-$A002
+#[derive(Debug, Default, Clone)]
+struct PulseChannel {
+    /// The volume of the channel, from 0 to 15
+    volume: u8,
+    /// The step of the duty sequence that the channel stops outputting after, from 0 to 7
+    duty: u8,
+    /// Whether the channel outputs its volume constantly, regardless of the duty
+    ignore_duty: bool,
+    /// The 12 bit period of the channel's timer
+    period: u16,
+    /// Whether the channel is enabled. A disabled channel is silent, and its duty sequence is held at the start.
+    enabled: bool,
+    /// The current value of the channel's timer, counting down to the next step of the duty sequence
+    timer: u16,
+    /// The step of the duty sequence, counting down from 15 to 0
+    step: u8,
+}
+
+impl PulseChannel {
+    /// Writes to one of the channel's three registers
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.ignore_duty = data & 0x80 > 0;
+                self.duty = (data >> 4) & 0x07;
+                self.volume = data & 0x0f;
+            }
+            1 => self.period = (self.period & 0x0f00) | u16::from(data),
+            2 => {
+                self.period = (self.period & 0x00ff) | (u16::from(data & 0x0f) << 8);
+                self.enabled = data & 0x80 > 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocks the channel's timer, with the period shifted right by the shift of the frequency control register
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0f;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Gets the current output of the channel, from 0 to 15
+    fn output(&self) -> u8 {
+        return if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        };
+    }
+    /// Writes the state of the channel to a save state
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.volume);
+        state.write_u8(self.duty);
+        state.write_bool(self.ignore_duty);
+        state.write_u16(self.period);
+        state.write_bool(self.enabled);
+        state.write_u16(self.timer);
+        state.write_u8(self.step);
+    }
+
+    /// Restores the state of the channel from a save state
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.volume = state.read_u8()? & 0x0f;
+        self.duty = state.read_u8()? & 0x07;
+        self.ignore_duty = state.read_bool()?;
+        self.period = state.read_u16()? & 0x0fff;
+        self.enabled = state.read_bool()?;
+        self.timer = state.read_u16()?;
+        self.step = state.read_u8()? & 0x0f;
+        return Ok(());
+    }
+}
+
+/// The VRC6's sawtooth channel, controlled through $B000-$B002
+#[derive(Debug, Default, Clone)]
+struct SawtoothChannel {
+    /// The amount added to the accumulator on every other step, from 0 to 63
+    rate: u8,
+    /// The 12 bit period of the channel's timer
+    period: u16,
+    /// Whether the channel is enabled. A disabled channel is silent, and its accumulator is held at zero.
+    enabled: bool,
+    /// The current value of the channel's timer, counting down to the next step
+    timer: u16,
+    /// The number of steps since the accumulator was last reset
+    step: u8,
+    /// The accumulator, whose top five bits are the output of the channel
+    accumulator: u8,
+}
+
+impl SawtoothChannel {
+    /// Writes to one of the channel's three registers
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => self.rate = data & 0x3f,
+            1 => self.period = (self.period & 0x0f00) | u16::from(data),
+            2 => {
+                self.period = (self.period & 0x00ff) | (u16::from(data & 0x0f) << 8);
+                self.enabled = data & 0x80 > 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocks the channel's timer, with the period shifted right by the shift of the frequency control register
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == SAWTOOTH_STEPS {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            // Rates above 42 overflow the accumulator, which distorts the waveform just like it does on the real chip
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    /// Gets the current output of the channel, from 0 to 31
+    fn output(&self) -> u8 {
+        return self.accumulator >> 3;
+    }
+    /// Writes the state of the channel to a save state
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rate);
+        state.write_u16(self.period);
+        state.write_bool(self.enabled);
+        state.write_u16(self.timer);
+        state.write_u8(self.step);
+        state.write_u8(self.accumulator);
+    }
+
+    /// Restores the state of the channel from a save state
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.rate = state.read_u8()? & 0x3f;
+        self.period = state.read_u16()? & 0x0fff;
+        self.enabled = state.read_bool()?;
+        self.timer = state.read_u16()?;
+        self.step = state.read_u8()? % SAWTOOTH_STEPS;
+        self.accumulator = state.read_u8()?;
+        return Ok(());
+    }
+}
+
+/// The expansion audio of the VRC6
+#[derive(Debug, Default, Clone)]
+pub(super) struct Vrc6Audio {
+    /// The two pulse channels, controlled through $9000-$9002 and $A000-$A002
+    pulses: [PulseChannel; 2],
+    /// The sawtooth channel, controlled through $B000-$B002
+    sawtooth: SawtoothChannel,
+    /// Whether every channel's timer is halted through the frequency control register at $9003
+    halted: bool,
+    /// The number of bits that every channel's period is shifted right by, from the frequency control register
+    shift: u8,
+}
+
+impl Vrc6Audio {
+    /// Writes to one of the audio registers at $9000-$9003, $A000-$A002 or $B000-$B002, with the register
+    /// select lines already put in the order of mapper 024
+    pub(super) fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x9000..=0x9002 => self.pulses[0].write(address & 0x03, data),
+            0x9003 => {
+                self.halted = data & 0x01 > 0;
+                self.shift = match data & 0x06 {
+                    0x00 => 0,
+                    0x02 => 4,
+                    _ => 8,
+                };
+            }
+            0xa000..=0xa002 => self.pulses[1].write(address & 0x03, data),
+            0xb000..=0xb002 => self.sawtooth.write(address & 0x03, data),
+            _ => {}
+        }
+    }
+
+    /// Clocks the channels, which happens every CPU cycle
+    pub(super) fn clock(&mut self) {
+        if self.halted {
+            return;
+        }
+        self.pulses[0].clock(self.shift);
+        self.pulses[1].clock(self.shift);
+        self.sawtooth.clock(self.shift);
+    }
+
+    /// Gets the mixed output of the channels, scaled to match the APU
+    pub(super) fn output(&self) -> f32 {
+        let sum = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        return f32::from(sum) * OUTPUT_SCALE;
+    }
+    /// Writes the state of the channels and the frequency control register to a save state
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        self.pulses.iter().for_each(|pulse| pulse.save_state(state));
+        self.sawtooth.save_state(state);
+        state.write_bool(self.halted);
+        state.write_u8(self.shift);
+    }
+
+    /// Restores the state of the channels and the frequency control register from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        for pulse in self.pulses.iter_mut() {
+            pulse.load_state(state)?;
+        }
+        self.sawtooth.load_state(state)?;
+        self.halted = state.read_bool()?;
+        // The periods are shifted right by the shift, which is only ever 0, 4 or 8
+        self.shift = state.read_u8()?.min(8);
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pulse_duty() {
+        let mut audio = Vrc6Audio::default();
+        // Volume 15 with a duty of 4/16, and the shortest period so that every clock steps the sequence
+        audio.write(0x9000, 0x3f);
+        audio.write(0x9001, 0x00);
+        audio.write(0x9002, 0x80);
+        let outputs: Vec<f32> = (0..16)
+            .map(|_| {
+                audio.clock();
+                audio.output()
+            })
+            .collect();
+        assert_eq!(4, outputs.iter().filter(|output| **output > 0.0).count());
+        assert!((outputs.iter().cloned().fold(0.0, f32::max) - 15.0 * OUTPUT_SCALE).abs() < f32::EPSILON);
+        // Halting the channels holds them where they are
+        audio.write(0x9003, 0x01);
+        let output = audio.output();
+        (0..16).for_each(|_| audio.clock());
+        assert_eq!(output, audio.output());
+    }
+
+    #[test]
+    fn test_sawtooth() {
+        let mut audio = Vrc6Audio::default();
+        audio.write(0xb000, 42);
+        audio.write(0xb001, 0x00);
+        audio.write(0xb002, 0x80);
+        let outputs: Vec<u8> = (0..SAWTOOTH_STEPS)
+            .map(|_| {
+                audio.clock();
+                audio.sawtooth.output()
+            })
+            .collect();
+        // The accumulator rises on every other step, then resets on the fourteenth
+        assert_eq!(vec![0, 5, 5, 10, 10, 15, 15, 21, 21, 26, 26, 31, 31, 0], outputs);
+    }
+}
@@ -0,0 +1,361 @@
+//! The emulator module contains the [Emulator](struct.Emulator.html), an opt-in wrapper around a [Nes](../nes/struct.Nes.html)
+//! that wires its subsystems together the way most frontends need them: the options are applied from a single config,
+//! battery backed saves are passed to a callback as they change, movies are recorded and played back with the movies
+//! feature, and each frame is run with a single call that returns everything the frontend needs to present it.
+//! ```
+//! use gc_nes_core::emulator::{Emulator, EmulatorConfig};
+//! use gc_nes_core::movie::FrameInput;
+//!
+//! # fn run(rom: &[u8], saved: Option<Vec<u8>>) -> Result<(), Box<dyn std::error::Error>> {
+//! let mut emulator = Emulator::load_rom(rom, EmulatorConfig {
+//!     audio_sample_rate: Some(48_000.0),
+//!     ..Default::default()
+//! })?;
+//! if let Some(saved) = saved {
+//!     emulator.load_save_data(&saved)?;
+//! }
+//! emulator.set_save_callback(|save_data| std::fs::write("game.sav", save_data).unwrap());
+//! loop {
+//!     let output = emulator.advance(FrameInput::default());
+//!     // Draw output.frame and queue output.audio
+//! }
+//! # }
+//! ```
+//! Only NTSC consoles are emulated and the palette is fixed, so neither is part of the config yet, and cheats aren't supported.
+
+use crate::audio::stereo::StereoPanning;
+#[cfg(feature = "movies")]
+use crate::cartridge::calculate_rom_md5;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::input::InputRemap;
+use crate::movie::FrameInput;
+#[cfg(feature = "movies")]
+use crate::movie::Movie;
+use crate::nes::{AccuracyOptions, AccuracyPreset, Nes, PpuConfig, NES_SCREEN_DIMENSIONS};
+use std::error::Error;
+
+/// How many frames pass between checks for changes to the battery backed memory that need to be saved
+const SAVE_INTERVAL_FRAMES: u64 = 60;
+
+/// Callback that is passed the cartridge's battery backed memory whenever it should be saved
+pub type SaveCallback = Box<dyn FnMut(&[u8])>;
+
+/// The options that an [Emulator](struct.Emulator.html) applies to its NES, gathered in one place
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EmulatorConfig {
+    /// The accuracy options that the NES runs with
+    pub accuracy: AccuracyOptions,
+    /// The options that change how the PPU draws the screen
+    pub ppu: PpuConfig,
+    /// The rate in Hz that audio samples are returned at, or None for one sample per CPU cycle
+    pub audio_sample_rate: Option<f64>,
+    /// Whether the audio passes through the NES' analog filters, which centre it on zero
+    pub analog_filters: bool,
+    /// The pan positions that the APU's channels are mixed into separate left and right outputs with, or None to mix
+    /// them in mono. While this is set, the audio of each frame alternates between the left and right outputs.
+    pub stereo_panning: Option<StereoPanning>,
+    /// Whether each frame is blended with the last, which smooths out the flicker that some games use for transparency
+    pub frame_blending: bool,
+    /// The mirroring mode that the nametables are forced to use, for ROMs with the wrong mirroring in their header
+    pub mirroring_override: Option<Mirroring>,
+    /// The remaps that the input of the first and second controllers is passed through before the NES sees it
+    pub input_remaps: [InputRemap; 2],
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        return EmulatorConfig {
+            accuracy: AccuracyPreset::Balanced.into(),
+            ppu: Default::default(),
+            audio_sample_rate: None,
+            analog_filters: false,
+            stereo_panning: None,
+            frame_blending: false,
+            mirroring_override: None,
+            input_remaps: Default::default(),
+        };
+    }
+}
+
+/// Everything that a frontend needs to present a single frame
+#[derive(Debug)]
+pub struct FrameOutput<'a> {
+    /// The completed frame, with each pixel in ARGB format
+    pub frame: &'a [u32; NES_SCREEN_DIMENSIONS],
+    /// The audio produced while the frame was run, at the config's sample rate
+    pub audio: Vec<f32>,
+    /// The input that the frame was run with after it was remapped, which comes from the movie while one is playing
+    pub input: FrameInput,
+    /// The number of frames that have been completed since power on
+    pub frame_count: u64,
+}
+
+/// What the emulator is doing with a movie
+#[cfg(feature = "movies")]
+#[derive(Debug, Clone)]
+enum MovieState {
+    /// Neither recording nor playing a movie
+    Idle,
+    /// Recording the input of each frame since power on
+    Recording(Movie),
+    /// Playing back the input of a movie from power on
+    Playing(Movie),
+}
+
+/// A NES along with the config, save persistence and movie handling that frontends usually build around it.
+/// The NES itself is still available through [get_nes](#method.get_nes) for anything that isn't covered.
+pub struct Emulator {
+    /// The NES being run
+    nes: Nes,
+    /// The cartridge as it was loaded, which the NES is recreated from to power it on for movies
+    #[cfg(feature = "movies")]
+    cartridge: Cartridge,
+    /// The MD5 hash of the ROM, which recorded movies are tagged with, if it is known
+    #[cfg(feature = "movies")]
+    rom_md5: Option<[u8; 16]>,
+    /// The options applied to the NES
+    config: EmulatorConfig,
+    /// Called with the battery backed memory whenever it has changed and should be saved
+    save_callback: Option<SaveCallback>,
+    /// The movie being recorded or played back, if any
+    #[cfg(feature = "movies")]
+    movie: MovieState,
+}
+
+impl Emulator {
+    /// Creates an emulator running a cartridge with the options from the config
+    pub fn new(cartridge: Cartridge, config: EmulatorConfig) -> Self {
+        let mut emulator = Emulator {
+            #[cfg(feature = "movies")]
+            nes: Nes::new(cartridge.clone()),
+            #[cfg(not(feature = "movies"))]
+            nes: Nes::new(cartridge),
+            #[cfg(feature = "movies")]
+            cartridge,
+            #[cfg(feature = "movies")]
+            rom_md5: None,
+            config,
+            save_callback: None,
+            #[cfg(feature = "movies")]
+            movie: MovieState::Idle,
+        };
+        emulator.set_config(config);
+        return emulator;
+    }
+
+    /// Creates an emulator running the ROM from the bytes of a .nes file, which also lets movies recorded
+    /// with it be tagged with the ROM's MD5 hash
+    pub fn load_rom(rom: &[u8], config: EmulatorConfig) -> Result<Self, Box<dyn Error>> {
+        let emulator = Emulator::new(Cartridge::load_from_reader(rom)?, config);
+        #[cfg(feature = "movies")]
+        let emulator = Emulator {
+            rom_md5: Some(calculate_rom_md5(rom)?),
+            ..emulator
+        };
+        return Ok(emulator);
+    }
+
+    /// Runs the NES to the end of the next frame with the input, or the input from the movie while one is
+    /// playing, and returns the frame along with the audio it produced. Playback stops at the end of the movie.
+    pub fn advance(&mut self, input: FrameInput) -> FrameOutput<'_> {
+        // Movies record the remapped input, so that they play back the same without the remaps
+        let input = self.nes.remap_frame_input(input);
+        #[cfg(feature = "movies")]
+        let input = self.apply_movie(input);
+        self.nes.update_frame_input(input);
+        self.nes.frame();
+        if self.nes.get_frame_count().is_multiple_of(SAVE_INTERVAL_FRAMES) {
+            self.flush_save();
+        }
+        let audio = self.nes.take_audio_samples();
+        let frame_count = self.nes.get_frame_count();
+        return FrameOutput {
+            frame: self.nes.get_screen(),
+            audio,
+            input,
+            frame_count,
+        };
+    }
+
+    /// Applies the options from a config to the NES
+    pub fn set_config(&mut self, config: EmulatorConfig) {
+        self.nes.set_accuracy(config.accuracy);
+        self.nes.set_ppu_config(config.ppu);
+        // Changing the sample rate discards the samples that haven't been taken, so it is only set when it changes
+        if let Some(sample_rate) = config
+            .audio_sample_rate
+            .filter(|&sample_rate| sample_rate != self.nes.get_audio_sample_rate())
+        {
+            self.nes.set_audio_sample_rate(sample_rate);
+        }
+        self.nes.set_analog_filters(config.analog_filters);
+        self.nes.set_stereo_panning(config.stereo_panning);
+        self.nes.get_video_backend_mut().set_frame_blending(config.frame_blending);
+        self.nes.override_mirroring(config.mirroring_override);
+        self.nes.set_controller_one_remap(config.input_remaps[0]);
+        self.nes.set_controller_two_remap(config.input_remaps[1]);
+        self.config = config;
+    }
+
+    /// Gets the options applied to the NES
+    pub fn get_config(&self) -> EmulatorConfig {
+        return self.config;
+    }
+
+    /// Sets the callback that is passed the cartridge's battery backed memory whenever it has changed and should be
+    /// saved, which is checked once a second and whenever [flush_save](#method.flush_save) is called.
+    /// The callback is never called for cartridges without battery backed memory.
+    pub fn set_save_callback<F: FnMut(&[u8]) + 'static>(&mut self, callback: F) {
+        self.save_callback = Some(Box::new(callback));
+    }
+
+    /// Restores the cartridge's battery backed memory from data previously passed to the save callback.
+    /// Should be called before the first frame is run.
+    pub fn load_save_data(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.nes.load_save_data(data)?;
+        self.nes.mark_save_flushed();
+        return Ok(());
+    }
+
+    /// Passes the battery backed memory to the save callback if it has changed since it was last saved,
+    /// for frontends to call before they exit
+    pub fn flush_save(&mut self) {
+        if let Some(callback) = &mut self.save_callback {
+            if self.nes.is_save_dirty() {
+                if let Some(save_data) = self.nes.get_save_data() {
+                    callback(&save_data);
+                }
+                self.nes.mark_save_flushed();
+            }
+        }
+    }
+
+    /// Records the input for the next frame to the movie being recorded, or replaces it with the input from the movie
+    /// being played back, stopping playback once the movie has ended
+    #[cfg(feature = "movies")]
+    fn apply_movie(&mut self, input: FrameInput) -> FrameInput {
+        let frame = self.nes.get_frame_count() as usize;
+        return match &mut self.movie {
+            MovieState::Idle => input,
+            MovieState::Recording(movie) => {
+                movie.set_input(frame, input);
+                input
+            }
+            MovieState::Playing(movie) if frame < movie.len() => movie.get_input(frame),
+            MovieState::Playing(_) => {
+                self.movie = MovieState::Idle;
+                input
+            }
+        };
+    }
+
+    /// Powers the NES on again and starts recording the input of each frame to a movie, stopping
+    /// any movie that was being recorded or played. Unsaved changes to the save data are saved first.
+    #[cfg(feature = "movies")]
+    pub fn record_movie(&mut self) {
+        self.power_on();
+        let mut movie = Movie::new();
+        movie.set_rom_md5(self.rom_md5);
+        self.movie = MovieState::Recording(movie);
+    }
+
+    /// Powers the NES on again and plays a movie back from the first frame, stopping any movie that was
+    /// being recorded or played. Unsaved changes to the save data are saved first.
+    #[cfg(feature = "movies")]
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.power_on();
+        self.movie = MovieState::Playing(movie);
+    }
+
+    /// Stops recording or playing a movie, returning it
+    #[cfg(feature = "movies")]
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        return match std::mem::replace(&mut self.movie, MovieState::Idle) {
+            MovieState::Idle => None,
+            MovieState::Recording(movie) | MovieState::Playing(movie) => Some(movie),
+        };
+    }
+
+    /// Checks if a movie is being played back
+    #[cfg(feature = "movies")]
+    pub fn is_playing_movie(&self) -> bool {
+        return matches!(self.movie, MovieState::Playing(_));
+    }
+
+    /// Checks if a movie is being recorded
+    #[cfg(feature = "movies")]
+    pub fn is_recording_movie(&self) -> bool {
+        return matches!(self.movie, MovieState::Recording(_));
+    }
+
+    /// Gets the NES being run
+    pub fn get_nes(&self) -> &Nes {
+        return &self.nes;
+    }
+
+    /// Gets the NES being run, for changing anything that the emulator doesn't cover
+    pub fn get_nes_mut(&mut self) -> &mut Nes {
+        return &mut self.nes;
+    }
+
+    /// Recreates the NES from the cartridge as it was loaded, so that movies start from the same state every time
+    #[cfg(feature = "movies")]
+    fn power_on(&mut self) {
+        self.flush_save();
+        self.nes = Nes::new(self.cartridge.clone());
+        self.set_config(self.config);
+    }
+}
+
+#[cfg(all(test, feature = "movies"))]
+mod test {
+    use super::*;
+    use crate::cartridge::test_utils::*;
+
+    #[test]
+    fn test_emulator_movies() {
+        let cartridge = get_mock_cartridge(MapperMock {
+            program_read_stub: |_, _| 0x00,
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        });
+        let mut emulator = Emulator::new(
+            cartridge,
+            EmulatorConfig {
+                audio_sample_rate: Some(44_100.0),
+                ..Default::default()
+            },
+        );
+        let output = emulator.advance(FrameInput::default());
+        assert_eq!(1, output.frame_count);
+        assert!(!output.audio.is_empty());
+
+        let inputs: Vec<FrameInput> = (0..3)
+            .map(|frame| FrameInput {
+                controller_one: Some(frame),
+                controller_two: None,
+            })
+            .collect();
+        emulator.record_movie();
+        assert_eq!(44_100.0, emulator.get_nes().get_audio_sample_rate());
+        for input in inputs.iter() {
+            emulator.advance(*input);
+        }
+        let movie = emulator.stop_movie().unwrap();
+        assert_eq!(&inputs[..], movie.frames());
+
+        emulator.play_movie(movie);
+        assert_eq!(inputs[0], emulator.advance(FrameInput::default()).input);
+        assert_eq!(inputs[1], emulator.advance(FrameInput::default()).input);
+        assert_eq!(inputs[2], emulator.advance(FrameInput::default()).input);
+        assert!(emulator.is_playing_movie());
+        // Playback stops at the end of the movie, handing control back to the frontend's input
+        let input = FrameInput {
+            controller_one: Some(0xff),
+            controller_two: None,
+        };
+        assert_eq!(input, emulator.advance(input).input);
+        assert!(!emulator.is_playing_movie());
+    }
+}
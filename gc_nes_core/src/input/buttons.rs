@@ -0,0 +1,60 @@
+//! The buttons module contains the bits that represent each button of a standard
+//! NES controller in the input state bytes passed to update_controller_one and
+//! update_controller_two, along with a builder for assembling those bytes.
+
+/// The A button
+pub const A: u8 = 0x01;
+/// The B button
+pub const B: u8 = 0x02;
+/// The Select button
+pub const SELECT: u8 = 0x04;
+/// The Start button
+pub const START: u8 = 0x08;
+/// Up on the D-pad
+pub const UP: u8 = 0x10;
+/// Down on the D-pad
+pub const DOWN: u8 = 0x20;
+/// Left on the D-pad
+pub const LEFT: u8 = 0x40;
+/// Right on the D-pad
+pub const RIGHT: u8 = 0x80;
+
+/// Builder for the input state byte of a standard NES controller.
+///
+/// ```
+/// use gc_nes_core::input::buttons::{self, ButtonStateBuilder};
+///
+/// let state = ButtonStateBuilder::new().press(buttons::A).set(buttons::LEFT, true).set(buttons::START, false).build();
+/// assert_eq!(buttons::A | buttons::LEFT, state);
+/// ```
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ButtonStateBuilder {
+    state: u8,
+}
+
+impl ButtonStateBuilder {
+    /// Creates a new builder with no buttons pressed
+    pub fn new() -> Self {
+        return Default::default();
+    }
+
+    /// Presses the button or buttons represented by the bits
+    pub fn press(self, buttons: u8) -> Self {
+        return self.set(buttons, true);
+    }
+
+    /// Sets whether the button or buttons represented by the bits are pressed
+    pub fn set(mut self, buttons: u8, pressed: bool) -> Self {
+        if pressed {
+            self.state |= buttons;
+        } else {
+            self.state &= !buttons;
+        }
+        return self;
+    }
+
+    /// Gets the input state byte with the buttons that have been pressed
+    pub fn build(self) -> u8 {
+        return self.state;
+    }
+}
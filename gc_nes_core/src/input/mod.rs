@@ -3,8 +3,38 @@
 //! that are a remnant of an old input system but I haven't gotten
 //! around to reworking it.
 
+pub mod buttons;
+mod remap;
+
+pub use remap::InputRemap;
+
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
+
+/// Controls when a new input state passed to the NES by the frontend becomes
+/// visible to the running game.
+///
+/// On hardware a standard controller samples its buttons when the game strobes
+/// the latch at $4016, so the state a game sees is the state at that moment.
+/// Frontends however tend to update the input state at arbitrary points relative
+/// to the emulated frame, which can make input latency vary between frontends.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum InputLatency {
+    /// Input state updates take effect immediately. If the game is in the middle of
+    /// reading the controller, it will see the new state partway through the read.
+    #[default]
+    Immediate,
+    /// Input state updates are held by the controller until the game next strobes
+    /// the controller latch, matching when real hardware samples the buttons.
+    NextLatch,
+}
+
+/// The bits of a controller port read that are driven by the controller port. The NES leaves the other three
+/// floating, so they keep the last value on the bus, which is usually 0x40 from the high byte of $4016 or $4017.
+const CONTROLLER_PORT_MASK: u8 = 0x1f;
+
 /// Enum for representing a NES input port
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum NesInput {
     /// State representing no connected controller
     Disconnected,
@@ -23,17 +53,65 @@ impl NesInput {
     /// Polls a single bit from the controller.
     /// On a standard NES controller, this will return the next bit in the controller's shift register.
     ///
-    /// The bus parameter is used for simulating open bus behaviour. It should be ORed with the three
-    /// bits that were polled.
+    /// The bus parameter is the last value on the bus, which fills the top three bits that the port doesn't drive.
+    /// A port with no controller connected drives its bits low, so it reads as just those top three bits.
     pub(crate) fn poll(&mut self, bus: u8) -> u8 {
-        match self {
-            NesInput::Disconnected => bus & 0xf4,
+        return match self {
+            NesInput::Disconnected => bus & !CONTROLLER_PORT_MASK,
             NesInput::Connected(controller) => controller.poll(bus),
+        };
+    }
+
+    /// Updates the state of the controller connected to the port, either immediately or once it is next latched,
+    /// connecting a controller if there isn't one, or disconnecting it if the state is None
+    pub(crate) fn update(&mut self, input_state: Option<u8>, latency: InputLatency) {
+        match (&mut *self, input_state) {
+            (NesInput::Disconnected, None) => {}
+            (NesInput::Connected(_), None) => *self = NesInput::Disconnected,
+            (NesInput::Disconnected, Some(state)) => *self = NesInput::Connected(NesInputDevice::new(state)),
+            (NesInput::Connected(device), Some(state)) => device.update_state(state, latency),
         }
     }
+
+    /// Checks if a controller is connected to the port
+    pub(crate) fn is_connected(&self) -> bool {
+        return matches!(self, NesInput::Connected(_));
+    }
+
+    /// Writes whether a controller is connected to the port, and the state of its shift register, to a save state
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.is_connected());
+        // A disconnected port is written as an idle controller, so that the state is the same size either way
+        let idle = NesInputDevice::new(0x00);
+        let device = match self {
+            NesInput::Disconnected => &idle,
+            NesInput::Connected(device) => device,
+        };
+        state.write_u8(device.shift_register);
+        state.write_bool(device.reload_latch);
+        state.write_u8(device.input_state);
+        state.write_option_u8(device.pending_input_state);
+    }
+
+    /// Restores the controller connected to the port, and the state of its shift register, from a save state
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        let connected = state.read_bool()?;
+        let device = NesInputDevice {
+            shift_register: state.read_u8()?,
+            reload_latch: state.read_bool()?,
+            input_state: state.read_u8()?,
+            pending_input_state: state.read_option_u8()?,
+        };
+        *self = if connected {
+            NesInput::Connected(device)
+        } else {
+            NesInput::Disconnected
+        };
+        return Ok(());
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct NesInputDevice {
     /// Shift register that stores the button information
     shift_register: u8,
@@ -41,6 +119,8 @@ pub(crate) struct NesInputDevice {
     reload_latch: bool,
     /// Stores the actual state of the controller
     input_state: u8,
+    /// Stores an input state that will replace the actual state the next time the controller is latched
+    pending_input_state: Option<u8>,
 }
 
 impl NesInputDevice {
@@ -50,36 +130,45 @@ impl NesInputDevice {
             shift_register: 0x00,
             reload_latch: false,
             input_state,
+            pending_input_state: None,
         }
     }
 
-    /// Updates the internal state of the device
-    pub(crate) fn update_state(&mut self, input_state: u8) {
-        self.input_state = input_state;
+    /// Updates the internal state of the device, either immediately or once the device is next latched
+    pub(crate) fn update_state(&mut self, input_state: u8, latency: InputLatency) {
+        match latency {
+            InputLatency::Immediate => self.input_state = input_state,
+            InputLatency::NextLatch => self.pending_input_state = Some(input_state),
+        }
     }
 
     /// The lower three bits of the data byte will be held and control input device behaviour.
     /// On a standard NES controller, this will load the shift registers so that they can be polled
     fn latch(&mut self, latch: u8) {
         self.reload_latch = latch & 0x01 == 0x01;
+        if self.reload_latch {
+            // The buttons are sampled when the latch is set, so this is when a pending state is applied
+            if let Some(input_state) = self.pending_input_state.take() {
+                self.input_state = input_state;
+            }
+        }
         self.reload_shift_register()
     }
 
     /// Polls a single bit from the controller.
     /// On a standard NES controller, this will return the next bit in the controller's shift register.
     ///
-    /// The bus parameter is used for simulating open bus behaviour. It should be ORed with the three
-    /// bits that were polled.
+    /// The bus parameter is the last value on the bus, which fills the top three bits that the port doesn't drive
     fn poll(&mut self, bus: u8) -> u8 {
         self.reload_shift_register();
-        // Select only the last bit of the
+        // Select only the last bit of the shift register
         let result = self.shift_register & 0x01;
         // Get the next bit in the shift register
         self.shift_register >>= 1;
         // Set the new bit to 1, which is returned after 8 polls on official NES controllers
         self.shift_register |= 0x80;
-        // Return the result bit with the top 5 bits as the previous byte on the bus
-        return result | (bus & 0xf8);
+        // Return the result bit with the top 3 bits as the previous byte on the bus
+        return result | (bus & !CONTROLLER_PORT_MASK);
     }
 
     /// Reloads the shift register to the input state
@@ -89,3 +178,47 @@ impl NesInputDevice {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_update_state_immediate() {
+        let mut device = NesInputDevice::new(0x00);
+        device.update_state(0x01, InputLatency::Immediate);
+        device.latch(0x00);
+        assert_eq!(0x00, device.poll(0x00));
+        device.latch(0x01);
+        assert_eq!(0x01, device.poll(0x00));
+    }
+
+    #[test]
+    fn test_update_state_next_latch() {
+        let mut device = NesInputDevice::new(0x00);
+        device.latch(0x01);
+        device.update_state(0x01, InputLatency::NextLatch);
+        // The latch is still set, but the new state hasn't been sampled yet
+        assert_eq!(0x00, device.poll(0x00));
+        device.latch(0x01);
+        device.latch(0x00);
+        assert_eq!(0x01, device.poll(0x00));
+        assert_eq!(None, device.pending_input_state);
+    }
+
+    #[test]
+    fn test_poll_open_bus() {
+        let mut port = NesInput::Disconnected;
+        assert!(!port.is_connected());
+        assert_eq!(0x40, port.poll(0x40));
+        assert_eq!(0xe0, port.poll(0xff));
+        port = NesInput::Connected(NesInputDevice::new(0x00));
+        assert!(port.is_connected());
+        port.latch(0x01);
+        port.latch(0x00);
+        assert_eq!(0x40, port.poll(0x5f));
+        // An idle controller reads ones once all eight buttons have been read
+        (0..7).for_each(|_| assert_eq!(0x40, port.poll(0x40)));
+        assert_eq!(0x41, port.poll(0x40));
+    }
+}
@@ -0,0 +1,128 @@
+//! The remap module changes which buttons the buttons of a controller press before the NES sees them, for players
+//! who can't comfortably use the standard layout. Remapping in the core rather than in each frontend means that a
+//! remap behaves the same everywhere, and that movies record the buttons the game actually saw.
+
+use super::buttons::{A, B, LEFT, RIGHT};
+
+/// The number of frames that pulsing buttons are pressed for, and then released for, while they are held
+const PULSE_FRAMES: u64 = 2;
+
+/// A table of the buttons that each button of a standard controller presses, applied to the input state
+/// passed to the NES before the game latches it.
+///
+/// ```
+/// use gc_nes_core::input::buttons;
+/// use gc_nes_core::input::InputRemap;
+///
+/// // Swap A and B, and let Select press A repeatedly while it is held, for playing with one hand
+/// let remap = InputRemap::new().swap_a_b().one_handed(buttons::SELECT);
+/// assert_eq!(buttons::B, remap.apply(buttons::A, 0));
+/// assert_eq!(buttons::A, remap.apply(buttons::SELECT, 0));
+/// assert_eq!(0, remap.apply(buttons::SELECT, 2));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InputRemap {
+    /// The buttons that each button presses, indexed by the position of the button's bit
+    buttons: [u8; 8],
+    /// The buttons that press the buttons they are mapped to on and off while held, rather than holding them
+    pulsing: u8,
+}
+
+impl InputRemap {
+    /// Creates a remap that leaves every button as it is
+    pub fn new() -> Self {
+        return Default::default();
+    }
+
+    /// Maps the button or buttons represented by the bits to press the buttons represented by another set of bits,
+    /// which can be none to disable them
+    pub fn map(mut self, button: u8, buttons: u8) -> Self {
+        for bit in (0..8).filter(|bit| button & (1 << bit) != 0) {
+            self.buttons[bit] = buttons;
+            self.pulsing &= !(1 << bit);
+        }
+        return self;
+    }
+
+    /// Swaps the buttons that two buttons press, where each is represented by a single bit
+    pub fn swap(mut self, first: u8, second: u8) -> Self {
+        let (first_bit, second_bit) = (first.trailing_zeros() as usize, second.trailing_zeros() as usize);
+        if first_bit < 8 && second_bit < 8 {
+            self.buttons.swap(first_bit, second_bit);
+            let first_pulsing = self.pulsing & first != 0;
+            let second_pulsing = self.pulsing & second != 0;
+            self.pulsing &= !(first | second);
+            if first_pulsing {
+                self.pulsing |= second;
+            }
+            if second_pulsing {
+                self.pulsing |= first;
+            }
+        }
+        return self;
+    }
+
+    /// Swaps the A and B buttons
+    pub fn swap_a_b(self) -> Self {
+        return self.swap(A, B);
+    }
+
+    /// Swaps left and right on the D-pad, for players who hold the controller the other way around
+    pub fn mirror_dpad(self) -> Self {
+        return self.swap(LEFT, RIGHT);
+    }
+
+    /// Maps the button or buttons represented by the bits to press and release the buttons represented by another
+    /// set of bits every few frames while they are held, like a turbo button
+    pub fn pulse(mut self, button: u8, buttons: u8) -> Self {
+        self = self.map(button, buttons);
+        self.pulsing |= button;
+        return self;
+    }
+
+    /// Maps a button to pulse A while it is held, so that games which need A to be pressed repeatedly can be
+    /// played by holding a single button
+    pub fn one_handed(self, button: u8) -> Self {
+        return self.pulse(button, A);
+    }
+
+    /// Gets the buttons that an input state presses through the remap, on the numbered frame.
+    /// Pulsing buttons press the buttons mapped to them on alternating pairs of frames.
+    pub fn apply(&self, input_state: u8, frame: u64) -> u8 {
+        let pulse_pressed = (frame / PULSE_FRAMES).is_multiple_of(2);
+        return (0..8)
+            .filter(|bit| input_state & (1 << bit) != 0)
+            .filter(|bit| pulse_pressed || self.pulsing & (1 << bit) == 0)
+            .fold(0, |state, bit| state | self.buttons[bit]);
+    }
+}
+
+impl Default for InputRemap {
+    fn default() -> Self {
+        return InputRemap {
+            buttons: [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80],
+            pulsing: 0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::buttons::*;
+    use super::*;
+
+    #[test]
+    fn test_input_remap() {
+        let remap = InputRemap::new().swap_a_b().mirror_dpad().map(SELECT, 0).pulse(START, START | A);
+        assert_eq!(B | RIGHT | UP, remap.apply(A | LEFT | UP, 0));
+        assert_eq!(A | LEFT, remap.apply(B | RIGHT, 0));
+        assert_eq!(0, remap.apply(SELECT, 0));
+        // Pulsing buttons are pressed for two frames and released for two
+        let pulses: Vec<u8> = (0..6).map(|frame| remap.apply(START | DOWN, frame)).collect();
+        let pressed = START | A | DOWN;
+        assert_eq!(vec![pressed, pressed, DOWN, DOWN, pressed, pressed], pulses);
+        // Swapping a button that is already remapped swaps what it presses
+        assert_eq!(B, remap.swap(A, SELECT).apply(SELECT, 0));
+        assert_eq!(A | B, InputRemap::new().apply(A | B, 0));
+    }
+}
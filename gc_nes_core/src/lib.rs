@@ -1,6 +1,6 @@
 //! gc_nes_core is, as the name would suggest, the core of my Ninendo Entertainment System emulator.
 //! It provides an interface for dependent crates to load and run NES ROMs, provide input, and extract
-//! rendered image data. Audio is currently unsupported.
+//! rendered image data and audio samples.
 //!
 //! ### Using the Emulator
 //!
@@ -12,7 +12,10 @@
 //! Dependent crates can use the emulator functionality as follows:
 //! ```ignore
 //! use gc_nes_core::cartridge::Cartridge;
+//! use gc_nes_core::input::buttons::{self, ButtonStateBuilder};
+//! use gc_nes_core::input::InputLatency;
 //! use gc_nes_core::nes::Nes;
+//! use gc_nes_core::video::{ArgbFrameBuffer, IndexedFrameBuffer};
 //!
 //!
 //! // Load a .nes file as a cartridge
@@ -24,10 +27,34 @@
 //! // Or run it cycle by cycle for a finer approach
 //! nes.cycle();
 //! // Provide input state:
-//! nes.update_controller_one(Some(0b0001_0100));
+//! nes.update_controller_one(Some(ButtonStateBuilder::new().press(buttons::UP).press(buttons::SELECT).build()));
 //! nes.update_controller_two(None); // Disconnected controller
+//! // Hold input state updates until the game next latches the controllers:
+//! nes.set_input_latency(InputLatency::NextLatch);
+//! // Pick a different video backend to change the format of the frames produced by the NES:
+//! let mut indexed_nes = Nes::with_video_backend(other_cartridge, IndexedFrameBuffer::default());
+//! let indexed_frame_buffer:&[u8; 61440] = indexed_nes.frame();
+//! // Take the audio produced since the last call, resampled to the host's sample rate:
+//! nes.set_audio_sample_rate(48_000.0);
+//! let samples: Vec<f32> = nes.take_audio_samples();
+//! // Or stream it to an AudioSink as it is produced:
+//! let mut streaming_nes = Nes::with_backends(another_cartridge, ArgbFrameBuffer::default(), my_audio_sink);
 //!
 //! ```
+//! Frontends that don't need that much control can use the [Emulator](emulator/struct.Emulator.html) instead, which
+//! applies a config to the NES, passes save data to a callback, records and plays back movies, and runs each frame
+//! with a single call to `advance` that returns the frame along with its audio.
+//!
+//! ### Features
+//! Parts of the core that not every frontend needs are behind features, so that builds which need to stay small can
+//! leave them out with `default-features = false`:
+//! * `extra-mappers`: the mappers past 004. Without it, only mappers 000 to 004 are supported.
+//! * `debugger`: execution traces, from [Nes::set_execution_trace](nes/struct.Nes.html#method.set_execution_trace),
+//!   and the CPU memory map, from [Nes::get_cpu_memory_map](nes/struct.Nes.html#method.get_cpu_memory_map)
+//! * `movies`: recording and playing back movies, and the [movie](movie/index.html) module's FM2 support
+//! * `filters`: the [post-processing chain](video/postprocess/index.html) of palettes and filters
+//!
+//! These are all enabled by default. `png-export`, `recorder`, `simd` and `testing` are opt-in.
 //!
 
 #![allow(clippy::needless_return)] // I prefer clarity of return
@@ -40,6 +67,16 @@ extern crate simple_error;
 #[macro_use]
 extern crate log;
 
+pub mod audio;
+pub mod capabilities;
 pub mod cartridge;
+pub mod emulator;
 pub mod input;
+pub mod movie;
 pub mod nes;
+pub mod state;
+pub mod timing;
+pub mod trace;
+pub mod video;
+
+pub use capabilities::capabilities;
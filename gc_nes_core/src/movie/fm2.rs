@@ -0,0 +1,155 @@
+//! The fm2 module reads and writes movies in FCEUX's FM2 text format, which is the most
+//! widely used format for NES tool-assisted speedruns.
+//!
+//! Only the standard controller ports are supported. Reset commands and the Four Score,
+//! Famicom Disk System, and Zapper extensions are ignored when reading.
+
+use super::*;
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+/// The characters representing each button in an FM2 input log, from the highest bit of the input state to the lowest
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+impl Movie {
+    /// Reads a movie in the FM2 format
+    pub fn read_fm2<T: BufRead>(reader: T) -> Result<Movie, Box<dyn Error>> {
+        let mut movie = Movie::new();
+        let mut ports_connected = [true, true];
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim_end();
+            if line.starts_with('|') {
+                let fields: Vec<&str> = line.split('|').collect();
+                if fields.len() < 4 {
+                    bail!("Invalid FM2 input log line: {}", line);
+                }
+                if fields[1].parse::<u8>().unwrap_or(0) != 0 {
+                    warn!("FM2 commands are unsupported and will be ignored on frame {}", movie.len());
+                }
+                let input = FrameInput {
+                    controller_one: if ports_connected[0] { Some(parse_fm2_buttons(fields[2])?) } else { None },
+                    controller_two: if ports_connected[1] { Some(parse_fm2_buttons(fields[3])?) } else { None },
+                };
+                movie.frames.push(input);
+            } else if let Some((key, value)) = line.split_once(' ') {
+                match key {
+                    "rerecordCount" => movie.rerecord_count = value.parse()?,
+                    "romChecksum" => movie.rom_md5 = parse_fm2_checksum(value),
+                    "port0" => ports_connected[0] = value == "1",
+                    "port1" => ports_connected[1] = value == "1",
+                    _ => {}
+                }
+            }
+        }
+        return Ok(movie);
+    }
+
+    /// Writes the movie in the FM2 format. The ROM filename is only used as a description in the header.
+    pub fn write_fm2<T: Write>(&self, mut writer: T, rom_filename: &str) -> Result<(), Box<dyn Error>> {
+        let ports_connected = [
+            self.frames.iter().any(|input| input.controller_one.is_some()),
+            self.frames.iter().any(|input| input.controller_two.is_some()),
+        ];
+        writeln!(writer, "version 3")?;
+        writeln!(writer, "emuVersion 0")?;
+        writeln!(writer, "rerecordCount {}", self.rerecord_count)?;
+        writeln!(writer, "palFlag 0")?;
+        writeln!(writer, "romFilename {}", rom_filename)?;
+        if let Some(rom_md5) = self.rom_md5 {
+            writeln!(writer, "romChecksum base64:{}", base64::encode(rom_md5))?;
+        }
+        writeln!(writer, "guid 00000000-0000-0000-0000-000000000000")?;
+        writeln!(writer, "comment author gc_nes_emulator v{}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(writer, "fourscore 0")?;
+        writeln!(writer, "port0 {}", ports_connected[0] as u8)?;
+        writeln!(writer, "port1 {}", ports_connected[1] as u8)?;
+        writeln!(writer, "port2 0")?;
+        for input in self.frames.iter() {
+            writeln!(
+                writer,
+                "|0|{}|{}||",
+                format_fm2_buttons(ports_connected[0], input.controller_one),
+                format_fm2_buttons(ports_connected[1], input.controller_two)
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+/// Parses the buttons of a single port from an FM2 input log line, where any character other than '.' or ' ' is pressed
+fn parse_fm2_buttons(buttons: &str) -> Result<u8, Box<dyn Error>> {
+    if buttons.len() != FM2_BUTTONS.len() {
+        bail!("Invalid FM2 controller input: {}", buttons);
+    }
+    return Ok(buttons
+        .bytes()
+        .enumerate()
+        .filter(|(_, button)| *button != b'.' && *button != b' ')
+        .fold(0x00, |state, (index, _)| state | (0x80 >> index)));
+}
+
+/// Formats the buttons of a single port for an FM2 input log line, disconnected ports are left empty
+fn format_fm2_buttons(port_connected: bool, input_state: Option<u8>) -> String {
+    if !port_connected {
+        return String::new();
+    }
+    let input_state = input_state.unwrap_or(0x00);
+    return FM2_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(index, button)| if input_state & (0x80 >> index) > 0 { *button as char } else { '.' })
+        .collect();
+}
+
+/// Parses the base64 encoded MD5 ROM checksum from an FM2 header, returning None if it is invalid
+fn parse_fm2_checksum(value: &str) -> Option<[u8; 16]> {
+    let decoded = base64::decode(value.strip_prefix("base64:")?).ok()?;
+    let mut checksum = [0; 16];
+    if decoded.len() != checksum.len() {
+        return None;
+    }
+    checksum.copy_from_slice(&decoded);
+    return Some(checksum);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_FM2: &str = "version 3\n\
+                            emuVersion 22020\n\
+                            rerecordCount 12\n\
+                            romChecksum base64:kAFQmDzST7DWlj99KOF/cg==\n\
+                            port0 1\n\
+                            port1 0\n\
+                            port2 0\n\
+                            |0|........|||\n\
+                            |0|R..U...A|||\n\
+                            |0|.L..TSB.|||\n";
+
+    #[test]
+    fn test_read_fm2() {
+        let movie = Movie::read_fm2(TEST_FM2.as_bytes()).unwrap();
+        assert_eq!(3, movie.len());
+        assert_eq!(12, movie.get_rerecord_count());
+        assert_eq!(Some(0x90), movie.get_rom_md5().map(|md5| md5[0]));
+        assert_eq!(Some(0x00), movie.get_input(0).controller_one);
+        assert_eq!(Some(0x91), movie.get_input(1).controller_one);
+        assert_eq!(Some(0x4e), movie.get_input(2).controller_one);
+        assert_eq!(None, movie.get_input(2).controller_two);
+    }
+
+    #[test]
+    fn test_write_fm2_round_trip() {
+        let movie = Movie::read_fm2(TEST_FM2.as_bytes()).unwrap();
+        let mut written = Vec::new();
+        movie.write_fm2(&mut written, "test.nes").unwrap();
+        assert_eq!(movie, Movie::read_fm2(written.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_read_fm2_invalid_input() {
+        Movie::read_fm2("|0|RL|||\n".as_bytes()).expect_err("Did not produce an error for invalid controller input");
+    }
+}
@@ -0,0 +1,212 @@
+//! The movie module contains types for recording the input given to the NES on
+//! each frame and playing it back, which is the basis of tool-assisted speedruns.
+//! Playback is deterministic, so a movie always produces the same frames when
+//! played back from power on with the same cartridge.
+//!
+//! Everything but [FrameInput](struct.FrameInput.html) needs the movies feature, which is enabled by default.
+
+#[cfg(feature = "movies")]
+mod fm2;
+#[cfg(feature = "movies")]
+mod renderer;
+
+#[cfg(feature = "movies")]
+use crate::nes::Nes;
+#[cfg(feature = "movies")]
+use crate::video::VideoBackend;
+#[cfg(feature = "movies")]
+use std::collections::BTreeMap;
+
+#[cfg(feature = "movies")]
+pub use renderer::FrameRenderer;
+
+/// The input state of both controller ports for a single frame, in the same format
+/// as the arguments to update_controller_one and update_controller_two.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FrameInput {
+    /// The state of the controller connected to the first port, or None if it is disconnected
+    pub controller_one: Option<u8>,
+    /// The state of the controller connected to the second port, or None if it is disconnected
+    pub controller_two: Option<u8>,
+}
+
+/// A recording of the input given to the NES on each frame since power on
+#[cfg(feature = "movies")]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Movie {
+    /// The input for each frame of the movie
+    frames: Vec<FrameInput>,
+    /// The number of times that input for a frame that had already been played was changed
+    rerecord_count: u32,
+    /// The MD5 hash of the ROM the movie was recorded with, as calculated by calculate_rom_md5
+    rom_md5: Option<[u8; 16]>,
+}
+
+#[cfg(feature = "movies")]
+impl Movie {
+    /// Creates a new empty movie
+    pub fn new() -> Self {
+        return Default::default();
+    }
+
+    /// Gets the number of frames in the movie
+    pub fn len(&self) -> usize {
+        return self.frames.len();
+    }
+
+    /// Checks if the movie has no frames
+    pub fn is_empty(&self) -> bool {
+        return self.frames.is_empty();
+    }
+
+    /// Gets the input for every frame of the movie
+    pub fn frames(&self) -> &[FrameInput] {
+        return &self.frames;
+    }
+
+    /// Gets the input for a frame, or the default input if the frame is past the end of the movie
+    pub fn get_input(&self, frame: usize) -> FrameInput {
+        return self.frames.get(frame).copied().unwrap_or_default();
+    }
+
+    /// Sets the input for a frame, extending the movie with default input if the frame is past the end
+    pub fn set_input(&mut self, frame: usize, input: FrameInput) {
+        if frame >= self.frames.len() {
+            self.frames.resize(frame + 1, Default::default());
+        }
+        self.frames[frame] = input;
+    }
+
+    /// Removes every frame from the movie after the first len frames
+    pub fn truncate(&mut self, len: usize) {
+        self.frames.truncate(len);
+    }
+
+    /// Gets the number of times that input for a frame that had already been played was changed
+    pub fn get_rerecord_count(&self) -> u32 {
+        return self.rerecord_count;
+    }
+
+    /// Records that the input for a frame that had already been played was changed
+    pub fn increment_rerecord_count(&mut self) {
+        self.rerecord_count = self.rerecord_count.saturating_add(1);
+    }
+
+    /// Gets the MD5 hash of the ROM the movie was recorded with, if it is known
+    pub fn get_rom_md5(&self) -> Option<[u8; 16]> {
+        return self.rom_md5;
+    }
+
+    /// Sets the MD5 hash of the ROM the movie was recorded with, as calculated by
+    /// [calculate_rom_md5](../cartridge/fn.calculate_rom_md5.html)
+    pub fn set_rom_md5(&mut self, rom_md5: Option<[u8; 16]>) {
+        self.rom_md5 = rom_md5;
+    }
+}
+
+/// A set of snapshots of a NES taken while playing back a movie, which allows seeking
+/// to any frame of the movie without replaying it from power on.
+///
+/// The snapshot for a frame holds the state of the NES just before the input for that
+/// frame is applied, so the snapshot for frame 0 is the NES at power on.
+#[cfg(feature = "movies")]
+pub struct Greenzone<V: VideoBackend + Clone> {
+    /// The snapshots of the NES, keyed by frame
+    snapshots: BTreeMap<usize, Nes<V>>,
+    /// The number of frames between snapshots
+    interval: usize,
+}
+
+#[cfg(feature = "movies")]
+impl<V: VideoBackend + Clone> Greenzone<V> {
+    /// Creates a new greenzone from a NES at power on, that takes a snapshot every interval frames
+    pub fn new(power_on: Nes<V>, interval: usize) -> Self {
+        let mut snapshots = BTreeMap::new();
+        snapshots.insert(0, power_on);
+        return Greenzone {
+            snapshots,
+            interval: interval.max(1),
+        };
+    }
+
+    /// Gets the last frame that has a snapshot, every frame up to this one can be seeked to quickly
+    pub fn get_last_frame(&self) -> usize {
+        return *self.snapshots.keys().next_back().unwrap_or(&0);
+    }
+
+    /// Discards the snapshots after the frame, which should be done whenever the input for the frame is changed
+    pub fn invalidate_after(&mut self, frame: usize) {
+        self.snapshots.split_off(&(frame + 1));
+    }
+
+    /// Produces the NES as it was just before the input for the frame was applied, by playing back the movie
+    /// from the closest earlier snapshot. Snapshots are taken along the way.
+    pub fn seek_to_frame(&mut self, movie: &Movie, frame: usize) -> Nes<V> {
+        let (&start, snapshot) = self
+            .snapshots
+            .range(..=frame)
+            .next_back()
+            .expect("The power on snapshot is never removed");
+        let mut nes = snapshot.clone();
+        for current in start..frame {
+            self.run_frame(&mut nes, movie, current);
+        }
+        return nes;
+    }
+
+    /// Runs a single frame of the movie on a NES that is just before the input for the frame is applied,
+    /// taking a snapshot afterwards if the next frame falls on the snapshot interval.
+    pub fn run_frame(&mut self, nes: &mut Nes<V>, movie: &Movie, frame: usize) {
+        nes.update_frame_input(movie.get_input(frame));
+        nes.frame();
+        if (frame + 1).is_multiple_of(self.interval) {
+            self.snapshots.insert(frame + 1, nes.clone());
+        }
+    }
+}
+
+#[cfg(all(test, feature = "movies"))]
+mod test {
+    use super::*;
+    use crate::cartridge::test_utils::{get_mock_cartridge, MapperMock};
+    use crate::cartridge::Mirroring;
+    use crate::video::NullVideoBackend;
+
+    fn get_test_nes() -> Nes<NullVideoBackend> {
+        let mapper = MapperMock {
+            program_read_stub: |_, _| 0x00,
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        };
+        return Nes::with_video_backend(get_mock_cartridge(mapper), NullVideoBackend);
+    }
+
+    #[test]
+    fn test_movie_set_input_extends() {
+        let mut movie = Movie::new();
+        let input = FrameInput {
+            controller_one: Some(0x01),
+            controller_two: None,
+        };
+        movie.set_input(3, input);
+        assert_eq!(4, movie.len());
+        assert_eq!(FrameInput::default(), movie.get_input(0));
+        assert_eq!(input, movie.get_input(3));
+        assert_eq!(FrameInput::default(), movie.get_input(10));
+    }
+
+    #[test]
+    fn test_greenzone_seek_takes_snapshots() {
+        let mut greenzone = Greenzone::new(get_test_nes(), 2);
+        let movie = Movie::new();
+        let nes = greenzone.seek_to_frame(&movie, 5);
+        assert_eq!(5, nes.get_frame_count());
+        assert_eq!(4, greenzone.get_last_frame());
+        greenzone.invalidate_after(2);
+        assert_eq!(2, greenzone.get_last_frame());
+        // Seeking backwards restores from an earlier snapshot
+        let nes = greenzone.seek_to_frame(&movie, 3);
+        assert_eq!(3, nes.get_frame_count());
+    }
+}
@@ -0,0 +1,103 @@
+//! The renderer module renders individual frames of a ROM, optionally played with a movie, without
+//! a window. It's meant for thumbnails, movie previews, and library screenshots, where only a handful
+//! of frames are needed but they could be anywhere in the movie.
+
+use super::{Greenzone, Movie};
+use crate::cartridge::Cartridge;
+use crate::nes::{Nes, NES_SCREEN_DIMENSIONS};
+use crate::video::{IndexedFrameBuffer, VideoBackend};
+
+/// The number of frames between the keyframes that the renderer keeps, one second of emulation
+const KEYFRAME_INTERVAL: usize = 60;
+
+/// Renders arbitrary frames of a ROM played with a movie. Keyframes are kept as frames are rendered,
+/// so rendering a frame only replays the movie from the closest earlier keyframe.
+pub struct FrameRenderer {
+    /// The keyframes of the NES
+    keyframes: Greenzone<IndexedFrameBuffer>,
+    /// The movie that provides the input for each frame
+    movie: Movie,
+}
+
+impl FrameRenderer {
+    /// Creates a new renderer for a cartridge, played with the movie if one is provided or with no input otherwise
+    pub fn new(cartridge: Cartridge, movie: Option<Movie>) -> Self {
+        let power_on = Nes::with_video_backend(cartridge, IndexedFrameBuffer::default());
+        return FrameRenderer {
+            keyframes: Greenzone::new(power_on, KEYFRAME_INTERVAL),
+            movie: movie.unwrap_or_default(),
+        };
+    }
+
+    /// Gets the movie that provides the input for each frame
+    pub fn get_movie(&self) -> &Movie {
+        return &self.movie;
+    }
+
+    /// Renders the image on screen after the first frame frames have been run, as NES colour indices.
+    /// Frame 0 is the blank screen at power on.
+    pub fn render_frame(&mut self, frame: usize) -> Box<[u8; NES_SCREEN_DIMENSIONS]> {
+        let nes = self.keyframes.seek_to_frame(&self.movie, frame);
+        return Box::new(*nes.get_video_backend().get_frame());
+    }
+
+    /// Renders the image on screen after the first frame frames have been run, with each pixel represented
+    /// by four bytes in RGBA order.
+    pub fn render_rgba_frame(&mut self, frame: usize) -> Vec<u8> {
+        let nes = self.keyframes.seek_to_frame(&self.movie, frame);
+        return nes
+            .get_video_backend()
+            .get_rgba_frame()
+            .expect("Indexed frames can always be converted to RGBA");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test_utils::{get_mock_cartridge, MapperMock};
+    use crate::cartridge::Mirroring;
+    use crate::movie::FrameInput;
+
+    fn get_test_cartridge() -> Cartridge {
+        let mapper = MapperMock {
+            program_read_stub: |_, _| 0x00,
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        };
+        return get_mock_cartridge(mapper);
+    }
+
+    #[test]
+    fn test_render_frame_out_of_order() {
+        let mut movie = Movie::new();
+        movie.set_input(
+            70,
+            FrameInput {
+                controller_one: Some(0x08),
+                controller_two: None,
+            },
+        );
+        let mut renderer = FrameRenderer::new(get_test_cartridge(), Some(movie.clone()));
+        let late = renderer.render_frame(130);
+        let early = renderer.render_frame(65);
+
+        let mut nes = Nes::with_video_backend(get_test_cartridge(), IndexedFrameBuffer::default());
+        for frame in 0..130 {
+            nes.update_frame_input(movie.get_input(frame));
+            nes.frame();
+            if frame + 1 == 65 {
+                assert_eq!(&early[..], &nes.get_video_backend().get_frame()[..]);
+            }
+        }
+        assert_eq!(&late[..], &nes.get_video_backend().get_frame()[..]);
+    }
+
+    #[test]
+    fn test_render_rgba_frame() {
+        let mut renderer = FrameRenderer::new(get_test_cartridge(), None);
+        assert_eq!(NES_SCREEN_DIMENSIONS * 4, renderer.render_rgba_frame(3).len());
+        assert!(renderer.get_movie().is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+//! The accuracy module contains the options for trading emulation accuracy for speed. Each option
+//! switches off an expensive behaviour that few games depend on, and the presets group them so
+//! that the subsystems are made less accurate together rather than piecemeal.
+
+/// A named group of accuracy options, from the most faithful to the fastest
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccuracyPreset {
+    /// Emulate every behaviour that the core knows about
+    Accuracy,
+    /// Skip behaviours that are expensive and that games are very unlikely to notice
+    Balanced,
+    /// Skip every optional behaviour, for slow hosts and fast forwarding
+    Speed,
+}
+
+/// The names of the presets in the order returned by [AccuracyPreset::name](enum.AccuracyPreset.html#method.name)
+pub(crate) const ACCURACY_PRESET_NAMES: &[&str] = &["accuracy", "balanced", "speed"];
+
+impl AccuracyPreset {
+    /// Gets the lowercase name of the preset, as listed in the core's capabilities
+    pub fn name(self) -> &'static str {
+        return match self {
+            AccuracyPreset::Accuracy => ACCURACY_PRESET_NAMES[0],
+            AccuracyPreset::Balanced => ACCURACY_PRESET_NAMES[1],
+            AccuracyPreset::Speed => ACCURACY_PRESET_NAMES[2],
+        };
+    }
+
+    /// Gets the preset with a lowercase name, or None if there isn't one
+    pub fn from_name(name: &str) -> Option<Self> {
+        return [AccuracyPreset::Accuracy, AccuracyPreset::Balanced, AccuracyPreset::Speed]
+            .iter()
+            .copied()
+            .find(|preset| preset.name() == name);
+    }
+}
+
+/// The individual accuracy options that a NES is run with. Start from a preset and override
+/// single options with struct update syntax:
+/// ```
+/// use gc_nes_core::nes::{AccuracyOptions, AccuracyPreset};
+///
+/// let options = AccuracyOptions {
+///     per_dot_sprite_evaluation: true,
+///     ..AccuracyPreset::Speed.into()
+/// };
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AccuracyOptions {
+    /// Evaluate which sprites are on the next scanline over the course of the current one, as the PPU does,
+    /// rather than all at once at the end of it. Only matters to games that change OAM mid-scanline.
+    pub per_dot_sprite_evaluation: bool,
+    /// Filter the PPU address line A12 before mappers count its rising edges for scanline interrupts, as the MMC3
+    /// does. Without the filter, A12 is only watched during the pattern table fetches, which is cheaper and counts
+    /// the same edges for games that don't switch pattern tables mid-scanline.
+    pub a12_filtering: bool,
+    /// Let the value left on the PPU's I/O bus fade to zero when it isn't refreshed, as the real bus does
+    /// after around 600 milliseconds. Only test ROMs are known to depend on this.
+    pub open_bus_decay: bool,
+    /// Suspend the CPU while the DMC channel fetches samples, rather than fetching them for free.
+    /// Only matters to code with cycle counted timing that runs while a sample is playing.
+    pub dmc_stalls: bool,
+}
+
+impl From<AccuracyPreset> for AccuracyOptions {
+    fn from(preset: AccuracyPreset) -> Self {
+        return match preset {
+            AccuracyPreset::Accuracy => AccuracyOptions {
+                per_dot_sprite_evaluation: true,
+                a12_filtering: true,
+                open_bus_decay: true,
+                dmc_stalls: true,
+            },
+            AccuracyPreset::Balanced => AccuracyOptions {
+                per_dot_sprite_evaluation: true,
+                a12_filtering: true,
+                open_bus_decay: false,
+                dmc_stalls: true,
+            },
+            AccuracyPreset::Speed => AccuracyOptions {
+                per_dot_sprite_evaluation: false,
+                a12_filtering: false,
+                open_bus_decay: false,
+                dmc_stalls: false,
+            },
+        };
+    }
+}
+
+impl Default for AccuracyOptions {
+    fn default() -> Self {
+        return AccuracyPreset::Balanced.into();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_preset_names_round_trip() {
+        for name in ACCURACY_PRESET_NAMES {
+            assert_eq!(*name, AccuracyPreset::from_name(name).unwrap().name());
+        }
+        assert_eq!(None, AccuracyPreset::from_name("perfect"));
+    }
+}
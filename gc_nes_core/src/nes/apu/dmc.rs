@@ -0,0 +1,204 @@
+//! The dmc module contains the APU's delta modulation channel, which plays 1 bit delta
+//! encoded samples that it fetches from memory by briefly taking over the CPU's bus.
+
+use super::CPU_DIVIDER;
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
+
+/// The NTSC timer periods of the DMC channel in CPU cycles, indexed by the bottom four bits of $4010
+const DMC_RATE_TABLE: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+
+/// Structure representing the APU's delta modulation channel, which is controlled through registers $4010-$4013
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct DmcChannel {
+    /// Whether the channel raises an interrupt when a sample finishes without looping
+    interrupt_enabled: bool,
+    /// Whether the sample restarts from the beginning when it finishes
+    looping: bool,
+    /// The period of the channel's timer in CPU cycles, from the DMC rate table
+    timer_period: u16,
+    /// The cycle of the master clock that the channel's timer next moves on to the next bit of the sample on
+    timer_clock: u64,
+    /// The 7 bit output level that the sample's bits move up or down
+    output_level: u8,
+    /// The address that samples start from, between $C000 and $FFC0
+    sample_address: u16,
+    /// The length of the samples in bytes
+    sample_length: u16,
+    /// The address of the next byte of the sample to be fetched
+    current_address: u16,
+    /// The number of bytes of the sample that are yet to be fetched
+    bytes_remaining: u16,
+    /// The byte that was most recently fetched, if it hasn't been moved to the shift register yet
+    sample_buffer: Option<u8>,
+    /// The bits of the sample byte that is being played, lowest bit first
+    shift_register: u8,
+    /// The number of bits left in the shift register before the next byte is taken from the sample buffer
+    bits_remaining: u8,
+    /// Set when the sample buffer was empty at the start of the current byte, holding the output level where it is
+    silence: bool,
+    /// Set when a sample that doesn't loop finishes with interrupts enabled, until it is acknowledged
+    pub(super) interrupt: bool,
+}
+
+impl DmcChannel {
+    /// Creates a new DMC channel
+    pub(super) fn new() -> Self {
+        DmcChannel {
+            interrupt_enabled: false,
+            looping: false,
+            timer_period: DMC_RATE_TABLE[0],
+            timer_clock: 0,
+            output_level: 0,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            interrupt: false,
+        }
+    }
+
+    /// Writes to one of the channel's four registers, selected by the bottom two bits of the address
+    pub(super) fn write(&mut self, address: u16, data: u8) {
+        match address & 0x03 {
+            0x00 => {
+                self.interrupt_enabled = data & 0x80 > 0;
+                self.looping = data & 0x40 > 0;
+                self.timer_period = DMC_RATE_TABLE[(data & 0x0f) as usize];
+                if !self.interrupt_enabled {
+                    self.interrupt = false;
+                }
+            }
+            0x01 => self.output_level = data & 0x7f,
+            0x02 => self.sample_address = 0xc000 | (u16::from(data) << 6),
+            0x03 => self.sample_length = (u16::from(data) << 4) + 1,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Enables or disables the channel through the status register. Enabling the channel
+    /// restarts the sample only if it has finished, and disabling it lets the remaining bits
+    /// of the current byte play out before falling silent.
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.interrupt = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    /// Starts the sample over from its first byte
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Checks if the channel is playing a sample, meaning there are bytes of it left to fetch
+    pub(super) fn is_active(&self) -> bool {
+        return self.bytes_remaining > 0;
+    }
+
+    /// Gets the address of the next sample byte if the sample buffer is empty and there are bytes left to fetch
+    pub(super) fn get_sample_request(&self) -> Option<u16> {
+        return if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        };
+    }
+
+    /// Fills the sample buffer with a byte fetched from the address returned by get_sample_request
+    pub(super) fn load_sample(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        // The address wraps around to $8000 rather than $0000 after passing the end of memory
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.interrupt_enabled {
+                self.interrupt = true;
+            }
+        }
+    }
+
+    /// Clocks the channel's timer, which happens every CPU cycle, on the cycle of the master clock that the CPU cycle runs on
+    pub(super) fn clock_timer(&mut self, master_clock: u64) {
+        if master_clock < self.timer_clock {
+            return;
+        }
+        self.timer_clock = master_clock + u64::from(self.timer_period) * CPU_DIVIDER;
+
+        if !self.silence {
+            // Each bit of the sample moves the output level up or down by two, as long as it stays within 7 bits
+            if self.shift_register & 0x01 > 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// Gets the current output of the channel, from 0 to 127
+    pub(super) fn output(&self) -> u8 {
+        return self.output_level;
+    }
+
+    /// Writes the state of the channel to a save state
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.interrupt_enabled);
+        state.write_bool(self.looping);
+        state.write_u16(self.timer_period);
+        state.write_u64(self.timer_clock);
+        state.write_u8(self.output_level);
+        state.write_u16(self.sample_address);
+        state.write_u16(self.sample_length);
+        state.write_u16(self.current_address);
+        state.write_u16(self.bytes_remaining);
+        state.write_option_u8(self.sample_buffer);
+        state.write_u8(self.shift_register);
+        state.write_u8(self.bits_remaining);
+        state.write_bool(self.silence);
+        state.write_bool(self.interrupt);
+    }
+
+    /// Restores the state of the channel from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.interrupt_enabled = state.read_bool()?;
+        self.looping = state.read_bool()?;
+        self.timer_period = state.read_u16()?;
+        self.timer_clock = state.read_u64()?;
+        self.output_level = state.read_u8()? & 0x7f;
+        self.sample_address = state.read_u16()?;
+        self.sample_length = state.read_u16()?;
+        self.current_address = state.read_u16()?;
+        self.bytes_remaining = state.read_u16()?;
+        self.sample_buffer = state.read_option_u8()?;
+        self.shift_register = state.read_u8()?;
+        // The bits remaining count down to zero before being reloaded with eight, so they are always between the two
+        self.bits_remaining = state.read_u8()?.clamp(1, 8);
+        self.silence = state.read_bool()?;
+        self.interrupt = state.read_bool()?;
+        return Ok(());
+    }
+}
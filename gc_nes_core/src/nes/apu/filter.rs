@@ -0,0 +1,129 @@
+//! The filter module contains the analog filters that the NES passes its mixed audio through
+//! on the way to the output, which shape the console's characteristic sound.
+
+use crate::nes::NES_AUDIO_SAMPLE_RATE;
+use std::f64::consts::PI;
+
+/// The cutoff frequency of the first high-pass filter, in Hz
+const FIRST_HIGH_PASS_CUTOFF: f64 = 90.0;
+/// The cutoff frequency of the second high-pass filter, in Hz
+const SECOND_HIGH_PASS_CUTOFF: f64 = 440.0;
+/// The cutoff frequency of the low-pass filter, in Hz
+const LOW_PASS_CUTOFF: f64 = 14_000.0;
+
+/// The chain of first-order filters between the APU's mixer and the NES' audio output: a high-pass filter at 90 Hz,
+/// another at 440 Hz and a low-pass filter at 14 kHz. The high-pass filters remove the DC offset of the mixer's
+/// output, centring it on zero, and thin out the bass, while the low-pass filter softens the edges of the waves.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct AnalogFilters {
+    /// The first high-pass filter, at 90 Hz
+    first_high_pass: HighPassFilter,
+    /// The second high-pass filter, at 440 Hz
+    second_high_pass: HighPassFilter,
+    /// The low-pass filter, at 14 kHz
+    low_pass: LowPassFilter,
+}
+
+impl AnalogFilters {
+    /// Creates the chain of filters, with a silent output
+    pub(super) fn new() -> Self {
+        return AnalogFilters {
+            first_high_pass: HighPassFilter::new(FIRST_HIGH_PASS_CUTOFF),
+            second_high_pass: HighPassFilter::new(SECOND_HIGH_PASS_CUTOFF),
+            low_pass: LowPassFilter::new(LOW_PASS_CUTOFF),
+        };
+    }
+
+    /// Passes a sample of the mixer's output through each of the filters, returning the filtered sample.
+    /// Called once every CPU cycle.
+    pub(super) fn process(&mut self, sample: f32) -> f32 {
+        let sample = self.first_high_pass.process(sample);
+        let sample = self.second_high_pass.process(sample);
+        return self.low_pass.process(sample);
+    }
+
+    /// Gets the last sample produced by the filters
+    pub(super) fn output(&self) -> f32 {
+        return self.low_pass.last_output;
+    }
+}
+
+/// A first-order RC high-pass filter, running at the APU's sample rate
+#[derive(Debug, Clone, PartialEq)]
+struct HighPassFilter {
+    /// The fraction of the previous output that carries over to the next, RC / (RC + dt)
+    coefficient: f32,
+    /// The last input sample
+    last_input: f32,
+    /// The last output sample
+    last_output: f32,
+}
+
+impl HighPassFilter {
+    /// Creates a high-pass filter with a cutoff frequency in Hz
+    fn new(cutoff: f64) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / NES_AUDIO_SAMPLE_RATE;
+        return HighPassFilter {
+            coefficient: (rc / (rc + dt)) as f32,
+            last_input: 0.0,
+            last_output: 0.0,
+        };
+    }
+
+    /// Filters a single sample
+    fn process(&mut self, sample: f32) -> f32 {
+        self.last_output = self.coefficient * (self.last_output + sample - self.last_input);
+        self.last_input = sample;
+        return self.last_output;
+    }
+}
+
+/// A first-order RC low-pass filter, running at the APU's sample rate
+#[derive(Debug, Clone, PartialEq)]
+struct LowPassFilter {
+    /// The fraction of the difference between the input and the previous output that is added to the output, dt / (RC + dt)
+    coefficient: f32,
+    /// The last output sample
+    last_output: f32,
+}
+
+impl LowPassFilter {
+    /// Creates a low-pass filter with a cutoff frequency in Hz
+    fn new(cutoff: f64) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / NES_AUDIO_SAMPLE_RATE;
+        return LowPassFilter {
+            coefficient: (dt / (rc + dt)) as f32,
+            last_output: 0.0,
+        };
+    }
+
+    /// Filters a single sample
+    fn process(&mut self, sample: f32) -> f32 {
+        self.last_output += self.coefficient * (sample - self.last_output);
+        return self.last_output;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_analog_filters() {
+        let mut filters = AnalogFilters::new();
+        // A constant input is an offset that the high-pass filters remove, after jumping with the step and decaying
+        let samples: Vec<f32> = (0..NES_AUDIO_SAMPLE_RATE as usize / 10).map(|_| filters.process(0.5)).collect();
+        assert!(samples.iter().any(|sample| *sample > 0.25));
+        assert!(filters.output().abs() < 0.001);
+
+        // A 20 kHz square wave is above the low-pass filter's cutoff, rounding it off well below its full amplitude
+        let period = (NES_AUDIO_SAMPLE_RATE / 20_000.0) as usize;
+        let peak = (0..NES_AUDIO_SAMPLE_RATE as usize / 10)
+            .map(|cycle| filters.process(if cycle % period < period / 2 { 1.0 } else { 0.0 }))
+            .skip(NES_AUDIO_SAMPLE_RATE as usize / 20)
+            .fold(0.0, f32::max);
+        assert!(peak > 0.25 && peak < 0.45, "{}", peak);
+    }
+}
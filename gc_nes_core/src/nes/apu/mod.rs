@@ -0,0 +1,870 @@
+//! The apu module holds the Audio Processing Unit of the NES,
+//! which is responsible for all of the NES' sound. At present,
+//! all five of the channels are implemented.
+
+mod dmc;
+mod filter;
+mod noise;
+mod pulse;
+mod triangle;
+mod units;
+
+use crate::audio::stereo::{pan_gains, StereoPanning};
+use crate::audio::trace::ApuRegisterWrite;
+use crate::audio::ChannelOutputs;
+use crate::state::{StateReader, StateWriter};
+use crate::timing::MasterClock;
+use dmc::DmcChannel;
+use filter::AnalogFilters;
+use noise::NoiseChannel;
+use pulse::PulseChannel;
+use std::error::Error;
+use triangle::TriangleChannel;
+
+/// The number of master clock cycles in each CPU cycle, which the APU runs once every
+const CPU_DIVIDER: u64 = MasterClock::NTSC.cpu_divider as u64;
+/// The CPU cycles of the frame counter's sequence on which the quarter frame units are clocked
+const QUARTER_FRAME_CYCLES: [u64; 4] = [7457, 14913, 22371, 29829];
+/// The CPU cycle on which the last step of the four step sequence occurs, and the frame interrupt is raised
+const FOUR_STEP_SEQUENCE_END: u64 = 29829;
+/// The CPU cycle on which the last step of the five step sequence occurs
+const FIVE_STEP_SEQUENCE_END: u64 = 37281;
+
+/// Structure containing the registers and state of the NES'
+/// Audio Processing Unit (In the real NES this is an extension
+/// of the CPU, but I am representing it separately).
+#[derive(Clone)]
+pub(super) struct NesApu {
+    /// The first of the two pulse channels, controlled through $4000-$4003
+    pulse_one: PulseChannel,
+    /// The second of the two pulse channels, controlled through $4004-$4007
+    pulse_two: PulseChannel,
+    /// The triangle channel, controlled through $4008-$400B
+    triangle: TriangleChannel,
+    /// The noise channel, controlled through $400C-$400F
+    noise: NoiseChannel,
+    /// The delta modulation channel, controlled through $4010-$4013
+    dmc: DmcChannel,
+    /// Whether the frame counter is running the five step sequence instead of the four step sequence
+    five_step_mode: bool,
+    /// Whether the frame counter is prevented from raising interrupts
+    interrupt_inhibit: bool,
+    /// Set when the frame counter raises an interrupt at the end of the four step sequence
+    frame_interrupt: bool,
+    /// Set when the frame interrupt is raised, until the interrupt request is passed on to the CPU
+    pending_interrupt_request: bool,
+    /// The cycle of the master clock that a write to $4017 resets the frame counter on, if one is pending
+    frame_counter_reset: Option<u64>,
+    /// The cycle of the master clock that the first CPU cycle of the frame counter's current sequence runs on
+    frame_counter_start: u64,
+    /// The cycle of the master clock that the APU's next cycle runs on, which register writes are timed from
+    master_clock: u64,
+    /// The analog filters that the mixed output, or the left output when mixing in stereo, is passed through,
+    /// or None if they are disabled
+    filters: Option<AnalogFilters>,
+    /// The analog filters that the right output is passed through when mixing in stereo, or None if they are disabled
+    right_filters: Option<AnalogFilters>,
+    /// The pan positions that the channels are mixed into the left and right outputs with, or None to mix in mono
+    panning: Option<StereoPanning>,
+    /// The output of the cartridge's expansion audio, which is added to the output of the APU's channels
+    expansion_output: f32,
+    /// The number of CPU cycles that the APU has run for
+    cycle_count: u64,
+    /// The writes to the APU's registers since they were last taken, or None if they aren't being traced
+    trace: Option<Vec<ApuRegisterWrite>>,
+}
+
+impl NesApu {
+    /// Create a new instance of a NES APU
+    pub fn new() -> Self {
+        NesApu {
+            pulse_one: PulseChannel::new(true),
+            pulse_two: PulseChannel::new(false),
+            triangle: Default::default(),
+            noise: NoiseChannel::new(),
+            dmc: DmcChannel::new(),
+            five_step_mode: false,
+            interrupt_inhibit: false,
+            frame_interrupt: false,
+            pending_interrupt_request: false,
+            frame_counter_reset: None,
+            frame_counter_start: 0,
+            master_clock: 0,
+            filters: None,
+            right_filters: None,
+            panning: None,
+            expansion_output: 0.0,
+            cycle_count: 0,
+            trace: None,
+        }
+    }
+
+    /// Runs a single APU cycle, which happens once every CPU cycle, on the cycle of the NES' master clock that the CPU
+    /// cycle runs on. The frame counter and the DMC channel are timed by the master clock rather than counting cycles.
+    pub fn cycle(&mut self, master_clock: u64) {
+        self.cycle_count += 1;
+        self.clock_channels(master_clock);
+        self.master_clock = master_clock + CPU_DIVIDER;
+        self.pulse_one.length_counter.end_cycle();
+        self.pulse_two.length_counter.end_cycle();
+        self.triangle.length_counter.end_cycle();
+        self.noise.length_counter.end_cycle();
+        match self.panning {
+            None => {
+                let mixed = self.mix_output();
+                if let Some(filters) = &mut self.filters {
+                    filters.process(mixed);
+                }
+            }
+            Some(panning) => {
+                let (left, right) = self.mix_stereo_output(panning);
+                if let (Some(filters), Some(right_filters)) = (&mut self.filters, &mut self.right_filters) {
+                    filters.process(left);
+                    right_filters.process(right);
+                }
+            }
+        }
+    }
+
+    /// Clocks the timers of the channels and the frame counter
+    fn clock_channels(&mut self, master_clock: u64) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer(master_clock);
+        // The pulse channels are only clocked on every other CPU cycle
+        if is_odd_cycle(master_clock) {
+            self.pulse_one.clock_timer();
+            self.pulse_two.clock_timer();
+        }
+
+        // Writes to $4017 reset the frame counter after a short delay, and entering
+        // the five step sequence immediately clocks every unit
+        if self.frame_counter_reset.is_some_and(|reset| master_clock >= reset) {
+            self.frame_counter_reset = None;
+            self.frame_counter_start = master_clock + CPU_DIVIDER;
+            if self.five_step_mode {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            return;
+        }
+
+        // Saturating, so that a corrupt save state with the sequence starting after this cycle can't underflow
+        let frame_counter_cycle = master_clock.saturating_sub(self.frame_counter_start) / CPU_DIVIDER + 1;
+        match (frame_counter_cycle, self.five_step_mode) {
+            // The four step sequence has no gap, so the final step clocks every unit
+            (FOUR_STEP_SEQUENCE_END, false) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.interrupt_inhibit {
+                    self.pending_interrupt_request |= !self.frame_interrupt;
+                    self.frame_interrupt = true;
+                }
+                self.frame_counter_start = master_clock + CPU_DIVIDER;
+            }
+            // The five step sequence skips its fourth step, clocking everything on the fifth
+            (FOUR_STEP_SEQUENCE_END, true) => {}
+            (FIVE_STEP_SEQUENCE_END, true) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                self.frame_counter_start = master_clock + CPU_DIVIDER;
+            }
+            (cycle, _) if cycle == QUARTER_FRAME_CYCLES[1] => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (cycle, _) if QUARTER_FRAME_CYCLES.contains(&cycle) => self.clock_quarter_frame(),
+            _ => {}
+        }
+    }
+
+    /// Clocks the envelopes and linear counter of the channels
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_one.clock_quarter_frame();
+        self.pulse_two.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    /// Clocks the length counters and sweep units of the channels
+    fn clock_half_frame(&mut self) {
+        self.pulse_one.clock_half_frame();
+        self.pulse_two.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// Gets the address of the next byte of the DMC channel's sample if it needs to be fetched.
+    /// The fetch is made by the bus, which passes the byte back through load_dmc_sample.
+    pub fn get_dmc_sample_request(&self) -> Option<u16> {
+        return self.dmc.get_sample_request();
+    }
+
+    /// Passes a byte fetched from the address returned by get_dmc_sample_request to the DMC channel
+    pub fn load_dmc_sample(&mut self, data: u8) {
+        let interrupt = self.dmc.interrupt;
+        self.dmc.load_sample(data);
+        self.pending_interrupt_request |= !interrupt && self.dmc.interrupt;
+    }
+
+    /// Returns true once each time the frame counter or the DMC channel raises its interrupt, so that
+    /// the request is only passed on to the CPU once, like the mapper interrupts.
+    pub fn get_pending_interrupt_request(&mut self) -> bool {
+        let value = self.pending_interrupt_request;
+        self.pending_interrupt_request = false;
+        return value;
+    }
+
+    /// Enables or disables the analog filters that the console passes its audio through. While they are
+    /// enabled, the output is centred on zero and ranges from -1.0 to 1.0. Enabling them starts them from silence.
+    pub fn set_analog_filters(&mut self, enabled: bool) {
+        self.filters = if enabled {
+            Some(self.filters.take().unwrap_or_else(AnalogFilters::new))
+        } else {
+            None
+        };
+        self.right_filters = self.filters.clone();
+    }
+
+    /// Checks if the analog filters are enabled
+    pub fn get_analog_filters(&self) -> bool {
+        return self.filters.is_some();
+    }
+
+    /// Gets the number of CPU cycles that the APU has run for
+    pub fn get_cycle_count(&self) -> u64 {
+        return self.cycle_count;
+    }
+
+    /// Enables or disables tracing writes to the APU's registers. Disabling it discards the writes that haven't been taken.
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.trace = if enabled { Some(self.trace.take().unwrap_or_default()) } else { None };
+    }
+
+    /// Takes the writes to the APU's registers that have been traced since the last call, oldest first
+    pub fn take_trace(&mut self) -> Vec<ApuRegisterWrite> {
+        return self.trace.as_mut().map(std::mem::take).unwrap_or_default();
+    }
+
+    /// Sets the pan positions that the channels are mixed into the left and right outputs with, or None to mix in mono.
+    /// The right output's filters start from the state of the left's, so switching doesn't make the output jump.
+    pub fn set_stereo_panning(&mut self, panning: Option<StereoPanning>) {
+        self.panning = panning;
+        self.right_filters = self.filters.clone();
+    }
+
+    /// Gets the pan positions that the channels are mixed with, or None if they are mixed in mono
+    pub fn get_stereo_panning(&self) -> Option<StereoPanning> {
+        return self.panning;
+    }
+
+    /// Gets the current output of the APU, from 0.0 to 1.0, or from -1.0 to 1.0 when the analog filters are enabled.
+    /// When mixing in stereo, this is the average of the left and right outputs.
+    pub fn output(&self) -> f32 {
+        if self.panning.is_some() {
+            let (left, right) = self.stereo_output();
+            return (left + right) / 2.0;
+        }
+        return match &self.filters {
+            Some(filters) => filters.output(),
+            None => self.mix_output(),
+        };
+    }
+
+    /// Gets the current left and right outputs of the APU, each from 0.0 to 1.0, or from -1.0 to 1.0 when the
+    /// analog filters are enabled. Both are the same as the mono output unless the channels are mixed in stereo.
+    pub fn stereo_output(&self) -> (f32, f32) {
+        let panning = match self.panning {
+            Some(panning) => panning,
+            None => {
+                let output = self.output();
+                return (output, output);
+            }
+        };
+        return match (&self.filters, &self.right_filters) {
+            (Some(filters), Some(right_filters)) => (filters.output(), right_filters.output()),
+            _ => self.mix_stereo_output(panning),
+        };
+    }
+
+    /// Sets the output of the cartridge's expansion audio, which is mixed with the APU's channels until it is next set
+    pub fn set_expansion_output(&mut self, output: f32) {
+        self.expansion_output = output;
+    }
+
+    /// Mixes the outputs of the APU's channels and the cartridge's expansion audio, before the analog filters
+    fn mix_output(&self) -> f32 {
+        return mix(self.channel_outputs()) + self.expansion_output;
+    }
+
+    /// Mixes the outputs of the APU's channels and the cartridge's expansion audio into the left and right outputs
+    /// with the pan positions, before the analog filters
+    fn mix_stereo_output(&self, panning: StereoPanning) -> (f32, f32) {
+        let (left, right) = mix_panned(self.channel_outputs(), panning);
+        let (expansion_left, expansion_right) = pan_gains(panning.expansion);
+        return (
+            left + self.expansion_output * expansion_left,
+            right + self.expansion_output * expansion_right,
+        );
+    }
+
+    /// Gets the current outputs of each of the APU's channels, before they are mixed. Expansion audio isn't included.
+    pub fn channel_outputs(&self) -> ChannelOutputs {
+        return ChannelOutputs {
+            pulse_one: self.pulse_one.output(),
+            pulse_two: self.pulse_two.output(),
+            triangle: self.triangle.output(),
+            noise: self.noise.output(),
+            dmc: self.dmc.output(),
+        };
+    }
+
+    /// Reads the status register at $4015, which reports which channels are still playing and which interrupts
+    /// are pending. Reading it acknowledges the frame interrupt, but not the DMC interrupt. Bit 5 isn't driven by the
+    /// APU, so it is left clear for the bus to fill in.
+    pub fn read(&mut self, address: u16) -> u8 {
+        if address != 0x4015 {
+            warn!("Read from write only APU address 0x{:04X}", address);
+            return 0x00;
+        }
+        let status = u8::from(!self.pulse_one.length_counter.is_silenced())
+            | u8::from(!self.pulse_two.length_counter.is_silenced()) << 1
+            | u8::from(!self.triangle.length_counter.is_silenced()) << 2
+            | u8::from(!self.noise.length_counter.is_silenced()) << 3
+            | u8::from(self.dmc.is_active()) << 4
+            | u8::from(self.frame_interrupt) << 6
+            | u8::from(self.dmc.interrupt) << 7;
+        self.frame_interrupt = false;
+        return status;
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(ApuRegisterWrite {
+                cycle: self.cycle_count,
+                register: address,
+                value: data,
+            });
+        }
+        match address {
+            0x4000..=0x4003 => self.pulse_one.write(address, data),
+            0x4004..=0x4007 => self.pulse_two.write(address, data),
+            0x4008..=0x400b => self.triangle.write(address, data),
+            0x400c..=0x400f => self.noise.write(address, data),
+            0x4010..=0x4013 => self.dmc.write(address, data),
+            0x4015 => {
+                // Enable or disable the length counters of each channel
+                self.pulse_one.length_counter.set_enabled(data & 0x01 > 0);
+                self.pulse_two.length_counter.set_enabled(data & 0x02 > 0);
+                self.triangle.length_counter.set_enabled(data & 0x04 > 0);
+                self.noise.length_counter.set_enabled(data & 0x08 > 0);
+                self.dmc.set_enabled(data & 0x10 > 0);
+            }
+            0x4017 => {
+                self.five_step_mode = data & 0x80 > 0;
+                self.interrupt_inhibit = data & 0x40 > 0;
+                if self.interrupt_inhibit {
+                    self.frame_interrupt = false;
+                }
+                // The frame counter is reset 3 CPU cycles after the write if it happens on a cycle
+                // that clocks the pulse channels, and 4 cycles after otherwise, counting the one it happens on
+                let delay = if is_odd_cycle(self.master_clock) { 3 } else { 4 };
+                self.frame_counter_reset = Some(self.master_clock + (delay - 1) * CPU_DIVIDER);
+            }
+            _ => warn!("Write to invalid APU address 0x{:04X}", address),
+        }
+    }
+
+    /// Writes the state of the channels and the frame counter to a save state. The analog filters, stereo panning
+    /// and register trace are options of the output rather than state of the APU, so they aren't saved.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        self.pulse_one.save_state(state);
+        self.pulse_two.save_state(state);
+        self.triangle.save_state(state);
+        self.noise.save_state(state);
+        self.dmc.save_state(state);
+        state.write_bool(self.five_step_mode);
+        state.write_bool(self.interrupt_inhibit);
+        state.write_bool(self.frame_interrupt);
+        state.write_bool(self.pending_interrupt_request);
+        state.write_option_u64(self.frame_counter_reset);
+        state.write_u64(self.frame_counter_start);
+        state.write_u64(self.master_clock);
+        state.write_u64(self.cycle_count);
+    }
+
+    /// Restores the state of the channels and the frame counter from a save state
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.pulse_one.load_state(state)?;
+        self.pulse_two.load_state(state)?;
+        self.triangle.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.dmc.load_state(state)?;
+        self.five_step_mode = state.read_bool()?;
+        self.interrupt_inhibit = state.read_bool()?;
+        self.frame_interrupt = state.read_bool()?;
+        self.pending_interrupt_request = state.read_bool()?;
+        self.frame_counter_reset = state.read_option_u64()?;
+        self.frame_counter_start = state.read_u64()?;
+        self.master_clock = state.read_u64()?;
+        self.cycle_count = state.read_u64()?;
+        return Ok(());
+    }
+}
+
+/// Checks if the CPU cycle on a cycle of the master clock is one of the odd cycles that the pulse channels are clocked on
+fn is_odd_cycle(master_clock: u64) -> bool {
+    return !(master_clock / CPU_DIVIDER).is_multiple_of(2);
+}
+
+/// Mixes the outputs of the APU's channels into a single output from 0.0 to 1.0.
+///
+/// The NES mixes the channels with resistors, so each channel's contribution shrinks as the others get louder. This
+/// calculates the formulas that the lookup tables on the NESdev wiki are built from, rather than keeping the tables.
+/// The pulse channels and the other three channels are mixed separately, so the loud DMC only quiets the triangle
+/// and noise channels.
+fn mix(channels: ChannelOutputs) -> f32 {
+    let pulse = f32::from(channels.pulse_one + channels.pulse_two);
+    let tnd = 3.0 * f32::from(channels.triangle) + 2.0 * f32::from(channels.noise) + f32::from(channels.dmc);
+    return mix_groups(pulse, tnd);
+}
+
+/// Mixes the outputs of the APU's channels into left and right outputs, each from 0.0 to 1.0, with each channel's
+/// volume in each output scaled by its pan position before they are mixed like [mix](fn.mix.html) does.
+/// With every channel in the centre, both outputs are the same as the mono mix.
+fn mix_panned(channels: ChannelOutputs, panning: StereoPanning) -> (f32, f32) {
+    let gains = [
+        pan_gains(panning.pulse_one),
+        pan_gains(panning.pulse_two),
+        pan_gains(panning.triangle),
+        pan_gains(panning.noise),
+        pan_gains(panning.dmc),
+    ];
+    let mix_side = |gain: fn(&(f32, f32)) -> f32| {
+        let pulse = f32::from(channels.pulse_one) * gain(&gains[0]) + f32::from(channels.pulse_two) * gain(&gains[1]);
+        let tnd = 3.0 * f32::from(channels.triangle) * gain(&gains[2])
+            + 2.0 * f32::from(channels.noise) * gain(&gains[3])
+            + f32::from(channels.dmc) * gain(&gains[4]);
+        return mix_groups(pulse, tnd);
+    };
+    return (mix_side(|gains| gains.0), mix_side(|gains| gains.1));
+}
+
+/// Mixes the weighted sums of the pulse channels and of the triangle, noise and delta modulation channels
+fn mix_groups(pulse: f32, tnd: f32) -> f32 {
+    let pulse_out = if pulse == 0.0 { 0.0 } else { 95.52 / (8128.0 / pulse + 100.0) };
+    let tnd_out = if tnd == 0.0 { 0.0 } else { 163.67 / (24329.0 / tnd + 100.0) };
+    return pulse_out + tnd_out;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mix() {
+        let silent = ChannelOutputs::default();
+        assert_eq!(0.0, mix(silent));
+        let loudest = ChannelOutputs {
+            pulse_one: 15,
+            pulse_two: 15,
+            triangle: 15,
+            noise: 15,
+            dmc: 127,
+        };
+        assert!((mix(loudest) - 1.0).abs() < 0.001);
+        // The triangle channel is quieter with the DMC's output level raised
+        let triangle = ChannelOutputs { triangle: 15, ..silent };
+        let dmc = ChannelOutputs { dmc: 127, ..silent };
+        let both = ChannelOutputs {
+            triangle: 15,
+            dmc: 127,
+            ..silent
+        };
+        assert!(mix(both) - mix(dmc) < mix(triangle) * 0.75);
+        // But the pulse channels aren't affected
+        let pulse = ChannelOutputs { pulse_one: 15, ..silent };
+        let pulse_and_dmc = ChannelOutputs {
+            pulse_one: 15,
+            dmc: 127,
+            ..silent
+        };
+        assert!((mix(pulse_and_dmc) - mix(dmc) - mix(pulse)).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_mix_panned() {
+        let channels = ChannelOutputs {
+            pulse_one: 15,
+            pulse_two: 7,
+            triangle: 9,
+            noise: 3,
+            dmc: 64,
+        };
+        assert_eq!((mix(channels), mix(channels)), mix_panned(channels, StereoPanning::default()));
+        let hard_panned = StereoPanning {
+            pulse_one: -1.0,
+            pulse_two: 1.0,
+            ..Default::default()
+        };
+        let (left, right) = mix_panned(channels, hard_panned);
+        let tnd = ChannelOutputs {
+            pulse_one: 0,
+            pulse_two: 0,
+            ..channels
+        };
+        assert_eq!(mix(ChannelOutputs { pulse_two: 0, ..channels }), left);
+        assert_eq!(mix(ChannelOutputs { pulse_one: 0, ..channels }), right);
+        assert!(left - mix(tnd) > right - mix(tnd));
+    }
+
+    #[test]
+    fn test_expansion_output() {
+        let mut apu = NesApu::new();
+        apu.cycle(apu.master_clock);
+        let silent = apu.output();
+        apu.set_expansion_output(0.25);
+        apu.cycle(apu.master_clock);
+        assert_eq!(silent + 0.25, apu.output());
+        apu.set_expansion_output(0.0);
+        apu.cycle(apu.master_clock);
+        assert_eq!(silent, apu.output());
+    }
+
+    /// A register write in an APU test script, made on the CPU cycle in the first field
+    type ScriptedWrite = (u64, u16, u8);
+
+    /// Runs the APU for a number of cycles, making the scripted register writes on their cycles.
+    /// Returns the output of the channel selected by the closure, sampled after every cycle.
+    fn run_script<F: Fn(&NesApu) -> u8>(apu: &mut NesApu, script: &[ScriptedWrite], cycles: u64, channel_output: F) -> Vec<u8> {
+        let mut samples = Vec::with_capacity(cycles as usize);
+        for cycle in 0..cycles {
+            for (_, address, data) in script.iter().filter(|(write_cycle, _, _)| *write_cycle == cycle) {
+                apu.write(*address, *data);
+            }
+            apu.cycle(apu.master_clock);
+            samples.push(channel_output(apu));
+        }
+        return samples;
+    }
+
+    /// Checks if the waveform rises from zero at the sample index, which happens once per period for every channel
+    fn is_rising_edge(samples: &[u8], index: usize) -> bool {
+        return samples[index - 1] == 0 && samples[index] > 0;
+    }
+
+    /// Measures the period of a waveform in cycles, as the distance between its last two rising edges
+    fn measure_period(samples: &[u8]) -> Option<usize> {
+        let rising_edges: Vec<usize> = (1..samples.len()).filter(|i| is_rising_edge(samples, *i)).collect();
+        return match rising_edges.as_slice() {
+            [.., second_last, last] => Some(last - second_last),
+            _ => None,
+        };
+    }
+
+    /// Measures the fraction of a single period of a waveform, ending at its last rising edge, that is non-zero
+    fn measure_duty(samples: &[u8]) -> Option<f32> {
+        let period = measure_period(samples)?;
+        let end = (1..samples.len()).rev().find(|i| is_rising_edge(samples, *i))?;
+        let high = samples[end - period..end].iter().filter(|sample| **sample > 0).count();
+        return Some(high as f32 / period as f32);
+    }
+
+    #[test]
+    fn test_pulse_period() {
+        let mut apu = NesApu::new();
+        let script = [(0, 0x4015, 0x01), (0, 0x4000, 0xbf), (0, 0x4002, 0xfd), (0, 0x4003, 0x00)];
+        let samples = run_script(&mut apu, &script, 20_000, |apu| apu.pulse_one.output());
+        // The pulse timer is clocked every other CPU cycle and the sequence has eight steps
+        assert_eq!(Some(16 * (0xfd + 1)), measure_period(&samples));
+    }
+
+    #[test]
+    fn test_pulse_duty_cycles() {
+        for (duty, expected) in [(0x00, 0.125), (0x40, 0.25), (0x80, 0.5), (0xc0, 0.75)].iter() {
+            let mut apu = NesApu::new();
+            let script = [(0, 0x4015, 0x02), (0, 0x4004, 0x3f | duty), (0, 0x4006, 0x40), (0, 0x4007, 0x00)];
+            let samples = run_script(&mut apu, &script, 5_000, |apu| apu.pulse_two.output());
+            assert_eq!(Some(*expected), measure_duty(&samples), "Duty byte 0x{:02X}", duty);
+        }
+    }
+
+    #[test]
+    fn test_pulse_envelope_decay() {
+        let mut apu = NesApu::new();
+        // Envelope with a divider period of one quarter frame and no looping
+        let script = [(0, 0x4015, 0x01), (0, 0x4000, 0x80), (0, 0x4002, 0x40), (0, 0x4003, 0x00)];
+        let volumes = run_script(&mut apu, &script, 29830 * 5, |apu| apu.pulse_one.envelope.output());
+        // The envelope restarts at 15 on the first quarter frame, then decays one step per quarter frame
+        assert_eq!(15, volumes[QUARTER_FRAME_CYCLES[0] as usize]);
+        assert_eq!(14, volumes[QUARTER_FRAME_CYCLES[1] as usize]);
+        assert_eq!(13, volumes[QUARTER_FRAME_CYCLES[2] as usize]);
+        assert_eq!(0, *volumes.last().unwrap());
+    }
+
+    #[test]
+    fn test_pulse_length_counter_silences_channel() {
+        let mut apu = NesApu::new();
+        // Length index 3 loads a length of 2 half frames
+        let script = [(0, 0x4015, 0x01), (0, 0x4000, 0x9f), (0, 0x4002, 0x40), (0, 0x4003, 0x18)];
+        let samples = run_script(&mut apu, &script, 29830 + 100, |apu| apu.pulse_one.output());
+        assert!(samples[..QUARTER_FRAME_CYCLES[1] as usize].iter().any(|sample| *sample > 0));
+        assert!(samples[QUARTER_FRAME_CYCLES[3] as usize + 1..].iter().all(|sample| *sample == 0));
+    }
+
+    #[test]
+    fn test_pulse_sweep_mutes_low_period() {
+        let mut apu = NesApu::new();
+        let script = [(0, 0x4015, 0x01), (0, 0x4000, 0xbf), (0, 0x4002, 0x07), (0, 0x4003, 0x00)];
+        let samples = run_script(&mut apu, &script, 1_000, |apu| apu.pulse_one.output());
+        assert!(samples.iter().all(|sample| *sample == 0));
+    }
+
+    #[test]
+    fn test_pulse_sweep_negate_difference() {
+        let mut apu = NesApu::new();
+        let script = [
+            (0, 0x4015, 0x03),
+            (0, 0x4001, 0x89),
+            (0, 0x4002, 0x00),
+            (0, 0x4003, 0x01),
+            (0, 0x4005, 0x89),
+            (0, 0x4006, 0x00),
+            (0, 0x4007, 0x01),
+        ];
+        run_script(&mut apu, &script, 1, |_| 0);
+        // Pulse one subtracts an extra one from the change
+        assert_eq!(0x100 - 0x80 - 1, apu.pulse_one.sweep_target_period());
+        assert_eq!(0x100 - 0x80, apu.pulse_two.sweep_target_period());
+    }
+
+    #[test]
+    fn test_triangle_period() {
+        let mut apu = NesApu::new();
+        let script = [(0, 0x4015, 0x04), (0, 0x4008, 0xff), (0, 0x400a, 0x40), (0, 0x400b, 0x00)];
+        let samples = run_script(&mut apu, &script, 20_000, |apu| apu.triangle.output());
+        // The triangle timer is clocked every CPU cycle and the sequence has 32 steps
+        assert_eq!(Some(32 * (0x40 + 1)), measure_period(&samples));
+    }
+
+    #[test]
+    fn test_triangle_linear_counter_halts_sequencer() {
+        let mut apu = NesApu::new();
+        // Linear counter of 1 quarter frame with the control flag clear
+        let script = [(0, 0x4015, 0x04), (0, 0x4008, 0x01), (0, 0x400a, 0x10), (0, 0x400b, 0x08)];
+        let samples = run_script(&mut apu, &script, 29830, |apu| apu.triangle.output());
+        let halted = &samples[QUARTER_FRAME_CYCLES[1] as usize..];
+        assert!(halted.iter().all(|sample| *sample == halted[0]));
+    }
+
+    #[test]
+    fn test_noise_shift_register_sequences() {
+        // The normal mode sequence repeats every 32767 shifts, the short mode sequence every 93 shifts
+        for (mode, expected_length) in [(0x00, 32767), (0x80, 93)].iter() {
+            let mut apu = NesApu::new();
+            run_script(&mut apu, &[(0, 0x400e, *mode)], 1, |_| 0);
+            let start = apu.noise.shift_register;
+            let length = (1..=32767).find(|_| {
+                apu.noise.clock_shift_register();
+                apu.noise.shift_register == start
+            });
+            assert_eq!(Some(*expected_length), length, "Mode byte 0x{:02X}", mode);
+        }
+    }
+
+    #[test]
+    fn test_noise_period() {
+        let mut apu = NesApu::new();
+        let script = [(0, 0x4015, 0x08), (0, 0x400c, 0x3f), (0, 0x400e, 0x04), (0, 0x400f, 0x00)];
+        let samples = run_script(&mut apu, &script, 64 * 100, |apu| apu.noise.output());
+        // The output can only change on cycles where the shift register is clocked
+        let changes: Vec<usize> = (1..samples.len()).filter(|i| samples[i - 1] != samples[*i]).collect();
+        assert!(!changes.is_empty());
+        assert!(changes.windows(2).all(|pair| (pair[1] - pair[0]) % 64 == 0));
+    }
+
+    #[test]
+    fn test_noise_length_counter_silences_channel() {
+        let mut apu = NesApu::new();
+        // Length index 3 loads a length of 2 half frames
+        let script = [(0, 0x4015, 0x08), (0, 0x400c, 0x1f), (0, 0x400e, 0x00), (0, 0x400f, 0x18)];
+        let samples = run_script(&mut apu, &script, 29830 + 100, |apu| apu.noise.output());
+        assert!(samples[..QUARTER_FRAME_CYCLES[1] as usize].iter().any(|sample| *sample > 0));
+        assert!(samples[QUARTER_FRAME_CYCLES[3] as usize + 1..].iter().all(|sample| *sample == 0));
+    }
+
+    /// Runs the APU, answering the DMC channel's sample requests immediately with the byte from the closure
+    fn run_dmc<F: Fn(u16) -> u8>(apu: &mut NesApu, cycles: u64, memory: F) -> Vec<u8> {
+        let mut samples = Vec::with_capacity(cycles as usize);
+        for _ in 0..cycles {
+            apu.cycle(apu.master_clock);
+            if let Some(address) = apu.get_dmc_sample_request() {
+                apu.load_dmc_sample(memory(address));
+            }
+            samples.push(apu.dmc.output());
+        }
+        return samples;
+    }
+
+    #[test]
+    fn test_dmc_output_follows_sample_bits() {
+        let mut apu = NesApu::new();
+        // The fastest rate, starting from the middle of the output range with a one byte sample
+        run_script(
+            &mut apu,
+            &[
+                (0, 0x4010, 0x0f),
+                (0, 0x4011, 0x40),
+                (0, 0x4012, 0x00),
+                (0, 0x4013, 0x00),
+                (0, 0x4015, 0x10),
+            ],
+            1,
+            |_| 0,
+        );
+        // The first byte is fetched straight away, but only starts playing once the silent byte before it has finished
+        let samples = run_dmc(&mut apu, 54 * 17, |_| 0b0000_1111);
+        assert_eq!(0x40, samples[54 * 8 - 2]);
+        assert_eq!(0x40 + 8, samples[54 * 11 - 1]);
+        assert_eq!(0x40, samples[54 * 15 - 1]);
+        assert_eq!(0x40, *samples.last().unwrap());
+    }
+
+    #[test]
+    fn test_dmc_sample_addresses() {
+        let mut apu = NesApu::new();
+        // A 65 byte sample at $FFC0 runs off the end of memory and wraps around to $8000
+        run_script(
+            &mut apu,
+            &[(0, 0x4010, 0x0f), (0, 0x4012, 0xff), (0, 0x4013, 0x04), (0, 0x4015, 0x10)],
+            1,
+            |_| 0,
+        );
+        let addresses = std::cell::RefCell::new(Vec::new());
+        run_dmc(&mut apu, 54 * 8 * 70, |address| {
+            addresses.borrow_mut().push(address);
+            0
+        });
+        let expected: Vec<u16> = (0xffc0..=0xffff).chain(0x8000..=0x8000).collect();
+        assert_eq!(expected, *addresses.borrow());
+    }
+
+    #[test]
+    fn test_dmc_interrupt() {
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &[(0, 0x4010, 0x8f), (0, 0x4013, 0x00), (0, 0x4015, 0x10)], 1, |_| 0);
+        run_dmc(&mut apu, 1, |_| 0);
+        // The interrupt is raised as soon as the last byte has been fetched
+        assert!(apu.dmc.interrupt);
+        assert!(apu.get_pending_interrupt_request());
+        // Writing to the status register acknowledges it
+        apu.write(0x4015, 0x00);
+        assert!(!apu.dmc.interrupt);
+
+        // Looping samples never raise an interrupt
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &[(0, 0x4010, 0xcf), (0, 0x4013, 0x00), (0, 0x4015, 0x10)], 1, |_| 0);
+        let fetches = std::cell::Cell::new(0);
+        run_dmc(&mut apu, 54 * 8 * 4, |_| {
+            fetches.set(fetches.get() + 1);
+            0
+        });
+        assert!(!apu.dmc.interrupt);
+        assert!(fetches.get() >= 4);
+    }
+
+    #[test]
+    fn test_length_counter_halt_timing() {
+        // The first half frame happens on the APU cycle after the writes made before script cycle 14912
+        let half_frame = QUARTER_FRAME_CYCLES[1] - 1;
+        // A halted length counter of 2 is resumed on the same cycle as the first half frame, which still sees it halted
+        let script = [(0, 0x4015, 0x01), (0, 0x4000, 0x30), (0, 0x4003, 0x18), (half_frame, 0x4000, 0x10)];
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &script, FOUR_STEP_SEQUENCE_END, |_| 0);
+        assert!(!apu.pulse_one.length_counter.is_silenced());
+        run_script(&mut apu, &[], QUARTER_FRAME_CYCLES[1], |_| 0);
+        assert!(apu.pulse_one.length_counter.is_silenced());
+    }
+
+    #[test]
+    fn test_length_counter_reload_timing() {
+        let half_frame = QUARTER_FRAME_CYCLES[1] - 1;
+        // Reloading a length counter of 2 on the same cycle as a half frame is ignored
+        let script = [(0, 0x4015, 0x01), (0, 0x4003, 0x18), (half_frame, 0x4003, 0xf8)];
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &script, FOUR_STEP_SEQUENCE_END, |_| 0);
+        assert!(apu.pulse_one.length_counter.is_silenced());
+        // But reloading one that has already reached zero isn't
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &[script[0], script[2]], FOUR_STEP_SEQUENCE_END, |_| 0);
+        assert!(!apu.pulse_one.length_counter.is_silenced());
+    }
+
+    #[test]
+    fn test_status_length_counters() {
+        let mut apu = NesApu::new();
+        // Load the length counters of the pulse and noise channels, but not the triangle channel
+        let script = [(0, 0x4015, 0x0f), (0, 0x4003, 0x08), (0, 0x4007, 0x08), (0, 0x400f, 0x08)];
+        run_script(&mut apu, &script, 1, |_| 0);
+        assert_eq!(0x0b, apu.read(0x4015));
+        // Disabling a channel clears its length counter
+        apu.write(0x4015, 0x0e);
+        assert_eq!(0x0a, apu.read(0x4015));
+    }
+
+    #[test]
+    fn test_status_interrupt_flags() {
+        let mut apu = NesApu::new();
+        let script = [(0, 0x4010, 0x8f), (0, 0x4013, 0x01), (0, 0x4015, 0x10)];
+        run_script(&mut apu, &script, 1, |_| 0);
+        // The DMC is active until the last of its 17 bytes has been fetched
+        assert_eq!(0x10, apu.read(0x4015));
+        run_dmc(&mut apu, FOUR_STEP_SEQUENCE_END, |_| 0);
+        assert_eq!(0xc0, apu.read(0x4015));
+        // Reading acknowledges the frame interrupt but not the DMC interrupt
+        assert_eq!(0x80, apu.read(0x4015));
+        apu.write(0x4010, 0x0f);
+        assert_eq!(0x00, apu.read(0x4015));
+    }
+
+    #[test]
+    fn test_frame_interrupt() {
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &[], FOUR_STEP_SEQUENCE_END, |_| 0);
+        assert!(apu.frame_interrupt);
+
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &[(0, 0x4017, 0x40)], FOUR_STEP_SEQUENCE_END, |_| 0);
+        assert!(!apu.frame_interrupt);
+
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &[(0, 0x4017, 0x80)], FIVE_STEP_SEQUENCE_END, |_| 0);
+        assert!(!apu.frame_interrupt);
+    }
+
+    #[test]
+    fn test_frame_interrupt_request() {
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &[], FOUR_STEP_SEQUENCE_END, |_| 0);
+        assert!(apu.get_pending_interrupt_request());
+        // The request is only made once, even though the interrupt flag stays set
+        run_script(&mut apu, &[], FOUR_STEP_SEQUENCE_END, |_| 0);
+        assert!(apu.frame_interrupt);
+        assert!(!apu.get_pending_interrupt_request());
+    }
+
+    #[test]
+    fn test_five_step_mode_write_clocks_immediately() {
+        // A length counter of 2, where the frame counter is switched to five step mode shortly after
+        let script = [(0, 0x4015, 0x01), (0, 0x4000, 0x10), (0, 0x4003, 0x18), (100, 0x4017, 0x80)];
+        let cycles = 100 + 4 + QUARTER_FRAME_CYCLES[1] + 1;
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &script, cycles, |_| 0);
+        // The write and the first half frame of the new sequence should both have clocked the length counter
+        assert!(apu.pulse_one.length_counter.is_silenced());
+
+        let mut apu = NesApu::new();
+        run_script(&mut apu, &[script[0], script[1], script[2], (100, 0x4017, 0x00)], cycles, |_| 0);
+        assert!(!apu.pulse_one.length_counter.is_silenced());
+    }
+}
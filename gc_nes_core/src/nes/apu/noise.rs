@@ -0,0 +1,118 @@
+//! The noise module contains the APU's pseudo-random noise channel.
+
+use super::units::{Envelope, LengthCounter};
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
+
+/// The NTSC timer periods of the noise channel in CPU cycles, indexed by the bottom four bits of $400E
+const NOISE_PERIOD_TABLE: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+
+/// Structure representing the APU's noise channel, which is controlled through registers $400C-$400F
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct NoiseChannel {
+    /// The 15 bit linear feedback shift register that produces the channel's pseudo-random sequence
+    pub(super) shift_register: u16,
+    /// Whether the shift register takes its feedback from bit 6 instead of bit 1, producing a short, more metallic sequence
+    short_mode: bool,
+    /// The period of the channel's timer in CPU cycles, from the noise period table
+    timer_period: u16,
+    /// The current value of the channel's timer, counting down to the next shift of the shift register
+    timer: u16,
+    /// The envelope generator that controls the volume of the channel
+    pub(super) envelope: Envelope,
+    /// The length counter that silences the channel after a number of half frames
+    pub(super) length_counter: LengthCounter,
+}
+
+impl NoiseChannel {
+    /// Creates a new noise channel, with the shift register loaded with 1 as it is on power up
+    pub(super) fn new() -> Self {
+        NoiseChannel {
+            shift_register: 1,
+            short_mode: false,
+            timer_period: NOISE_PERIOD_TABLE[0],
+            timer: 0,
+            envelope: Default::default(),
+            length_counter: Default::default(),
+        }
+    }
+
+    /// Writes to one of the channel's four registers, selected by the bottom two bits of the address
+    pub(super) fn write(&mut self, address: u16, data: u8) {
+        match address & 0x03 {
+            0x00 => {
+                self.length_counter.set_halted(data & 0x20 > 0);
+                self.envelope.write_control(data);
+            }
+            0x01 => {} // Unused
+            0x02 => {
+                self.short_mode = data & 0x80 > 0;
+                self.timer_period = NOISE_PERIOD_TABLE[(data & 0x0f) as usize];
+            }
+            0x03 => {
+                self.length_counter.load(data);
+                self.envelope.restart();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocks the channel's timer, which happens every CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            // The period table is in CPU cycles, including the cycle on which the timer reloads
+            self.timer = self.timer_period - 1;
+            self.clock_shift_register();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Shifts the shift register right by one, feeding the exclusive or of bit 0 and either bit 1 or bit 6 into bit 14
+    pub(super) fn clock_shift_register(&mut self) {
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x01;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    /// Clocks the channel's units that are driven by quarter frames
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocks the channel's units that are driven by half frames
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// Gets the current output of the channel, from 0 to 15. The channel is
+    /// silent whenever bit 0 of the shift register is set.
+    pub(super) fn output(&self) -> u8 {
+        return if self.length_counter.is_silenced() || self.shift_register & 0x01 > 0 {
+            0
+        } else {
+            self.envelope.output()
+        };
+    }
+
+    /// Writes the state of the channel to a save state
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.shift_register);
+        state.write_bool(self.short_mode);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        self.envelope.save_state(state);
+        self.length_counter.save_state(state);
+    }
+
+    /// Restores the state of the channel from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.shift_register = state.read_u16()?;
+        self.short_mode = state.read_bool()?;
+        // The timer reloads with one less than the period, so the period can't be zero
+        self.timer_period = state.read_u16()?.max(1);
+        self.timer = state.read_u16()?;
+        self.envelope.load_state(state)?;
+        return self.length_counter.load_state(state);
+    }
+}
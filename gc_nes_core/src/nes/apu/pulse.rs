@@ -0,0 +1,183 @@
+//! The pulse module contains the APU's two pulse (square wave) channels.
+
+use super::units::{Envelope, LengthCounter};
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
+
+/// The waveforms of the four pulse duty cycles (12.5%, 25%, 50%, and 25% negated)
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Structure representing one of the APU's pulse channels, which are controlled
+/// through registers $4000-$4003 and $4004-$4007.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PulseChannel {
+    /// Whether this is the first pulse channel, which negates its sweep differently from the second
+    is_pulse_one: bool,
+    /// The selected duty cycle, used to index the duty table
+    duty: u8,
+    /// The position of the channel in its eight step duty sequence
+    sequence_position: u8,
+    /// The 11 bit period of the channel's timer
+    timer_period: u16,
+    /// The current value of the channel's timer, counting down to the next sequence step
+    timer: u16,
+    /// Whether the sweep unit is enabled
+    sweep_enabled: bool,
+    /// The number of half frames between sweep unit period adjustments, minus one
+    sweep_period: u8,
+    /// Whether the sweep unit decreases the timer period instead of increasing it
+    sweep_negate: bool,
+    /// The shift count of the sweep unit, the period is adjusted by the period shifted right by this amount
+    sweep_shift: u8,
+    /// The sweep unit's divider, counting down the half frames until the next adjustment
+    sweep_divider: u8,
+    /// Set when the sweep register is written, reloading the divider on the next half frame
+    sweep_reload: bool,
+    /// The envelope generator that controls the volume of the channel
+    pub(super) envelope: Envelope,
+    /// The length counter that silences the channel after a number of half frames
+    pub(super) length_counter: LengthCounter,
+}
+
+impl PulseChannel {
+    /// Creates a new pulse channel. The two pulse channels differ in how their sweep units negate.
+    pub(super) fn new(is_pulse_one: bool) -> Self {
+        PulseChannel {
+            is_pulse_one,
+            duty: 0,
+            sequence_position: 0,
+            timer_period: 0,
+            timer: 0,
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+            envelope: Default::default(),
+            length_counter: Default::default(),
+        }
+    }
+
+    /// Writes to one of the channel's four registers, selected by the bottom two bits of the address
+    pub(super) fn write(&mut self, address: u16, data: u8) {
+        match address & 0x03 {
+            0x00 => {
+                self.duty = data >> 6;
+                self.length_counter.set_halted(data & 0x20 > 0);
+                self.envelope.write_control(data);
+            }
+            0x01 => {
+                self.sweep_enabled = data & 0x80 > 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = data & 0x08 > 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            0x02 => self.timer_period = (self.timer_period & 0x0700) | u16::from(data),
+            0x03 => {
+                self.timer_period = (self.timer_period & 0x00ff) | (u16::from(data & 0x07) << 8);
+                self.length_counter.load(data);
+                // Writing the length counter restarts the sequencer and the envelope
+                self.sequence_position = 0;
+                self.envelope.restart();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocks the channel's timer, which happens every other CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_position = (self.sequence_position + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocks the channel's units that are driven by quarter frames
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocks the channel's units that are driven by half frames
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.is_sweep_muting() {
+            self.timer_period = self.sweep_target_period();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    /// Calculates the period that the sweep unit is moving the timer period towards
+    pub(super) fn sweep_target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        return if self.sweep_negate {
+            // The first pulse channel negates with one's complement, subtracting an extra one
+            self.timer_period.saturating_sub(change + self.is_pulse_one as u16)
+        } else {
+            self.timer_period + change
+        };
+    }
+
+    /// Checks if the sweep unit is muting the channel, which happens when the period is too
+    /// small or the target period overflows, even if the sweep unit is disabled.
+    fn is_sweep_muting(&self) -> bool {
+        return self.timer_period < 8 || self.sweep_target_period() > 0x07ff;
+    }
+
+    /// Gets the current output of the channel, from 0 to 15
+    pub(super) fn output(&self) -> u8 {
+        return if self.length_counter.is_silenced() || self.is_sweep_muting() || DUTY_TABLE[self.duty as usize][self.sequence_position as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        };
+    }
+
+    /// Writes the state of the channel to a save state
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.duty);
+        state.write_u8(self.sequence_position);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        state.write_bool(self.sweep_enabled);
+        state.write_u8(self.sweep_period);
+        state.write_bool(self.sweep_negate);
+        state.write_u8(self.sweep_shift);
+        state.write_u8(self.sweep_divider);
+        state.write_bool(self.sweep_reload);
+        self.envelope.save_state(state);
+        self.length_counter.save_state(state);
+    }
+
+    /// Restores the state of the channel from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        // The duty and sequence position index the duty table, so they are kept in range
+        self.duty = state.read_u8()? & 0x03;
+        self.sequence_position = state.read_u8()? & 0x07;
+        self.timer_period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.sweep_enabled = state.read_bool()?;
+        self.sweep_period = state.read_u8()?;
+        self.sweep_negate = state.read_bool()?;
+        self.sweep_shift = state.read_u8()? & 0x07;
+        self.sweep_divider = state.read_u8()?;
+        self.sweep_reload = state.read_bool()?;
+        self.envelope.load_state(state)?;
+        return self.length_counter.load_state(state);
+    }
+}
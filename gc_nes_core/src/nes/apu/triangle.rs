@@ -0,0 +1,114 @@
+//! The triangle module contains the APU's triangle wave channel.
+
+use super::units::LengthCounter;
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
+
+/// The 32 step sequence of volumes that the triangle channel outputs
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// Structure representing the APU's triangle channel, which is controlled through registers $4008-$400B
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(super) struct TriangleChannel {
+    /// The position of the channel in its 32 step sequence
+    sequence_position: u8,
+    /// The 11 bit period of the channel's timer
+    timer_period: u16,
+    /// The current value of the channel's timer, counting down to the next sequence step
+    timer: u16,
+    /// Whether the linear counter reload flag is cleared on each quarter frame, doubles as the length counter halt flag
+    control: bool,
+    /// The value that the linear counter is reloaded with
+    linear_counter_reload: u8,
+    /// The linear counter, a finer grained alternative to the length counter
+    linear_counter: u8,
+    /// Set when the length counter is loaded, reloading the linear counter on the next quarter frame
+    linear_counter_reload_flag: bool,
+    /// The length counter that silences the channel after a number of half frames
+    pub(super) length_counter: LengthCounter,
+}
+
+impl TriangleChannel {
+    /// Writes to one of the channel's four registers, selected by the bottom two bits of the address
+    pub(super) fn write(&mut self, address: u16, data: u8) {
+        match address & 0x03 {
+            0x00 => {
+                self.control = data & 0x80 > 0;
+                self.length_counter.set_halted(self.control);
+                self.linear_counter_reload = data & 0x7f;
+            }
+            0x01 => {} // Unused
+            0x02 => self.timer_period = (self.timer_period & 0x0700) | u16::from(data),
+            0x03 => {
+                self.timer_period = (self.timer_period & 0x00ff) | (u16::from(data & 0x07) << 8);
+                self.length_counter.load(data);
+                self.linear_counter_reload_flag = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocks the channel's timer, which happens every CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            // The sequencer only advances while both counters are non-zero
+            if self.linear_counter > 0 && !self.length_counter.is_silenced() {
+                self.sequence_position = (self.sequence_position + 1) & 0x1f;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocks the channel's units that are driven by quarter frames
+    pub(super) fn clock_quarter_frame(&mut self) {
+        if self.linear_counter_reload_flag {
+            self.linear_counter = self.linear_counter_reload;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_counter_reload_flag = false;
+        }
+    }
+
+    /// Clocks the channel's units that are driven by half frames
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// Gets the current output of the channel, from 0 to 15. Silencing the triangle channel
+    /// stops its sequencer rather than dropping the output to zero, so this is always the
+    /// current step of the sequence.
+    pub(super) fn output(&self) -> u8 {
+        return TRIANGLE_SEQUENCE[self.sequence_position as usize];
+    }
+
+    /// Writes the state of the channel to a save state
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.sequence_position);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        state.write_bool(self.control);
+        state.write_u8(self.linear_counter_reload);
+        state.write_u8(self.linear_counter);
+        state.write_bool(self.linear_counter_reload_flag);
+        self.length_counter.save_state(state);
+    }
+
+    /// Restores the state of the channel from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        // The sequence position indexes the sequence, so it is kept in range
+        self.sequence_position = state.read_u8()? & 0x1f;
+        self.timer_period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.control = state.read_bool()?;
+        self.linear_counter_reload = state.read_u8()?;
+        self.linear_counter = state.read_u8()?;
+        self.linear_counter_reload_flag = state.read_bool()?;
+        return self.length_counter.load_state(state);
+    }
+}
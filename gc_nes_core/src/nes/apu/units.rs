@@ -0,0 +1,171 @@
+//! The units module contains the components that are shared between
+//! multiple APU channels, like the envelope generator and the length counter.
+
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
+
+/// Table of the values loaded into a length counter, indexed by the top five bits
+/// of the byte written to a channel's length counter load register.
+const LENGTH_TABLE: [u8; 0x20] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// The envelope generator controls the volume of the pulse and noise channels, either
+/// holding a constant volume or producing a decreasing saw envelope.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(super) struct Envelope {
+    /// Set when the channel's length counter load register is written, restarting the envelope
+    start: bool,
+    /// Divider that counts down quarter frames between changes in the decay level
+    divider: u8,
+    /// The volume of the envelope when constant volume is disabled
+    decay_level: u8,
+    /// Whether the decay level should loop back to 15 after reaching 0
+    looping: bool,
+    /// Whether the envelope outputs a constant volume instead of the decay level
+    constant_volume: bool,
+    /// The constant volume, which doubles as the reload value for the divider
+    volume: u8,
+}
+
+impl Envelope {
+    /// Sets the envelope parameters from the lower six bits of a channel's control register
+    pub(super) fn write_control(&mut self, data: u8) {
+        self.looping = data & 0x20 > 0;
+        self.constant_volume = data & 0x10 > 0;
+        self.volume = data & 0x0f;
+    }
+
+    /// Restarts the envelope on the next quarter frame
+    pub(super) fn restart(&mut self) {
+        self.start = true;
+    }
+
+    /// Clocks the envelope, which happens every quarter frame
+    pub(super) fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay_level = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay_level > 0 {
+                self.decay_level -= 1;
+            } else if self.looping {
+                self.decay_level = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    /// Gets the current volume of the envelope, from 0 to 15
+    pub(super) fn output(&self) -> u8 {
+        return if self.constant_volume { self.volume } else { self.decay_level };
+    }
+
+    /// Writes the state of the envelope to a save state
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.start);
+        state.write_u8(self.divider);
+        state.write_u8(self.decay_level);
+        state.write_bool(self.looping);
+        state.write_bool(self.constant_volume);
+        state.write_u8(self.volume);
+    }
+
+    /// Restores the state of the envelope from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.start = state.read_bool()?;
+        self.divider = state.read_u8()?;
+        self.decay_level = state.read_u8()?;
+        self.looping = state.read_bool()?;
+        self.constant_volume = state.read_bool()?;
+        self.volume = state.read_u8()?;
+        return Ok(());
+    }
+}
+
+/// The length counter automatically silences a channel after a set number of half frames
+///
+/// Writes to the halt flag and the counter only take effect at the end of the APU cycle they were made on, so a half
+/// frame on the same cycle sees the old halt flag, and it cancels a reload if it clocks a counter that isn't zero.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(super) struct LengthCounter {
+    /// Whether the channel is enabled through the status register. A disabled length counter is held at zero.
+    enabled: bool,
+    /// Whether the length counter is halted, preventing it from counting down
+    halted: bool,
+    /// The number of half frames remaining before the channel is silenced
+    counter: u8,
+    /// The halt flag written during the current APU cycle, if it has been written
+    pending_halted: Option<bool>,
+    /// The value loaded into the counter during the current APU cycle, if it has been loaded
+    pending_load: Option<u8>,
+}
+
+impl LengthCounter {
+    /// Enables or disables the length counter through the status register
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+            self.pending_load = None;
+        }
+    }
+
+    /// Halts or resumes the length counter at the end of the current APU cycle
+    pub(super) fn set_halted(&mut self, halted: bool) {
+        self.pending_halted = Some(halted);
+    }
+
+    /// Loads the counter from the length table using the top five bits of the data byte at the end of the current APU cycle
+    pub(super) fn load(&mut self, data: u8) {
+        if self.enabled {
+            self.pending_load = Some(LENGTH_TABLE[(data >> 3) as usize]);
+        }
+    }
+
+    /// Clocks the length counter, which happens every half frame
+    pub(super) fn clock(&mut self) {
+        if !self.halted && self.counter > 0 {
+            self.counter -= 1;
+            // A reload on the same cycle as a half frame is ignored unless the counter was already zero
+            self.pending_load = None;
+        }
+    }
+
+    /// Applies the writes made during the APU cycle, which happens at the end of every APU cycle
+    pub(super) fn end_cycle(&mut self) {
+        if let Some(halted) = self.pending_halted.take() {
+            self.halted = halted;
+        }
+        if let Some(counter) = self.pending_load.take() {
+            self.counter = counter;
+        }
+    }
+
+    /// Checks if the length counter has silenced the channel
+    pub(super) fn is_silenced(&self) -> bool {
+        return self.counter == 0;
+    }
+
+    /// Writes the state of the length counter to a save state
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_bool(self.halted);
+        state.write_u8(self.counter);
+        state.write_option_u8(self.pending_halted.map(u8::from));
+        state.write_option_u8(self.pending_load);
+    }
+
+    /// Restores the state of the length counter from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.enabled = state.read_bool()?;
+        self.halted = state.read_bool()?;
+        self.counter = state.read_u8()?;
+        self.pending_halted = state.read_option_u8()?.map(|halted| halted != 0);
+        self.pending_load = state.read_option_u8()?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,174 @@
+//! The cpu_state module saves and restores the state of the CPU. The 6502 emulation doesn't expose the cycles left
+//! in the instruction it is running or the interrupts it has pending, so they are worked out by running copies of it
+//! against a probe bus that stands in for the NES' own.
+
+use super::emulator_6502::{Interface6502, MOS6502};
+use crate::state::{StateReader, StateWriter};
+use crate::trace::InterruptKind;
+use std::error::Error;
+
+/// The address of the vector that the CPU reads when it takes a non-maskable interrupt
+const NMI_VECTOR: u16 = 0xfffa;
+/// The address of the vector that the CPU reads when it takes an interrupt request
+const IRQ_VECTOR: u16 = 0xfffe;
+/// The interrupt disable flag of the status register
+const INTERRUPT_DISABLE: u8 = 0x04;
+/// The most cycles that the CPU can have left, which it has straight after being reset
+const MAX_REMAINING_CYCLES: u8 = 8;
+/// The opcode of NOP, which the probe bus returns for every read so that the CPU never does anything
+const NOP_OPCODE: u8 = 0xea;
+
+/// A bus that only watches the CPU take interrupts, reading NOPs everywhere and ignoring writes
+#[derive(Default)]
+struct ProbeBus {
+    /// Whether the CPU has written to the bus, which it does to push the program counter before taking an interrupt
+    pushed: bool,
+    /// The interrupt whose vector the CPU read, if it took one
+    interrupt: Option<InterruptKind>,
+}
+
+impl Interface6502 for ProbeBus {
+    fn read(&mut self, address: u16) -> u8 {
+        // Only vector reads after the push count, since the program counter can be at a vector too
+        if self.pushed {
+            match address {
+                NMI_VECTOR => self.interrupt = Some(InterruptKind::Nmi),
+                IRQ_VECTOR => self.interrupt = Some(InterruptKind::Irq),
+                _ => {}
+            }
+        }
+        return NOP_OPCODE;
+    }
+
+    fn write(&mut self, _address: u16, _data: u8) {
+        self.pushed = true;
+    }
+}
+
+/// Gets the interrupt that the CPU will take once its current instruction completes, if one is pending. The CPU takes a
+/// pending NMI ahead of an interrupt request, forgetting the request, so only the NMI needs to be known when both are.
+/// A pending interrupt request is found even while interrupts are disabled, when the CPU holds it until they aren't.
+fn get_pending_interrupt(cpu: &MOS6502) -> Option<InterruptKind> {
+    let mut probe = cpu.clone();
+    let mut bus = ProbeBus::default();
+    // The rest of the instruction doesn't touch the bus, since the whole instruction ran on its first cycle
+    while probe.get_remaining_cycles() > 0 {
+        probe.cycle(&mut bus);
+    }
+    probe.set_status_register(probe.get_status_register() & !INTERRUPT_DISABLE);
+    probe.cycle(&mut bus);
+    return bus.interrupt;
+}
+
+/// Writes the registers of the CPU, the cycles left in its current instruction and its pending interrupt to a save state
+pub(super) fn save_cpu_state(cpu: &MOS6502, state: &mut StateWriter) {
+    state.write_u8(cpu.get_accumulator());
+    state.write_u8(cpu.get_x_register());
+    state.write_u8(cpu.get_y_register());
+    state.write_u16(cpu.get_program_counter());
+    state.write_u8(cpu.get_stack_pointer());
+    state.write_u8(cpu.get_status_register());
+    state.write_u8(cpu.get_remaining_cycles());
+    state.write_u8(match get_pending_interrupt(cpu) {
+        None => 0,
+        Some(InterruptKind::Irq) => 1,
+        Some(InterruptKind::Nmi) => 2,
+    });
+}
+
+/// Creates a CPU in the state written by save_cpu_state. Only the total number of cycles that the CPU has run, which
+/// it only uses for its own logging, isn't restored.
+pub(super) fn load_cpu_state(state: &mut StateReader) -> Result<MOS6502, Box<dyn Error>> {
+    let accumulator = state.read_u8()?;
+    let x_register = state.read_u8()?;
+    let y_register = state.read_u8()?;
+    let program_counter = state.read_u16()?;
+    let stack_pointer = state.read_u8()?;
+    let status_register = state.read_u8()?;
+    let remaining_cycles = state.read_u8()?;
+    if remaining_cycles > MAX_REMAINING_CYCLES {
+        bail!("Invalid CPU cycles remaining in save state: {}", remaining_cycles);
+    }
+    let pending_interrupt = match state.read_u8()? {
+        0 => None,
+        1 => Some(InterruptKind::Irq),
+        2 => Some(InterruptKind::Nmi),
+        interrupt => bail!("Invalid pending interrupt in save state: {}", interrupt),
+    };
+
+    // Resetting leaves the CPU with the most cycles left, and every cycle after that only counts one of them down
+    let mut cpu = MOS6502::new();
+    let mut bus = ProbeBus::default();
+    cpu.reset(&mut bus);
+    (remaining_cycles..MAX_REMAINING_CYCLES).for_each(|_| cpu.cycle(&mut bus));
+    cpu.set_accumulator(accumulator);
+    cpu.set_x_register(x_register);
+    cpu.set_y_register(y_register);
+    cpu.set_program_counter(program_counter);
+    cpu.set_stack_pointer(stack_pointer);
+    cpu.set_status_register(status_register);
+    match pending_interrupt {
+        None => {}
+        Some(InterruptKind::Irq) => cpu.interrupt_request(),
+        Some(InterruptKind::Nmi) => cpu.non_maskable_interrupt_request(),
+    }
+    return Ok(cpu);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Saves the state of a CPU and loads it back into a new one
+    fn round_trip(cpu: &MOS6502) -> MOS6502 {
+        let mut state = StateWriter::new();
+        save_cpu_state(cpu, &mut state);
+        let bytes = state.into_bytes();
+        let mut reader = StateReader::new(&bytes);
+        let loaded = load_cpu_state(&mut reader).unwrap();
+        reader.finish().unwrap();
+        return loaded;
+    }
+
+    #[test]
+    fn test_cpu_state_round_trip() {
+        let mut cpu = MOS6502::new_start(0x8000);
+        let mut bus = ProbeBus::default();
+        // Partway through a NOP, with interrupts disabled and an interrupt request waiting for them to be enabled
+        cpu.cycle(&mut bus);
+        cpu.set_accumulator(0x12);
+        cpu.set_x_register(0x34);
+        cpu.set_y_register(0x56);
+        cpu.set_stack_pointer(0xf0);
+        cpu.set_status_register(0x24 | INTERRUPT_DISABLE);
+        cpu.interrupt_request();
+        assert_eq!(Some(InterruptKind::Irq), get_pending_interrupt(&cpu));
+
+        let loaded = round_trip(&cpu);
+        assert_eq!(cpu.get_accumulator(), loaded.get_accumulator());
+        assert_eq!(cpu.get_x_register(), loaded.get_x_register());
+        assert_eq!(cpu.get_y_register(), loaded.get_y_register());
+        assert_eq!(cpu.get_program_counter(), loaded.get_program_counter());
+        assert_eq!(cpu.get_stack_pointer(), loaded.get_stack_pointer());
+        assert_eq!(cpu.get_status_register(), loaded.get_status_register());
+        assert_eq!(1, loaded.get_remaining_cycles());
+        assert_eq!(Some(InterruptKind::Irq), get_pending_interrupt(&loaded));
+
+        // An NMI is taken ahead of the interrupt request
+        cpu.non_maskable_interrupt_request();
+        assert_eq!(Some(InterruptKind::Nmi), get_pending_interrupt(&round_trip(&cpu)));
+    }
+
+    #[test]
+    fn test_cpu_state_without_interrupt() {
+        // A NOP at the NMI vector isn't mistaken for the CPU taking an NMI
+        let cpu = MOS6502::new_start(NMI_VECTOR);
+        assert_eq!(None, get_pending_interrupt(&cpu));
+        let mut bus = ProbeBus::default();
+        let mut reset = MOS6502::new();
+        reset.reset(&mut bus);
+        let loaded = round_trip(&reset);
+        assert_eq!(MAX_REMAINING_CYCLES, loaded.get_remaining_cycles());
+        assert_eq!(None, get_pending_interrupt(&loaded));
+    }
+}
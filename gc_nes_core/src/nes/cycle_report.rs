@@ -0,0 +1,142 @@
+//! The cycle report module accounts for where the CPU's cycles went during each frame, so that homebrew developers
+//! can profile whether their NMI handler fits in vertical blank, which lasts about 2273 CPU cycles on an NTSC NES.
+
+use crate::trace::InterruptKind;
+
+/// How the CPU cycles of a single frame were spent
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FrameCycleReport {
+    /// The number of the frame, counting from zero when the NES was created
+    pub frame: u64,
+    /// The number of CPU cycles in the frame, including those that DMA stalled the CPU for
+    pub cpu_cycles: u64,
+    /// The number of CPU cycles spent taking NMIs and running their handlers, up to and including the RTI
+    pub nmi_handler_cycles: u64,
+    /// The number of CPU cycles spent taking IRQs, including BRK instructions, and running their handlers, up to and
+    /// including the RTI. Cycles of an IRQ handler interrupted by an NMI count towards the NMI handler instead.
+    pub irq_handler_cycles: u64,
+    /// The number of NMIs taken during the frame
+    pub nmi_count: u32,
+    /// The number of IRQs taken during the frame, including BRK instructions
+    pub irq_count: u32,
+    /// The number of CPU cycles that OAM DMA stalled the CPU for
+    pub oam_dma_cycles: u64,
+    /// The number of CPU cycles that the DMC's sample fetches stalled the CPU for
+    pub dmc_dma_cycles: u64,
+}
+
+/// Tracks the interrupt handlers that the CPU is running and counts the cycles of the frame in progress
+#[derive(Debug, Default, Clone)]
+pub(super) struct CycleAccounting {
+    /// The report for the frame in progress
+    current: FrameCycleReport,
+    /// The report for the last frame that was completed, if there has been one
+    last: Option<FrameCycleReport>,
+    /// The interrupt handlers that the CPU is running, innermost last, with the stack pointer from before the
+    /// interrupt was taken. The handler has returned once the stack pointer is back at or above it.
+    handlers: Vec<(InterruptKind, u8)>,
+}
+
+impl CycleAccounting {
+    /// Called when the CPU is about to start an instruction, to notice when interrupt handlers have returned
+    pub(super) fn start_instruction(&mut self, stack_pointer: u8) {
+        while self
+            .handlers
+            .last()
+            .is_some_and(|(_, return_stack_pointer)| stack_pointer >= *return_stack_pointer)
+        {
+            self.handlers.pop();
+        }
+    }
+
+    /// Called when the CPU takes an interrupt, with the stack pointer from before it pushed the return address
+    pub(super) fn interrupt_taken(&mut self, kind: InterruptKind, stack_pointer: u8) {
+        match kind {
+            InterruptKind::Nmi => self.current.nmi_count += 1,
+            InterruptKind::Irq => self.current.irq_count += 1,
+        }
+        self.handlers.push((kind, stack_pointer));
+    }
+
+    /// Counts a cycle that the CPU ran for, towards the innermost interrupt handler if it is running one
+    pub(super) fn cpu_cycle(&mut self) {
+        self.current.cpu_cycles += 1;
+        match self.handlers.last() {
+            Some((InterruptKind::Nmi, _)) => self.current.nmi_handler_cycles += 1,
+            Some((InterruptKind::Irq, _)) => self.current.irq_handler_cycles += 1,
+            None => {}
+        }
+    }
+
+    /// Counts a cycle that OAM DMA stalled the CPU for
+    pub(super) fn oam_dma_cycle(&mut self) {
+        self.current.cpu_cycles += 1;
+        self.current.oam_dma_cycles += 1;
+    }
+
+    /// Counts a cycle that a DMC sample fetch stalled the CPU for
+    pub(super) fn dmc_dma_cycle(&mut self) {
+        self.current.cpu_cycles += 1;
+        self.current.dmc_dma_cycles += 1;
+    }
+
+    /// Completes the report for the frame in progress, which has the number given, and starts the report for the next one
+    pub(super) fn end_frame(&mut self, frame: u64) {
+        self.current.frame = frame;
+        self.last = Some(std::mem::take(&mut self.current));
+    }
+
+    /// Forgets the interrupt handlers that were running when the console was reset
+    pub(super) fn reset(&mut self) {
+        self.handlers.clear();
+    }
+
+    /// Gets the report for the last frame that was completed, or None if no frames have been completed
+    pub(super) fn get_last_report(&self) -> Option<FrameCycleReport> {
+        return self.last;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cycle_accounting() {
+        let mut accounting = CycleAccounting::default();
+        accounting.start_instruction(0xfd);
+        accounting.cpu_cycle();
+        // An IRQ handler is interrupted by an NMI, which returns before the IRQ handler does
+        accounting.interrupt_taken(InterruptKind::Irq, 0xfd);
+        (0..7).for_each(|_| accounting.cpu_cycle());
+        accounting.start_instruction(0xfa);
+        accounting.interrupt_taken(InterruptKind::Nmi, 0xfa);
+        (0..8).for_each(|_| accounting.cpu_cycle());
+        accounting.start_instruction(0xf7);
+        (0..6).for_each(|_| accounting.cpu_cycle());
+        accounting.oam_dma_cycle();
+        accounting.start_instruction(0xfa);
+        (0..6).for_each(|_| accounting.cpu_cycle());
+        accounting.start_instruction(0xfd);
+        accounting.cpu_cycle();
+        accounting.dmc_dma_cycle();
+        assert_eq!(None, accounting.get_last_report());
+        accounting.end_frame(0);
+        assert_eq!(
+            Some(FrameCycleReport {
+                frame: 0,
+                cpu_cycles: 31,
+                nmi_handler_cycles: 14,
+                irq_handler_cycles: 13,
+                nmi_count: 1,
+                irq_count: 1,
+                oam_dma_cycles: 1,
+                dmc_dma_cycles: 1,
+            }),
+            accounting.get_last_report()
+        );
+        accounting.end_frame(1);
+        assert_eq!(1, accounting.get_last_report().unwrap().frame);
+        assert_eq!(0, accounting.get_last_report().unwrap().cpu_cycles);
+    }
+}
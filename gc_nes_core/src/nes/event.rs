@@ -0,0 +1,47 @@
+//! The event module contains the events that the NES can report to tools observing it, such as debuggers.
+
+/// Something that happened inside the NES, along with the cycle of the [master clock](../nes/struct.Nes.html#method.master_clock_cycles) it happened on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NesEvent {
+    /// OAM DMA began copying from a page of the CPU's address space, after waiting for the CPU to be suspended
+    DmaStarted {
+        /// The page that is being copied, so that DMA from cartridge space can be told apart from DMA from RAM
+        page: u8,
+        /// The master clock cycle that the DMA started on
+        cycle: u64,
+    },
+    /// OAM DMA finished copying all 256 bytes of a page to OAM
+    DmaFinished {
+        /// The page that was copied
+        page: u8,
+        /// The master clock cycle that the DMA finished on
+        cycle: u64,
+    },
+    /// The CPU read or wrote $2007 while the PPU was rendering, which corrupts the scroll position instead of
+    /// moving the vram address along. Some games do this deliberately, but it is usually a timing bug.
+    VramAccessDuringRendering {
+        /// The scanline the PPU was on
+        scanline: u16,
+        /// The dot of the scanline the PPU was on
+        dot: u16,
+        /// Whether the access was a write rather than a read
+        write: bool,
+        /// The master clock cycle that the access was made on
+        cycle: u64,
+    },
+    /// The battery backed program RAM changed for the first time since the frontend last marked it as flushed,
+    /// so the save data needs to be written out again
+    SaveDataDirty {
+        /// The master clock cycle that the change was made on
+        cycle: u64,
+    },
+    /// The game appears to have hung, having gone the number of frames set with
+    /// [Nes::set_hang_detection](../nes/struct.Nes.html#method.set_hang_detection) without reading PPUSTATUS,
+    /// latching the controllers or changing RAM. Recorded once, until the game shows a sign of running again.
+    GameHung {
+        /// The number of consecutive frames the game went without any sign of running
+        idle_frames: u32,
+        /// The master clock cycle of the end of the frame that the game became hung on
+        cycle: u64,
+    },
+}
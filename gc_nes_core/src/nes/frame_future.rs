@@ -0,0 +1,109 @@
+//! The frame future module lets the NES be run from async code, for frontends whose GUI framework or wasm executor
+//! drives everything through futures. Running a whole frame at once would hold the executor for the full frame, so the
+//! future instead runs a limited number of cycles each time it's polled and asks to be polled again, letting other tasks
+//! run in between.
+//!
+//! The future only wakes itself, so it doesn't depend on any particular runtime.
+
+use crate::audio::AudioSink;
+use crate::nes::Nes;
+use crate::video::VideoBackend;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The number of cycles that a frame future runs each time it's polled by default, about a ninth of a frame
+pub const DEFAULT_FRAME_CYCLE_BUDGET: u32 = 10_000;
+
+/// A future that runs the NES to the end of the current frame, a limited number of cycles at a time, and resolves to
+/// the screen. Created by [frame_async](struct.Nes.html#method.frame_async).
+#[must_use = "futures do nothing unless they are awaited or polled"]
+pub struct FrameFuture<'a, V: VideoBackend, A: AudioSink> {
+    /// The NES being run, taken once the frame is complete
+    nes: Option<&'a mut Nes<V, A>>,
+    /// The frame count when the future was created, the frame is complete once it changes
+    start_frame: u64,
+    /// The maximum number of cycles to run each time the future is polled
+    cycle_budget: u32,
+}
+
+impl<'a, V: VideoBackend, A: AudioSink> FrameFuture<'a, V, A> {
+    /// Creates a future that runs at most cycle_budget cycles of the NES each time it is polled. Budgets below 1 are treated as 1.
+    pub(super) fn new(nes: &'a mut Nes<V, A>, cycle_budget: u32) -> Self {
+        return FrameFuture {
+            start_frame: nes.bus.ppu.frame_count,
+            nes: Some(nes),
+            cycle_budget: cycle_budget.max(1),
+        };
+    }
+}
+
+impl<'a, V: VideoBackend, A: AudioSink> Future for FrameFuture<'a, V, A> {
+    type Output = &'a V::Frame;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let start_frame = self.start_frame;
+        let cycle_budget = self.cycle_budget;
+        let nes = self.nes.as_mut().expect("FrameFuture polled after completion");
+        if nes.paused {
+            // Paused frames are filled with silence at once, the same as the blocking frame method
+            return Poll::Ready(self.nes.take().unwrap().frame());
+        }
+        for _ in 0..cycle_budget {
+            nes.cycle();
+            if nes.bus.ppu.frame_count != start_frame {
+                return Poll::Ready(self.nes.take().unwrap().get_screen());
+            }
+        }
+        cx.waker().wake_by_ref();
+        return Poll::Pending;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test_utils::*;
+    use crate::cartridge::Mirroring;
+    use std::cell::Cell;
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    /// Creates a waker that counts how many times it has been woken through the data pointer
+    fn counting_waker(count: &Cell<u32>) -> Waker {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |data| RawWaker::new(data, &VTABLE),
+            |_| {},
+            |data| {
+                let count = unsafe { &*(data as *const Cell<u32>) };
+                count.set(count.get() + 1);
+            },
+            |_| {},
+        );
+        return unsafe { Waker::from_raw(RawWaker::new(count as *const _ as *const (), &VTABLE)) };
+    }
+
+    #[test]
+    fn test_frame_future() {
+        let mut nes = Nes::new(get_mock_cartridge(MapperMock {
+            program_read_stub: |_, _| 0x00,
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        }));
+        nes.frame();
+        let frame_count = nes.get_frame_count();
+        let wakes = Cell::new(0);
+        let waker = counting_waker(&wakes);
+        let mut context = Context::from_waker(&waker);
+        let mut polls = 0;
+        let mut future = nes.frame_async_with_budget(10_000);
+        while Pin::new(&mut future).poll(&mut context).is_pending() {
+            polls += 1;
+        }
+        // A frame is 89342 cycles, so the future yields eight times before the ninth poll completes it
+        assert_eq!(8, polls);
+        assert_eq!(8, wakes.get());
+        assert_eq!(frame_count + 1, nes.get_frame_count());
+        assert_eq!((0, 0), nes.get_ppu_position());
+    }
+}
@@ -0,0 +1,124 @@
+//! The latency stats module counts lag frames, the frames in which the game never latched the controllers, and measures
+//! how long after latching them the next NMI comes. Speedrunners use these to compare how responsive games are, and
+//! they show whether running ahead removes the frames of latency that it should.
+
+/// The number of master clock cycles in an NTSC frame with rendering disabled, four for each of its 341 * 262 PPU dots
+const CYCLES_PER_FRAME: f64 = 341.0 * 262.0 * 4.0;
+
+/// Counters of lag frames and input latency since the NES was created or the stats were last cleared
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct LatencyStats {
+    /// The number of frames completed
+    pub frames: u64,
+    /// The number of completed frames in which the game didn't latch the controllers by writing to $4016
+    pub lag_frames: u64,
+    /// The number of controller latches that were followed by an NMI, which the average latency is taken over.
+    /// Only the first latch between two NMIs is counted.
+    pub latency_samples: u64,
+    /// The average number of frames from the controllers being latched to the next NMI, or 0 if there are no samples
+    pub average_latency_frames: f64,
+}
+
+impl LatencyStats {
+    /// Gets the fraction of the completed frames that were lag frames, or 0 if no frames have been completed
+    pub fn lag_frame_ratio(&self) -> f64 {
+        return if self.frames == 0 {
+            0.0
+        } else {
+            self.lag_frames as f64 / self.frames as f64
+        };
+    }
+}
+
+/// Tracks the controller latches and NMIs of the frame in progress
+#[derive(Debug, Default, Clone)]
+pub(super) struct LatencyTracker {
+    /// The stats counted so far, apart from the average latency
+    stats: LatencyStats,
+    /// The total number of cycles between each counted latch and the NMI that followed it
+    total_latency_cycles: u64,
+    /// Whether the controllers have been latched during the frame in progress
+    latched_this_frame: bool,
+    /// The cycle that the controllers were first latched at since the last NMI, if they have been
+    pending_latch: Option<u64>,
+}
+
+impl LatencyTracker {
+    /// Called when the game latches the controllers, with the master clock cycle that it happened at
+    pub(super) fn input_latched(&mut self, cycle: u64) {
+        self.latched_this_frame = true;
+        self.pending_latch.get_or_insert(cycle);
+    }
+
+    /// Called when the CPU takes an NMI, with the master clock cycle that it happened at
+    pub(super) fn nmi_taken(&mut self, cycle: u64) {
+        if let Some(latch) = self.pending_latch.take() {
+            self.stats.latency_samples += 1;
+            self.total_latency_cycles += cycle.saturating_sub(latch);
+        }
+    }
+
+    /// Completes the frame in progress, counting it as a lag frame if the controllers weren't latched during it
+    pub(super) fn end_frame(&mut self) {
+        self.stats.frames += 1;
+        if !self.latched_this_frame {
+            self.stats.lag_frames += 1;
+        }
+        self.latched_this_frame = false;
+    }
+
+    /// Forgets the latch waiting for an NMI when the console is reset, since the game starts over without taking it
+    pub(super) fn reset(&mut self) {
+        self.pending_latch = None;
+    }
+
+    /// Clears the counters, starting the stats over from the frame in progress
+    pub(super) fn clear(&mut self) {
+        *self = LatencyTracker::default();
+    }
+
+    /// Gets the stats counted so far
+    pub(super) fn get_stats(&self) -> LatencyStats {
+        let average_latency_frames = if self.stats.latency_samples == 0 {
+            0.0
+        } else {
+            self.total_latency_cycles as f64 / self.stats.latency_samples as f64 / CYCLES_PER_FRAME
+        };
+        return LatencyStats {
+            average_latency_frames,
+            ..self.stats
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_latency_tracker() {
+        let mut tracker = LatencyTracker::default();
+        assert_eq!(LatencyStats::default(), tracker.get_stats());
+        // A frame without a latch is a lag frame, and an NMI without a latch before it isn't a sample
+        tracker.nmi_taken(1000);
+        tracker.end_frame();
+        // Only the first of two latches before an NMI is counted
+        let frame = 89342 * 4;
+        tracker.input_latched(frame);
+        tracker.input_latched(frame + 200);
+        tracker.end_frame();
+        tracker.nmi_taken(frame * 2);
+        tracker.input_latched(frame * 2 + 400);
+        tracker.end_frame();
+        tracker.nmi_taken(frame * 2 + 400 + frame / 2);
+        tracker.end_frame();
+        let stats = tracker.get_stats();
+        assert_eq!(4, stats.frames);
+        assert_eq!(2, stats.lag_frames);
+        assert_eq!(2, stats.latency_samples);
+        assert!((stats.average_latency_frames - 0.75).abs() < 0.001);
+        assert!((stats.lag_frame_ratio() - 0.5).abs() < f64::EPSILON);
+        tracker.clear();
+        assert_eq!(LatencyStats::default(), tracker.get_stats());
+    }
+}
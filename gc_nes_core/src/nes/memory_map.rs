@@ -0,0 +1,142 @@
+//! The memory map module describes the regions of the CPU address space, so that debuggers can annotate hex views
+//! with what each address is, where mirrors lead, and which bank of program ROM is switched in, without hard-coding
+//! knowledge of the NES.
+
+use crate::cartridge::{BankMapping, PROGRAM_WINDOW_COUNT, PROGRAM_WINDOW_SIZE};
+
+/// The kind of memory or device that a region of the CPU address space is connected to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryRegionKind {
+    /// The NES' 2 KiB of internal RAM, or one of its mirrors
+    Ram,
+    /// The eight PPU registers, or one of their mirrors
+    PpuRegisters,
+    /// The APU registers, OAM DMA and the controller ports
+    ApuIo,
+    /// The APU and I/O test registers, which are disabled on retail consoles
+    TestMode,
+    /// Cartridge space that is only used by some mappers, for registers or extra memory
+    CartridgeExpansion,
+    /// Cartridge space usually used for program RAM, which is battery backed on some boards
+    ProgramRam,
+    /// A window of cartridge space that program ROM is switched into
+    ProgramRom,
+}
+
+/// A range of the CPU address space and what it is connected to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    /// The first address in the region
+    pub start: u16,
+    /// The last address in the region, inclusive
+    pub end: u16,
+    /// What the region is connected to
+    pub kind: MemoryRegionKind,
+    /// A short description of the region for display
+    pub name: &'static str,
+    /// The first and last address of the range that the region mirrors, if it is a mirror. Mirrors smaller than
+    /// the region repeat throughout it, like the PPU registers do every 8 bytes.
+    pub mirror_of: Option<(u16, u16)>,
+    /// The 8 KiB bank of program ROM switched into the region, for program ROM windows. Banks are numbered like
+    /// they are in [BankMapping](../cartridge/struct.BankMapping.html).
+    pub bank: Option<usize>,
+}
+
+impl MemoryRegion {
+    /// Creates a region that isn't a mirror and has no bank switched in
+    const fn new(start: u16, end: u16, kind: MemoryRegionKind, name: &'static str) -> Self {
+        return MemoryRegion {
+            start,
+            end,
+            kind,
+            name,
+            mirror_of: None,
+            bank: None,
+        };
+    }
+
+    /// Creates a region that mirrors the range from mirror_start to mirror_end
+    const fn mirror(start: u16, end: u16, kind: MemoryRegionKind, name: &'static str, mirror_start: u16, mirror_end: u16) -> Self {
+        return MemoryRegion {
+            start,
+            end,
+            kind,
+            name,
+            mirror_of: Some((mirror_start, mirror_end)),
+            bank: None,
+        };
+    }
+
+    /// Checks if an address is inside of the region
+    pub fn contains(&self, address: u16) -> bool {
+        return (self.start..=self.end).contains(&address);
+    }
+
+    /// Gets the address that an address inside of the region actually accesses, following the mirror if the
+    /// region is one. Addresses outside of mirrors are returned unchanged.
+    pub fn canonical_address(&self, address: u16) -> u16 {
+        return match self.mirror_of {
+            Some((mirror_start, mirror_end)) => mirror_start + (address - self.start) % (mirror_end - mirror_start + 1),
+            None => address,
+        };
+    }
+}
+
+/// The regions of the CPU address space below program ROM, which are the same for every cartridge
+const FIXED_REGIONS: [MemoryRegion; 10] = [
+    MemoryRegion::new(0x0000, 0x07ff, MemoryRegionKind::Ram, "RAM"),
+    MemoryRegion::mirror(0x0800, 0x0fff, MemoryRegionKind::Ram, "RAM mirror", 0x0000, 0x07ff),
+    MemoryRegion::mirror(0x1000, 0x17ff, MemoryRegionKind::Ram, "RAM mirror", 0x0000, 0x07ff),
+    MemoryRegion::mirror(0x1800, 0x1fff, MemoryRegionKind::Ram, "RAM mirror", 0x0000, 0x07ff),
+    MemoryRegion::new(0x2000, 0x2007, MemoryRegionKind::PpuRegisters, "PPU registers"),
+    MemoryRegion::mirror(0x2008, 0x3fff, MemoryRegionKind::PpuRegisters, "PPU register mirrors", 0x2000, 0x2007),
+    MemoryRegion::new(0x4000, 0x4017, MemoryRegionKind::ApuIo, "APU and I/O registers"),
+    MemoryRegion::new(0x4018, 0x401f, MemoryRegionKind::TestMode, "APU and I/O test mode"),
+    MemoryRegion::new(0x4020, 0x5fff, MemoryRegionKind::CartridgeExpansion, "Cartridge expansion"),
+    MemoryRegion::new(0x6000, 0x7fff, MemoryRegionKind::ProgramRam, "Program RAM"),
+];
+
+/// Gets the regions of the CPU address space in address order, covering every address from $0000 to $FFFF, with the
+/// program ROM windows labelled with the banks in the bank mapping
+pub fn cpu_memory_map(bank_mapping: &BankMapping) -> Vec<MemoryRegion> {
+    let mut regions = FIXED_REGIONS.to_vec();
+    for window in 0..PROGRAM_WINDOW_COUNT {
+        let start = (0x8000 + window * PROGRAM_WINDOW_SIZE) as u16;
+        regions.push(MemoryRegion {
+            bank: Some(bank_mapping.program_banks[window]),
+            ..MemoryRegion::new(
+                start,
+                start + (PROGRAM_WINDOW_SIZE - 1) as u16,
+                MemoryRegionKind::ProgramRom,
+                "Program ROM",
+            )
+        });
+    }
+    return regions;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cpu_memory_map() {
+        let bank_mapping = BankMapping {
+            program_banks: [4, 5, 6, 7],
+            ..Default::default()
+        };
+        let regions = cpu_memory_map(&bank_mapping);
+        // The regions are contiguous and cover the whole address space
+        assert_eq!(0x0000, regions[0].start);
+        assert_eq!(0xffff, regions.last().unwrap().end);
+        assert!(regions.windows(2).all(|pair| pair[0].end + 1 == pair[1].start));
+
+        let region_of = |address: u16| regions.iter().find(|region| region.contains(address)).unwrap();
+        assert_eq!(0x0123, region_of(0x1923).canonical_address(0x1923));
+        assert_eq!(0x2002, region_of(0x3ffa).canonical_address(0x3ffa));
+        assert_eq!(0x4016, region_of(0x4016).canonical_address(0x4016));
+        assert_eq!(MemoryRegionKind::ApuIo, region_of(0x4016).kind);
+        assert_eq!(Some(6), region_of(0xc000).bank);
+        assert_eq!(None, region_of(0x6000).bank);
+    }
+}
@@ -4,20 +4,95 @@
 
 extern crate emulator_6502;
 
-use crate::cartridge::Cartridge;
-use crate::input::{NesInput, NesInputDevice};
+use crate::audio::stereo::StereoPanning;
+use crate::audio::trace::ApuRegisterWrite;
+use crate::audio::{AudioSampleBuffer, AudioSink, AUDIO_SAMPLE_LIMIT};
+use crate::cartridge::{BankMapping, Cartridge, CartridgeInfo, CharacterUsage, ConsoleTiming, Mirroring};
+use crate::input::{InputLatency, InputRemap, NesInput};
+use crate::movie::FrameInput;
 use crate::nes::apu::NesApu;
-use crate::nes::ppu::NesPpu;
+use crate::nes::ppu::{NesPpu, OVERSCAN_SIZE};
+use crate::state::{read_state_header, write_state_header, StateReader, StateThumbnail, StateWriter};
+use crate::timing::MasterClock;
+use crate::trace::InterruptKind;
+#[cfg(feature = "debugger")]
+use crate::trace::TraceEvent;
+use crate::video::anaglyph::Eye;
+use crate::video::messages::MessageQueue;
+#[cfg(feature = "filters")]
+use crate::video::postprocess::{CapturingBackend, PostProcessChain, RgbaImage};
+#[cfg(feature = "filters")]
+use crate::video::IndexedFrameBuffer;
+use crate::video::{ArgbFrameBuffer, OutputTransform, Rotation, VideoBackend, VideoInfo, NES_SCREEN_HEIGHT};
 use emulator_6502::{Interface6502, MOS6502};
+use std::error::Error;
+#[cfg(feature = "png-export")]
+use std::ops::Range;
+#[cfg(feature = "png-export")]
+use std::path::Path;
+use std::time::Duration;
 
+mod accuracy;
 mod apu;
+mod cpu_state;
+mod cycle_report;
+mod event;
+mod frame_future;
+mod latency_stats;
+#[cfg(feature = "debugger")]
+mod memory_map;
+mod pixel_owner;
 mod ppu;
+mod ppu_config;
+mod split;
+mod test_rom;
+mod watchdog;
+
+pub(crate) use accuracy::ACCURACY_PRESET_NAMES;
+pub use accuracy::{AccuracyOptions, AccuracyPreset};
+use cpu_state::{load_cpu_state, save_cpu_state};
+use cycle_report::CycleAccounting;
+pub use cycle_report::FrameCycleReport;
+pub use event::NesEvent;
+pub use frame_future::{FrameFuture, DEFAULT_FRAME_CYCLE_BUDGET};
+pub use latency_stats::LatencyStats;
+use latency_stats::LatencyTracker;
+#[cfg(feature = "debugger")]
+pub use memory_map::{cpu_memory_map, MemoryRegion, MemoryRegionKind};
+pub use pixel_owner::PixelOwner;
+pub use ppu_config::PpuConfig;
+pub use split::{plan_scroll_split, ScrollSplit};
+pub use test_rom::TestRomStatus;
+use watchdog::HangWatchdog;
 
 /// The dimensions of NES screen in pixels
 pub const NES_SCREEN_DIMENSIONS: usize = 256 * 240;
+/// The number of frames the NES produces each second
+pub const NES_FRAME_RATE: f64 = MasterClock::NTSC.frame_rate();
+/// The rate that audio samples are pushed to the [AudioSink](../audio/trait.AudioSink.html) at, which is one sample per CPU cycle
+pub const NES_AUDIO_SAMPLE_RATE: f64 = MasterClock::NTSC.cpu_rate();
+/// The number of CPU cycles that a DMC sample fetch stalls the CPU for
+const DMC_DMA_CYCLES: u8 = 4;
+/// The number of CPU cycles that a DMC sample fetch stalls OAM DMA for when they overlap
+const DMC_DMA_CYCLES_DURING_OAM_DMA: u8 = 2;
+/// The starting value of the 64-bit FNV-1a hash used by screen_region_hash
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+/// The prime that the 64-bit FNV-1a hash multiplies by after each byte
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+/// The number of scanlines in a frame, including vertical blank and the pre-render scanline
+const SCANLINES_PER_FRAME: u16 = MasterClock::NTSC.scanlines_per_frame as u16;
+/// The scanline that vertical blank starts on
+const VBLANK_SCANLINE: u16 = 241;
 
-/// Struct that represents the NES itself
-pub struct Nes {
+/// Struct that represents the NES itself.
+///
+/// The type parameters select the [VideoBackend](../video/trait.VideoBackend.html) that the PPU
+/// draws to, which defaults to a buffer of 32 bit ARGB colour values, and the [AudioSink](../audio/trait.AudioSink.html)
+/// that the APU's output is pushed to, which defaults to a buffer that the frontend takes samples from.
+///
+/// A NES with a cloneable video backend and audio sink can be cloned to take a snapshot of its complete state.
+#[derive(Clone)]
+pub struct Nes<V: VideoBackend = ArgbFrameBuffer, A: AudioSink = AudioSampleBuffer> {
     // NES Components-----------------------------------------------------------------------------------------------------------------
     /// The cpu of the NES
     ///
@@ -25,14 +100,59 @@ pub struct Nes {
     cpu: MOS6502,
     /// The bus of the NES, which holds ownership of the other components
     bus: Bus,
+    /// The video backend that the PPU draws pixels to
+    video: V,
+    /// The audio sink that the output of the APU is pushed to
+    audio: A,
     // Additional Tracking Information------------------------------------------------------------------------------------------------
-    /// The number of cycles that have been executed so far
+    /// The number of cycles that have been executed since the NES was created or last reset, which the CPU and PPU are aligned by
     cycle_count: u64,
+    /// The number of master clock cycles that have passed since the NES was created, which keeps counting through resets
+    master_clock: u64,
+    /// The speed multiplier the NES should be run at, with 1.0 being normal speed
+    speed: f32,
+    /// The fraction of a frame that has been owed to the frontend but not yet run
+    frame_accumulator: f64,
+    /// The events that have happened since they were last taken, or None if events aren't being recorded
+    events: Option<Vec<NesEvent>>,
+    /// The accuracy options that the NES is running with
+    accuracy: AccuracyOptions,
+    /// The remaps applied to the input states of the controllers in the first and second ports
+    input_remaps: [InputRemap; 2],
+    /// The rotation and mirroring applied to the screen as it is drawn
+    output_transform: OutputTransform,
+    /// The generation of the save data when the frontend last reported that it had been saved
+    flushed_save_generation: u64,
+    /// Whether the NES is paused, which stops cycle and frame from running it
+    paused: bool,
+    /// The eye that the Famicom 3D System's glasses show the frame being drawn to
+    drawing_eye: Eye,
+    /// The eye that the Famicom 3D System's glasses showed the last completed frame to
+    frame_eye: Eye,
+    /// The banks that were switched into the CPU and PPU address spaces when the last frame was completed
+    bank_mapping: BankMapping,
+    /// The frame count that the console is reset at once it is reached, if a reset has been scheduled
+    scheduled_reset: Option<u64>,
+    /// Where the CPU's cycles went during the frame in progress and the last completed frame
+    cycle_accounting: CycleAccounting,
+    /// The lag frames and latency between the game latching the controllers and the next NMI
+    latency: LatencyTracker,
+    /// Counts the frames that the game spends without any sign of running, or None if hang detection is disabled
+    hang_watchdog: Option<HangWatchdog>,
+    /// The palette and filters that frames are passed through by post_process_frame
+    #[cfg(feature = "filters")]
+    post_processing: PostProcessChain,
+    /// The colour indices and colour emphasis of the frame, kept for the post-processing chain whatever the video backend
+    #[cfg(feature = "filters")]
+    post_processing_frame: IndexedFrameBuffer,
+    /// The messages posted by the frontend, drawn into the video backend at the end of each frame if rendering is enabled
+    messages: MessageQueue,
 }
 
 /// Struct that represents the NES components that are connected to the main bus.
 /// The primary reasons for this classes existence is to allow for reading and writing by the cpu
 /// after the NES has been decomposed.
+#[derive(Clone)]
 struct Bus {
     /// The cartridge loaded into the NES
     cartridge: Box<Cartridge>,
@@ -48,6 +168,27 @@ struct Bus {
     input_device_two: NesInput,
     /// The status of the OAM DMA process. When OAM DMA is activated the value is set to Some(DmaStatus)
     dma_status: Option<DmaStatus>,
+    /// Determines when input state updates are applied to the connected input devices
+    input_latency: InputLatency,
+    /// The last value on the CPU data bus, which is what reads from addresses that nothing drives return
+    open_bus: u8,
+    /// The number of CPU cycles left until a DMC sample fetch in progress reads memory. The CPU and OAM DMA are stalled until it does.
+    dmc_dma_cycles: u8,
+    /// The bottom three bits of the last write to $4016, which drive the OUT0-OUT2 pins of the expansion port
+    expansion_output: u8,
+    /// The kind of interrupt whose vector was last read, which is how the CPU taking an interrupt is noticed
+    vector_read: Option<InterruptKind>,
+    /// Whether the controllers were latched by a write to $4016 during the last CPU cycle
+    input_latched: bool,
+    /// Whether the CPU has read PPUSTATUS, latched the controllers or changed RAM during the frame in progress,
+    /// which are the signs of a running game that hang detection looks for
+    activity: bool,
+    /// The address and value of the last read, if no write has come since. A write to the address that was just read
+    /// comes from a read-modify-write instruction, which writes the value back unchanged before the modified value.
+    last_read: Option<(u16, u8)>,
+    /// The execution trace since it was last taken, or None if execution isn't being traced
+    #[cfg(feature = "debugger")]
+    trace: Option<Vec<TraceEvent>>,
 }
 
 /// Struct that wraps an option to represent if oam dma is in progress and how far along it is.
@@ -68,42 +209,197 @@ struct DmaStatus {
 impl Nes {
     /// Creates a new NES instance with no connected controllers
     pub fn new(cartridge: Cartridge) -> Self {
+        return Nes::with_video_backend(cartridge, Default::default());
+    }
+}
+
+impl<V: VideoBackend> Nes<V> {
+    /// Creates a new NES instance with no connected controllers that draws to the provided video backend
+    pub fn with_video_backend(cartridge: Cartridge, video: V) -> Self {
+        return Nes::with_backends(cartridge, video, Default::default());
+    }
+
+    /// Takes the mixed audio output produced since the last call, oldest first, from 0.0 to 1.0, or from -1.0 to 1.0
+    /// with the [analog filters](#method.set_analog_filters) enabled.
+    /// Samples are produced at the rate set by [set_audio_sample_rate](#method.set_audio_sample_rate), or at
+    /// [NES_AUDIO_SAMPLE_RATE](constant.NES_AUDIO_SAMPLE_RATE.html), one per CPU cycle, if it hasn't been set.
+    /// Samples stop being collected once about a second's worth has built up without being taken.
+    /// While [stereo panning](#method.set_stereo_panning) is set, the samples alternate between the left and right outputs.
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        return self.audio.take_samples();
+    }
+
+    /// Sets the rate in Hz that samples returned by [take_audio_samples](#method.take_audio_samples) are produced at,
+    /// such as 44100 or 48000 to match the host's audio device. Samples that haven't been taken yet are discarded.
+    /// Rates that aren't positive or are above [NES_AUDIO_SAMPLE_RATE](constant.NES_AUDIO_SAMPLE_RATE.html) are ignored.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: f64) {
+        match AudioSampleBuffer::with_sample_rate(sample_rate) {
+            Some(mut buffer) => {
+                buffer.set_speed(f64::from(self.speed));
+                self.audio = buffer;
+            }
+            None => warn!("Ignored invalid audio sample rate: {}", sample_rate),
+        }
+    }
+
+    /// Gets the rate in Hz that samples returned by [take_audio_samples](#method.take_audio_samples) are produced at
+    pub fn get_audio_sample_rate(&self) -> f64 {
+        return self.audio.get_sample_rate();
+    }
+
+    /// Runs the NES for exactly as many cycles as it takes to produce the requested number of audio samples, at
+    /// the rate set by [set_audio_sample_rate](#method.set_audio_sample_rate), and returns them. Samples that were
+    /// already waiting to be taken are returned first, with fewer cycles run to make up the rest.
+    ///
+    /// This lets the host's audio callback drive the emulation, so that audio never runs dry and the frontend shows
+    /// whichever frame is on the screen when the host's display refreshes, rather than sleeping between frames.
+    /// While the NES is paused, silence is returned instead. At most about a second's worth of samples are
+    /// returned at once. While [stereo panning](#method.set_stereo_panning) is set, the count is of samples for each
+    /// output, and twice as many values are returned, alternating between the left and right outputs.
+    pub fn run_until_samples(&mut self, count: usize) -> Vec<f32> {
+        let channels = if self.bus.apu.get_stereo_panning().is_some() { 2 } else { 1 };
+        let count = (count * channels).min(AUDIO_SAMPLE_LIMIT);
+        if self.paused {
+            let mut samples = self.audio.take_samples_up_to(count);
+            samples.resize(count, 0.0);
+            return samples;
+        }
+        while self.audio.get_sample_count() < count {
+            self.cycle();
+        }
+        return self.audio.take_samples_up_to(count);
+    }
+}
+
+impl<V: VideoBackend, A: AudioSink> Nes<V, A> {
+    /// Creates a new NES instance with no connected controllers that draws to the provided video backend and
+    /// pushes its audio to the provided audio sink, for frontends that stream audio to the host as it is produced
+    pub fn with_backends(cartridge: Cartridge, video: V, audio: A) -> Self {
+        let timing = cartridge.get_info().timing;
+        if matches!(timing, ConsoleTiming::Pal | ConsoleTiming::Dendy) {
+            warn!("The cartridge was made for {:?} timing, but only NTSC timing is emulated", timing);
+        }
         let mut bus = Bus {
             cartridge: Box::new(cartridge),
-            ppu: NesPpu::new(),
+            ppu: NesPpu::new(Default::default()),
             apu: NesApu::new(),
             ram: Box::new([0; 0x0800]),
             input_device_one: NesInput::Disconnected,
             input_device_two: NesInput::Disconnected,
             dma_status: None,
+            input_latency: Default::default(),
+            open_bus: 0x00,
+            dmc_dma_cycles: 0,
+            expansion_output: 0x00,
+            vector_read: None,
+            input_latched: false,
+            activity: false,
+            last_read: None,
+            #[cfg(feature = "debugger")]
+            trace: None,
         };
 
         Nes {
             cpu: MOS6502::new_reset_position(&mut bus),
             bus,
+            video,
+            audio,
             cycle_count: 0,
+            master_clock: 0,
+            speed: 1.0,
+            frame_accumulator: 0.0,
+            events: None,
+            accuracy: Default::default(),
+            input_remaps: Default::default(),
+            output_transform: Default::default(),
+            flushed_save_generation: 0,
+            paused: false,
+            drawing_eye: Default::default(),
+            frame_eye: Default::default(),
+            bank_mapping: Default::default(),
+            scheduled_reset: None,
+            cycle_accounting: Default::default(),
+            latency: Default::default(),
+            hang_watchdog: None,
+            #[cfg(feature = "filters")]
+            post_processing: Default::default(),
+            #[cfg(feature = "filters")]
+            post_processing_frame: Default::default(),
+            messages: Default::default(),
         }
     }
 
-    /// Executes a single cycle of the NES
+    /// Executes a single cycle of the NES, or does nothing if the NES is paused
     pub fn cycle(&mut self) {
-        if self.cycle_count % 3 == 0 {
+        if self.paused {
+            return;
+        }
+        if self.cycle_count.is_multiple_of(3) {
             //Copy the dma_status so that the bus is not decomposed which would prevent calling methods on it in the match statement
             let mut dma_status = self.bus.dma_status;
+            if self.bus.dmc_dma_cycles > 0 {
+                self.cycle_accounting.dmc_dma_cycle();
+            } else if dma_status.is_some() {
+                self.cycle_accounting.oam_dma_cycle();
+            }
             // This was created as a personal exercise in pattern matching, but isn't very readable.
             // I should consider alternatives.
             match (self.cycle_count, &mut dma_status) {
+                // A DMC sample fetch takes over the bus, stalling both the CPU and OAM DMA
+                _ if self.bus.dmc_dma_cycles > 0 => {
+                    self.bus.dmc_dma_cycles -= 1;
+                    if self.bus.dmc_dma_cycles == 0 {
+                        self.bus.fetch_dmc_sample();
+                    }
+                }
                 // DMA disabled, CPU cycles every third ppu dot
                 (_, None) => {
+                    let save_generation = self.bus.cartridge.get_save_generation();
+                    #[cfg(feature = "debugger")]
+                    let trace_start = self.start_instruction_trace();
+                    let stack_pointer = self.cpu.get_stack_pointer();
+                    if self.cpu.get_remaining_cycles() == 0 {
+                        self.cycle_accounting.start_instruction(stack_pointer);
+                    }
+                    self.bus.vector_read = None;
                     self.cpu.cycle(&mut self.bus);
+                    if let Some(kind) = self.bus.vector_read {
+                        self.cycle_accounting.interrupt_taken(kind, stack_pointer);
+                        if kind == InterruptKind::Nmi {
+                            self.latency.nmi_taken(self.master_clock);
+                        }
+                    }
+                    if self.bus.input_latched {
+                        self.bus.input_latched = false;
+                        self.latency.input_latched(self.master_clock);
+                    }
+                    self.cycle_accounting.cpu_cycle();
+                    #[cfg(feature = "debugger")]
+                    if let Some((index, event)) = trace_start {
+                        self.finish_instruction_trace(index, event);
+                    }
+                    if save_generation == self.flushed_save_generation && self.bus.cartridge.get_save_generation() != save_generation {
+                        self.record_event(NesEvent::SaveDataDirty { cycle: self.master_clock });
+                    }
                     // DMA status may have been changed, copy it back
                     dma_status = self.bus.dma_status;
                 }
                 // DMA ENABLED ------------------------------------------------------------------------------------------------------------
                 // DMA can only start on an even clock cycle
-                (c, Some(DmaStatus { dma_wait: wait @ true, .. })) if c % 2 == 1 => {
+                (
+                    c,
+                    Some(DmaStatus {
+                        dma_wait: wait @ true,
+                        dma_start_address,
+                        ..
+                    }),
+                ) if c % 2 == 1 => {
                     trace!("DMA Initiated on cycle: {}!", self.cycle_count);
                     *wait = false;
+                    self.record_event(NesEvent::DmaStarted {
+                        page: (*dma_start_address >> 8) as u8,
+                        cycle: self.master_clock,
+                    });
                 }
                 // DMA must wait a clock cycle for reads to be resolved
                 (_, Some(DmaStatus { dma_wait: true, .. })) => (),
@@ -124,24 +420,107 @@ impl Nes {
                     _,
                     Some(DmaStatus {
                         dma_wait: false,
+                        dma_start_address,
                         dma_count,
                         dma_buffer,
-                        ..
                     }),
                 ) => {
                     self.bus.ppu.oam_dma_write(*dma_count, *dma_buffer);
                     *dma_count = dma_count.wrapping_add(1);
                     // When the count has wrapped around, the DMA is over
                     if *dma_count == 0 {
-                        dma_status = None;
                         trace!("DMA ended on cycle: {}!", self.cycle_count);
+                        self.record_event(NesEvent::DmaFinished {
+                            page: (*dma_start_address >> 8) as u8,
+                            cycle: self.master_clock,
+                        });
+                        dma_status = None;
                     }
                 }
             }
             self.bus.dma_status = dma_status;
+            if let Some(access) = self.bus.ppu.take_rendering_vram_access() {
+                self.record_event(NesEvent::VramAccessDuringRendering {
+                    scanline: access.scanline,
+                    dot: access.dot,
+                    write: access.write,
+                    cycle: self.master_clock,
+                });
+            }
+            // The APU is clocked alongside the CPU, even while DMA has the CPU suspended, mixing in the cartridge's expansion audio
+            self.bus.cartridge.cpu_cycle(self.master_clock);
+            self.bus.cartridge.audio_cycle();
+            self.bus.apu.set_expansion_output(self.bus.cartridge.get_audio_output());
+            self.bus.apu.cycle(self.master_clock);
+            let channels = self.bus.apu.channel_outputs();
+            if self.bus.apu.get_stereo_panning().is_some() {
+                let (left, right) = self.bus.apu.stereo_output();
+                self.audio.push_stereo_sample(left, right, channels);
+            } else {
+                self.audio.push_sample(self.bus.apu.output(), channels);
+            }
+            if self.bus.apu.get_pending_interrupt_request() {
+                self.cpu.interrupt_request();
+            }
+            if self.bus.dmc_dma_cycles == 0 && self.bus.apu.get_dmc_sample_request().is_some() {
+                if self.accuracy.dmc_stalls {
+                    // The fetch usually stalls the CPU for four cycles, but only two when it lands during OAM DMA
+                    self.bus.dmc_dma_cycles = if self.bus.dma_status.is_some() {
+                        DMC_DMA_CYCLES_DURING_OAM_DMA
+                    } else {
+                        DMC_DMA_CYCLES
+                    };
+                } else {
+                    self.bus.fetch_dmc_sample();
+                }
+            }
         }
         // PPU cycle runs regardless
-        self.bus.ppu.cycle(&mut self.bus.cartridge, &mut self.cpu);
+        let frame_count = self.bus.ppu.frame_count;
+        #[cfg(feature = "filters")]
+        let video = &mut CapturingBackend {
+            backend: &mut self.video,
+            frame: &mut self.post_processing_frame,
+        };
+        #[cfg(not(feature = "filters"))]
+        let video = &mut self.video;
+        self.bus.ppu.cycle(&mut self.bus.cartridge, &mut self.cpu, video);
+        if self.bus.ppu.frame_count != frame_count {
+            // 3D games switch the glasses' shutters during vertical blank, ready for the frame that is about to be drawn
+            self.frame_eye = self.drawing_eye;
+            self.drawing_eye = Eye::from_expansion_output(self.bus.expansion_output);
+            self.bank_mapping = self.bus.cartridge.get_bank_mapping();
+            // The frame count is incremented at the end of the pre-render scanline, so the frame that was just drawn is the previous one
+            self.cycle_accounting.end_frame(self.bus.ppu.frame_count - 1);
+            self.latency.end_frame();
+            let activity = std::mem::take(&mut self.bus.activity);
+            if let Some(watchdog) = &mut self.hang_watchdog {
+                if watchdog.end_frame(activity) {
+                    let idle_frames = watchdog.get_idle_frames();
+                    self.record_event(NesEvent::GameHung {
+                        idle_frames,
+                        cycle: self.master_clock,
+                    });
+                }
+            }
+            #[cfg(feature = "debugger")]
+            self.bus.record_trace(TraceEvent::Frame {
+                cycle: self.bus.apu.get_cycle_count(),
+                frame: self.bus.ppu.frame_count,
+            });
+            #[cfg(feature = "filters")]
+            let video = &mut CapturingBackend {
+                backend: &mut self.video,
+                frame: &mut self.post_processing_frame,
+            };
+            #[cfg(not(feature = "filters"))]
+            let video = &mut self.video;
+            self.messages.end_frame(video, &self.output_transform);
+            if self.scheduled_reset.is_some_and(|frame| self.bus.ppu.frame_count >= frame) {
+                self.scheduled_reset = None;
+                self.reset();
+            }
+        }
 
         // Check if the Cartridge is triggering an interrupt
         if self.bus.cartridge.get_pending_interrupt_request() {
@@ -149,22 +528,114 @@ impl Nes {
         }
 
         self.cycle_count += 1;
+        self.master_clock += u64::from(MasterClock::NTSC.ppu_divider);
     }
 
     /// Runs as many cycles as necessary to complete the current frame.
-    /// Returns the frame as an of 32 bit colour ARGB colour values.
-    #[cfg(not(feature = "web-frame-format"))]
-    pub fn frame(&mut self) -> &[u32; NES_SCREEN_DIMENSIONS] {
-        self.complete_frame();
+    /// Returns the frame in the format produced by the video backend.
+    ///
+    /// While the NES is paused, the last frame is returned again and a frame's worth of silence is pushed
+    /// to the audio sink instead, so frontends that play audio as frames are run don't run out of samples.
+    pub fn frame(&mut self) -> &V::Frame {
+        if self.paused {
+            let silence = (NES_AUDIO_SAMPLE_RATE / NES_FRAME_RATE).round() as usize;
+            if self.bus.apu.get_stereo_panning().is_some() {
+                (0..silence).for_each(|_| self.audio.push_stereo_sample(0.0, 0.0, Default::default()));
+            } else {
+                (0..silence).for_each(|_| self.audio.push_sample(0.0, Default::default()));
+            }
+        } else {
+            self.complete_frame();
+        }
         return self.get_screen();
     }
 
-    /// Runs as many cycles as necessary to complete the current frame.
-    /// Returns the frame as an of 32 bit colour ARGB colour values.
-    #[cfg(feature = "web-frame-format")]
-    pub fn frame(&mut self) -> &[u8; NES_SCREEN_DIMENSIONS * 4] {
-        self.complete_frame();
-        return self.get_screen();
+    /// Returns a future that runs the NES to the end of the current frame like [frame](#method.frame), so that async
+    /// frontends can interleave emulation with their other tasks. The future runs
+    /// [DEFAULT_FRAME_CYCLE_BUDGET](constant.DEFAULT_FRAME_CYCLE_BUDGET.html) cycles each time it is polled, then wakes
+    /// itself and yields to the executor until the frame is complete.
+    pub fn frame_async(&mut self) -> FrameFuture<'_, V, A> {
+        return self.frame_async_with_budget(DEFAULT_FRAME_CYCLE_BUDGET);
+    }
+
+    /// Returns a future like [frame_async](#method.frame_async) that runs at most cycle_budget cycles each time it is
+    /// polled. Smaller budgets yield to the executor more often at the cost of more polls per frame.
+    pub fn frame_async_with_budget(&mut self, cycle_budget: u32) -> FrameFuture<'_, V, A> {
+        return FrameFuture::new(self, cycle_budget);
+    }
+
+    /// Runs the NES to the end of the current frame like [frame](#method.frame), but without drawing it to the video
+    /// backend, for frontends on hosts that can't keep up. The CPU, APU and everything about the PPU that the game can
+    /// observe run as normal, so the audio stays smooth while frames are dropped. The video backend keeps the pixels
+    /// that were drawn before, so the next frame should be run with frame before the screen is shown again.
+    pub fn skip_frame(&mut self) {
+        self.bus.ppu.set_skip_drawing(true);
+        self.frame();
+        self.bus.ppu.set_skip_drawing(false);
+    }
+
+    /// Runs the NES until the PPU has set the vertical blank flag, which happens on the second dot of scanline 241,
+    /// stopping before the CPU sees the flag or takes the NMI. If the PPU is already past that point, the NES runs into
+    /// the next frame's vertical blank. Does nothing while the NES is paused.
+    pub fn run_until_vblank(&mut self) {
+        self.run_until_ppu_position(VBLANK_SCANLINE, 2);
+    }
+
+    /// Runs the NES until the PPU is about to start the scanline, 0 to 239 for the visible scanlines, 240 to 260 for
+    /// vertical blank and 261 for the pre-render scanline. If the PPU is already on or past the start of it, the NES runs
+    /// into the next frame's. Does nothing while the NES is paused.
+    pub fn run_until_scanline(&mut self, scanline: u16) -> Result<(), Box<dyn Error>> {
+        if scanline >= SCANLINES_PER_FRAME {
+            bail!(
+                "There are only {} scanlines in a frame, so scanline {} can't be reached",
+                SCANLINES_PER_FRAME,
+                scanline
+            );
+        }
+        self.run_until_ppu_position(scanline, 0);
+        return Ok(());
+    }
+
+    /// Gets the scanline (0 to 261) and dot (0 to 340) that the PPU will run next
+    pub fn get_ppu_position(&self) -> (u16, u16) {
+        return self.bus.ppu.get_position();
+    }
+
+    /// Runs cycles until the PPU is about to run the dot of the scanline, at least one cycle and never more than a frame
+    fn run_until_ppu_position(&mut self, scanline: u16, dot: u16) {
+        if self.paused {
+            return;
+        }
+        loop {
+            self.cycle();
+            if self.bus.ppu.get_position() == (scanline, dot) {
+                return;
+            }
+        }
+    }
+
+    /// Runs the NES until the end of the range of frames, writing each frame in the range to the directory
+    /// as a numbered PNG image (frame_000042.png, for example). Frames that have already been completed
+    /// are skipped. Requires a video backend that can produce RGBA frames.
+    #[cfg(feature = "png-export")]
+    pub fn dump_frames(&mut self, directory: &Path, frames: Range<u64>) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(directory)?;
+        while self.get_frame_count() < frames.end {
+            self.complete_frame();
+            // The frame count is incremented at the end of the pre-render scanline, so the frame that was
+            // just drawn is the previous one.
+            let frame_number = self.get_frame_count() - 1;
+            if frames.contains(&frame_number) {
+                let rgba_frame = match self.video.get_rgba_frame() {
+                    Some(rgba_frame) => rgba_frame,
+                    None => bail!("The video backend can't produce RGBA frames"),
+                };
+                let (width, height) = self.output_transform.get_output_dimensions();
+                let png_data = crate::video::encode_png(&rgba_frame, width as u32, height as u32)?;
+                std::fs::write(directory.join(format!("frame_{:06}.png", frame_number)), png_data)?;
+            }
+        }
+        return Ok(());
     }
 
     /// Runs as many cycles as necessary to complete the current frame.
@@ -175,43 +646,743 @@ impl Nes {
         }
     }
 
-    /// Updates the state of the input device connected to the first port
+    /// Updates the state of the input device connected to the first port, or disconnects it if the state is None.
+    ///
+    /// Reads from a port with no controller return only the open bus bits, usually 0x40, while an idle controller
+    /// reads ones once its eight buttons have been read, which some games check to detect whether a controller is connected.
+    ///
+    /// The state is passed through the port's [remap](#method.set_controller_one_remap) first.
     pub fn update_controller_one(&mut self, input_state: Option<u8>) {
-        match (&mut self.bus.input_device_one, input_state) {
-            (NesInput::Disconnected, None) => {}
-            (NesInput::Connected(_), None) => self.bus.input_device_one = NesInput::Disconnected,
-            (NesInput::Disconnected, Some(state)) => self.bus.input_device_one = NesInput::Connected(NesInputDevice::new(state)),
-            (NesInput::Connected(ref mut device), Some(state)) => device.update_state(state),
-        }
+        let input_state = input_state.map(|state| self.input_remaps[0].apply(state, self.bus.ppu.frame_count));
+        self.bus.input_device_one.update(input_state, self.bus.input_latency);
     }
 
-    /// Updates the state of the input device connected to the first port
+    /// Updates the state of the input device connected to the second port, or disconnects it if the state is None.
+    /// See [update_controller_one](#method.update_controller_one) for how ports without a controller read.
+    /// The state is passed through the port's [remap](#method.set_controller_two_remap) first.
     pub fn update_controller_two(&mut self, input_state: Option<u8>) {
-        match (&mut self.bus.input_device_two, input_state) {
-            (NesInput::Disconnected, None) => {}
-            (NesInput::Connected(_), None) => self.bus.input_device_two = NesInput::Disconnected,
-            (NesInput::Disconnected, Some(state)) => self.bus.input_device_two = NesInput::Connected(NesInputDevice::new(state)),
-            (NesInput::Connected(ref mut device), Some(state)) => device.update_state(state),
+        let input_state = input_state.map(|state| self.input_remaps[1].apply(state, self.bus.ppu.frame_count));
+        self.bus.input_device_two.update(input_state, self.bus.input_latency);
+    }
+
+    /// Sets the remap that the input states passed for the controller in the first port are passed through, such as
+    /// one swapping A and B for accessibility. Remapping in the core means that movies record the remapped input.
+    pub fn set_controller_one_remap(&mut self, remap: InputRemap) {
+        self.input_remaps[0] = remap;
+    }
+
+    /// Sets the remap that the input states passed for the controller in the second port are passed through
+    pub fn set_controller_two_remap(&mut self, remap: InputRemap) {
+        self.input_remaps[1] = remap;
+    }
+
+    /// Gets the remap that the input states passed for the controller in the first port are passed through
+    pub fn get_controller_one_remap(&self) -> InputRemap {
+        return self.input_remaps[0];
+    }
+
+    /// Gets the remap that the input states passed for the controller in the second port are passed through
+    pub fn get_controller_two_remap(&self) -> InputRemap {
+        return self.input_remaps[1];
+    }
+
+    /// Passes a frame of input through the remaps of both ports, as it would be applied on the current frame.
+    /// This is the input to record in a movie, which is played back with [update_frame_input](#method.update_frame_input).
+    pub fn remap_frame_input(&self, input: FrameInput) -> FrameInput {
+        let frame = self.bus.ppu.frame_count;
+        return FrameInput {
+            controller_one: input.controller_one.map(|state| self.input_remaps[0].apply(state, frame)),
+            controller_two: input.controller_two.map(|state| self.input_remaps[1].apply(state, frame)),
+        };
+    }
+
+    /// Checks if a controller is connected to the first port
+    pub fn is_controller_one_connected(&self) -> bool {
+        return self.bus.input_device_one.is_connected();
+    }
+
+    /// Checks if a controller is connected to the second port
+    pub fn is_controller_two_connected(&self) -> bool {
+        return self.bus.input_device_two.is_connected();
+    }
+
+    /// Updates the state of the input devices connected to both ports from a single frame of movie input.
+    /// Unlike [update_controller_one](#method.update_controller_one), the input isn't passed through the ports'
+    /// remaps, since movies record input after it has been remapped. Frontends recording live input should pass it
+    /// through [remap_frame_input](#method.remap_frame_input) first, and record and apply the result.
+    pub fn update_frame_input(&mut self, input: FrameInput) {
+        self.bus.input_device_one.update(input.controller_one, self.bus.input_latency);
+        self.bus.input_device_two.update(input.controller_two, self.bus.input_latency);
+    }
+
+    /// Sets the speed multiplier that the NES should run at, where 1.0 is normal speed,
+    /// values below 1.0 are slow motion, and values above 1.0 are fast forward.
+    /// Non-positive and non-finite values are ignored.
+    ///
+    /// The speed changes the number of frames returned by [frames_for_host_refresh](#method.frames_for_host_refresh),
+    /// and the audio sink's resampler produces samples at its output rate divided by the speed, so each host refresh
+    /// gets the same number of samples at any speed and the audio keeps pace with the picture. The pitch rises and
+    /// falls with the speed. Audio at the APU's own rate, before [set_audio_sample_rate](#method.set_audio_sample_rate)
+    /// has been called, isn't resampled, so it plays back faster or slower along with the NES.
+    pub fn set_speed(&mut self, multiplier: f32) {
+        if multiplier.is_finite() && multiplier > 0.0 {
+            self.speed = multiplier;
+            self.audio.set_speed(f64::from(multiplier));
+        } else {
+            warn!("Ignored invalid speed multiplier: {}", multiplier)
+        }
+    }
+
+    /// Gets the speed multiplier that the NES is running at
+    pub fn get_speed(&self) -> f32 {
+        return self.speed;
+    }
+
+    /// Returns the number of frames a frontend should run for a single refresh of the host display,
+    /// given the host's refresh rate in Hz, in order to run the NES at the speed set by [set_speed](#method.set_speed).
+    ///
+    /// Fractional frames are carried over to later calls, so calling this once per host refresh will
+    /// run the correct number of frames on average, even when the refresh rates don't divide evenly.
+    pub fn frames_for_host_refresh(&mut self, host_refresh_rate: f64) -> u32 {
+        if !(host_refresh_rate.is_finite() && host_refresh_rate > 0.0) {
+            warn!("Invalid host refresh rate: {}", host_refresh_rate);
+            return 0;
         }
+        self.frame_accumulator += NES_FRAME_RATE * f64::from(self.speed) / host_refresh_rate;
+        let frames = self.frame_accumulator.floor();
+        self.frame_accumulator -= frames;
+        return frames as u32;
     }
 
-    /// Gets the current state of the screen from the PPU's screen buffer as an array of 32 bit colour values.
-    #[cfg(not(feature = "web-frame-format"))]
-    pub fn get_screen(&mut self) -> &[u32; NES_SCREEN_DIMENSIONS] {
-        self.bus.ppu.get_screen()
+    /// Gets the master clock that the NES' timing is derived from, for frontends that pace video and audio exactly
+    pub fn get_master_clock(&self) -> MasterClock {
+        return MasterClock::NTSC;
     }
 
-    /// Gets the current state of the screen from the PPU's screen buffer in web format where each pixel is four bytes in RGBA order for web rendering.
-    #[cfg(feature = "web-frame-format")]
-    pub fn get_screen(&mut self) -> &[u8; NES_SCREEN_DIMENSIONS * 4] {
-        self.bus.ppu.get_screen()
+    /// Gets the size, pixel aspect ratio and refresh rate of the frames that the NES produces, as shown by the region's
+    /// televisions, so that frontends don't need to assume 60Hz and 8:7 pixels
+    pub fn video_info(&self) -> VideoInfo {
+        let clock = self.get_master_clock();
+        let (width, height) = self.output_transform.get_output_dimensions();
+        let pixel_aspect_ratio = match self.output_transform.rotation {
+            Rotation::None | Rotation::Clockwise180 => clock.pixel_aspect_ratio(),
+            Rotation::Clockwise90 | Rotation::Clockwise270 => 1.0 / clock.pixel_aspect_ratio(),
+        };
+        let overscan_lines = if self.bus.ppu.get_config().hide_overscan { 2 * OVERSCAN_SIZE } else { 0 };
+        return VideoInfo {
+            width,
+            height,
+            active_lines: NES_SCREEN_HEIGHT - overscan_lines,
+            pixel_aspect_ratio,
+            refresh_rate: clock.frame_rate(),
+        };
+    }
+
+    /// Gets the number of frames that the NES has completed
+    pub fn get_frame_count(&self) -> u64 {
+        return self.bus.ppu.frame_count;
+    }
+
+    /// Gets the instantaneous mixed output level of the APU, from 0.0 to 1.0, or from -1.0 to 1.0 with the
+    /// [analog filters](#method.set_analog_filters) enabled. Always 0.0 while the NES is paused.
+    pub fn get_audio_output(&self) -> f32 {
+        return if self.paused { 0.0 } else { self.bus.apu.output() };
+    }
+
+    /// Enables or disables the NES' analog audio filters, which are off by default. These are the high-pass filters
+    /// at 90 Hz and 440 Hz and the low-pass filter at 14 kHz that the console's audio passes through on its way to
+    /// the output, which remove the mixer's DC offset and give the NES its characteristic sound.
+    pub fn set_analog_filters(&mut self, enabled: bool) {
+        self.bus.apu.set_analog_filters(enabled);
+    }
+
+    /// Checks if the NES' analog audio filters are enabled
+    pub fn get_analog_filters(&self) -> bool {
+        return self.bus.apu.get_analog_filters();
+    }
+
+    /// Sets the pan positions that the APU's channels are mixed into separate left and right outputs with, or None to
+    /// mix them in mono like the console does, which is the default. While panning is set, the audio sink is passed
+    /// stereo samples through [push_stereo_sample](../audio/trait.AudioSink.html#method.push_stereo_sample), and the
+    /// samples returned by [take_audio_samples](#method.take_audio_samples) alternate between the left and right outputs.
+    pub fn set_stereo_panning(&mut self, panning: Option<StereoPanning>) {
+        self.bus.apu.set_stereo_panning(panning);
+    }
+
+    /// Gets the pan positions that the APU's channels are mixed with, or None if they are mixed in mono
+    pub fn get_stereo_panning(&self) -> Option<StereoPanning> {
+        return self.bus.apu.get_stereo_panning();
+    }
+
+    /// Pauses or resumes the NES. While paused, [cycle](#method.cycle) does nothing and [frame](#method.frame)
+    /// returns the last frame without running the NES, so every frontend pauses the same way. Resuming
+    /// continues from exactly where the NES was paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Checks if the NES is paused
+    pub fn is_paused(&self) -> bool {
+        return self.paused;
+    }
+
+    /// Gets the eye that the Famicom 3D System's shutter glasses showed the last completed frame to, which games
+    /// that support the glasses alternate between every frame. Always the left eye for other games. See the
+    /// [anaglyph module](../video/anaglyph/index.html) for combining the frames for each eye into a single image.
+    pub fn get_frame_eye(&self) -> Eye {
+        return self.frame_eye;
+    }
+
+    /// Gets the banks of program ROM and character memory that were switched into each window of the CPU and PPU
+    /// address spaces when the last frame was completed, for debuggers and code/data loggers. Games switch banks
+    /// throughout a frame, so this is a snapshot rather than a record of every bank that was used.
+    pub fn get_bank_mapping(&self) -> BankMapping {
+        return self.bank_mapping;
+    }
+
+    /// Sets when input state updates passed to update_controller_one and update_controller_two take effect.
+    ///
+    /// With `InputLatency::Immediate`, the default, new states are visible to the game as soon as they are passed in.
+    /// With `InputLatency::NextLatch` new states are held until the game next strobes the controller latch, so the
+    /// game sees the same input regardless of when in the frame the frontend provided it.
+    pub fn set_input_latency(&mut self, input_latency: InputLatency) {
+        self.bus.input_latency = input_latency;
+    }
+
+    /// Sets the accuracy options that the NES runs with, either from a preset or with individual overrides.
+    /// Takes effect immediately, though options that change how a scanline is rendered only apply from the next one.
+    pub fn set_accuracy(&mut self, options: AccuracyOptions) {
+        self.accuracy = options;
+        self.bus.ppu.set_accuracy(&options);
+        self.bus.cartridge.set_a12_filtering(options.a12_filtering);
+    }
+
+    /// Gets the accuracy options that the NES is running with
+    pub fn get_accuracy(&self) -> AccuracyOptions {
+        return self.accuracy;
+    }
+
+    /// Sets the options that change how the PPU draws the screen, such as the sprite limit and hiding overscan
+    pub fn set_ppu_config(&mut self, config: PpuConfig) {
+        self.bus.ppu.set_config(config);
+    }
+
+    /// Gets the options that the PPU draws the screen with
+    pub fn get_ppu_config(&self) -> PpuConfig {
+        return self.bus.ppu.get_config();
+    }
+
+    /// Sets the rotation and mirroring applied to the screen as it is drawn to the video backend, for displays that
+    /// are mounted sideways or upside down. See [OutputTransform](../video/struct.OutputTransform.html) for how rotated
+    /// frames are laid out. Pixels are moved as they are drawn, so the current frame is only fully transformed once the
+    /// next one has been completed.
+    pub fn set_output_transform(&mut self, rotation: Rotation, flip_horizontal: bool, flip_vertical: bool) {
+        self.output_transform = OutputTransform {
+            rotation,
+            flip_horizontal,
+            flip_vertical,
+        };
+        self.bus.ppu.set_output_transform(self.output_transform);
+    }
+
+    /// Gets the rotation and mirroring applied to the screen as it is drawn
+    pub fn get_output_transform(&self) -> OutputTransform {
+        return self.output_transform;
+    }
+
+    /// Forces the nametables to use a mirroring mode regardless of the cartridge's header and mapper, or restores
+    /// them with None. This is a workaround for ROMs whose headers declare the wrong mirroring, which shows up as
+    /// garbled backgrounds while the screen scrolls.
+    pub fn override_mirroring(&mut self, mirroring: Option<Mirroring>) {
+        self.bus.cartridge.override_mirroring(mirroring);
+    }
+
+    /// Gets the mirroring mode that the nametables have been forced to use, if any
+    pub fn get_mirroring_override(&self) -> Option<Mirroring> {
+        return self.bus.cartridge.get_mirroring_override();
+    }
+
+    /// Sets whether the cartridge records which tiles of its character memory the PPU reads, for tools that look for
+    /// unused graphics or need to know which tiles to replace. Tracking is off by default, as it slows every PPU fetch.
+    pub fn set_character_usage_tracking(&mut self, enabled: bool) {
+        self.bus.cartridge.set_character_usage_tracking(enabled);
+    }
+
+    /// Gets the tiles of character memory that have been read since tracking was enabled, or None if it isn't
+    pub fn get_character_usage(&self) -> Option<&CharacterUsage> {
+        return self.bus.cartridge.get_character_usage();
+    }
+
+    /// Gets a copy of the cartridge's character memory, in the order it is stored in the .nes file.
+    /// See [Cartridge::export_chr](../cartridge/struct.Cartridge.html#method.export_chr).
+    pub fn export_chr(&self) -> Vec<u8> {
+        return self.bus.cartridge.export_chr();
+    }
+
+    /// Replaces the cartridge's character memory while the game runs, for previewing edited graphics.
+    /// See [Cartridge::import_chr](../cartridge/struct.Cartridge.html#method.import_chr).
+    pub fn import_chr(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        return self.bus.cartridge.import_chr(data);
+    }
+
+    /// Replaces the cartridge's program ROM and character memory while the game runs, for an edit, assemble and reload
+    /// loop when developing homebrew. The CPU, PPU, RAM and mapper state are all kept, so the game carries on from
+    /// where it was with the new code and graphics.
+    /// See [Cartridge::reload_rom_data](../cartridge/struct.Cartridge.html#method.reload_rom_data).
+    pub fn reload_rom_data(&mut self, program_rom: &[u8], character_rom: &[u8]) -> Result<(), Box<dyn Error>> {
+        return self.bus.cartridge.reload_rom_data(program_rom, character_rom);
+    }
+
+    /// Gets the information from the header of the loaded cartridge, for frontends to configure themselves with,
+    /// like connecting the cartridge's default expansion device.
+    /// See [Cartridge::get_info](../cartridge/struct.Cartridge.html#method.get_info).
+    pub fn get_cartridge_info(&self) -> &CartridgeInfo {
+        return self.bus.cartridge.get_info();
+    }
+
+    /// Checks if the cartridge's program RAM is battery backed, meaning the game expects it to persist between sessions
+    pub fn is_battery_backed(&self) -> bool {
+        return self.bus.cartridge.is_battery_backed();
+    }
+
+    /// Checks if the cartridge has anything for the frontend to persist between sessions, which is the case for
+    /// battery backed program RAM and for mappers with their own non-volatile memory
+    pub fn has_save_data(&self) -> bool {
+        return self.bus.cartridge.has_save_data();
+    }
+
+    /// Gets the contents of the cartridge's program RAM if it is battery backed, followed by the mapper's non-volatile
+    /// state if it has any, for the frontend to persist. Returns None if the cartridge has nothing to save.
+    pub fn get_save_data(&self) -> Option<Vec<u8>> {
+        return self.bus.cartridge.get_save_data();
+    }
+
+    /// Gets the number of writes that have changed the cartridge's save data since it was loaded.
+    /// Frontends that save periodically can compare it to the generation they last saved, rather than the data itself.
+    pub fn sram_generation(&self) -> u64 {
+        return self.bus.cartridge.get_save_generation();
+    }
+
+    /// Checks if the battery backed program RAM has changed since the frontend last called
+    /// [mark_save_flushed](#method.mark_save_flushed), or since the cartridge was loaded
+    pub fn is_save_dirty(&self) -> bool {
+        return self.sram_generation() != self.flushed_save_generation;
+    }
+
+    /// Tells the NES that the save data has been written somewhere safe, clearing the dirty flag.
+    /// A [SaveDataDirty](enum.NesEvent.html#variant.SaveDataDirty) event is recorded the next time it changes.
+    pub fn mark_save_flushed(&mut self) {
+        self.flushed_save_generation = self.sram_generation();
+    }
+
+    /// Restores the cartridge's battery backed program RAM from data previously returned by
+    /// [get_save_data](#method.get_save_data). Should be called before the game starts running.
+    pub fn load_save_data(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        return self.bus.cartridge.load_save_data(data);
+    }
+
+    /// Sets whether the NES records [NesEvents](enum.NesEvent.html) for tools to observe.
+    /// Recorded events accumulate until they are collected with [take_events](#method.take_events),
+    /// and disabling recording discards any that haven't been collected.
+    pub fn set_event_recording(&mut self, enabled: bool) {
+        self.events = if enabled { Some(self.events.take().unwrap_or_default()) } else { None };
+    }
+
+    /// Takes the events that have been recorded since the last call, oldest first.
+    /// Returns an empty list if event recording is disabled.
+    pub fn take_events(&mut self) -> Vec<NesEvent> {
+        return self.events.as_mut().map(std::mem::take).unwrap_or_default();
+    }
+
+    /// Sets whether every write to the APU's registers is traced, along with the CPU cycle it was made on, for ripping
+    /// music and diagnosing audio bugs. Traced writes accumulate until they are collected with
+    /// [take_apu_trace](#method.take_apu_trace), and disabling tracing discards any that haven't been collected.
+    /// See the [trace module](../audio/trace/index.html) for the formats that traces can be written in.
+    pub fn set_apu_trace(&mut self, enabled: bool) {
+        self.bus.apu.set_trace(enabled);
+    }
+
+    /// Takes the writes to the APU's registers that have been traced since the last call, oldest first.
+    /// Returns an empty list if tracing is disabled.
+    pub fn take_apu_trace(&mut self) -> Vec<ApuRegisterWrite> {
+        return self.bus.apu.take_trace();
+    }
+
+    /// Gets how the CPU's cycles were spent during the last completed frame: how many went to NMI and IRQ handlers,
+    /// and how many DMA stalled the CPU for. Returns None if no frames have been completed.
+    pub fn get_frame_cycle_report(&self) -> Option<FrameCycleReport> {
+        return self.cycle_accounting.get_last_report();
+    }
+
+    /// Gets the number of lag frames, in which the game didn't latch the controllers, and the average number of frames
+    /// from the controllers being latched to the next NMI, counted since the NES was created or the stats were cleared
+    pub fn latency_stats(&self) -> LatencyStats {
+        return self.latency.get_stats();
+    }
+
+    /// Clears the lag frame and latency counters, so that they only cover the frames that follow
+    pub fn clear_latency_stats(&mut self) {
+        self.latency.clear();
+    }
+
+    /// Sets the number of consecutive frames that the game has to spend without reading PPUSTATUS, latching the
+    /// controllers or changing RAM before it is considered hung, or None to disable hang detection, which is the default.
+    /// A [GameHung](enum.NesEvent.html#variant.GameHung) event is recorded when the game becomes hung, for frontends
+    /// to tell the player and for batch runners to record. Setting it starts the count over.
+    pub fn set_hang_detection(&mut self, idle_frames: Option<u32>) {
+        self.hang_watchdog = idle_frames.map(HangWatchdog::new);
+    }
+
+    /// Checks if the game appears to have hung, having spent at least the number of frames set with
+    /// [set_hang_detection](#method.set_hang_detection) without any sign of running. Always false if hang detection is disabled.
+    pub fn is_hung(&self) -> bool {
+        return self.hang_watchdog.as_ref().is_some_and(HangWatchdog::is_hung);
+    }
+
+    /// Sets whether the PPU records what drew each pixel of the screen: the backdrop, the background, or which sprite
+    /// in OAM. Tracking is off by default, and disabling it discards the record.
+    pub fn set_pixel_owner_tracking(&mut self, enabled: bool) {
+        self.bus.ppu.set_pixel_owner_tracking(enabled);
+    }
+
+    /// Gets what drew each pixel of the screen, stored row by row in the same layout as the video backend, after the
+    /// output transform. Pixels hidden by the overscan mask belong to the backdrop. Pixels of the frame in progress
+    /// hold their owners from the last frame until they're drawn. Returns None if pixel owners aren't being tracked.
+    pub fn get_pixel_owners(&self) -> Option<&[PixelOwner]> {
+        return self.bus.ppu.get_pixel_owners().map(|pixel_owners| &pixel_owners[..]);
+    }
+
+    /// Sets whether the CPU's execution is traced, recording every instruction, access to the CPU bus, interrupt and
+    /// completed frame for debugging and for diffing against other emulators. Traced events accumulate until they
+    /// are collected with [take_execution_trace](#method.take_execution_trace), and disabling tracing discards any
+    /// that haven't been collected. See the [trace module](../trace/index.html) for the formats that traces can be written in.
+    #[cfg(feature = "debugger")]
+    pub fn set_execution_trace(&mut self, enabled: bool) {
+        self.bus.trace = if enabled {
+            Some(self.bus.trace.take().unwrap_or_default())
+        } else {
+            None
+        };
+    }
+
+    /// Takes the events that have been traced since the last call, oldest first.
+    /// Returns an empty list if execution isn't being traced.
+    #[cfg(feature = "debugger")]
+    pub fn take_execution_trace(&mut self) -> Vec<TraceEvent> {
+        return self.bus.trace.as_mut().map(std::mem::take).unwrap_or_default();
+    }
+
+    /// Gets the regions of the CPU address space in address order, for debuggers to annotate hex views with.
+    /// Unlike [get_bank_mapping](#method.get_bank_mapping), the program ROM windows are labelled with the banks
+    /// that are switched in right now rather than at the end of the last frame.
+    #[cfg(feature = "debugger")]
+    pub fn get_cpu_memory_map(&self) -> Vec<MemoryRegion> {
+        return cpu_memory_map(&self.bus.cartridge.get_bank_mapping());
+    }
+
+    /// If execution is being traced and the CPU is about to start an instruction or take an interrupt, records the
+    /// state of its registers before it does. Returns where the accesses that the CPU makes will start in the trace,
+    /// along with the instruction event, which can only be completed once the CPU has run.
+    #[cfg(feature = "debugger")]
+    fn start_instruction_trace(&self) -> Option<(usize, TraceEvent)> {
+        let trace = self.bus.trace.as_ref()?;
+        if self.cpu.get_remaining_cycles() != 0 {
+            return None;
+        }
+        let event = TraceEvent::Instruction {
+            cycle: self.bus.apu.get_cycle_count(),
+            program_counter: self.cpu.get_program_counter(),
+            opcode: 0x00,
+            accumulator: self.cpu.get_accumulator(),
+            x_register: self.cpu.get_x_register(),
+            y_register: self.cpu.get_y_register(),
+            status_register: self.cpu.get_status_register(),
+            stack_pointer: self.cpu.get_stack_pointer(),
+        };
+        return Some((trace.len(), event));
+    }
+
+    /// Inserts the event started by [start_instruction_trace](#method.start_instruction_trace) ahead of the accesses that
+    /// the CPU made. If the CPU's first access was to fetch the opcode, the event is an instruction. Otherwise, the CPU
+    /// took an interrupt, and the vector it read shows which.
+    #[cfg(feature = "debugger")]
+    fn finish_instruction_trace(&mut self, index: usize, mut event: TraceEvent) {
+        let trace = match &mut self.bus.trace {
+            Some(trace) => trace,
+            None => return,
+        };
+        if let TraceEvent::Instruction {
+            cycle,
+            program_counter,
+            ref mut opcode,
+            ..
+        } = event
+        {
+            match trace.get(index) {
+                Some(TraceEvent::Read { address, value, .. }) if *address == program_counter => *opcode = *value,
+                _ => {
+                    let nmi = trace[index..]
+                        .iter()
+                        .any(|access| matches!(access, TraceEvent::Read { address: 0xfffa, .. }));
+                    event = TraceEvent::Interrupt {
+                        cycle,
+                        kind: if nmi { InterruptKind::Nmi } else { InterruptKind::Irq },
+                        return_address: program_counter,
+                    };
+                }
+            }
+        }
+        trace.insert(index, event);
+    }
+
+    /// Gets the number of CPU cycles that have run since the NES was created, which traced APU writes and execution
+    /// traces are timestamped with. Each takes twelve cycles of the [master clock](#method.master_clock_cycles).
+    pub fn get_cpu_cycle_count(&self) -> u64 {
+        return self.bus.apu.get_cycle_count();
+    }
+
+    /// Gets the number of cycles of the [master clock](#method.get_master_clock) that have passed since the NES was created,
+    /// four for each PPU dot. Unlike the cycles that the CPU and PPU are aligned by, it keeps counting through resets, so events and the
+    /// latency stats are timestamped with it, and it is the clock that tools lining up what happened in the NES with
+    /// something outside of it, like movies and netplay, should use. The APU's frame counter and DMC channel and the
+    /// mappers' interrupt counters are timed by it too.
+    pub fn master_clock_cycles(&self) -> u64 {
+        return self.master_clock;
+    }
+
+    /// Records an event if event recording is enabled
+    fn record_event(&mut self, event: NesEvent) {
+        if let Some(events) = &mut self.events {
+            events.push(event);
+        }
+    }
+
+    /// Gets the current state of the screen from the video backend.
+    pub fn get_screen(&mut self) -> &V::Frame {
+        return self.video.get_frame();
+    }
+
+    /// Passes the current frame through the [post-processing chain](#method.get_post_processing_mut), returning the
+    /// filtered image. The NES keeps the colour indices and colour emphasis of the frame for the chain itself, so this
+    /// works whichever video backend the PPU draws to. The frame is processed as it appears after the
+    /// [output transform](#method.set_output_transform), with any [messages](#method.post_message) drawn into it.
+    #[cfg(feature = "filters")]
+    pub fn post_process_frame(&mut self) -> RgbaImage {
+        let frame = &self.post_processing_frame;
+        let (width, height) = self.output_transform.get_output_dimensions();
+        return self.post_processing.process(frame.get_frame(), frame.get_emphasis_frame(), width, height);
+    }
+
+    /// Computes a hash of the pixels in a rectangle of the screen, for auto-splitters and tests that need to recognise
+    /// screens such as a title screen or a level transition without comparing whole images. The coordinates are in the
+    /// screen as it appears after the [output transform](#method.set_output_transform). The hash is 64-bit FNV-1a
+    /// over the RGBA bytes of the pixels, row by row, so it stays the same between runs and versions for the same
+    /// pixels. Fails if the rectangle isn't within the screen, or if the video backend can't produce RGBA frames.
+    pub fn screen_region_hash(&self, x: usize, y: usize, width: usize, height: usize) -> Result<u64, Box<dyn Error>> {
+        let (screen_width, screen_height) = self.output_transform.get_output_dimensions();
+        if x + width > screen_width || y + height > screen_height {
+            bail!(
+                "The region {}x{} at ({}, {}) is outside of the {}x{} screen",
+                width,
+                height,
+                x,
+                y,
+                screen_width,
+                screen_height
+            );
+        }
+        let rgba_frame = match self.video.get_rgba_frame() {
+            Some(rgba_frame) => rgba_frame,
+            None => bail!("The video backend can't produce RGBA frames"),
+        };
+        let hash = (y..y + height)
+            .flat_map(|row| &rgba_frame[(row * screen_width + x) * 4..(row * screen_width + x + width) * 4])
+            .fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME));
+        return Ok(hash);
+    }
+
+    /// Replaces the palette and filters that [post_process_frame](#method.post_process_frame) passes frames through
+    #[cfg(feature = "filters")]
+    pub fn set_post_processing(&mut self, chain: PostProcessChain) {
+        self.post_processing = chain;
+    }
+
+    /// Gets a mutable reference to the palette and filters that [post_process_frame](#method.post_process_frame)
+    /// passes frames through, for adding filters to the end of the chain
+    #[cfg(feature = "filters")]
+    pub fn get_post_processing_mut(&mut self) -> &mut PostProcessChain {
+        return &mut self.post_processing;
+    }
+
+    /// Posts a message, such as a notification that a state was saved, that lasts for a duration of emulated time.
+    /// See the [messages module](../video/messages/index.html) for drawing messages into the frames.
+    pub fn post_message(&mut self, text: &str, duration: Duration) {
+        self.messages.post(text, duration);
+    }
+
+    /// Gets a mutable reference to the messages that have been posted and haven't expired yet, for reading them or
+    /// enabling rendering
+    pub fn get_messages_mut(&mut self) -> &mut MessageQueue {
+        return &mut self.messages;
+    }
+
+    /// Gets a reference to the video backend that the PPU draws to
+    pub fn get_video_backend(&self) -> &V {
+        return &self.video;
+    }
+
+    /// Gets a mutable reference to the video backend that the PPU draws to
+    pub fn get_video_backend_mut(&mut self) -> &mut V {
+        return &mut self.video;
+    }
+
+    /// Gets a reference to the audio sink that the APU's output is pushed to
+    pub fn get_audio_sink(&self) -> &A {
+        return &self.audio;
+    }
+
+    /// Gets a mutable reference to the audio sink that the APU's output is pushed to
+    pub fn get_audio_sink_mut(&mut self) -> &mut A {
+        return &mut self.audio;
+    }
+
+    /// Reads from the CPU's address space, with the same side effects as a read by the CPU, such as acknowledging the
+    /// PPU's vertical blank flag or shifting a controller's state. For tools that drive the bus directly, like fuzzers.
+    pub fn cpu_bus_read(&mut self, address: u16) -> u8 {
+        return self.bus.read(address);
+    }
+
+    /// Writes to the CPU's address space, with the same effects as a write by the CPU
+    pub fn cpu_bus_write(&mut self, address: u16, data: u8) {
+        self.bus.write(address, data);
+    }
+
+    /// Reads from the PPU's address space through the same mirroring as rendering, for tile editors and trainers.
+    /// Unlike reading through $2007, the PPU's read buffer and address latches are left untouched.
+    pub fn debug_vram_read(&mut self, address: u16) -> u8 {
+        return self.bus.ppu.debug_vram_read(&mut self.bus.cartridge, address);
+    }
+
+    /// Writes to the PPU's address space through the same mirroring as rendering, for tile editors and trainers
+    /// that change the nametables, palettes or character RAM while the game runs. Unlike writing through $2007,
+    /// the PPU's address latches are left untouched.
+    pub fn debug_vram_write(&mut self, address: u16, data: u8) {
+        self.bus.ppu.debug_vram_write(&mut self.bus.cartridge, address, data);
     }
 
     /// Resets the state of the console
     pub fn reset(&mut self) {
-        self.cycle_count = 0;
+        // The CPU keeps its place among the PPU's cycles, so that its cycles stay evenly spaced on the master clock
+        // that the APU and mappers are timed by
+        self.cycle_count %= 3;
         self.cpu.reset(&mut self.bus);
         self.bus.reset();
+        self.cycle_accounting.reset();
+        self.latency.reset();
+    }
+
+    /// Saves the state of the running game: the CPU, PPU and APU, the RAM, the cartridge's RAM and mapper registers,
+    /// the controllers and the master clock. Loading it with [load_state](#method.load_state) carries on from exactly
+    /// the same point, which is what save states, rewinding, run-ahead and netplay are built on.
+    ///
+    /// The state starts with the "GCNS" magic number and the [save state version](../state/constant.SAVE_STATE_VERSION.html),
+    /// followed by a flag for whether the state has a thumbnail and the state of the components. The options that the NES is run with and the frame in the video backend
+    /// aren't saved, and the state of a given cartridge is always the same size, so frontends can allocate it once.
+    pub fn save_state(&self) -> Vec<u8> {
+        return self.save_state_with(None);
+    }
+
+    /// Saves the state of the running game like [save_state](#method.save_state), along with a half size thumbnail of
+    /// the current frame that [peek_state_thumbnail](../state/fn.peek_state_thumbnail.html) can read without loading
+    /// the state. The thumbnail is left out if the video backend doesn't keep the colours of the frame.
+    pub fn save_state_with_thumbnail(&self) -> Vec<u8> {
+        let (width, height) = self.output_transform.get_output_dimensions();
+        let thumbnail = self
+            .video
+            .get_rgba_frame()
+            .map(|frame| StateThumbnail::from_rgba_frame(&frame, width, height));
+        return self.save_state_with(thumbnail.as_ref());
+    }
+
+    /// Saves the state of the running game after the header, which holds the thumbnail if there is one
+    fn save_state_with(&self, thumbnail: Option<&StateThumbnail>) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        write_state_header(&mut state, thumbnail);
+        state.write_u64(self.cycle_count);
+        state.write_u64(self.master_clock);
+        state.write_bool(self.drawing_eye == Eye::Right);
+        state.write_bool(self.frame_eye == Eye::Right);
+        save_cpu_state(&self.cpu, &mut state);
+        self.bus.save_state(&mut state);
+        return state.into_bytes();
+    }
+
+    /// Restores the state of a game saved by [save_state](#method.save_state) or
+    /// [save_state_with_thumbnail](#method.save_state_with_thumbnail), which has to have been saved with the
+    /// same cartridge. Fails, leaving the NES as it was, if the state isn't a save state, is from a different version
+    /// of the format or another cartridge, or is corrupt. The frame in the video backend is kept until the next one is drawn.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut state = StateReader::new(data);
+        read_state_header(&mut state)?;
+        let cycle_count = state.read_u64()?;
+        let master_clock = state.read_u64()?;
+        let drawing_eye = if state.read_bool()? { Eye::Right } else { Eye::Left };
+        let frame_eye = if state.read_bool()? { Eye::Right } else { Eye::Left };
+        let cpu = load_cpu_state(&mut state)?;
+        // The bus is loaded into a copy, so that a state that fails partway through doesn't leave it half loaded
+        let mut bus = self.bus.clone();
+        bus.load_state(&mut state)?;
+        state.finish()?;
+
+        self.cycle_count = cycle_count;
+        self.master_clock = master_clock;
+        self.drawing_eye = drawing_eye;
+        self.frame_eye = frame_eye;
+        self.cpu = cpu;
+        self.bus = bus;
+        self.bank_mapping = self.bus.cartridge.get_bank_mapping();
+        return Ok(());
+    }
+
+    /// Gets the status that a test ROM using blargg's framework, like the apu_test suite, reports through cartridge RAM,
+    /// or None if the ROM hasn't reported one. See [TestRomStatus](enum.TestRomStatus.html) for what it means.
+    pub fn get_test_rom_status(&self) -> Option<TestRomStatus> {
+        return TestRomStatus::read(|address| self.bus.cartridge.program_read(address));
+    }
+
+    /// Schedules the console to be reset once a number of frames have completed, counting the frame in progress,
+    /// like pressing the reset button at that point. For automating test ROMs that ask for reset to be pressed
+    /// partway through. Scheduling another reset replaces the last one, and scheduling one after zero frames
+    /// resets the console straight away.
+    pub fn schedule_reset(&mut self, after_frames: u64) {
+        if after_frames == 0 {
+            self.scheduled_reset = None;
+            self.reset();
+        } else {
+            self.scheduled_reset = Some(self.get_frame_count() + after_frames);
+        }
+    }
+
+    /// Cancels a reset scheduled with [schedule_reset](#method.schedule_reset)
+    pub fn cancel_scheduled_reset(&mut self) {
+        self.scheduled_reset = None;
+    }
+
+    /// Gets the frame count that a scheduled reset happens at, or None if no reset is scheduled
+    pub fn get_scheduled_reset(&self) -> Option<u64> {
+        return self.scheduled_reset;
+    }
+
+    /// Raises the CPU's interrupt request line, as a mapper or the APU would, for test automation. Like any other
+    /// interrupt request, it is taken once the current instruction completes, unless the CPU has interrupts disabled.
+    pub fn inject_irq(&mut self) {
+        self.cpu.interrupt_request();
+    }
+
+    /// Triggers a non-maskable interrupt, as the PPU does at the start of vertical blank, for test automation.
+    /// It is taken once the current instruction completes, whether or not the PPU has NMIs enabled.
+    pub fn inject_nmi(&mut self) {
+        self.cpu.non_maskable_interrupt_request();
     }
 }
 
@@ -221,36 +1392,145 @@ impl Bus {
         self.ppu.reset();
         // self.apu.reset();
     }
+
+    /// Writes the state of the console components on the bus to a save state. The input latency, execution trace and
+    /// the flags that only last until the end of the CPU cycle or frame aren't saved.
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram[..]);
+        state.write_u8(self.open_bus);
+        state.write_bool(self.dma_status.is_some());
+        let dma_status = self.dma_status.unwrap_or_else(|| DmaStatus::new(0x00));
+        state.write_bool(dma_status.dma_wait);
+        state.write_u16(dma_status.dma_start_address);
+        state.write_u8(dma_status.dma_count);
+        state.write_u8(dma_status.dma_buffer);
+        state.write_u8(self.dmc_dma_cycles);
+        state.write_u8(self.expansion_output);
+        let (last_read_address, last_read_value) = self.last_read.unwrap_or_default();
+        state.write_bool(self.last_read.is_some());
+        state.write_u16(last_read_address);
+        state.write_u8(last_read_value);
+        self.input_device_one.save_state(state);
+        self.input_device_two.save_state(state);
+        self.ppu.save_state(state);
+        self.apu.save_state(state);
+        self.cartridge.save_state(state);
+    }
+
+    /// Restores the state of the console components on the bus from a save state
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        *self.ram = state.read_array()?;
+        self.open_bus = state.read_u8()?;
+        let dma_active = state.read_bool()?;
+        let dma_status = DmaStatus {
+            dma_wait: state.read_bool()?,
+            dma_start_address: state.read_u16()?,
+            dma_count: state.read_u8()?,
+            dma_buffer: state.read_u8()?,
+        };
+        self.dma_status = if dma_active { Some(dma_status) } else { None };
+        self.dmc_dma_cycles = state.read_u8()?;
+        self.expansion_output = state.read_u8()?;
+        let last_read = state.read_bool()?;
+        let last_read_address = state.read_u16()?;
+        let last_read_value = state.read_u8()?;
+        self.last_read = if last_read { Some((last_read_address, last_read_value)) } else { None };
+        self.input_device_one.load_state(state)?;
+        self.input_device_two.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.apu.load_state(state)?;
+        return self.cartridge.load_state(state);
+    }
+
+    /// Reads the byte that the DMC channel requested from memory and passes it back to the APU
+    fn fetch_dmc_sample(&mut self) {
+        if let Some(address) = self.apu.get_dmc_sample_request() {
+            let data = self.read(address);
+            self.apu.load_dmc_sample(data);
+        }
+    }
+
+    /// Records an event if execution is being traced
+    #[cfg(feature = "debugger")]
+    fn record_trace(&mut self, event: TraceEvent) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(event);
+        }
+    }
 }
 
 impl Interface6502 for Bus {
     fn read(&mut self, address: u16) -> u8 {
-        match address {
+        let value = match address {
             0x0000..=0x1fff => self.ram[usize::from(address) & 0x07ff], // Addresses 0x0800-0x1fff mirror the 2KiB of ram
-            0x2000..=0x3fff => self.ppu.read(&mut self.cartridge, address), // Mirroring will be done by the ppu
-            0x4000..=0x4015 => self.apu.read(address),
-            0x4016 => self.input_device_one.poll(0x00), // Read one bit from the first controller TODO: Open Bus Behaviour
-            0x4017 => self.input_device_two.poll(0x00), // Read one bit from the second controller
-            0x4018..=0x401f => 0x00,                    // Usually disabled on the nes TODO: Decide how to handle these
-            0x4020..=0xffff => self.cartridge.program_read(address), // Addresses above 0x4020 read from the cartridge
+            0x2000..=0x3fff => {
+                // Reading PPUSTATUS, at $2002 and its mirrors, is how games wait for vertical blank
+                self.activity |= address & 0x0007 == 0x0002;
+                self.ppu.read(&mut self.cartridge, address) // Mirroring will be done by the ppu
+            }
+            0x4000..=0x4014 => self.open_bus,                          // The APU and DMA registers are write only
+            0x4015 => self.apu.read(address) | (self.open_bus & 0x20), // Bit 5 of the APU status isn't driven
+            0x4016 => self.input_device_one.poll(self.open_bus),       // Read one bit from the first controller
+            0x4017 => self.input_device_two.poll(self.open_bus),       // Read one bit from the second controller
+            0x4018..=0x401f => self.open_bus,                          // Usually disabled on the nes
+            0x4020..=0xffff => self.cartridge.program_read(address),   // Addresses above 0x4020 read from the cartridge
+        };
+        self.open_bus = value;
+        self.last_read = Some((address, value));
+        match address {
+            0xfffa => self.vector_read = Some(InterruptKind::Nmi),
+            0xfffe => self.vector_read = Some(InterruptKind::Irq),
+            _ => {}
         }
+        #[cfg(feature = "debugger")]
+        self.record_trace(TraceEvent::Read {
+            cycle: self.apu.get_cycle_count(),
+            address,
+            value,
+        });
+        return value;
     }
 
     fn write(&mut self, address: u16, data: u8) {
+        self.open_bus = data;
+        let last_read = self.last_read.take();
+        #[cfg(feature = "debugger")]
+        self.record_trace(TraceEvent::Write {
+            cycle: self.apu.get_cycle_count(),
+            address,
+            value: data,
+        });
         match address {
-            0x0000..=0x1fff => self.ram[usize::from(address) & 0x07ff] = data, // Addresses 0x0800-0x1fff mirror the 2KiB of ram
+            0x0000..=0x1fff => {
+                // Addresses 0x0800-0x1fff mirror the 2KiB of ram
+                let byte = &mut self.ram[usize::from(address) & 0x07ff];
+                self.activity |= *byte != data;
+                *byte = data;
+            }
             0x2000..=0x3fff => self.ppu.write(&mut self.cartridge, address, data), // Mirroring will be done by the ppu
             0x4000..=0x4013 => self.apu.write(address, data),
             0x4014 => self.dma_status = Some(DmaStatus::new(data)), // Begins the OAM DMA operation at the data page
             0x4015 => self.apu.write(address, data),                // Write to the APU's sound channel register
             0x4016 => {
+                self.expansion_output = data & 0x07;
                 // Set the shift register reload latch on the both controllers
+                self.input_latched |= data & 0x01 != 0;
+                self.activity |= data & 0x01 != 0;
                 self.input_device_one.latch(data);
                 self.input_device_two.latch(data);
             }
             0x4017 => self.apu.write(address, data), // Writing to the second controller address is the APU frame counter control
             0x4018..=0x401f => warn!("Write to disabled address 0x{:04X}", address), // Usually disabled on the nes
-            0x4020..=0xffff => self.cartridge.program_write(address, data), // Addresses above 0x4020 write to the cartridge
+            0x4020..=0xffff => {
+                // The CPU only makes one write for read-modify-write instructions, so the unchanged value that the real
+                // CPU writes first is made here, for mappers like the MMC1 that react to both
+                if let Some((read_address, value)) = last_read {
+                    if read_address == address {
+                        self.cartridge.program_write(address, value);
+                    }
+                }
+                self.cartridge.program_write(address, data); // Addresses above 0x4020 write to the cartridge
+            }
         }
     }
 }
@@ -267,4 +1547,886 @@ impl DmaStatus {
     }
 }
 
-// TODO: Write DMA tests
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::ChannelOutputs;
+    use crate::cartridge::test_utils::*;
+    use crate::cartridge::Mirroring;
+    use crate::input::buttons;
+    use crate::state::peek_state_thumbnail;
+    #[cfg(feature = "filters")]
+    use crate::video::postprocess::NearestScaler;
+    #[cfg(feature = "filters")]
+    use crate::video::NES_COLOUR_MAP_WEB;
+    use crate::video::{NullVideoBackend, NES_SCREEN_WIDTH};
+
+    /// Creates a NES with a mock cartridge that reads zeroes from program and character memory
+    fn get_test_nes() -> Nes {
+        return Nes::new(get_mock_cartridge(MapperMock {
+            program_read_stub: |_, _| 0x00,
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_debug_vram() {
+        let mut nes = get_test_nes();
+        // Horizontal mirroring maps $2400 onto $2000, and $2C00 onto $2800
+        nes.debug_vram_write(0x2405, 0x42);
+        assert_eq!(0x42, nes.debug_vram_read(0x2005));
+        assert_eq!(0x00, nes.debug_vram_read(0x2805));
+        // The sprite palettes' first entries mirror the background palettes'
+        nes.debug_vram_write(0x3f10, 0x2a);
+        assert_eq!(0x2a, nes.debug_vram_read(0x3f00));
+        // Reads through $2007 are unaffected
+        assert_eq!(0x00, nes.bus.ppu.read(&mut nes.bus.cartridge, 0x2007));
+    }
+
+    #[test]
+    fn test_schedule_reset() {
+        let mut nes = get_test_nes();
+        nes.schedule_reset(2);
+        assert_eq!(Some(2), nes.get_scheduled_reset());
+        nes.frame();
+        assert!(nes.cycle_count > 0);
+        nes.frame();
+        // The reset happened as the second frame completed, restarting the cycle count but not the master clock,
+        // with the CPU keeping its place among the PPU's cycles
+        assert!(nes.cycle_count <= 3);
+        assert!(nes.master_clock_cycles() > 341 * 262 * 4);
+        assert_eq!(nes.cycle_count % 3, nes.master_clock_cycles() / 4 % 3);
+        assert_eq!(None, nes.get_scheduled_reset());
+
+        nes.schedule_reset(1);
+        nes.cancel_scheduled_reset();
+        nes.frame();
+        assert!(nes.cycle_count > 3);
+    }
+
+    #[test]
+    fn test_inject_nmi() {
+        // Both the NMI vector at $FFFA and the reset vector at $FFFC point to $8000, which is followed by NOPs
+        let mut nes = Nes::new(get_mock_cartridge(MapperMock {
+            program_read_stub: |address, _| match address {
+                0xfffb | 0xfffd => 0x80,
+                0xfffa | 0xfffc => 0x00,
+                _ => 0xea, // NOP
+            },
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        }));
+        (0..100).for_each(|_| nes.cycle());
+        assert_eq!(0x00, nes.bus.ram[0x01fd]);
+        nes.inject_nmi();
+        (0..100).for_each(|_| nes.cycle());
+        // Taking the interrupt pushed the address of the NOPs it interrupted onto the stack
+        assert_eq!(0x80, nes.bus.ram[0x01fd]);
+    }
+
+    #[test]
+    #[cfg(feature = "filters")]
+    fn test_post_process_frame() {
+        // The frame is kept for the chain even though the video backend throws every pixel away
+        let mut nes = Nes::with_video_backend(*get_test_nes().bus.cartridge, NullVideoBackend);
+        nes.get_post_processing_mut().push(Box::new(NearestScaler::new(2)));
+        nes.set_output_transform(Rotation::Clockwise90, false, false);
+        let image = nes.post_process_frame();
+        assert_eq!((480, 512), (image.width, image.height));
+        assert_eq!(NES_COLOUR_MAP_WEB[0x00], image.pixels[0..4]);
+
+        // Emphasising red with rendering enabled darkens the green and blue of the backdrop
+        nes.get_post_processing_mut().clear();
+        nes.cpu_bus_write(0x2001, 0x3e);
+        nes.frame();
+        nes.frame();
+        let [red, green, blue, _] = NES_COLOUR_MAP_WEB[0x00];
+        assert_eq!([red, green * 3 / 4, blue * 3 / 4, 0xff], nes.post_process_frame().pixels[0..4]);
+    }
+
+    #[test]
+    fn test_controller_connection() {
+        let mut nes = get_test_nes();
+        assert!(!nes.is_controller_one_connected());
+        // The open bus bits usually come from the high byte of the address, which the CPU reads just before
+        nes.bus.open_bus = 0x40;
+        assert_eq!(0x40, nes.cpu_bus_read(0x4016));
+        nes.update_controller_one(Some(0x01));
+        assert!(nes.is_controller_one_connected());
+        assert!(!nes.is_controller_two_connected());
+        nes.cpu_bus_write(0x4016, 0x01);
+        nes.cpu_bus_write(0x4016, 0x00);
+        nes.bus.open_bus = 0x40;
+        assert_eq!(0x41, nes.cpu_bus_read(0x4016));
+        nes.update_controller_one(None);
+        assert!(!nes.is_controller_one_connected());
+    }
+
+    #[test]
+    fn test_controller_remap() {
+        let mut nes = get_test_nes();
+        nes.set_controller_one_remap(InputRemap::new().swap_a_b());
+        let input = FrameInput {
+            controller_one: Some(buttons::A),
+            controller_two: Some(buttons::A),
+        };
+        let remapped = nes.remap_frame_input(input);
+        assert_eq!(Some(buttons::B), remapped.controller_one);
+        assert_eq!(Some(buttons::A), remapped.controller_two);
+        // The remap is applied to live input, but not to movie input, which is already remapped
+        let read_controller_one = |nes: &mut Nes| {
+            nes.cpu_bus_write(0x4016, 0x01);
+            nes.cpu_bus_write(0x4016, 0x00);
+            return (0..8).fold(0, |state, bit| state | (nes.cpu_bus_read(0x4016) & 0x01) << bit);
+        };
+        nes.update_controller_one(input.controller_one);
+        assert_eq!(buttons::B, read_controller_one(&mut nes));
+        nes.update_frame_input(input);
+        assert_eq!(buttons::A, read_controller_one(&mut nes));
+    }
+
+    #[test]
+    fn test_run_until_ppu_position() {
+        let mut nes = get_test_nes();
+        nes.run_until_vblank();
+        assert_eq!((241, 2), nes.get_ppu_position());
+        assert_eq!(0x80, nes.cpu_bus_read(0x2002) & 0x80);
+        let frame_count = nes.get_frame_count();
+        nes.run_until_scanline(100).unwrap();
+        assert_eq!((100, 0), nes.get_ppu_position());
+        assert_eq!(frame_count + 1, nes.get_frame_count());
+        nes.run_until_scanline(100).unwrap();
+        assert_eq!(frame_count + 2, nes.get_frame_count());
+        assert!(nes.run_until_scanline(262).is_err());
+        nes.set_paused(true);
+        nes.run_until_vblank();
+        assert_eq!((100, 0), nes.get_ppu_position());
+    }
+
+    #[test]
+    fn test_video_info() {
+        let mut nes = get_test_nes();
+        let info = nes.video_info();
+        assert_eq!((256, 240, 240), (info.width, info.height, info.active_lines));
+        assert!((info.refresh_rate - NES_FRAME_RATE).abs() < 1e-9);
+        assert!((info.display_aspect_ratio() - 256.0 * 8.0 / 7.0 / 240.0).abs() < 1e-9);
+        nes.set_ppu_config(PpuConfig {
+            hide_overscan: true,
+            ..Default::default()
+        });
+        nes.set_output_transform(Rotation::Clockwise90, false, false);
+        let rotated = nes.video_info();
+        assert_eq!((240, 256, 224), (rotated.width, rotated.height, rotated.active_lines));
+        assert!((rotated.display_aspect_ratio() * info.display_aspect_ratio() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_screen_region_hash() {
+        let mut nes = get_test_nes();
+        nes.frame();
+        // The screen is a single colour, so regions of the same size hash the same wherever they are
+        let region = nes.screen_region_hash(0, 0, 16, 16).unwrap();
+        assert_eq!(region, nes.screen_region_hash(240, 224, 16, 16).unwrap());
+        assert_ne!(region, nes.screen_region_hash(0, 0, 16, 8).unwrap());
+        nes.video.draw_pixel(5, 5, 0x16);
+        assert_ne!(region, nes.screen_region_hash(0, 0, 16, 16).unwrap());
+        assert_eq!(region, nes.screen_region_hash(240, 224, 16, 16).unwrap());
+        assert!(nes.screen_region_hash(241, 0, 16, 16).is_err());
+        let nes = Nes::with_video_backend(*nes.bus.cartridge, NullVideoBackend);
+        assert!(nes.screen_region_hash(0, 0, 16, 16).is_err());
+    }
+
+    #[test]
+    fn test_pixel_owners() {
+        // Sprites are drawn from the left pattern table, which is solid, and the background from the right, which is empty
+        let mut nes = Nes::new(get_mock_cartridge(MapperMock {
+            program_read_stub: |_, _| 0x00,
+            character_read_stub: |address, _| if address < 0x1000 { 0xff } else { 0x00 },
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        }));
+        assert_eq!(None, nes.get_pixel_owners());
+        nes.set_pixel_owner_tracking(true);
+        nes.cpu_bus_write(0x2003, 0x00);
+        for i in 0..=0xffu8 {
+            // Sprite 5 is at (100, 50) and the rest are hidden below the screen
+            let value = match i {
+                0x14 => 50,
+                0x17 => 100,
+                _ if i % 4 == 0 => 0xff,
+                _ => 0x00,
+            };
+            nes.cpu_bus_write(0x2004, value);
+        }
+        nes.cpu_bus_write(0x2000, 0x10);
+        nes.cpu_bus_write(0x2001, 0x1e);
+        nes.frame();
+        nes.frame();
+        let pixel_owners = nes.get_pixel_owners().unwrap();
+        assert_eq!(NES_SCREEN_DIMENSIONS, pixel_owners.len());
+        // Sprites are drawn on the scanline after their y coordinate
+        assert_eq!(PixelOwner::Sprite(5), pixel_owners[100 + 51 * NES_SCREEN_WIDTH]);
+        assert_eq!(PixelOwner::Sprite(5), pixel_owners[107 + 58 * NES_SCREEN_WIDTH]);
+        assert_eq!(PixelOwner::Backdrop, pixel_owners[108 + 51 * NES_SCREEN_WIDTH]);
+        assert_eq!(PixelOwner::Backdrop, pixel_owners[100 + 50 * NES_SCREEN_WIDTH]);
+        nes.set_pixel_owner_tracking(false);
+        assert_eq!(None, nes.get_pixel_owners());
+    }
+
+    #[test]
+    fn test_sprites_from_right_pattern_table() {
+        // Sprites are drawn from the right pattern table, which is solid, and the background from the left, which is empty
+        let mut nes = Nes::new(get_mock_cartridge(MapperMock {
+            program_read_stub: |_, _| 0x00,
+            character_read_stub: |address, _| if address >= 0x1000 { 0xff } else { 0x00 },
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        }));
+        nes.set_pixel_owner_tracking(true);
+        nes.cpu_bus_write(0x2003, 0x00);
+        for i in 0..=0xffu8 {
+            // Sprite 5 is at (100, 50) and the rest are hidden below the screen
+            let value = match i {
+                0x14 => 50,
+                0x17 => 100,
+                _ if i % 4 == 0 => 0xff,
+                _ => 0x00,
+            };
+            nes.cpu_bus_write(0x2004, value);
+        }
+        nes.cpu_bus_write(0x2000, 0x08);
+        nes.cpu_bus_write(0x2001, 0x1e);
+        nes.frame();
+        nes.frame();
+        let pixel_owners = nes.get_pixel_owners().unwrap();
+        assert_eq!(PixelOwner::Sprite(5), pixel_owners[100 + 51 * NES_SCREEN_WIDTH]);
+        assert_eq!(PixelOwner::Sprite(5), pixel_owners[107 + 58 * NES_SCREEN_WIDTH]);
+        assert_eq!(PixelOwner::Backdrop, pixel_owners[108 + 51 * NES_SCREEN_WIDTH]);
+    }
+
+    #[test]
+    fn test_frame_cycle_report() {
+        // The reset and NMI vectors point to $8000, which is followed by NOPs, so the NMI handler never returns
+        let mut nes = Nes::new(get_mock_cartridge(MapperMock {
+            program_read_stub: |address, _| match address {
+                0xfffb | 0xfffd => 0x80,
+                0xfffa | 0xfffc => 0x00,
+                _ => 0xea, // NOP
+            },
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        }));
+        assert_eq!(None, nes.get_frame_cycle_report());
+        nes.frame();
+        nes.cpu_bus_write(0x4014, 0x02);
+        nes.frame();
+        let report = nes.get_frame_cycle_report().unwrap();
+        assert_eq!(nes.get_frame_count() - 1, report.frame);
+        assert!((29_780..=29_781).contains(&report.cpu_cycles));
+        assert!((513..=514).contains(&report.oam_dma_cycles));
+        assert_eq!((0, 0), (report.nmi_count, report.nmi_handler_cycles));
+        nes.inject_nmi();
+        nes.frame();
+        assert_eq!(1, nes.get_frame_cycle_report().unwrap().nmi_count);
+        nes.frame();
+        let report = nes.get_frame_cycle_report().unwrap();
+        assert_eq!(report.cpu_cycles, report.nmi_handler_cycles);
+        assert_eq!(0, report.irq_handler_cycles);
+    }
+
+    #[test]
+    #[cfg(feature = "debugger")]
+    fn test_execution_trace() {
+        // The reset and NMI vectors point to $8000, which is followed by NOPs
+        let mut nes = Nes::new(get_mock_cartridge(MapperMock {
+            program_read_stub: |address, _| match address {
+                0xfffb | 0xfffd => 0x80,
+                0xfffa | 0xfffc => 0x00,
+                _ => 0xea, // NOP
+            },
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        }));
+        (0..100).for_each(|_| nes.cycle());
+        assert!(nes.take_execution_trace().is_empty());
+        nes.set_execution_trace(true);
+        (0..100).for_each(|_| nes.cycle());
+        let trace = nes.take_execution_trace();
+        let (index, program_counter) = trace
+            .iter()
+            .enumerate()
+            .find_map(|(index, event)| match event {
+                TraceEvent::Instruction { program_counter, opcode, .. } => {
+                    assert_eq!(0xea, *opcode);
+                    Some((index, *program_counter))
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            Some(&TraceEvent::Read {
+                cycle: trace[index].get_cycle(),
+                address: program_counter,
+                value: 0xea
+            }),
+            trace.get(index + 1)
+        );
+        nes.inject_nmi();
+        (0..100).for_each(|_| nes.cycle());
+        let trace = nes.take_execution_trace();
+        let index = trace.iter().position(|event| matches!(event, TraceEvent::Interrupt { .. })).unwrap();
+        assert!(matches!(
+            trace[index],
+            TraceEvent::Interrupt {
+                kind: InterruptKind::Nmi,
+                ..
+            }
+        ));
+        // Pushing the return address onto the stack is the first thing the CPU does
+        assert!(matches!(
+            trace[index + 1],
+            TraceEvent::Write {
+                address: 0x01fd,
+                value: 0x80,
+                ..
+            }
+        ));
+        // Frames are traced as they are completed
+        nes.frame();
+        let frame_count = nes.get_frame_count();
+        assert_eq!(
+            vec![frame_count],
+            nes.take_execution_trace()
+                .iter()
+                .filter_map(|event| match event {
+                    TraceEvent::Frame { frame, .. } => Some(*frame),
+                    _ => None,
+                })
+                .collect::<Vec<u64>>()
+        );
+        nes.set_execution_trace(false);
+        nes.cycle();
+        assert!(nes.take_execution_trace().is_empty());
+    }
+
+    /// Runs the NES until it records a DMA finished event, returning all of the events recorded
+    fn run_until_dma_finished(nes: &mut Nes) -> Vec<NesEvent> {
+        let mut events = Vec::new();
+        for _ in 0..2000 {
+            nes.cycle();
+            events.append(&mut nes.take_events());
+            if let Some(NesEvent::DmaFinished { .. }) = events.last() {
+                return events;
+            }
+        }
+        panic!("DMA did not finish");
+    }
+
+    #[test]
+    fn test_dma_events() {
+        let mut nes = get_test_nes();
+        nes.set_event_recording(true);
+        nes.bus.write(0x4014, 0x02);
+        let events = run_until_dma_finished(&mut nes);
+        assert_eq!(2, events.len());
+        match (events[0], events[1]) {
+            (NesEvent::DmaStarted { page: 0x02, cycle: start }, NesEvent::DmaFinished { page: 0x02, cycle: end }) => {
+                // Copying 256 bytes takes 512 CPU cycles after the one DMA starts on, each of which is twelve master clock cycles
+                assert_eq!(512 * 12, end - start);
+            }
+            _ => panic!("Unexpected DMA events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn test_dma_events_disabled() {
+        let mut nes = get_test_nes();
+        nes.bus.write(0x4014, 0x02);
+        (0..2000).for_each(|_| nes.cycle());
+        assert!(nes.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_mid_dma() {
+        let mut nes = get_test_nes();
+        nes.set_event_recording(true);
+        nes.bus.write(0x4014, 0x02);
+        (0..600).for_each(|_| nes.cycle());
+        let mut snapshot = nes.clone();
+        snapshot.take_events();
+        // The snapshot should pick up the DMA where it was left rather than starting it again
+        assert_eq!(run_until_dma_finished(&mut nes)[1..], run_until_dma_finished(&mut snapshot)[..]);
+    }
+
+    #[test]
+    fn test_dma_from_register_pages() {
+        // Games can point DMA at any page, including the PPU and APU registers, which must not bring the NES down
+        for page in &[0x20, 0x3f, 0x40, 0xff] {
+            let mut nes = get_test_nes();
+            nes.set_event_recording(true);
+            nes.bus.write(0x4014, *page);
+            run_until_dma_finished(&mut nes);
+        }
+    }
+
+    #[test]
+    fn test_dma_from_ppu_registers_has_side_effects() {
+        let mut nes = get_test_nes();
+        nes.set_event_recording(true);
+        // Fill part of a nametable with its offsets, then point the vram address back at the start of it
+        nes.bus.write(0x2006, 0x24);
+        nes.bus.write(0x2006, 0x00);
+        (0..0x40).for_each(|offset| nes.bus.write(0x2007, offset));
+        nes.bus.write(0x2006, 0x24);
+        nes.bus.write(0x2006, 0x00);
+        // Copying from the PPU registers reads $2007 32 times, moving the vram address along with each read
+        nes.bus.write(0x4014, 0x20);
+        run_until_dma_finished(&mut nes);
+        // Reads from $2007 are buffered, so the next one returns the byte that the final DMA read fetched
+        assert_eq!(0x1f, nes.bus.read(0x2007));
+    }
+
+    #[test]
+    fn test_write_only_registers_read_open_bus() {
+        let mut nes = get_test_nes();
+        nes.bus.write(0x4000, 0x3c);
+        assert_eq!(0x3c, nes.bus.read(0x4000));
+        assert_eq!(0x3c, nes.bus.read(0x4014));
+        assert_eq!(0x3c, nes.bus.read(0x401f));
+        nes.bus.read(0x0000);
+        assert_eq!(0x00, nes.bus.read(0x4018));
+    }
+
+    /// Starts a one byte DMC sample at the fastest rate, with the DMC interrupt enabled
+    fn start_dmc_sample(nes: &mut Nes) {
+        nes.bus.write(0x4010, 0x8f);
+        nes.bus.write(0x4013, 0x00);
+        nes.bus.write(0x4015, 0x10);
+    }
+
+    #[test]
+    fn test_dmc_fetch_stalls_oam_dma() {
+        let mut nes = get_test_nes();
+        nes.set_event_recording(true);
+        nes.bus.write(0x4014, 0x02);
+        (0..30).for_each(|_| nes.cycle());
+        start_dmc_sample(&mut nes);
+        let events = run_until_dma_finished(&mut nes);
+        match (events[0], events[1]) {
+            (NesEvent::DmaStarted { cycle: start, .. }, NesEvent::DmaFinished { cycle: end, .. }) => {
+                assert_eq!((512 + DMC_DMA_CYCLES_DURING_OAM_DMA as u64) * 12, end - start);
+            }
+            _ => panic!("Unexpected DMA events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn test_dmc_fetch_without_stalls() {
+        let mut nes = get_test_nes();
+        nes.set_accuracy(AccuracyPreset::Speed.into());
+        start_dmc_sample(&mut nes);
+        (0..3).for_each(|_| nes.cycle());
+        // Without stalls the fetch happens on the same CPU cycle as the request
+        assert!(nes.bus.apu.get_dmc_sample_request().is_none());
+        assert_eq!(0, nes.bus.dmc_dma_cycles);
+
+        let mut nes = get_test_nes();
+        start_dmc_sample(&mut nes);
+        (0..3).for_each(|_| nes.cycle());
+        assert!(nes.bus.apu.get_dmc_sample_request().is_some());
+        assert_eq!(DMC_DMA_CYCLES, nes.bus.dmc_dma_cycles);
+    }
+
+    /// Creates a NES with an NROM cartridge that has battery backed program RAM, running the program from $8000
+    fn get_battery_backed_nes(program: &[u8]) -> Nes {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 0x01, 0x01, 0x02, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut program_rom = vec![0xea; 0x4000];
+        program_rom[..program.len()].copy_from_slice(program);
+        // The reset vector points at the start of the program
+        program_rom[0x3ffc..0x3ffe].copy_from_slice(&[0x00, 0x80]);
+        rom.extend(program_rom);
+        rom.resize(rom.len() + 0x2000, 0x00);
+        return Nes::new(Cartridge::load_from_reader(rom.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_save_dirty() {
+        // LDA #$01, STA $6000, then loop forever
+        let mut nes = get_battery_backed_nes(&[0xa9, 0x01, 0x8d, 0x00, 0x60, 0x4c, 0x05, 0x80]);
+        nes.set_event_recording(true);
+        assert!(!nes.is_save_dirty());
+
+        (0..100).for_each(|_| nes.cycle());
+        assert_eq!(1, nes.sram_generation());
+        assert!(nes.is_save_dirty());
+        assert!(matches!(nes.take_events()[..], [NesEvent::SaveDataDirty { .. }]));
+
+        nes.mark_save_flushed();
+        assert!(!nes.is_save_dirty());
+    }
+
+    #[test]
+    fn test_save_data() {
+        let mut nes = get_battery_backed_nes(&[0x4c, 0x00, 0x80]);
+        assert!(nes.is_battery_backed());
+        assert!(nes.has_save_data());
+        let mut save_data = vec![0x00; 0x2000];
+        save_data[0x10] = 0x42;
+        nes.load_save_data(&save_data).unwrap();
+        assert_eq!(Some(save_data), nes.get_save_data());
+
+        let nes = get_test_nes();
+        assert!(!nes.is_battery_backed());
+        assert!(!nes.has_save_data());
+        assert!(nes.get_save_data().is_none());
+    }
+
+    /// Creates a NES with a battery backed NROM cartridge running a program that keeps the CPU, PPU, APU, controllers
+    /// and program RAM busy, changing the scroll and reading the first controller in its NMI handler
+    fn get_busy_nes() -> Nes {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 0x01, 0x01, 0x02, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut program_rom = vec![0xea; 0x4000];
+        #[rustfmt::skip]
+        let program = [
+            0xa9, 0x80, 0x8d, 0x00, 0x20, // LDA #$80, STA $2000
+            0xa9, 0x1e, 0x8d, 0x01, 0x20, // LDA #$1E, STA $2001
+            0xa9, 0x0f, 0x8d, 0x15, 0x40, // LDA #$0F, STA $4015
+            0xa9, 0xbf, 0x8d, 0x00, 0x40, // LDA #$BF, STA $4000
+            0xa9, 0x08, 0x8d, 0x03, 0x40, // LDA #$08, STA $4003
+            0xee, 0x10, 0x00, 0xad, 0x10, 0x00, // INC $0010, LDA $0010
+            0x8d, 0x02, 0x40, 0x8d, 0x00, 0x60, 0x4c, 0x19, 0x80, // STA $4002, STA $6000, JMP $8019
+            0xe6, 0x11, 0xa5, 0x11, 0x8d, 0x05, 0x20, 0x8d, 0x05, 0x20, // INC $11, LDA $11, STA $2005, STA $2005
+            0xa9, 0x01, 0x8d, 0x16, 0x40, 0xa9, 0x00, 0x8d, 0x16, 0x40, // LDA #$01, STA $4016, LDA #$00, STA $4016
+            0xad, 0x16, 0x40, 0x85, 0x12, 0x40, // LDA $4016, STA $12, RTI
+        ];
+        program_rom[..program.len()].copy_from_slice(&program);
+        // The NMI vector points at the handler after the main loop, and the reset vector at the start of the program
+        program_rom[0x3ffa..0x3ffe].copy_from_slice(&[0x28, 0x80, 0x00, 0x80]);
+        rom.extend(program_rom);
+        rom.resize(rom.len() + 0x2000, 0x00);
+        return Nes::new(Cartridge::load_from_reader(rom.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_save_state_round_trip() {
+        let mut nes = get_busy_nes();
+        nes.update_controller_one(Some(buttons::A | buttons::START));
+        (0..3).for_each(|_| {
+            nes.frame();
+        });
+        // Partway through a frame, and an instruction
+        (0..12345).for_each(|_| nes.cycle());
+        assert_ne!(0, nes.cpu.get_remaining_cycles());
+        nes.take_audio_samples();
+        let state = nes.save_state();
+        assert_eq!(b"GCNS", &state[..4]);
+
+        let mut loaded = get_busy_nes();
+        loaded.load_state(&state).unwrap();
+        assert_eq!(state, loaded.save_state());
+        // The frame in progress was partly drawn before the state was saved, so only the frames after it are compared
+        nes.frame();
+        loaded.frame();
+        for _ in 0..3 {
+            assert!(nes.frame()[..] == loaded.frame()[..]);
+        }
+        assert_eq!(nes.take_audio_samples(), loaded.take_audio_samples());
+        assert_eq!(nes.get_save_data(), loaded.get_save_data());
+        assert_eq!(nes.save_state(), loaded.save_state());
+        // The state of a cartridge is always the same size
+        assert_eq!(state.len(), nes.save_state().len());
+    }
+
+    #[test]
+    fn test_load_state_errors() {
+        let mut nes = get_busy_nes();
+        nes.frame();
+        let state = nes.save_state();
+        assert!(nes.load_state(b"not a save state").is_err());
+        let mut next_version = state.clone();
+        next_version[4] += 1;
+        assert!(nes.load_state(&next_version).is_err());
+        assert!(nes.load_state(&state[..state.len() - 1]).is_err());
+        let mut extended = state.clone();
+        extended.push(0x00);
+        assert!(nes.load_state(&extended).is_err());
+        // States can only be loaded with the cartridge they were saved with
+        assert!(get_test_nes().load_state(&state).is_err());
+        // A failed load leaves the NES as it was
+        assert_eq!(state, nes.save_state());
+    }
+
+    #[test]
+    fn test_save_state_with_thumbnail() {
+        let mut nes = get_busy_nes();
+        nes.frame();
+        let state = nes.save_state();
+        let with_thumbnail = nes.save_state_with_thumbnail();
+        let thumbnail = peek_state_thumbnail(&with_thumbnail).unwrap().unwrap();
+        assert_eq!((NES_SCREEN_WIDTH / 2, NES_SCREEN_HEIGHT / 2), (thumbnail.width, thumbnail.height));
+        assert_eq!(NES_SCREEN_DIMENSIONS, thumbnail.pixels.len());
+        assert_eq!(None, peek_state_thumbnail(&state).unwrap());
+        // The thumbnail is skipped when the state is loaded
+        nes.frame();
+        nes.load_state(&with_thumbnail).unwrap();
+        assert_eq!(state, nes.save_state());
+        // Backends that don't keep the colours of the frame save states without one
+        let nes = Nes::with_video_backend(*nes.bus.cartridge, NullVideoBackend);
+        assert_eq!(None, peek_state_thumbnail(&nes.save_state_with_thumbnail()).unwrap());
+    }
+
+    #[test]
+    fn test_hang_detection() {
+        // JMP $8000, looping forever without reading PPUSTATUS, latching the controllers or changing RAM
+        let mut nes = get_battery_backed_nes(&[0x4c, 0x00, 0x80]);
+        nes.set_event_recording(true);
+        nes.set_hang_detection(Some(3));
+        for _ in 0..2 {
+            nes.frame();
+        }
+        assert!(!nes.is_hung());
+        nes.frame();
+        assert!(nes.is_hung());
+        assert!(matches!(nes.take_events()[..], [NesEvent::GameHung { idle_frames: 3, .. }]));
+        nes.set_hang_detection(None);
+        assert!(!nes.is_hung());
+
+        // BIT $2002, JMP $8000, waiting for vertical blank that never comes because NMI is disabled
+        let mut nes = get_battery_backed_nes(&[0x2c, 0x02, 0x20, 0x4c, 0x00, 0x80]);
+        nes.set_hang_detection(Some(3));
+        for _ in 0..5 {
+            nes.frame();
+        }
+        assert!(!nes.is_hung());
+    }
+
+    #[test]
+    fn test_take_audio_samples() {
+        let mut nes = get_test_nes();
+        (0..300).for_each(|_| nes.cycle());
+        let samples = nes.take_audio_samples();
+        // One sample is produced every three PPU cycles
+        assert_eq!(100, samples.len());
+        assert!(samples.iter().all(|sample| (0.0..=1.0).contains(sample)));
+        assert!(nes.take_audio_samples().is_empty());
+    }
+
+    #[test]
+    fn test_skip_frame() {
+        let mut nes = get_test_nes();
+        nes.skip_frame();
+        nes.take_audio_samples();
+        nes.skip_frame();
+        assert_eq!(2, nes.get_frame_count());
+        assert!(nes.get_screen().iter().all(|pixel| *pixel == 0));
+        // The audio is produced as normal while frames are skipped
+        let skipped_samples = nes.take_audio_samples().len();
+        nes.frame();
+        assert!(nes.take_audio_samples().len().abs_diff(skipped_samples) <= 1);
+        assert!(nes.get_screen().iter().all(|pixel| *pixel != 0));
+    }
+
+    #[test]
+    fn test_apu_trace() {
+        let mut nes = get_test_nes();
+        nes.cpu_bus_write(0x4000, 0x30);
+        nes.set_apu_trace(true);
+        (0..30).for_each(|_| nes.cycle());
+        let cycle = nes.get_cpu_cycle_count();
+        nes.cpu_bus_write(0x4015, 0x0f);
+        nes.cpu_bus_write(0x4016, 0x01);
+        nes.cpu_bus_write(0x4017, 0x40);
+        let trace = nes.take_apu_trace();
+        assert_eq!(
+            vec![(cycle, 0x4015, 0x0f), (cycle, 0x4017, 0x40)],
+            trace.iter().map(|write| (write.cycle, write.register, write.value)).collect::<Vec<_>>()
+        );
+        assert!(nes.take_apu_trace().is_empty());
+        nes.set_apu_trace(false);
+        nes.cpu_bus_write(0x4015, 0x00);
+        nes.set_apu_trace(true);
+        assert!(nes.take_apu_trace().is_empty());
+    }
+
+    #[test]
+    fn test_frame_eye() {
+        let mut nes = get_test_nes();
+        nes.cpu_bus_write(0x4016, 0x02);
+        nes.frame();
+        // The frame that was being drawn when the shutters switched is still for the left eye
+        assert_eq!(Eye::Left, nes.get_frame_eye());
+        nes.frame();
+        assert_eq!(Eye::Right, nes.get_frame_eye());
+        nes.cpu_bus_write(0x4016, 0x01);
+        nes.frame();
+        nes.frame();
+        assert_eq!(Eye::Left, nes.get_frame_eye());
+    }
+
+    #[test]
+    fn test_run_until_samples() {
+        let mut nes = get_test_nes();
+        nes.set_audio_sample_rate(44_100.0);
+        assert_eq!(735, nes.run_until_samples(735).len());
+        // The samples were produced by the last cycle, so none are left over
+        assert_eq!(0, nes.audio.get_sample_count());
+        let cycle_count = nes.cycle_count;
+        assert_eq!(1, nes.run_until_samples(1).len());
+        // A sample is produced at most every 41 CPU cycles, or 123 PPU cycles
+        assert!(nes.cycle_count - cycle_count <= 3 * (NES_AUDIO_SAMPLE_RATE / 44_100.0).ceil() as u64);
+
+        nes.set_paused(true);
+        assert_eq!(vec![0.0; 100], nes.run_until_samples(100));
+    }
+
+    #[test]
+    fn test_stereo_panning() {
+        let mut nes = get_test_nes();
+        nes.set_audio_sample_rate(44_100.0);
+        // The first frame after power on is a different length to the rest
+        nes.frame();
+        nes.take_audio_samples();
+        nes.frame();
+        let mono = nes.take_audio_samples();
+
+        nes.set_stereo_panning(Some(StereoPanning::default()));
+        nes.frame();
+        let stereo = nes.take_audio_samples();
+        // With every channel in the centre, each frame of stereo samples holds the same sample twice
+        assert!(stereo.len().abs_diff(mono.len() * 2) <= 2);
+        assert!(stereo.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+        assert_eq!(20, nes.run_until_samples(10).len());
+
+        nes.set_stereo_panning(None);
+        assert_eq!(10, nes.run_until_samples(10).len());
+    }
+
+    #[test]
+    fn test_paused() {
+        let mut nes = get_test_nes();
+        nes.frame();
+        nes.take_audio_samples();
+        nes.set_paused(true);
+        assert!(nes.is_paused());
+        (0..300).for_each(|_| nes.cycle());
+        nes.frame();
+        assert_eq!(1, nes.get_frame_count());
+        assert_eq!(0.0, nes.get_audio_output());
+        // A frame's worth of silence is produced in place of the frame
+        let samples = nes.take_audio_samples();
+        assert_eq!(29_781, samples.len());
+        assert!(samples.iter().all(|sample| *sample == 0.0));
+
+        nes.set_paused(false);
+        nes.frame();
+        assert_eq!(2, nes.get_frame_count());
+    }
+
+    #[test]
+    fn test_set_audio_sample_rate() {
+        let mut nes = get_test_nes();
+        nes.set_audio_sample_rate(44_100.0);
+        nes.set_audio_sample_rate(-1.0);
+        assert_eq!(44_100.0, nes.get_audio_sample_rate());
+        (0..3 * 1_000).for_each(|_| nes.cycle());
+        // A thousand CPU cycles is a little over 24 samples at 44.1 kHz
+        assert_eq!(24, nes.take_audio_samples().len());
+    }
+
+    #[test]
+    fn test_audio_sink() {
+        /// Audio sink that counts the samples pushed to it and keeps the last channel outputs
+        #[derive(Default)]
+        struct CountingSink(usize, ChannelOutputs);
+        impl AudioSink for CountingSink {
+            fn push_sample(&mut self, _sample: f32, channels: ChannelOutputs) {
+                self.0 += 1;
+                self.1 = channels;
+            }
+        }
+
+        let cartridge = get_mock_cartridge(MapperMock {
+            program_read_stub: |_, _| 0x00,
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        });
+        let mut nes = Nes::with_backends(cartridge, NullVideoBackend, CountingSink::default());
+        nes.bus.write(0x4011, 0x40); // Set the DMC's output level directly
+        (0..30).for_each(|_| nes.cycle());
+        assert_eq!(10, nes.get_audio_sink().0);
+        assert_eq!(0x40, nes.get_audio_sink().1.dmc);
+    }
+
+    #[test]
+    fn test_frames_for_host_refresh_normal_speed() {
+        let mut nes = get_test_nes();
+        let frames: u32 = (0..600).map(|_| nes.frames_for_host_refresh(NES_FRAME_RATE)).sum();
+        assert!((599..=600).contains(&frames));
+    }
+
+    #[test]
+    fn test_frames_for_host_refresh_fast_forward() {
+        let mut nes = get_test_nes();
+        nes.set_speed(2.0);
+        let frames: u32 = (0..120).map(|_| nes.frames_for_host_refresh(120.0)).sum();
+        assert_eq!(120, frames);
+    }
+
+    #[test]
+    fn test_frames_for_host_refresh_slow_motion() {
+        let mut nes = get_test_nes();
+        nes.set_speed(0.5);
+        let frames: u32 = (0..60).map(|_| nes.frames_for_host_refresh(60.0)).sum();
+        assert_eq!(30, frames);
+    }
+
+    #[test]
+    fn test_audio_samples_per_host_refresh() {
+        for speed in [0.5, 1.0, 2.0] {
+            let mut nes = get_test_nes();
+            nes.set_audio_sample_rate(44_100.0);
+            nes.set_speed(speed);
+            // The first frame after power on is cut short
+            nes.frame();
+            nes.take_audio_samples();
+            let mut samples = 0;
+            for _ in 0..30 {
+                for _ in 0..nes.frames_for_host_refresh(60.0) {
+                    nes.frame();
+                }
+                samples += nes.take_audio_samples().len();
+            }
+            // Half a second of refreshes gets half a second of samples at any speed, give or take the frame still to be run
+            assert!(
+                (22_050 - samples as i64).abs() < (44_100.0 / NES_FRAME_RATE / f64::from(speed)) as i64,
+                "{} samples at {}x",
+                samples,
+                speed
+            );
+        }
+        // The speed carries over to a new sample rate
+        let mut nes = get_test_nes();
+        nes.set_speed(2.0);
+        nes.frame();
+        nes.set_audio_sample_rate(44_100.0);
+        for _ in 0..30 {
+            nes.frame();
+        }
+        let samples = nes.take_audio_samples().len() as f64;
+        assert!((samples - 15.0 * 44_100.0 / NES_FRAME_RATE).abs() < 2.0, "{} samples", samples);
+    }
+
+    #[test]
+    fn test_set_speed_invalid() {
+        let mut nes = get_test_nes();
+        nes.set_speed(0.0);
+        nes.set_speed(f32::NAN);
+        assert_eq!(1.0, nes.get_speed());
+    }
+}
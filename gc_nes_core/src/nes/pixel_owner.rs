@@ -0,0 +1,14 @@
+//! The pixel owner module contains the tags that record what drew each pixel of the screen, for hitbox visualizers,
+//! auto-splitters and debug overlays that highlight sprites.
+
+/// What drew a single pixel of the screen, after the PPU decided whether the background or a sprite had priority
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PixelOwner {
+    /// Neither the background nor a sprite drew the pixel, so it shows the backdrop colour at $3F00
+    #[default]
+    Backdrop,
+    /// The background drew the pixel, including when it covered a sprite with its priority bit set
+    Background,
+    /// A sprite drew the pixel, identified by its index in OAM (0 to 63)
+    Sprite(u8),
+}
@@ -3,8 +3,13 @@
 //! to the screen.
 
 use super::emulator_6502::MOS6502;
+use super::{AccuracyOptions, PpuConfig};
+use super::{PixelOwner, NES_SCREEN_DIMENSIONS};
 use crate::cartridge::{Cartridge, Mirroring};
+use crate::state::{StateReader, StateWriter};
+use crate::video::{OutputTransform, VideoBackend, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
 use bit_reverse::BitwiseReverse;
+use std::error::Error;
 
 /// The total number of scanlines in a frame.
 const MAX_SCANLINES: u16 = 261;
@@ -23,8 +28,31 @@ const COARSE_Y_OFFSET: u16 = 5;
 const FINE_Y_MASK: u16 = 0b01110000_00000000;
 /// The offset of the coarse y bits in the vram address.
 const FINE_Y_OFFSET: u16 = 12;
+/// The number of reads from write only registers that are logged before the warnings are suppressed.
+const MAX_WRITE_ONLY_READ_WARNINGS: u8 = 8;
+/// The number of frames that the I/O latch holds its value for without being refreshed, when open bus decay is enabled.
+const IO_LATCH_DECAY_FRAMES: u8 = 36;
+/// The number of sprites that the PPU can draw on a single scanline.
+const SCANLINE_SPRITE_LIMIT: usize = 8;
+/// The number of sprites in object attribute memory, which can all be drawn on one scanline when the sprite limit is disabled.
+const OAM_SPRITE_COUNT: usize = 64;
+/// The width in pixels of the border around the screen that is hidden when overscan is hidden.
+pub(super) const OVERSCAN_SIZE: usize = 8;
+/// The colour index of the black that hidden overscan is drawn with.
+const OVERSCAN_COLOUR: u8 = 0x0f;
+
+/// An access to the vram through $2007 that was made while the PPU was rendering, which corrupts the scroll position
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) struct RenderingVramAccess {
+    /// The scanline the PPU was on
+    pub(super) scanline: u16,
+    /// The dot of the scanline the PPU was on
+    pub(super) dot: u16,
+    /// Whether the access was a write rather than a read
+    pub(super) write: bool,
+}
 
-#[cfg_attr(test, derive(Clone))]
+#[derive(Clone)]
 /// Structure used to hold the registers and the state of the NES Picture Processing Unit
 pub(super) struct NesPpu {
     /// Register containing flags used for controlling the function of the PPU
@@ -45,6 +73,27 @@ pub(super) struct NesPpu {
     write_latch: bool,
     /// Buffer for storing data between reads.
     read_buffer: u8,
+    /// The last value on the data bus between the CPU and the PPU, which is what reads from
+    /// write only registers return.
+    io_latch: u8,
+    /// The number of reads from write only registers that have been logged
+    write_only_read_warnings: u8,
+    /// The number of frames since the I/O latch was last refreshed
+    io_latch_age: u8,
+    /// Whether the I/O latch fades to zero when it hasn't been refreshed for a while
+    open_bus_decay: bool,
+    /// Whether sprite evaluation is spread across the visible cycles of each scanline, or done all at once
+    per_dot_sprite_evaluation: bool,
+    /// The options that change how the screen is drawn
+    config: PpuConfig,
+    /// The colour emphasis bits that were last passed to the video backend
+    reported_emphasis: u8,
+    /// Whether pixels are worked out without being drawn to the video backend, for frames that won't be shown
+    skip_drawing: bool,
+    /// The rotation and mirroring applied to pixels as they are drawn to the video backend
+    output_transform: OutputTransform,
+    /// The last access through $2007 that was made while rendering, until it is reported
+    rendering_vram_access: Option<RenderingVramAccess>,
     /// The pattern ram stores values used for mapping the sprite bitmaps to colours that the NES
     /// can display.
     palette_ram: Box<[u8; 0x20]>,
@@ -53,17 +102,10 @@ pub(super) struct NesPpu {
     /// used for scrolling.
     name_table: Box<[u8; 0x800]>,
     /// Object attribute memory stores 4 bytes of information about up to 64 sprites
-    object_attribute_memory: Box<[u8; u8::max_value() as usize + 1]>,
+    object_attribute_memory: Box<[u8; u8::MAX as usize + 1]>,
     /// Secondary object attribute memory stores sprite information for up to 8 sprites on the
-    /// scanline that is currently being rendered.
-    secondary_object_attribute_memory: [u8; 0x20],
-    /// The current state of the screen as an array of 32 bit colour values for minifb rendering
-    #[cfg(not(feature = "web-frame-format"))]
-    screen_buffer: Box<[u32; super::NES_SCREEN_DIMENSIONS]>,
-    /// The current state of the screen as an array of bytes, where each pixel is represented by a set of
-    /// four bytes in RGBA order for web rendering.
-    #[cfg(feature = "web-frame-format")]
-    screen_buffer: Box<[u8; super::NES_SCREEN_DIMENSIONS * 4]>,
+    /// scanline that is currently being rendered, or up to all 64 when the sprite limit is disabled.
+    secondary_object_attribute_memory: [u8; OAM_SPRITE_COUNT * 4],
     /// The scanline (0 to 261) of the screen that is currently being drawn
     scanline: u16,
     /// The cycle (0 to 340) of the current scanline
@@ -92,38 +134,35 @@ pub(super) struct NesPpu {
     sprite_evaluation_index: u8,
     /// The secondary sprite evaluation index stores which index in the secondary OAM the next
     /// sprite will be written to.
-    secondary_sprite_evaluation_index: u8,
+    secondary_sprite_evaluation_index: u16,
     /// The sprite evaluation wrapped boolean indicates whether or not the all 64 sprites have
     /// been evaluated.
     sprite_evaluation_wrapped: bool,
     /// The sprite shifters low array contains the low plane of the sprite bitmaps for up to eight
-    /// sprites on a scanline.
-    sprite_shifters_lo: [u8; 8],
+    /// sprites on a scanline, or all 64 without the sprite limit.
+    sprite_shifters_lo: [u8; OAM_SPRITE_COUNT],
     /// The sprite shifters low array contains the high plane of the sprite bitmaps for up to eight
-    /// sprites on a scanline.
-    sprite_shifters_hi: [u8; 8],
-    /// The sprite attributes array contains the attribute bytes for up to eight sprites on a scanline.
-    sprite_attributes: [SpriteAttribute; 8],
+    /// sprites on a scanline, or all 64 without the sprite limit.
+    sprite_shifters_hi: [u8; OAM_SPRITE_COUNT],
+    /// The sprite attributes array contains the attribute bytes for up to eight sprites on a scanline,
+    /// or all 64 without the sprite limit.
+    sprite_attributes: [SpriteAttribute; OAM_SPRITE_COUNT],
     /// The sprite x offset array contains the distance between the leftmost pixel of a sprite and
     /// the pixel for the current cycle.
-    sprite_x_offsets: [i16; 8],
-}
-
-#[cfg(not(feature = "web-frame-format"))]
-/// Create a new screen buffer, extracted to a function for conditional compilation
-fn new_screen_buffer() -> Box<[u32; super::NES_SCREEN_DIMENSIONS]> {
-    return Box::new([0; super::NES_SCREEN_DIMENSIONS]);
-}
-
-#[cfg(feature = "web-frame-format")]
-/// Create a new screen buffer, extracted to a function for conditional compilation
-fn new_screen_buffer() -> Box<[u8; super::NES_SCREEN_DIMENSIONS * 4]> {
-    return Box::new([0; super::NES_SCREEN_DIMENSIONS * 4]);
+    sprite_x_offsets: [i16; OAM_SPRITE_COUNT],
+    /// The index in OAM of each sprite in the secondary OAM
+    secondary_sprite_indices: [u8; OAM_SPRITE_COUNT],
+    /// The index in OAM of each sprite loaded into the sprite shifters
+    sprite_indices: [u8; OAM_SPRITE_COUNT],
+    /// The sprite shifter that the last foreground pixel was taken from, or None if no sprite was on the pixel
+    foreground_sprite: Option<usize>,
+    /// What drew each pixel of the screen, laid out like the video backend, or None if pixel owners aren't tracked
+    pixel_owners: Option<Box<[PixelOwner; NES_SCREEN_DIMENSIONS]>>,
 }
 
 impl NesPpu {
-    /// Create a new instance of a NesPpu
-    pub fn new() -> Self {
+    /// Create a new instance of a NesPpu that draws the screen with the provided options
+    pub fn new(config: PpuConfig) -> Self {
         NesPpu {
             ctrl_flags: Default::default(),
             mask_flags: Default::default(),
@@ -134,11 +173,20 @@ impl NesPpu {
             fine_x_scroll: 0,
             write_latch: false,
             read_buffer: 0x00,
+            io_latch: 0x00,
+            write_only_read_warnings: 0,
+            io_latch_age: 0,
+            open_bus_decay: false,
+            per_dot_sprite_evaluation: true,
+            config,
+            reported_emphasis: 0,
+            skip_drawing: false,
+            output_transform: Default::default(),
+            rendering_vram_access: None,
             palette_ram: Box::new([0; 0x20]),
             name_table: Box::new([0; 0x800]),
-            object_attribute_memory: Box::new([0xff; u8::max_value() as usize + 1]),
-            secondary_object_attribute_memory: [0; 0x20],
-            screen_buffer: new_screen_buffer(),
+            object_attribute_memory: Box::new([0xff; u8::MAX as usize + 1]),
+            secondary_object_attribute_memory: [0; OAM_SPRITE_COUNT * 4],
             scanline: 261,
             cycle: 0,
             frame_count: 0,
@@ -153,15 +201,19 @@ impl NesPpu {
             sprite_evaluation_index: 0,
             secondary_sprite_evaluation_index: 0,
             sprite_evaluation_wrapped: false,
-            sprite_shifters_lo: [0; 8],
-            sprite_shifters_hi: [0; 8],
-            sprite_attributes: [SpriteAttribute::from_bits(0).unwrap(); 8],
-            sprite_x_offsets: [0; 8],
+            sprite_shifters_lo: [0; OAM_SPRITE_COUNT],
+            sprite_shifters_hi: [0; OAM_SPRITE_COUNT],
+            sprite_attributes: [SpriteAttribute::from_bits(0).unwrap(); OAM_SPRITE_COUNT],
+            sprite_x_offsets: [0; OAM_SPRITE_COUNT],
+            secondary_sprite_indices: [0; OAM_SPRITE_COUNT],
+            sprite_indices: [0; OAM_SPRITE_COUNT],
+            foreground_sprite: None,
+            pixel_owners: None,
         }
     }
 
-    /// Runs a single PPU cycle, which draws a single pixel into the frame buffer
-    pub fn cycle(&mut self, cartridge: &mut Cartridge, cpu: &mut MOS6502) {
+    /// Runs a single PPU cycle, which draws a single pixel to the video backend
+    pub fn cycle<V: VideoBackend>(&mut self, cartridge: &mut Cartridge, cpu: &mut MOS6502, video: &mut V) {
         match self.scanline {
             MAX_SCANLINES | 0..=239 => {
                 match self.cycle {
@@ -179,11 +231,24 @@ impl NesPpu {
 
                         self.select_next_background_tile(cartridge);
 
-                        self.perform_sprite_evaluation();
+                        if self.per_dot_sprite_evaluation {
+                            self.perform_sprite_evaluation();
+                        } else if self.cycle == 256 {
+                            self.evaluate_sprites_at_once();
+                        }
 
                         // Draw pixel to the screen during visible pixels
                         if self.cycle <= 256 && self.scanline != MAX_SCANLINES {
-                            self.draw_pixel(cartridge)
+                            if self.skip_drawing {
+                                // The pixel is still worked out for sprite zero hits, which games time their raster effects with
+                                let (background_pixel, _) = self.calculate_background_pixel();
+                                self.calculate_foreground_pixel(background_pixel);
+                            } else {
+                                self.draw_pixel(cartridge, video);
+                                if self.cycle == 256 {
+                                    video.end_of_scanline(self.scanline as usize);
+                                }
+                            }
                         }
 
                         // Special Cases!
@@ -199,14 +264,22 @@ impl NesPpu {
                         // Perform the rest of sprite evaluation, loading the sprite data into shift
                         // registers for rendering.
                         // The real PPU does this over eight cycles, but for the time being
-                        // I'm  going to do it all in one for simplicity.
-                        if self.cycle % 8 == 1 {
+                        // I'm  going to do it all in one for simplicity, on the cycle that the
+                        // pattern fetches start on so that mappers watching A12 see them on time.
+                        if self.cycle == 257 {
                             // Reset on the first cycle
-                            if self.cycle == 257 {
-                                self.secondary_sprite_evaluation_index = 0;
-                            }
+                            self.secondary_sprite_evaluation_index = 0;
+                        }
+                        if self.cycle % 8 == 5 {
                             self.load_foregroud_shifters(cartridge);
                             self.secondary_sprite_evaluation_index += 4;
+                            // Without the sprite limit, the sprites past the first eight are loaded along with the eighth
+                            if self.cycle == 317 {
+                                while (self.secondary_sprite_evaluation_index as usize) < self.get_secondary_oam_capacity() {
+                                    self.load_foregroud_shifters(cartridge);
+                                    self.secondary_sprite_evaluation_index += 4;
+                                }
+                            }
                         }
 
                         // Special Cases!
@@ -220,8 +293,6 @@ impl NesPpu {
                                 self.current_vram_address =
                                     (self.current_vram_address & !(0x400 | COARSE_X_MASK)) | (self.temporary_vram_address & (0x400 | COARSE_X_MASK))
                             }
-                            (260, 0..=240, true) if self.ctrl_flags.intersects(PpuCtrl::SPRITE_SELECT) => cartridge.end_of_scanline(),
-                            (324, 0..=240, true) if self.ctrl_flags.intersects(PpuCtrl::BACKGROUND_SELECT) => cartridge.end_of_scanline(),
                             // Load the y information from the temporary vram address into the active vram address repeatedly
                             (280..=304, MAX_SCANLINES, true) => {
                                 self.current_vram_address = (self.current_vram_address & !(FINE_Y_MASK | 0x800 | COARSE_Y_MASK))
@@ -231,14 +302,20 @@ impl NesPpu {
                         }
                     } // Final four cycles just make dummy reads
                     c @ 337..=340 if c & 0x1 == 0 => {
-                        cartridge.character_read(0x00);
-                    } // TODO: Read from the correct location
+                        // Both fetch the next tile's nametable byte again, which some mappers watch to detect the end of the scanline
+                        self.rendering_read(0x2000 | (self.current_vram_address & 0x0fff), cartridge);
+                    }
                     // Idle cycles to simulate two cycle read time
                     337..=340 => {}
                     _ => panic!("Invalid Cycle: {}", self.cycle), // TODO: Consider unreachable!()
                 }
             }
-            240 => {} // Nothing happens on the first scanline off the screen
+            // Nothing happens on the first scanline off the screen, but the PPU stops fetching and leaves the vram address on its bus
+            240 => {
+                if self.cycle == 0 {
+                    self.drive_vram_address(cartridge);
+                }
+            }
             241 => {
                 if self.cycle == 1 {
                     // The vertical blank flag is set on the second cycle of scanline 241
@@ -260,6 +337,12 @@ impl NesPpu {
                 self.cycle = 0;
                 self.scanline = 0;
                 self.frame_count += 1;
+                if self.open_bus_decay {
+                    self.io_latch_age = self.io_latch_age.saturating_add(1);
+                    if self.io_latch_age >= IO_LATCH_DECAY_FRAMES {
+                        self.io_latch = 0x00;
+                    }
+                }
             }
             (MAX_CYCLES, _, _) => {
                 self.cycle = 0;
@@ -277,7 +360,7 @@ impl NesPpu {
                 // Load the shifters from the latches
                 self.reload_shifters();
                 // Read the byte of the next pattern from the nametable
-                self.nametable_id = self.vram_read(0x2000 | (self.current_vram_address & 0x0fff), cartridge);
+                self.nametable_id = self.rendering_read(0x2000 | (self.current_vram_address & 0x0fff), cartridge);
             }
             // Read the byte from the attribute table containing palette information
             3 => self.attribute_latch = self.read_attribute_table_byte(cartridge),
@@ -286,7 +369,7 @@ impl NesPpu {
             // second pattern table is combined with the pattern id in the nametable
             // and the fine y scroll in the address.
             5 => {
-                self.pattern_latch_lo = self.vram_read(
+                self.pattern_latch_lo = self.rendering_read(
                     ((self.ctrl_flags.intersects(PpuCtrl::BACKGROUND_SELECT) as u16) << 12)
                         | ((self.nametable_id as u16) << 4)
                         | (self.current_vram_address >> FINE_Y_OFFSET),
@@ -295,7 +378,7 @@ impl NesPpu {
             }
             // Same as above, but offset by eight pixels
             7 => {
-                self.pattern_latch_hi = self.vram_read(
+                self.pattern_latch_hi = self.rendering_read(
                     (((self.ctrl_flags.intersects(PpuCtrl::BACKGROUND_SELECT) as u16) << 12)
                         | ((self.nametable_id as u16) << 4)
                         | (self.current_vram_address >> FINE_Y_OFFSET))
@@ -320,7 +403,10 @@ impl NesPpu {
                 if self.cycle == 1 {
                     // Reset the index on the first cycle
                     self.secondary_sprite_evaluation_index = 0;
-                } else if self.cycle % 2 == 0 {
+                    // The secondary OAM past the first eight sprites is only used without the sprite limit
+                    let capacity = self.get_secondary_oam_capacity();
+                    self.secondary_object_attribute_memory[SCANLINE_SPRITE_LIMIT * 4..capacity].fill(0xff);
+                } else if self.cycle.is_multiple_of(2) {
                     // The actual PPU reads and writes in alternating cycles, but
                     // this is much simpler.
                     self.secondary_object_attribute_memory[self.secondary_sprite_evaluation_index as usize] = self.oam_read();
@@ -328,72 +414,163 @@ impl NesPpu {
                 }
             }
             // Remaining cycles in the visible scanline fill it back up again
-            65..=256 => {
+            65..=256 if self.cycle.is_multiple_of(2) => {
                 // The real PPU reads from OAM take place on odd cycles and writes to secondary OAM on even cycles.
                 // This would require adding a lot more intermediate state fields to the NesPpu struct so it has been
                 // simplified into a single cycle here.
-                if self.cycle % 2 == 0 {
-                    if self.cycle == 66 {
-                        // Reset the state variables on the first cycle
-                        self.sprite_evaluation_index = 0;
-                        self.secondary_sprite_evaluation_index = 0;
-                        self.sprite_evaluation_wrapped = false;
-                    }
-
-                    let sprite_y = self.object_attribute_memory[self.sprite_evaluation_index as usize] as u16;
-                    let sprite_height = if self.ctrl_flags.intersects(PpuCtrl::SPRITE_HEIGHT) { 16 } else { 8 };
-
-                    // Prevent duplication of sprites in secondary OAM by ensuring the
-                    // evaluation doesn't continue after all the sprites in OAM have
-                    // been evaluated.
-                    if !self.sprite_evaluation_wrapped {
-                        if (self.secondary_sprite_evaluation_index as usize) < self.secondary_object_attribute_memory.len() {
-                            // Copy the first 8 sprites found on the scanline into the secondary oam
-                            if self.scanline >= sprite_y && self.scanline - sprite_y < sprite_height {
-                                // If the sprite overlaps with the scanline, copy its object attribute
-                                // data into the secondary memory for evaluation on the next scanline
-                                self.secondary_object_attribute_memory
-                                    [self.secondary_sprite_evaluation_index as usize..self.secondary_sprite_evaluation_index as usize + 4]
-                                    .clone_from_slice(
-                                        &self.object_attribute_memory
-                                            [self.sprite_evaluation_index as usize..self.sprite_evaluation_index as usize + 4],
-                                    );
-
-                                // This doesn't happen in the real PPU, but I am using
-                                // the unused flags in the attribute byte to keep track
-                                // of which sprite is sprite zero.
-                                if self.sprite_evaluation_index == 0 {
-                                    self.secondary_object_attribute_memory[self.secondary_sprite_evaluation_index as usize + 2] |=
-                                        SpriteAttribute::SPRITE_ZERO.bits
-                                } else {
-                                    self.secondary_object_attribute_memory[self.secondary_sprite_evaluation_index as usize + 2] &=
-                                        !SpriteAttribute::SPRITE_ZERO.bits
-                                }
+                if self.cycle == 66 {
+                    // Reset the state variables on the first cycle
+                    self.sprite_evaluation_index = 0;
+                    self.secondary_sprite_evaluation_index = 0;
+                    self.sprite_evaluation_wrapped = false;
+                }
 
-                                self.secondary_sprite_evaluation_index += 4;
+                let sprite_y = self.object_attribute_memory[self.sprite_evaluation_index as usize] as u16;
+                let sprite_height = if self.ctrl_flags.intersects(PpuCtrl::SPRITE_HEIGHT) { 16 } else { 8 };
+
+                // Prevent duplication of sprites in secondary OAM by ensuring the
+                // evaluation doesn't continue after all the sprites in OAM have
+                // been evaluated.
+                if !self.sprite_evaluation_wrapped {
+                    if (self.secondary_sprite_evaluation_index as usize) < self.get_secondary_oam_capacity() {
+                        // Copy the first 8 sprites found on the scanline into the secondary oam
+                        if self.scanline >= sprite_y && self.scanline - sprite_y < sprite_height {
+                            // If the sprite overlaps with the scanline, copy its object attribute
+                            // data into the secondary memory for evaluation on the next scanline
+                            self.secondary_object_attribute_memory
+                                [self.secondary_sprite_evaluation_index as usize..self.secondary_sprite_evaluation_index as usize + 4]
+                                .clone_from_slice(
+                                    &self.object_attribute_memory[self.sprite_evaluation_index as usize..self.sprite_evaluation_index as usize + 4],
+                                );
+
+                            // This doesn't happen in the real PPU, but I am using
+                            // the unused flags in the attribute byte to keep track
+                            // of which sprite is sprite zero.
+                            self.secondary_sprite_indices[self.secondary_sprite_evaluation_index as usize / 4] = self.sprite_evaluation_index / 4;
+                            if self.sprite_evaluation_index == 0 {
+                                self.secondary_object_attribute_memory[self.secondary_sprite_evaluation_index as usize + 2] |=
+                                    SpriteAttribute::SPRITE_ZERO.bits
+                            } else {
+                                self.secondary_object_attribute_memory[self.secondary_sprite_evaluation_index as usize + 2] &=
+                                    !SpriteAttribute::SPRITE_ZERO.bits
                             }
-                        } else if !self.status_flags.intersects(PpuStatus::SPRITE_OVERFLOW) {
-                            // Once 8 sprites have been found, we need to check if an overflow has occurred.
-                            if self.scanline >= sprite_y && self.scanline - sprite_y < sprite_height {
-                                // If there is another sprite on the scanline, set the overflow flag
-                                self.status_flags.set(PpuStatus::SPRITE_OVERFLOW, true)
+
+                            // Without the sprite limit, the overflow flag is set when the ninth sprite is found
+                            if self.secondary_sprite_evaluation_index as usize >= SCANLINE_SPRITE_LIMIT * 4 {
+                                self.status_flags.set(PpuStatus::SPRITE_OVERFLOW, true);
                             }
-                            // There's a bug that offsets the checked address when determining
-                            // if an overflow occurred, causing false negatives and positives
-                            let (temp_sprite_eval, temp_bool) = self.sprite_evaluation_index.overflowing_add(1);
-                            self.sprite_evaluation_index = temp_sprite_eval;
-                            self.sprite_evaluation_wrapped = self.sprite_evaluation_wrapped || temp_bool;
+                            self.secondary_sprite_evaluation_index += 4;
+                        }
+                    } else if !self.status_flags.intersects(PpuStatus::SPRITE_OVERFLOW) {
+                        // Once 8 sprites have been found, we need to check if an overflow has occurred.
+                        if self.scanline >= sprite_y && self.scanline - sprite_y < sprite_height {
+                            // If there is another sprite on the scanline, set the overflow flag
+                            self.status_flags.set(PpuStatus::SPRITE_OVERFLOW, true)
                         }
+                        // There's a bug that offsets the checked address when determining
+                        // if an overflow occurred, causing false negatives and positives
+                        let (temp_sprite_eval, temp_bool) = self.sprite_evaluation_index.overflowing_add(1);
+                        self.sprite_evaluation_index = temp_sprite_eval;
+                        self.sprite_evaluation_wrapped = self.sprite_evaluation_wrapped || temp_bool;
                     }
-                    let (temp_sprite_eval, temp_bool) = self.sprite_evaluation_index.overflowing_add(4);
-                    self.sprite_evaluation_index = temp_sprite_eval;
-                    self.sprite_evaluation_wrapped = self.sprite_evaluation_wrapped || temp_bool;
                 }
+                let (temp_sprite_eval, temp_bool) = self.sprite_evaluation_index.overflowing_add(4);
+                self.sprite_evaluation_index = temp_sprite_eval;
+                self.sprite_evaluation_wrapped = self.sprite_evaluation_wrapped || temp_bool;
             }
             _ => {}
         }
     }
 
+    /// Fills the secondary object attribute memory with the sprites on the next scanline in a single step, which is
+    /// used in place of perform_sprite_evaluation when per dot sprite evaluation is disabled. Changes to OAM part way
+    /// through the scanline aren't seen, and the sprite overflow flag is set without the PPU's buggy search.
+    fn evaluate_sprites_at_once(&mut self) {
+        let capacity = self.get_secondary_oam_capacity();
+        self.secondary_object_attribute_memory[..capacity].fill(0xff);
+        let sprite_height = if self.ctrl_flags.intersects(PpuCtrl::SPRITE_HEIGHT) { 16 } else { 8 };
+        let mut sprites_found = 0;
+        for sprite_index in (0..self.object_attribute_memory.len()).step_by(4) {
+            let sprite_y = self.object_attribute_memory[sprite_index] as u16;
+            if self.scanline < sprite_y || self.scanline - sprite_y >= sprite_height {
+                continue;
+            }
+            if sprites_found >= SCANLINE_SPRITE_LIMIT {
+                self.status_flags.set(PpuStatus::SPRITE_OVERFLOW, true);
+            }
+            if sprites_found * 4 == capacity {
+                break;
+            }
+            let secondary_index = sprites_found * 4;
+            self.secondary_object_attribute_memory[secondary_index..secondary_index + 4]
+                .clone_from_slice(&self.object_attribute_memory[sprite_index..sprite_index + 4]);
+            self.secondary_sprite_indices[sprites_found] = (sprite_index / 4) as u8;
+            // Track sprite zero in the unused attribute bits, like perform_sprite_evaluation does
+            if sprite_index == 0 {
+                self.secondary_object_attribute_memory[secondary_index + 2] |= SpriteAttribute::SPRITE_ZERO.bits
+            } else {
+                self.secondary_object_attribute_memory[secondary_index + 2] &= !SpriteAttribute::SPRITE_ZERO.bits
+            }
+            sprites_found += 1;
+        }
+    }
+
+    /// Applies the accuracy options that affect the PPU
+    pub(super) fn set_accuracy(&mut self, options: &AccuracyOptions) {
+        self.per_dot_sprite_evaluation = options.per_dot_sprite_evaluation;
+        self.open_bus_decay = options.open_bus_decay;
+    }
+
+    /// Sets the options that change how the screen is drawn
+    pub(super) fn set_config(&mut self, config: PpuConfig) {
+        self.config = config;
+    }
+
+    /// Gets the options that change how the screen is drawn
+    pub(super) fn get_config(&self) -> PpuConfig {
+        return self.config;
+    }
+
+    /// Sets whether pixels are worked out without being drawn to the video backend. Everything that the CPU can
+    /// observe, including sprite zero hits, carries on as normal.
+    pub(super) fn set_skip_drawing(&mut self, skip: bool) {
+        self.skip_drawing = skip;
+    }
+
+    /// Gets the scanline and dot that the PPU will run next
+    pub(super) fn get_position(&self) -> (u16, u16) {
+        return (self.scanline, self.cycle);
+    }
+
+    /// Sets whether the PPU records what drew each pixel of the screen. Disabling it discards the record.
+    pub(super) fn set_pixel_owner_tracking(&mut self, enabled: bool) {
+        self.pixel_owners = match (enabled, self.pixel_owners.take()) {
+            (true, Some(pixel_owners)) => Some(pixel_owners),
+            (true, None) => Some(Box::new([Default::default(); NES_SCREEN_DIMENSIONS])),
+            (false, _) => None,
+        };
+    }
+
+    /// Gets what drew each pixel of the screen, or None if pixel owners aren't being tracked
+    pub(super) fn get_pixel_owners(&self) -> Option<&[PixelOwner; NES_SCREEN_DIMENSIONS]> {
+        return self.pixel_owners.as_deref();
+    }
+
+    /// Gets the number of bytes of secondary OAM that sprite evaluation fills, which is eight sprites'
+    /// worth unless the sprite limit is disabled
+    fn get_secondary_oam_capacity(&self) -> usize {
+        return if self.config.sprite_limit {
+            SCANLINE_SPRITE_LIMIT * 4
+        } else {
+            OAM_SPRITE_COUNT * 4
+        };
+    }
+
+    /// Sets the rotation and mirroring applied to pixels as they are drawn
+    pub(super) fn set_output_transform(&mut self, output_transform: OutputTransform) {
+        self.output_transform = output_transform;
+    }
+
     /// Loads one sprite into the different sprite shifter arrays for the next scanline based on the current position of the secondary sprite evaluation index.
     /// Normally these reads would occur over an eight cycle period, but I'm doing them all in a single cycle for convenience.
     fn load_foregroud_shifters(&mut self, cartridge: &mut Cartridge) {
@@ -403,6 +580,7 @@ impl NesPpu {
         if sprite_y != 0xff {
             let sprite_pattern_id = self.secondary_object_attribute_memory[self.secondary_sprite_evaluation_index as usize + 1] as u16; // Cast here instead of later
 
+            self.sprite_indices[sprite_index] = self.secondary_sprite_indices[sprite_index];
             self.sprite_attributes[sprite_index] =
                 SpriteAttribute::from_bits_truncate(self.secondary_object_attribute_memory[self.secondary_sprite_evaluation_index as usize + 2]);
 
@@ -418,7 +596,8 @@ impl NesPpu {
             }
 
             let sprite_address: u16 = if !self.ctrl_flags.intersects(PpuCtrl::SPRITE_HEIGHT) {
-                (((self.ctrl_flags & PpuCtrl::SPRITE_SELECT).bits as u16) << 8) | (sprite_pattern_id << 4) | sprite_pattern_row
+                // The sprite select flag is bit 3, which selects the pattern table at $1000
+                (((self.ctrl_flags & PpuCtrl::SPRITE_SELECT).bits as u16) << 9) | (sprite_pattern_id << 4) | sprite_pattern_row
             } else {
                 // Determine which of the two tiles in a 16 bit sprite should be shown
                 let tile_id = ((self.scanline - sprite_y as u16) >> 3) ^ (self.sprite_attributes[sprite_index].bits >> 7) as u16;
@@ -428,18 +607,27 @@ impl NesPpu {
                 ((sprite_pattern_id & 0x01) << 12) | (((sprite_pattern_id & 0xfe) + tile_id) << 4) | (sprite_pattern_row & 0x07)
             };
 
-            self.sprite_shifters_lo[sprite_index] = self.vram_read(sprite_address, cartridge);
-            self.sprite_shifters_hi[sprite_index] = self.vram_read(sprite_address + 8, cartridge);
+            self.sprite_shifters_lo[sprite_index] = self.rendering_read(sprite_address, cartridge);
+            self.sprite_shifters_hi[sprite_index] = self.rendering_read(sprite_address + 8, cartridge);
 
             if self.sprite_attributes[sprite_index].intersects(SpriteAttribute::HORIZONTAL_MIRROR) {
                 self.sprite_shifters_lo[sprite_index] = self.sprite_shifters_lo[sprite_index].swap_bits();
                 self.sprite_shifters_hi[sprite_index] = self.sprite_shifters_hi[sprite_index].swap_bits();
             }
+        } else if self.is_rendering() {
+            // The PPU still fetches tile $FF for the empty slots, which mappers watching A12 count on.
+            // The data is thrown away, so it isn't read from character memory.
+            let dummy_address = if self.ctrl_flags.intersects(PpuCtrl::SPRITE_HEIGHT) {
+                0x1ff0
+            } else {
+                (((self.ctrl_flags & PpuCtrl::SPRITE_SELECT).bits as u16) << 9) | 0x0ff0
+            };
+            cartridge.ppu_address_bus(dummy_address, self.get_dot());
         }
     }
 
-    /// Draws a 32 bit RGB value to the screen buffer based on the background and foreground bit shifters
-    fn draw_pixel(&mut self, cartridge: &mut Cartridge) {
+    /// Draws a pixel to the video backend based on the background and foreground bit shifters
+    fn draw_pixel<V: VideoBackend>(&mut self, cartridge: &mut Cartridge, video: &mut V) {
         let (background_pixel, background_palette) = self.calculate_background_pixel();
 
         let (foreground_pixel, foreground_palette, foreground_priority) = self.calculate_foreground_pixel(background_pixel);
@@ -453,22 +641,35 @@ impl NesPpu {
             foreground_priority,
         );
 
-        let colour_index = self.vram_read(0x3f00 | ((palette as u16) << 2) | pixel as u16, cartridge) as usize;
-        self.draw_pixel_to_screen_buffer(colour_index);
-    }
-
-    #[cfg(not(feature = "web-frame-format"))]
-    /// Draw pixel to screen buffer, separated from draw_pixel for conditional compilation
-    fn draw_pixel_to_screen_buffer(&mut self, colour_index: usize) {
-        self.screen_buffer[((self.cycle - 1) as usize + (self.scanline as usize * 256)) as usize] = NES_COLOUR_MAP[colour_index]
-    }
-
-    #[cfg(feature = "web-frame-format")]
-    /// Draw pixel to screen buffer, separated from draw_pixel for conditional compilation
-    fn draw_pixel_to_screen_buffer(&mut self, colour_index: usize) {
-        let screen_buffer_index = 4 * ((self.cycle - 1) as usize + (self.scanline as usize * 256)) as usize;
-        // TODO: Consider unsafe block here to skip length check
-        self.screen_buffer[screen_buffer_index..screen_buffer_index + 4].copy_from_slice(&NES_COLOUR_MAP_WEB[colour_index]);
+        // Palette ram entries are six bits wide, so mask the colour index into the range of valid NES colours
+        let mut colour_index = self.vram_read(0x3f00 | ((palette as u16) << 2) | pixel as u16, cartridge) & 0x3f;
+        if self.config.greyscale && self.mask_flags.intersects(PpuMask::GREYSCALE) {
+            // Greyscale selects the grey in the first column of the palette for the colour's brightness
+            colour_index &= 0x30;
+        }
+        let (screen_x, screen_y) = ((self.cycle - 1) as usize, self.scanline as usize);
+        let hidden = self.config.hide_overscan
+            && (!(OVERSCAN_SIZE..NES_SCREEN_WIDTH - OVERSCAN_SIZE).contains(&screen_x)
+                || !(OVERSCAN_SIZE..NES_SCREEN_HEIGHT - OVERSCAN_SIZE).contains(&screen_y));
+        if hidden {
+            colour_index = OVERSCAN_COLOUR;
+        }
+        let emphasis = if self.config.emphasis { self.mask_flags.bits >> 5 } else { 0 };
+        if emphasis != self.reported_emphasis {
+            video.set_emphasis(emphasis);
+            self.reported_emphasis = emphasis;
+        }
+        let (x, y) = self.output_transform.apply(screen_x, screen_y);
+        if let Some(pixel_owners) = &mut self.pixel_owners {
+            // Sprite palettes are the last four, so the sprite drew the pixel if one of them was chosen
+            pixel_owners[x + y * NES_SCREEN_WIDTH] = match (pixel, self.foreground_sprite) {
+                _ if hidden => PixelOwner::Backdrop,
+                (0x00, _) => PixelOwner::Backdrop,
+                (_, Some(sprite)) if palette >= 0x04 => PixelOwner::Sprite(self.sprite_indices[sprite]),
+                _ => PixelOwner::Background,
+            };
+        }
+        video.draw_pixel(x, y, colour_index);
     }
 
     /// Calculates that background pixel and palette based on the shifters
@@ -497,8 +698,9 @@ impl NesPpu {
         let mut foreground_pixel = 0x00;
         let mut foreground_palette = 0x00;
         let mut foreground_priority = false;
+        self.foreground_sprite = None;
 
-        for i in 0..self.sprite_x_offsets.len() {
+        for i in 0..self.get_secondary_oam_capacity() / 4 {
             // Decrement all the sprite x offsets from the current pixel
             if self.sprite_x_offsets[i] > -0x8 {
                 self.sprite_x_offsets[i] -= 1;
@@ -514,6 +716,9 @@ impl NesPpu {
 
                     foreground_palette = (self.sprite_attributes[i] & SpriteAttribute::PALETTE).bits + 0x04;
                     foreground_priority = !self.sprite_attributes[i].intersects(SpriteAttribute::PRIORITY);
+                    if foreground_pixel != 0x00 {
+                        self.foreground_sprite = Some(i);
+                    }
                 }
 
                 // Check for Sprite Zero hit
@@ -538,29 +743,23 @@ impl NesPpu {
     /// Function for reading from the PPU. Any address passed to the function will be mapped to one of
     /// the eight valid ppu addresses ( address % 8), equivalent to only using the lowest three bits
     pub fn read(&mut self, cartridge: &mut Cartridge, address: u16) -> u8 {
-        match address & 0x07 {
+        let value = match address & 0x07 {
             // Mirroring first 3 bits
-            0x0000 => {
-                warn!("Attempting to read from ppu control flag");
-                return 0x00; // TODO: Check this behaviour
-            }
-            0x0001 => {
-                warn!("Attempting to read from ppu mask flag");
-                return 0x00; // TODO: Check this behaviour
+            0x0000 | 0x0001 | 0x0003 | 0x0005 | 0x0006 => {
+                // Write only registers don't drive the bus, so whatever was last on it is read back
+                self.warn_write_only_read(address);
+                return self.io_latch;
             }
             0x0002 => {
                 // When the value of the status flag is read, the bottom values retain whatever was last
                 // on the PPU bus
-                let value = self.status_flags.bits | (self.read_buffer & 0x1f);
+                let value = self.status_flags.bits | (self.io_latch & 0x1f);
                 // Reset Vertical Blank flag and the latch
                 self.status_flags.set(PpuStatus::VERTICAL_BLANK, false);
                 self.write_latch = false;
-                return value;
+                value
             }
-            0x0003 => panic!("Attempting to read from ppu OAM address"), // TODO: Check this behaviour
             0x0004 => self.oam_read(),
-            0x0005 => panic!("Attempting to read from ppu scroll address"), // TODO: Check this behaviour
-            0x0006 => panic!("Attempting to read from ppu vram address"),   // TODO: Check this behaviour
             0x0007 => {
                 // Reading from the PPU is delayed by a cycle*, so return data from the last address
                 // that was read from.
@@ -573,20 +772,37 @@ impl NesPpu {
                 }
 
                 // Increment the address in the x or y direction depending on a ctrl flag
-                self.current_vram_address += if self.ctrl_flags.intersects(PpuCtrl::VRAM_INCREMENT) {
-                    0x20
-                } else {
-                    0x01
-                };
-                return temp;
+                self.increment_vram_address(false);
+                self.drive_vram_address(cartridge);
+                temp
+            }
+            _ => unreachable!("Address is masked to three bits"),
+        };
+        self.io_latch = value;
+        self.io_latch_age = 0;
+        return value;
+    }
+
+    /// Logs a warning for a read from a write only register, until enough have been logged that more
+    /// would only flood the log of a game that does it every frame.
+    fn warn_write_only_read(&mut self, address: u16) {
+        if self.write_only_read_warnings < MAX_WRITE_ONLY_READ_WARNINGS {
+            self.write_only_read_warnings += 1;
+            warn!(
+                "Attempted to read from write only ppu register 0x{:04X}, returning the I/O latch",
+                0x2000 | (address & 0x07)
+            );
+            if self.write_only_read_warnings == MAX_WRITE_ONLY_READ_WARNINGS {
+                warn!("Suppressing further warnings about reads from write only ppu registers");
             }
-            _ => panic!("Invalid PPU Read Address"), // TODO: Consider unreachable!()
         }
     }
 
     /// Function for writing to the PPU. Any address passed to the function will be mapped to one of
     /// the eight valid ppu addresses ( address % 8), equivalent to only using the lowest three bits
     pub fn write(&mut self, cartridge: &mut Cartridge, address: u16, data: u8) {
+        self.io_latch = data;
+        self.io_latch_age = 0;
         match address & 0x07 {
             // Mirroring first 3 bits
             0x0000 => {
@@ -596,25 +812,52 @@ impl NesPpu {
                 // Select the nametables based on the new values set to the ctrl register
                 self.temporary_vram_address |= (data as u16 & 0b11) << 10
             }
-            0x0001 => self.mask_flags.bits = data,
+            0x0001 => {
+                self.mask_flags.bits = data;
+                self.drive_vram_address(cartridge);
+            }
             0x0002 => warn!("Ignored attempted write to the ppu status flag. Data: {:2X}", data), // TODO: Check this behaviour
             0x0003 => self.oam_address = data,
             0x0004 => self.oam_write(data),
             0x0005 => self.scroll_write(data),
-            0x0006 => self.vram_address_write(data),
+            0x0006 => {
+                self.vram_address_write(data);
+                self.drive_vram_address(cartridge);
+            }
             0x0007 => {
                 self.vram_write(self.current_vram_address, data, cartridge);
-                // Increment the address in the x or y direction depending on a ctrl flag
-                self.current_vram_address += if self.ctrl_flags.intersects(PpuCtrl::VRAM_INCREMENT) {
-                    0x20
-                } else {
-                    0x01
-                }
+                self.increment_vram_address(true);
+                self.drive_vram_address(cartridge);
             }
             _ => warn!("Invalid PPU Write Address"), // TODO: Consider unreachable!()
         }
     }
 
+    /// Increments the vram address after an access through $2007 in the x or y direction depending on a ctrl flag.
+    /// The address register is only 15 bits wide, so it wraps around rather than overflowing.
+    ///
+    /// While rendering, the vram address is also the scroll position that tiles are being fetched from, and the access
+    /// increments the coarse x and y scroll together instead, as they would be at the end of a tile and scanline.
+    fn increment_vram_address(&mut self, write: bool) {
+        if self.is_rendering() {
+            self.rendering_vram_access = Some(RenderingVramAccess {
+                scanline: self.scanline,
+                dot: self.cycle,
+                write,
+            });
+            self.coarse_x_increment();
+            self.y_increment();
+            return;
+        }
+
+        let increment = if self.ctrl_flags.intersects(PpuCtrl::VRAM_INCREMENT) {
+            0x20
+        } else {
+            0x01
+        };
+        self.current_vram_address = (self.current_vram_address + increment) & 0x7fff;
+    }
+
     /// Function for reading from the Object Attribute Memory
     fn oam_read(&mut self) -> u8 {
         // During the first 64 cycles of each visible scanline, reading from oam always returns 0xff.
@@ -628,18 +871,68 @@ impl NesPpu {
 
     /// Reads from the internal bus of the PPU
     fn vram_read(&mut self, address: u16, cartridge: &mut Cartridge) -> u8 {
+        // The PPU address bus is only 14 bits wide, so the top bit of the vram address is ignored
+        let address = address & 0x3fff;
         return match address {
             0x0000..=0x1fff => cartridge.character_read(address),
             0x2000..=0x3eff => self.name_table[self.apply_name_table_mirroring(cartridge, address)],
-            0x3f00..=0x3fff => self.palette_ram[self.apply_palette_mirroring(address)],
-            _ => panic!("Attempt to read from an invalid PPU bus address: 0x{:4X}!", address),
+            _ => self.palette_ram[self.apply_palette_mirroring(address)],
+        };
+    }
+
+    /// Reads from the internal bus of the PPU to render the screen, putting the address on the PPU's address bus for
+    /// mappers that watch it if rendering is enabled. The fetches are only simulated while rendering is disabled.
+    fn rendering_read(&mut self, address: u16, cartridge: &mut Cartridge) -> u8 {
+        if self.is_rendering() {
+            cartridge.ppu_address_bus(address & 0x3fff, self.get_dot());
+        }
+        return self.vram_read(address, cartridge);
+    }
+
+    /// Puts the vram address on the PPU's address bus for mappers that watch it, which is where it is left while
+    /// the PPU isn't rendering. Games can clock scanline counters by changing it through $2006 and $2007.
+    fn drive_vram_address(&mut self, cartridge: &mut Cartridge) {
+        if !self.is_rendering() {
+            cartridge.ppu_address_bus(self.current_vram_address & 0x3fff, self.get_dot());
+        }
+    }
+
+    /// Gets the number of dots since the PPU was created, as a timestamp for the accesses on its address bus.
+    /// The dot skipped on odd frames is counted, which doesn't matter for the short intervals that it is used to measure.
+    fn get_dot(&self) -> u64 {
+        let dots_per_scanline = u64::from(MAX_CYCLES) + 1;
+        return (self.frame_count * (u64::from(MAX_SCANLINES) + 1) + u64::from(self.scanline)) * dots_per_scanline + u64::from(self.cycle);
+    }
+
+    /// Reads from the internal bus of the PPU for debuggers, with the same mirroring as rendering, but without
+    /// recording the read in the cartridge's character memory usage
+    pub(super) fn debug_vram_read(&mut self, cartridge: &mut Cartridge, address: u16) -> u8 {
+        return match address & 0x3fff {
+            0x0000..=0x1fff => cartridge.debug_character_read(address & 0x3fff),
+            _ => self.vram_read(address, cartridge),
         };
     }
 
+    /// Writes onto the internal bus of the PPU for debuggers, with the same mirroring as rendering
+    pub(super) fn debug_vram_write(&mut self, cartridge: &mut Cartridge, address: u16, data: u8) {
+        self.vram_write(address, data, cartridge);
+    }
+
     /// Function for writing to the Object Attribute Memory
     fn oam_write(&mut self, data: u8) {
         self.object_attribute_memory[self.oam_address as usize] = data;
-        self.oam_address += 1; // Writing to the oam address increments it
+        self.oam_address = self.oam_address.wrapping_add(1); // Writing to the oam address increments it, wrapping around to the start
+    }
+
+    /// Checks if the PPU is rendering, meaning that it is on a visible or pre-render scanline with rendering enabled
+    fn is_rendering(&self) -> bool {
+        return self.mask_flags.intersects(PpuMask::BACKGROUND_ENABLE | PpuMask::SPRITE_ENABLE)
+            && (self.scanline <= 239 || self.scanline == MAX_SCANLINES);
+    }
+
+    /// Takes the last access through $2007 that was made while rendering, if there has been one since the last call
+    pub(super) fn take_rendering_vram_access(&mut self) -> Option<RenderingVramAccess> {
+        return self.rendering_vram_access.take();
     }
 
     /// Function used by the CPU during DMA to write to the PPU's OAM
@@ -756,26 +1049,14 @@ impl NesPpu {
 
     /// Writes onto the internal bus of the PPU.
     fn vram_write(&mut self, address: u16, data: u8, cartridge: &mut Cartridge) {
+        let address = address & 0x3fff;
         match address {
             0x0000..=0x1fff => cartridge.character_write(address, data),
             0x2000..=0x3eff => self.name_table[self.apply_name_table_mirroring(cartridge, address)] = data,
-            0x3f00..=0x3fff => self.palette_ram[self.apply_palette_mirroring(address)] = data,
-            _ => panic!("Attempt to write to an invalid PPU bus address: 0x{:4X}!", address),
+            _ => self.palette_ram[self.apply_palette_mirroring(address)] = data,
         }
     }
 
-    /// Gets the screen buffer from the PPU as an array of 32 bit colour values.
-    #[cfg(not(feature = "web-frame-format"))]
-    pub(super) fn get_screen(&mut self) -> &[u32; super::NES_SCREEN_DIMENSIONS] {
-        return &self.screen_buffer;
-    }
-
-    /// Gets the screen buffer from the PPU in web format where each pixel is four bytes in RGBA order for web rendering.
-    #[cfg(feature = "web-frame-format")]
-    pub(super) fn get_screen(&mut self) -> &[u8; super::NES_SCREEN_DIMENSIONS * 4] {
-        return &self.screen_buffer;
-    }
-
     /// Maps an address to a name table address by applying mirroring.
     fn apply_name_table_mirroring(&mut self, cartridge: &mut Cartridge, address: u16) -> usize {
         return match cartridge.get_mirroring() {
@@ -810,7 +1091,7 @@ impl NesPpu {
         // Select the attribute byte in the y direction (top 3 bits of the y component moved into the correct position)
         attribute_address |= (self.current_vram_address & 0x380) >> 4;
         // Return the two bits of the attribute byte that refer to the correct quadrant
-        return self.vram_read(attribute_address, cartridge)
+        return self.rendering_read(attribute_address, cartridge)
             >> (
                 // Shift the attribute byte right by four bits if we're selecting one of the bottom tiles
                 (((self.current_vram_address >> COARSE_Y_OFFSET) & 0x02) << 0x1) |
@@ -850,6 +1131,98 @@ impl NesPpu {
         self.fine_x_scroll = 0x00;
         self.temporary_vram_address = 0x00;
     }
+
+    /// Writes the registers, memory and rendering state of the PPU to a save state. The options that change how the
+    /// screen is drawn, the emphasis last passed to the video backend and the pixel owners aren't state of the PPU.
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.ctrl_flags.bits());
+        state.write_u8(self.mask_flags.bits());
+        state.write_u8(self.status_flags.bits());
+        state.write_u8(self.oam_address);
+        state.write_u16(self.temporary_vram_address);
+        state.write_u16(self.current_vram_address);
+        state.write_u8(self.fine_x_scroll);
+        state.write_bool(self.write_latch);
+        state.write_u8(self.read_buffer);
+        state.write_u8(self.io_latch);
+        state.write_u8(self.io_latch_age);
+        state.write_bytes(&self.palette_ram[..]);
+        state.write_bytes(&self.name_table[..]);
+        state.write_bytes(&self.object_attribute_memory[..]);
+        state.write_bytes(&self.secondary_object_attribute_memory);
+        state.write_u16(self.scanline);
+        state.write_u16(self.cycle);
+        state.write_u64(self.frame_count);
+        state.write_u8(self.pattern_latch_lo);
+        state.write_u8(self.pattern_latch_hi);
+        state.write_u16(self.pattern_shifter_lo);
+        state.write_u16(self.pattern_shifter_hi);
+        state.write_u8(self.attribute_latch);
+        state.write_u16(self.attribute_shifter_lo);
+        state.write_u16(self.attribute_shifter_hi);
+        state.write_u8(self.nametable_id);
+        state.write_u8(self.sprite_evaluation_index);
+        state.write_u16(self.secondary_sprite_evaluation_index);
+        state.write_bool(self.sprite_evaluation_wrapped);
+        state.write_bytes(&self.sprite_shifters_lo);
+        state.write_bytes(&self.sprite_shifters_hi);
+        self.sprite_attributes.iter().for_each(|attribute| state.write_u8(attribute.bits()));
+        self.sprite_x_offsets.iter().for_each(|offset| state.write_u16(*offset as u16));
+        state.write_bytes(&self.secondary_sprite_indices);
+        state.write_bytes(&self.sprite_indices);
+        state.write_option_u8(self.foreground_sprite.map(|sprite| sprite as u8));
+    }
+
+    /// Restores the registers, memory and rendering state of the PPU from a save state
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.ctrl_flags = PpuCtrl::from_bits_truncate(state.read_u8()?);
+        self.mask_flags = PpuMask::from_bits_truncate(state.read_u8()?);
+        self.status_flags = PpuStatus::from_bits_truncate(state.read_u8()?);
+        self.oam_address = state.read_u8()?;
+        self.temporary_vram_address = state.read_u16()? & 0x7fff;
+        self.current_vram_address = state.read_u16()? & 0x7fff;
+        self.fine_x_scroll = state.read_u8()? & 0x07;
+        self.write_latch = state.read_bool()?;
+        self.read_buffer = state.read_u8()?;
+        self.io_latch = state.read_u8()?;
+        self.io_latch_age = state.read_u8()?;
+        *self.palette_ram = state.read_array()?;
+        *self.name_table = state.read_array()?;
+        *self.object_attribute_memory = state.read_array()?;
+        self.secondary_object_attribute_memory = state.read_array()?;
+        self.scanline = state.read_u16()?;
+        self.cycle = state.read_u16()?;
+        if self.scanline > MAX_SCANLINES || self.cycle > MAX_CYCLES {
+            bail!("Invalid PPU position in save state: scanline {}, cycle {}", self.scanline, self.cycle);
+        }
+        self.frame_count = state.read_u64()?;
+        self.pattern_latch_lo = state.read_u8()?;
+        self.pattern_latch_hi = state.read_u8()?;
+        self.pattern_shifter_lo = state.read_u16()?;
+        self.pattern_shifter_hi = state.read_u16()?;
+        self.attribute_latch = state.read_u8()?;
+        self.attribute_shifter_lo = state.read_u16()?;
+        self.attribute_shifter_hi = state.read_u16()?;
+        self.nametable_id = state.read_u8()?;
+        self.sprite_evaluation_index = state.read_u8()?;
+        self.secondary_sprite_evaluation_index = state.read_u16()?;
+        if usize::from(self.secondary_sprite_evaluation_index) > self.secondary_object_attribute_memory.len() {
+            bail!("Invalid secondary OAM index in save state: {}", self.secondary_sprite_evaluation_index);
+        }
+        self.sprite_evaluation_wrapped = state.read_bool()?;
+        self.sprite_shifters_lo = state.read_array()?;
+        self.sprite_shifters_hi = state.read_array()?;
+        for attribute in self.sprite_attributes.iter_mut() {
+            *attribute = SpriteAttribute::from_bits_truncate(state.read_u8()?);
+        }
+        for offset in self.sprite_x_offsets.iter_mut() {
+            *offset = state.read_u16()? as i16;
+        }
+        self.secondary_sprite_indices = state.read_array()?;
+        self.sprite_indices = state.read_array()?;
+        self.foreground_sprite = state.read_option_u8()?.map(usize::from).filter(|sprite| *sprite < OAM_SPRITE_COUNT);
+        return Ok(());
+    }
 }
 
 bitflags! {
@@ -909,84 +1282,6 @@ impl Default for PpuStatus {
     }
 }
 
-#[allow(clippy::unreadable_literal)] // Allow standard 6 character colour hex codes
-#[cfg(not(feature = "web-frame-format"))]
-const NES_COLOUR_MAP: [u32; 0x40] = [
-    0x464646, 0x00065a, 0x000678, 0x020673, 0x35034c, 0x57000e, 0x5a0000, 0x410000, 0x120200, 0x001400, 0x001e00, 0x001e00, 0x001521, 0x000000,
-    0x000000, 0x000000, 0x9d9d9d, 0x004ab9, 0x0530e1, 0x5718da, 0x9f07a7, 0xcc0255, 0xcf0b00, 0xa42300, 0x5c3f00, 0x0b5800, 0x006600, 0x006713,
-    0x005e6e, 0x000000, 0x000000, 0x000000, 0xfeffff, 0x1f9eff, 0x5376ff, 0x9865ff, 0xfc67ff, 0xff6cb3, 0xff7466, 0xff8014, 0xc49a00, 0x71b300,
-    0x28c421, 0x00c874, 0x00bfd0, 0x2b2b2b, 0x000000, 0x000000, 0xfeffff, 0x9ed5ff, 0xafc0ff, 0xd0b8ff, 0xfebfff, 0xffc0e0, 0xffc3bd, 0xffca9c,
-    0xe7d58b, 0xc5df8e, 0xa6e6a3, 0x94e8c5, 0x92e4eb, 0xa7a7a7, 0x000000, 0x000000,
-];
-
-#[cfg(feature = "web-frame-format")]
-const NES_COLOUR_MAP_WEB: [[u8; 0x04]; 0x40] = [
-    [0x46, 0x46, 0x46, 0xff],
-    [0x00, 0x06, 0x5a, 0xff],
-    [0x00, 0x06, 0x78, 0xff],
-    [0x02, 0x06, 0x73, 0xff],
-    [0x35, 0x03, 0x4c, 0xff],
-    [0x57, 0x00, 0x0e, 0xff],
-    [0x5a, 0x00, 0x00, 0xff],
-    [0x41, 0x00, 0x00, 0xff],
-    [0x12, 0x02, 0x00, 0xff],
-    [0x00, 0x14, 0x00, 0xff],
-    [0x00, 0x1e, 0x00, 0xff],
-    [0x00, 0x1e, 0x00, 0xff],
-    [0x00, 0x15, 0x21, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0x9d, 0x9d, 0x9d, 0xff],
-    [0x00, 0x4a, 0xb9, 0xff],
-    [0x05, 0x30, 0xe1, 0xff],
-    [0x57, 0x18, 0xda, 0xff],
-    [0x9f, 0x07, 0xa7, 0xff],
-    [0xcc, 0x02, 0x55, 0xff],
-    [0xcf, 0x0b, 0x00, 0xff],
-    [0xa4, 0x23, 0x00, 0xff],
-    [0x5c, 0x3f, 0x00, 0xff],
-    [0x0b, 0x58, 0x00, 0xff],
-    [0x00, 0x66, 0x00, 0xff],
-    [0x00, 0x67, 0x13, 0xff],
-    [0x00, 0x5e, 0x6e, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0xfe, 0xff, 0xff, 0xff],
-    [0x1f, 0x9e, 0xff, 0xff],
-    [0x53, 0x76, 0xff, 0xff],
-    [0x98, 0x65, 0xff, 0xff],
-    [0xfc, 0x67, 0xff, 0xff],
-    [0xff, 0x6c, 0xb3, 0xff],
-    [0xff, 0x74, 0x66, 0xff],
-    [0xff, 0x80, 0x14, 0xff],
-    [0xc4, 0x9a, 0x00, 0xff],
-    [0x71, 0xb3, 0x00, 0xff],
-    [0x28, 0xc4, 0x21, 0xff],
-    [0x00, 0xc8, 0x74, 0xff],
-    [0x00, 0xbf, 0xd0, 0xff],
-    [0x2b, 0x2b, 0x2b, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0xfe, 0xff, 0xff, 0xff],
-    [0x9e, 0xd5, 0xff, 0xff],
-    [0xaf, 0xc0, 0xff, 0xff],
-    [0xd0, 0xb8, 0xff, 0xff],
-    [0xfe, 0xbf, 0xff, 0xff],
-    [0xff, 0xc0, 0xe0, 0xff],
-    [0xff, 0xc3, 0xbd, 0xff],
-    [0xff, 0xca, 0x9c, 0xff],
-    [0xe7, 0xd5, 0x8b, 0xff],
-    [0xc5, 0xdf, 0x8e, 0xff],
-    [0xa6, 0xe6, 0xa3, 0xff],
-    [0x94, 0xe8, 0xc5, 0xff],
-    [0x92, 0xe4, 0xeb, 0xff],
-    [0xa7, 0xa7, 0xa7, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-    [0x00, 0x00, 0x00, 0xff],
-];
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1127,12 +1422,12 @@ mod test {
             ..Default::default()
         };
 
-        let ppu_expected = NesPpu {
+        let mut ppu_expected = NesPpu {
             cycle: 64,
             secondary_sprite_evaluation_index: 32,
-            secondary_object_attribute_memory: [0xff; 32],
             ..ppu_base.clone()
         };
+        ppu_expected.secondary_object_attribute_memory[..32].fill(0xff);
 
         for i in 0..=64 {
             ppu_base.cycle = i;
@@ -1253,6 +1548,170 @@ mod test {
         assert_eq!(ppu_expected, ppu_base)
     }
 
+    #[test]
+    fn test_evaluate_sprites_at_once_matches_per_dot() {
+        let mut ppu_per_dot = NesPpu {
+            scanline: 40,
+            ..Default::default()
+        };
+        // Sprite zero and nine more sprites on the scanline, with one that isn't in between
+        for sprite in 0..11 {
+            let sprite_y = if sprite == 3 { 80 } else { 35 + sprite as u8 / 3 };
+            ppu_per_dot.object_attribute_memory[sprite * 4..sprite * 4 + 4].copy_from_slice(&[sprite_y, sprite as u8, 0x00, 100]);
+        }
+        for sprite in 11..64 {
+            ppu_per_dot.object_attribute_memory[sprite * 4] = 0xef;
+        }
+        let mut ppu_at_once = ppu_per_dot.clone();
+
+        for cycle in 1..=256 {
+            ppu_per_dot.cycle = cycle;
+            ppu_per_dot.perform_sprite_evaluation();
+        }
+        ppu_at_once.evaluate_sprites_at_once();
+
+        assert_eq!(
+            ppu_per_dot.secondary_object_attribute_memory,
+            ppu_at_once.secondary_object_attribute_memory
+        );
+        assert!(ppu_at_once.status_flags.intersects(PpuStatus::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn test_sprite_evaluation_without_sprite_limit() {
+        let mut ppu_per_dot = NesPpu {
+            scanline: 40,
+            config: PpuConfig {
+                sprite_limit: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for sprite in 0..64 {
+            let sprite_y = if sprite < 10 { 35 } else { 0xef };
+            ppu_per_dot.object_attribute_memory[sprite * 4..sprite * 4 + 4].copy_from_slice(&[sprite_y, sprite as u8, 0x00, 100]);
+        }
+        let mut ppu_at_once = ppu_per_dot.clone();
+
+        for cycle in 1..=256 {
+            ppu_per_dot.cycle = cycle;
+            ppu_per_dot.perform_sprite_evaluation();
+        }
+        ppu_at_once.evaluate_sprites_at_once();
+
+        // All ten sprites on the scanline are found, and the overflow flag is still set
+        for ppu in [&ppu_per_dot, &ppu_at_once] {
+            assert_eq!([35, 9, 0x00, 100], ppu.secondary_object_attribute_memory[36..40]);
+            assert_eq!(0xff, ppu.secondary_object_attribute_memory[40]);
+            assert!(ppu.status_flags.intersects(PpuStatus::SPRITE_OVERFLOW));
+        }
+    }
+
+    #[test]
+    fn test_draw_pixel_config() {
+        let mut ppu = NesPpu {
+            scanline: 4,
+            cycle: 100,
+            mask_flags: PpuMask::GREYSCALE,
+            ..Default::default()
+        };
+        ppu.palette_ram[0] = 0x16;
+        let mut cartridge = get_mock_cartridge(MapperMock { ..Default::default() });
+        let mut video = crate::video::IndexedFrameBuffer::default();
+        ppu.draw_pixel(&mut cartridge, &mut video);
+        assert_eq!(0x10, video.get_frame()[99 + 4 * NES_SCREEN_WIDTH]);
+
+        ppu.set_config(PpuConfig {
+            hide_overscan: true,
+            greyscale: false,
+            ..Default::default()
+        });
+        ppu.draw_pixel(&mut cartridge, &mut video);
+        assert_eq!(OVERSCAN_COLOUR, video.get_frame()[99 + 4 * NES_SCREEN_WIDTH]);
+        ppu.scanline = 8;
+        ppu.draw_pixel(&mut cartridge, &mut video);
+        assert_eq!(0x16, video.get_frame()[99 + 8 * NES_SCREEN_WIDTH]);
+    }
+
+    #[test]
+    fn test_plan_scroll_split_sets_vram_address() {
+        let split = crate::nes::plan_scroll_split(32, 0x6d, 0x9b, 3).unwrap();
+        let mut ppu = NesPpu {
+            scanline: split.write_scanline,
+            cycle: *split.write_dots.start(),
+            mask_flags: PpuMask::BACKGROUND_ENABLE,
+            ..Default::default()
+        };
+        let mut cartridge = get_mock_cartridge(MapperMock {
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        });
+        let mut cpu = MOS6502::new();
+        for (address, data) in split.writes.iter() {
+            ppu.write(&mut cartridge, *address, *data);
+        }
+        // Fine Y, nametable, coarse Y, and coarse X
+        let expected_address = (0x9b & 0x07) << FINE_Y_OFFSET | 3 << 10 | (0x9b >> 3) << COARSE_Y_OFFSET | 0x6d >> 3;
+        assert_eq!(0x6d & 0x07, ppu.fine_x_scroll);
+        assert_eq!(expected_address, ppu.current_vram_address);
+
+        // Running to the split scanline only moves coarse X on by the two tiles fetched ahead of it
+        while ppu.scanline == split.write_scanline {
+            ppu.cycle(&mut cartridge, &mut cpu, &mut crate::video::NullVideoBackend);
+        }
+        assert_eq!(expected_address + 2, ppu.current_vram_address);
+    }
+
+    #[test]
+    fn test_dummy_fetches_read_nametable() {
+        let mut ppu = NesPpu {
+            scanline: 0,
+            cycle: 337,
+            mask_flags: PpuMask::BACKGROUND_ENABLE,
+            ..Default::default()
+        };
+        // The default character read stub panics, so reading the pattern tables fails the test
+        let mut cartridge = get_mock_cartridge(MapperMock {
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        });
+        let mut cpu = MOS6502::new();
+        while ppu.cycle != 0 {
+            ppu.cycle(&mut cartridge, &mut cpu, &mut crate::video::NullVideoBackend);
+        }
+        assert_eq!(1, ppu.scanline);
+    }
+
+    #[test]
+    fn test_io_latch_decay() {
+        let mut ppu = NesPpu {
+            scanline: MAX_SCANLINES,
+            cycle: MAX_CYCLES,
+            frame_count: 0,
+            ..Default::default()
+        };
+        let mut cartridge = get_mock_cartridge(MapperMock {
+            character_read_stub: |_, _| 0x00,
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        });
+        let mut cpu = MOS6502::new();
+        ppu.set_accuracy(&AccuracyOptions {
+            open_bus_decay: true,
+            ..Default::default()
+        });
+        ppu.write(&mut cartridge, 0x2003, 0x5a);
+        for frame in 0..IO_LATCH_DECAY_FRAMES {
+            assert_eq!(0x5a, ppu.read(&mut cartridge, 0x2000), "I/O latch decayed after {} frames", frame);
+            ppu.scanline = MAX_SCANLINES;
+            ppu.cycle = MAX_CYCLES;
+            ppu.frame_count = 0;
+            ppu.cycle(&mut cartridge, &mut cpu, &mut crate::video::NullVideoBackend);
+        }
+        assert_eq!(0x00, ppu.read(&mut cartridge, 0x2000));
+    }
+
     #[test]
     fn test_calculate_background_pixel() {
         let mut ppu_base = NesPpu {
@@ -1334,10 +1793,10 @@ mod test {
         let mut ppu_base = NesPpu {
             mask_flags: PpuMask::SPRITE_ENABLE | PpuMask::SPRITE_LEFT_ENABLE,
             cycle: 9,
-            sprite_x_offsets: [-8; 8],
-            sprite_attributes: [SpriteAttribute::from_bits_truncate(0); 8],
-            sprite_shifters_lo: [0; 8],
-            sprite_shifters_hi: [0; 8],
+            sprite_x_offsets: [-8; OAM_SPRITE_COUNT],
+            sprite_attributes: [SpriteAttribute::from_bits_truncate(0); OAM_SPRITE_COUNT],
+            sprite_shifters_lo: [0; OAM_SPRITE_COUNT],
+            sprite_shifters_hi: [0; OAM_SPRITE_COUNT],
             status_flags: PpuStatus::from_bits(0).unwrap(),
             ..Default::default()
         };
@@ -1368,10 +1827,10 @@ mod test {
         let mut ppu_base = NesPpu {
             mask_flags: PpuMask::from_bits(0).unwrap(),
             cycle: 9,
-            sprite_x_offsets: [-8; 8],
-            sprite_attributes: [SpriteAttribute::from_bits_truncate(0); 8],
-            sprite_shifters_lo: [0; 8],
-            sprite_shifters_hi: [0; 8],
+            sprite_x_offsets: [-8; OAM_SPRITE_COUNT],
+            sprite_attributes: [SpriteAttribute::from_bits_truncate(0); OAM_SPRITE_COUNT],
+            sprite_shifters_lo: [0; OAM_SPRITE_COUNT],
+            sprite_shifters_hi: [0; OAM_SPRITE_COUNT],
             status_flags: PpuStatus::from_bits(0).unwrap(),
             ..Default::default()
         };
@@ -1402,10 +1861,10 @@ mod test {
         let mut ppu_base = NesPpu {
             mask_flags: PpuMask::SPRITE_ENABLE,
             cycle: 6,
-            sprite_x_offsets: [-8; 8],
-            sprite_attributes: [SpriteAttribute::from_bits_truncate(0); 8],
-            sprite_shifters_lo: [0; 8],
-            sprite_shifters_hi: [0; 8],
+            sprite_x_offsets: [-8; OAM_SPRITE_COUNT],
+            sprite_attributes: [SpriteAttribute::from_bits_truncate(0); OAM_SPRITE_COUNT],
+            sprite_shifters_lo: [0; OAM_SPRITE_COUNT],
+            sprite_shifters_hi: [0; OAM_SPRITE_COUNT],
             status_flags: PpuStatus::from_bits(0).unwrap(),
             ..Default::default()
         };
@@ -1436,10 +1895,10 @@ mod test {
         let mut ppu_base = NesPpu {
             mask_flags: PpuMask::SPRITE_ENABLE,
             cycle: 9,
-            sprite_x_offsets: [-8; 8],
-            sprite_attributes: [SpriteAttribute::from_bits_truncate(0); 8],
-            sprite_shifters_lo: [0; 8],
-            sprite_shifters_hi: [0; 8],
+            sprite_x_offsets: [-8; OAM_SPRITE_COUNT],
+            sprite_attributes: [SpriteAttribute::from_bits_truncate(0); OAM_SPRITE_COUNT],
+            sprite_shifters_lo: [0; OAM_SPRITE_COUNT],
+            sprite_shifters_hi: [0; OAM_SPRITE_COUNT],
             status_flags: PpuStatus::from_bits(0).unwrap(),
             ..Default::default()
         };
@@ -1901,6 +2360,108 @@ mod test {
         assert_eq!(ppu_expected, ppu_base);
     }
 
+    #[test]
+    fn test_write_only_register_reads_return_io_latch() {
+        let mut ppu = NesPpu {
+            status_flags: PpuStatus::VERTICAL_BLANK,
+            ..Default::default()
+        };
+        let mut cartridge = get_mock_cartridge(Default::default());
+
+        ppu.write(&mut cartridge, 0x2003, 0x5a);
+        for address in &[0x2000, 0x2001, 0x2003, 0x2005, 0x2006] {
+            assert_eq!(0x5a, ppu.read(&mut cartridge, *address));
+        }
+        // The status register only drives the top three bits
+        assert_eq!(0x9a, ppu.read(&mut cartridge, 0x2002));
+        assert_eq!(0x9a, ppu.read(&mut cartridge, 0x2005));
+        for _ in 0..MAX_WRITE_ONLY_READ_WARNINGS {
+            ppu.read(&mut cartridge, 0x2000);
+        }
+        assert_eq!(MAX_WRITE_ONLY_READ_WARNINGS, ppu.write_only_read_warnings);
+    }
+
+    #[test]
+    fn test_vram_access_during_rendering() {
+        let mut ppu = NesPpu {
+            // Nametable $2003 with a fine y scroll of 2
+            current_vram_address: 0x2003,
+            mask_flags: PpuMask::BACKGROUND_ENABLE,
+            scanline: 100,
+            cycle: 50,
+            ..Default::default()
+        };
+        let mut cartridge = get_mock_cartridge(MapperMock {
+            get_mirroring_stub: |_| Mirroring::Horizontal,
+            ..Default::default()
+        });
+
+        ppu.write(&mut cartridge, 0x2007, 0x00);
+        // Rather than adding one, both coarse x and fine y are incremented
+        assert_eq!(0x3004, ppu.current_vram_address);
+        assert_eq!(
+            Some(RenderingVramAccess {
+                scanline: 100,
+                dot: 50,
+                write: true
+            }),
+            ppu.take_rendering_vram_access()
+        );
+        assert_eq!(None, ppu.take_rendering_vram_access());
+
+        // Accesses during vertical blank increment normally
+        ppu.scanline = 241;
+        ppu.read(&mut cartridge, 0x2007);
+        assert_eq!(0x3005, ppu.current_vram_address);
+        assert_eq!(None, ppu.take_rendering_vram_access());
+    }
+
+    /// Renders from the start of the pre-render scanline with an MMC3 set to interrupt after four scanlines, returning
+    /// the position that the PPU reached when the interrupt was requested, or None if it wasn't by the end of the frame
+    fn run_until_mmc3_interrupt(ctrl: u8, a12_filtering: bool) -> Option<(u16, u16)> {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0x40, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.resize(16 + 0x8000 + 0x2000, 0x00);
+        let mut cartridge = Cartridge::load_from_reader(rom.as_slice()).unwrap();
+        cartridge.set_a12_filtering(a12_filtering);
+        cartridge.program_write(0xc000, 0x04);
+        cartridge.program_write(0xc001, 0x00);
+        cartridge.program_write(0xe001, 0x00);
+        // No sprites are on any of the scanlines, so only the empty slots are fetched
+        let mut ppu = NesPpu {
+            scanline: MAX_SCANLINES,
+            object_attribute_memory: Box::new([0xff; u8::MAX as usize + 1]),
+            secondary_object_attribute_memory: [0xff; OAM_SPRITE_COUNT * 4],
+            ..Default::default()
+        };
+        let mut cpu = MOS6502::new();
+        ppu.write(&mut cartridge, 0x2000, ctrl);
+        ppu.write(&mut cartridge, 0x2001, 0x18);
+        while ppu.scanline != 240 {
+            ppu.cycle(&mut cartridge, &mut cpu, &mut crate::video::NullVideoBackend);
+            if cartridge.get_pending_interrupt_request() {
+                return Some((ppu.scanline, ppu.cycle - 1));
+            }
+        }
+        return None;
+    }
+
+    #[test]
+    fn test_mmc3_counts_a12_rises() {
+        // The first rise reloads the counter, which reaches zero four rises later
+        for a12_filtering in [true, false] {
+            // With sprites at $1000, A12 rises as the sprites are fetched, starting on the pre-render scanline
+            assert_eq!(Some((3, 261)), run_until_mmc3_interrupt(PpuCtrl::SPRITE_SELECT.bits, a12_filtering));
+            // With the background at $1000, A12 rises as the first tile of the next scanline is fetched, as well as at
+            // the start of the pre-render scanline
+            assert_eq!(Some((2, 325)), run_until_mmc3_interrupt(PpuCtrl::BACKGROUND_SELECT.bits, a12_filtering));
+            // 8x16 sprites with no sprites on the scanline fetch tile $FF, which is at $1000
+            assert_eq!(Some((3, 261)), run_until_mmc3_interrupt(PpuCtrl::SPRITE_HEIGHT.bits, a12_filtering));
+        }
+        // With both at $1000, A12 only falls briefly for the nametable fetches, which the filter ignores
+        let both = (PpuCtrl::SPRITE_SELECT | PpuCtrl::BACKGROUND_SELECT).bits;
+        assert_eq!(None, run_until_mmc3_interrupt(both, true));
+    }
+
     impl Default for NesPpu {
         fn default() -> Self {
             NesPpu {
@@ -1913,11 +2474,20 @@ mod test {
                 fine_x_scroll: 0,
                 write_latch: false,
                 read_buffer: 0,
+                io_latch: 0,
+                write_only_read_warnings: 0,
+                io_latch_age: 0,
+                open_bus_decay: false,
+                per_dot_sprite_evaluation: true,
+                config: Default::default(),
+                reported_emphasis: 0,
+                skip_drawing: false,
+                output_transform: Default::default(),
+                rendering_vram_access: None,
                 palette_ram: Box::new([0; 32]),
                 name_table: Box::new([0; 2048]),
                 object_attribute_memory: Box::new([0; 256]),
-                secondary_object_attribute_memory: [0; 32],
-                screen_buffer: new_screen_buffer(),
+                secondary_object_attribute_memory: [0; OAM_SPRITE_COUNT * 4],
                 scanline: 0,
                 cycle: 0,
                 frame_count: 0,
@@ -1932,10 +2502,14 @@ mod test {
                 sprite_evaluation_index: 0,
                 secondary_sprite_evaluation_index: 0,
                 sprite_evaluation_wrapped: false,
-                sprite_shifters_lo: [0; 8],
-                sprite_shifters_hi: [0; 8],
-                sprite_attributes: [Default::default(); 8],
-                sprite_x_offsets: [0; 8],
+                sprite_shifters_lo: [0; OAM_SPRITE_COUNT],
+                sprite_shifters_hi: [0; OAM_SPRITE_COUNT],
+                sprite_attributes: [Default::default(); OAM_SPRITE_COUNT],
+                sprite_x_offsets: [0; OAM_SPRITE_COUNT],
+                secondary_sprite_indices: [0; OAM_SPRITE_COUNT],
+                sprite_indices: [0; OAM_SPRITE_COUNT],
+                foreground_sprite: None,
+                pixel_owners: None,
             }
         }
     }
@@ -1951,6 +2525,7 @@ mod test {
                 .field("fine_x_scroll", &self.fine_x_scroll)
                 .field("ppu_write_latch", &self.write_latch)
                 .field("ppu_data_buffer", &self.read_buffer)
+                .field("io_latch", &self.io_latch)
                 .field("scanline", &self.scanline)
                 .field("cycle", &self.cycle)
                 .field("frame_count", &self.frame_count)
@@ -1984,6 +2559,7 @@ mod test {
                 && self.fine_x_scroll == other.fine_x_scroll
                 && self.write_latch == other.write_latch
                 && self.read_buffer == other.read_buffer
+                && self.io_latch == other.io_latch
                 && self.scanline == other.scanline
                 && self.cycle == other.cycle
                 && self.frame_count == other.frame_count
@@ -0,0 +1,40 @@
+//! The ppu_config module contains the options that change how the PPU draws the screen, as opposed to
+//! the accuracy options which trade faithfulness for speed. They are gathered into a single struct
+//! that the PPU is created with, so that new video options don't each need their own setter.
+
+/// The options that change how the PPU draws the screen. Override single options with struct update syntax:
+/// ```
+/// use gc_nes_core::nes::PpuConfig;
+///
+/// let config = PpuConfig {
+///     sprite_limit: false,
+///     ..Default::default()
+/// };
+/// ```
+/// Frame blending works on colours rather than the PPU's colour indices, so it is an option of the
+/// [ArgbFrameBuffer](../video/struct.ArgbFrameBuffer.html#method.set_frame_blending) instead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PpuConfig {
+    /// Limit each scanline to eight sprites, as the PPU does. Games that show more than eight sprites on a scanline
+    /// flicker them so that each is seen some of the time, which disabling the limit removes. Games that rely on
+    /// the limit to hide sprites, usually behind a column of blank sprites, will show them instead.
+    pub sprite_limit: bool,
+    /// Draw the eight pixels around each edge of the screen as black, where televisions usually cut the picture off.
+    /// Many games leave garbage in this area while they scroll.
+    pub hide_overscan: bool,
+    /// Draw the screen in greyscale while the game sets the greyscale bit of $2001, as the PPU does
+    pub greyscale: bool,
+    /// Pass the colour emphasis bits of $2001 on to the video backend, which can tint the colours it draws with them
+    pub emphasis: bool,
+}
+
+impl Default for PpuConfig {
+    fn default() -> Self {
+        return PpuConfig {
+            sprite_limit: true,
+            hide_overscan: false,
+            greyscale: true,
+            emphasis: true,
+        };
+    }
+}
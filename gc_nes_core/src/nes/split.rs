@@ -0,0 +1,99 @@
+//! The split module contains helpers for homebrew developers working out how to change the scroll
+//! position part way down the screen, a raster split, and testing it inside the emulator.
+//!
+//! Changing the scroll mid-frame takes four register writes: the nametable through $2006, the
+//! Y and then X scroll through $2005, and finally the low byte of the vram address through $2006.
+//! The first three only change the PPU's temporary vram address (t), so they can be made at any
+//! time during the frame. The last copies t into the vram address the PPU renders from (v), so it
+//! has to land in the horizontal blank of the scanline before the split, after the PPU has finished
+//! incrementing v for that scanline and before it fetches the first tiles of the next.
+//! ```
+//! use gc_nes_core::nes::plan_scroll_split;
+//!
+//! // Show the top of the second nametable, scrolled 12 pixels to the right, from scanline 32 down
+//! let split = plan_scroll_split(32, 12, 0, 1).unwrap();
+//! assert_eq!([(0x2006, 0x04), (0x2005, 0x00), (0x2005, 0x0c), (0x2006, 0x01)], split.writes);
+//! assert_eq!(31, split.write_scanline);
+//! ```
+
+use std::error::Error;
+use std::ops::RangeInclusive;
+
+/// The number of PPU dots in each scanline
+const DOTS_PER_SCANLINE: u64 = 341;
+/// The number of PPU dots in each CPU cycle
+const DOTS_PER_CPU_CYCLE: u64 = 3;
+/// The dots of a rendered scanline between the vertical increment of v and the first tile fetches for the next scanline
+const HORIZONTAL_BLANK_DOTS: RangeInclusive<u16> = 257..=320;
+
+/// The register writes and timing needed to change the scroll position at a scanline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrollSplit {
+    /// The writes to make, in order, as pairs of CPU address and value
+    pub writes: [(u16, u8); 4],
+    /// The scanline that the final write has to land on
+    pub write_scanline: u16,
+    /// The PPU dots of write_scanline that the final write has to land within
+    pub write_dots: RangeInclusive<u16>,
+}
+
+impl ScrollSplit {
+    /// Gets the CPU cycles, counted from the first dot of scanline 0, that the final write has to land within.
+    /// A CPU cycle lines up with a different one of three PPU dots on each power on, so the window is
+    /// narrowed to the cycles that are inside it for every alignment.
+    pub fn get_cpu_cycle_window(&self) -> RangeInclusive<u64> {
+        let scanline_start = u64::from(self.write_scanline) * DOTS_PER_SCANLINE;
+        let first_dot = scanline_start + u64::from(*self.write_dots.start());
+        let last_dot = scanline_start + u64::from(*self.write_dots.end());
+        return (first_dot / DOTS_PER_CPU_CYCLE + 1)..=(last_dot / DOTS_PER_CPU_CYCLE - 1);
+    }
+}
+
+/// Works out how to show the scroll position (scroll_x, scroll_y) of a nametable (0 to 3) from a scanline
+/// (1 to 239) down. The scroll position is in pixels from the top left of the nametable, like the values
+/// written to $2005 in vertical blank.
+pub fn plan_scroll_split(scanline: u16, scroll_x: u8, scroll_y: u8, nametable: u8) -> Result<ScrollSplit, Box<dyn Error>> {
+    if scanline == 0 || scanline > 239 {
+        bail!("Raster splits can only start on scanlines 1 to 239, not {}", scanline);
+    }
+    if scroll_y > 239 {
+        bail!("The Y scroll position must be from 0 to 239, not {}", scroll_y);
+    }
+    if nametable > 3 {
+        bail!("The nametable must be from 0 to 3, not {}", nametable);
+    }
+    return Ok(ScrollSplit {
+        writes: [
+            // Selects the nametable in t, and clears the top bit of fine Y
+            (0x2006, nametable << 2),
+            // Sets coarse Y and fine Y in t
+            (0x2005, scroll_y),
+            // Sets coarse X in t and fine X immediately
+            (0x2005, scroll_x),
+            // Sets coarse X and the low bits of coarse Y in t again, then copies t into v
+            (0x2006, ((scroll_y & 0xf8) << 2) | (scroll_x >> 3)),
+        ],
+        write_scanline: scanline - 1,
+        write_dots: HORIZONTAL_BLANK_DOTS,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plan_scroll_split_invalid() {
+        assert!(plan_scroll_split(0, 0, 0, 0).is_err());
+        assert!(plan_scroll_split(240, 0, 0, 0).is_err());
+        assert!(plan_scroll_split(100, 0, 240, 0).is_err());
+        assert!(plan_scroll_split(100, 0, 0, 4).is_err());
+    }
+
+    #[test]
+    fn test_cpu_cycle_window() {
+        let split = plan_scroll_split(1, 0, 0, 0).unwrap();
+        // Dots 257 to 320 of scanline 0 are cycles 85.67 to 106.67, so only 86 to 105 are safe
+        assert_eq!(86..=105, split.get_cpu_cycle_window());
+    }
+}
@@ -0,0 +1,86 @@
+//! The test rom module reads the results that blargg's test ROMs, such as apu_test and the other suites that share
+//! their framework, report through cartridge RAM, so that the suites can be run without a window or a person
+//! reading the screen.
+//!
+//! The ROMs write the signature $DE $B0 $61 to $6001-$6003 once the status at $6000 is valid. The status is $80
+//! while the test is running, $81 when reset needs to be pressed, and the result code once the test has finished:
+//! zero for a pass and the number of the failed test otherwise. A text description of the result is written from
+//! $6004 and ends at the first zero byte.
+
+/// The address of the status byte
+const STATUS_ADDRESS: u16 = 0x6000;
+/// The signature that is written after the status byte once the status is valid
+const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+/// The address that the text description of the result starts at
+const TEXT_ADDRESS: u16 = 0x6004;
+/// The end of the cartridge RAM that the text is read from
+const TEXT_END_ADDRESS: u16 = 0x7fff;
+
+/// The status that a test ROM reports through cartridge RAM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestRomStatus {
+    /// The test is still running
+    Running,
+    /// The test needs reset to be pressed to continue, which should be done after at least 100 milliseconds
+    ResetRequested,
+    /// The test finished with a result code, zero for a pass, along with the text the ROM printed
+    Finished {
+        /// The result code, zero for a pass and the number of the failed test otherwise
+        code: u8,
+        /// The text that the ROM printed, describing the result
+        text: String,
+    },
+}
+
+impl TestRomStatus {
+    /// Reads the status from the cartridge RAM through the read function, or returns None if the signature hasn't been written
+    pub(super) fn read<F: Fn(u16) -> u8>(read: F) -> Option<TestRomStatus> {
+        if (0..3).any(|i| read(STATUS_ADDRESS + 1 + i) != SIGNATURE[usize::from(i)]) {
+            return None;
+        }
+        return Some(match read(STATUS_ADDRESS) {
+            0x80 => TestRomStatus::Running,
+            0x81 => TestRomStatus::ResetRequested,
+            code => TestRomStatus::Finished {
+                code,
+                text: (TEXT_ADDRESS..=TEXT_END_ADDRESS)
+                    .map(&read)
+                    .take_while(|character| *character != 0x00)
+                    .map(char::from)
+                    .collect(),
+            },
+        });
+    }
+
+    /// Checks if the test finished and passed
+    pub fn is_passed(&self) -> bool {
+        return matches!(self, TestRomStatus::Finished { code: 0, .. });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_status() {
+        let mut ram = [0x00u8; 0x2000];
+        assert_eq!(None, TestRomStatus::read(|address| ram[usize::from(address - 0x6000)]));
+        ram[0..4].copy_from_slice(&[0x80, 0xde, 0xb0, 0x61]);
+        assert_eq!(
+            Some(TestRomStatus::Running),
+            TestRomStatus::read(|address| ram[usize::from(address - 0x6000)])
+        );
+        ram[0] = 0x03;
+        ram[4..10].copy_from_slice(b"Fail\n\0");
+        let status = TestRomStatus::read(|address| ram[usize::from(address - 0x6000)]).unwrap();
+        assert_eq!(
+            TestRomStatus::Finished {
+                code: 3,
+                text: "Fail\n".to_string()
+            },
+            status
+        );
+        assert!(!status.is_passed());
+    }
+}
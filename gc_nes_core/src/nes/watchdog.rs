@@ -0,0 +1,64 @@
+//! The watchdog module detects games that appear to have hung, by counting the frames in which the CPU did nothing
+//! that a running game does every frame: reading PPUSTATUS, latching the controllers or changing RAM. A game stuck
+//! in a tight loop after a crash, or waiting on an interrupt that never comes, does none of them.
+
+/// Counts the consecutive frames without any sign of the game running, against the number that it takes to be hung
+#[derive(Debug, Clone)]
+pub(super) struct HangWatchdog {
+    /// The number of consecutive idle frames that the game has to spend to be considered hung
+    threshold: u32,
+    /// The number of consecutive frames completed without any sign of the game running
+    idle_frames: u32,
+}
+
+impl HangWatchdog {
+    /// Creates a watchdog that considers the game hung after the threshold number of idle frames
+    pub(super) fn new(threshold: u32) -> Self {
+        return HangWatchdog { threshold, idle_frames: 0 };
+    }
+
+    /// Completes a frame, with whether the game showed any sign of running during it.
+    /// Returns true if the game has just become hung with this frame.
+    pub(super) fn end_frame(&mut self, active: bool) -> bool {
+        if active {
+            self.idle_frames = 0;
+            return false;
+        }
+        self.idle_frames = self.idle_frames.saturating_add(1);
+        return self.idle_frames == self.threshold;
+    }
+
+    /// Checks if the game has been idle for at least the threshold number of frames
+    pub(super) fn is_hung(&self) -> bool {
+        return self.idle_frames >= self.threshold;
+    }
+
+    /// Gets the number of consecutive frames completed without any sign of the game running
+    pub(super) fn get_idle_frames(&self) -> u32 {
+        return self.idle_frames;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hang_watchdog() {
+        let mut watchdog = HangWatchdog::new(3);
+        assert!(!watchdog.end_frame(false));
+        assert!(!watchdog.end_frame(false));
+        // Activity starts the count over
+        assert!(!watchdog.end_frame(true));
+        assert!(!watchdog.end_frame(false));
+        assert!(!watchdog.end_frame(false));
+        assert!(!watchdog.is_hung());
+        // The game only becomes hung once, on the frame that reaches the threshold
+        assert!(watchdog.end_frame(false));
+        assert!(watchdog.is_hung());
+        assert!(!watchdog.end_frame(false));
+        assert_eq!(4, watchdog.get_idle_frames());
+        assert!(!watchdog.end_frame(true));
+        assert!(!watchdog.is_hung());
+    }
+}
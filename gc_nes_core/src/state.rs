@@ -0,0 +1,325 @@
+//! The state module contains the reader and writer that the NES' components save their state into, which
+//! [Nes::save_state](../nes/struct.Nes.html#method.save_state) puts together into a versioned save state.
+//! Mappers added by other crates use them to save their registers through
+//! [Mapper::save_state](../cartridge/trait.Mapper.html#method.save_state).
+//!
+//! Save states can carry a half size [thumbnail](struct.StateThumbnail.html) of the screen ahead of the state of the
+//! components, which [peek_state_thumbnail](fn.peek_state_thumbnail.html) reads without loading the rest, so that
+//! frontends can show previews of their save slots.
+//!
+//! Values are written in little endian order, one after another, with nothing to mark where each one is. Components
+//! have to read their state back in the same order it was written, so values that are only sometimes present, like
+//! an Option, are written the same way every time to keep a cartridge's save states the same size.
+
+use std::convert::TryInto;
+use std::error::Error;
+
+/// The version of the save state format, which changes whenever the state of any of the components does
+pub const SAVE_STATE_VERSION: u32 = 1;
+/// The magic number that save states start with
+const SAVE_STATE_MAGIC: &[u8] = b"GCNS";
+
+/// A half size copy of the screen when a save state was saved, with each pixel represented by four bytes in RGBA order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateThumbnail {
+    /// The width of the thumbnail in pixels
+    pub width: usize,
+    /// The height of the thumbnail in pixels
+    pub height: usize,
+    /// The pixels of the thumbnail, stored row by row from the top left
+    pub pixels: Vec<u8>,
+}
+
+impl StateThumbnail {
+    /// Downscales an RGBA frame to half its width and height by averaging each 2x2 block of pixels
+    pub(crate) fn from_rgba_frame(frame: &[u8], frame_width: usize, frame_height: usize) -> Self {
+        let (width, height) = (frame_width / 2, frame_height / 2);
+        let mut pixels = vec![0xff; width * height * 4];
+        for (index, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+            let top_left = 2 * (index % width) + 2 * (index / width) * frame_width;
+            let block = [top_left, top_left + 1, top_left + frame_width, top_left + frame_width + 1];
+            for (channel, value) in pixel.iter_mut().take(3).enumerate() {
+                let channel_sum: usize = block.iter().map(|frame_index| frame[4 * frame_index + channel] as usize).sum();
+                *value = (channel_sum / 4) as u8;
+            }
+        }
+        return StateThumbnail { width, height, pixels };
+    }
+}
+
+/// Writes the start of a save state: the magic number, the version, and the thumbnail if there is one
+pub(crate) fn write_state_header(state: &mut StateWriter, thumbnail: Option<&StateThumbnail>) {
+    state.write_bytes(SAVE_STATE_MAGIC);
+    state.write_u32(SAVE_STATE_VERSION);
+    state.write_bool(thumbnail.is_some());
+    if let Some(thumbnail) = thumbnail {
+        state.write_u16(thumbnail.width as u16);
+        state.write_u16(thumbnail.height as u16);
+        state.write_bytes(&thumbnail.pixels);
+    }
+}
+
+/// Reads the start of a save state written by write_state_header, failing if the data isn't a save state or is from
+/// another version of the format
+pub(crate) fn read_state_header(state: &mut StateReader) -> Result<Option<StateThumbnail>, Box<dyn Error>> {
+    if state.read_bytes(SAVE_STATE_MAGIC.len()).ok() != Some(SAVE_STATE_MAGIC) {
+        bail!("The data is not a save state");
+    }
+    let version = state.read_u32()?;
+    if version != SAVE_STATE_VERSION {
+        bail!("Save state version {} is not supported, only version {} is", version, SAVE_STATE_VERSION);
+    }
+    if !state.read_bool()? {
+        return Ok(None);
+    }
+    let width = state.read_u16()? as usize;
+    let height = state.read_u16()? as usize;
+    let pixels = state.read_bytes(width * height * 4)?.to_vec();
+    return Ok(Some(StateThumbnail { width, height, pixels }));
+}
+
+/// Reads the thumbnail from a save state saved by [Nes::save_state_with_thumbnail](../nes/struct.Nes.html#method.save_state_with_thumbnail)
+/// without loading the rest of it. Returns None if the state was saved without one, and fails if the data isn't a
+/// save state or is from another version of the format.
+pub fn peek_state_thumbnail(data: &[u8]) -> Result<Option<StateThumbnail>, Box<dyn Error>> {
+    return read_state_header(&mut StateReader::new(data));
+}
+
+/// Writes the state of the NES' components as bytes
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    /// The bytes written so far
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Creates a new writer with nothing written to it
+    pub fn new() -> Self {
+        return Default::default();
+    }
+
+    /// Writes a single byte
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    /// Writes a boolean as a single byte, 1 for true and 0 for false
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    /// Writes a 16 bit value
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a 32 bit value
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a 64 bit value
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes an optional byte, which takes up the same space whether it is present or not
+    pub fn write_option_u8(&mut self, value: Option<u8>) {
+        self.write_bool(value.is_some());
+        self.write_u8(value.unwrap_or_default());
+    }
+
+    /// Writes an optional 64 bit value, which takes up the same space whether it is present or not
+    pub fn write_option_u64(&mut self, value: Option<u64>) {
+        self.write_bool(value.is_some());
+        self.write_u64(value.unwrap_or_default());
+    }
+
+    /// Writes bytes whose length the reader already knows, like the contents of a fixed size array
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Writes bytes preceded by their length, for memory whose size depends on the cartridge
+    pub fn write_block(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+
+    /// Consumes the writer, returning the bytes written to it
+    pub fn into_bytes(self) -> Vec<u8> {
+        return self.data;
+    }
+}
+
+/// Reads the state of the NES' components back from the bytes written by a [StateWriter](struct.StateWriter.html).
+/// Reads past the end of the state fail, rather than panicking, since save states can come from anywhere.
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    /// The bytes that haven't been read yet
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Creates a new reader for the provided bytes
+    pub fn new(data: &'a [u8]) -> Self {
+        return StateReader { data };
+    }
+
+    /// Reads the next bytes whose length the reader already knows
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if length > self.data.len() {
+            bail!("The save state ended {} bytes early", length - self.data.len());
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        return Ok(bytes);
+    }
+
+    /// Reads the next bytes into an array, like the contents of a fixed size array in the component
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn Error>> {
+        return Ok(self.read_bytes(N)?.try_into()?);
+    }
+
+    /// Reads a single byte
+    pub fn read_u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        return Ok(self.read_bytes(1)?[0]);
+    }
+
+    /// Reads a boolean, failing for bytes other than 0 and 1
+    pub fn read_bool(&mut self) -> Result<bool, Box<dyn Error>> {
+        return match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => bail!("Invalid boolean in save state: {}", value),
+        };
+    }
+
+    /// Reads a 16 bit value
+    pub fn read_u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        return Ok(u16::from_le_bytes(self.read_array()?));
+    }
+
+    /// Reads a 32 bit value
+    pub fn read_u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        return Ok(u32::from_le_bytes(self.read_array()?));
+    }
+
+    /// Reads a 64 bit value
+    pub fn read_u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        return Ok(u64::from_le_bytes(self.read_array()?));
+    }
+
+    /// Reads an optional byte written by [write_option_u8](struct.StateWriter.html#method.write_option_u8)
+    pub fn read_option_u8(&mut self) -> Result<Option<u8>, Box<dyn Error>> {
+        let present = self.read_bool()?;
+        let value = self.read_u8()?;
+        return Ok(if present { Some(value) } else { None });
+    }
+
+    /// Reads an optional 64 bit value written by [write_option_u64](struct.StateWriter.html#method.write_option_u64)
+    pub fn read_option_u64(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        let present = self.read_bool()?;
+        let value = self.read_u64()?;
+        return Ok(if present { Some(value) } else { None });
+    }
+
+    /// Reads bytes preceded by their length, written by [write_block](struct.StateWriter.html#method.write_block)
+    pub fn read_block(&mut self) -> Result<&'a [u8], Box<dyn Error>> {
+        let length = self.read_u32()? as usize;
+        return self.read_bytes(length);
+    }
+
+    /// Reads bytes preceded by their length into memory that is already the right size, failing if the length is
+    /// different, which means the state was saved with a different cartridge
+    pub fn read_block_into(&mut self, memory: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        let block = self.read_block()?;
+        if block.len() != memory.len() {
+            bail!("Save state has {} bytes of memory where {} were expected", block.len(), memory.len());
+        }
+        memory.copy_from_slice(block);
+        return Ok(());
+    }
+
+    /// Checks that every byte of the state has been read, failing if there are bytes left over
+    pub fn finish(&self) -> Result<(), Box<dyn Error>> {
+        if !self.data.is_empty() {
+            bail!("The save state has {} bytes left over", self.data.len());
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut writer = StateWriter::new();
+        writer.write_u8(0x12);
+        writer.write_bool(true);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789a_bcde);
+        writer.write_u64(0x0123_4567_89ab_cdef);
+        writer.write_option_u8(None);
+        writer.write_option_u64(Some(42));
+        writer.write_bytes(&[1, 2, 3]);
+        writer.write_block(&[4, 5]);
+        let bytes = writer.into_bytes();
+        // Options take up the same space whether they are present or not
+        assert_eq!(1 + 1 + 2 + 4 + 8 + 2 + 9 + 3 + 6, bytes.len());
+
+        let mut reader = StateReader::new(&bytes);
+        assert_eq!(0x12, reader.read_u8().unwrap());
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(0x3456, reader.read_u16().unwrap());
+        assert_eq!(0x789a_bcde, reader.read_u32().unwrap());
+        assert_eq!(0x0123_4567_89ab_cdef, reader.read_u64().unwrap());
+        assert_eq!(None, reader.read_option_u8().unwrap());
+        assert_eq!(Some(42), reader.read_option_u64().unwrap());
+        assert_eq!([1, 2, 3], reader.read_array().unwrap());
+        let mut memory = [0; 2];
+        reader.read_block_into(&mut memory).unwrap();
+        assert_eq!([4, 5], memory);
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn test_state_reader_errors() {
+        let mut reader = StateReader::new(&[0x02, 0x01]);
+        assert!(reader.read_bool().is_err());
+        assert!(reader.read_u16().is_err());
+        assert!(reader.finish().is_err());
+
+        let mut writer = StateWriter::new();
+        writer.write_block(&[0; 4]);
+        let bytes = writer.into_bytes();
+        let mut memory = [0; 8];
+        assert!(StateReader::new(&bytes).read_block_into(&mut memory).is_err());
+    }
+
+    #[test]
+    fn test_state_thumbnail() {
+        // A 4x2 frame, whose left half is red and green and whose right half is white
+        let mut frame = vec![0xff; 4 * 8];
+        frame[..8].copy_from_slice(&[0xff, 0x00, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff]);
+        frame[16..24].copy_from_slice(&[0x00, 0xff, 0x00, 0xff, 0xff, 0x00, 0x00, 0xff]);
+        let thumbnail = StateThumbnail::from_rgba_frame(&frame, 4, 2);
+        assert_eq!((2, 1), (thumbnail.width, thumbnail.height));
+        assert_eq!(vec![0x7f, 0x7f, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff], thumbnail.pixels);
+
+        let mut writer = StateWriter::new();
+        write_state_header(&mut writer, Some(&thumbnail));
+        writer.write_u8(0x12);
+        let bytes = writer.into_bytes();
+        assert_eq!(Some(thumbnail), peek_state_thumbnail(&bytes).unwrap());
+
+        let mut writer = StateWriter::new();
+        write_state_header(&mut writer, None);
+        let bytes = writer.into_bytes();
+        assert_eq!(None, peek_state_thumbnail(&bytes).unwrap());
+        assert!(peek_state_thumbnail(&bytes[..bytes.len() - 1]).is_err());
+        assert!(peek_state_thumbnail(b"NES\x1a").is_err());
+    }
+}
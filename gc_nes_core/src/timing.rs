@@ -0,0 +1,113 @@
+//! The timing module models the NES' master clock, which the CPU and PPU clocks are divided from, so that frontends
+//! can derive exact pacing for video and audio rather than approximating a frame as 16 ms.
+//! ```
+//! use gc_nes_core::cartridge::Cartridge;
+//! use gc_nes_core::nes::Nes;
+//!
+//! # fn run(cartridge: Cartridge) {
+//! let nes = Nes::new(cartridge);
+//! let clock = nes.get_master_clock();
+//! // Sleep for exactly one frame between frames
+//! let frame_duration = clock.frame_duration();
+//! // Expect about 800 samples per frame from the audio device
+//! let samples_per_frame = clock.audio_samples_per_frame(48_000.0);
+//! # }
+//! ```
+//! Only NTSC consoles are emulated, so [MasterClock::NTSC](struct.MasterClock.html#associatedconstant.NTSC) is the
+//! only clock for now.
+
+use std::time::Duration;
+
+/// The rates that an NES' master clock runs at and is divided by to clock the CPU and PPU, along with the number of
+/// PPU cycles in each frame, which together determine the console's frame rate and audio sample rate
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MasterClock {
+    /// The rate of the master clock crystal, in Hz
+    pub master_rate: f64,
+    /// The number of master clock cycles in each CPU cycle
+    pub cpu_divider: u32,
+    /// The number of master clock cycles in each PPU cycle
+    pub ppu_divider: u32,
+    /// The number of PPU cycles in each scanline
+    pub dots_per_scanline: u32,
+    /// The number of scanlines in each frame, including vertical blank
+    pub scanlines_per_frame: u32,
+    /// The rate, in Hz, that a television picture of the console's video standard would be sampled at for its pixels
+    /// to be square, which the rate of the PPU's pixels is compared against to find their shape
+    pub square_pixel_rate: f64,
+}
+
+impl MasterClock {
+    /// The clock of the NTSC NES, which divides a 236.25 / 11 MHz crystal by 12 for the CPU and 4 for the PPU
+    pub const NTSC: MasterClock = MasterClock {
+        master_rate: 236_250_000.0 / 11.0,
+        cpu_divider: 12,
+        ppu_divider: 4,
+        dots_per_scanline: 341,
+        scanlines_per_frame: 262,
+        square_pixel_rate: 135_000_000.0 / 22.0,
+    };
+
+    /// Gets the rate that the CPU runs at, in Hz, which is also the rate that the APU produces audio samples at
+    pub const fn cpu_rate(&self) -> f64 {
+        return self.master_rate / self.cpu_divider as f64;
+    }
+
+    /// Gets the rate that the PPU runs at, in Hz
+    pub const fn ppu_rate(&self) -> f64 {
+        return self.master_rate / self.ppu_divider as f64;
+    }
+
+    /// Gets the average number of PPU cycles in each frame. While rendering is enabled, the PPU skips a cycle on
+    /// every other frame, so frames alternate between one cycle shorter and the full length.
+    pub const fn ppu_cycles_per_frame(&self) -> f64 {
+        return (self.dots_per_scanline * self.scanlines_per_frame) as f64 - 0.5;
+    }
+
+    /// Gets the average number of CPU cycles in each frame
+    pub const fn cpu_cycles_per_frame(&self) -> f64 {
+        return self.ppu_cycles_per_frame() * self.ppu_divider as f64 / self.cpu_divider as f64;
+    }
+
+    /// Gets the rate that frames are produced at, in Hz
+    pub const fn frame_rate(&self) -> f64 {
+        return self.ppu_rate() / self.ppu_cycles_per_frame();
+    }
+
+    /// Gets the ratio of the width of the PPU's pixels to their height on a television, 8:7 for NTSC
+    pub fn pixel_aspect_ratio(&self) -> f64 {
+        return self.square_pixel_rate / self.ppu_rate();
+    }
+
+    /// Gets the average duration of a single frame
+    pub fn frame_duration(&self) -> Duration {
+        return Duration::from_secs_f64(1.0 / self.frame_rate());
+    }
+
+    /// Gets the number of CPU cycles, and so samples produced by the APU, in each sample at an output rate in Hz
+    pub fn cpu_cycles_per_audio_sample(&self, sample_rate: f64) -> f64 {
+        return self.cpu_rate() / sample_rate;
+    }
+
+    /// Gets the average number of samples at an output rate in Hz that are produced during each frame
+    pub fn audio_samples_per_frame(&self, sample_rate: f64) -> f64 {
+        return sample_rate / self.frame_rate();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ntsc_clock() {
+        let clock = MasterClock::NTSC;
+        assert!((clock.cpu_rate() - 1_789_772.727).abs() < 0.001);
+        assert_eq!(29_780.5, clock.cpu_cycles_per_frame());
+        assert!((clock.frame_rate() - 60.0988).abs() < 0.0001);
+        assert!((clock.audio_samples_per_frame(48_000.0) - 798.7).abs() < 0.1);
+        assert!((clock.cpu_cycles_per_audio_sample(44_100.0) - 40.58).abs() < 0.01);
+        assert_eq!(16_639_263, clock.frame_duration().as_nanos());
+        assert!((clock.pixel_aspect_ratio() - 8.0 / 7.0).abs() < 1e-9);
+    }
+}
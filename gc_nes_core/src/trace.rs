@@ -0,0 +1,276 @@
+//! The trace module contains the events that make up a trace of the NES' execution, which
+//! [Nes::set_execution_trace](../nes/struct.Nes.html#method.set_execution_trace) enables, and the formats that
+//! traces are written in: a text log in the style of the nestest log, for reading, and JSON Lines, for analysis
+//! pipelines and for diffing against other emulators.
+//! ```
+//! # #[cfg(feature = "debugger")]
+//! # mod example {
+//! use gc_nes_core::cartridge::Cartridge;
+//! use gc_nes_core::nes::Nes;
+//! use gc_nes_core::trace::{write_trace, TraceFormat};
+//!
+//! # fn run(cartridge: Cartridge) -> std::io::Result<()> {
+//! let mut nes = Nes::new(cartridge);
+//! nes.set_execution_trace(true);
+//! let mut output = std::io::stdout();
+//! for _ in 0..10 {
+//!     nes.frame();
+//!     write_trace(&mut output, &nes.take_execution_trace(), TraceFormat::JsonLines)?;
+//! }
+//! # return Ok(());
+//! # }
+//! # }
+//! ```
+//!
+//! ### JSON Lines Format
+//! Each event is written as a JSON object on its own line, with a `type` field naming the kind of event and a
+//! `cycle` field holding the CPU cycle it happened on, counted from when the NES was created. All other values
+//! are unsigned integers. The fields of each type of event are:
+//! * `instruction`: `pc`, `opcode`, `a`, `x`, `y`, `p` and `sp`, the state of the CPU before the instruction runs
+//! * `read` and `write`: `address` and `value`, for every access to the CPU bus, including those made by DMA
+//! * `interrupt`: `kind`, either `"irq"` or `"nmi"`, and `return_address`, where the CPU returns to afterwards
+//! * `frame`: `frame`, the number of frames completed, when the PPU completes a frame
+//!
+//! New fields may be added to events, but existing fields won't be changed or removed.
+//!
+//! The CPU makes all of an instruction's accesses to the bus on the instruction's first cycle, so the reads and
+//! writes that follow an instruction or interrupt share its cycle, rather than being spread across the cycles that
+//! the real CPU would have made them on.
+//!
+//! Everything but [InterruptKind](enum.InterruptKind.html) needs the debugger feature, which is enabled by default.
+
+#[cfg(feature = "debugger")]
+use std::fmt;
+#[cfg(feature = "debugger")]
+use std::io::{self, Write};
+
+/// The kinds of interrupt that the CPU takes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterruptKind {
+    /// A maskable interrupt request, from the APU or the cartridge
+    Irq,
+    /// A non-maskable interrupt, from the PPU at the start of vertical blank
+    Nmi,
+}
+
+impl InterruptKind {
+    /// Gets the name of the interrupt kind, as it is written in traces
+    pub fn name(self) -> &'static str {
+        return match self {
+            InterruptKind::Irq => "irq",
+            InterruptKind::Nmi => "nmi",
+        };
+    }
+}
+
+/// A single event in a trace of the NES' execution. Each holds the CPU cycle that it happened on,
+/// counted from when the NES was created.
+#[cfg(feature = "debugger")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// The CPU started executing an instruction, with the state of its registers before the instruction runs
+    Instruction {
+        /// The CPU cycle that the instruction's opcode was read on
+        cycle: u64,
+        /// The address of the instruction's opcode
+        program_counter: u16,
+        /// The instruction's opcode
+        opcode: u8,
+        /// The accumulator
+        accumulator: u8,
+        /// The X register
+        x_register: u8,
+        /// The Y register
+        y_register: u8,
+        /// The status register, holding the CPU's flags
+        status_register: u8,
+        /// The stack pointer
+        stack_pointer: u8,
+    },
+    /// A value was read from the CPU bus
+    Read {
+        /// The CPU cycle that the read was made on
+        cycle: u64,
+        /// The address that was read from
+        address: u16,
+        /// The value that was read
+        value: u8,
+    },
+    /// A value was written to the CPU bus
+    Write {
+        /// The CPU cycle that the write was made on
+        cycle: u64,
+        /// The address that was written to
+        address: u16,
+        /// The value that was written
+        value: u8,
+    },
+    /// The CPU took an interrupt instead of executing the next instruction, which it returns to afterwards
+    Interrupt {
+        /// The CPU cycle that the CPU started taking the interrupt on
+        cycle: u64,
+        /// Whether the interrupt was an IRQ or an NMI
+        kind: InterruptKind,
+        /// The address of the instruction that the CPU returns to once the interrupt has been handled
+        return_address: u16,
+    },
+    /// The PPU completed a frame
+    Frame {
+        /// The CPU cycle that the frame was completed on
+        cycle: u64,
+        /// The number of frames that have been completed, including this one
+        frame: u64,
+    },
+}
+
+#[cfg(feature = "debugger")]
+impl TraceEvent {
+    /// Gets the CPU cycle that the event happened on
+    pub fn get_cycle(&self) -> u64 {
+        return match self {
+            TraceEvent::Instruction { cycle, .. }
+            | TraceEvent::Read { cycle, .. }
+            | TraceEvent::Write { cycle, .. }
+            | TraceEvent::Interrupt { cycle, .. }
+            | TraceEvent::Frame { cycle, .. } => *cycle,
+        };
+    }
+
+    /// Formats the event as a single line of JSON, without the trailing newline
+    pub fn to_json(&self) -> String {
+        return match self {
+            TraceEvent::Instruction {
+                cycle,
+                program_counter,
+                opcode,
+                accumulator,
+                x_register,
+                y_register,
+                status_register,
+                stack_pointer,
+            } => format!(
+                r#"{{"type":"instruction","cycle":{},"pc":{},"opcode":{},"a":{},"x":{},"y":{},"p":{},"sp":{}}}"#,
+                cycle, program_counter, opcode, accumulator, x_register, y_register, status_register, stack_pointer
+            ),
+            TraceEvent::Read { cycle, address, value } => format!(r#"{{"type":"read","cycle":{},"address":{},"value":{}}}"#, cycle, address, value),
+            TraceEvent::Write { cycle, address, value } => format!(r#"{{"type":"write","cycle":{},"address":{},"value":{}}}"#, cycle, address, value),
+            TraceEvent::Interrupt { cycle, kind, return_address } => format!(
+                r#"{{"type":"interrupt","cycle":{},"kind":"{}","return_address":{}}}"#,
+                cycle,
+                kind.name(),
+                return_address
+            ),
+            TraceEvent::Frame { cycle, frame } => format!(r#"{{"type":"frame","cycle":{},"frame":{}}}"#, cycle, frame),
+        };
+    }
+}
+
+/// Formats the event as a line of a text log. Instructions follow the layout of the nestest log, and the other
+/// events are indented beneath the instruction that caused them.
+#[cfg(feature = "debugger")]
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            TraceEvent::Instruction {
+                cycle,
+                program_counter,
+                opcode,
+                accumulator,
+                x_register,
+                y_register,
+                status_register,
+                stack_pointer,
+            } => write!(
+                f,
+                "{:04X}  {:02X}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                program_counter, opcode, accumulator, x_register, y_register, status_register, stack_pointer, cycle
+            ),
+            TraceEvent::Read { cycle, address, value } => write!(f, "      READ  ${:04X}=${:02X} CYC:{}", address, value, cycle),
+            TraceEvent::Write { cycle, address, value } => write!(f, "      WRITE ${:04X}=${:02X} CYC:{}", address, value, cycle),
+            TraceEvent::Interrupt { cycle, kind, return_address } => {
+                write!(f, "{} (return to ${:04X}) CYC:{}", kind.name().to_uppercase(), return_address, cycle)
+            }
+            TraceEvent::Frame { cycle, frame } => write!(f, "FRAME {} CYC:{}", frame, cycle),
+        };
+    }
+}
+
+/// The formats that traces can be written in
+#[cfg(feature = "debugger")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// A text log, with instructions in the style of the nestest log
+    Text,
+    /// One JSON object per line, in the format described in the [module documentation](index.html#json-lines-format)
+    JsonLines,
+}
+
+#[cfg(feature = "debugger")]
+impl TraceFormat {
+    /// Gets a trace format from its name, "text" or "jsonl"
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "text" => Some(TraceFormat::Text),
+            "jsonl" => Some(TraceFormat::JsonLines),
+            _ => None,
+        };
+    }
+}
+
+/// Writes the events to the writer in the format, one per line
+#[cfg(feature = "debugger")]
+pub fn write_trace<W: Write>(writer: &mut W, events: &[TraceEvent], format: TraceFormat) -> io::Result<()> {
+    for event in events {
+        match format {
+            TraceFormat::Text => writeln!(writer, "{}", event)?,
+            TraceFormat::JsonLines => writeln!(writer, "{}", event.to_json())?,
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(all(test, feature = "debugger"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_trace() {
+        let events = [
+            TraceEvent::Instruction {
+                cycle: 7,
+                program_counter: 0xc000,
+                opcode: 0x4c,
+                accumulator: 0x00,
+                x_register: 0x00,
+                y_register: 0x00,
+                status_register: 0x24,
+                stack_pointer: 0xfd,
+            },
+            TraceEvent::Read {
+                cycle: 7,
+                address: 0xc000,
+                value: 0x4c,
+            },
+            TraceEvent::Interrupt {
+                cycle: 10,
+                kind: InterruptKind::Nmi,
+                return_address: 0xc5f5,
+            },
+        ];
+        let mut text = Vec::new();
+        write_trace(&mut text, &events, TraceFormat::Text).unwrap();
+        assert_eq!(
+            "C000  4C  A:00 X:00 Y:00 P:24 SP:FD CYC:7\n      READ  $C000=$4C CYC:7\nNMI (return to $C5F5) CYC:10\n",
+            String::from_utf8(text).unwrap()
+        );
+        let mut json = Vec::new();
+        write_trace(&mut json, &events, TraceFormat::JsonLines).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        let lines: Vec<&str> = json.lines().collect();
+        assert_eq!(
+            r#"{"type":"instruction","cycle":7,"pc":49152,"opcode":76,"a":0,"x":0,"y":0,"p":36,"sp":253}"#,
+            lines[0]
+        );
+        assert_eq!(r#"{"type":"interrupt","cycle":10,"kind":"nmi","return_address":50677}"#, lines[2]);
+    }
+}
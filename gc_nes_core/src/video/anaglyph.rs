@@ -0,0 +1,90 @@
+//! The anaglyph module contains support for the Famicom 3D System, the shutter glasses used by a handful of
+//! games such as Famicom 3D Hot Rally. Those games draw alternate frames for each eye, switching the glasses'
+//! shutters through the expansion port as they do, which [Nes::get_frame_eye](../../nes/struct.Nes.html#method.get_frame_eye)
+//! reports. Without the glasses, the frames can be combined into a red-cyan anaglyph image with an
+//! [AnaglyphCompositor](struct.AnaglyphCompositor.html).
+//! ```
+//! use gc_nes_core::cartridge::Cartridge;
+//! use gc_nes_core::nes::Nes;
+//! use gc_nes_core::video::anaglyph::AnaglyphCompositor;
+//! use gc_nes_core::video::VideoBackend;
+//!
+//! # fn run(cartridge: Cartridge) {
+//! let mut nes = Nes::new(cartridge);
+//! let mut compositor = AnaglyphCompositor::default();
+//! nes.frame();
+//! compositor.add_frame(nes.get_frame_eye(), &nes.get_video_backend().get_rgba_frame().unwrap());
+//! let anaglyph: &[u8] = compositor.get_frame();
+//! # }
+//! ```
+
+use crate::nes::NES_SCREEN_DIMENSIONS;
+
+/// The eye that a frame is shown to by the Famicom 3D System's shutter glasses
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Eye {
+    /// The left eye, which sees frames drawn while OUT1 of the expansion port is clear
+    #[default]
+    Left,
+    /// The right eye, which sees frames drawn while OUT1 of the expansion port is set
+    Right,
+}
+
+impl Eye {
+    /// Gets the eye that the glasses show the screen to, from the value last written to the expansion port outputs
+    /// through $4016. OUT1, bit 1, switches the shutters.
+    pub(crate) fn from_expansion_output(output: u8) -> Self {
+        return if output & 0x02 > 0 { Eye::Right } else { Eye::Left };
+    }
+}
+
+/// Combines the frames for each eye into a red-cyan anaglyph image, for viewing the Famicom 3D System's games
+/// with coloured glasses instead of shutter glasses. The red channel is taken from the latest frame for the left
+/// eye and the green and blue channels from the latest frame for the right eye.
+#[derive(Clone)]
+pub struct AnaglyphCompositor {
+    /// The combined image, as RGBA bytes
+    buffer: Box<[u8; NES_SCREEN_DIMENSIONS * 4]>,
+}
+
+impl Default for AnaglyphCompositor {
+    fn default() -> Self {
+        return AnaglyphCompositor {
+            buffer: Box::new([0xff; NES_SCREEN_DIMENSIONS * 4]),
+        };
+    }
+}
+
+impl AnaglyphCompositor {
+    /// Adds a frame of RGBA bytes for an eye, replacing the channels taken from that eye's previous frame
+    pub fn add_frame(&mut self, eye: Eye, rgba_frame: &[u8]) {
+        let channels = match eye {
+            Eye::Left => 0..1,
+            Eye::Right => 1..3,
+        };
+        for (output, input) in self.buffer.chunks_exact_mut(4).zip(rgba_frame.chunks_exact(4)) {
+            output[channels.clone()].copy_from_slice(&input[channels.clone()]);
+        }
+    }
+
+    /// Gets the combined image, as RGBA bytes
+    pub fn get_frame(&self) -> &[u8; NES_SCREEN_DIMENSIONS * 4] {
+        return &self.buffer;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_anaglyph_compositor() {
+        assert_eq!(Eye::Left, Eye::from_expansion_output(0x05));
+        assert_eq!(Eye::Right, Eye::from_expansion_output(0x02));
+
+        let mut compositor = AnaglyphCompositor::default();
+        compositor.add_frame(Eye::Left, &[0x10, 0x20, 0x30, 0xff].repeat(NES_SCREEN_DIMENSIONS));
+        compositor.add_frame(Eye::Right, &[0x40, 0x50, 0x60, 0xff].repeat(NES_SCREEN_DIMENSIONS));
+        assert!(compositor.get_frame().chunks_exact(4).all(|pixel| pixel == [0x10, 0x50, 0x60, 0xff]));
+    }
+}
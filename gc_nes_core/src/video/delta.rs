@@ -0,0 +1,155 @@
+//! The delta module contains [FrameDelta](struct.FrameDelta.html), a compact description of the
+//! changes between two frames, for streaming the screen to a remote display with little bandwidth.
+//!
+//! Frames are compared as NES colour indices, as produced by an [IndexedFrameBuffer](../struct.IndexedFrameBuffer.html),
+//! in cells of 8x8 pixels. Most games only change a small part of the screen each frame, so sending the cells that
+//! changed is usually far smaller than sending the whole frame, without the cost of a general purpose compressor.
+
+use super::{NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
+use crate::nes::NES_SCREEN_DIMENSIONS;
+use std::convert::TryInto;
+use std::error::Error;
+
+/// The width and height of the cells that frames are compared in, in pixels
+pub const CELL_SIZE: usize = 8;
+/// The number of cells in each row of the screen
+const CELL_COLUMNS: usize = NES_SCREEN_WIDTH / CELL_SIZE;
+/// The number of cells in the screen
+const CELL_COUNT: usize = CELL_COLUMNS * (NES_SCREEN_HEIGHT / CELL_SIZE);
+/// The number of bytes that a cell takes up when a delta is encoded, two for its index followed by its pixels
+const ENCODED_CELL_SIZE: usize = 2 + CELL_SIZE * CELL_SIZE;
+
+/// A cell of the screen that changed between two frames
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedCell {
+    /// The index of the cell, counting across each row of cells from the top left of the screen
+    pub index: u16,
+    /// The colour indices of the cell's pixels in the new frame, row by row
+    pub pixels: [u8; CELL_SIZE * CELL_SIZE],
+}
+
+/// The cells that changed between two frames, which turn the first frame into the second when applied to it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameDelta {
+    /// The cells that changed, in order of their index
+    cells: Vec<ChangedCell>,
+}
+
+impl FrameDelta {
+    /// Finds the cells that changed between the previous frame and the current one
+    pub fn between(previous: &[u8; NES_SCREEN_DIMENSIONS], current: &[u8; NES_SCREEN_DIMENSIONS]) -> Self {
+        return FrameDelta {
+            cells: (0..CELL_COUNT)
+                .filter(|cell| cell_rows(*cell).any(|row| previous[row.clone()] != current[row]))
+                .map(|cell| read_cell(current, cell))
+                .collect(),
+        };
+    }
+
+    /// Creates a delta containing every cell of the frame, which can be applied to any frame to replace it.
+    /// Used to start a stream, or to recover after a delta was lost.
+    pub fn keyframe(current: &[u8; NES_SCREEN_DIMENSIONS]) -> Self {
+        return FrameDelta {
+            cells: (0..CELL_COUNT).map(|cell| read_cell(current, cell)).collect(),
+        };
+    }
+
+    /// Applies the delta to the frame it was calculated from, turning it into the frame that followed
+    pub fn apply(&self, frame: &mut [u8; NES_SCREEN_DIMENSIONS]) {
+        for cell in self.cells.iter() {
+            for (row, pixels) in cell_rows(usize::from(cell.index)).zip(cell.pixels.chunks_exact(CELL_SIZE)) {
+                frame[row].copy_from_slice(pixels);
+            }
+        }
+    }
+
+    /// Gets the cells that changed, in order of their index
+    pub fn get_changed_cells(&self) -> &[ChangedCell] {
+        return &self.cells;
+    }
+
+    /// Checks if the frames were identical
+    pub fn is_empty(&self) -> bool {
+        return self.cells.is_empty();
+    }
+
+    /// Encodes the delta for sending to a remote display. Each changed cell is encoded as its index, as a little
+    /// endian 16 bit integer, followed by its 64 pixels, so a delta takes 66 bytes for each cell that changed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.cells.len() * ENCODED_CELL_SIZE);
+        for cell in self.cells.iter() {
+            bytes.extend_from_slice(&cell.index.to_le_bytes());
+            bytes.extend_from_slice(&cell.pixels);
+        }
+        return bytes;
+    }
+
+    /// Decodes a delta encoded by [to_bytes](#method.to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if !bytes.len().is_multiple_of(ENCODED_CELL_SIZE) {
+            bail!("A frame delta of {} bytes isn't a whole number of cells", bytes.len());
+        }
+        let mut cells = Vec::with_capacity(bytes.len() / ENCODED_CELL_SIZE);
+        for encoded in bytes.chunks_exact(ENCODED_CELL_SIZE) {
+            let index = u16::from_le_bytes([encoded[0], encoded[1]]);
+            if usize::from(index) >= CELL_COUNT {
+                bail!("Frame delta cell index {} is outside of the screen", index);
+            }
+            cells.push(ChangedCell {
+                index,
+                pixels: encoded[2..].try_into()?,
+            });
+        }
+        return Ok(FrameDelta { cells });
+    }
+}
+
+/// Gets the ranges of a frame that hold each row of a cell's pixels
+fn cell_rows(cell: usize) -> impl Iterator<Item = std::ops::Range<usize>> {
+    let left = (cell % CELL_COLUMNS) * CELL_SIZE;
+    let top = (cell / CELL_COLUMNS) * CELL_SIZE;
+    return (top..top + CELL_SIZE).map(move |y| {
+        let start = y * NES_SCREEN_WIDTH + left;
+        start..start + CELL_SIZE
+    });
+}
+
+/// Copies the pixels of a cell out of a frame
+fn read_cell(frame: &[u8; NES_SCREEN_DIMENSIONS], cell: usize) -> ChangedCell {
+    let mut pixels = [0; CELL_SIZE * CELL_SIZE];
+    for (row, destination) in cell_rows(cell).zip(pixels.chunks_exact_mut(CELL_SIZE)) {
+        destination.copy_from_slice(&frame[row]);
+    }
+    return ChangedCell { index: cell as u16, pixels };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frame_delta() {
+        let previous = [0x0f; NES_SCREEN_DIMENSIONS];
+        let mut current = previous;
+        current[0] = 0x30;
+        current[100 * NES_SCREEN_WIDTH + 17] = 0x16;
+        let delta = FrameDelta::between(&previous, &current);
+        // The two changed pixels are in the first cell and the third cell of the thirteenth row of cells
+        let indices: Vec<u16> = delta.get_changed_cells().iter().map(|cell| cell.index).collect();
+        assert_eq!(vec![0, 12 * 32 + 2], indices);
+
+        let decoded = FrameDelta::from_bytes(&delta.to_bytes()).unwrap();
+        assert_eq!(delta, decoded);
+        let mut frame = previous;
+        decoded.apply(&mut frame);
+        assert!(frame[..] == current[..]);
+
+        let mut frame = [0x00; NES_SCREEN_DIMENSIONS];
+        FrameDelta::keyframe(&current).apply(&mut frame);
+        assert!(frame[..] == current[..]);
+        assert!(FrameDelta::between(&current, &frame).is_empty());
+
+        assert!(FrameDelta::from_bytes(&[0; 65]).is_err());
+        assert!(FrameDelta::from_bytes(&[0xff; 66]).is_err());
+    }
+}
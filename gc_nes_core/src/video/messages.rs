@@ -0,0 +1,292 @@
+//! The messages module contains the queue of short notifications, such as a state being saved or a controller being
+//! connected, that frontends post to the NES so that they look the same in every frontend. Frontends can draw the
+//! messages themselves, or have the NES draw them into the video backend at the end of each frame with its own 8x8 font.
+//! ```
+//! use gc_nes_core::cartridge::Cartridge;
+//! use gc_nes_core::nes::Nes;
+//! use std::time::Duration;
+//!
+//! # fn run(cartridge: Cartridge) {
+//! let mut nes = Nes::new(cartridge);
+//! nes.get_messages_mut().set_rendering(true);
+//! nes.post_message("State saved", Duration::from_secs(2));
+//! // The message is drawn in the bottom left corner of each frame for the next two seconds
+//! nes.frame();
+//! # }
+//! ```
+
+use crate::nes::NES_FRAME_RATE;
+use crate::video::{OutputTransform, VideoBackend, NES_SCREEN_WIDTH};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The most messages that are kept at once, the oldest are dropped when more are posted
+const MAX_MESSAGES: usize = 4;
+/// The width and height of each character in pixels
+const GLYPH_SIZE: usize = 8;
+/// The space in pixels between the text of a message and the edge of its background
+const MESSAGE_PADDING: usize = 1;
+/// The distance in pixels between the messages and the edges of the screen, which keeps them clear of the overscan
+const MESSAGE_MARGIN: usize = 8;
+/// The colour index that the text of messages is drawn with
+const TEXT_COLOUR: u8 = 0x30;
+/// The colour index that the background behind messages is drawn with
+const BACKGROUND_COLOUR: u8 = 0x0f;
+/// The printable ASCII characters from space to tilde, eight rows each with the leftmost pixel in the highest bit
+const FONT: [[u8; GLYPH_SIZE]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x10, 0x10, 0x10, 0x10, 0x00, 0x00, 0x10, 0x00], // !
+    [0x28, 0x28, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x28, 0x28, 0x7c, 0x28, 0x7c, 0x28, 0x28, 0x00], // #
+    [0x10, 0x3c, 0x50, 0x38, 0x14, 0x78, 0x10, 0x00], // $
+    [0x60, 0x64, 0x08, 0x10, 0x20, 0x4c, 0x0c, 0x00], // %
+    [0x30, 0x48, 0x50, 0x20, 0x54, 0x48, 0x34, 0x00], // &
+    [0x30, 0x10, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x08, 0x10, 0x20, 0x20, 0x20, 0x10, 0x08, 0x00], // (
+    [0x20, 0x10, 0x08, 0x08, 0x08, 0x10, 0x20, 0x00], // )
+    [0x00, 0x10, 0x54, 0x38, 0x54, 0x10, 0x00, 0x00], // *
+    [0x00, 0x10, 0x10, 0x7c, 0x10, 0x10, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x30, 0x10, 0x20, 0x00], // ,
+    [0x00, 0x00, 0x00, 0x7c, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x00], // .
+    [0x00, 0x04, 0x08, 0x10, 0x20, 0x40, 0x00, 0x00], // /
+    [0x38, 0x44, 0x4c, 0x54, 0x64, 0x44, 0x38, 0x00], // 0
+    [0x10, 0x30, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // 1
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x20, 0x7c, 0x00], // 2
+    [0x7c, 0x08, 0x10, 0x08, 0x04, 0x44, 0x38, 0x00], // 3
+    [0x08, 0x18, 0x28, 0x48, 0x7c, 0x08, 0x08, 0x00], // 4
+    [0x7c, 0x40, 0x78, 0x04, 0x04, 0x44, 0x38, 0x00], // 5
+    [0x18, 0x20, 0x40, 0x78, 0x44, 0x44, 0x38, 0x00], // 6
+    [0x7c, 0x04, 0x08, 0x10, 0x20, 0x20, 0x20, 0x00], // 7
+    [0x38, 0x44, 0x44, 0x38, 0x44, 0x44, 0x38, 0x00], // 8
+    [0x38, 0x44, 0x44, 0x3c, 0x04, 0x08, 0x30, 0x00], // 9
+    [0x00, 0x30, 0x30, 0x00, 0x30, 0x30, 0x00, 0x00], // :
+    [0x00, 0x30, 0x30, 0x00, 0x30, 0x10, 0x20, 0x00], // ;
+    [0x08, 0x10, 0x20, 0x40, 0x20, 0x10, 0x08, 0x00], // <
+    [0x00, 0x00, 0x7c, 0x00, 0x7c, 0x00, 0x00, 0x00], // =
+    [0x20, 0x10, 0x08, 0x04, 0x08, 0x10, 0x20, 0x00], // >
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // ?
+    [0x38, 0x44, 0x04, 0x34, 0x54, 0x54, 0x38, 0x00], // @
+    [0x38, 0x44, 0x44, 0x44, 0x7c, 0x44, 0x44, 0x00], // A
+    [0x78, 0x44, 0x44, 0x78, 0x44, 0x44, 0x78, 0x00], // B
+    [0x38, 0x44, 0x40, 0x40, 0x40, 0x44, 0x38, 0x00], // C
+    [0x70, 0x48, 0x44, 0x44, 0x44, 0x48, 0x70, 0x00], // D
+    [0x7c, 0x40, 0x40, 0x78, 0x40, 0x40, 0x7c, 0x00], // E
+    [0x7c, 0x40, 0x40, 0x78, 0x40, 0x40, 0x40, 0x00], // F
+    [0x38, 0x44, 0x40, 0x5c, 0x44, 0x44, 0x3c, 0x00], // G
+    [0x44, 0x44, 0x44, 0x7c, 0x44, 0x44, 0x44, 0x00], // H
+    [0x38, 0x10, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // I
+    [0x1c, 0x08, 0x08, 0x08, 0x08, 0x48, 0x30, 0x00], // J
+    [0x44, 0x48, 0x50, 0x60, 0x50, 0x48, 0x44, 0x00], // K
+    [0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x7c, 0x00], // L
+    [0x44, 0x6c, 0x54, 0x54, 0x44, 0x44, 0x44, 0x00], // M
+    [0x44, 0x44, 0x64, 0x54, 0x4c, 0x44, 0x44, 0x00], // N
+    [0x38, 0x44, 0x44, 0x44, 0x44, 0x44, 0x38, 0x00], // O
+    [0x78, 0x44, 0x44, 0x78, 0x40, 0x40, 0x40, 0x00], // P
+    [0x38, 0x44, 0x44, 0x44, 0x54, 0x48, 0x34, 0x00], // Q
+    [0x78, 0x44, 0x44, 0x78, 0x50, 0x48, 0x44, 0x00], // R
+    [0x3c, 0x40, 0x40, 0x38, 0x04, 0x04, 0x78, 0x00], // S
+    [0x7c, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00], // T
+    [0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x38, 0x00], // U
+    [0x44, 0x44, 0x44, 0x44, 0x44, 0x28, 0x10, 0x00], // V
+    [0x44, 0x44, 0x44, 0x54, 0x54, 0x54, 0x28, 0x00], // W
+    [0x44, 0x44, 0x28, 0x10, 0x28, 0x44, 0x44, 0x00], // X
+    [0x44, 0x44, 0x44, 0x28, 0x10, 0x10, 0x10, 0x00], // Y
+    [0x7c, 0x04, 0x08, 0x10, 0x20, 0x40, 0x7c, 0x00], // Z
+    [0x38, 0x20, 0x20, 0x20, 0x20, 0x20, 0x38, 0x00], // [
+    [0x00, 0x40, 0x20, 0x10, 0x08, 0x04, 0x00, 0x00], // \
+    [0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x38, 0x00], // ]
+    [0x10, 0x28, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x00], // _
+    [0x20, 0x10, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x38, 0x04, 0x3c, 0x44, 0x3c, 0x00], // a
+    [0x40, 0x40, 0x58, 0x64, 0x44, 0x44, 0x78, 0x00], // b
+    [0x00, 0x00, 0x38, 0x40, 0x40, 0x44, 0x38, 0x00], // c
+    [0x04, 0x04, 0x34, 0x4c, 0x44, 0x44, 0x3c, 0x00], // d
+    [0x00, 0x00, 0x38, 0x44, 0x7c, 0x40, 0x38, 0x00], // e
+    [0x18, 0x24, 0x20, 0x70, 0x20, 0x20, 0x20, 0x00], // f
+    [0x00, 0x3c, 0x44, 0x44, 0x3c, 0x04, 0x38, 0x00], // g
+    [0x40, 0x40, 0x58, 0x64, 0x44, 0x44, 0x44, 0x00], // h
+    [0x10, 0x00, 0x30, 0x10, 0x10, 0x10, 0x38, 0x00], // i
+    [0x08, 0x00, 0x18, 0x08, 0x08, 0x48, 0x30, 0x00], // j
+    [0x40, 0x40, 0x48, 0x50, 0x60, 0x50, 0x48, 0x00], // k
+    [0x30, 0x10, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // l
+    [0x00, 0x00, 0x68, 0x54, 0x54, 0x44, 0x44, 0x00], // m
+    [0x00, 0x00, 0x58, 0x64, 0x44, 0x44, 0x44, 0x00], // n
+    [0x00, 0x00, 0x38, 0x44, 0x44, 0x44, 0x38, 0x00], // o
+    [0x00, 0x00, 0x78, 0x44, 0x78, 0x40, 0x40, 0x00], // p
+    [0x00, 0x00, 0x34, 0x4c, 0x3c, 0x04, 0x04, 0x00], // q
+    [0x00, 0x00, 0x58, 0x64, 0x40, 0x40, 0x40, 0x00], // r
+    [0x00, 0x00, 0x38, 0x40, 0x38, 0x04, 0x78, 0x00], // s
+    [0x20, 0x20, 0x70, 0x20, 0x20, 0x24, 0x18, 0x00], // t
+    [0x00, 0x00, 0x44, 0x44, 0x44, 0x4c, 0x34, 0x00], // u
+    [0x00, 0x00, 0x44, 0x44, 0x44, 0x28, 0x10, 0x00], // v
+    [0x00, 0x00, 0x44, 0x44, 0x54, 0x54, 0x28, 0x00], // w
+    [0x00, 0x00, 0x44, 0x28, 0x10, 0x28, 0x44, 0x00], // x
+    [0x00, 0x00, 0x44, 0x44, 0x3c, 0x04, 0x38, 0x00], // y
+    [0x00, 0x00, 0x7c, 0x08, 0x10, 0x20, 0x7c, 0x00], // z
+    [0x08, 0x10, 0x10, 0x20, 0x10, 0x10, 0x08, 0x00], // {
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00], // |
+    [0x20, 0x10, 0x10, 0x08, 0x10, 0x10, 0x20, 0x00], // }
+    [0x00, 0x00, 0x00, 0x34, 0x48, 0x00, 0x00, 0x00], // ~
+];
+
+/// A message posted to the NES
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// The text of the message
+    pub text: String,
+    /// The number of frames left until the message expires
+    pub remaining_frames: u32,
+}
+
+/// The messages that have been posted to the NES and haven't expired yet, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct MessageQueue {
+    messages: VecDeque<Message>,
+    /// Whether the messages are drawn into the video backend at the end of each frame
+    rendering: bool,
+}
+
+impl MessageQueue {
+    /// Posts a message that lasts for a duration of emulated time, so it doesn't expire while the NES is paused.
+    /// Only the four newest messages are kept.
+    pub fn post(&mut self, text: &str, duration: Duration) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(Message {
+            text: text.to_string(),
+            remaining_frames: ((duration.as_secs_f64() * NES_FRAME_RATE).round() as u32).max(1),
+        });
+    }
+
+    /// Gets the messages that haven't expired yet, oldest first, for frontends that draw them themselves
+    pub fn get_messages(&self) -> impl Iterator<Item = &Message> {
+        return self.messages.iter();
+    }
+
+    /// Removes every message
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+
+    /// Sets whether the NES draws the messages into the video backend at the end of each frame, in the bottom left
+    /// corner of the screen with the newest at the bottom. Disabled by default. Backends that pass scanlines on as
+    /// they are drawn have already passed the frame on by the time the messages are drawn, so don't show them.
+    pub fn set_rendering(&mut self, enabled: bool) {
+        self.rendering = enabled;
+    }
+
+    /// Checks if the NES draws the messages into the video backend
+    pub fn is_rendering(&self) -> bool {
+        return self.rendering;
+    }
+
+    /// Draws the messages into the frame that was just completed if rendering is enabled, then counts the frame
+    /// towards each message's expiry
+    pub(crate) fn end_frame<V: VideoBackend>(&mut self, video: &mut V, output_transform: &OutputTransform) {
+        if self.rendering {
+            self.draw(video, output_transform);
+        }
+        self.messages.iter_mut().for_each(|message| message.remaining_frames -= 1);
+        self.messages.retain(|message| message.remaining_frames > 0);
+    }
+
+    /// Draws the messages upright on the transformed screen, one per line, with text that doesn't fit cut off
+    fn draw<V: VideoBackend>(&self, video: &mut V, output_transform: &OutputTransform) {
+        let (width, height) = output_transform.get_output_dimensions();
+        let line_height = GLYPH_SIZE + 2 * MESSAGE_PADDING;
+        let max_characters = (width - 2 * MESSAGE_MARGIN - 2 * MESSAGE_PADDING) / GLYPH_SIZE;
+        let first_line = height - MESSAGE_MARGIN - self.messages.len() * line_height;
+        // The coordinates are positions on the transformed screen, stored row by row in a buffer the width of the NES' screen
+        let mut draw_pixel = |x: usize, y: usize, colour_index: u8| {
+            let index = x + y * width;
+            video.draw_pixel(index % NES_SCREEN_WIDTH, index / NES_SCREEN_WIDTH, colour_index);
+        };
+        for (message, line) in self.messages.iter().zip(0..) {
+            let top = first_line + line * line_height;
+            let characters: Vec<u8> = message.text.chars().take(max_characters).map(glyph_index).collect();
+            let box_width = characters.len() * GLYPH_SIZE + 2 * MESSAGE_PADDING;
+            for y in 0..line_height {
+                for x in 0..box_width {
+                    let (glyph_x, glyph_y) = (x.wrapping_sub(MESSAGE_PADDING), y.wrapping_sub(MESSAGE_PADDING));
+                    let lit = glyph_y < GLYPH_SIZE
+                        && characters
+                            .get(glyph_x / GLYPH_SIZE)
+                            .is_some_and(|glyph| FONT[usize::from(*glyph)][glyph_y] & (0x80 >> (glyph_x % GLYPH_SIZE)) != 0);
+                    draw_pixel(MESSAGE_MARGIN + x, top + y, if lit { TEXT_COLOUR } else { BACKGROUND_COLOUR });
+                }
+            }
+        }
+    }
+}
+
+/// Gets the index in the font of a character's glyph, which is the question mark for characters it doesn't have
+fn glyph_index(character: char) -> u8 {
+    return match character {
+        ' '..='~' => character as u8 - b' ',
+        _ => b'?' - b' ',
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::video::{IndexedFrameBuffer, Rotation};
+
+    #[test]
+    fn test_message_expiry() {
+        let mut queue = MessageQueue::default();
+        let mut video = IndexedFrameBuffer::default();
+        queue.post("Short", Duration::from_millis(20));
+        queue.post("Long", Duration::from_secs(1));
+        assert_eq!(1, queue.get_messages().next().unwrap().remaining_frames);
+        queue.end_frame(&mut video, &Default::default());
+        assert_eq!(
+            vec!["Long"],
+            queue.get_messages().map(|message| message.text.as_str()).collect::<Vec<_>>()
+        );
+        (0..MAX_MESSAGES).for_each(|_| queue.post("New", Duration::from_secs(1)));
+        assert!(queue.get_messages().all(|message| message.text == "New"));
+    }
+
+    #[test]
+    fn test_message_rendering() {
+        let mut queue = MessageQueue::default();
+        let mut video = IndexedFrameBuffer::default();
+        queue.post("T\u{e9}", Duration::from_secs(1));
+        queue.end_frame(&mut video, &Default::default());
+        assert!(video.get_frame().iter().all(|colour_index| *colour_index == 0x00));
+
+        queue.set_rendering(true);
+        queue.end_frame(&mut video, &Default::default());
+        let pixel = |x: usize, y: usize| video.get_frame()[x + y * NES_SCREEN_WIDTH];
+        // The top of the T starts after the background's padding, and the unknown character is drawn as a question mark
+        let (left, top) = (MESSAGE_MARGIN + MESSAGE_PADDING, 240 - MESSAGE_MARGIN - GLYPH_SIZE - MESSAGE_PADDING);
+        assert_eq!(BACKGROUND_COLOUR, pixel(left - 1, top));
+        assert_eq!(
+            vec![
+                BACKGROUND_COLOUR,
+                TEXT_COLOUR,
+                TEXT_COLOUR,
+                TEXT_COLOUR,
+                TEXT_COLOUR,
+                TEXT_COLOUR,
+                BACKGROUND_COLOUR
+            ],
+            (left..left + 7).map(|x| pixel(x, top)).collect::<Vec<_>>()
+        );
+        assert_eq!(TEXT_COLOUR, pixel(left + GLYPH_SIZE + 3, top + 6));
+
+        // Rotated screens have the messages drawn upright in their bottom left corner
+        let mut video = IndexedFrameBuffer::default();
+        let output_transform = OutputTransform {
+            rotation: Rotation::Clockwise90,
+            ..Default::default()
+        };
+        queue.end_frame(&mut video, &output_transform);
+        let index = left + 1 + (256 - MESSAGE_MARGIN - GLYPH_SIZE - MESSAGE_PADDING) * 240;
+        assert_eq!(TEXT_COLOUR, video.get_frame()[index]);
+    }
+}
@@ -0,0 +1,703 @@
+//! The video module contains the backends that the PPU draws its output to.
+//! Each pixel that the PPU draws is passed to a [VideoBackend](trait.VideoBackend.html)
+//! as a NES colour index, and it is up to the backend to decide what to do with it.
+//! This allows frontends to pick an output format without paying for buffers they don't use.
+
+use crate::nes::NES_SCREEN_DIMENSIONS;
+#[cfg(feature = "png-export")]
+use std::error::Error;
+
+pub mod anaglyph;
+pub mod delta;
+pub mod messages;
+#[cfg(feature = "filters")]
+pub mod postprocess;
+#[cfg(feature = "recorder")]
+pub mod recorder;
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+mod simd;
+
+/// The width of the NES screen in pixels
+pub const NES_SCREEN_WIDTH: usize = 256;
+/// The height of the NES screen in pixels
+pub const NES_SCREEN_HEIGHT: usize = 240;
+
+/// Describes the frames that a NES produces and how they should be shown, so that frontends can scale and pace them
+/// without assuming a region. Returned by [Nes::video_info](../nes/struct.Nes.html#method.video_info).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VideoInfo {
+    /// The width of the frames in pixels, after the output transform
+    pub width: usize,
+    /// The height of the frames in pixels, after the output transform
+    pub height: usize,
+    /// The number of scanlines of each frame that show the picture, which excludes the overscan when it is hidden
+    pub active_lines: usize,
+    /// The ratio of the width of each pixel to its height when the frames are shown as a television would, after the
+    /// output transform
+    pub pixel_aspect_ratio: f64,
+    /// The rate that frames are produced at, in Hz
+    pub refresh_rate: f64,
+}
+
+impl VideoInfo {
+    /// Gets the ratio of the width of the frames to their height when their pixels are given their aspect ratio
+    pub fn display_aspect_ratio(&self) -> f64 {
+        return self.width as f64 * self.pixel_aspect_ratio / self.height as f64;
+    }
+}
+
+/// Trait for types that receive the pixels drawn by the PPU.
+pub trait VideoBackend {
+    /// The type of the frame that the backend produces
+    type Frame: ?Sized;
+
+    /// Called by the PPU for every visible pixel that it draws, with the x and y coordinates of
+    /// the pixel and the NES colour index (0x00 to 0x3f) that was selected for it.
+    fn draw_pixel(&mut self, x: usize, y: usize, colour_index: u8);
+
+    /// Called by the PPU after it draws the last pixel of each visible scanline, with the index of the scanline (0 to 239)
+    fn end_of_scanline(&mut self, _scanline: usize) {}
+
+    /// Called by the PPU before it draws a pixel whenever the colour emphasis bits of $2001 have changed, with the red,
+    /// green and blue emphasis bits in bits 0, 1 and 2. Emphasis darkens the colours that aren't emphasised,
+    /// which backends that only store colour indices can't show.
+    fn set_emphasis(&mut self, _emphasis: u8) {}
+
+    /// Gets the current state of the frame
+    fn get_frame(&self) -> &Self::Frame;
+
+    /// Gets a copy of the current state of the frame with each pixel represented by four bytes in RGBA order,
+    /// or None if the backend doesn't keep the colour information needed to produce one.
+    fn get_rgba_frame(&self) -> Option<Vec<u8>> {
+        return None;
+    }
+
+    /// Gets the current state of the frame as NES colour indices, stored row by row, or None if the backend
+    /// doesn't keep them.
+    fn get_indexed_frame(&self) -> Option<&[u8]> {
+        return None;
+    }
+}
+
+/// The clockwise rotation applied to the screen by an [OutputTransform](struct.OutputTransform.html)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+/// A rotation and mirroring applied to the screen as it is drawn, for displays that are mounted sideways or upside down.
+/// The flips are applied to the screen as the NES sees it, before it is rotated.
+///
+/// Rotating by 90 or 270 degrees produces a frame that is 240 pixels wide and 256 pixels tall. It fills the same number of
+/// pixels, stored row by row like an unrotated frame, so the coordinates passed to [draw_pixel](trait.VideoBackend.html#tymethod.draw_pixel)
+/// are those of the pixel's position in a 256 pixel wide buffer rather than its position on the rotated screen.
+/// Backends that store pixels row by row, like all of the backends in this module, need no changes to support it.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct OutputTransform {
+    /// The clockwise rotation of the screen
+    pub rotation: Rotation,
+    /// Whether the screen is mirrored left to right
+    pub flip_horizontal: bool,
+    /// Whether the screen is mirrored top to bottom
+    pub flip_vertical: bool,
+}
+
+impl OutputTransform {
+    /// Gets the width and height of the frames produced with the transform in pixels
+    pub fn get_output_dimensions(&self) -> (usize, usize) {
+        return match self.rotation {
+            Rotation::None | Rotation::Clockwise180 => (NES_SCREEN_WIDTH, NES_SCREEN_HEIGHT),
+            Rotation::Clockwise90 | Rotation::Clockwise270 => (NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH),
+        };
+    }
+
+    /// Moves a pixel on the NES' screen to where it belongs in the transformed frame, returning the
+    /// coordinates of that position in a buffer that is the width of the NES' screen.
+    pub(crate) fn apply(&self, x: usize, y: usize) -> (usize, usize) {
+        let x = if self.flip_horizontal { NES_SCREEN_WIDTH - 1 - x } else { x };
+        let y = if self.flip_vertical { NES_SCREEN_HEIGHT - 1 - y } else { y };
+        let (output_x, output_y) = match self.rotation {
+            Rotation::None => return (x, y),
+            Rotation::Clockwise90 => (NES_SCREEN_HEIGHT - 1 - y, x),
+            Rotation::Clockwise180 => (NES_SCREEN_WIDTH - 1 - x, NES_SCREEN_HEIGHT - 1 - y),
+            Rotation::Clockwise270 => (y, NES_SCREEN_WIDTH - 1 - x),
+        };
+        let index = output_x + output_y * self.get_output_dimensions().0;
+        return (index % NES_SCREEN_WIDTH, index / NES_SCREEN_WIDTH);
+    }
+}
+
+/// The default video backend, which stores the screen as an array of 32 bit ARGB colour values
+#[derive(Clone)]
+pub struct ArgbFrameBuffer {
+    buffer: Box<[u32; NES_SCREEN_DIMENSIONS]>,
+    /// The colours drawn in the previous frame, before blending, or None if frame blending is disabled
+    previous_frame: Option<Box<[u32; NES_SCREEN_DIMENSIONS]>>,
+    /// The colour emphasis bits that pixels are currently drawn with
+    emphasis: u8,
+}
+
+impl Default for ArgbFrameBuffer {
+    fn default() -> Self {
+        ArgbFrameBuffer {
+            buffer: Box::new([0; NES_SCREEN_DIMENSIONS]),
+            previous_frame: None,
+            emphasis: 0,
+        }
+    }
+}
+
+impl ArgbFrameBuffer {
+    /// Enables or disables frame blending, which is off by default. With blending enabled, each pixel is drawn as an
+    /// even mix of its colour in this frame and the last, like the persistence of a CRT's phosphors. This smooths
+    /// out the flicker of games that show sprites on alternate frames to make them look transparent, such as the
+    /// shadows in Micro Machines.
+    pub fn set_frame_blending(&mut self, enabled: bool) {
+        self.previous_frame = if enabled { Some(self.buffer.clone()) } else { None };
+    }
+
+    /// Checks if frame blending is enabled
+    pub fn get_frame_blending(&self) -> bool {
+        return self.previous_frame.is_some();
+    }
+}
+
+impl VideoBackend for ArgbFrameBuffer {
+    type Frame = [u32; NES_SCREEN_DIMENSIONS];
+
+    fn draw_pixel(&mut self, x: usize, y: usize, colour_index: u8) {
+        let index = x + y * NES_SCREEN_WIDTH;
+        let colour = emphasise_argb(NES_COLOUR_MAP[colour_index as usize], self.emphasis);
+        self.buffer[index] = match &mut self.previous_frame {
+            Some(previous_frame) => blend_argb(std::mem::replace(&mut previous_frame[index], colour), colour),
+            None => colour,
+        };
+    }
+
+    fn set_emphasis(&mut self, emphasis: u8) {
+        self.emphasis = emphasis;
+    }
+
+    fn get_frame(&self) -> &Self::Frame {
+        return &self.buffer;
+    }
+
+    fn get_rgba_frame(&self) -> Option<Vec<u8>> {
+        return Some(self.buffer.iter().flat_map(|colour| argb_to_rgba(*colour).to_vec()).collect());
+    }
+}
+
+/// Video backend that stores the screen as an array of bytes, where each pixel is represented by a set of
+/// four bytes in RGBA order for web rendering.
+#[derive(Clone)]
+pub struct RgbaFrameBuffer {
+    buffer: Box<[u8; NES_SCREEN_DIMENSIONS * 4]>,
+    emphasis: u8,
+}
+
+impl Default for RgbaFrameBuffer {
+    fn default() -> Self {
+        RgbaFrameBuffer {
+            buffer: Box::new([0; NES_SCREEN_DIMENSIONS * 4]),
+            emphasis: 0,
+        }
+    }
+}
+
+impl VideoBackend for RgbaFrameBuffer {
+    type Frame = [u8; NES_SCREEN_DIMENSIONS * 4];
+
+    fn draw_pixel(&mut self, x: usize, y: usize, colour_index: u8) {
+        let buffer_index = 4 * (x + y * NES_SCREEN_WIDTH);
+        let pixel = &mut self.buffer[buffer_index..buffer_index + 4];
+        pixel.copy_from_slice(&NES_COLOUR_MAP_WEB[colour_index as usize]);
+        emphasise_rgba(pixel, self.emphasis);
+    }
+
+    fn set_emphasis(&mut self, emphasis: u8) {
+        self.emphasis = emphasis;
+    }
+
+    fn get_frame(&self) -> &Self::Frame {
+        return &self.buffer;
+    }
+
+    fn get_rgba_frame(&self) -> Option<Vec<u8>> {
+        return Some(self.buffer.to_vec());
+    }
+}
+
+/// Video backend that stores the screen as an array of NES colour indices, leaving the conversion
+/// to real colours up to the frontend (for custom palettes or filters, for example).
+#[derive(Clone)]
+pub struct IndexedFrameBuffer {
+    buffer: Box<[u8; NES_SCREEN_DIMENSIONS]>,
+    emphasis_buffer: Box<[u8; NES_SCREEN_DIMENSIONS]>,
+    emphasis: u8,
+}
+
+impl Default for IndexedFrameBuffer {
+    fn default() -> Self {
+        IndexedFrameBuffer {
+            buffer: Box::new([0; NES_SCREEN_DIMENSIONS]),
+            emphasis_buffer: Box::new([0; NES_SCREEN_DIMENSIONS]),
+            emphasis: 0,
+        }
+    }
+}
+
+impl IndexedFrameBuffer {
+    /// Gets the colour emphasis bits that each pixel of the frame was drawn with, in the same order as the colour
+    /// indices, for frontends that convert the indices to colours themselves
+    pub fn get_emphasis_frame(&self) -> &[u8; NES_SCREEN_DIMENSIONS] {
+        return &self.emphasis_buffer;
+    }
+}
+
+impl VideoBackend for IndexedFrameBuffer {
+    type Frame = [u8; NES_SCREEN_DIMENSIONS];
+
+    fn draw_pixel(&mut self, x: usize, y: usize, colour_index: u8) {
+        self.buffer[x + y * NES_SCREEN_WIDTH] = colour_index;
+        self.emphasis_buffer[x + y * NES_SCREEN_WIDTH] = self.emphasis;
+    }
+
+    fn set_emphasis(&mut self, emphasis: u8) {
+        self.emphasis = emphasis;
+    }
+
+    fn get_frame(&self) -> &Self::Frame {
+        return &self.buffer;
+    }
+
+    fn get_rgba_frame(&self) -> Option<Vec<u8>> {
+        let mut rgba_frame = vec![0; NES_SCREEN_DIMENSIONS * 4];
+        indexed_to_rgba(self.buffer.as_ref(), &mut rgba_frame);
+        emphasise_rgba_frame(self.emphasis_buffer.as_ref(), &mut rgba_frame);
+        return Some(rgba_frame);
+    }
+
+    fn get_indexed_frame(&self) -> Option<&[u8]> {
+        return Some(self.buffer.as_ref());
+    }
+}
+
+/// Video backend that discards all of the pixels drawn by the PPU, for headless use where
+/// the screen is never looked at.
+#[derive(Default, Clone)]
+pub struct NullVideoBackend;
+
+impl VideoBackend for NullVideoBackend {
+    type Frame = ();
+
+    fn draw_pixel(&mut self, _x: usize, _y: usize, _colour_index: u8) {}
+
+    fn get_frame(&self) -> &Self::Frame {
+        return &();
+    }
+}
+
+/// Video backend that wraps another backend, passing each scanline to a callback as soon as the PPU has finished
+/// drawing it, for frontends that present the screen as it is drawn (beam racing) or apply effects line by line.
+/// The callback receives the index of the scanline and its pixels as NES colour indices, from left to right as the PPU
+/// drew them, which [indexed_to_rgba](fn.indexed_to_rgba.html) can convert to RGBA.
+/// ```
+/// # use gc_nes_core::cartridge::Cartridge;
+/// use gc_nes_core::nes::Nes;
+/// use gc_nes_core::video::{ArgbFrameBuffer, ScanlineCallbackBackend};
+///
+/// # fn run(cartridge: Cartridge) {
+/// let backend = ScanlineCallbackBackend::new(ArgbFrameBuffer::default(), |scanline: usize, pixels: &[u8]| {
+///     println!("Scanline {} starts with colour 0x{:02X}", scanline, pixels[0]);
+/// });
+/// let mut nes = Nes::with_video_backend(cartridge, backend);
+/// nes.frame();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ScanlineCallbackBackend<V: VideoBackend, F: FnMut(usize, &[u8])> {
+    backend: V,
+    callback: F,
+    /// The colour indices of the pixels drawn so far on the current scanline
+    line: Box<[u8; NES_SCREEN_WIDTH]>,
+    /// The number of pixels drawn so far on the current scanline
+    line_length: usize,
+}
+
+impl<V: VideoBackend, F: FnMut(usize, &[u8])> ScanlineCallbackBackend<V, F> {
+    /// Creates a backend that draws to the wrapped backend and passes each finished scanline to the callback
+    pub fn new(backend: V, callback: F) -> Self {
+        ScanlineCallbackBackend {
+            backend,
+            callback,
+            line: Box::new([0; NES_SCREEN_WIDTH]),
+            line_length: 0,
+        }
+    }
+
+    /// Gets a reference to the wrapped backend
+    pub fn get_backend(&self) -> &V {
+        return &self.backend;
+    }
+
+    /// Gets a mutable reference to the wrapped backend
+    pub fn get_backend_mut(&mut self) -> &mut V {
+        return &mut self.backend;
+    }
+}
+
+impl<V: VideoBackend, F: FnMut(usize, &[u8])> VideoBackend for ScanlineCallbackBackend<V, F> {
+    type Frame = V::Frame;
+
+    fn draw_pixel(&mut self, x: usize, y: usize, colour_index: u8) {
+        self.backend.draw_pixel(x, y, colour_index);
+        // Pixels are collected in the order they are drawn rather than by their coordinates,
+        // which an output transform may have moved off of the scanline
+        if self.line_length < NES_SCREEN_WIDTH {
+            self.line[self.line_length] = colour_index;
+            self.line_length += 1;
+        }
+    }
+
+    fn set_emphasis(&mut self, emphasis: u8) {
+        self.backend.set_emphasis(emphasis);
+    }
+
+    fn end_of_scanline(&mut self, scanline: usize) {
+        self.backend.end_of_scanline(scanline);
+        (self.callback)(scanline, &self.line[..self.line_length]);
+        self.line_length = 0;
+    }
+
+    fn get_frame(&self) -> &Self::Frame {
+        return self.backend.get_frame();
+    }
+
+    fn get_rgba_frame(&self) -> Option<Vec<u8>> {
+        return self.backend.get_rgba_frame();
+    }
+
+    fn get_indexed_frame(&self) -> Option<&[u8]> {
+        return self.backend.get_indexed_frame();
+    }
+}
+
+/// Converts a frame of NES colour indices into four bytes per pixel in RGBA order, stopping when either slice runs out.
+/// Converting a whole frame at once is cheaper than converting each pixel as it is drawn, and with the `simd` feature
+/// enabled on a wasm32 target compiled with the `simd128` target feature, the conversion uses WASM SIMD instructions.
+pub fn indexed_to_rgba(indices: &[u8], rgba: &mut [u8]) {
+    #[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+    let converted = simd::indexed_to_rgba(indices, rgba);
+    #[cfg(not(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128")))]
+    let converted = 0;
+
+    for (pixel, colour_index) in rgba[converted * 4..].chunks_exact_mut(4).zip(&indices[converted..]) {
+        pixel.copy_from_slice(&NES_COLOUR_MAP_WEB[(colour_index & 0x3f) as usize]);
+    }
+}
+
+/// Applies the colour emphasis bits of each pixel to a frame in RGBA order, like the one produced by
+/// [indexed_to_rgba](fn.indexed_to_rgba.html), stopping when either slice runs out. The emphasis bits of an
+/// [IndexedFrameBuffer](struct.IndexedFrameBuffer.html) frame are available from its `get_emphasis_frame`.
+pub fn emphasise_rgba_frame(emphasis: &[u8], rgba: &mut [u8]) {
+    for (pixel, emphasis) in rgba.chunks_exact_mut(4).zip(emphasis) {
+        emphasise_rgba(pixel, *emphasis);
+    }
+}
+
+/// Applies colour emphasis to a pixel of four bytes in RGBA order, the same way as for ARGB colours
+fn emphasise_rgba(pixel: &mut [u8], emphasis: u8) {
+    if emphasis != 0 {
+        let colour = u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]]);
+        pixel[..3].copy_from_slice(&argb_to_rgba(emphasise_argb(colour, emphasis))[..3]);
+    }
+}
+
+/// Darkens the channels of a 32 bit ARGB colour value that aren't emphasised to three quarters of their brightness,
+/// given the red, green and blue emphasis bits in bits 0, 1 and 2. Colours are left alone without any emphasis.
+fn emphasise_argb(colour: u32, emphasis: u8) -> u32 {
+    if emphasis == 0 {
+        return colour;
+    }
+    let mut emphasised = colour & 0xff00_0000;
+    for (bit, shift) in [(0x01, 16), (0x02, 8), (0x04, 0)] {
+        let channel = (colour >> shift) & 0xff;
+        emphasised |= if emphasis & bit > 0 { channel } else { channel * 3 / 4 } << shift;
+    }
+    return emphasised;
+}
+
+/// Mixes two 32 bit ARGB colour values evenly, averaging each channel without letting it carry into the next
+fn blend_argb(first: u32, second: u32) -> u32 {
+    return (first & second) + (((first ^ second) & 0xfefe_fefe) >> 1);
+}
+
+/// Converts a 32 bit ARGB colour value into four bytes in RGBA order, with full opacity
+fn argb_to_rgba(colour: u32) -> [u8; 4] {
+    return [(colour >> 16) as u8, (colour >> 8) as u8, colour as u8, 0xff];
+}
+
+/// Encodes a frame with each pixel represented by four bytes in RGBA order as a PNG image
+#[cfg(feature = "png-export")]
+pub fn encode_png(rgba_frame: &[u8], width: u32, height: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut png_data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(rgba_frame)?;
+    }
+    return Ok(png_data);
+}
+
+/// Map of the NES colour indices to 32 bit ARGB colour values
+#[allow(clippy::unreadable_literal)] // Allow standard 6 character colour hex codes
+pub const NES_COLOUR_MAP: [u32; 0x40] = [
+    0x464646, 0x00065a, 0x000678, 0x020673, 0x35034c, 0x57000e, 0x5a0000, 0x410000, 0x120200, 0x001400, 0x001e00, 0x001e00, 0x001521, 0x000000,
+    0x000000, 0x000000, 0x9d9d9d, 0x004ab9, 0x0530e1, 0x5718da, 0x9f07a7, 0xcc0255, 0xcf0b00, 0xa42300, 0x5c3f00, 0x0b5800, 0x006600, 0x006713,
+    0x005e6e, 0x000000, 0x000000, 0x000000, 0xfeffff, 0x1f9eff, 0x5376ff, 0x9865ff, 0xfc67ff, 0xff6cb3, 0xff7466, 0xff8014, 0xc49a00, 0x71b300,
+    0x28c421, 0x00c874, 0x00bfd0, 0x2b2b2b, 0x000000, 0x000000, 0xfeffff, 0x9ed5ff, 0xafc0ff, 0xd0b8ff, 0xfebfff, 0xffc0e0, 0xffc3bd, 0xffca9c,
+    0xe7d58b, 0xc5df8e, 0xa6e6a3, 0x94e8c5, 0x92e4eb, 0xa7a7a7, 0x000000, 0x000000,
+];
+
+/// Map of the NES colour indices to colour values as four bytes in RGBA order
+pub const NES_COLOUR_MAP_WEB: [[u8; 0x04]; 0x40] = [
+    [0x46, 0x46, 0x46, 0xff],
+    [0x00, 0x06, 0x5a, 0xff],
+    [0x00, 0x06, 0x78, 0xff],
+    [0x02, 0x06, 0x73, 0xff],
+    [0x35, 0x03, 0x4c, 0xff],
+    [0x57, 0x00, 0x0e, 0xff],
+    [0x5a, 0x00, 0x00, 0xff],
+    [0x41, 0x00, 0x00, 0xff],
+    [0x12, 0x02, 0x00, 0xff],
+    [0x00, 0x14, 0x00, 0xff],
+    [0x00, 0x1e, 0x00, 0xff],
+    [0x00, 0x1e, 0x00, 0xff],
+    [0x00, 0x15, 0x21, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x9d, 0x9d, 0x9d, 0xff],
+    [0x00, 0x4a, 0xb9, 0xff],
+    [0x05, 0x30, 0xe1, 0xff],
+    [0x57, 0x18, 0xda, 0xff],
+    [0x9f, 0x07, 0xa7, 0xff],
+    [0xcc, 0x02, 0x55, 0xff],
+    [0xcf, 0x0b, 0x00, 0xff],
+    [0xa4, 0x23, 0x00, 0xff],
+    [0x5c, 0x3f, 0x00, 0xff],
+    [0x0b, 0x58, 0x00, 0xff],
+    [0x00, 0x66, 0x00, 0xff],
+    [0x00, 0x67, 0x13, 0xff],
+    [0x00, 0x5e, 0x6e, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0xfe, 0xff, 0xff, 0xff],
+    [0x1f, 0x9e, 0xff, 0xff],
+    [0x53, 0x76, 0xff, 0xff],
+    [0x98, 0x65, 0xff, 0xff],
+    [0xfc, 0x67, 0xff, 0xff],
+    [0xff, 0x6c, 0xb3, 0xff],
+    [0xff, 0x74, 0x66, 0xff],
+    [0xff, 0x80, 0x14, 0xff],
+    [0xc4, 0x9a, 0x00, 0xff],
+    [0x71, 0xb3, 0x00, 0xff],
+    [0x28, 0xc4, 0x21, 0xff],
+    [0x00, 0xc8, 0x74, 0xff],
+    [0x00, 0xbf, 0xd0, 0xff],
+    [0x2b, 0x2b, 0x2b, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0xfe, 0xff, 0xff, 0xff],
+    [0x9e, 0xd5, 0xff, 0xff],
+    [0xaf, 0xc0, 0xff, 0xff],
+    [0xd0, 0xb8, 0xff, 0xff],
+    [0xfe, 0xbf, 0xff, 0xff],
+    [0xff, 0xc0, 0xe0, 0xff],
+    [0xff, 0xc3, 0xbd, 0xff],
+    [0xff, 0xca, 0x9c, 0xff],
+    [0xe7, 0xd5, 0x8b, 0xff],
+    [0xc5, 0xdf, 0x8e, 0xff],
+    [0xa6, 0xe6, 0xa3, 0xff],
+    [0x94, 0xe8, 0xc5, 0xff],
+    [0x92, 0xe4, 0xeb, 0xff],
+    [0xa7, 0xa7, 0xa7, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_argb_frame_buffer_draw_pixel() {
+        let mut backend = ArgbFrameBuffer::default();
+        backend.draw_pixel(3, 2, 0x21);
+        assert_eq!(NES_COLOUR_MAP[0x21], backend.get_frame()[3 + 2 * NES_SCREEN_WIDTH]);
+    }
+
+    #[test]
+    fn test_argb_frame_buffer_blending() {
+        let mut backend = ArgbFrameBuffer::default();
+        backend.set_frame_blending(true);
+        backend.draw_pixel(3, 2, 0x30);
+        assert_eq!(blend_argb(0, NES_COLOUR_MAP[0x30]), backend.get_frame()[3 + 2 * NES_SCREEN_WIDTH]);
+        // The next frame is blended with the colour drawn in the last, rather than the blended colour
+        backend.draw_pixel(3, 2, 0x0f);
+        assert_eq!(
+            blend_argb(NES_COLOUR_MAP[0x30], NES_COLOUR_MAP[0x0f]),
+            backend.get_frame()[3 + 2 * NES_SCREEN_WIDTH]
+        );
+        assert_eq!(0x0080_8000, blend_argb(0x00ff_ff00, 0x0001_0100));
+        backend.set_frame_blending(false);
+        backend.draw_pixel(3, 2, 0x30);
+        assert_eq!(NES_COLOUR_MAP[0x30], backend.get_frame()[3 + 2 * NES_SCREEN_WIDTH]);
+    }
+
+    #[test]
+    fn test_argb_frame_buffer_emphasis() {
+        let mut backend = ArgbFrameBuffer::default();
+        backend.set_emphasis(0x01);
+        backend.draw_pixel(0, 0, 0x30);
+        // The white at 0x30 keeps its red channel, but its green and blue channels are darkened
+        assert_eq!(0xfebfbf, backend.get_frame()[0]);
+        assert_eq!(NES_COLOUR_MAP[0x30], emphasise_argb(NES_COLOUR_MAP[0x30], 0x00));
+    }
+
+    #[test]
+    fn test_rgba_frame_buffer_draw_pixel() {
+        let mut backend = RgbaFrameBuffer::default();
+        backend.draw_pixel(3, 2, 0x21);
+        let index = 4 * (3 + 2 * NES_SCREEN_WIDTH);
+        assert_eq!(NES_COLOUR_MAP_WEB[0x21], backend.get_frame()[index..index + 4]);
+    }
+
+    #[test]
+    fn test_rgba_frame_buffer_emphasis() {
+        let mut backend = RgbaFrameBuffer::default();
+        backend.set_emphasis(0x01);
+        backend.draw_pixel(0, 0, 0x30);
+        assert_eq!([0xfe, 0xbf, 0xbf, 0xff], backend.get_frame()[0..4]);
+        backend.set_emphasis(0x00);
+        backend.draw_pixel(0, 0, 0x30);
+        assert_eq!(NES_COLOUR_MAP_WEB[0x30], backend.get_frame()[0..4]);
+    }
+
+    #[test]
+    fn test_indexed_frame_buffer_emphasis() {
+        let mut backend = IndexedFrameBuffer::default();
+        backend.set_emphasis(0x06);
+        backend.draw_pixel(0, 0, 0x30);
+        backend.set_emphasis(0x00);
+        backend.draw_pixel(1, 0, 0x30);
+        // The colour index is left alone, with the emphasis applied when converting the frame to RGBA
+        assert_eq!(0x30, backend.get_frame()[0]);
+        assert_eq!([0x06, 0x00], backend.get_emphasis_frame()[0..2]);
+        let rgba_frame = backend.get_rgba_frame().unwrap();
+        assert_eq!([0xbe, 0xff, 0xff, 0xff], rgba_frame[0..4]);
+        assert_eq!(NES_COLOUR_MAP_WEB[0x30], rgba_frame[4..8]);
+    }
+
+    #[test]
+    fn test_argb_frame_buffer_get_rgba_frame() {
+        let mut backend = ArgbFrameBuffer::default();
+        backend.draw_pixel(1, 0, 0x21);
+        assert_eq!(NES_COLOUR_MAP_WEB[0x21], backend.get_rgba_frame().unwrap()[4..8]);
+    }
+
+    #[cfg(feature = "png-export")]
+    #[test]
+    fn test_encode_png() {
+        let png_data = encode_png(&[0xff; 4 * 4], 2, 2).unwrap();
+        assert_eq!([0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a], png_data[0..8]);
+    }
+
+    #[test]
+    fn test_indexed_to_rgba() {
+        let indices: Vec<u8> = (0..=0x50).collect();
+        let mut rgba = vec![0; indices.len() * 4];
+        indexed_to_rgba(&indices, &mut rgba);
+        for (index, pixel) in rgba.chunks_exact(4).enumerate() {
+            assert_eq!(NES_COLOUR_MAP_WEB[index & 0x3f], pixel, "Colour index 0x{:02X}", index);
+        }
+    }
+
+    /// Gets the index in the frame buffer that a pixel is drawn to with a transform
+    fn transformed_index(transform: OutputTransform, x: usize, y: usize) -> usize {
+        let (buffer_x, buffer_y) = transform.apply(x, y);
+        return buffer_x + buffer_y * NES_SCREEN_WIDTH;
+    }
+
+    #[test]
+    fn test_output_transform_rotation() {
+        let rotated = |rotation| OutputTransform {
+            rotation,
+            ..Default::default()
+        };
+        // The top right corner of the screen
+        assert_eq!(255, transformed_index(rotated(Rotation::None), 255, 0));
+        assert_eq!(255 * 240 + 239, transformed_index(rotated(Rotation::Clockwise90), 255, 0));
+        assert_eq!(239 * 256, transformed_index(rotated(Rotation::Clockwise180), 255, 0));
+        assert_eq!(0, transformed_index(rotated(Rotation::Clockwise270), 255, 0));
+    }
+
+    #[test]
+    fn test_output_transform_flips_before_rotating() {
+        let transform = OutputTransform {
+            rotation: Rotation::Clockwise90,
+            flip_horizontal: true,
+            flip_vertical: false,
+        };
+        // Flipping moves the top left corner to the top right, which rotating moves to the bottom right
+        assert_eq!(NES_SCREEN_DIMENSIONS - 1, transformed_index(transform, 0, 0));
+        assert_eq!((240, 256), transform.get_output_dimensions());
+    }
+
+    #[test]
+    fn test_output_transform_covers_every_pixel() {
+        let transform = OutputTransform {
+            rotation: Rotation::Clockwise270,
+            flip_horizontal: false,
+            flip_vertical: true,
+        };
+        let mut covered = vec![false; NES_SCREEN_DIMENSIONS];
+        for y in 0..NES_SCREEN_HEIGHT {
+            for x in 0..NES_SCREEN_WIDTH {
+                covered[transformed_index(transform, x, y)] = true;
+            }
+        }
+        assert!(covered.iter().all(|pixel| *pixel));
+    }
+
+    #[test]
+    fn test_scanline_callback_backend() {
+        let mut lines = Vec::new();
+        {
+            let mut backend = ScanlineCallbackBackend::new(IndexedFrameBuffer::default(), |scanline: usize, pixels: &[u8]| {
+                lines.push((scanline, pixels.to_vec()));
+            });
+            (0..NES_SCREEN_WIDTH).for_each(|x| backend.draw_pixel(x, 7, x as u8 & 0x3f));
+            backend.end_of_scanline(7);
+            assert_eq!(0x3f, backend.get_frame()[63 + 7 * NES_SCREEN_WIDTH]);
+        }
+        assert_eq!(1, lines.len());
+        assert_eq!(7, lines[0].0);
+        assert_eq!(NES_SCREEN_WIDTH, lines[0].1.len());
+        assert_eq!(0x3f, lines[0].1[63]);
+    }
+
+    #[test]
+    fn test_indexed_frame_buffer_draw_pixel() {
+        let mut backend = IndexedFrameBuffer::default();
+        backend.draw_pixel(255, 239, 0x21);
+        assert_eq!(0x21, backend.get_frame()[NES_SCREEN_DIMENSIONS - 1]);
+    }
+}
@@ -0,0 +1,342 @@
+//! The postprocess module contains the chain of filters that frames are passed through before they are shown,
+//! so that a filter written once works the same in every frontend.
+//!
+//! The chain starts by converting the NES colour indices of a frame into RGBA through its palette, which can be
+//! replaced with a custom one, and then passes the result through each [PostProcessor](trait.PostProcessor.html)
+//! in turn. The NES keeps the colour indices and colour emphasis of each frame for the chain itself, so it works with
+//! any video backend. Each processor receives the original colour indices along with the output of the processor before it,
+//! so filters that work from the NES' signal, like NTSC filters, can sit anywhere in the chain, and each one can
+//! change the size of the image, like scalers do.
+//! ```
+//! use gc_nes_core::cartridge::Cartridge;
+//! use gc_nes_core::nes::Nes;
+//! use gc_nes_core::video::postprocess::NearestScaler;
+//!
+//! # fn run(cartridge: Cartridge) {
+//! let mut nes = Nes::new(cartridge);
+//! nes.get_post_processing_mut().push(Box::new(NearestScaler::new(3)));
+//! nes.frame();
+//! let image = nes.post_process_frame();
+//! assert_eq!((768, 720), (image.width, image.height));
+//! # }
+//! ```
+
+use crate::video::{emphasise_rgba_frame, indexed_to_rgba, IndexedFrameBuffer, VideoBackend, NES_COLOUR_MAP_WEB};
+use std::error::Error;
+
+/// A palette mapping each of the 64 NES colour indices to four bytes in RGBA order
+pub type Palette = [[u8; 4]; 0x40];
+
+/// An image with each pixel represented by four bytes in RGBA order, stored row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    /// The width of the image in pixels
+    pub width: usize,
+    /// The height of the image in pixels
+    pub height: usize,
+    /// The pixels of the image, four bytes each
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Creates an image of the given size with every pixel transparent black
+    pub fn new(width: usize, height: usize) -> Self {
+        return RgbaImage {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        };
+    }
+}
+
+/// The frame that a post-processing chain is run on, as the PPU drew it
+#[derive(Debug, Copy, Clone)]
+pub struct IndexedFrame<'a> {
+    /// The NES colour index (0x00 to 0x3f) of each pixel, stored row by row
+    pub indices: &'a [u8],
+    /// The colour emphasis bits that each pixel was drawn with, with red, green and blue in bits 0, 1 and 2
+    pub emphasis: &'a [u8],
+    /// The width of the frame in pixels
+    pub width: usize,
+    /// The height of the frame in pixels
+    pub height: usize,
+    /// The palette of the chain that the frame is being processed by
+    pub palette: &'a Palette,
+}
+
+/// A single filter in a [PostProcessChain](struct.PostProcessChain.html)
+pub trait PostProcessor: PostProcessorClone {
+    /// Processes the output of the previous processor in the chain, or the frame converted through the chain's
+    /// palette with its colour emphasis applied for the first processor, into an image of any size
+    fn process(&mut self, frame: &IndexedFrame, input: RgbaImage) -> RgbaImage;
+}
+
+/// Trait that allows boxed post-processors to be cloned, implemented for every post-processor that implements Clone
+pub trait PostProcessorClone {
+    /// Clones the post-processor into a new box
+    fn box_clone(&self) -> Box<dyn PostProcessor>;
+}
+
+impl<T: 'static + PostProcessor + Clone> PostProcessorClone for T {
+    fn box_clone(&self) -> Box<dyn PostProcessor> {
+        return Box::new(self.clone());
+    }
+}
+
+impl Clone for Box<dyn PostProcessor> {
+    fn clone(&self) -> Self {
+        return self.box_clone();
+    }
+}
+
+/// A palette followed by any number of [PostProcessors](trait.PostProcessor.html) that frames are passed through in order
+#[derive(Clone)]
+pub struct PostProcessChain {
+    /// The palette that colour indices are converted to RGBA through
+    palette: Box<Palette>,
+    /// The processors that the converted frame is passed through, in order
+    processors: Vec<Box<dyn PostProcessor>>,
+}
+
+impl Default for PostProcessChain {
+    fn default() -> Self {
+        PostProcessChain {
+            palette: Box::new(NES_COLOUR_MAP_WEB),
+            processors: Vec::new(),
+        }
+    }
+}
+
+impl PostProcessChain {
+    /// Replaces the palette that colour indices are converted through, which is the emulator's own palette by default
+    pub fn set_palette(&mut self, palette: Palette) {
+        *self.palette = palette;
+    }
+
+    /// Gets the palette that colour indices are converted through
+    pub fn get_palette(&self) -> &Palette {
+        return &self.palette;
+    }
+
+    /// Adds a processor to the end of the chain
+    pub fn push(&mut self, processor: Box<dyn PostProcessor>) {
+        self.processors.push(processor);
+    }
+
+    /// Removes every processor from the chain, leaving only the palette
+    pub fn clear(&mut self) {
+        self.processors.clear();
+    }
+
+    /// Gets the number of processors in the chain
+    pub fn len(&self) -> usize {
+        return self.processors.len();
+    }
+
+    /// Checks if the chain has no processors, meaning it only converts frames through its palette
+    pub fn is_empty(&self) -> bool {
+        return self.processors.is_empty();
+    }
+
+    /// Passes a frame of NES colour indices and the colour emphasis of each pixel, both stored row by row, through
+    /// the palette and each processor in turn
+    pub fn process(&mut self, indices: &[u8], emphasis: &[u8], width: usize, height: usize) -> RgbaImage {
+        let mut image = RgbaImage::new(width, height);
+        if *self.palette == NES_COLOUR_MAP_WEB {
+            // The emulator's own palette can take the faster conversion, which uses SIMD where it is available
+            indexed_to_rgba(indices, &mut image.pixels);
+        } else {
+            for (pixel, colour_index) in image.pixels.chunks_exact_mut(4).zip(indices) {
+                pixel.copy_from_slice(&self.palette[usize::from(colour_index & 0x3f)]);
+            }
+        }
+        emphasise_rgba_frame(emphasis, &mut image.pixels);
+        let frame = IndexedFrame {
+            indices,
+            emphasis,
+            width,
+            height,
+            palette: &self.palette,
+        };
+        for processor in &mut self.processors {
+            image = processor.process(&frame, image);
+        }
+        return image;
+    }
+}
+
+/// Reads a palette from the 192 byte .pal files that most NES emulators and palette generators use, with three bytes
+/// in RGB order for each of the 64 colour indices. Files with the extra colours for every combination of colour
+/// emphasis are also accepted, only the first 64 colours are used.
+pub fn read_palette(data: &[u8]) -> Result<Palette, Box<dyn Error>> {
+    if data.len() < 0x40 * 3 {
+        bail!("A palette needs 192 bytes of colours, but only {} were given", data.len());
+    }
+    let mut palette = [[0x00, 0x00, 0x00, 0xff]; 0x40];
+    for (colour, rgb) in palette.iter_mut().zip(data.chunks_exact(3)) {
+        colour[..3].copy_from_slice(rgb);
+    }
+    return Ok(palette);
+}
+
+/// Blends each image with the one before it evenly, like the persistence of a CRT's phosphors. This smooths out
+/// the flicker of games that show sprites on alternate frames to make them look transparent.
+#[derive(Debug, Clone, Default)]
+pub struct FrameBlender {
+    /// The last image that was passed to the blender, before it was blended
+    previous_image: Option<RgbaImage>,
+}
+
+impl PostProcessor for FrameBlender {
+    fn process(&mut self, _frame: &IndexedFrame, input: RgbaImage) -> RgbaImage {
+        let mut output = input.clone();
+        if let Some(previous_image) = self.previous_image.replace(input) {
+            if previous_image.pixels.len() == output.pixels.len() {
+                for (channel, previous_channel) in output.pixels.iter_mut().zip(&previous_image.pixels) {
+                    *channel = ((u16::from(*channel) + u16::from(*previous_channel)) / 2) as u8;
+                }
+            }
+        }
+        return output;
+    }
+}
+
+/// Video backend that draws to another backend, while keeping a copy of the frame's colour indices and colour
+/// emphasis for the post-processing chain so that the chain doesn't depend on the backend the NES draws to
+pub(crate) struct CapturingBackend<'a, V: VideoBackend> {
+    /// The backend that the NES draws to
+    pub(crate) backend: &'a mut V,
+    /// The copy of the frame kept for the post-processing chain
+    pub(crate) frame: &'a mut IndexedFrameBuffer,
+}
+
+impl<V: VideoBackend> VideoBackend for CapturingBackend<'_, V> {
+    type Frame = V::Frame;
+
+    fn draw_pixel(&mut self, x: usize, y: usize, colour_index: u8) {
+        self.backend.draw_pixel(x, y, colour_index);
+        self.frame.draw_pixel(x, y, colour_index);
+    }
+
+    fn set_emphasis(&mut self, emphasis: u8) {
+        self.backend.set_emphasis(emphasis);
+        self.frame.set_emphasis(emphasis);
+    }
+
+    fn get_frame(&self) -> &Self::Frame {
+        return self.backend.get_frame();
+    }
+
+    fn get_rgba_frame(&self) -> Option<Vec<u8>> {
+        return self.backend.get_rgba_frame();
+    }
+
+    fn get_indexed_frame(&self) -> Option<&[u8]> {
+        return self.backend.get_indexed_frame();
+    }
+}
+
+/// Scales images up by a whole number factor, repeating each pixel without smoothing
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NearestScaler {
+    /// The number of times each pixel is repeated across and down
+    factor: usize,
+}
+
+impl NearestScaler {
+    /// Creates a scaler that repeats each pixel factor times across and down. Factors below 1 are treated as 1.
+    pub fn new(factor: usize) -> Self {
+        return NearestScaler { factor: factor.max(1) };
+    }
+}
+
+impl PostProcessor for NearestScaler {
+    fn process(&mut self, _frame: &IndexedFrame, input: RgbaImage) -> RgbaImage {
+        let mut output = RgbaImage::new(input.width * self.factor, input.height * self.factor);
+        for (row, input_row) in output.pixels.chunks_exact_mut(output.width * 4).zip(0..) {
+            let source = &input.pixels[input_row / self.factor * input.width * 4..][..input.width * 4];
+            for (pixel, x) in row.chunks_exact_mut(4).zip(0..) {
+                pixel.copy_from_slice(&source[x / self.factor * 4..][..4]);
+            }
+        }
+        return output;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Processor that inverts the colour of every pixel that the NES drew with colour index 0x0f
+    #[derive(Clone)]
+    struct InvertBlack;
+
+    impl PostProcessor for InvertBlack {
+        fn process(&mut self, frame: &IndexedFrame, mut input: RgbaImage) -> RgbaImage {
+            for (pixel, colour_index) in input.pixels.chunks_exact_mut(4).zip(frame.indices) {
+                if *colour_index == 0x0f {
+                    pixel[0..3].iter_mut().for_each(|channel| *channel = !*channel);
+                }
+            }
+            return input;
+        }
+    }
+
+    #[test]
+    fn test_post_process_chain() {
+        let mut chain = PostProcessChain::default();
+        let mut palette = [[0x00, 0x00, 0x00, 0xff]; 0x40];
+        palette[0x01] = [0x10, 0x20, 0x30, 0xff];
+        chain.set_palette(palette);
+        chain.push(Box::new(InvertBlack));
+        chain.push(Box::new(NearestScaler::new(2)));
+        assert_eq!(2, chain.len());
+        let image = chain.clone().process(&[0x01, 0x0f], &[0x00, 0x00], 2, 1);
+        assert_eq!((4, 2), (image.width, image.height));
+        assert_eq!(
+            [0x10, 0x20, 0x30, 0xff, 0x10, 0x20, 0x30, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            image.pixels[0..16]
+        );
+        assert_eq!(image.pixels[0..16], image.pixels[16..32]);
+        chain.clear();
+        assert_eq!(vec![0x10, 0x20, 0x30, 0xff], chain.process(&[0x01], &[0x00], 1, 1).pixels);
+        // Emphasising blue darkens the red and green channels
+        assert_eq!(vec![0x0c, 0x18, 0x30, 0xff], chain.process(&[0x01], &[0x04], 1, 1).pixels);
+    }
+
+    #[test]
+    fn test_read_palette() {
+        let mut data: Vec<u8> = (0..0x40 * 3).map(|byte| byte as u8).collect();
+        let palette = read_palette(&data).unwrap();
+        assert_eq!([0x00, 0x01, 0x02, 0xff], palette[0x00]);
+        assert_eq!([0xbd, 0xbe, 0xbf, 0xff], palette[0x3f]);
+        data.truncate(100);
+        assert!(read_palette(&data).is_err());
+    }
+
+    #[test]
+    fn test_frame_blender() {
+        let mut blender = FrameBlender::default();
+        let frame = IndexedFrame {
+            indices: &[0x00],
+            emphasis: &[0x00],
+            width: 1,
+            height: 1,
+            palette: &NES_COLOUR_MAP_WEB,
+        };
+        let image = |pixels: Vec<u8>| RgbaImage { width: 1, height: 1, pixels };
+        assert_eq!(
+            vec![0x80, 0x00, 0x40, 0xff],
+            blender.process(&frame, image(vec![0x80, 0x00, 0x40, 0xff])).pixels
+        );
+        // Each image is blended with the last one that was passed in, rather than the last output
+        assert_eq!(
+            vec![0x40, 0x7f, 0x40, 0xff],
+            blender.process(&frame, image(vec![0x00, 0xff, 0x40, 0xff])).pixels
+        );
+        assert_eq!(
+            vec![0x00, 0xff, 0x40, 0xff],
+            blender.process(&frame, image(vec![0x00, 0xff, 0x40, 0xff])).pixels
+        );
+    }
+}
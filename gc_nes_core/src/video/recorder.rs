@@ -0,0 +1,442 @@
+//! The recorder module contains the [ClipRecorder](struct.ClipRecorder.html), which collects
+//! frames produced by the NES and encodes them as an animated GIF or APNG clip.
+//!
+//! Clips are encoded at the NES' native resolution with the NES palette as their colour table,
+//! so frames are stored with a single byte per pixel. Only the region of each frame that changed
+//! since the previous one is encoded, and frames that didn't change are merged into the one before.
+
+use super::*;
+use crate::nes::NES_FRAME_RATE;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// The shortest delay between GIF frames, in hundredths of a second, that browsers play at the
+/// intended speed. Shorter delays are usually slowed down to a tenth of a second, so the NES'
+/// 60 frames per second are reduced to 50 by dropping frames to stay above it.
+const GIF_MIN_DELAY: usize = 2;
+/// The largest delay that a GIF frame can have, in hundredths of a second
+const GIF_MAX_DELAY: usize = u16::MAX as usize;
+/// The denominator of APNG frame delays in seconds, so that the numerator counts hundredths of a NES frame
+#[cfg(feature = "png-export")]
+const APNG_DELAY_DENOMINATOR: u16 = 6010;
+/// The numerator of the APNG frame delay for a single NES frame
+#[cfg(feature = "png-export")]
+const APNG_FRAME_DELAY: usize = 100;
+/// The largest number of NES frames that a single APNG frame can be shown for
+#[cfg(feature = "png-export")]
+const APNG_MAX_DELAY: usize = u16::MAX as usize / APNG_FRAME_DELAY;
+/// The minimum LZW code size of GIF image data, enough to represent all 64 NES colours
+const GIF_MIN_CODE_SIZE: u8 = 6;
+/// The largest number of codes that a GIF LZW table can hold
+const GIF_MAX_CODES: u16 = 4096;
+
+/// The formats that a clip can be encoded in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClipFormat {
+    /// An animated GIF, played at 50 frames per second
+    Gif,
+    /// An animated PNG, played at the NES' full frame rate
+    Apng,
+}
+
+/// Collects frames from the NES to encode as an animated clip
+#[derive(Clone, Default)]
+pub struct ClipRecorder {
+    /// The distinct frames that have been recorded, each with the index of the NES frame it first appeared on
+    frames: Vec<(Box<[u8; NES_SCREEN_DIMENSIONS]>, usize)>,
+    /// The total number of NES frames that have been recorded
+    frame_count: usize,
+    /// Cache of the NES colour index that each RGB colour is closest to
+    colour_indices: HashMap<[u8; 3], u8>,
+}
+
+/// A region of the screen that changed between two frames of a clip, and how long it is shown for
+struct ClipFrame {
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+    /// The colour indices of the pixels in the region, in rows from the top left
+    pixels: Vec<u8>,
+    /// How long the frame is shown for, in the time units of the clip's format
+    delay: usize,
+}
+
+impl ClipRecorder {
+    /// Creates a new recorder with no frames
+    pub fn new() -> Self {
+        return Default::default();
+    }
+
+    /// Gets the number of NES frames that have been recorded
+    pub fn len(&self) -> usize {
+        return self.frame_count;
+    }
+
+    /// Checks if no frames have been recorded
+    pub fn is_empty(&self) -> bool {
+        return self.frame_count == 0;
+    }
+
+    /// Records the current frame of a video backend, which must be able to produce RGBA frames
+    pub fn add_frame<V: VideoBackend>(&mut self, backend: &V) -> Result<(), Box<dyn Error>> {
+        return match backend.get_rgba_frame() {
+            Some(rgba_frame) => {
+                self.add_rgba_frame(&rgba_frame);
+                Ok(())
+            }
+            None => bail!("The video backend can't produce RGBA frames"),
+        };
+    }
+
+    /// Records a frame with each pixel represented by four bytes in RGBA order.
+    /// Colours that aren't in the NES palette are recorded as the closest colour that is.
+    pub fn add_rgba_frame(&mut self, rgba_frame: &[u8]) {
+        let mut frame = Box::new([0; NES_SCREEN_DIMENSIONS]);
+        for (pixel, rgba) in frame.iter_mut().zip(rgba_frame.chunks_exact(4)) {
+            let rgb = [rgba[0], rgba[1], rgba[2]];
+            *pixel = *self.colour_indices.entry(rgb).or_insert_with(|| find_closest_colour(rgb));
+        }
+        // Identical frames are only stored once
+        if !matches!(self.frames.last(), Some((last_frame, _)) if *last_frame == frame) {
+            self.frames.push((frame, self.frame_count));
+        }
+        self.frame_count += 1;
+    }
+
+    /// Encodes the recorded frames as a looping animated clip
+    pub fn encode(&self, format: ClipFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        if self.is_empty() {
+            bail!("Can't encode a clip with no frames");
+        }
+        return match format {
+            ClipFormat::Gif => {
+                let timestamp = |frame: usize| (frame as f64 * 100.0 / NES_FRAME_RATE).round() as usize;
+                Ok(encode_gif(&self.get_clip_frames(timestamp, GIF_MIN_DELAY, GIF_MAX_DELAY)))
+            }
+            #[cfg(feature = "png-export")]
+            ClipFormat::Apng => encode_apng(&self.get_clip_frames(|frame| frame, 1, APNG_MAX_DELAY)),
+            #[cfg(not(feature = "png-export"))]
+            ClipFormat::Apng => bail!("APNG clips require the png-export feature"),
+        };
+    }
+
+    /// Selects the frames that will be shown in a clip, where the timestamp closure converts a NES frame index
+    /// into the clip's time units. The clip shows the latest NES frame at every multiple of the minimum delay.
+    fn get_clip_frames<F: Fn(usize) -> usize>(&self, timestamp: F, min_delay: usize, max_delay: usize) -> Vec<ClipFrame> {
+        let end = timestamp(self.frame_count);
+        let mut shown: Vec<(&[u8; NES_SCREEN_DIMENSIONS], usize)> = Vec::new();
+        let mut next_frame = 0;
+        for tick in (0..end).step_by(min_delay) {
+            while next_frame < self.frames.len() && timestamp(self.frames[next_frame].1) <= tick {
+                next_frame += 1;
+            }
+            // The first frame is always recorded on NES frame 0, which has a timestamp of 0
+            shown.push((&self.frames[next_frame - 1].0, tick));
+        }
+
+        let mut clip_frames: Vec<ClipFrame> = Vec::new();
+        let mut previous: Option<&[u8; NES_SCREEN_DIMENSIONS]> = None;
+        for (index, (frame, start)) in shown.iter().enumerate() {
+            // The last frame is shown for at least the minimum delay, even if the recording ended sooner
+            let mut delay = shown
+                .get(index + 1)
+                .map_or(min_delay.max(end - start), |(_, next_start)| *next_start - start);
+            let mut changed = previous.map_or(Some((0, 0, NES_SCREEN_WIDTH, NES_SCREEN_HEIGHT)), |previous| {
+                find_changed_region(previous, frame)
+            });
+            previous = Some(frame);
+            while delay > 0 {
+                // Unchanged frames are merged into the frame before, as long as its delay doesn't overflow
+                if let (None, Some(last)) = (changed, clip_frames.last_mut()) {
+                    let merged = delay.min(max_delay - last.delay);
+                    last.delay += merged;
+                    delay -= merged;
+                    if delay == 0 {
+                        break;
+                    }
+                }
+                // Unchanged frames that can't be merged are encoded as a single pixel
+                let (left, top, width, height) = changed.unwrap_or((0, 0, 1, 1));
+                clip_frames.push(ClipFrame {
+                    left,
+                    top,
+                    width,
+                    height,
+                    pixels: (top..top + height)
+                        .flat_map(|y| frame[y * NES_SCREEN_WIDTH + left..y * NES_SCREEN_WIDTH + left + width].iter().copied())
+                        .collect(),
+                    delay: delay.min(max_delay),
+                });
+                delay -= delay.min(max_delay);
+                changed = None;
+            }
+        }
+        return clip_frames;
+    }
+}
+
+/// Finds the NES colour index of the colour in the palette that is closest to an RGB colour
+fn find_closest_colour(rgb: [u8; 3]) -> u8 {
+    let distance = |colour: &[u8; 4]| -> i32 {
+        return (0..3).map(|channel| (i32::from(colour[channel]) - i32::from(rgb[channel])).pow(2)).sum();
+    };
+    return (0..NES_COLOUR_MAP_WEB.len())
+        .min_by_key(|index| distance(&NES_COLOUR_MAP_WEB[*index]))
+        .unwrap_or(0) as u8;
+}
+
+/// Finds the bounding box of the pixels that differ between two frames as (left, top, width, height),
+/// or None if the frames are identical
+fn find_changed_region(previous: &[u8; NES_SCREEN_DIMENSIONS], frame: &[u8; NES_SCREEN_DIMENSIONS]) -> Option<(usize, usize, usize, usize)> {
+    let (mut left, mut top, mut right, mut bottom) = (NES_SCREEN_WIDTH, NES_SCREEN_HEIGHT, 0, 0);
+    for (index, _) in previous.iter().zip(frame.iter()).enumerate().filter(|(_, (a, b))| a != b) {
+        let (x, y) = (index % NES_SCREEN_WIDTH, index / NES_SCREEN_WIDTH);
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+    return if left > right {
+        None
+    } else {
+        Some((left, top, right - left + 1, bottom - top + 1))
+    };
+}
+
+/// Encodes the frames of a clip as a looping animated GIF with delays in hundredths of a second
+fn encode_gif(clip_frames: &[ClipFrame]) -> Vec<u8> {
+    let mut gif = Vec::new();
+    gif.extend_from_slice(b"GIF89a");
+    gif.extend_from_slice(&(NES_SCREEN_WIDTH as u16).to_le_bytes());
+    gif.extend_from_slice(&(NES_SCREEN_HEIGHT as u16).to_le_bytes());
+    // Global colour table of 2^(5 + 1) colours with 8 bits per channel, no background colour or aspect ratio
+    gif.extend_from_slice(&[0xf5, 0x00, 0x00]);
+    NES_COLOUR_MAP_WEB.iter().for_each(|colour| gif.extend_from_slice(&colour[..3]));
+    // Netscape application extension to loop the animation forever
+    gif.extend_from_slice(&[0x21, 0xff, 0x0b]);
+    gif.extend_from_slice(b"NETSCAPE2.0");
+    gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+    for frame in clip_frames {
+        // Graphic control extension, leaving each frame in place to be drawn over by the next
+        gif.extend_from_slice(&[0x21, 0xf9, 0x04, 0x04]);
+        gif.extend_from_slice(&(frame.delay as u16).to_le_bytes());
+        gif.extend_from_slice(&[0x00, 0x00]);
+        // Image descriptor
+        gif.push(0x2c);
+        for value in [frame.left, frame.top, frame.width, frame.height].iter() {
+            gif.extend_from_slice(&(*value as u16).to_le_bytes());
+        }
+        gif.push(0x00);
+        // Image data, split into sub-blocks of up to 255 bytes
+        gif.push(GIF_MIN_CODE_SIZE);
+        for block in lzw_encode(&frame.pixels, GIF_MIN_CODE_SIZE).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.push(0x00);
+    }
+    gif.push(0x3b);
+    return gif;
+}
+
+/// Compresses colour indices with the variable code length LZW compression used by GIF images
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear_code: u16 = 1 << min_code_size;
+    let end_code = clear_code + 1;
+    let mut writer = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end_code + 1;
+    let mut code_size = min_code_size + 1;
+
+    writer.write(clear_code, code_size);
+    let mut prefix = u16::from(indices[0]);
+    for index in indices[1..].iter() {
+        match table.get(&(prefix, *index)) {
+            Some(code) => prefix = *code,
+            None => {
+                writer.write(prefix, code_size);
+                if next_code == GIF_MAX_CODES {
+                    // The table is full, so start again with a new one
+                    writer.write(clear_code, code_size);
+                    table.clear();
+                    next_code = end_code + 1;
+                    code_size = min_code_size + 1;
+                } else {
+                    if next_code >= 1 << code_size {
+                        code_size += 1;
+                    }
+                    table.insert((prefix, *index), next_code);
+                    next_code += 1;
+                }
+                prefix = u16::from(*index);
+            }
+        }
+    }
+    writer.write(prefix, code_size);
+    writer.write(end_code, code_size);
+    return writer.finish();
+}
+
+/// Packs variable length codes into bytes, least significant bit first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    buffer_length: u8,
+}
+
+impl BitWriter {
+    /// Writes the lowest bits of a code
+    fn write(&mut self, code: u16, length: u8) {
+        self.buffer |= u32::from(code) << self.buffer_length;
+        self.buffer_length += length;
+        while self.buffer_length >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.buffer_length -= 8;
+        }
+    }
+
+    /// Writes out any remaining bits, padded to a full byte, and returns the packed bytes
+    fn finish(mut self) -> Vec<u8> {
+        if self.buffer_length > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        return self.bytes;
+    }
+}
+
+/// Encodes the frames of a clip as a looping animated PNG with delays in NES frames
+#[cfg(feature = "png-export")]
+fn encode_apng(clip_frames: &[ClipFrame]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let palette: Vec<u8> = NES_COLOUR_MAP_WEB.iter().flat_map(|colour| colour[..3].to_vec()).collect();
+    let mut png_data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, NES_SCREEN_WIDTH as u32, NES_SCREEN_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette);
+        encoder.set_animated(clip_frames.len() as u32, 0)?;
+        let mut writer = encoder.write_header()?;
+        for frame in clip_frames {
+            // The position is reset first so that the new dimensions are never out of bounds
+            writer.reset_frame_position()?;
+            writer.set_frame_dimension(frame.width as u32, frame.height as u32)?;
+            writer.set_frame_position(frame.left as u32, frame.top as u32)?;
+            writer.set_frame_delay((frame.delay * APNG_FRAME_DELAY) as u16, APNG_DELAY_DENOMINATOR)?;
+            writer.write_image_data(&frame.pixels)?;
+        }
+        writer.finish()?;
+    }
+    return Ok(png_data);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Decompresses GIF LZW data, to check the encoder against
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear_code: u16 = 1 << min_code_size;
+        let mut output = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let (mut buffer, mut buffer_length, mut bytes) = (0u32, 0u8, data.iter());
+        loop {
+            while buffer_length < code_size {
+                buffer |= u32::from(*bytes.next().unwrap()) << buffer_length;
+                buffer_length += 8;
+            }
+            let code = (buffer & ((1 << code_size) - 1)) as u16;
+            buffer >>= code_size;
+            buffer_length -= code_size;
+
+            if code == clear_code {
+                table = (0..clear_code + 2).map(|index| vec![index as u8]).collect();
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            } else if code == clear_code + 1 {
+                return output;
+            }
+            let entry = match (table.get(code as usize), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.clone(), vec![previous[0]]].concat(),
+                (None, None) => panic!("Invalid code {}", code),
+            };
+            output.extend_from_slice(&entry);
+            if let Some(previous) = previous {
+                table.push([previous, vec![entry[0]]].concat());
+                if table.len() == 1 << code_size && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+            previous = Some(entry);
+        }
+    }
+
+    /// Creates an RGBA frame filled with a single NES colour, with a different colour at one pixel
+    fn get_test_frame(background: usize, pixel: usize) -> Vec<u8> {
+        let mut frame: Vec<u8> = (0..NES_SCREEN_DIMENSIONS).flat_map(|_| NES_COLOUR_MAP_WEB[background].to_vec()).collect();
+        frame[pixel * 4..pixel * 4 + 4].copy_from_slice(&NES_COLOUR_MAP_WEB[0x21]);
+        return frame;
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        // Long enough to fill the code table and force it to be cleared
+        let indices: Vec<u8> = (0..20_000u32).map(|i| ((i * 7 + i / 13) % 64) as u8).collect();
+        assert_eq!(indices, lzw_decode(&lzw_encode(&indices, GIF_MIN_CODE_SIZE), GIF_MIN_CODE_SIZE));
+    }
+
+    #[test]
+    fn test_add_rgba_frame_merges_identical_frames() {
+        let mut recorder = ClipRecorder::new();
+        recorder.add_rgba_frame(&get_test_frame(0x0f, 0));
+        recorder.add_rgba_frame(&get_test_frame(0x0f, 0));
+        recorder.add_rgba_frame(&get_test_frame(0x0f, 1));
+        assert_eq!(3, recorder.len());
+        assert_eq!(2, recorder.frames.len());
+        assert_eq!(0x21, recorder.frames[1].0[1]);
+    }
+
+    #[test]
+    fn test_gif_clip_frames_drop_to_fifty_fps() {
+        let mut recorder = ClipRecorder::new();
+        (0..60).for_each(|frame| recorder.add_rgba_frame(&get_test_frame(0x0f, frame)));
+        let timestamp = |frame: usize| (frame as f64 * 100.0 / NES_FRAME_RATE).round() as usize;
+        let clip_frames = recorder.get_clip_frames(timestamp, GIF_MIN_DELAY, GIF_MAX_DELAY);
+        assert_eq!(50, clip_frames.len());
+        assert!(clip_frames.iter().all(|frame| frame.delay == 2));
+        // Only the changed pixels are encoded after the first frame
+        assert_eq!(NES_SCREEN_DIMENSIONS, clip_frames[0].pixels.len());
+        assert_eq!(2, clip_frames[1].width);
+    }
+
+    #[test]
+    fn test_encode_gif() {
+        let mut recorder = ClipRecorder::new();
+        recorder.add_rgba_frame(&get_test_frame(0x0f, 0));
+        let gif = recorder.encode(ClipFormat::Gif).unwrap();
+        assert_eq!(b"GIF89a", &gif[..6]);
+        assert_eq!(Some(&0x3b), gif.last());
+    }
+
+    #[test]
+    fn test_encode_empty() {
+        ClipRecorder::new().encode(ClipFormat::Gif).expect_err("Encoded a clip with no frames");
+    }
+
+    #[cfg(feature = "png-export")]
+    #[test]
+    fn test_encode_apng() {
+        let mut recorder = ClipRecorder::new();
+        (0..3).for_each(|frame| recorder.add_rgba_frame(&get_test_frame(0x0f, frame)));
+        let apng = recorder.encode(ClipFormat::Apng).unwrap();
+        let reader = png::Decoder::new(apng.as_slice()).read_info().unwrap();
+        assert_eq!(3, reader.info().animation_control.unwrap().num_frames);
+    }
+}
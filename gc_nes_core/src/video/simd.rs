@@ -0,0 +1,102 @@
+//! The simd module contains the WASM SIMD implementation of the bulk conversion from NES colour
+//! indices to RGBA bytes. It is only compiled when targeting wasm32 with the `simd128` target feature.
+//!
+//! WASM has no runtime feature detection, as a module containing a single SIMD instruction fails to
+//! validate on a browser without SIMD support. The fallback happens when the module is loaded instead:
+//! the frontend builds the module both with and without `simd128`, and picks one based on browser support.
+
+use super::NES_COLOUR_MAP_WEB;
+use core::arch::wasm32::*;
+
+/// The number of colour indices converted by each iteration of the SIMD loop
+const LANES: usize = 16;
+
+/// Converts the colour indices to RGBA in blocks of sixteen, looking the colours up with byte swizzles.
+/// Returns the number of indices that were converted, which excludes any left over after the last full block.
+pub(super) fn indexed_to_rgba(indices: &[u8], rgba: &mut [u8]) -> usize {
+    // Each colour channel of the 64 entry palette is split into four tables of sixteen, since a swizzle
+    // can only select from sixteen bytes. Swizzle selects zero for lane indices of sixteen and above.
+    let mut tables = [[u8x16_splat(0); 4]; 3];
+    for (channel, channel_tables) in tables.iter_mut().enumerate() {
+        for (quarter, table) in channel_tables.iter_mut().enumerate() {
+            let mut bytes = [0u8; LANES];
+            for (lane, byte) in bytes.iter_mut().enumerate() {
+                *byte = NES_COLOUR_MAP_WEB[quarter * LANES + lane][channel];
+            }
+            // Safety: bytes is 16 bytes long, and v128_load has no alignment requirement
+            *table = unsafe { v128_load(bytes.as_ptr() as *const v128) };
+        }
+    }
+    let alpha = u8x16_splat(0xff);
+
+    let blocks = indices.len().min(rgba.len() / 4) / LANES;
+    for block in 0..blocks {
+        // Safety: block is in bounds for both slices, as blocks was limited by the lengths of both
+        let index = v128_and(unsafe { v128_load(indices[block * LANES..].as_ptr() as *const v128) }, u8x16_splat(0x3f));
+        let [red, green, blue] = tables.map(|channel_tables| {
+            channel_tables.iter().enumerate().fold(u8x16_splat(0), |channel, (quarter, table)| {
+                v128_or(channel, i8x16_swizzle(*table, u8x16_sub(index, u8x16_splat((quarter * LANES) as u8))))
+            })
+        });
+
+        // Interleave the channels into RGBA order, first into red-green and blue-alpha pairs
+        let red_green_low = u8x16_shuffle::<0, 16, 1, 17, 2, 18, 3, 19, 4, 20, 5, 21, 6, 22, 7, 23>(red, green);
+        let red_green_high = u8x16_shuffle::<8, 24, 9, 25, 10, 26, 11, 27, 12, 28, 13, 29, 14, 30, 15, 31>(red, green);
+        let blue_alpha_low = u8x16_shuffle::<0, 16, 1, 17, 2, 18, 3, 19, 4, 20, 5, 21, 6, 22, 7, 23>(blue, alpha);
+        let blue_alpha_high = u8x16_shuffle::<8, 24, 9, 25, 10, 26, 11, 27, 12, 28, 13, 29, 14, 30, 15, 31>(blue, alpha);
+        let pixels = [
+            u16x8_shuffle::<0, 8, 1, 9, 2, 10, 3, 11>(red_green_low, blue_alpha_low),
+            u16x8_shuffle::<4, 12, 5, 13, 6, 14, 7, 15>(red_green_low, blue_alpha_low),
+            u16x8_shuffle::<0, 8, 1, 9, 2, 10, 3, 11>(red_green_high, blue_alpha_high),
+            u16x8_shuffle::<4, 12, 5, 13, 6, 14, 7, 15>(red_green_high, blue_alpha_high),
+        ];
+        for (quarter, pixels) in pixels.iter().enumerate() {
+            let offset = (block * LANES + quarter * 4) * 4;
+            // Safety: offset is in bounds, as blocks was limited by the length of rgba
+            unsafe { v128_store(rgba[offset..].as_mut_ptr() as *mut v128, *pixels) };
+        }
+    }
+    return blocks * LANES;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Converts the colour indices one at a time, the same way as the scalar path
+    fn scalar_indexed_to_rgba(indices: &[u8]) -> Vec<u8> {
+        return indices
+            .iter()
+            .flat_map(|colour_index| NES_COLOUR_MAP_WEB[(colour_index & 0x3f) as usize])
+            .collect();
+    }
+
+    #[test]
+    fn test_indexed_to_rgba_matches_scalar() {
+        // Every byte value, including the ones with the unused upper bits set
+        let indices: Vec<u8> = (0..=0xffu8).collect();
+        let mut rgba = vec![0u8; indices.len() * 4];
+        assert_eq!(indices.len(), indexed_to_rgba(&indices, &mut rgba));
+        assert_eq!(scalar_indexed_to_rgba(&indices), rgba);
+    }
+
+    #[test]
+    fn test_indexed_to_rgba_leaves_partial_block() {
+        let indices: Vec<u8> = (0..LANES as u8 + 5).collect();
+        let mut rgba = vec![0u8; indices.len() * 4];
+        assert_eq!(LANES, indexed_to_rgba(&indices, &mut rgba));
+        assert_eq!(scalar_indexed_to_rgba(&indices[..LANES]), rgba[..LANES * 4]);
+        assert!(rgba[LANES * 4..].iter().all(|&byte| byte == 0));
+        // The public conversion finishes the partial block with the scalar path
+        super::super::indexed_to_rgba(&indices, &mut rgba);
+        assert_eq!(scalar_indexed_to_rgba(&indices), rgba);
+    }
+
+    #[test]
+    fn test_indexed_to_rgba_stops_at_shorter_slice() {
+        let indices = [0x16u8; LANES * 2];
+        let mut rgba = vec![0u8; LANES * 4 + 8];
+        assert_eq!(LANES, indexed_to_rgba(&indices, &mut rgba));
+        assert!(rgba[LANES * 4..].iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,185 @@
+//! The audio module plays the NES' audio through the host's default output device, which is only available when
+//! gc_nes_desktop is built with the audio feature.
+//!
+//! The main loop queues the samples of each refresh in a buffer that the device's callback plays from, and is
+//! clocked by how full that buffer is: it runs frames whenever the buffer drops below half the configured latency's
+//! worth of samples. The buffer holds at most the full latency's worth, dropping the oldest past that, and leaves
+//! the device playing silence if the main loop hitches long enough to empty it. A higher latency rides out longer
+//! hitches before the device runs dry, at the cost of the sound lagging further behind the picture.
+
+#[cfg(feature = "audio")]
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+#[cfg(feature = "audio")]
+use std::collections::VecDeque;
+#[cfg(feature = "audio")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "audio")]
+use std::time::{Duration, Instant};
+
+/// Wrapper around the audio output stream, which is only available when gc_nes_desktop is built with the audio feature
+pub struct AudioOutput {
+    #[cfg(feature = "audio")]
+    output: Option<CpalOutput>,
+}
+
+/// An open output stream and the buffer that it plays from
+#[cfg(feature = "audio")]
+struct CpalOutput {
+    /// The stream, which stops playing when it is dropped
+    _stream: cpal::Stream,
+    /// The samples waiting to be played, oldest first
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    /// The rate that the device plays samples at, in Hz
+    sample_rate: f64,
+    /// The most samples that can be waiting to be played
+    capacity: usize,
+}
+
+#[cfg(feature = "audio")]
+impl AudioOutput {
+    /// Opens the default output device, holding up to the latency in milliseconds of audio waiting to be played.
+    /// The device is asked to use the buffer size in frames if one is given, or its own default if not.
+    /// Logs a warning if the device is unavailable, and the audio is discarded instead.
+    pub fn new(latency_ms: u32, buffer_size: Option<u32>) -> Self {
+        let output = match CpalOutput::open(latency_ms, buffer_size) {
+            Ok(output) => Some(output),
+            Err(error) => {
+                warn!("Unable to open the audio device: {}", error);
+                None
+            }
+        };
+        return AudioOutput { output };
+    }
+
+    /// Gets the rate that the device plays samples at, in Hz, or None if there is no device
+    pub fn get_sample_rate(&self) -> Option<f64> {
+        return self.output.as_ref().map(|output| output.sample_rate);
+    }
+
+    /// Queues samples to be played after the ones already waiting, dropping the oldest if there are too many
+    pub fn queue(&self, samples: &[f32]) {
+        if let Some(output) = &self.output {
+            let mut queue = output.queue.lock().expect("Audio queue poisoned");
+            queue.extend(samples);
+            let excess = queue.len().saturating_sub(output.capacity);
+            queue.drain(..excess);
+        }
+    }
+
+    /// Gets how many samples the queue is short of its target of half the latency's worth, or 0 if there is no device
+    pub fn get_sample_deficit(&self) -> usize {
+        return match &self.output {
+            Some(output) => (output.capacity / 2).saturating_sub(output.get_queued_samples()),
+            None => 0,
+        };
+    }
+
+    /// Checks if less than a quarter of the latency's worth of samples is waiting, meaning the device is about to run dry
+    pub fn is_running_low(&self) -> bool {
+        return match &self.output {
+            Some(output) => output.get_queued_samples() < output.capacity / 4,
+            None => false,
+        };
+    }
+
+    /// Sleeps until the queue drops below its target and needs more samples. Gives up once the latency has
+    /// passed, so that a stalled device can't stop the window from responding.
+    pub fn wait_for_deficit(&self) {
+        if let Some(output) = &self.output {
+            let timeout = Instant::now() + Duration::from_secs_f64(output.capacity as f64 / output.sample_rate);
+            while self.get_sample_deficit() == 0 && Instant::now() < timeout {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+impl CpalOutput {
+    /// Opens the default output device and starts it playing from an empty buffer
+    fn open(latency_ms: u32, buffer_size: Option<u32>) -> Result<Self, Box<dyn std::error::Error>> {
+        let device = cpal::default_host().default_output_device().ok_or("No audio output device found")?;
+        let supported = device.default_output_config()?;
+        let sample_format = supported.sample_format();
+        let mut config: cpal::StreamConfig = supported.into();
+        if let Some(frames) = buffer_size {
+            config.buffer_size = cpal::BufferSize::Fixed(frames);
+        }
+        let sample_rate = f64::from(config.sample_rate.0);
+        let capacity = (sample_rate * f64::from(latency_ms) / 1000.0) as usize;
+        let queue = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone())?,
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone())?,
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone())?,
+            format => return Err(format!("Unsupported audio sample format: {}", format).into()),
+        };
+        stream.play()?;
+        info!(
+            "Playing audio at {} Hz with {} ms of latency and {:?} buffers",
+            sample_rate, latency_ms, config.buffer_size
+        );
+        return Ok(CpalOutput {
+            _stream: stream,
+            queue,
+            sample_rate,
+            capacity,
+        });
+    }
+
+    /// Gets the number of samples waiting to be played
+    fn get_queued_samples(&self) -> usize {
+        return self.queue.lock().expect("Audio queue poisoned").len();
+    }
+}
+
+/// Builds an output stream that plays the queued samples on every channel of the device, or silence once they run out
+#[cfg(feature = "audio")]
+fn build_stream<T: cpal::SizedSample + cpal::FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let channels = usize::from(config.channels);
+    return device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let mut queue = queue.lock().expect("Audio queue poisoned");
+            for frame in data.chunks_mut(channels) {
+                let sample = T::from_sample(queue.pop_front().unwrap_or(0.0));
+                frame.iter_mut().for_each(|output| *output = sample);
+            }
+        },
+        |error| warn!("Audio stream error: {}", error),
+        None,
+    );
+}
+
+#[cfg(not(feature = "audio"))]
+impl AudioOutput {
+    /// Creates the audio wrapper. Without the audio feature, there is never a device to play to.
+    pub fn new(_latency_ms: u32, _buffer_size: Option<u32>) -> Self {
+        return AudioOutput {};
+    }
+
+    /// Always returns None without the audio feature
+    pub fn get_sample_rate(&self) -> Option<f64> {
+        return None;
+    }
+
+    /// Discards the samples without the audio feature
+    pub fn queue(&self, _samples: &[f32]) {}
+
+    /// Always returns 0 without the audio feature
+    pub fn get_sample_deficit(&self) -> usize {
+        return 0;
+    }
+
+    /// Always returns false without the audio feature
+    pub fn is_running_low(&self) -> bool {
+        return false;
+    }
+
+    /// Returns immediately without the audio feature
+    pub fn wait_for_deficit(&self) {}
+}
@@ -0,0 +1,387 @@
+//! The commands module contains the commands of gc_nes_desktop that run without a window,
+//! which are useful for scripting and bug triage.
+
+use gc_nes_core::cartridge::{calculate_rom_md5, Cartridge, CartridgeInfo, MapperSupport};
+use gc_nes_core::movie::{FrameRenderer, Movie};
+use gc_nes_core::nes::{Nes, TestRomStatus, NES_FRAME_RATE};
+use gc_nes_core::trace::{write_trace, TraceFormat};
+use gc_nes_core::video::{encode_png, IndexedFrameBuffer, VideoBackend, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Formats a hash as a string of lowercase hexadecimal digits
+fn format_hash(hash: &[u8]) -> String {
+    return hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+/// Prints the header information, mapper support, and MD5 hash of a NES ROM
+pub fn info(file: &Path) -> Result<(), Box<dyn Error>> {
+    let info = CartridgeInfo::read_from_file(file)?;
+    let rom_md5 = calculate_rom_md5(BufReader::new(File::open(file)?))?;
+    println!("File:             {}", file.display());
+    println!("Format:           {}", if info.nes2 { "NES 2.0" } else { "iNES" });
+    println!(
+        "Console:          {}",
+        match (info.vs_system, info.vs_dual_system) {
+            (true, true) => "Vs. DualSystem (unsupported)",
+            (true, false) => "Vs. System",
+            (false, _) => "NES",
+        }
+    );
+    println!("Mapper:           {:03} (submapper {})", info.mapper_id, info.submapper_id);
+    match info.mapper_support {
+        MapperSupport::Full => println!("Mapper support:   Full"),
+        MapperSupport::Partial { notes } => println!("Mapper support:   Partial - {}", notes),
+        MapperSupport::Unsupported => println!("Mapper support:   Unsupported"),
+    }
+    println!("Program ROM:      {} KiB", info.program_rom_size / 1024);
+    if info.character_rom_size == 0 {
+        println!("Character ROM:    None (uses character RAM)");
+    } else {
+        println!("Character ROM:    {} KiB", info.character_rom_size / 1024);
+    }
+    println!(
+        "Mirroring:        {}",
+        match (info.four_screen, info.vertical_mirroring) {
+            (true, _) => "Four screen",
+            (false, true) => "Vertical",
+            (false, false) => "Horizontal",
+        }
+    );
+    println!("Battery:          {}", if info.battery { "Yes" } else { "No" });
+    println!("Trainer:          {}", if info.trainer { "Yes" } else { "No" });
+    println!("ROM MD5:          {}", format_hash(&rom_md5));
+    return Ok(());
+}
+
+/// Plays an FM2 movie back without a window, printing whether it was recorded with the ROM
+/// and a hash of the final frame. Returns an error if the movie was recorded with a different ROM.
+pub fn verify(file: &Path, movie_file: &Path) -> Result<(), Box<dyn Error>> {
+    let movie = Movie::read_fm2(BufReader::new(File::open(movie_file)?))?;
+    let rom_md5 = calculate_rom_md5(BufReader::new(File::open(file)?))?;
+    let mut nes = Nes::with_video_backend(Cartridge::load_from_file(file)?, IndexedFrameBuffer::default());
+
+    let timer = Instant::now();
+    for input in movie.frames() {
+        nes.update_frame_input(*input);
+        nes.frame();
+    }
+    println!("Frames:           {}", movie.len());
+    println!("Rerecords:        {}", movie.get_rerecord_count());
+    println!("Playback time:    {:.2}s", timer.elapsed().as_secs_f64());
+    println!(
+        "Final frame MD5:  {}",
+        format_hash(&md5::compute(nes.get_video_backend().get_frame().as_ref()).0)
+    );
+
+    return match movie.get_rom_md5() {
+        Some(movie_md5) if movie_md5 == rom_md5 => {
+            println!("ROM checksum:     Matches");
+            Ok(())
+        }
+        Some(movie_md5) => {
+            println!("ROM checksum:     Mismatch");
+            Err(format!(
+                "The movie was recorded with a ROM with an MD5 of {}, but this ROM's is {}",
+                format_hash(&movie_md5),
+                format_hash(&rom_md5)
+            )
+            .into())
+        }
+        None => {
+            println!("ROM checksum:     Not in movie");
+            Ok(())
+        }
+    };
+}
+
+/// Runs a NES ROM without a window as fast as possible and reports the speed
+pub fn bench(file: &Path, frames: u64) -> Result<(), Box<dyn Error>> {
+    let mut nes = Nes::new(Cartridge::load_from_file(file)?);
+    let timer = Instant::now();
+    for _ in 0..frames {
+        nes.frame();
+    }
+    let elapsed = timer.elapsed().as_secs_f64();
+    let frames_per_second = frames as f64 / elapsed;
+    println!("Frames:           {}", frames);
+    println!("Time:             {:.3}s", elapsed);
+    println!("FPS:              {:.1}", frames_per_second);
+    println!("Speed:            {:.1}x", frames_per_second / NES_FRAME_RATE);
+    return Ok(());
+}
+
+/// Renders a single frame of a NES ROM to a PNG without a window, played with the input from an FM2 movie if one is given
+pub fn thumbnail(file: &Path, output: &Path, frame: usize, movie_file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let movie = match movie_file {
+        Some(movie_file) => Some(Movie::read_fm2(BufReader::new(File::open(movie_file)?))?),
+        None => None,
+    };
+    let mut renderer = FrameRenderer::new(Cartridge::load_from_file(file)?, movie);
+    let rgba_frame = renderer.render_rgba_frame(frame);
+    File::create(output)?.write_all(&encode_png(&rgba_frame, NES_SCREEN_WIDTH as u32, NES_SCREEN_HEIGHT as u32)?)?;
+    println!("Wrote frame {} to {}", frame, output.display());
+    return Ok(());
+}
+
+/// Runs a NES ROM without a window for a number of frames, writing a trace of its execution to a file in the named format
+pub fn trace(file: &Path, output: &Path, frames: u64, format: &str) -> Result<(), Box<dyn Error>> {
+    let format = TraceFormat::from_name(format).ok_or_else(|| format!("Unknown trace format: {}", format))?;
+    let mut nes = Nes::new(Cartridge::load_from_file(file)?);
+    let mut writer = BufWriter::new(File::create(output)?);
+    nes.set_execution_trace(true);
+    for _ in 0..frames {
+        nes.frame();
+        write_trace(&mut writer, &nes.take_execution_trace(), format)?;
+    }
+    writer.flush()?;
+    println!("Wrote {} frames of trace to {}", frames, output.display());
+    return Ok(());
+}
+
+/// The number of frames that test ROMs are given to notice that reset is being held, about 100 milliseconds
+const TEST_ROM_RESET_FRAMES: u64 = 6;
+
+/// Runs a test ROM without a window until it reports that it has finished, pressing reset whenever it asks for it.
+/// Returns the last status it reported, or None if it never reported one.
+fn run_test_rom(file: &Path, frames: u64) -> Result<Option<TestRomStatus>, Box<dyn Error>> {
+    let mut nes = Nes::new(Cartridge::load_from_file(file)?);
+    let mut status = None;
+    for _ in 0..frames {
+        nes.frame();
+        status = nes.get_test_rom_status();
+        match status {
+            Some(TestRomStatus::Finished { .. }) => break,
+            // The ROM keeps asking until it has been reset, so only press it once
+            Some(TestRomStatus::ResetRequested) if nes.get_scheduled_reset().is_none() => nes.schedule_reset(TEST_ROM_RESET_FRAMES),
+            _ => {}
+        }
+    }
+    return Ok(status);
+}
+
+/// Runs test ROMs that report their results like blargg's suites without a window, printing whether each passed along
+/// with the text it printed. Returns an error if any of them failed, didn't finish in time or couldn't be loaded.
+pub fn test_roms(files: &[PathBuf], frames: u64) -> Result<(), Box<dyn Error>> {
+    let mut failures = 0;
+    for file in files {
+        let (result, text) = match run_test_rom(file, frames) {
+            Ok(Some(TestRomStatus::Finished { code: 0, text })) => ("PASS".to_string(), text),
+            Ok(Some(TestRomStatus::Finished { code, text })) => (format!("FAIL #{}", code), text),
+            Ok(Some(_)) => ("TIMEOUT".to_string(), String::new()),
+            Ok(None) => ("NO RESULT".to_string(), String::new()),
+            Err(error) => ("ERROR".to_string(), error.to_string()),
+        };
+        if result != "PASS" {
+            failures += 1;
+        }
+        println!("{:<10} {}", result, file.display());
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            println!("           {}", line.trim());
+        }
+    }
+    println!("{} of {} test ROMs passed", files.len() - failures, files.len());
+    return if failures == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} test ROMs didn't pass", failures).into())
+    };
+}
+
+/// The result of running one ROM for the compatibility matrix
+struct CompatibilityResult {
+    /// The path to the .nes file
+    file: PathBuf,
+    /// The ROM's mapper id, or None if its header couldn't be read
+    mapper: Option<u16>,
+    /// How well the ROM's mapper is supported: "full", "partial" or "unsupported", or "unknown" if its header couldn't be read
+    support: &'static str,
+    /// Whether the last frame run had more than one colour in it, which is taken to mean the game booted
+    booted: bool,
+    /// Whether the game appeared to hang at any point while it was running
+    hung: bool,
+    /// The error that the ROM failed to load with, or the message of the panic it caused while running
+    fault: Option<String>,
+    /// The speed that the ROM ran at in frames per second, or None if it didn't run to the end
+    fps: Option<f64>,
+}
+
+/// Finds the .nes files in a directory and all of the directories inside it, sorted by path
+fn find_roms(directory: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut roms = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            roms.append(&mut find_roms(&path)?);
+        } else if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("nes")) {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    return Ok(roms);
+}
+
+/// The number of frames a ROM has to go without any sign of running to be recorded as hung, two seconds' worth
+const HANG_FRAMES: u32 = 120;
+
+/// Loads a ROM and runs it without a window for a number of frames, recording whether it booted, hung or faulted
+fn check_compatibility(file: &Path, frames: u64) -> CompatibilityResult {
+    let mut result = CompatibilityResult {
+        file: file.to_path_buf(),
+        mapper: None,
+        support: "unknown",
+        booted: false,
+        hung: false,
+        fault: None,
+        fps: None,
+    };
+    let info = match CartridgeInfo::read_from_file(file) {
+        Ok(info) => info,
+        Err(error) => {
+            result.fault = Some(error.to_string());
+            return result;
+        }
+    };
+    result.mapper = Some(info.mapper_id);
+    result.support = match info.mapper_support {
+        MapperSupport::Full => "full",
+        MapperSupport::Partial { .. } => "partial",
+        MapperSupport::Unsupported => "unsupported",
+    };
+    let cartridge = match Cartridge::load_from_file(file) {
+        Ok(cartridge) => cartridge,
+        Err(error) => {
+            result.fault = Some(error.to_string());
+            return result;
+        }
+    };
+    let timer = Instant::now();
+    // A panic in the core is a fault in this ROM, rather than a reason to stop checking the rest
+    let run = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut nes = Nes::with_video_backend(cartridge, IndexedFrameBuffer::default());
+        nes.set_hang_detection(Some(HANG_FRAMES));
+        let mut hung = false;
+        for _ in 0..frames {
+            nes.frame();
+            hung |= nes.is_hung();
+        }
+        let frame = nes.get_video_backend().get_frame();
+        return (frame.iter().any(|&colour| colour != frame[0]), hung);
+    }));
+    match run {
+        Ok((booted, hung)) => {
+            result.booted = booted;
+            result.hung = hung;
+            result.fps = Some(frames as f64 / timer.elapsed().as_secs_f64());
+        }
+        Err(panic) => {
+            result.fault = Some(match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+                (Some(message), _) => message.to_string(),
+                (_, Some(message)) => message.clone(),
+                _ => "Panicked".to_string(),
+            });
+        }
+    }
+    return result;
+}
+
+/// Quotes a string for a CSV field, doubling any quotes inside it
+fn csv_string(value: &str) -> String {
+    return format!("\"{}\"", value.replace('"', "\"\""));
+}
+
+/// Quotes a string for JSON, escaping the characters that JSON strings can't hold
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for character in value.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            character if (character as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", character as u32)),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    return quoted;
+}
+
+/// Writes the compatibility matrix as CSV, with a header row
+fn write_compatibility_csv<W: Write>(writer: &mut W, results: &[CompatibilityResult]) -> std::io::Result<()> {
+    writeln!(writer, "file,mapper,support,booted,hung,fault,fps")?;
+    for result in results {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{}",
+            csv_string(&result.file.to_string_lossy()),
+            result.mapper.map(|mapper| mapper.to_string()).unwrap_or_default(),
+            result.support,
+            result.booted,
+            result.hung,
+            result.fault.as_deref().map(csv_string).unwrap_or_default(),
+            result.fps.map(|fps| format!("{:.1}", fps)).unwrap_or_default(),
+        )?;
+    }
+    return Ok(());
+}
+
+/// Writes the compatibility matrix as a JSON array with an object for each ROM, using null for missing values
+fn write_compatibility_json<W: Write>(writer: &mut W, results: &[CompatibilityResult]) -> std::io::Result<()> {
+    writeln!(writer, "[")?;
+    for (index, result) in results.iter().enumerate() {
+        writeln!(
+            writer,
+            "  {{\"file\": {}, \"mapper\": {}, \"support\": \"{}\", \"booted\": {}, \"hung\": {}, \"fault\": {}, \"fps\": {}}}{}",
+            json_string(&result.file.to_string_lossy()),
+            result.mapper.map_or("null".to_string(), |mapper| mapper.to_string()),
+            result.support,
+            result.booted,
+            result.hung,
+            result.fault.as_deref().map_or("null".to_string(), json_string),
+            result.fps.map_or("null".to_string(), |fps| format!("{:.1}", fps)),
+            if index + 1 < results.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(writer, "]")?;
+    return Ok(());
+}
+
+/// Runs every ROM in a directory without a window for a number of frames, and writes a compatibility matrix of their
+/// mappers, whether they booted, hung or faulted, and how fast they ran, either to a file or to standard output
+pub fn compat(directory: &Path, frames: u64, format: &str, output: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let roms = find_roms(directory)?;
+    // The panics are recorded as faults, so the default hook printing them would only clutter the output
+    let panic_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let results: Vec<CompatibilityResult> = roms.iter().map(|file| check_compatibility(file, frames)).collect();
+    std::panic::set_hook(panic_hook);
+
+    let mut writer: Box<dyn Write> = match output {
+        Some(output) => Box::new(BufWriter::new(File::create(output)?)),
+        None => Box::new(std::io::stdout()),
+    };
+    match format {
+        "csv" => write_compatibility_csv(&mut writer, &results)?,
+        "json" => write_compatibility_json(&mut writer, &results)?,
+        _ => return Err(format!("Unknown compatibility matrix format: {}", format).into()),
+    }
+    writer.flush()?;
+    if let Some(output) = output {
+        let booted = results.iter().filter(|result| result.booted).count();
+        let hung = results.iter().filter(|result| result.hung).count();
+        let faulted = results.iter().filter(|result| result.fault.is_some()).count();
+        println!(
+            "Wrote {} ROMs to {}: {} booted, {} hung, {} faulted",
+            results.len(),
+            output.display(),
+            booted,
+            hung,
+            faulted
+        );
+    }
+    return Ok(());
+}
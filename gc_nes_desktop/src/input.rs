@@ -0,0 +1,175 @@
+//! The input module maps the keyboard and any connected gamepads to the two NES controllers.
+//! Each player is bound to a list of input sources, whose button states are combined.
+
+use gc_nes_core::input::buttons::{self, ButtonStateBuilder};
+use minifb::{Key, Window};
+use std::str::FromStr;
+
+/// The keys that make up one of the two keyboard layouts, in the order A, B, Select, Start, Up, Down, Left, Right
+type KeyboardLayout = [Key; 8];
+
+/// The primary keyboard layout, with the D-pad on WASD
+const KEYBOARD_LAYOUT_ONE: KeyboardLayout = [Key::Space, Key::LeftShift, Key::Y, Key::T, Key::W, Key::S, Key::A, Key::D];
+/// The secondary keyboard layout, with the D-pad on the arrow keys
+const KEYBOARD_LAYOUT_TWO: KeyboardLayout = [Key::L, Key::K, Key::RightShift, Key::Enter, Key::Up, Key::Down, Key::Left, Key::Right];
+/// The NES buttons in the same order as the keys of a keyboard layout
+const LAYOUT_BUTTONS: [u8; 8] = [
+    buttons::A,
+    buttons::B,
+    buttons::SELECT,
+    buttons::START,
+    buttons::UP,
+    buttons::DOWN,
+    buttons::LEFT,
+    buttons::RIGHT,
+];
+
+/// A single source of input that a player can be bound to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputSource {
+    /// One of the keyboard layouts, selected with "keyboard" and "keyboard2"
+    Keyboard(&'static KeyboardLayout),
+    /// A gamepad, identified by its index in the order gamepads were connected, selected with "gamepad0", "gamepad1", etc.
+    Gamepad(usize),
+}
+
+/// The input sources bound to a single player. A player with no sources is treated as a disconnected controller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerBinding {
+    sources: Vec<InputSource>,
+}
+
+impl PlayerBinding {
+    /// Checks if the binding uses any gamepads
+    pub fn uses_gamepads(&self) -> bool {
+        return self.sources.iter().any(|source| matches!(source, InputSource::Gamepad(_)));
+    }
+
+    /// Gets the state of the bound controller as an input state byte, or None if nothing is bound
+    pub fn get_state(&self, window: &Window, gamepads: &Gamepads) -> Option<u8> {
+        if self.sources.is_empty() {
+            return None;
+        }
+        let state = self.sources.iter().fold(0x00, |state, source| {
+            state
+                | match source {
+                    InputSource::Keyboard(layout) => get_keyboard_state(window, layout),
+                    InputSource::Gamepad(index) => gamepads.get_state(*index),
+                }
+        });
+        return Some(state);
+    }
+}
+
+impl FromStr for PlayerBinding {
+    type Err = String;
+
+    /// Parses a binding profile, either "none" or a comma separated list of input
+    /// sources like "keyboard", "keyboard2" or "keyboard,gamepad0".
+    fn from_str(profile: &str) -> Result<Self, Self::Err> {
+        if profile == "none" {
+            return Ok(PlayerBinding { sources: Vec::new() });
+        }
+        let sources = profile
+            .split(',')
+            .map(|source| match source.trim() {
+                "keyboard" => Ok(InputSource::Keyboard(&KEYBOARD_LAYOUT_ONE)),
+                "keyboard2" => Ok(InputSource::Keyboard(&KEYBOARD_LAYOUT_TWO)),
+                gamepad if gamepad.starts_with("gamepad") => gamepad["gamepad".len()..]
+                    .parse()
+                    .map(InputSource::Gamepad)
+                    .map_err(|_| format!("Invalid gamepad index in input source: {}", gamepad)),
+                other => Err(format!("Unknown input source: {}", other)),
+            })
+            .collect::<Result<Vec<InputSource>, String>>()?;
+        return Ok(PlayerBinding { sources });
+    }
+}
+
+/// Gets the state of one of the keyboard layouts as an input state byte
+fn get_keyboard_state(window: &Window, layout: &KeyboardLayout) -> u8 {
+    return layout
+        .iter()
+        .zip(LAYOUT_BUTTONS.iter())
+        .fold(ButtonStateBuilder::new(), |builder, (key, button)| {
+            builder.set(*button, window.is_key_down(*key))
+        })
+        .build();
+}
+
+/// Wrapper around the gamepad library, which is only available when gc_nes_desktop is built with the gamepad feature
+pub struct Gamepads {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+#[cfg(feature = "gamepad")]
+impl Gamepads {
+    /// Connects to the gamepads, logging a warning if they are unavailable
+    pub fn new() -> Self {
+        let gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(error) => {
+                warn!("Unable to access gamepads: {}", error);
+                None
+            }
+        };
+        return Gamepads { gilrs };
+    }
+
+    /// Processes the pending gamepad events so that button states are up to date.
+    /// Returns a description of each gamepad that was connected or disconnected, for showing to the players.
+    pub fn update(&mut self) -> Vec<String> {
+        let mut connections = Vec::new();
+        if let Some(gilrs) = &mut self.gilrs {
+            while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+                let change = match event {
+                    gilrs::EventType::Connected => "connected",
+                    gilrs::EventType::Disconnected => "disconnected",
+                    _ => continue,
+                };
+                connections.push(format!("{} {}", gilrs.gamepad(id).name(), change));
+            }
+        }
+        return connections;
+    }
+
+    /// Gets the state of the gamepad at the index as an input state byte. Disconnected gamepads have no buttons pressed.
+    fn get_state(&self, index: usize) -> u8 {
+        use gilrs::Button;
+
+        let gamepad = match self.gilrs.as_ref().and_then(|gilrs| gilrs.gamepads().nth(index)) {
+            Some((_, gamepad)) => gamepad,
+            None => return 0x00,
+        };
+        // The NES' B button is to the left of the A button, so they map to the bottom and right face buttons
+        return ButtonStateBuilder::new()
+            .set(buttons::A, gamepad.is_pressed(Button::East))
+            .set(buttons::B, gamepad.is_pressed(Button::South))
+            .set(buttons::SELECT, gamepad.is_pressed(Button::Select))
+            .set(buttons::START, gamepad.is_pressed(Button::Start))
+            .set(buttons::UP, gamepad.is_pressed(Button::DPadUp))
+            .set(buttons::DOWN, gamepad.is_pressed(Button::DPadDown))
+            .set(buttons::LEFT, gamepad.is_pressed(Button::DPadLeft))
+            .set(buttons::RIGHT, gamepad.is_pressed(Button::DPadRight))
+            .build();
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+impl Gamepads {
+    /// Creates the gamepad wrapper. Without the gamepad feature, no gamepads are ever connected.
+    pub fn new() -> Self {
+        return Gamepads {};
+    }
+
+    /// Never reports any gamepads being connected or disconnected without the gamepad feature
+    pub fn update(&mut self) -> Vec<String> {
+        return Vec::new();
+    }
+
+    /// Always returns a state with no buttons pressed without the gamepad feature
+    fn get_state(&self, _index: usize) -> u8 {
+        return 0x00;
+    }
+}
@@ -7,36 +7,165 @@
 //! ### Running a ROM
 //! Launch gc_nes_desktop from the commandline like so:
 //!
-//! `gc_nes_desktop.exe --scale 4 SomeNesRom.nes`
+//! `gc_nes_desktop.exe run --scale 4 SomeNesRom.nes`
 //!
+//! Pass `--accuracy speed` to skip expensive emulation details that few games depend on, which helps on slow
+//! machines, or `--accuracy accuracy` to emulate everything the core knows about. The default is `balanced`.
+//! Pass `--blend-frames` to blend each frame with the last, like a CRT, which smooths out the flicker of games
+//! that draw sprites on alternate frames to make them look transparent.
+//! Pass `--mirroring vertical` (or `horizontal`, `lower` or `upper`) to force the nametable mirroring of ROMs
+//! whose headers declare the wrong one, which shows up as garbled backgrounds while the screen scrolls.
+//! Pass `--palette SomePalette.pal` to draw the screen with the colours from a 192 byte palette file, like the ones
+//! that most NES emulators and palette generators make, instead of the emulator's own palette.
+//! Pass `--frame-skip` on slow machines to skip drawing frames whenever the emulator falls behind, which keeps
+//! the game running at full speed while the screen updates less often.
+//!
+//! ### Audio
+//! Audio is only played when gc_nes_desktop is built with the `audio` feature, which requires ALSA on Linux.
+//! If the sound crackles on a slow machine, pass `--audio-latency 200` to let more audio wait to be played
+//! (the default is 100 milliseconds), or `--audio-buffer 2048` to ask the audio device to play in larger
+//! buffers. Both trade latency for fewer underruns, so the sound lags further behind the picture.
+//!
+//! ### Game Settings
+//! Settings for a single game can be kept in a file next to the ROM with the same name and a `.cfg` extension,
+//! like `SomeNesRom.cfg`, so that they don't need to be passed every time. Each line sets one option as
+//! `name = value`, and lines starting with `#` are comments. Options passed on the command line take precedence.
+//! * `mirroring = vertical` forces the nametable mirroring, like `--mirroring`
+//!
+//! ### Other Commands
+//! * `record SomeNesRom.nes movie.fm2` runs the ROM like `run`, and writes the input to an FM2 movie when the window is closed
+//! * `info SomeNesRom.nes` prints the ROM's header information, mapper support, and MD5 hash
+//! * `verify SomeNesRom.nes movie.fm2` plays an FM2 movie back without a window, checking that it was recorded
+//!   with the same ROM and printing a hash of the final frame
+//! * `bench SomeNesRom.nes --frames 600` runs the ROM without a window as fast as possible and reports the speed
+//! * `thumbnail SomeNesRom.nes thumbnail.png --frame 600 --movie movie.fm2` renders a single frame of the ROM to a PNG
+//!   without a window, played with the input from an FM2 movie if one is given
+//! * `trace SomeNesRom.nes trace.jsonl --frames 10 --format jsonl` writes a trace of every instruction, memory access,
+//!   interrupt and frame of the ROM's execution, either as a text log like nestest's or as JSON Lines for analysis tools
+//! * `test-rom apu_test/rom_singles/*.nes --frames 3600` runs test ROMs that report their results like blargg's suites
+//!   without a window, pressing reset when they ask for it, and prints which passed. Exits with an error if any didn't.
+//! * `compat roms/ --frames 600 --format csv --output matrix.csv` runs every ROM in a directory without a window and
+//!   writes a compatibility matrix with each ROM's mapper, whether it booted, hung or faulted, and the speed it ran
+//!   at, as CSV or JSON. A ROM counts as booted if its last frame has more than one colour in it, as hung if it went
+//!   two seconds without reading PPUSTATUS, latching the controllers or changing RAM, and as faulted if it couldn't
+//!   be loaded or the emulator panicked while running it.
+//!
+//! Run `gc_nes_desktop.exe help <command>` for all of the options of each command.
 //!
 //! ### Controls
-//! gc_nes_desktop maps the NES input to the following keys:
-//! * D-pad to WASD
-//! * Start to T
-//! * Select to Y
-//! * A to Space
-//! * B to Left Shift
+//! Each player can be bound to any mix of the two keyboard layouts and connected gamepads
+//! with the `--p1` and `--p2` options, which take `none` or a comma separated list of
+//! `keyboard`, `keyboard2`, and `gamepad0`, `gamepad1`, etc. By default, player one uses the
+//! first keyboard layout and player two is disconnected. To play with a friend on the
+//! keyboard while also using a gamepad:
+//!
+//! `gc_nes_desktop.exe run --p1 keyboard,gamepad0 --p2 keyboard2 SomeNesRom.nes`
+//!
+//! | NES    | `keyboard`  | `keyboard2`   | Gamepad                   |
+//! |--------|-------------|---------------|---------------------------|
+//! | D-pad  | WASD        | Arrow Keys    | D-pad                     |
+//! | Start  | T           | Enter         | Start                     |
+//! | Select | Y           | Right Shift   | Select                    |
+//! | A      | Space       | L             | Right face button         |
+//! | B      | Left Shift  | K             | Bottom face button        |
+//!
+//! Gamepads are only available when gc_nes_desktop is built with the `gamepad` feature,
+//! which requires libudev on Linux.
+//!
+//! ### TAS Editor
+//! Launching with `--tas` starts the experimental TAS editor, which pauses the NES on a timeline
+//! of frames and shows the input for the frames around the current one in a piano roll below the
+//! screen. Frames that can be returned to instantly (the greenzone) are shaded green.
+//! * Left and Right step back and forward one frame
+//! * 1 to 8 toggle A, B, Select, Start, Up, Down, Left and Right on the current frame
+//! * Tab switches between editing the first and second controller
+//! * Enter plays or pauses the movie, recording the bound controllers when playing past its end
+//!
+//! Changing the input of a frame that later frames were played with counts as a rerecord.
+//!
+//! ### Recording Clips
+//! Press F9 while running a ROM to start recording a clip, and F9 again to save it next to the ROM as an
+//! animated GIF. Pass `--clip-format apng` to save clips as animated PNGs at the full 60 frames per second instead.
+//!
+//! ### Save States
+//! Press F5 while running a ROM to save its state, and F7 to load it again. The state is kept until the window is
+//! closed, and can't be loaded while recording a movie. Saving and loading states, and connecting and
+//! disconnecting gamepads, are shown as messages in the bottom left corner of the screen.
+
+#![allow(clippy::needless_return)] // I prefer clarity of return
 
+mod audio;
+mod commands;
+mod input;
+mod settings;
+mod tas;
+mod timing;
+
+use crate::audio::AudioOutput;
+use crate::input::{Gamepads, PlayerBinding};
+use crate::settings::GameSettings;
 use crate::structopt::StructOpt;
-use gc_nes_core::cartridge::Cartridge;
-use gc_nes_core::nes::Nes;
-use minifb::{Key, Scale, Window, WindowOptions};
+use crate::tas::TasEditor;
+use crate::timing::{FramePacer, LoopClock, HOST_REFRESH_RATE, MAX_SKIPPED_REFRESHES};
+use gc_nes_core::cartridge::{calculate_rom_md5, Cartridge, CartridgeInfo, MapperSupport, Mirroring};
+use gc_nes_core::movie::{FrameInput, Movie};
+use gc_nes_core::nes::{AccuracyPreset, Nes};
+use gc_nes_core::video::postprocess::{read_palette, FrameBlender};
+use gc_nes_core::video::recorder::{ClipFormat, ClipRecorder};
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
 use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long the messages shown on the screen, such as a state being saved, stay there
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
 
 #[macro_use]
 extern crate log;
 extern crate gc_nes_core;
 extern crate structopt;
 
-const FRAME_DURATION: Duration = Duration::from_millis(16);
-
 fn main() {
-    let arguments = Arguments::from_args();
+    let command = Command::from_args();
     std::env::set_var("RUST_LOG", "gc_nes_core::cartridge::mapper=debug,gc_nes_core::cartridge=trace");
     env_logger::init();
 
+    let result = match command {
+        Command::Run(arguments) => run_in_window(&arguments, None),
+        Command::Record { run, movie } => run_in_window(&run, Some(&movie)),
+        Command::Info { file } => commands::info(&file),
+        Command::Verify { file, movie } => commands::verify(&file, &movie),
+        Command::Bench { file, frames } => commands::bench(&file, frames),
+        Command::Thumbnail { file, output, frame, movie } => commands::thumbnail(&file, &output, frame, movie.as_deref()),
+        Command::Trace {
+            file,
+            output,
+            frames,
+            format,
+        } => commands::trace(&file, &output, frames, &format),
+        Command::TestRom { files, frames } => commands::test_roms(&files, frames),
+        Command::Compat {
+            directory,
+            frames,
+            format,
+            output,
+        } => commands::compat(&directory, frames, &format, output.as_deref()),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Opens a window and runs the NES in it, either normally or in the TAS editor.
+/// If a movie path is provided, the input is written to it as an FM2 movie when the window is closed.
+fn run_in_window(arguments: &RunArguments, movie_path: Option<&PathBuf>) -> Result<(), Box<dyn Error>> {
+    if !check_mapper_support(arguments)? {
+        std::process::exit(1);
+    }
+
     let scale = match arguments.scale {
         1 => Scale::X1,
         2 => Scale::X2,
@@ -47,13 +176,14 @@ fn main() {
         _ => Scale::X2,
     };
 
+    // The TAS editor draws its piano roll below the screen
+    let window_height = if arguments.tas { 240 + tas::PIANO_ROLL_HEIGHT } else { 240 };
     let mut window = Window::new(
         format!("gc_nes_emulator v{}", env!("CARGO_PKG_VERSION")).as_ref(),
         256,
-        240,
+        window_height,
         WindowOptions { scale, ..Default::default() },
-    )
-    .expect("Error opening window");
+    )?;
 
     info!(
         "Starting {} by {}, version {}...",
@@ -61,24 +191,316 @@ fn main() {
         env!("CARGO_PKG_AUTHORS"),
         env!("CARGO_PKG_VERSION")
     );
-    let cartridge = Cartridge::load_from_file(&arguments.file).expect("File read error"); // TODO: Present a message to the user instead of crashing
+    let settings = GameSettings::load_for_rom(&arguments.file)?;
+    let cartridge = Cartridge::load_from_file(&arguments.file)?;
     let mut nes = Nes::new(cartridge);
-    let buffer = nes.frame();
-    window.update_with_buffer(buffer).expect("Error updating frame buffer");
+    nes.override_mirroring(arguments.mirroring.as_deref().and_then(Mirroring::from_name).or(settings.mirroring));
+    nes.set_speed(arguments.speed);
+    if let Some(preset) = AccuracyPreset::from_name(&arguments.accuracy) {
+        nes.set_accuracy(preset.into());
+    }
+    // The TAS editor and clips use the frames drawn to the video backend, while the screen is drawn through the post-processing chain
+    nes.get_video_backend_mut().set_frame_blending(arguments.blend_frames);
+    nes.get_messages_mut().set_rendering(true);
+    if arguments.blend_frames {
+        nes.get_post_processing_mut().push(Box::new(FrameBlender::default()));
+    }
+    if let Some(palette_path) = &arguments.palette {
+        nes.get_post_processing_mut().set_palette(read_palette(&std::fs::read(palette_path)?)?);
+    }
 
+    let mut gamepads = Gamepads::new();
+    if (arguments.player_one.uses_gamepads() || arguments.player_two.uses_gamepads()) && !cfg!(feature = "gamepad") {
+        warn!("Gamepads are bound, but gc_nes_desktop was built without the gamepad feature");
+    }
+
+    let movie = if arguments.tas {
+        Some(run_tas_editor(&mut window, nes, new_movie(arguments)?, arguments, &mut gamepads))
+    } else {
+        // The input is only kept in a movie when it is being recorded
+        let mut movie = match movie_path {
+            Some(_) => Some(new_movie(arguments)?),
+            None => None,
+        };
+        let audio = AudioOutput::new(arguments.audio_latency, arguments.audio_buffer);
+        if let Some(sample_rate) = audio.get_sample_rate() {
+            nes.set_audio_sample_rate(sample_rate);
+            nes.set_analog_filters(true);
+        }
+        run(&mut window, nes, movie.as_mut(), arguments, &mut gamepads, &audio);
+        movie
+    };
+
+    if let (Some(movie_path), Some(movie)) = (movie_path, movie) {
+        let rom_filename = arguments.file.file_name().unwrap_or_default().to_string_lossy();
+        movie.write_fm2(BufWriter::new(File::create(movie_path)?), &rom_filename)?;
+        println!("Wrote {} frames to {}", movie.len(), movie_path.display());
+    }
+    return Ok(());
+}
+
+/// Creates an empty movie for the ROM being run, with the ROM's checksum so that it can be verified against it later
+fn new_movie(arguments: &RunArguments) -> Result<Movie, Box<dyn Error>> {
+    let mut movie = Movie::new();
+    movie.set_rom_md5(Some(calculate_rom_md5(BufReader::new(File::open(&arguments.file)?))?));
+    return Ok(movie);
+}
+
+/// Reads the cartridge's header and tells the user which mapper it uses and how well it is supported,
+/// before the window opens. Returns whether the cartridge should be run, asking the user first if
+/// the mapper is only partially supported, and refusing Vs. DualSystem games.
+fn check_mapper_support(arguments: &RunArguments) -> Result<bool, Box<dyn Error>> {
+    let info = CartridgeInfo::read_from_file(&arguments.file)?;
+    if info.vs_dual_system {
+        // The second console and screen would need a second NES in the core, with the two sharing memory
+        eprintln!("This is a Vs. DualSystem game, which needs two linked consoles that can't be emulated yet.");
+        return Ok(false);
+    }
+    return Ok(match info.mapper_support {
+        MapperSupport::Full => {
+            info!("Cartridge uses mapper {:03}, which is fully supported", info.mapper_id);
+            true
+        }
+        MapperSupport::Partial { notes } => {
+            println!("This cartridge uses mapper {:03}, which is only partially supported:", info.mapper_id);
+            println!("{}", notes);
+            println!("Some games using this mapper may not run correctly.");
+            arguments.yes || prompt_continue()
+        }
+        MapperSupport::Unsupported => {
+            eprintln!("This cartridge uses mapper {:03}, which is not supported.", info.mapper_id);
+            eprintln!("Supported mappers: {:03?}", gc_nes_core::capabilities().supported_mappers);
+            false
+        }
+    });
+}
+
+/// Asks the user on the command line whether to continue, returning true if they answer yes
+fn prompt_continue() -> bool {
+    print!("Continue anyway? [y/N] ");
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    return std::io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y");
+}
+
+/// Runs the NES normally, with input from the players' bindings and its audio queued on the audio output.
+/// The loop is clocked by the audio output when it has a device, and by the host refresh rate otherwise.
+/// The input for each frame is recorded in the movie, if there is one.
+fn run(window: &mut Window, mut nes: Nes, mut movie: Option<&mut Movie>, arguments: &RunArguments, gamepads: &mut Gamepads, audio: &AudioOutput) {
+    let mut clock = LoopClock::new(audio);
+    let mut clip_recorder: Option<ClipRecorder> = None;
+    let mut skipped_refreshes = 0;
+    let mut saved_state: Option<Nes> = None;
     while window.is_open() {
-        let timer = Instant::now();
-        nes.update_controller_one(Some(get_controller_one_state(&window)));
-        window.update_with_buffer(nes.frame()).expect("Error updating frame buffer");
-        // This isn't exactly the most portable way of timing the frames but it will do for now
-        if let Some(duration) = FRAME_DURATION.checked_sub(timer.elapsed()) {
-            std::thread::sleep(duration)
+        if window.is_key_pressed(Key::F5, KeyRepeat::No) {
+            saved_state = Some(nes.clone());
+            nes.post_message("State saved", MESSAGE_DURATION);
+        }
+        if window.is_key_pressed(Key::F7, KeyRepeat::No) {
+            match &saved_state {
+                // The recorded input would no longer match the frames it was played on
+                Some(_) if movie.is_some() => nes.post_message("States can't be loaded while recording", MESSAGE_DURATION),
+                Some(state) => {
+                    nes = state.clone();
+                    nes.post_message("State loaded", MESSAGE_DURATION);
+                }
+                None => nes.post_message("No state saved", MESSAGE_DURATION),
+            }
+        }
+        if window.is_key_pressed(Key::F9, KeyRepeat::No) {
+            clip_recorder = match clip_recorder.take() {
+                Some(recorder) => {
+                    save_clip(&recorder, arguments);
+                    None
+                }
+                None => {
+                    println!("Started recording a clip, press F9 again to save it");
+                    Some(ClipRecorder::new())
+                }
+            };
+        }
+        for connection in gamepads.update() {
+            nes.post_message(&connection, MESSAGE_DURATION);
+        }
+        let input = FrameInput {
+            controller_one: arguments.player_one.get_state(window, gamepads),
+            controller_two: arguments.player_two.get_state(window, gamepads),
+        };
+        // Refreshes that are already late are run without drawing, which keeps the game running at full speed while
+        // the screen updates less often. Clips need every frame, so nothing is skipped while one is recording.
+        let skip = arguments.frame_skip && clip_recorder.is_none() && clock.is_behind() && skipped_refreshes < MAX_SKIPPED_REFRESHES;
+        for _ in 0..clock.frames_for_refresh(&mut nes) {
+            // The remapped input is recorded, since movies are played back without the remaps
+            let input = nes.remap_frame_input(input);
+            nes.update_frame_input(input);
+            if let Some(movie) = &mut movie {
+                movie.set_input(movie.len(), input);
+            }
+            if skip {
+                nes.skip_frame();
+                continue;
+            }
+            nes.frame();
+            if let Some(recorder) = &mut clip_recorder {
+                recorder.add_frame(nes.get_video_backend()).expect("Error recording clip frame");
+            }
         }
+        audio.queue(&nes.take_audio_samples());
+        if skip {
+            skipped_refreshes += 1;
+            window.update();
+        } else {
+            skipped_refreshes = 0;
+            let image = nes.post_process_frame();
+            let buffer: Vec<u32> = image
+                .pixels
+                .chunks_exact(4)
+                .map(|pixel| u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]]))
+                .collect();
+            window.update_with_buffer(&buffer).expect("Error updating frame buffer");
+        }
+        clock.wait();
+    }
+    if let Some(recorder) = clip_recorder {
+        save_clip(&recorder, arguments);
+    }
+}
+
+/// Encodes a recorded clip in the format selected by the arguments and saves it next to the ROM,
+/// named after the ROM and the time it was saved
+fn save_clip(recorder: &ClipRecorder, arguments: &RunArguments) {
+    let (format, extension) = match arguments.clip_format.as_str() {
+        "apng" => (ClipFormat::Apng, "png"),
+        _ => (ClipFormat::Gif, "gif"),
+    };
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let file_stem = arguments.file.file_stem().unwrap_or_default().to_string_lossy();
+    let path = arguments.file.with_file_name(format!("{}-{}.{}", file_stem, timestamp, extension));
+    match recorder.encode(format).and_then(|clip| Ok(std::fs::write(&path, clip)?)) {
+        Ok(()) => println!("Saved {} frame clip to {}", recorder.len(), path.display()),
+        Err(error) => eprintln!("Failed to save clip: {}", error),
     }
 }
 
+/// Runs the NES in the TAS editor, which records the players' bindings while playing past the end of the movie.
+/// Returns the edited movie once the window is closed.
+fn run_tas_editor(window: &mut Window, nes: Nes, movie: Movie, arguments: &RunArguments, gamepads: &mut Gamepads) -> Movie {
+    let mut editor = TasEditor::new(nes, movie);
+    let mut buffer = vec![0; 256 * (240 + tas::PIANO_ROLL_HEIGHT)];
+    let mut pacer = FramePacer::new(HOST_REFRESH_RATE);
+    while window.is_open() {
+        gamepads.update();
+        editor.handle_input(window);
+        editor.update(FrameInput {
+            controller_one: arguments.player_one.get_state(window, gamepads),
+            controller_two: arguments.player_two.get_state(window, gamepads),
+        });
+        editor.draw(&mut buffer);
+        window.set_title(&editor.get_title());
+        window.update_with_buffer(&buffer).expect("Error updating frame buffer");
+        pacer.wait();
+    }
+    return editor.into_movie();
+}
+
+/// The commands of gc_nes_desktop
 #[derive(StructOpt, Debug)]
-pub struct Arguments {
+#[structopt(name = "gc_nes_desktop")]
+pub enum Command {
+    /// Runs a NES ROM in a window
+    Run(RunArguments),
+    /// Runs a NES ROM in a window, writing the input to an FM2 movie when the window is closed
+    Record {
+        #[structopt(flatten)]
+        run: RunArguments,
+        /// The path that the FM2 movie will be written to
+        #[structopt(parse(from_os_str))]
+        movie: PathBuf,
+    },
+    /// Prints the header information, mapper support, and MD5 hash of a NES ROM
+    Info {
+        /// The path to the .nes file
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Plays an FM2 movie back without a window, printing whether it was recorded with the ROM and a hash of the final frame
+    Verify {
+        /// The path to the .nes file
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// The path to the FM2 movie
+        #[structopt(parse(from_os_str))]
+        movie: PathBuf,
+    },
+    /// Runs a NES ROM without a window as fast as possible and reports the speed
+    Bench {
+        /// The path to the .nes file
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// The number of frames to run
+        #[structopt(long = "frames", default_value = "600")]
+        frames: u64,
+    },
+    /// Renders a single frame of a NES ROM to a PNG without a window, optionally played with an FM2 movie
+    Thumbnail {
+        /// The path to the .nes file
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// The path that the PNG will be written to
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// The number of frames to run before taking the image
+        #[structopt(long = "frame", default_value = "600")]
+        frame: usize,
+        /// The path to an FM2 movie to provide the input
+        #[structopt(long = "movie", parse(from_os_str))]
+        movie: Option<PathBuf>,
+    },
+    /// Runs a NES ROM without a window and writes a trace of the CPU's execution to a file
+    Trace {
+        /// The path to the .nes file
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// The path that the trace will be written to
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// The number of frames to trace
+        #[structopt(long = "frames", default_value = "1")]
+        frames: u64,
+        /// The format to write the trace in: "text", in the style of the nestest log, or "jsonl", one JSON object per line
+        #[structopt(long = "format", default_value = "text", possible_values = &["text", "jsonl"])]
+        format: String,
+    },
+    /// Runs test ROMs that report their results like blargg's suites without a window and prints which passed
+    TestRom {
+        /// The paths to the .nes files
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+        /// The number of frames to give each ROM to finish before it fails
+        #[structopt(long = "frames", default_value = "3600")]
+        frames: u64,
+    },
+    /// Runs every NES ROM in a directory without a window and writes a compatibility matrix of the results
+    Compat {
+        /// The directory to search for .nes files, including the directories inside it
+        #[structopt(parse(from_os_str))]
+        directory: PathBuf,
+        /// The number of frames to run each ROM for
+        #[structopt(long = "frames", default_value = "600")]
+        frames: u64,
+        /// The format to write the matrix in: "csv" or "json"
+        #[structopt(long = "format", default_value = "csv", possible_values = &["csv", "json"])]
+        format: String,
+        /// The path that the matrix will be written to, instead of standard output
+        #[structopt(long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+}
+
+// The options for running a NES ROM in a window. This isn't a doc comment because
+// structopt would use it as the description of the commands that flatten it.
+#[derive(StructOpt, Debug)]
+pub struct RunArguments {
     /// The Path to the .nes file that the NES ROM will be loaded from
     #[structopt(parse(from_os_str))]
     file: PathBuf,
@@ -86,19 +508,47 @@ pub struct Arguments {
     /// resolution of 256x240 (In powers of two)
     #[structopt(short = "s", long = "scale", default_value = "2")]
     scale: u8,
-}
-
-/// Get the state of controller one as a input state byte
-#[allow(clippy::needless_return)]
-fn get_controller_one_state(window: &Window) -> u8 {
-    // Get the appropriate controller state byte from the keys
-    // TODO: Make these re-bindable
-    return (window.is_key_down(Key::Space) as u8) |           // A
-        (window.is_key_down(Key::LeftShift) as u8) << 1 |  // B
-        (window.is_key_down(Key::Y) as u8) << 2 |      // Select
-        (window.is_key_down(Key::T) as u8) << 3 |     // Start
-        (window.is_key_down(Key::W) as u8) << 4 |          // Up
-        (window.is_key_down(Key::S) as u8) << 5 |          // Down
-        (window.is_key_down(Key::A) as u8) << 6 |          // Left
-        (window.is_key_down(Key::D) as u8) << 7; // Right
+    /// The speed multiplier to run the emulator at, where 1.0 is normal speed
+    #[structopt(long = "speed", default_value = "1.0")]
+    speed: f32,
+    /// The input sources bound to player one: "none" or a comma separated list
+    /// of "keyboard", "keyboard2", and "gamepad0", "gamepad1", etc.
+    #[structopt(long = "p1", default_value = "keyboard")]
+    player_one: PlayerBinding,
+    /// The input sources bound to player two, in the same format as player one
+    #[structopt(long = "p2", default_value = "none")]
+    player_two: PlayerBinding,
+    /// Starts the experimental TAS editor, which pauses the NES on a timeline of frames whose input can be edited
+    #[structopt(long = "tas")]
+    tas: bool,
+    /// The accuracy preset to run the emulator with: "accuracy", "balanced" or "speed"
+    #[structopt(long = "accuracy", default_value = "balanced", possible_values = &["accuracy", "balanced", "speed"])]
+    accuracy: String,
+    /// The format that clips recorded with F9 are saved in: "gif" or "apng"
+    #[structopt(long = "clip-format", default_value = "gif", possible_values = &["gif", "apng"])]
+    clip_format: String,
+    /// Runs cartridges with partially supported mappers without asking first
+    #[structopt(short = "y", long = "yes")]
+    yes: bool,
+    /// Blends each frame with the last, which smooths out the flicker that some games use for transparency
+    #[structopt(long = "blend-frames")]
+    blend_frames: bool,
+    /// Forces the nametables to use a mirroring mode, for ROMs with the wrong mirroring in their header:
+    /// "horizontal", "vertical", "lower" or "upper"
+    #[structopt(long = "mirroring", possible_values = &["horizontal", "vertical", "lower", "upper"])]
+    mirroring: Option<String>,
+    /// Skips drawing frames when the host falls behind, keeping the game at full speed while the screen updates less often
+    #[structopt(long = "frame-skip")]
+    frame_skip: bool,
+    /// The most audio that can be waiting to be played, in milliseconds. Raising it prevents crackling on slow
+    /// machines, at the cost of the sound lagging further behind the picture
+    #[structopt(long = "audio-latency", default_value = "100")]
+    audio_latency: u32,
+    /// The size in frames of the buffers that the audio device is asked to play in, instead of its default.
+    /// Larger buffers are less likely to underrun, but add to the latency
+    #[structopt(long = "audio-buffer")]
+    audio_buffer: Option<u32>,
+    /// A .pal file with the colours to draw the screen with, instead of the emulator's own palette
+    #[structopt(long = "palette", parse(from_os_str))]
+    palette: Option<PathBuf>,
 }
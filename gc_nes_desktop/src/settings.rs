@@ -0,0 +1,53 @@
+//! The settings module contains the per-game settings of gc_nes_desktop, which are read from a file
+//! next to the ROM with the same name and a .cfg extension. Each line of the file sets one option as
+//! `name = value`, and lines starting with `#` are comments:
+//!
+//! ```text
+//! # The header of this dump declares the wrong mirroring
+//! mirroring = vertical
+//! ```
+//!
+//! Options passed on the command line take precedence over the settings file.
+
+use gc_nes_core::cartridge::Mirroring;
+use std::error::Error;
+use std::path::Path;
+
+/// The settings for a single game
+#[derive(Debug, Default)]
+pub struct GameSettings {
+    /// The mirroring mode that the nametables are forced to use, for ROMs with the wrong mirroring in their header
+    pub mirroring: Option<Mirroring>,
+}
+
+impl GameSettings {
+    /// Reads the settings for the ROM at a path from its settings file, or returns the default settings if it doesn't have one
+    pub fn load_for_rom(rom_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let path = rom_path.with_extension("cfg");
+        if !path.exists() {
+            return Ok(GameSettings::default());
+        }
+        info!("Loading game settings from {}", path.display());
+        let mut settings = GameSettings::default();
+        for (number, line) in std::fs::read_to_string(&path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => return Err(format!("Expected `name = value` on line {} of {}", number + 1, path.display()).into()),
+            };
+            match name {
+                "mirroring" => {
+                    settings.mirroring = match Mirroring::from_name(value) {
+                        Some(mirroring) => Some(mirroring),
+                        None => return Err(format!("Unknown mirroring mode on line {} of {}: {}", number + 1, path.display(), value).into()),
+                    }
+                }
+                _ => warn!("Ignoring unknown setting on line {} of {}: {}", number + 1, path.display(), name),
+            }
+        }
+        return Ok(settings);
+    }
+}
@@ -0,0 +1,192 @@
+//! The tas module contains the experimental TAS editor mode, which pauses the emulator
+//! on a timeline of frames whose input can be toggled one button at a time. The input
+//! for the frames around the current one is shown in a piano roll below the screen.
+
+use gc_nes_core::input::buttons;
+use gc_nes_core::movie::{FrameInput, Greenzone, Movie};
+use gc_nes_core::nes::Nes;
+use gc_nes_core::video::{ArgbFrameBuffer, VideoBackend, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
+use minifb::{Key, KeyRepeat, Window};
+
+/// The size of a single cell of the piano roll in pixels
+const CELL_SIZE: usize = 8;
+/// The number of frames shown in the piano roll
+const PIANO_ROLL_FRAMES: usize = NES_SCREEN_WIDTH / CELL_SIZE;
+/// The number of frames shown in the piano roll before the current frame
+const PIANO_ROLL_FRAMES_BEFORE: usize = 8;
+/// The height of the piano roll in pixels, with a row for each button
+pub const PIANO_ROLL_HEIGHT: usize = CELL_SIZE * 8;
+/// The number of frames between greenzone snapshots
+const GREENZONE_INTERVAL: usize = 10;
+
+/// The keys that toggle each button of the piano roll, in the same order as the rows
+const TOGGLE_KEYS: [Key; 8] = [Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8];
+/// The buttons represented by each row of the piano roll
+const ROW_BUTTONS: [u8; 8] = [
+    buttons::A,
+    buttons::B,
+    buttons::SELECT,
+    buttons::START,
+    buttons::UP,
+    buttons::DOWN,
+    buttons::LEFT,
+    buttons::RIGHT,
+];
+/// The colours of pressed buttons in each row of the piano roll
+const ROW_COLOURS: [u32; 8] = [
+    0x00e04040, 0x00e0a040, 0x00a0a0a0, 0x00f0f0f0, 0x0040a0e0, 0x0040a0e0, 0x0040a0e0, 0x0040a0e0,
+];
+/// The background colour of frames that are in the greenzone
+const GREENZONE_COLOUR: u32 = 0x00203c20;
+/// The background colour of frames that are not in the greenzone
+const BACKGROUND_COLOUR: u32 = 0x00202020;
+/// The background colour of the current frame
+const CURRENT_FRAME_COLOUR: u32 = 0x00404070;
+
+/// The state of the TAS editor
+pub struct TasEditor {
+    /// The movie being edited
+    movie: Movie,
+    /// Snapshots of the NES for quickly seeking through the movie
+    greenzone: Greenzone<ArgbFrameBuffer>,
+    /// The NES, just before the input for the current frame is applied
+    nes: Nes,
+    /// The current frame of the movie
+    frame: usize,
+    /// Whether the piano roll is editing the second controller instead of the first
+    editing_player_two: bool,
+    /// Whether the movie is playing instead of paused on the current frame
+    playing: bool,
+}
+
+impl TasEditor {
+    /// Creates a new TAS editor for a movie, starting from a NES at power on
+    pub fn new(nes: Nes, movie: Movie) -> Self {
+        return TasEditor {
+            movie,
+            greenzone: Greenzone::new(nes.clone(), GREENZONE_INTERVAL),
+            nes,
+            frame: 0,
+            editing_player_two: false,
+            playing: false,
+        };
+    }
+
+    /// Handles the editor's controls:
+    /// * Left and Right to step back and forward one frame
+    /// * Enter to play or pause the movie
+    /// * Tab to switch between editing the first and second controller
+    /// * 1 to 8 to toggle A, B, Select, Start, Up, Down, Left and Right on the current frame
+    pub fn handle_input(&mut self, window: &Window) {
+        if window.is_key_pressed(Key::Enter, KeyRepeat::No) {
+            self.playing = !self.playing;
+        }
+        if window.is_key_pressed(Key::Tab, KeyRepeat::No) {
+            self.editing_player_two = !self.editing_player_two;
+        }
+        if window.is_key_pressed(Key::Right, KeyRepeat::Yes) {
+            self.advance();
+        }
+        if window.is_key_pressed(Key::Left, KeyRepeat::Yes) && self.frame > 0 {
+            self.seek_to_frame(self.frame - 1);
+        }
+        for (key, button) in TOGGLE_KEYS.iter().zip(ROW_BUTTONS.iter()) {
+            if window.is_key_pressed(*key, KeyRepeat::No) {
+                self.toggle_button(*button);
+            }
+        }
+    }
+
+    /// Runs the current frame if the movie is playing. When playback passes the end
+    /// of the movie, the live input is recorded instead.
+    pub fn update(&mut self, live_input: FrameInput) {
+        if self.playing {
+            if self.frame >= self.movie.len() {
+                self.movie.set_input(self.frame, live_input);
+            }
+            self.advance();
+        }
+    }
+
+    /// Consumes the editor, returning the edited movie
+    pub fn into_movie(self) -> Movie {
+        return self.movie;
+    }
+
+    /// Gets the window title describing the editor's state
+    pub fn get_title(&self) -> String {
+        return format!(
+            "gc_nes_emulator TAS editor - Frame {}/{} - Rerecords {} - Editing player {}{}",
+            self.frame,
+            self.movie.len(),
+            self.movie.get_rerecord_count(),
+            if self.editing_player_two { 2 } else { 1 },
+            if self.playing { " - Playing" } else { "" }
+        );
+    }
+
+    /// Draws the screen and the piano roll below it into a buffer the size of the editor window
+    pub fn draw(&self, buffer: &mut [u32]) {
+        buffer[..NES_SCREEN_WIDTH * NES_SCREEN_HEIGHT].copy_from_slice(self.nes.get_video_backend().get_frame());
+        let first_frame = self.frame.saturating_sub(PIANO_ROLL_FRAMES_BEFORE);
+        for column in 0..PIANO_ROLL_FRAMES {
+            let frame = first_frame + column;
+            let input = self.get_player_input(self.movie.get_input(frame));
+            let background = match frame {
+                frame if frame == self.frame => CURRENT_FRAME_COLOUR,
+                frame if frame <= self.greenzone.get_last_frame() => GREENZONE_COLOUR,
+                _ => BACKGROUND_COLOUR,
+            };
+            for (row, button) in ROW_BUTTONS.iter().enumerate() {
+                let colour = if input.unwrap_or(0x00) & button > 0 {
+                    ROW_COLOURS[row]
+                } else {
+                    background
+                };
+                // Leave a one pixel border around each cell to draw the grid
+                for y in 0..CELL_SIZE - 1 {
+                    let start = (NES_SCREEN_HEIGHT + row * CELL_SIZE + y) * NES_SCREEN_WIDTH + column * CELL_SIZE;
+                    buffer[start..start + CELL_SIZE - 1].iter_mut().for_each(|pixel| *pixel = colour);
+                }
+            }
+        }
+    }
+
+    /// Runs the current frame and moves on to the next one
+    fn advance(&mut self) {
+        self.greenzone.run_frame(&mut self.nes, &self.movie, self.frame);
+        self.frame += 1;
+    }
+
+    /// Moves to a frame of the movie, restoring the NES from the greenzone
+    fn seek_to_frame(&mut self, frame: usize) {
+        self.nes = self.greenzone.seek_to_frame(&self.movie, frame);
+        self.frame = frame;
+    }
+
+    /// Toggles a button for the edited controller on the current frame
+    fn toggle_button(&mut self, button: u8) {
+        let mut input = self.movie.get_input(self.frame);
+        let state = self.get_player_input(input).unwrap_or(0x00) ^ button;
+        if self.editing_player_two {
+            input.controller_two = Some(state);
+        } else {
+            input.controller_one = Some(state);
+        }
+        self.movie.set_input(self.frame, input);
+        // Changing input that later frames were played with is a rerecord
+        if self.greenzone.get_last_frame() > self.frame {
+            self.movie.increment_rerecord_count();
+        }
+        self.greenzone.invalidate_after(self.frame);
+    }
+
+    /// Gets the input of the edited controller from a frame's input
+    fn get_player_input(&self, input: FrameInput) -> Option<u8> {
+        return if self.editing_player_two {
+            input.controller_two
+        } else {
+            input.controller_one
+        };
+    }
+}
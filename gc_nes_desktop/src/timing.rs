@@ -0,0 +1,118 @@
+//! The timing module decides how many frames the window's main loop runs for each refresh, and how long it waits
+//! before the next one.
+//!
+//! When audio is playing, the loop is clocked by the audio device. Each refresh runs enough whole frames to top
+//! the audio queue back up to its target fill level, then waits for the device to play it back down, so the
+//! emulator runs at exactly the rate the device consumes samples and nothing is dropped or padded with silence.
+//! Whole frames are run rather than an exact number of samples so that movies and clips still see every frame.
+//!
+//! Without an audio device, the [FramePacer] clocks the loop from the system timer instead. Rather than sleeping
+//! for a fixed duration after each refresh, which lets the time spent emulating and presenting a frame accumulate
+//! as drift, it sleeps until a deadline that advances by exactly one refresh period each time. Combined with
+//! [Nes::frames_for_host_refresh](gc_nes_core::nes::Nes::frames_for_host_refresh), this keeps the emulator running
+//! at the NES's real frame rate on average.
+
+use crate::audio::AudioOutput;
+use gc_nes_core::nes::{Nes, NES_FRAME_RATE};
+use std::time::{Duration, Instant};
+
+/// The refresh rate that the main loop is paced to, in Hz
+pub const HOST_REFRESH_RATE: f64 = 60.0;
+/// How many refreshes the loop can fall behind before the pacer gives up on catching up
+const MAX_REFRESHES_BEHIND: u32 = 4;
+/// The most refreshes in a row that frame skipping can drop, so that the screen keeps moving on very slow hosts
+pub const MAX_SKIPPED_REFRESHES: u32 = 3;
+
+/// Paces a loop to a fixed refresh rate using deadlines, so that timing errors don't accumulate
+pub struct FramePacer {
+    /// The duration of a single refresh
+    period: Duration,
+    /// The time that the current refresh should end at
+    deadline: Instant,
+}
+
+impl FramePacer {
+    /// Creates a new pacer for a refresh rate in Hz, with the first refresh starting now
+    pub fn new(refresh_rate: f64) -> Self {
+        let period = Duration::from_secs_f64(1.0 / refresh_rate);
+        return FramePacer {
+            period,
+            deadline: Instant::now() + period,
+        };
+    }
+
+    /// Gets the refresh rate that the pacer was created with, in Hz
+    pub fn get_refresh_rate(&self) -> f64 {
+        return 1.0 / self.period.as_secs_f64();
+    }
+
+    /// Checks if the current refresh should already have ended, meaning that the loop is running late
+    pub fn is_behind(&self) -> bool {
+        return Instant::now() > self.deadline;
+    }
+
+    /// Sleeps until the end of the current refresh and starts the next one.
+    /// If the loop has fallen too far behind, such as after the window was dragged,
+    /// the missed refreshes are skipped rather than run back to back.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if let Some(duration) = self.deadline.checked_duration_since(now) {
+            std::thread::sleep(duration);
+        } else if now.duration_since(self.deadline) > self.period * MAX_REFRESHES_BEHIND {
+            self.deadline = now;
+        }
+        self.deadline += self.period;
+    }
+}
+
+/// Clocks the window's main loop from the audio device when there is one, or from the system timer otherwise
+pub enum LoopClock<'a> {
+    /// Runs frames to keep the audio queue at its target fill level
+    Audio {
+        /// The output that the NES' audio is queued on
+        output: &'a AudioOutput,
+        /// The rate that the output plays samples at, in Hz
+        sample_rate: f64,
+    },
+    /// Runs the frames due at a fixed refresh rate
+    Timer(FramePacer),
+}
+
+impl<'a> LoopClock<'a> {
+    /// Creates a clock driven by the audio output if it has a device, falling back to pacing at the host refresh rate
+    pub fn new(audio: &'a AudioOutput) -> Self {
+        return match audio.get_sample_rate() {
+            Some(sample_rate) => LoopClock::Audio { output: audio, sample_rate },
+            None => LoopClock::Timer(FramePacer::new(HOST_REFRESH_RATE)),
+        };
+    }
+
+    /// Gets the number of frames to run for the current refresh. When clocked by audio, this is enough frames
+    /// at the NES's current speed to make up the audio queue's deficit, which can be none.
+    pub fn frames_for_refresh(&self, nes: &mut Nes) -> u32 {
+        return match self {
+            LoopClock::Audio { output, sample_rate } => {
+                let samples_per_frame = sample_rate / (NES_FRAME_RATE * f64::from(nes.get_speed()));
+                (output.get_sample_deficit() as f64 / samples_per_frame).ceil() as u32
+            }
+            LoopClock::Timer(pacer) => nes.frames_for_host_refresh(pacer.get_refresh_rate()),
+        };
+    }
+
+    /// Checks if the loop is running late, either because the audio device is about to run dry
+    /// or because the current refresh should already have ended
+    pub fn is_behind(&self) -> bool {
+        return match self {
+            LoopClock::Audio { output, .. } => output.is_running_low(),
+            LoopClock::Timer(pacer) => pacer.is_behind(),
+        };
+    }
+
+    /// Waits until the next refresh is due
+    pub fn wait(&mut self) {
+        match self {
+            LoopClock::Audio { output, .. } => output.wait_for_deficit(),
+            LoopClock::Timer(pacer) => pacer.wait(),
+        }
+    }
+}
@@ -0,0 +1,605 @@
+//! gc_nes_libretro wraps my gc_nes_core crate as a [libretro](https://www.libretro.com/) core,
+//! so that it can be loaded into RetroArch and other libretro frontends, which provide
+//! shaders, netplay, and a full user interface on top of the emulator.
+//!
+//! ### Building the Core
+//! `cargo build --release -p gc_nes_libretro`
+//!
+//! Then load the resulting library (`gc_nes_libretro.dll`, `libgc_nes_libretro.so`, or
+//! `libgc_nes_libretro.dylib`) in RetroArch through Load Core, or from the command line:
+//!
+//! `retroarch -L target/release/libgc_nes_libretro.so SomeNesRom.nes`
+//!
+//! ### Limitations
+//! * Messages, such as a controller being connected, are drawn into the screen by the core rather than shown
+//!   through the frontend's notifications. Frontends that embed the core can post their own with
+//!   `gc_nes_libretro_post_message`.
+//! * Audio is produced by sampling the APU's output at the output sample rate, without any filtering.
+
+#![allow(clippy::needless_return)] // I prefer clarity of return
+
+mod libretro;
+
+use gc_nes_core::cartridge::Cartridge;
+use gc_nes_core::input::buttons;
+use gc_nes_core::nes::{Nes, NES_FRAME_RATE};
+use gc_nes_core::video::{NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
+use libretro::*;
+use log::Level;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_uint, c_void};
+use std::time::Duration;
+
+#[macro_use]
+extern crate log;
+
+/// The rate that audio samples are produced at, in Hz
+const SAMPLE_RATE: f64 = 44_100.0;
+/// How long the messages that the core shows itself, such as a controller being connected, stay on the screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+/// The libretro joypad buttons that correspond to each button of a NES controller
+const BUTTON_MAP: [(c_uint, u8); 8] = [
+    (RETRO_DEVICE_ID_JOYPAD_A, buttons::A),
+    (RETRO_DEVICE_ID_JOYPAD_B, buttons::B),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, buttons::SELECT),
+    (RETRO_DEVICE_ID_JOYPAD_START, buttons::START),
+    (RETRO_DEVICE_ID_JOYPAD_UP, buttons::UP),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, buttons::DOWN),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, buttons::LEFT),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, buttons::RIGHT),
+];
+
+thread_local! {
+    /// The state of the core. Libretro frontends call every function from the same thread.
+    static CORE: RefCell<Core> = RefCell::new(Core::default());
+}
+
+/// The state of the libretro core between calls from the frontend
+struct Core {
+    /// The loaded game, or None if no game is loaded
+    nes: Option<Nes>,
+    /// The callbacks provided by the frontend
+    environment: Option<retro_environment_t>,
+    video_refresh: Option<retro_video_refresh_t>,
+    audio_sample_batch: Option<retro_audio_sample_batch_t>,
+    input_poll: Option<retro_input_poll_t>,
+    input_state: Option<retro_input_state_t>,
+    /// The frontend's log interface, or None if it doesn't provide one
+    log: Option<retro_log_printf_t>,
+    /// The devices that the frontend has connected to each controller port
+    port_devices: [c_uint; 2],
+    /// Interleaved stereo samples produced during the current frame
+    audio_buffer: Vec<i16>,
+}
+
+impl Default for Core {
+    fn default() -> Self {
+        return Core {
+            nes: None,
+            environment: None,
+            video_refresh: None,
+            audio_sample_batch: None,
+            input_poll: None,
+            input_state: None,
+            log: None,
+            port_devices: [RETRO_DEVICE_JOYPAD; 2],
+            audio_buffer: Vec::new(),
+        };
+    }
+}
+
+impl Core {
+    /// Reads the state of the controller connected to a port from the frontend, or None if there isn't one
+    unsafe fn get_port_input(&self, port: c_uint) -> Option<u8> {
+        let input_state = self.input_state?;
+        if self.port_devices[port as usize] == RETRO_DEVICE_NONE {
+            return None;
+        }
+        return Some(BUTTON_MAP.iter().fold(0x00, |state, (id, button)| {
+            if input_state(port, RETRO_DEVICE_JOYPAD, 0, *id) != 0 {
+                state | button
+            } else {
+                state
+            }
+        }));
+    }
+
+    /// Asks the frontend whether it will show the next frame, which it won't while it is skipping frames to keep up.
+    /// Frontends that don't support the query always show it.
+    unsafe fn is_video_enabled(&self) -> bool {
+        let mut flags: c_uint = 0;
+        return match self.environment {
+            Some(environment) if environment(RETRO_ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE, &mut flags as *mut c_uint as *mut c_void) => flags & 0x01 > 0,
+            _ => true,
+        };
+    }
+
+    /// Logs a message through the frontend's log interface, or with the log crate if the frontend doesn't provide one
+    fn log(&self, level: Level, message: &str) {
+        match (self.log, CString::new(message)) {
+            (Some(log), Ok(message)) => {
+                let level = match level {
+                    Level::Error => RETRO_LOG_ERROR,
+                    Level::Warn => RETRO_LOG_WARN,
+                    Level::Info => RETRO_LOG_INFO,
+                    Level::Debug | Level::Trace => RETRO_LOG_DEBUG,
+                };
+                unsafe { log(level, "%s\n\0".as_ptr() as *const c_char, message.as_ptr()) };
+            }
+            _ => log!(level, "{}", message),
+        }
+    }
+
+    /// Runs the NES for a single frame, collecting the audio samples it produced. Frames that won't be shown are
+    /// run without drawing them, which saves time on hosts that can't keep up.
+    fn run_frame(&mut self, draw: bool) {
+        let nes = match &mut self.nes {
+            Some(nes) => nes,
+            None => return,
+        };
+        if draw {
+            nes.frame();
+        } else {
+            nes.skip_frame();
+        }
+        for output in nes.take_audio_samples() {
+            let sample = (output * f32::from(i16::MAX)) as i16;
+            self.audio_buffer.extend_from_slice(&[sample, sample]);
+        }
+    }
+}
+
+/// Runs a closure with the core's state
+fn with_core<R, F: FnOnce(&mut Core) -> R>(function: F) -> R {
+    return CORE.with(|core| function(&mut core.borrow_mut()));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    return RETRO_API_VERSION;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    with_core(|core| *core = Core::default());
+}
+
+/// # Safety
+/// `callback` must be a libretro environment callback, which is asked for the frontend's log interface.
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(callback: retro_environment_t) {
+    let mut log_callback = retro_log_callback { log: None };
+    let log = if callback(
+        RETRO_ENVIRONMENT_GET_LOG_INTERFACE,
+        &mut log_callback as *mut retro_log_callback as *mut c_void,
+    ) {
+        log_callback.log
+    } else {
+        None
+    };
+    with_core(|core| {
+        core.environment = Some(callback);
+        core.log = log;
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: retro_video_refresh_t) {
+    with_core(|core| core.video_refresh = Some(callback));
+}
+
+/// Unused, the core always provides audio in batches
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: retro_audio_sample_t) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: retro_audio_sample_batch_t) {
+    with_core(|core| core.audio_sample_batch = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: retro_input_poll_t) {
+    with_core(|core| core.input_poll = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: retro_input_state_t) {
+    with_core(|core| core.input_state = Some(callback));
+}
+
+/// # Safety
+/// `info` must point to a retro_system_info structure that can be written to.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut retro_system_info) {
+    if let Some(info) = info.as_mut() {
+        info.library_name = "gc_nes_emulator\0".as_ptr() as *const c_char;
+        info.library_version = concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char;
+        info.valid_extensions = "nes\0".as_ptr() as *const c_char;
+        info.need_fullpath = false;
+        info.block_extract = false;
+    }
+}
+
+/// # Safety
+/// `info` must point to a retro_system_av_info structure that can be written to.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut retro_system_av_info) {
+    if let Some(info) = info.as_mut() {
+        info.geometry = retro_game_geometry {
+            base_width: NES_SCREEN_WIDTH as c_uint,
+            base_height: NES_SCREEN_HEIGHT as c_uint,
+            max_width: NES_SCREEN_WIDTH as c_uint,
+            max_height: NES_SCREEN_HEIGHT as c_uint,
+            // NES pixels are slightly wider than they are tall on a television
+            aspect_ratio: 4.0 / 3.0,
+        };
+        info.timing = retro_system_timing {
+            fps: NES_FRAME_RATE,
+            sample_rate: SAMPLE_RATE,
+        };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(port: c_uint, device: c_uint) {
+    if port < 2 {
+        with_core(|core| {
+            let connected = device != RETRO_DEVICE_NONE;
+            let was_connected = std::mem::replace(&mut core.port_devices[port as usize], device) != RETRO_DEVICE_NONE;
+            if let (Some(nes), true) = (&mut core.nes, connected != was_connected) {
+                let change = if connected { "connected" } else { "disconnected" };
+                nes.post_message(&format!("Controller {} {}", port + 1, change), MESSAGE_DURATION);
+            }
+        });
+    } else {
+        with_core(|core| core.log(Level::Warn, &format!("Ignored device for invalid port {}", port)));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core(|core| {
+        if let Some(nes) = &mut core.nes {
+            nes.reset();
+        }
+    });
+}
+
+/// # Safety
+/// Must be called by a libretro frontend after a game has been loaded.
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    with_core(|core| {
+        if let Some(input_poll) = core.input_poll {
+            input_poll();
+        }
+        let controller_one = core.get_port_input(0);
+        let controller_two = core.get_port_input(1);
+        if let Some(nes) = &mut core.nes {
+            nes.update_controller_one(controller_one);
+            nes.update_controller_two(controller_two);
+        }
+
+        let draw = core.is_video_enabled();
+        core.run_frame(draw);
+
+        if let (Some(video_refresh), Some(nes)) = (core.video_refresh, &mut core.nes) {
+            let frame = nes.get_screen();
+            video_refresh(
+                frame.as_ptr() as *const c_void,
+                NES_SCREEN_WIDTH as c_uint,
+                NES_SCREEN_HEIGHT as c_uint,
+                NES_SCREEN_WIDTH * std::mem::size_of::<u32>(),
+            );
+        }
+        if let Some(audio_sample_batch) = core.audio_sample_batch {
+            audio_sample_batch(core.audio_buffer.as_ptr(), core.audio_buffer.len() / 2);
+        }
+        core.audio_buffer.clear();
+    });
+}
+
+/// Save states of a cartridge are always the same size, so the size of the current state is enough for every state
+/// that the frontend will ask for. Zero, which frontends take to mean save states aren't supported, without a game.
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    return with_core(|core| core.nes.as_ref().map_or(0, |nes| nes.save_state().len()));
+}
+
+/// # Safety
+/// `data` must be null or point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    return with_core(|core| match &core.nes {
+        Some(nes) => {
+            let state = nes.save_state();
+            if state.len() > size {
+                return false;
+            }
+            std::slice::from_raw_parts_mut(data as *mut u8, state.len()).copy_from_slice(&state);
+            true
+        }
+        None => false,
+    });
+}
+
+/// # Safety
+/// `data` must be null or point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let state = std::slice::from_raw_parts(data as *const u8, size);
+    return with_core(|core| match &mut core.nes {
+        Some(nes) => match nes.load_state(state) {
+            Ok(()) => true,
+            Err(error) => {
+                core.log(Level::Error, &format!("Failed to load save state: {}", error));
+                false
+            }
+        },
+        None => false,
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+/// `game` must be null or point to a retro_game_info structure whose data points to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const retro_game_info) -> bool {
+    let game = match game.as_ref() {
+        Some(game) if !game.data.is_null() => game,
+        _ => return false,
+    };
+    return with_core(|core| {
+        if let Some(environment) = core.environment {
+            let mut pixel_format = RETRO_PIXEL_FORMAT_XRGB8888;
+            if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut pixel_format as *mut c_uint as *mut c_void) {
+                core.log(Level::Error, "The frontend doesn't support the XRGB8888 pixel format");
+                return false;
+            }
+        }
+        let rom = std::slice::from_raw_parts(game.data as *const u8, game.size);
+        return match Cartridge::load_from_reader(rom) {
+            Ok(cartridge) => {
+                let mut nes = Nes::new(cartridge);
+                nes.set_audio_sample_rate(SAMPLE_RATE);
+                nes.get_messages_mut().set_rendering(true);
+                core.nes = Some(nes);
+                true
+            }
+            Err(error) => {
+                core.log(Level::Error, &format!("Failed to load ROM: {}", error));
+                false
+            }
+        };
+    });
+}
+
+/// Shows a message in the bottom left corner of the screen for a duration in milliseconds of emulated time, for
+/// frontends that embed the core directly. Returns false if no game is loaded or the text isn't valid UTF-8.
+///
+/// # Safety
+/// `text` must be null or a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gc_nes_libretro_post_message(text: *const c_char, duration_ms: c_uint) -> bool {
+    if text.is_null() {
+        return false;
+    }
+    let text = match CStr::from_ptr(text).to_str() {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    return with_core(|core| match &mut core.nes {
+        Some(nes) => {
+            nes.post_message(text, Duration::from_millis(u64::from(duration_ms)));
+            true
+        }
+        None => false,
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const retro_game_info, _num_info: usize) -> bool {
+    return false;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    with_core(|core| core.nes = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    return RETRO_REGION_NTSC;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    return std::ptr::null_mut();
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    return 0;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static VIDEO_FRAMES: AtomicUsize = AtomicUsize::new(0);
+    static AUDIO_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn video_refresh_stub(_data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+        assert_eq!((256, 240, 1024), (width, height, pitch));
+        VIDEO_FRAMES.fetch_add(1, Ordering::SeqCst);
+    }
+
+    unsafe extern "C" fn audio_sample_batch_stub(_data: *const i16, frames: usize) -> usize {
+        AUDIO_FRAMES.fetch_add(frames, Ordering::SeqCst);
+        return frames;
+    }
+
+    unsafe extern "C" fn unsupported_environment_stub(_cmd: c_uint, _data: *mut c_void) -> bool {
+        return false;
+    }
+
+    unsafe extern "C" fn input_state_stub(_port: c_uint, _device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+        return (id == RETRO_DEVICE_ID_JOYPAD_START) as i16;
+    }
+
+    /// Builds a .nes file with a single bank of program ROM that loops forever at $8000
+    fn get_test_rom() -> Vec<u8> {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 0x01, 0x01, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut program_rom = vec![0x00; 0x4000];
+        // JMP $8000
+        program_rom[..3].copy_from_slice(&[0x4c, 0x00, 0x80]);
+        // Reset vector
+        program_rom[0x3ffc..0x3ffe].copy_from_slice(&[0x00, 0x80]);
+        rom.extend_from_slice(&program_rom);
+        rom.extend_from_slice(&[0x00; 0x2000]);
+        return rom;
+    }
+
+    #[test]
+    fn test_system_info() {
+        let mut info = retro_system_info {
+            library_name: std::ptr::null(),
+            library_version: std::ptr::null(),
+            valid_extensions: std::ptr::null(),
+            need_fullpath: true,
+            block_extract: true,
+        };
+        unsafe {
+            retro_get_system_info(&mut info);
+            assert_eq!("gc_nes_emulator", CStr::from_ptr(info.library_name).to_str().unwrap());
+            assert_eq!(env!("CARGO_PKG_VERSION"), CStr::from_ptr(info.library_version).to_str().unwrap());
+            assert_eq!("nes", CStr::from_ptr(info.valid_extensions).to_str().unwrap());
+        }
+        assert!(!info.need_fullpath);
+    }
+
+    #[test]
+    fn test_load_and_run() {
+        let rom = get_test_rom();
+        let game = retro_game_info {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        retro_set_video_refresh(video_refresh_stub);
+        retro_set_audio_sample_batch(audio_sample_batch_stub);
+        retro_set_input_state(input_state_stub);
+        unsafe {
+            assert!(retro_load_game(&game));
+            (0..60).for_each(|_| retro_run());
+        }
+        assert_eq!(60, VIDEO_FRAMES.load(Ordering::SeqCst));
+        // Sixty frames of audio should have been produced, give or take a frame's worth of samples
+        let audio_frames = AUDIO_FRAMES.load(Ordering::SeqCst) as f64;
+        let expected = 60.0 * SAMPLE_RATE / NES_FRAME_RATE;
+        assert!(
+            (audio_frames - expected).abs() < SAMPLE_RATE / NES_FRAME_RATE,
+            "{} audio frames",
+            audio_frames
+        );
+        assert_eq!(Some(buttons::START), with_core(|core| unsafe { core.get_port_input(0) }));
+
+        retro_set_controller_port_device(1, RETRO_DEVICE_NONE);
+        // Setting the same device again doesn't post another message
+        retro_set_controller_port_device(1, RETRO_DEVICE_NONE);
+        assert!(unsafe { gc_nes_libretro_post_message(b"Hello\0".as_ptr() as *const c_char, 1000) });
+        let messages: Vec<String> = with_core(|core| {
+            let messages = core.nes.as_mut().unwrap().get_messages_mut();
+            return messages.get_messages().map(|message| message.text.clone()).collect();
+        });
+        assert_eq!(vec!["Controller 2 disconnected", "Hello"], messages);
+        retro_unload_game();
+        assert!(!unsafe { gc_nes_libretro_post_message(b"Hello\0".as_ptr() as *const c_char, 1000) });
+    }
+
+    #[test]
+    fn test_load_invalid_game() {
+        let rom = [0x00; 16];
+        let game = retro_game_info {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        unsafe {
+            assert!(!retro_load_game(&game));
+            assert!(!retro_load_game(std::ptr::null()));
+        }
+    }
+
+    #[test]
+    fn test_log_without_interface() {
+        unsafe { retro_set_environment(unsupported_environment_stub) };
+        assert!(with_core(|core| core.log.is_none()));
+        let rom = get_test_rom();
+        let game = retro_game_info {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        // The pixel format is rejected, which is logged with the log crate instead
+        assert!(!unsafe { retro_load_game(&game) });
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let rom = get_test_rom();
+        let game = retro_game_info {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        assert_eq!(0, retro_serialize_size());
+        unsafe {
+            assert!(retro_load_game(&game));
+            (0..10).for_each(|_| retro_run());
+            let size = retro_serialize_size();
+            assert_ne!(0, size);
+            let mut saved = vec![0u8; size];
+            assert!(!retro_serialize(saved.as_mut_ptr() as *mut c_void, size - 1));
+            assert!(retro_serialize(saved.as_mut_ptr() as *mut c_void, size));
+
+            (0..5).for_each(|_| retro_run());
+            let mut expected = vec![0u8; size];
+            assert!(retro_serialize(expected.as_mut_ptr() as *mut c_void, size));
+            assert_ne!(saved, expected);
+
+            // Running the same frames again from the loaded state ends up in the same state
+            assert!(retro_unserialize(saved.as_ptr() as *const c_void, size));
+            (0..5).for_each(|_| retro_run());
+            let mut actual = vec![0u8; size];
+            assert!(retro_serialize(actual.as_mut_ptr() as *mut c_void, size));
+            assert_eq!(expected, actual);
+
+            assert!(!retro_unserialize(saved.as_ptr() as *const c_void, size - 1));
+            assert!(!retro_unserialize(std::ptr::null(), size));
+        }
+        retro_unload_game();
+        assert_eq!(0, retro_serialize_size());
+    }
+}
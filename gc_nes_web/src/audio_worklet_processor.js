@@ -0,0 +1,58 @@
+// AudioWorkletProcessor that plays the NES' audio, registered as "gc-nes-audio". Post the Float32Arrays returned by
+// WebNes.take_audio_samples to the node's port and they are played in order, on every channel of the output.
+// Loaded into the AudioWorklet from the string returned by audio_worklet_processor_source.
+
+// The most audio that can be waiting to be played, in seconds. Older samples are dropped past this, so that
+// running the emulator slightly faster than the audio device can't build up latency.
+const MAX_BUFFERED_SECONDS = 0.1;
+
+class NesAudioProcessor extends AudioWorkletProcessor {
+    constructor() {
+        super();
+        // The chunks of samples waiting to be played, oldest first
+        this.chunks = [];
+        // The number of samples of the oldest chunk that have already been played
+        this.offset = 0;
+        // The number of samples waiting to be played across all of the chunks
+        this.buffered = 0;
+        this.port.onmessage = (event) => this.enqueue(event.data);
+    }
+
+    enqueue(samples) {
+        if (samples.length === 0) {
+            return;
+        }
+        this.chunks.push(samples);
+        this.buffered += samples.length;
+        while (this.buffered > sampleRate * MAX_BUFFERED_SECONDS && this.chunks.length > 1) {
+            this.buffered -= this.chunks.shift().length - this.offset;
+            this.offset = 0;
+        }
+    }
+
+    process(inputs, outputs) {
+        const output = outputs[0];
+        const channel = output[0];
+        let written = 0;
+        while (written < channel.length && this.chunks.length > 0) {
+            const chunk = this.chunks[0];
+            const count = Math.min(channel.length - written, chunk.length - this.offset);
+            channel.set(chunk.subarray(this.offset, this.offset + count), written);
+            written += count;
+            this.offset += count;
+            this.buffered -= count;
+            if (this.offset === chunk.length) {
+                this.chunks.shift();
+                this.offset = 0;
+            }
+        }
+        // Play silence if the emulator has fallen behind
+        channel.fill(0, written);
+        for (let index = 1; index < output.length; index++) {
+            output[index].set(channel);
+        }
+        return true;
+    }
+}
+
+registerProcessor("gc-nes-audio", NesAudioProcessor);
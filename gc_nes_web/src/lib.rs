@@ -1,7 +1,6 @@
 //! gc_nes_web wraps the public functions exposed by my gc_nes_core crate for use
 //! in the browser through Web Assembly - Javascript interop. It provides an interface
-//! to load and run NES ROMs, provide input, and extract rendered image data.
-//! Audio is currently unsupported.
+//! to load and run NES ROMs, provide input, and extract rendered image data and audio.
 //!
 //! ### Install in an NPM Project
 //! `npm install gc_nes_web`
@@ -10,34 +9,104 @@
 //! ```javascript
 //! // Import the package
 //! const wasm = await import ("gc_nes_web");
-//! // Create the NES object
+//! // Create the NES object, which throws an error if the ROM can't be loaded
 //! let nes = this.state.wasm.nes(romArrayOfBytes);
 //! // Run the emulator to the completion of the next frame and retrieve it
 //! let frame = nes.frame();
 //! // Or run just one cycle and get the frame separately
 //! nes.cycle();
 //! let frame = nes.get_screen();
-//! // Drawing to a Canvas
-//! let offscreenCanvas = new OffscreenCanvas(256, 240);
-//! let offscreenCanvasContext = offscreenCanvas.getContext("2d");
-//! let imageData = offscreenCanvasContext?.createImageData(256, 240);
-//! imageData.data.set(frame);
-//! offscreenCanvasContext.putImageData(imageData, 0, 0);
-//! // mainCanvasContext is the 2D context for the Canvas you actually want to draw to.
-//! mainCanvasContext.drawImage(offscreenCanvas, 0, 0);
+//! // Provide input state, either with the button bits or the builder
+//! nes.update_controller_one(wasm.Button.Up | wasm.Button.A);
+//! nes.update_controller_one(new wasm.ButtonStateBuilder().set(wasm.Button.Start, true).build());
+//! // Or let the NES map the keyboard to both controllers, and remap keys with set_key_mapping
+//! document.addEventListener("keydown", (event) => nes.handle_key_event(event.code, true) && event.preventDefault());
+//! document.addEventListener("keyup", (event) => nes.handle_key_event(event.code, false) && event.preventDefault());
+//! nes.set_key_mapping("KeyX", 0, wasm.Button.A);
+//! // Paint the current frame onto a 256x240 Canvas, or an OffscreenCanvas in a worker
+//! nes.blit_to_canvas(canvas.getContext("2d"));
+//! nes.blit_to_offscreen_canvas(offscreenCanvas.getContext("2d"));
+//! // Draw the screen with the colours from a .pal file instead of the emulator's own palette
+//! nes.set_palette(new Uint8Array(await (await fetch("custom.pal")).arrayBuffer()));
+//! // Show a message in the corner of the screen for two seconds
+//! nes.post_message("State saved", 2000);
+//! // Record the frames produced by nes.frame() as an animated GIF
+//! nes.start_gif();
+//! let gifBytes = nes.stop_gif();
+//! let gifUrl = URL.createObjectURL(new Blob([gifBytes], { type: "image/gif" }));
+//! // Persist battery backed saves wherever you like, keyed by the hash of the ROM
+//! nes.set_persistence_callbacks(
+//!     (romHash, saveBytes) => localStorage.setItem(romHash, JSON.stringify(Array.from(saveBytes))),
+//!     (romHash) => new Uint8Array(JSON.parse(localStorage.getItem(romHash) ?? "[]")),
+//! );
 //! ```
 //!
+//! ### Playing Audio
+//! gc_nes_web comes with an AudioWorkletProcessor, registered as `gc-nes-audio`, that plays the samples taken from
+//! the NES in order and plays silence if the emulator falls behind. Load it into the audio context from its source,
+//! then pass it the samples produced by each frame:
+//! ```javascript
+//! const audioContext = new AudioContext();
+//! const processorUrl = URL.createObjectURL(new Blob([wasm.audio_worklet_processor_source()], { type: "text/javascript" }));
+//! await audioContext.audioWorklet.addModule(processorUrl);
+//! const audioNode = new AudioWorkletNode(audioContext, "gc-nes-audio");
+//! audioNode.connect(audioContext.destination);
+//! // Centre the NES' output on zero, as the console's own filters do
+//! nes.set_analog_filters(true);
+//! // After running each frame, send its samples at the audio context's rate to the worklet
+//! nes.frame();
+//! audioNode.port.postMessage(nes.take_audio_samples(audioContext.sampleRate));
+//! ```
+//! Browsers only start audio contexts after the user has interacted with the page, so create it in a click handler.
+//!
 //! Through [wasm-pack](https://github.com/rustwasm/wasm-pack), gc_nes_web has full Typescript support
 //!
+//! ### Building with WASM SIMD
+//! Enabling the `simd` feature converts frames to RGBA with WASM SIMD instructions, which browsers without SIMD
+//! support will refuse to load. Since WASM can't detect SIMD support at runtime, build the package twice and pick
+//! between them when loading, using a check like the one in [wasm-feature-detect](https://github.com/GoogleChromeLabs/wasm-feature-detect):
+//! ```sh
+//! RUSTFLAGS="-C target-feature=+simd128" wasm-pack build --out-dir pkg-simd -- --features simd
+//! wasm-pack build --out-dir pkg
+//! ```
+//!
+//! ### Leaving Out Mappers
+//! gc_nes_web builds gc_nes_core without its tracing and movie features, which it doesn't expose.
+//! The mappers past 004 are behind the `extra-mappers` feature, which is enabled by default. Turning it off shrinks
+//! the package, leaving support for mappers 000 to 004, which cover most of the NES library:
+//! ```sh
+//! wasm-pack build --out-dir pkg -- --no-default-features --features console_error_panic_hook,filters,recorder,web-canvas
+//! ```
+//!
 //! ### Try it Now
 //!
 //! You can try out gc_nes_emulator on my website, at https://garettcooper.com/#/nes-emulator
 
 mod utils;
 
-use gc_nes_core::cartridge::Cartridge;
-use gc_nes_core::nes::{Nes, NES_SCREEN_DIMENSIONS};
+use gc_nes_core::capabilities::Capabilities;
+use gc_nes_core::cartridge::{calculate_rom_md5, Cartridge};
+use gc_nes_core::nes::{AccuracyPreset, Nes, NES_SCREEN_DIMENSIONS};
+#[cfg(feature = "filters")]
+use gc_nes_core::video::postprocess::read_palette;
+#[cfg(feature = "recorder")]
+use gc_nes_core::video::recorder::{ClipFormat, ClipRecorder};
+use gc_nes_core::video::IndexedFrameBuffer;
+#[cfg(not(feature = "filters"))]
+use gc_nes_core::video::{emphasise_rgba_frame, indexed_to_rgba};
+#[cfg(feature = "web-canvas")]
+use gc_nes_core::video::{NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
+#[cfg(feature = "web-canvas")]
+use js_sys::Uint8ClampedArray;
+use js_sys::{Function, Uint8Array};
+use std::collections::HashMap;
+use std::time::Duration;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+#[cfg(feature = "web-canvas")]
+use web_sys::{CanvasRenderingContext2d, ImageData, OffscreenCanvasRenderingContext2d};
+
+pub use gc_nes_core::input::buttons;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -45,18 +114,78 @@ use wasm_bindgen::prelude::*;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// The source of the AudioWorkletProcessor that plays the samples returned by take_audio_samples
+const AUDIO_WORKLET_PROCESSOR_SOURCE: &str = include_str!("audio_worklet_processor.js");
+
+/// How many frames pass between checks for changes to the battery backed memory that need to be persisted
+const PERSISTENCE_INTERVAL_FRAMES: u64 = 60;
+
+/// The keys that handle_key_event maps to the buttons of each controller by default, as KeyboardEvent.code values,
+/// matching the keyboard layouts of the desktop frontend
+const DEFAULT_KEY_MAP: [(&str, u8, Button); 16] = [
+    ("Space", 0, Button::A),
+    ("ShiftLeft", 0, Button::B),
+    ("KeyY", 0, Button::Select),
+    ("KeyT", 0, Button::Start),
+    ("KeyW", 0, Button::Up),
+    ("KeyS", 0, Button::Down),
+    ("KeyA", 0, Button::Left),
+    ("KeyD", 0, Button::Right),
+    ("KeyL", 1, Button::A),
+    ("KeyK", 1, Button::B),
+    ("ShiftRight", 1, Button::Select),
+    ("Enter", 1, Button::Start),
+    ("ArrowUp", 1, Button::Up),
+    ("ArrowDown", 1, Button::Down),
+    ("ArrowLeft", 1, Button::Left),
+    ("ArrowRight", 1, Button::Right),
+];
+
 #[wasm_bindgen]
 /// Structure used the represent the NES itself in WASM.
 pub struct WebNes {
-    nes: Nes,
+    /// The NES, which draws colour indices that are converted to RGBA a whole frame at a time
+    nes: Nes<IndexedFrameBuffer>,
+    /// Records the frames produced by frame() while a GIF is being recorded
+    #[cfg(feature = "recorder")]
+    clip_recorder: Option<ClipRecorder>,
+    /// The hex encoded MD5 hash of the loaded ROM, used as the key for persisted data
+    rom_hash: String,
+    /// Called with the ROM hash and the save data whenever the battery backed memory should be persisted
+    save_callback: Option<Function>,
+    /// The generation of the save data as of the last time it was persisted or restored
+    persisted_save_generation: u64,
+    /// The image that frames are copied into before they are painted onto a canvas, along with a view of its pixels,
+    /// created by the first blit and reused after that
+    #[cfg(feature = "web-canvas")]
+    canvas_image: Option<(ImageData, Uint8ClampedArray)>,
+    /// The port, 0 or 1, and the button bit that handle_key_event maps each KeyboardEvent.code to
+    key_map: HashMap<String, (u8, u8)>,
+    /// The state of each controller built up from the key events passed to handle_key_event
+    key_states: [u8; 2],
 }
 
 #[wasm_bindgen]
 impl WebNes {
     /// Creates a new NES instance with no connected controllers.
+    /// Messages posted with post_message are drawn into the bottom left corner of the screen.
     pub fn new(cartridge: WebCartridge) -> WebNes {
+        let mut nes = Nes::with_video_backend(cartridge.cartridge, IndexedFrameBuffer::default());
+        nes.get_messages_mut().set_rendering(true);
         WebNes {
-            nes: Nes::new(cartridge.cartridge),
+            nes,
+            #[cfg(feature = "recorder")]
+            clip_recorder: None,
+            rom_hash: cartridge.rom_hash,
+            save_callback: None,
+            persisted_save_generation: 0,
+            #[cfg(feature = "web-canvas")]
+            canvas_image: None,
+            key_map: DEFAULT_KEY_MAP
+                .iter()
+                .map(|(code, port, button)| (code.to_string(), (*port, *button as u8)))
+                .collect(),
+            key_states: [0; 2],
         }
     }
 
@@ -69,14 +198,148 @@ impl WebNes {
     /// Returns the frame as a Vector of bytes, with each pixel of the
     /// NES screen represented by four bytes in RGBA order.
     pub fn frame(&mut self) -> Vec<u8> {
-        self.nes.frame().to_vec()
+        self.nes.frame();
+        let frame = self.get_screen();
+        #[cfg(feature = "recorder")]
+        if let Some(recorder) = &mut self.clip_recorder {
+            recorder.add_rgba_frame(&frame);
+        }
+        if self.nes.get_frame_count().is_multiple_of(PERSISTENCE_INTERVAL_FRAMES) {
+            self.persist_save_data();
+        }
+        frame
+    }
+
+    /// Takes the mixed audio output produced since the last call, oldest first, as a Float32Array of samples
+    /// at the sample rate in Hz, which should be the rate of the audio context they are played in. Samples
+    /// produced at a different rate before the first call, or before the rate changed, are discarded.
+    pub fn take_audio_samples(&mut self, sample_rate: f64) -> Vec<f32> {
+        if self.nes.get_audio_sample_rate() != sample_rate {
+            self.nes.set_audio_sample_rate(sample_rate);
+        }
+        self.nes.take_audio_samples()
+    }
+
+    /// Enables or disables the NES' analog audio filters, which are off by default. With them enabled,
+    /// samples range from -1.0 to 1.0 and are centred on zero, rather than ranging from 0.0 to 1.0.
+    pub fn set_analog_filters(&mut self, enabled: bool) {
+        self.nes.set_analog_filters(enabled);
+    }
+
+    /// Sets the callbacks used to persist the cartridge's battery backed memory, letting the page decide
+    /// where it is stored. `save_callback(romHash, bytes)` is called with a Uint8Array whenever the memory
+    /// has changed and should be saved, and `load_callback(romHash)` is called immediately to restore it,
+    /// returning a Uint8Array of previously saved bytes, or undefined if there are none.
+    /// The callbacks are never called for cartridges without anything to save.
+    /// Throws if the data returned by the load callback can't be loaded into the cartridge.
+    pub fn set_persistence_callbacks(&mut self, save_callback: Function, load_callback: Function) -> Result<(), JsValue> {
+        self.save_callback = Some(save_callback);
+        if self.nes.get_save_data().is_none() {
+            return Ok(());
+        }
+
+        let saved = load_callback.call1(&JsValue::NULL, &JsValue::from_str(&self.rom_hash))?;
+        if let Ok(saved) = saved.dyn_into::<Uint8Array>() {
+            self.nes
+                .load_save_data(&saved.to_vec())
+                .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        }
+        self.persisted_save_generation = self.nes.sram_generation();
+        Ok(())
+    }
+
+    /// Passes the battery backed memory to the save callback if it has changed since it was last persisted
+    fn persist_save_data(&mut self) {
+        if self.nes.sram_generation() == self.persisted_save_generation {
+            return;
+        }
+        let (save_callback, save_data) = match (&self.save_callback, self.nes.get_save_data()) {
+            (Some(save_callback), Some(save_data)) => (save_callback, save_data),
+            _ => return,
+        };
+        // Errors thrown by the callback are ignored so that a failure to save doesn't stop the game
+        let _ = save_callback.call2(&JsValue::NULL, &JsValue::from_str(&self.rom_hash), &Uint8Array::from(&save_data[..]));
+        self.persisted_save_generation = self.nes.sram_generation();
+    }
+
+    /// Draws the screen with the colours from a 192 byte .pal file, like the ones that most NES emulators and palette
+    /// generators make, instead of the emulator's own palette. Throws an error if the palette is too short.
+    #[cfg(feature = "filters")]
+    pub fn set_palette(&mut self, palette: &[u8]) -> Result<(), JsValue> {
+        let palette = read_palette(palette).map_err(|error| JsValue::from_str(&error.to_string()))?;
+        self.nes.get_post_processing_mut().set_palette(palette);
+        Ok(())
+    }
+
+    /// Shows a message, such as a notification that a state was saved, in the bottom left corner of the screen
+    /// for a duration in milliseconds of emulated time.
+    pub fn post_message(&mut self, text: &str, duration_ms: u32) {
+        self.nes.post_message(text, Duration::from_millis(u64::from(duration_ms)));
+    }
+
+    /// Starts recording the frames produced by frame() as an animated GIF,
+    /// discarding any recording that was already in progress.
+    #[cfg(feature = "recorder")]
+    pub fn start_gif(&mut self) {
+        self.clip_recorder = Some(ClipRecorder::new());
+    }
+
+    /// Stops recording and returns the bytes of the animated GIF,
+    /// which are empty if no frames were recorded.
+    #[cfg(feature = "recorder")]
+    pub fn stop_gif(&mut self) -> Vec<u8> {
+        self.clip_recorder
+            .take()
+            .and_then(|recorder| recorder.encode(ClipFormat::Gif).ok())
+            .unwrap_or_default()
+    }
+
+    /// Gets the current state of the screen, drawn through the post-processing chain.
+    /// Returns the frame as a Vector of bytes, with each pixel of the
+    /// NES screen represented by four bytes in RGBA order.
+    #[cfg(feature = "filters")]
+    pub fn get_screen(&mut self) -> Vec<u8> {
+        self.nes.post_process_frame().pixels
     }
 
     /// Gets the current state of the screen from the PPU's screen buffer.
     /// Returns the frame as a Vector of bytes, with each pixel of the
     /// NES screen represented by four bytes in RGBA order.
+    #[cfg(not(feature = "filters"))]
     pub fn get_screen(&mut self) -> Vec<u8> {
-        self.nes.get_screen().to_vec()
+        let mut frame = vec![0; NES_SCREEN_DIMENSIONS * 4];
+        indexed_to_rgba(self.nes.get_screen().as_ref(), &mut frame);
+        emphasise_rgba_frame(self.nes.get_video_backend().get_emphasis_frame().as_ref(), &mut frame);
+        frame
+    }
+
+    /// Paints the current frame onto a canvas through its 2D context, with the top left of the frame at the top left of
+    /// the canvas. The canvas should be 256x240, and can be scaled up with CSS.
+    #[cfg(feature = "web-canvas")]
+    pub fn blit_to_canvas(&mut self, context: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        context.put_image_data(self.update_canvas_image()?, 0.0, 0.0)
+    }
+
+    /// Paints the current frame onto an OffscreenCanvas through its 2D context, for emulators running in a worker.
+    /// See [blit_to_canvas](#method.blit_to_canvas).
+    #[cfg(feature = "web-canvas")]
+    pub fn blit_to_offscreen_canvas(&mut self, context: &OffscreenCanvasRenderingContext2d) -> Result<(), JsValue> {
+        context.put_image_data(self.update_canvas_image()?, 0.0, 0.0)
+    }
+
+    /// Copies the current frame into the image used by the blit functions, creating it the first time it is needed
+    #[cfg(feature = "web-canvas")]
+    fn update_canvas_image(&mut self) -> Result<&ImageData, JsValue> {
+        let frame = self.get_screen();
+        if self.canvas_image.is_none() {
+            let image_data = ImageData::new_with_sw(NES_SCREEN_WIDTH as u32, NES_SCREEN_HEIGHT as u32)?;
+            // ImageData::data returns a copy of the pixels, so a view of them is taken from the property instead
+            let pixels = js_sys::Reflect::get(&image_data, &JsValue::from_str("data"))?.dyn_into::<Uint8ClampedArray>()?;
+            self.canvas_image = Some((image_data, pixels));
+        }
+        let (image_data, pixels) = self.canvas_image.as_ref().unwrap();
+        pixels.copy_from(&frame);
+        Ok(image_data)
     }
 
     /// Updates the state of the input device connected to the first port.
@@ -84,9 +347,84 @@ impl WebNes {
         self.nes.update_controller_one(Some(controller_state));
     }
 
-    /// Updates the state of the input device connected to the first port.
+    /// Updates the state of the input device connected to the second port.
     pub fn update_controller_two(&mut self, controller_state: u8) {
-        self.nes.update_controller_one(Some(controller_state));
+        self.nes.update_controller_two(Some(controller_state));
+    }
+
+    /// Presses or releases the button mapped to a key, given the `code` of a keydown or keyup KeyboardEvent, and
+    /// updates that button's controller. Returns false if the key isn't mapped, so that the page can let the browser
+    /// handle it. By default the first controller is on WASD with Space, Left Shift, Y and T for A, B, Select and Start,
+    /// and the second is on the arrow keys with L, K, Right Shift and Enter. The states passed to update_controller_one
+    /// and update_controller_two are replaced by the next key event for that controller.
+    /// ```javascript
+    /// document.addEventListener("keydown", (event) => nes.handle_key_event(event.code, true) && event.preventDefault());
+    /// document.addEventListener("keyup", (event) => nes.handle_key_event(event.code, false) && event.preventDefault());
+    /// ```
+    pub fn handle_key_event(&mut self, code: &str, pressed: bool) -> bool {
+        let (port, button) = match self.key_map.get(code) {
+            Some(mapping) => *mapping,
+            None => return false,
+        };
+        let state = &mut self.key_states[usize::from(port)];
+        if pressed {
+            *state |= button;
+        } else {
+            *state &= !button;
+        }
+        let state = Some(*state);
+        match port {
+            0 => self.nes.update_controller_one(state),
+            _ => self.nes.update_controller_two(state),
+        }
+        true
+    }
+
+    /// Maps a key, given as a KeyboardEvent.code value, to a button of the controller on a port, 0 for the first
+    /// port and 1 for the second, replacing its previous mapping. Returns false and leaves the key map unchanged if
+    /// the port doesn't exist.
+    pub fn set_key_mapping(&mut self, code: &str, port: u8, button: Button) -> bool {
+        if port > 1 {
+            return false;
+        }
+        self.key_map.insert(code.to_string(), (port, button as u8));
+        true
+    }
+
+    /// Removes the mapping of every key, including the default ones, so that a key map can be built from scratch
+    /// with set_key_mapping. Any buttons held through the key map are released.
+    pub fn clear_key_map(&mut self) {
+        self.key_map.clear();
+        if self.key_states[0] != 0 {
+            self.nes.update_controller_one(Some(0));
+        }
+        if self.key_states[1] != 0 {
+            self.nes.update_controller_two(Some(0));
+        }
+        self.key_states = [0; 2];
+    }
+
+    /// Sets the speed multiplier that the NES should run at, where 1.0 is normal speed.
+    pub fn set_speed(&mut self, multiplier: f32) {
+        self.nes.set_speed(multiplier);
+    }
+
+    /// Returns the number of frames that should be run for a single refresh of the
+    /// browser's display, given its refresh rate in Hz, to run at the current speed.
+    pub fn frames_for_host_refresh(&mut self, host_refresh_rate: f64) -> u32 {
+        self.nes.frames_for_host_refresh(host_refresh_rate)
+    }
+
+    /// Selects one of the accuracy presets listed by the core's capabilities by name.
+    /// Returns false and leaves the accuracy unchanged if there is no preset with the name.
+    pub fn set_accuracy_preset(&mut self, name: &str) -> bool {
+        match AccuracyPreset::from_name(name) {
+            Some(preset) => {
+                self.nes.set_accuracy(preset.into());
+                true
+            }
+            None => false,
+        }
     }
 
     /// Resets the state of the NES.
@@ -99,22 +437,129 @@ impl WebNes {
 /// Structure used to represent a NES Cartridge in WASM.
 pub struct WebCartridge {
     cartridge: Cartridge,
+    rom_hash: String,
 }
 
 #[wasm_bindgen]
 impl WebCartridge {
-    /// Loads a NES ROM from an array of bytes into a WebCartridge struct
-    pub fn load(rom: &[u8]) -> WebCartridge {
-        WebCartridge {
-            cartridge: Cartridge::load_from_reader(rom).unwrap(),
+    /// Loads a NES ROM from an array of bytes into a WebCartridge struct.
+    /// Throws an error with the reason if the ROM can't be loaded.
+    pub fn load(rom: &[u8]) -> Result<WebCartridge, JsValue> {
+        let to_js_error = |error: Box<dyn std::error::Error>| JsValue::from_str(&error.to_string());
+        Ok(WebCartridge {
+            cartridge: Cartridge::load_from_reader(rom).map_err(to_js_error)?,
+            rom_hash: calculate_rom_md5(rom)
+                .map_err(to_js_error)?
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        })
+    }
+}
+
+#[wasm_bindgen]
+/// Creates a new NES instance, loading the passed array of bytes as the ROM.
+/// Throws an error with the reason if the ROM can't be loaded.
+pub fn nes(rom: &[u8]) -> Result<WebNes, JsValue> {
+    Ok(WebNes::new(WebCartridge::load(rom)?))
+}
+
+#[wasm_bindgen]
+/// Structure used to describe the features supported by the NES core in WASM.
+pub struct WebCapabilities {
+    capabilities: Capabilities,
+}
+
+#[wasm_bindgen]
+impl WebCapabilities {
+    /// The version of the gc_nes_core crate that was compiled into the WASM module
+    pub fn version(&self) -> String {
+        self.capabilities.version.to_string()
+    }
+
+    /// The iNES ids of the mappers that cartridges can be loaded with
+    pub fn supported_mappers(&self) -> Vec<u16> {
+        self.capabilities.supported_mappers.to_vec()
+    }
+
+    /// Checks if a mapper with the provided iNES id is supported
+    pub fn supports_mapper(&self, mapper_id: u16) -> bool {
+        self.capabilities.supports_mapper(mapper_id)
+    }
+
+    /// Whether or not the core produces audio
+    pub fn audio(&self) -> bool {
+        self.capabilities.audio
+    }
+
+    /// The version of the save state format, or undefined if save states are unsupported
+    pub fn save_state_version(&self) -> Option<u32> {
+        self.capabilities.save_state_version
+    }
+
+    /// The names of the emulation accuracy modes that can be selected
+    pub fn accuracy_modes(&self) -> Vec<JsValue> {
+        self.capabilities.accuracy_modes.iter().map(|mode| JsValue::from_str(mode)).collect()
+    }
+}
+
+#[wasm_bindgen]
+/// Gets a description of the features supported by the NES core
+pub fn capabilities() -> WebCapabilities {
+    WebCapabilities {
+        capabilities: gc_nes_core::capabilities(),
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone)]
+/// The bits that represent each button of a standard NES controller in the
+/// controller state passed to update_controller_one and update_controller_two.
+/// Combine them with bitwise or to press multiple buttons at once.
+pub enum Button {
+    A = 0x01,
+    B = 0x02,
+    Select = 0x04,
+    Start = 0x08,
+    Up = 0x10,
+    Down = 0x20,
+    Left = 0x40,
+    Right = 0x80,
+}
+
+#[wasm_bindgen]
+/// Builder for the controller state passed to update_controller_one and update_controller_two.
+#[derive(Default)]
+pub struct ButtonStateBuilder {
+    builder: buttons::ButtonStateBuilder,
+}
+
+#[wasm_bindgen]
+impl ButtonStateBuilder {
+    /// Creates a new builder with no buttons pressed
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ButtonStateBuilder {
+        Default::default()
+    }
+
+    /// Sets whether the button is pressed
+    pub fn set(self, button: Button, pressed: bool) -> ButtonStateBuilder {
+        ButtonStateBuilder {
+            builder: self.builder.set(button as u8, pressed),
         }
     }
+
+    /// Gets the controller state with the buttons that have been pressed
+    pub fn build(&self) -> u8 {
+        self.builder.build()
+    }
 }
 
 #[wasm_bindgen]
-/// Creates a new NES instance, loading the passed array of bytes as the ROM
-pub fn nes(rom: &[u8]) -> WebNes {
-    WebNes::new(WebCartridge::load(rom))
+/// Gets the Javascript source of the AudioWorkletProcessor that plays the samples returned by take_audio_samples,
+/// registered as "gc-nes-audio". Load it into an audio context with `audioWorklet.addModule` from a Blob URL.
+pub fn audio_worklet_processor_source() -> String {
+    AUDIO_WORKLET_PROCESSOR_SOURCE.to_string()
 }
 
 #[wasm_bindgen]